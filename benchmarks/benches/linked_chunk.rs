@@ -83,9 +83,7 @@ fn writing(c: &mut Criterion) {
                     }
 
                     {
-                        operations.push(Operation::PushGapBack(Gap {
-                            prev_token: format!("gap{gap_nth}"),
-                        }));
+                        operations.push(Operation::PushGapBack(Gap::new(format!("gap{gap_nth}"))));
                         gap_nth += 1;
                     }
                 }
@@ -191,7 +189,7 @@ fn reading(c: &mut Criterion) {
                         break;
                     }
                     lc.push_items_back(events_chunk);
-                    lc.push_gap_back(Gap { prev_token: format!("gap{num_gaps}") });
+                    lc.push_gap_back(Gap::new(format!("gap{num_gaps}")));
                     num_gaps += 1;
                 }
 
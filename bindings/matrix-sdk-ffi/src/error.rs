@@ -245,6 +245,9 @@ pub enum QueueWedgeError {
     /// Some mime type couldn't be parsed.
     InvalidMimeType { mime_type: String },
 
+    /// The media upload has been paused by the user.
+    UploadPaused,
+
     /// Other errors.
     GenericApiError { msg: String },
 }
@@ -269,6 +272,7 @@ impl Display for QueueWedgeError {
             QueueWedgeError::InvalidMimeType { mime_type } => {
                 write!(f, "Invalid mime type '{mime_type}' for media upload")
             }
+            QueueWedgeError::UploadPaused => f.write_str("The media upload has been paused"),
             QueueWedgeError::GenericApiError { msg } => f.write_str(msg),
         }
     }
@@ -296,6 +300,7 @@ impl From<SdkQueueWedgeError> for QueueWedgeError {
             SdkQueueWedgeError::InvalidMimeType { mime_type } => {
                 Self::InvalidMimeType { mime_type }
             }
+            SdkQueueWedgeError::UploadPaused => Self::UploadPaused,
             SdkQueueWedgeError::GenericApiError { msg } => Self::GenericApiError { msg },
         }
     }
@@ -1,5 +1,8 @@
 use anyhow::Context as _;
-use matrix_sdk::{room_preview::RoomPreview as SdkRoomPreview, Client};
+use matrix_sdk::{
+    room_preview::{RoomPreview as SdkRoomPreview, RoomPreviewAction as SdkRoomPreviewAction},
+    Client,
+};
 use ruma::{room::RoomType as RumaRoomType, space::SpaceRoomJoinRule};
 use tracing::warn;
 
@@ -46,6 +49,7 @@ impl RoomPreview {
                 .heroes
                 .as_ref()
                 .map(|heroes| heroes.iter().map(|h| h.to_owned().into()).collect()),
+            next_action: info.next_action.into(),
         })
     }
 
@@ -120,6 +124,41 @@ pub struct RoomPreviewInfo {
     pub is_direct: Option<bool>,
     /// Room heroes.
     pub heroes: Option<Vec<RoomHero>>,
+    /// What the current user should do next to get into the room.
+    pub next_action: RoomPreviewAction,
+}
+
+/// What the current user should do next to get into a previewed room.
+#[derive(uniffi::Enum)]
+pub enum RoomPreviewAction {
+    /// The user is already a member of the room.
+    AlreadyJoined,
+    /// The user can join the room directly.
+    Join,
+    /// The user has been invited to the room, and can accept the invite.
+    AcceptInvite,
+    /// The user can knock on the room, to ask for permission to join it.
+    Knock,
+    /// The user has knocked on the room, and is waiting for an answer.
+    AwaitKnockAnswer,
+    /// The user needs to be invited to the room.
+    AwaitInvite,
+    /// The user has been banned from the room.
+    Banned,
+}
+
+impl From<SdkRoomPreviewAction> for RoomPreviewAction {
+    fn from(action: SdkRoomPreviewAction) -> Self {
+        match action {
+            SdkRoomPreviewAction::AlreadyJoined => Self::AlreadyJoined,
+            SdkRoomPreviewAction::Join => Self::Join,
+            SdkRoomPreviewAction::AcceptInvite => Self::AcceptInvite,
+            SdkRoomPreviewAction::Knock => Self::Knock,
+            SdkRoomPreviewAction::AwaitKnockAnswer => Self::AwaitKnockAnswer,
+            SdkRoomPreviewAction::AwaitInvite => Self::AwaitInvite,
+            SdkRoomPreviewAction::Banned => Self::Banned,
+        }
+    }
 }
 
 impl TryFrom<&SpaceRoomJoinRule> for JoinRule {
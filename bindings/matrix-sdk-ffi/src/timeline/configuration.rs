@@ -3,13 +3,14 @@ use std::sync::Arc;
 use matrix_sdk_ui::timeline::event_type_filter::TimelineEventTypeFilter as InnerTimelineEventTypeFilter;
 use ruma::{
     events::{AnySyncTimelineEvent, TimelineEventType},
-    EventId,
+    EventId, MilliSecondsSinceUnixEpoch,
 };
 
 use super::FocusEventError;
 use crate::{
     error::ClientError,
     event::{MessageLikeEventType, RoomMessageEventMessageType, StateEventType},
+    utils::u64_to_uint,
 };
 
 #[derive(uniffi::Object)]
@@ -76,6 +77,14 @@ pub enum TimelineFocus {
         /// Whether to hide in-thread replies from the live timeline.
         hide_threaded_events: bool,
     },
+    Date {
+        /// The date to focus on, in milliseconds since the unix epoch.
+        date: u64,
+        /// The number of context events to load around the focused date.
+        num_context_events: u16,
+        /// Whether to hide in-thread replies from the live timeline.
+        hide_threaded_events: bool,
+    },
     Thread {
         /// The thread root event ID to focus on.
         root_event_id: String,
@@ -107,6 +116,13 @@ impl TryFrom<TimelineFocus> for matrix_sdk_ui::timeline::TimelineFocus {
                     hide_threaded_events,
                 })
             }
+            TimelineFocus::Date { date, num_context_events, hide_threaded_events } => {
+                Ok(Self::Date {
+                    date: MilliSecondsSinceUnixEpoch(u64_to_uint(date)),
+                    num_context_events,
+                    hide_threaded_events,
+                })
+            }
             TimelineFocus::Thread { root_event_id } => {
                 let parsed_root_event_id = EventId::parse(&root_event_id).map_err(|err| {
                     FocusEventError::InvalidEventId {
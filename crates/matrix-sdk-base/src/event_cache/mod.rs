@@ -15,6 +15,8 @@
 //! Event cache store and common types shared with `matrix_sdk::event_cache`.
 
 use matrix_sdk_common::deserialized_responses::TimelineEvent;
+use ruma::{EventId, OwnedEventId};
+use serde::{Deserialize, Serialize};
 
 pub mod store;
 
@@ -22,9 +24,162 @@ pub mod store;
 pub type Event = TimelineEvent;
 
 /// The kind of gap the event storage holds.
-#[derive(Clone, Debug)]
+///
+/// Use [`Gap::new`], [`Gap::forward`] or [`Gap::for_evicted_events`] to create
+/// one.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "SerializedGap", into = "SerializedGap")]
 pub struct Gap {
     /// The token to use in the query, extracted from a previous "from" /
     /// "end" field of a `/messages` response.
-    pub prev_token: String,
+    ///
+    /// It's `None` if the gap stands for evicted events, see
+    /// [`Gap::evicted_before`], or if it's a gap to paginate forwards, see
+    /// [`Gap::next_token`].
+    pub prev_token: Option<String>,
+
+    /// The token to paginate forwards from this gap, extracted from a previous
+    /// "end" field of a `/context` or `/messages` response.
+    ///
+    /// Only the gaps after the events of a detached linked chunk have such a
+    /// token, the gaps of a room's linked chunk are always paginated backwards.
+    pub next_token: Option<String>,
+
+    /// If the gap stands for events that were evicted from the event cache
+    /// store, the ID of the event that was right after them.
+    ///
+    /// Such a gap doesn't hold a real `/messages` token: a token must be
+    /// resolved first, for example with a `/context` request on this event.
+    pub evicted_before: Option<OwnedEventId>,
+}
+
+impl Gap {
+    /// Create a gap with the token to paginate backwards from it.
+    pub fn new(prev_token: impl Into<String>) -> Self {
+        Self { prev_token: Some(prev_token.into()), next_token: None, evicted_before: None }
+    }
+
+    /// Create a gap with the token to paginate forwards from it.
+    pub fn forward(next_token: impl Into<String>) -> Self {
+        Self { prev_token: None, next_token: Some(next_token.into()), evicted_before: None }
+    }
+
+    /// Create a gap standing for events that were evicted from the event cache
+    /// store, and that were right before the event with the given ID.
+    pub fn for_evicted_events(next_event_id: &EventId) -> Self {
+        Self { prev_token: None, next_token: None, evicted_before: Some(next_event_id.to_owned()) }
+    }
+
+    /// The token to paginate backwards from this gap, if it isn't a gap of
+    /// evicted events.
+    pub fn token(&self) -> Option<&str> {
+        self.prev_token.as_deref()
+    }
+}
+
+/// The serialized form of a [`Gap`], as it's persisted by the stores.
+///
+/// The gaps with only a backwards token are serialized as the token only, as
+/// they were before the other fields were introduced. The other gaps are
+/// serialized with all their fields, so no combination of them is lost.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SerializedGap {
+    Token(String),
+    Fields {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        prev_token: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        next_token: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        evicted_before: Option<OwnedEventId>,
+    },
+}
+
+impl From<SerializedGap> for Gap {
+    fn from(value: SerializedGap) -> Self {
+        match value {
+            SerializedGap::Token(prev_token) => Self::new(prev_token),
+            SerializedGap::Fields { prev_token, next_token, evicted_before } => {
+                Self { prev_token, next_token, evicted_before }
+            }
+        }
+    }
+}
+
+impl From<Gap> for SerializedGap {
+    fn from(value: Gap) -> Self {
+        match value {
+            Gap { prev_token: Some(prev_token), next_token: None, evicted_before: None } => {
+                Self::Token(prev_token)
+            }
+            Gap { prev_token, next_token, evicted_before } => {
+                Self::Fields { prev_token, next_token, evicted_before }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{event_id, owned_event_id};
+    use serde_json::json;
+
+    use super::Gap;
+
+    #[test]
+    fn test_gap_serialization() {
+        // A gap with a token is serialized as the token only.
+        let gap = Gap::new("raclette");
+        assert_eq!(serde_json::to_value(&gap).unwrap(), json!("raclette"));
+        assert_eq!(serde_json::from_value::<Gap>(json!("raclette")).unwrap(), gap);
+        assert_eq!(gap.token(), Some("raclette"));
+
+        // A gap of evicted events keeps the ID of the event after them.
+        let gap = Gap::for_evicted_events(event_id!("$ev0"));
+        let serialized = serde_json::to_value(&gap).unwrap();
+        assert_eq!(serialized, json!({ "evicted_before": "$ev0" }));
+        let deserialized = serde_json::from_value::<Gap>(serialized).unwrap();
+        assert_eq!(deserialized.evicted_before, Some(owned_event_id!("$ev0")));
+        assert_eq!(deserialized.token(), None);
+
+        // A gap to paginate forwards keeps its token apart.
+        let gap = Gap::forward("fondue");
+        let serialized = serde_json::to_value(&gap).unwrap();
+        assert_eq!(serialized, json!({ "next_token": "fondue" }));
+        let deserialized = serde_json::from_value::<Gap>(serialized).unwrap();
+        assert_eq!(deserialized.next_token.as_deref(), Some("fondue"));
+        assert_eq!(deserialized.token(), None);
+    }
+
+    #[test]
+    fn test_gap_serialization_round_trip() {
+        let prev_tokens = [None, Some("raclette".to_owned())];
+        let next_tokens = [None, Some("fondue".to_owned())];
+        let evicted_befores = [None, Some(owned_event_id!("$ev0"))];
+
+        for prev_token in &prev_tokens {
+            for next_token in &next_tokens {
+                for evicted_before in &evicted_befores {
+                    let gap = Gap {
+                        prev_token: prev_token.clone(),
+                        next_token: next_token.clone(),
+                        evicted_before: evicted_before.clone(),
+                    };
+
+                    let serialized = serde_json::to_vec(&gap).unwrap();
+                    let deserialized = serde_json::from_slice::<Gap>(&serialized).unwrap();
+                    assert_eq!(deserialized, gap);
+                }
+            }
+        }
+
+        // A gap without any field isn't confused with a gap with an empty token.
+        let gap = Gap { prev_token: None, next_token: None, evicted_before: None };
+        assert_eq!(serde_json::to_value(&gap).unwrap(), json!({}));
+
+        let gap = Gap::new("");
+        assert_eq!(serde_json::to_value(&gap).unwrap(), json!(""));
+        assert_eq!(serde_json::from_value::<Gap>(json!("")).unwrap(), gap);
+    }
 }
@@ -0,0 +1,217 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Types for the index of the aggregated relations of events, kept by the
+//! event cache store.
+
+use std::collections::BTreeMap;
+
+use ruma::{MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedUserId};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+
+use crate::event_cache::Event;
+
+/// The relations aggregated on an event, as indexed by the event cache.
+///
+/// This allows to know the latest edit, the reactions and the redaction of an
+/// event without having to load all the events related to it, or even to have
+/// loaded them in the first place.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventAggregations {
+    /// The ID of the latest edit of the event, if it has been edited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latest_edit: Option<OwnedEventId>,
+
+    /// The reactions to the event, by key.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub reactions: BTreeMap<String, Vec<ReactionAggregation>>,
+
+    /// The ID of the redaction of the event, if it has been redacted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redacted_by: Option<OwnedEventId>,
+}
+
+/// A reaction to an event, in its [`EventAggregations`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReactionAggregation {
+    /// The ID of the reaction event.
+    pub event_id: OwnedEventId,
+
+    /// The sender of the reaction.
+    pub sender: OwnedUserId,
+
+    /// The time at which the reaction was sent.
+    pub timestamp: MilliSecondsSinceUnixEpoch,
+}
+
+/// The fields of a related event needed to aggregate it.
+#[derive(Deserialize)]
+struct RelatedEvent {
+    event_id: OwnedEventId,
+    sender: OwnedUserId,
+    origin_server_ts: MilliSecondsSinceUnixEpoch,
+    content: RelatedEventContent,
+    #[serde(default)]
+    unsigned: RelatedEventUnsigned,
+}
+
+#[derive(Default, Deserialize)]
+struct RelatedEventUnsigned {
+    redacted_because: Option<IgnoredAny>,
+}
+
+#[derive(Deserialize)]
+struct RelatedEventContent {
+    #[serde(rename = "m.relates_to")]
+    relates_to: Option<RelatesTo>,
+}
+
+#[derive(Deserialize)]
+struct RelatesTo {
+    rel_type: String,
+    key: Option<String>,
+}
+
+impl EventAggregations {
+    /// Whether there is nothing aggregated on the event.
+    pub fn is_empty(&self) -> bool {
+        self.latest_edit.is_none() && self.reactions.is_empty() && self.redacted_by.is_none()
+    }
+
+    /// The number of reactions to the event with the given key.
+    pub fn reaction_count(&self, key: &str) -> usize {
+        self.reactions.get(key).map_or(0, Vec::len)
+    }
+
+    /// Recompute the latest edit and the reactions from the events related to
+    /// the event.
+    ///
+    /// `original` is the event itself, if it's known, to ignore the edits that
+    /// weren't sent by the sender of the event. `related` must be sorted in
+    /// the order of the room's timeline, so the last edit is the latest one.
+    ///
+    /// Redacted related events are ignored, since they lose their relation.
+    pub fn set_relations<'a>(
+        &mut self,
+        original: Option<&Event>,
+        related: impl IntoIterator<Item = &'a Event>,
+    ) {
+        let original_sender = original
+            .and_then(|event| event.raw().get_field::<OwnedUserId>("sender").ok().flatten());
+
+        self.latest_edit = None;
+        self.reactions.clear();
+
+        for event in related {
+            let Ok(related) = event.raw().deserialize_as::<RelatedEvent>() else {
+                continue;
+            };
+            if related.unsigned.redacted_because.is_some() {
+                continue;
+            }
+            let Some(relates_to) = related.content.relates_to else {
+                continue;
+            };
+
+            match relates_to.rel_type.as_str() {
+                "m.replace"
+                    if original_sender.as_ref().is_none_or(|sender| *sender == related.sender) =>
+                {
+                    self.latest_edit = Some(related.event_id);
+                }
+
+                "m.annotation" => {
+                    let Some(key) = relates_to.key else {
+                        continue;
+                    };
+
+                    let reactions = self.reactions.entry(key).or_default();
+
+                    // Only keep the latest reaction of a sender with the same key.
+                    reactions.retain(|reaction| reaction.sender != related.sender);
+                    reactions.push(ReactionAggregation {
+                        event_id: related.event_id,
+                        sender: related.sender,
+                        timestamp: related.origin_server_ts,
+                    });
+                }
+
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_test::{event_factory::EventFactory, ALICE, BOB};
+    use ruma::{event_id, events::room::message::RoomMessageEventContentWithoutRelation};
+
+    use super::EventAggregations;
+
+    #[test]
+    fn test_set_relations() {
+        let f = EventFactory::new().sender(*ALICE);
+        let original_id = event_id!("$original");
+        let original = f.text_msg("hello").event_id(original_id).into_event();
+
+        let related = [
+            f.text_msg("* hell")
+                .edit(original_id, RoomMessageEventContentWithoutRelation::text_plain("hell"))
+                .event_id(event_id!("$edit1"))
+                .into_event(),
+            f.reaction(original_id, "👍").event_id(event_id!("$reaction1")).into_event(),
+            f.reaction(original_id, "👍")
+                .sender(*BOB)
+                .event_id(event_id!("$reaction2"))
+                .into_event(),
+            f.reaction(original_id, "🎉")
+                .sender(*BOB)
+                .event_id(event_id!("$reaction3"))
+                .into_event(),
+            f.text_msg("* hello!")
+                .edit(original_id, RoomMessageEventContentWithoutRelation::text_plain("hello!"))
+                .event_id(event_id!("$edit2"))
+                .into_event(),
+            // An edit by another user is ignored.
+            f.text_msg("* pwned")
+                .edit(original_id, RoomMessageEventContentWithoutRelation::text_plain("pwned"))
+                .sender(*BOB)
+                .event_id(event_id!("$edit3"))
+                .into_event(),
+        ];
+
+        let mut aggregations = EventAggregations::default();
+        aggregations.set_relations(Some(&original), &related);
+
+        assert_eq!(aggregations.latest_edit.as_deref(), Some(event_id!("$edit2")));
+        assert_eq!(aggregations.reaction_count("👍"), 2);
+        assert_eq!(aggregations.reaction_count("🎉"), 1);
+        assert_eq!(aggregations.reaction_count("🤷"), 0);
+        assert_eq!(aggregations.reactions["🎉"][0].event_id, event_id!("$reaction3"));
+        assert_eq!(aggregations.reactions["🎉"][0].sender, *BOB);
+        assert!(aggregations.redacted_by.is_none());
+
+        // Without the original event, the latest edit is the last one.
+        aggregations.set_relations(None, &related);
+        assert_eq!(aggregations.latest_edit.as_deref(), Some(event_id!("$edit3")));
+
+        // Once all the related events are gone, only the redaction is left.
+        aggregations.redacted_by = Some(event_id!("$redaction").to_owned());
+        aggregations.set_relations(Some(&original), &[]);
+        assert!(aggregations.latest_edit.is_none());
+        assert!(aggregations.reactions.is_empty());
+        assert!(!aggregations.is_empty());
+    }
+}
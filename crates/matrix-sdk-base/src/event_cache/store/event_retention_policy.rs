@@ -0,0 +1,605 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Configuration to decide which events to keep in the linked chunks of the
+//! event cache, allowing to do periodic cleanups to avoid to have the size of
+//! the event cache grow indefinitely.
+//!
+//! To proceed to a cleanup, first set the [`EventRetentionPolicy`] to use with
+//! [`EventCacheStore::set_event_retention_policy()`]. Then call
+//! [`EventCacheStore::clean_up_linked_chunks()`].
+//!
+//! Evicted chunks are replaced by a gap created with
+//! [`Gap::for_evicted_events()`], so the events can be fetched again from the
+//! homeserver with a back-pagination.
+//!
+//! [`EventCacheStore::set_event_retention_policy()`]: crate::event_cache::store::EventCacheStore::set_event_retention_policy
+//! [`EventCacheStore::clean_up_linked_chunks()`]: crate::event_cache::store::EventCacheStore::clean_up_linked_chunks
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use matrix_sdk_common::linked_chunk::{ChunkContent, ChunkIdentifier, RawChunk, Update};
+use ruma::{time::Duration, MilliSecondsSinceUnixEpoch, OwnedEventId};
+use serde::{Deserialize, Serialize};
+
+use crate::event_cache::{Event, Gap};
+
+/// The retention policy for the events in the linked chunks of the
+/// [`EventCacheStore`].
+///
+/// The oldest chunks of a linked chunk are evicted first, and the last chunk of
+/// a linked chunk is never evicted.
+///
+/// [`EventCacheStore`]: crate::event_cache::store::EventCacheStore
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+#[non_exhaustive]
+pub struct EventRetentionPolicy {
+    /// The maximum number of events to keep in the linked chunk of a room.
+    ///
+    /// If this is set and a room has more events than this value, the oldest
+    /// chunks of the room will be evicted during a cleanup until the number of
+    /// events is below this threshold.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_events_per_room: Option<u64>,
+
+    /// The maximum authorized size of all the events in the linked chunks, in
+    /// bytes.
+    ///
+    /// The size of an event is defined as the size of its serialized JSON, so
+    /// it might differ from the size taken by the event in the database.
+    ///
+    /// If this is set and the size of the events is bigger than this value, the
+    /// oldest chunks of all the rooms will be evicted during a cleanup until
+    /// the size is below this threshold.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cache_size: Option<u64>,
+
+    /// The duration after which an event is considered expired.
+    ///
+    /// If this is set, chunks whose most recent event is older than this
+    /// duration, according to its `origin_server_ts`, will be evicted during a
+    /// cleanup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<Duration>,
+}
+
+impl EventRetentionPolicy {
+    /// Create an [`EventRetentionPolicy`] with the default values.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty [`EventRetentionPolicy`].
+    ///
+    /// This means that all events will be kept and cleanups have no effect.
+    pub fn empty() -> Self {
+        Self { max_events_per_room: None, max_cache_size: None, max_age: None }
+    }
+
+    /// Set the maximum number of events to keep in the linked chunk of a room.
+    pub fn with_max_events_per_room(mut self, count: Option<u64>) -> Self {
+        self.max_events_per_room = count;
+        self
+    }
+
+    /// Set the maximum authorized size of all the events in the linked chunks,
+    /// in bytes.
+    pub fn with_max_cache_size(mut self, size: Option<u64>) -> Self {
+        self.max_cache_size = size;
+        self
+    }
+
+    /// Set the duration after which an event is considered expired.
+    pub fn with_max_age(mut self, duration: Option<Duration>) -> Self {
+        self.max_age = duration;
+        self
+    }
+
+    /// Whether this policy has limitations.
+    ///
+    /// If this policy has no limitations, a cleanup job would have no effect.
+    ///
+    /// Returns `true` if at least one limitation is set.
+    pub fn has_limitations(&self) -> bool {
+        self.max_events_per_room.is_some()
+            || self.max_cache_size.is_some()
+            || self.max_age.is_some()
+    }
+
+    /// Whether an event sent at the given time has expired.
+    ///
+    /// # Arguments
+    ///
+    /// * `current_time` - The current time.
+    ///
+    /// * `origin_server_ts` - The time when the event was sent.
+    pub fn has_event_expired(
+        &self,
+        current_time: MilliSecondsSinceUnixEpoch,
+        origin_server_ts: MilliSecondsSinceUnixEpoch,
+    ) -> bool {
+        self.max_age.is_some_and(|max_age| {
+            let elapsed =
+                u64::from(current_time.get()).saturating_sub(origin_server_ts.get().into());
+            u128::from(elapsed) >= max_age.as_millis()
+        })
+    }
+
+    /// Compute the chunks to evict from the given linked chunks to respect this
+    /// policy.
+    ///
+    /// This is meant to be used by implementations of
+    /// [`EventCacheStore::clean_up_linked_chunks()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `linked_chunks` - All the linked chunks of the store, as a list of
+    ///   pairs of a key identifying the linked chunk and the chunks of the
+    ///   linked chunk, in any order.
+    ///
+    /// * `current_time` - The current time.
+    ///
+    /// Returns the list of linked chunks that have chunks to evict, identified
+    /// by their key.
+    ///
+    /// [`EventCacheStore::clean_up_linked_chunks()`]: crate::event_cache::store::EventCacheStore::clean_up_linked_chunks
+    pub fn compute_evictions<K>(
+        &self,
+        linked_chunks: Vec<(K, Vec<RawChunk<Event, Gap>>)>,
+        current_time: MilliSecondsSinceUnixEpoch,
+    ) -> Vec<(K, LinkedChunkEviction)> {
+        if !self.has_limitations() {
+            return Vec::new();
+        }
+
+        let mut linked_chunks = linked_chunks
+            .into_iter()
+            .map(|(key, chunks)| EvictableLinkedChunk::new(key, chunks))
+            .collect::<Vec<_>>();
+
+        // First, apply the limits that are specific to each linked chunk.
+        for linked_chunk in &mut linked_chunks {
+            if let Some(max_events) = self.max_events_per_room {
+                let mut num_events = linked_chunk.num_kept_events();
+
+                while num_events > max_events && linked_chunk.can_evict_more() {
+                    num_events -=
+                        linked_chunk.chunks[linked_chunk.num_evicted].event_ids.len() as u64;
+                    linked_chunk.num_evicted += 1;
+                }
+            }
+
+            if self.max_age.is_some() {
+                while linked_chunk.can_evict_more()
+                    && linked_chunk.chunks[linked_chunk.num_evicted]
+                        .latest_timestamp
+                        .is_none_or(|ts| self.has_event_expired(current_time, ts))
+                {
+                    linked_chunk.num_evicted += 1;
+                }
+            }
+        }
+
+        // Then, evict the oldest chunks of all the linked chunks until the total size
+        // is below the limit.
+        if let Some(max_cache_size) = self.max_cache_size {
+            let mut cache_size =
+                linked_chunks.iter().map(EvictableLinkedChunk::kept_size).sum::<u64>();
+
+            // Candidates are the first kept chunk of each linked chunk, with the oldest
+            // one at the top of the heap.
+            let mut candidates = linked_chunks
+                .iter()
+                .enumerate()
+                .filter_map(|(index, linked_chunk)| linked_chunk.next_candidate(index))
+                .collect::<BinaryHeap<_>>();
+
+            while cache_size > max_cache_size {
+                let Some(Reverse((_, index))) = candidates.pop() else {
+                    break;
+                };
+
+                let linked_chunk = &mut linked_chunks[index];
+                cache_size -= linked_chunk.chunks[linked_chunk.num_evicted].size;
+                linked_chunk.num_evicted += 1;
+
+                if let Some(candidate) = linked_chunk.next_candidate(index) {
+                    candidates.push(candidate);
+                }
+            }
+        }
+
+        linked_chunks.into_iter().filter_map(EvictableLinkedChunk::into_eviction).collect()
+    }
+}
+
+impl Default for EventRetentionPolicy {
+    fn default() -> Self {
+        // Events are kept forever by default, the homeserver being the source of
+        // truth for them.
+        Self::empty()
+    }
+}
+
+/// The chunks to evict from a linked chunk, as computed by
+/// [`EventRetentionPolicy::compute_evictions()`].
+///
+/// The evicted chunks are always the first chunks of the linked chunk. They
+/// must be replaced by a gap, so the evicted events can be back-paginated
+/// again.
+#[derive(Debug, Clone)]
+pub struct LinkedChunkEviction {
+    /// The identifiers of the evicted chunks, from the first one of the linked
+    /// chunk.
+    pub evicted_chunks: Vec<ChunkIdentifier>,
+
+    /// The IDs of the events in the evicted chunks.
+    pub evicted_events: Vec<OwnedEventId>,
+
+    /// The first chunk that is kept in the linked chunk.
+    pub first_kept_chunk: ChunkIdentifier,
+
+    /// The gap to insert in place of the evicted chunks.
+    ///
+    /// It must reuse the identifier of the last evicted chunk, to avoid
+    /// clashing with the identifiers generated for the linked chunk.
+    ///
+    /// This is `None` if the first kept chunk is already a gap.
+    pub gap: Option<Gap>,
+}
+
+impl LinkedChunkEviction {
+    /// The identifier to use for the [`LinkedChunkEviction::gap`].
+    pub fn gap_identifier(&self) -> ChunkIdentifier {
+        *self.evicted_chunks.last().expect("at least one chunk is evicted")
+    }
+
+    /// The updates to apply to the linked chunk to perform this eviction.
+    pub fn updates(&self) -> Vec<Update<Event, Gap>> {
+        let mut updates = self
+            .evicted_chunks
+            .iter()
+            .map(|identifier| Update::RemoveChunk(*identifier))
+            .collect::<Vec<_>>();
+
+        if let Some(gap) = &self.gap {
+            updates.push(Update::NewGapChunk {
+                previous: None,
+                new: self.gap_identifier(),
+                next: Some(self.first_kept_chunk),
+                gap: gap.clone(),
+            });
+        }
+
+        updates
+    }
+}
+
+/// The information about a chunk that is needed to apply an
+/// [`EventRetentionPolicy`].
+struct EvictableChunk {
+    identifier: ChunkIdentifier,
+    is_gap: bool,
+    event_ids: Vec<OwnedEventId>,
+    size: u64,
+    latest_timestamp: Option<MilliSecondsSinceUnixEpoch>,
+}
+
+impl EvictableChunk {
+    fn new(chunk: RawChunk<Event, Gap>) -> Self {
+        match chunk.content {
+            ChunkContent::Gap(_) => Self {
+                identifier: chunk.identifier,
+                is_gap: true,
+                event_ids: Vec::new(),
+                size: 0,
+                latest_timestamp: None,
+            },
+            ChunkContent::Items(events) => Self {
+                identifier: chunk.identifier,
+                is_gap: false,
+                event_ids: events.iter().filter_map(|event| event.event_id()).collect(),
+                size: events.iter().map(|event| event.raw().json().get().len() as u64).sum(),
+                latest_timestamp: events
+                    .iter()
+                    .filter_map(|event| {
+                        event
+                            .raw()
+                            .get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts")
+                            .ok()?
+                    })
+                    .max(),
+            },
+        }
+    }
+}
+
+/// A linked chunk on which an [`EventRetentionPolicy`] is being applied.
+struct EvictableLinkedChunk<K> {
+    key: K,
+
+    /// The chunks, from the first one to the last one.
+    chunks: Vec<EvictableChunk>,
+
+    /// The number of chunks to evict, at the start of `chunks`.
+    num_evicted: usize,
+}
+
+impl<K> EvictableLinkedChunk<K> {
+    fn new(key: K, chunks: Vec<RawChunk<Event, Gap>>) -> Self {
+        // Sort the chunks by following the links from the first one.
+        let mut first = None;
+        let mut chunks = chunks
+            .into_iter()
+            .map(|chunk| {
+                if chunk.previous.is_none() {
+                    first = Some(chunk.identifier);
+                }
+                (chunk.identifier, chunk)
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut sorted = Vec::with_capacity(chunks.len());
+        let mut next = first;
+
+        while let Some(chunk) = next.and_then(|identifier| chunks.remove(&identifier)) {
+            next = chunk.next;
+            sorted.push(EvictableChunk::new(chunk));
+        }
+
+        Self { key, chunks: sorted, num_evicted: 0 }
+    }
+
+    /// Whether another chunk can be evicted, knowing that the last chunk is
+    /// never evicted.
+    fn can_evict_more(&self) -> bool {
+        self.num_evicted + 1 < self.chunks.len()
+    }
+
+    fn kept_chunks(&self) -> &[EvictableChunk] {
+        &self.chunks[self.num_evicted..]
+    }
+
+    fn num_kept_events(&self) -> u64 {
+        self.kept_chunks().iter().map(|chunk| chunk.event_ids.len() as u64).sum()
+    }
+
+    fn kept_size(&self) -> u64 {
+        self.kept_chunks().iter().map(|chunk| chunk.size).sum()
+    }
+
+    /// The next chunk to evict to reduce the total size, as an entry of a
+    /// min-heap sorted by age.
+    fn next_candidate(
+        &self,
+        index: usize,
+    ) -> Option<Reverse<(Option<MilliSecondsSinceUnixEpoch>, usize)>> {
+        self.can_evict_more()
+            .then(|| Reverse((self.chunks[self.num_evicted].latest_timestamp, index)))
+    }
+
+    /// The eviction to perform on this linked chunk, if any.
+    fn into_eviction(self) -> Option<(K, LinkedChunkEviction)> {
+        let (evicted, kept) = self.chunks.split_at(self.num_evicted);
+        let first_kept = kept.first()?;
+
+        // Don't bother evicting chunks that don't contain events.
+        if evicted.iter().all(|chunk| chunk.event_ids.is_empty()) {
+            return None;
+        }
+
+        let gap = if first_kept.is_gap {
+            None
+        } else {
+            // The gap needs an event to resolve its token. If there is no event in the kept
+            // chunks, it is not possible to evict the previous chunks.
+            let next_event_id = kept.iter().find_map(|chunk| chunk.event_ids.first())?;
+            Some(Gap::for_evicted_events(next_event_id))
+        };
+
+        let eviction = LinkedChunkEviction {
+            evicted_chunks: evicted.iter().map(|chunk| chunk.identifier).collect(),
+            evicted_events: evicted.iter().flat_map(|chunk| chunk.event_ids.clone()).collect(),
+            first_kept_chunk: first_kept.identifier,
+            gap,
+        };
+
+        Some((self.key, eviction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_common::linked_chunk::{ChunkContent, ChunkIdentifier as CId, RawChunk};
+    use matrix_sdk_test::{event_factory::EventFactory, ALICE};
+    use ruma::{event_id, room_id, time::Duration, uint, EventId, MilliSecondsSinceUnixEpoch};
+
+    use super::EventRetentionPolicy;
+    use crate::event_cache::{Event, Gap};
+
+    fn event(event_id: &EventId, ts: u64) -> Event {
+        EventFactory::new()
+            .room(room_id!("!galette:saucisse.bzh"))
+            .sender(*ALICE)
+            .text_msg("hello")
+            .event_id(event_id)
+            .server_ts(ts)
+            .into_event()
+    }
+
+    fn items(
+        id: u64,
+        previous: Option<u64>,
+        next: Option<u64>,
+        events: Vec<Event>,
+    ) -> RawChunk<Event, Gap> {
+        RawChunk {
+            content: ChunkContent::Items(events),
+            previous: previous.map(CId::new),
+            identifier: CId::new(id),
+            next: next.map(CId::new),
+        }
+    }
+
+    fn gap(id: u64, previous: Option<u64>, next: Option<u64>) -> RawChunk<Event, Gap> {
+        RawChunk {
+            content: ChunkContent::Gap(Gap::new("prev")),
+            previous: previous.map(CId::new),
+            identifier: CId::new(id),
+            next: next.map(CId::new),
+        }
+    }
+
+    #[test]
+    fn test_event_retention_policy_has_limitations() {
+        let mut policy = EventRetentionPolicy::empty();
+        assert!(!policy.has_limitations());
+
+        policy = policy.with_max_events_per_room(Some(100));
+        assert!(policy.has_limitations());
+
+        policy = policy.with_max_events_per_room(None).with_max_cache_size(Some(1_024));
+        assert!(policy.has_limitations());
+
+        policy = policy.with_max_cache_size(None).with_max_age(Some(Duration::from_secs(60)));
+        assert!(policy.has_limitations());
+
+        // With default values.
+        assert!(!EventRetentionPolicy::new().has_limitations());
+    }
+
+    #[test]
+    fn test_event_retention_policy_has_event_expired() {
+        let sent = MilliSecondsSinceUnixEpoch(uint!(30_000));
+        let now = MilliSecondsSinceUnixEpoch(uint!(90_000));
+
+        let mut policy = EventRetentionPolicy::empty();
+        assert!(!policy.has_event_expired(now, sent));
+
+        policy = policy.with_max_age(Some(Duration::from_secs(120)));
+        assert!(!policy.has_event_expired(now, sent));
+
+        policy = policy.with_max_age(Some(Duration::from_secs(60)));
+        assert!(policy.has_event_expired(now, sent));
+
+        // An event from the future hasn't expired.
+        assert!(!policy.has_event_expired(sent, now));
+    }
+
+    #[test]
+    fn test_compute_evictions_max_events_per_room() {
+        // Chunks are given out of order on purpose.
+        let chunks = vec![
+            items(2, Some(1), None, vec![event(event_id!("$4"), 4), event(event_id!("$5"), 5)]),
+            items(0, None, Some(1), vec![event(event_id!("$1"), 1), event(event_id!("$2"), 2)]),
+            items(1, Some(0), Some(2), vec![event(event_id!("$3"), 3)]),
+        ];
+
+        let now = MilliSecondsSinceUnixEpoch(uint!(10));
+        let policy = EventRetentionPolicy::empty().with_max_events_per_room(Some(3));
+        let mut evictions = policy.compute_evictions(vec![("room", chunks)], now);
+
+        assert_eq!(evictions.len(), 1);
+        let (key, eviction) = evictions.remove(0);
+        assert_eq!(key, "room");
+        assert_eq!(eviction.evicted_chunks, vec![CId::new(0)]);
+        assert_eq!(eviction.evicted_events, vec![event_id!("$1"), event_id!("$2")]);
+        assert_eq!(eviction.first_kept_chunk, CId::new(1));
+        assert_eq!(eviction.gap_identifier(), CId::new(0));
+
+        let gap = eviction.gap.unwrap();
+        assert_eq!(gap.evicted_before.as_deref().unwrap(), "$3");
+    }
+
+    #[test]
+    fn test_compute_evictions_never_evicts_last_chunk() {
+        let chunks = vec![
+            gap(0, None, Some(1)),
+            items(1, Some(0), None, vec![event(event_id!("$1"), 1), event(event_id!("$2"), 2)]),
+        ];
+
+        let now = MilliSecondsSinceUnixEpoch(uint!(10));
+        let policy = EventRetentionPolicy::empty()
+            .with_max_events_per_room(Some(0))
+            .with_max_age(Some(Duration::ZERO));
+
+        assert!(policy.compute_evictions(vec![("room", chunks)], now).is_empty());
+    }
+
+    #[test]
+    fn test_compute_evictions_max_age() {
+        let chunks = vec![
+            items(0, None, Some(1), vec![event(event_id!("$1"), 1_000)]),
+            gap(1, Some(0), Some(2)),
+            items(2, Some(1), Some(3), vec![event(event_id!("$2"), 2_000)]),
+            items(3, Some(2), None, vec![event(event_id!("$3"), 60_000)]),
+        ];
+
+        let now = MilliSecondsSinceUnixEpoch(uint!(61_000));
+        let policy = EventRetentionPolicy::empty().with_max_age(Some(Duration::from_secs(30)));
+        let (_, eviction) = policy.compute_evictions(vec![((), chunks)], now).remove(0);
+
+        assert_eq!(eviction.evicted_chunks, vec![CId::new(0), CId::new(1), CId::new(2)]);
+        assert_eq!(eviction.evicted_events, vec![event_id!("$1"), event_id!("$2")]);
+        assert_eq!(eviction.first_kept_chunk, CId::new(3));
+        assert!(eviction.gap.is_some());
+    }
+
+    #[test]
+    fn test_compute_evictions_keeps_existing_gap() {
+        let chunks = vec![
+            items(0, None, Some(1), vec![event(event_id!("$1"), 1)]),
+            gap(1, Some(0), Some(2)),
+            items(2, Some(1), None, vec![event(event_id!("$2"), 2)]),
+        ];
+
+        let now = MilliSecondsSinceUnixEpoch(uint!(10));
+        let policy = EventRetentionPolicy::empty().with_max_events_per_room(Some(1));
+        let (_, eviction) = policy.compute_evictions(vec![((), chunks)], now).remove(0);
+
+        assert_eq!(eviction.evicted_chunks, vec![CId::new(0)]);
+        assert_eq!(eviction.first_kept_chunk, CId::new(1));
+        assert!(eviction.gap.is_none());
+    }
+
+    #[test]
+    fn test_compute_evictions_max_cache_size() {
+        let room_0 = vec![
+            items(0, None, Some(1), vec![event(event_id!("$a1"), 1)]),
+            items(1, Some(0), Some(2), vec![event(event_id!("$a2"), 4)]),
+            items(2, Some(1), None, vec![event(event_id!("$a3"), 6)]),
+        ];
+        let room_1 = vec![
+            items(0, None, Some(1), vec![event(event_id!("$b1"), 2)]),
+            items(1, Some(0), Some(2), vec![event(event_id!("$b2"), 3)]),
+            items(2, Some(1), None, vec![event(event_id!("$b3"), 5)]),
+        ];
+
+        let event_size = event(event_id!("$a1"), 1).raw().json().get().len() as u64;
+        let now = MilliSecondsSinceUnixEpoch(uint!(10));
+
+        // Keep 3 events: the 3 oldest events are evicted, across both rooms.
+        let policy = EventRetentionPolicy::empty().with_max_cache_size(Some(3 * event_size));
+        let mut evictions = policy.compute_evictions(vec![(0, room_0), (1, room_1)], now);
+        evictions.sort_by_key(|(key, _)| *key);
+
+        assert_eq!(evictions.len(), 2);
+        assert_eq!(evictions[0].1.evicted_events, vec![event_id!("$a1")]);
+        assert_eq!(evictions[1].1.evicted_events, vec![event_id!("$b1"), event_id!("$b2")]);
+    }
+}
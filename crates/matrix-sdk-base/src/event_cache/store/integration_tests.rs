@@ -25,6 +25,7 @@ use matrix_sdk_common::{
     linked_chunk::{
         lazy_loader, ChunkContent, ChunkIdentifier as CId, LinkedChunkId, Position, Update,
     },
+    store_usage::ItemsUsage,
 };
 use matrix_sdk_test::{event_factory::EventFactory, ALICE, DEFAULT_TEST_ROOM_ID};
 use ruma::{
@@ -34,15 +35,18 @@ use ruma::{
         relation::RelationType,
         room::{message::RoomMessageEventContentWithoutRelation, MediaSource},
     },
-    mxc_uri,
+    mxc_uri, owned_event_id,
     push::Action,
-    room_id, uint, EventId, RoomId,
+    room_id, uint, EventId, MilliSecondsSinceUnixEpoch, RoomId,
 };
 
-use super::{media::IgnoreMediaRetentionPolicy, DynEventCacheStore};
+use super::{
+    media::IgnoreMediaRetentionPolicy, DynEventCacheStore, EventAggregations, EventRetentionPolicy,
+    ReactionAggregation,
+};
 use crate::{
     event_cache::{store::DEFAULT_CHUNK_CAPACITY, Gap},
-    media::{MediaFormat, MediaRequestParameters, MediaThumbnailSettings},
+    media::{MediaFormat, MediaRequestParameters, MediaThumbnailSettings, UNKNOWN_MIME_TYPE},
 };
 
 /// Create a test event with all data filled, for testing that linked chunk
@@ -142,6 +146,10 @@ pub trait EventCacheStoreIntegrationTests {
     /// Test that removing a room from storage empties all associated data.
     async fn test_remove_room(&self);
 
+    /// Test that the detached linked chunk of a room is kept apart from the
+    /// room's linked chunk.
+    async fn test_detached_linked_chunk(&self);
+
     /// Test that filtering duplicated events works as expected.
     async fn test_filter_duplicated_events(&self);
 
@@ -153,6 +161,18 @@ pub trait EventCacheStoreIntegrationTests {
 
     /// Test that saving an event works as expected.
     async fn test_save_event(&self);
+
+    /// Test saving and loading the aggregations of events, and that they're
+    /// cleared along with the linked chunks.
+    async fn test_event_aggregations(&self);
+
+    /// Test that the linked chunks are cleaned up according to the
+    /// `EventRetentionPolicy`.
+    async fn test_clean_up_linked_chunks(&self);
+
+    /// Test that the events and media usage are reported correctly, and kept
+    /// after a compaction.
+    async fn test_storage_usage(&self);
 }
 
 impl EventCacheStoreIntegrationTests for DynEventCacheStore {
@@ -362,7 +382,7 @@ impl EventCacheStoreIntegrationTests for DynEventCacheStore {
                     previous: Some(CId::new(0)),
                     new: CId::new(1),
                     next: None,
-                    gap: Gap { prev_token: "parmesan".to_owned() },
+                    gap: Gap::new("parmesan"),
                 },
                 // another items chunk
                 Update::NewItemsChunk { previous: Some(CId::new(1)), new: CId::new(2), next: None },
@@ -403,7 +423,7 @@ impl EventCacheStoreIntegrationTests for DynEventCacheStore {
             assert_eq!(second.identifier(), CId::new(1));
 
             assert_matches!(second.content(), ChunkContent::Gap(gap) => {
-                assert_eq!(gap.prev_token, "parmesan");
+                assert_eq!(gap.prev_token.as_deref(), Some("parmesan"));
             });
         }
 
@@ -442,7 +462,7 @@ impl EventCacheStoreIntegrationTests for DynEventCacheStore {
                     previous: Some(CId::new(0)),
                     new: CId::new(1),
                     next: None,
-                    gap: Gap { prev_token: "parmesan".to_owned() },
+                    gap: Gap::new("parmesan"),
                 },
                 // another items chunk
                 Update::NewItemsChunk { previous: Some(CId::new(1)), new: CId::new(2), next: None },
@@ -515,7 +535,7 @@ impl EventCacheStoreIntegrationTests for DynEventCacheStore {
                     previous: Some(CId::new(0)),
                     new: CId::new(1),
                     next: None,
-                    gap: Gap { prev_token: "morbier".to_owned() },
+                    gap: Gap::new("morbier"),
                 },
                 // new chunk for items
                 Update::NewItemsChunk { previous: Some(CId::new(1)), new: CId::new(2), next: None },
@@ -593,7 +613,7 @@ impl EventCacheStoreIntegrationTests for DynEventCacheStore {
                 assert_eq!(chunk.lazy_previous(), Some(CId::new(0)));
 
                 assert_matches!(chunk.content(), ChunkContent::Gap(gap) => {
-                    assert_eq!(gap.prev_token, "morbier");
+                    assert_eq!(gap.prev_token.as_deref(), Some("morbier"));
                 });
             });
 
@@ -631,7 +651,7 @@ impl EventCacheStoreIntegrationTests for DynEventCacheStore {
 
                 // Already asserted, but let's be sure nothing breaks.
                 assert_matches!(chunk.content(), ChunkContent::Gap(gap) => {
-                    assert_eq!(gap.prev_token, "morbier");
+                    assert_eq!(gap.prev_token.as_deref(), Some("morbier"));
                 });
             });
 
@@ -682,7 +702,7 @@ impl EventCacheStoreIntegrationTests for DynEventCacheStore {
                 assert!(chunk.lazy_previous().is_none());
 
                 assert_matches!(chunk.content(), ChunkContent::Gap(gap) => {
-                    assert_eq!(gap.prev_token, "morbier");
+                    assert_eq!(gap.prev_token.as_deref(), Some("morbier"));
                 });
             });
 
@@ -745,7 +765,7 @@ impl EventCacheStoreIntegrationTests for DynEventCacheStore {
                     previous: Some(CId::new(0)),
                     new: CId::new(1),
                     next: None,
-                    gap: Gap { prev_token: "bleu d'auvergne".to_owned() },
+                    gap: Gap::new("bleu d'auvergne"),
                 },
                 // another items chunk
                 Update::NewItemsChunk { previous: Some(CId::new(1)), new: CId::new(2), next: None },
@@ -837,6 +857,97 @@ impl EventCacheStoreIntegrationTests for DynEventCacheStore {
         assert!(!r1_linked_chunk.is_empty());
     }
 
+    async fn test_detached_linked_chunk(&self) {
+        let room_id = room_id!("!r0:matrix.org");
+        let room_linked_chunk_id = LinkedChunkId::Room(room_id);
+        let detached_linked_chunk_id = LinkedChunkId::Detached(room_id);
+
+        let event_live = make_test_event(room_id, "live");
+        let event_detached = make_test_event(room_id, "detached");
+
+        // Fill the room's linked chunk.
+        self.handle_linked_chunk_updates(
+            room_linked_chunk_id,
+            vec![
+                Update::NewItemsChunk { previous: None, new: CId::new(0), next: None },
+                Update::PushItems {
+                    at: Position::new(CId::new(0), 0),
+                    items: vec![event_live.clone()],
+                },
+            ],
+        )
+        .await
+        .unwrap();
+
+        // Fill the detached linked chunk, with the same chunk identifiers.
+        self.handle_linked_chunk_updates(
+            detached_linked_chunk_id,
+            vec![
+                Update::NewGapChunk {
+                    previous: None,
+                    new: CId::new(0),
+                    next: None,
+                    gap: Gap::new("prev"),
+                },
+                Update::NewItemsChunk { previous: Some(CId::new(0)), new: CId::new(1), next: None },
+                Update::PushItems {
+                    at: Position::new(CId::new(1), 0),
+                    items: vec![event_detached.clone()],
+                },
+            ],
+        )
+        .await
+        .unwrap();
+
+        // Both linked chunks are distinct.
+        let room_chunks = self.load_all_chunks(room_linked_chunk_id).await.unwrap();
+        assert_eq!(room_chunks.len(), 1);
+        assert_matches!(&room_chunks[0].content, ChunkContent::Items(events) => {
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].event_id(), event_live.event_id());
+        });
+
+        let detached_chunks = self.load_all_chunks(detached_linked_chunk_id).await.unwrap();
+        assert_eq!(detached_chunks.len(), 2);
+
+        let duplicates = self
+            .filter_duplicated_events(
+                detached_linked_chunk_id,
+                vec![event_live.event_id().unwrap(), event_detached.event_id().unwrap()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].0, event_detached.event_id().unwrap());
+
+        // Clearing the detached linked chunk doesn't touch the room's linked chunk.
+        self.handle_linked_chunk_updates(detached_linked_chunk_id, vec![Update::Clear])
+            .await
+            .unwrap();
+
+        assert!(self.load_all_chunks(detached_linked_chunk_id).await.unwrap().is_empty());
+        assert_eq!(self.load_all_chunks(room_linked_chunk_id).await.unwrap().len(), 1);
+
+        // Removing the room removes both linked chunks.
+        self.handle_linked_chunk_updates(
+            detached_linked_chunk_id,
+            vec![
+                Update::NewItemsChunk { previous: None, new: CId::new(0), next: None },
+                Update::PushItems {
+                    at: Position::new(CId::new(0), 0),
+                    items: vec![event_detached],
+                },
+            ],
+        )
+        .await
+        .unwrap();
+
+        self.remove_room(room_id).await.unwrap();
+
+        assert!(self.load_all_chunks(room_linked_chunk_id).await.unwrap().is_empty());
+        assert!(self.load_all_chunks(detached_linked_chunk_id).await.unwrap().is_empty());
+    }
+
     async fn test_filter_duplicated_events(&self) {
         let room_id = room_id!("!r0:matrix.org");
         let linked_chunk_id = LinkedChunkId::Room(room_id);
@@ -864,7 +975,7 @@ impl EventCacheStoreIntegrationTests for DynEventCacheStore {
                     previous: Some(CId::new(0)),
                     new: CId::new(1),
                     next: None,
-                    gap: Gap { prev_token: "brillat-savarin".to_owned() },
+                    gap: Gap::new("brillat-savarin"),
                 },
                 Update::NewItemsChunk { previous: Some(CId::new(1)), new: CId::new(2), next: None },
                 Update::PushItems {
@@ -1134,6 +1245,390 @@ impl EventCacheStoreIntegrationTests for DynEventCacheStore {
             .expect("failed to query for finding an event")
             .is_none());
     }
+
+    async fn test_event_aggregations(&self) {
+        let room_id = room_id!("!r0:matrix.org");
+        let another_room_id = room_id!("!r1:matrix.org");
+        let event_id = event_id!("$ev0");
+        let another_event_id = event_id!("$ev1");
+
+        // Nothing is indexed at first.
+        let loaded = self
+            .load_event_aggregations(room_id, &[event_id.to_owned()])
+            .await
+            .expect("failed to load aggregations");
+        assert!(loaded.is_empty());
+
+        let aggregations = EventAggregations {
+            latest_edit: Some(event_id!("$edit").to_owned()),
+            reactions: BTreeMap::from([(
+                "👍".to_owned(),
+                vec![ReactionAggregation {
+                    event_id: event_id!("$reaction").to_owned(),
+                    sender: ALICE.to_owned(),
+                    timestamp: MilliSecondsSinceUnixEpoch(uint!(42)),
+                }],
+            )]),
+            redacted_by: None,
+        };
+
+        self.save_event_aggregations(room_id, event_id, aggregations.clone())
+            .await
+            .expect("failed to save aggregations");
+        self.save_event_aggregations(
+            another_room_id,
+            another_event_id,
+            EventAggregations {
+                redacted_by: Some(event_id!("$redaction").to_owned()),
+                ..Default::default()
+            },
+        )
+        .await
+        .expect("failed to save aggregations");
+
+        // The aggregations can be loaded, in their own room only.
+        let loaded = self
+            .load_event_aggregations(room_id, &[event_id.to_owned(), another_event_id.to_owned()])
+            .await
+            .expect("failed to load aggregations");
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[event_id], aggregations);
+
+        // Saving new aggregations replaces the previous ones.
+        let aggregations = EventAggregations { latest_edit: None, ..aggregations };
+        self.save_event_aggregations(room_id, event_id, aggregations.clone())
+            .await
+            .expect("failed to save aggregations");
+        let loaded = self
+            .load_event_aggregations(room_id, &[event_id.to_owned()])
+            .await
+            .expect("failed to load aggregations");
+        assert_eq!(loaded[event_id], aggregations);
+
+        // Saving empty aggregations removes them.
+        self.save_event_aggregations(room_id, event_id, EventAggregations::default())
+            .await
+            .expect("failed to save aggregations");
+        let loaded = self
+            .load_event_aggregations(room_id, &[event_id.to_owned()])
+            .await
+            .expect("failed to load aggregations");
+        assert!(loaded.is_empty());
+
+        // Clearing a room's linked chunk clears its aggregations, but not those of
+        // other rooms.
+        self.save_event_aggregations(room_id, event_id, aggregations.clone())
+            .await
+            .expect("failed to save aggregations");
+        self.handle_linked_chunk_updates(LinkedChunkId::Room(room_id), vec![Update::Clear])
+            .await
+            .expect("failed to clear the linked chunk");
+
+        let loaded = self
+            .load_event_aggregations(room_id, &[event_id.to_owned()])
+            .await
+            .expect("failed to load aggregations");
+        assert!(loaded.is_empty());
+
+        let loaded = self
+            .load_event_aggregations(another_room_id, &[another_event_id.to_owned()])
+            .await
+            .expect("failed to load aggregations");
+        assert_eq!(loaded.len(), 1);
+
+        // Clearing all the linked chunks clears all the aggregations.
+        self.clear_all_linked_chunks().await.expect("failed to clear all the linked chunks");
+
+        let loaded = self
+            .load_event_aggregations(another_room_id, &[another_event_id.to_owned()])
+            .await
+            .expect("failed to load aggregations");
+        assert!(loaded.is_empty());
+    }
+
+    async fn test_clean_up_linked_chunks(&self) {
+        let r0 = room_id!("!r0:matrix.org");
+        let linked_chunk_id0 = LinkedChunkId::Room(r0);
+        let r1 = room_id!("!r1:matrix.org");
+        let linked_chunk_id1 = LinkedChunkId::Room(r1);
+
+        let event = |room_id: &RoomId, msg: &str, event_id: &EventId| {
+            make_test_event_with_event_id(room_id, msg, Some(event_id))
+        };
+
+        // The default policy doesn't evict anything.
+        assert!(!self.event_retention_policy().has_limitations());
+
+        self.handle_linked_chunk_updates(
+            linked_chunk_id0,
+            vec![
+                Update::NewItemsChunk { previous: None, new: CId::new(0), next: None },
+                Update::PushItems {
+                    at: Position::new(CId::new(0), 0),
+                    items: vec![
+                        event(r0, "hello", event_id!("$ev0")),
+                        event(r0, "world", event_id!("$ev1")),
+                    ],
+                },
+                Update::NewItemsChunk { previous: Some(CId::new(0)), new: CId::new(1), next: None },
+                Update::PushItems {
+                    at: Position::new(CId::new(1), 0),
+                    items: vec![event(r0, "sup", event_id!("$ev2"))],
+                },
+                Update::NewItemsChunk { previous: Some(CId::new(1)), new: CId::new(2), next: None },
+                Update::PushItems {
+                    at: Position::new(CId::new(2), 0),
+                    items: vec![event(r0, "yo", event_id!("$ev3"))],
+                },
+            ],
+        )
+        .await
+        .unwrap();
+
+        self.handle_linked_chunk_updates(
+            linked_chunk_id1,
+            vec![
+                Update::NewItemsChunk { previous: None, new: CId::new(0), next: None },
+                Update::PushItems {
+                    at: Position::new(CId::new(0), 0),
+                    items: vec![
+                        event(r1, "bonjour", event_id!("$ev4")),
+                        event(r1, "monde", event_id!("$ev5")),
+                    ],
+                },
+            ],
+        )
+        .await
+        .unwrap();
+
+        // The detached linked chunk of the first room has more events than allowed too.
+        let detached_linked_chunk_id0 = LinkedChunkId::Detached(r0);
+        self.handle_linked_chunk_updates(
+            detached_linked_chunk_id0,
+            vec![
+                Update::NewItemsChunk { previous: None, new: CId::new(0), next: None },
+                Update::PushItems {
+                    at: Position::new(CId::new(0), 0),
+                    items: vec![
+                        event(r0, "far", event_id!("$ev6")),
+                        event(r0, "away", event_id!("$ev7")),
+                    ],
+                },
+                Update::NewItemsChunk { previous: Some(CId::new(0)), new: CId::new(1), next: None },
+                Update::PushItems {
+                    at: Position::new(CId::new(1), 0),
+                    items: vec![event(r0, "in the past", event_id!("$ev8"))],
+                },
+            ],
+        )
+        .await
+        .unwrap();
+
+        // Some events have aggregations.
+        let aggregations =
+            EventAggregations { latest_edit: Some(owned_event_id!("$edit")), ..Default::default() };
+        for event_id in [event_id!("$ev0"), event_id!("$ev2")] {
+            self.save_event_aggregations(r0, event_id, aggregations.clone()).await.unwrap();
+        }
+
+        // Nothing happens without limitations.
+        self.clean_up_linked_chunks().await.unwrap();
+        assert_eq!(self.load_all_chunks(linked_chunk_id0).await.unwrap().len(), 3);
+
+        // Keep at most 2 events per room.
+        let policy = EventRetentionPolicy::empty().with_max_events_per_room(Some(2));
+        self.set_event_retention_policy(policy).await.unwrap();
+        assert_eq!(self.event_retention_policy(), policy);
+
+        self.clean_up_linked_chunks().await.unwrap();
+
+        // The first chunk of the first room was replaced by a gap.
+        let lc = lazy_loader::from_all_chunks::<3, _, _>(
+            self.load_all_chunks(linked_chunk_id0).await.unwrap(),
+        )
+        .unwrap()
+        .unwrap();
+        let mut chunks = lc.chunks();
+
+        let first = chunks.next().unwrap();
+        assert_eq!(first.identifier(), CId::new(0));
+        assert_matches!(first.content(), ChunkContent::Gap(gap) => {
+            assert_eq!(
+                gap.evicted_before.as_deref(),
+                Some(event_id!("$ev2"))
+            );
+        });
+
+        let second = chunks.next().unwrap();
+        assert_eq!(second.identifier(), CId::new(1));
+        assert_matches!(second.content(), ChunkContent::Items(events) => {
+            assert_eq!(events.len(), 1);
+            check_test_event(&events[0], "sup");
+        });
+
+        let third = chunks.next().unwrap();
+        assert_eq!(third.identifier(), CId::new(2));
+        assert_matches!(third.content(), ChunkContent::Items(events) => {
+            assert_eq!(events.len(), 1);
+            check_test_event(&events[0], "yo");
+        });
+
+        assert!(chunks.next().is_none());
+
+        // The evicted events are gone, along with their aggregations.
+        assert!(self.find_event(r0, event_id!("$ev0")).await.unwrap().is_none());
+        assert!(self.find_event(r0, event_id!("$ev1")).await.unwrap().is_none());
+        assert!(self.find_event(r0, event_id!("$ev2")).await.unwrap().is_some());
+
+        let loaded = self
+            .load_event_aggregations(r0, &[owned_event_id!("$ev0"), owned_event_id!("$ev2")])
+            .await
+            .unwrap();
+        assert_eq!(loaded.keys().collect::<Vec<_>>(), [event_id!("$ev2")]);
+
+        // The detached linked chunk isn't subject to the policy.
+        let chunks = self.load_all_chunks(detached_linked_chunk_id0).await.unwrap();
+        assert_eq!(chunks.len(), 2);
+
+        // The second room is untouched.
+        let chunks = self.load_all_chunks(linked_chunk_id1).await.unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_matches!(&chunks[0].content, ChunkContent::Items(events) => {
+            assert_eq!(events.len(), 2);
+        });
+
+        // Even without any event allowed, the last chunk of a room is never evicted.
+        let policy = EventRetentionPolicy::empty().with_max_events_per_room(Some(0));
+        self.set_event_retention_policy(policy).await.unwrap();
+        self.clean_up_linked_chunks().await.unwrap();
+
+        let lc = lazy_loader::from_all_chunks::<3, _, _>(
+            self.load_all_chunks(linked_chunk_id0).await.unwrap(),
+        )
+        .unwrap()
+        .unwrap();
+        let mut chunks = lc.chunks();
+
+        // The new gap reuses the identifier of the last evicted chunk.
+        let first = chunks.next().unwrap();
+        assert_eq!(first.identifier(), CId::new(1));
+        assert_matches!(first.content(), ChunkContent::Gap(gap) => {
+            assert_eq!(
+                gap.evicted_before.as_deref(),
+                Some(event_id!("$ev3"))
+            );
+        });
+
+        let second = chunks.next().unwrap();
+        assert_eq!(second.identifier(), CId::new(2));
+        assert_matches!(second.content(), ChunkContent::Items(events) => {
+            assert_eq!(events.len(), 1);
+            check_test_event(&events[0], "yo");
+        });
+
+        assert!(chunks.next().is_none());
+
+        let chunks = self.load_all_chunks(linked_chunk_id1).await.unwrap();
+        assert_eq!(chunks.len(), 1);
+    }
+
+    async fn test_storage_usage(&self) {
+        let r0 = room_id!("!r0:matrix.org");
+        let r1 = room_id!("!r1:matrix.org");
+
+        let event = |room_id: &RoomId, msg: &str, event_id: &EventId| {
+            make_test_event_with_event_id(room_id, msg, Some(event_id))
+        };
+        let event_size = |event: &TimelineEvent| event.raw().json().get().len() as u64;
+
+        // Nothing is stored yet.
+        assert_eq!(self.room_events_usage(r0).await.unwrap(), ItemsUsage::default());
+        assert!(self.media_usage().await.unwrap().is_empty());
+
+        let ev0 = event(r0, "hello", event_id!("$ev0"));
+        let ev1 = event(r0, "world", event_id!("$ev1"));
+        let ev2 = event(r0, "out of band", event_id!("$ev2"));
+        let ev3 = event(r1, "bonjour", event_id!("$ev3"));
+
+        self.handle_linked_chunk_updates(
+            LinkedChunkId::Room(r0),
+            vec![
+                Update::NewItemsChunk { previous: None, new: CId::new(0), next: None },
+                Update::PushItems {
+                    at: Position::new(CId::new(0), 0),
+                    items: vec![ev0.clone(), ev1.clone()],
+                },
+            ],
+        )
+        .await
+        .unwrap();
+        self.save_event(r0, ev2.clone()).await.unwrap();
+
+        self.handle_linked_chunk_updates(
+            LinkedChunkId::Room(r1),
+            vec![
+                Update::NewItemsChunk { previous: None, new: CId::new(0), next: None },
+                Update::PushItems { at: Position::new(CId::new(0), 0), items: vec![ev3.clone()] },
+            ],
+        )
+        .await
+        .unwrap();
+
+        let png: Vec<u8> = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        let text: Vec<u8> = "hello".into();
+        for (uri, content) in [
+            (mxc_uri!("mxc://localhost/png"), png.clone()),
+            (mxc_uri!("mxc://localhost/text"), text.clone()),
+        ] {
+            let request = MediaRequestParameters {
+                source: MediaSource::Plain(uri.to_owned()),
+                format: MediaFormat::File,
+            };
+            self.add_media_content(&request, content, IgnoreMediaRetentionPolicy::No)
+                .await
+                .unwrap();
+        }
+
+        let check_usage = async || {
+            // Events in the linked chunk and out-of-band events are all accounted for. The
+            // stores may add some overhead to the size of the events.
+            let usage = self.room_events_usage(r0).await.unwrap();
+            assert_eq!(usage.count, 3);
+            assert!(usage.size >= event_size(&ev0) + event_size(&ev1) + event_size(&ev2));
+
+            let usage = self.room_events_usage(r1).await.unwrap();
+            assert_eq!(usage.count, 1);
+            assert!(usage.size >= event_size(&ev3));
+
+            assert_eq!(
+                self.room_events_usage(room_id!("!unknown:matrix.org")).await.unwrap(),
+                ItemsUsage::default()
+            );
+
+            // Media are grouped by MIME type.
+            let media_usage = self.media_usage().await.unwrap();
+            assert_eq!(media_usage.len(), 2);
+            assert_eq!(media_usage["image/png"].count, 1);
+            assert!(media_usage["image/png"].size >= png.len() as u64);
+            assert_eq!(media_usage[UNKNOWN_MIME_TYPE].count, 1);
+            assert!(media_usage[UNKNOWN_MIME_TYPE].size >= text.len() as u64);
+        };
+
+        check_usage().await;
+
+        let usage_before = self.storage_usage().await.unwrap();
+        self.compact().await.unwrap();
+        let usage_after = self.storage_usage().await.unwrap();
+
+        // The data is still there after a compaction.
+        check_usage().await;
+        assert_eq!(usage_after.tables, usage_before.tables);
+
+        // A compaction never makes a store bigger.
+        if let (Some(before), Some(after)) = (usage_before.total_size, usage_after.total_size) {
+            assert!(after <= before);
+        }
+    }
 }
 
 /// Macro building to allow your `EventCacheStore` implementation to run the
@@ -1231,6 +1726,13 @@ macro_rules! event_cache_store_integration_tests {
                 event_cache_store.test_remove_room().await;
             }
 
+            #[async_test]
+            async fn test_detached_linked_chunk() {
+                let event_cache_store =
+                    get_event_cache_store().await.unwrap().into_event_cache_store();
+                event_cache_store.test_detached_linked_chunk().await;
+            }
+
             #[async_test]
             async fn test_filter_duplicated_events() {
                 let event_cache_store =
@@ -1258,6 +1760,27 @@ macro_rules! event_cache_store_integration_tests {
                     get_event_cache_store().await.unwrap().into_event_cache_store();
                 event_cache_store.test_save_event().await;
             }
+
+            #[async_test]
+            async fn test_event_aggregations() {
+                let event_cache_store =
+                    get_event_cache_store().await.unwrap().into_event_cache_store();
+                event_cache_store.test_event_aggregations().await;
+            }
+
+            #[async_test]
+            async fn test_clean_up_linked_chunks() {
+                let event_cache_store =
+                    get_event_cache_store().await.unwrap().into_event_cache_store();
+                event_cache_store.test_clean_up_linked_chunks().await;
+            }
+
+            #[async_test]
+            async fn test_storage_usage() {
+                let event_cache_store =
+                    get_event_cache_store().await.unwrap().into_event_cache_store();
+                event_cache_store.test_storage_usage().await;
+            }
         }
     };
 }
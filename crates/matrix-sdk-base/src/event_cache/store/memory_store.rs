@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     num::NonZeroUsize,
     sync::{Arc, RwLock as StdRwLock},
 };
@@ -26,22 +26,23 @@ use matrix_sdk_common::{
     },
     ring_buffer::RingBuffer,
     store_locks::memory_store_helper::try_take_leased_lock,
+    store_usage::{ItemsUsage, StoreUsage},
 };
 use ruma::{
     events::relation::RelationType,
     time::{Instant, SystemTime},
-    EventId, MxcUri, OwnedEventId, OwnedMxcUri, RoomId,
+    EventId, MilliSecondsSinceUnixEpoch, MxcUri, OwnedEventId, OwnedMxcUri, OwnedRoomId, RoomId,
 };
 use tracing::error;
 
 use super::{
     compute_filters_string, extract_event_relation,
     media::{EventCacheStoreMedia, IgnoreMediaRetentionPolicy, MediaRetentionPolicy, MediaService},
-    EventCacheStore, EventCacheStoreError, Result,
+    EventAggregations, EventCacheStore, EventCacheStoreError, EventRetentionPolicy, Result,
 };
 use crate::{
     event_cache::{Event, Gap},
-    media::{MediaRequestParameters, UniqueKey as _},
+    media::{guess_mime_type, MediaRequestParameters, UniqueKey as _},
 };
 
 /// In-memory, non-persistent implementation of the `EventCacheStore`.
@@ -58,6 +59,8 @@ struct MemoryStoreInner {
     media: RingBuffer<MediaContent>,
     leases: HashMap<String, (String, Instant)>,
     events: RelationalLinkedChunk<OwnedEventId, Event, Gap>,
+    aggregations: HashMap<OwnedRoomId, BTreeMap<OwnedEventId, EventAggregations>>,
+    event_retention_policy: EventRetentionPolicy,
     media_retention_policy: Option<MediaRetentionPolicy>,
     last_media_cleanup_time: SystemTime,
 }
@@ -71,6 +74,9 @@ struct MediaContent {
     /// The unique key of the content.
     key: String,
 
+    /// The request the content was stored with.
+    request: MediaRequestParameters,
+
     /// The bytes of the content.
     data: Vec<u8>,
 
@@ -95,6 +101,8 @@ impl Default for MemoryStore {
                 media: RingBuffer::new(NUMBER_OF_MEDIAS),
                 leases: Default::default(),
                 events: RelationalLinkedChunk::new(),
+                aggregations: Default::default(),
+                event_retention_policy: EventRetentionPolicy::default(),
                 media_retention_policy: None,
                 last_media_cleanup_time,
             })),
@@ -132,6 +140,18 @@ impl EventCacheStore for MemoryStore {
         updates: Vec<Update<Event, Gap>>,
     ) -> Result<(), Self::Error> {
         let mut inner = self.inner.write().unwrap();
+
+        // The aggregations are indexed per room, so they're cleared along with the
+        // room's linked chunk.
+        if updates.iter().any(|update| matches!(update, Update::Clear)) {
+            match linked_chunk_id {
+                LinkedChunkId::Room(room_id) => {
+                    inner.aggregations.remove(room_id);
+                }
+                LinkedChunkId::Detached(_) => {}
+            }
+        }
+
         inner.events.apply_updates(linked_chunk_id, updates);
 
         Ok(())
@@ -183,7 +203,9 @@ impl EventCacheStore for MemoryStore {
     }
 
     async fn clear_all_linked_chunks(&self) -> Result<(), Self::Error> {
-        self.inner.write().unwrap().events.clear();
+        let mut inner = self.inner.write().unwrap();
+        inner.events.clear();
+        inner.aggregations.clear();
         Ok(())
     }
 
@@ -278,6 +300,105 @@ impl EventCacheStore for MemoryStore {
         Ok(())
     }
 
+    async fn get_all_room_events(&self, room_id: &RoomId) -> Result<Vec<Event>, Self::Error> {
+        let inner = self.inner.read().unwrap();
+
+        let target_linked_chunk_id = OwnedLinkedChunkId::Room(room_id.to_owned());
+
+        Ok(inner.events.items(&target_linked_chunk_id).map(|(event, _pos)| event.clone()).collect())
+    }
+
+    async fn save_event_aggregations(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        aggregations: EventAggregations,
+    ) -> Result<(), Self::Error> {
+        let mut inner = self.inner.write().unwrap();
+
+        if aggregations.is_empty() {
+            if let Some(room_aggregations) = inner.aggregations.get_mut(room_id) {
+                room_aggregations.remove(event_id);
+            }
+        } else {
+            inner
+                .aggregations
+                .entry(room_id.to_owned())
+                .or_default()
+                .insert(event_id.to_owned(), aggregations);
+        }
+
+        Ok(())
+    }
+
+    async fn load_event_aggregations(
+        &self,
+        room_id: &RoomId,
+        event_ids: &[OwnedEventId],
+    ) -> Result<BTreeMap<OwnedEventId, EventAggregations>, Self::Error> {
+        let inner = self.inner.read().unwrap();
+
+        let Some(room_aggregations) = inner.aggregations.get(room_id) else {
+            return Ok(BTreeMap::new());
+        };
+
+        Ok(event_ids
+            .iter()
+            .filter_map(|event_id| {
+                Some((event_id.clone(), room_aggregations.get(event_id)?.clone()))
+            })
+            .collect())
+    }
+
+    async fn set_event_retention_policy(
+        &self,
+        policy: EventRetentionPolicy,
+    ) -> Result<(), Self::Error> {
+        self.inner.write().unwrap().event_retention_policy = policy;
+        Ok(())
+    }
+
+    fn event_retention_policy(&self) -> EventRetentionPolicy {
+        self.inner.read().unwrap().event_retention_policy
+    }
+
+    async fn clean_up_linked_chunks(&self) -> Result<(), Self::Error> {
+        let mut inner = self.inner.write().unwrap();
+
+        // The policy only applies to the linked chunks of the rooms, not to the
+        // detached ones.
+        let linked_chunks = inner
+            .events
+            .linked_chunk_ids()
+            .into_iter()
+            .filter(|linked_chunk_id| matches!(linked_chunk_id, OwnedLinkedChunkId::Room(_)))
+            .map(|linked_chunk_id| {
+                let chunks = inner
+                    .events
+                    .load_all_chunks(linked_chunk_id.as_ref())
+                    .map_err(|err| EventCacheStoreError::InvalidData { details: err })?;
+                Ok((linked_chunk_id, chunks))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let evictions = inner
+            .event_retention_policy
+            .compute_evictions(linked_chunks, MilliSecondsSinceUnixEpoch::now());
+
+        for (linked_chunk_id, eviction) in evictions {
+            inner.events.apply_updates(linked_chunk_id.as_ref(), eviction.updates());
+            inner.events.remove_items(linked_chunk_id.as_ref(), &eviction.evicted_events);
+
+            if let Some(room_aggregations) = inner.aggregations.get_mut(linked_chunk_id.room_id()) {
+                for event_id in &eviction.evicted_events {
+                    room_aggregations.remove(event_id);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     async fn add_media_content(
         &self,
         request: &MediaRequestParameters,
@@ -301,6 +422,7 @@ impl EventCacheStore for MemoryStore {
         {
             media_content.uri = to.uri().to_owned();
             media_content.key = to.unique_key();
+            media_content.request = to.clone();
         }
 
         Ok(())
@@ -373,6 +495,62 @@ impl EventCacheStore for MemoryStore {
     async fn clean_up_media_cache(&self) -> Result<(), Self::Error> {
         self.media_service.clean_up_media_cache(self).await
     }
+
+    async fn get_all_media_content(
+        &self,
+    ) -> Result<Vec<(MediaRequestParameters, Vec<u8>, IgnoreMediaRetentionPolicy)>, Self::Error>
+    {
+        let inner = self.inner.read().unwrap();
+
+        Ok(inner
+            .media
+            .iter()
+            .map(|media| {
+                let ignore_policy = if media.ignore_policy {
+                    IgnoreMediaRetentionPolicy::Yes
+                } else {
+                    IgnoreMediaRetentionPolicy::No
+                };
+                (media.request.clone(), media.data.clone(), ignore_policy)
+            })
+            .collect())
+    }
+
+    async fn storage_usage(&self) -> Result<StoreUsage, Self::Error> {
+        // Nothing is stored on disk, so there's nothing to report.
+        Ok(StoreUsage::default())
+    }
+
+    async fn room_events_usage(&self, room_id: &RoomId) -> Result<ItemsUsage, Self::Error> {
+        let inner = self.inner.read().unwrap();
+
+        let mut usage = ItemsUsage::default();
+
+        for (event, _pos) in inner.events.items(&OwnedLinkedChunkId::Room(room_id.to_owned())) {
+            usage.add(event.raw().json().get().len() as u64);
+        }
+
+        Ok(usage)
+    }
+
+    async fn media_usage(&self) -> Result<BTreeMap<String, ItemsUsage>, Self::Error> {
+        let inner = self.inner.read().unwrap();
+
+        let mut usage = BTreeMap::<String, ItemsUsage>::new();
+
+        for media in inner.media.iter() {
+            usage
+                .entry(guess_mime_type(&media.data).to_owned())
+                .or_default()
+                .add(media.data.len() as u64);
+        }
+
+        Ok(usage)
+    }
+
+    async fn compact(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 #[cfg_attr(target_family = "wasm", async_trait(?Send))]
@@ -417,6 +595,7 @@ impl EventCacheStoreMedia for MemoryStore {
         inner.media.push(MediaContent {
             uri: request.uri().to_owned(),
             key: request.unique_key(),
+            request: request.clone(),
             data,
             ignore_policy,
             last_access,
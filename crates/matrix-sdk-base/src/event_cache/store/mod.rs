@@ -24,6 +24,8 @@ use std::{fmt, ops::Deref, str::Utf8Error, sync::Arc};
 #[cfg(any(test, feature = "testing"))]
 #[macro_use]
 pub mod integration_tests;
+mod event_aggregations;
+mod event_retention_policy;
 pub mod media;
 mod memory_store;
 mod traits;
@@ -42,6 +44,8 @@ use tracing::trace;
 #[cfg(any(test, feature = "testing"))]
 pub use self::integration_tests::EventCacheStoreIntegrationTests;
 pub use self::{
+    event_aggregations::{EventAggregations, ReactionAggregation},
+    event_retention_policy::{EventRetentionPolicy, LinkedChunkEviction},
     memory_store::MemoryStore,
     traits::{DynEventCacheStore, EventCacheStore, IntoEventCacheStore, DEFAULT_CHUNK_CAPACITY},
 };
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{fmt, sync::Arc};
+use std::{collections::BTreeMap, fmt, sync::Arc};
 
 use async_trait::async_trait;
 use matrix_sdk_common::{
@@ -20,13 +20,14 @@ use matrix_sdk_common::{
         ChunkIdentifier, ChunkIdentifierGenerator, ChunkMetadata, LinkedChunkId, Position,
         RawChunk, Update,
     },
+    store_usage::{ItemsUsage, StoreUsage},
     AsyncTraitDeps,
 };
 use ruma::{events::relation::RelationType, EventId, MxcUri, OwnedEventId, RoomId};
 
 use super::{
     media::{IgnoreMediaRetentionPolicy, MediaRetentionPolicy},
-    EventCacheStoreError,
+    EventAggregations, EventCacheStoreError, EventRetentionPolicy,
 };
 use crate::{
     event_cache::{Event, Gap},
@@ -65,9 +66,11 @@ pub trait EventCacheStore: AsyncTraitDeps {
 
     /// Remove all data tied to a given room from the cache.
     async fn remove_room(&self, room_id: &RoomId) -> Result<(), Self::Error> {
-        // Right now, this means removing all the linked chunk. If implementations
+        // Right now, this means removing all the linked chunks. If implementations
         // override this behavior, they should *also* include this code.
-        self.handle_linked_chunk_updates(LinkedChunkId::Room(room_id), vec![Update::Clear]).await
+        self.handle_linked_chunk_updates(LinkedChunkId::Room(room_id), vec![Update::Clear]).await?;
+        self.handle_linked_chunk_updates(LinkedChunkId::Detached(room_id), vec![Update::Clear])
+            .await
     }
 
     /// Return all the raw components of a linked chunk, so the caller may
@@ -164,6 +167,63 @@ pub trait EventCacheStore: AsyncTraitDeps {
     /// without causing an error.
     async fn save_event(&self, room_id: &RoomId, event: Event) -> Result<(), Self::Error>;
 
+    /// Get all the events of a room, whether they are part of a linked chunk
+    /// or have been saved out-of-band with [`Self::save_event`].
+    async fn get_all_room_events(&self, room_id: &RoomId) -> Result<Vec<Event>, Self::Error>;
+
+    /// Save the aggregated relations of an event, replacing the previous ones.
+    ///
+    /// If `aggregations` is empty, the previous ones must be removed.
+    ///
+    /// The aggregations of a room must be removed when its linked chunk is
+    /// cleared with an [`Update::Clear`], or by
+    /// [`Self::clear_all_linked_chunks`].
+    async fn save_event_aggregations(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        aggregations: EventAggregations,
+    ) -> Result<(), Self::Error>;
+
+    /// Load the aggregated relations of the given events, if they have any.
+    ///
+    /// The events without aggregations are missing from the returned map.
+    async fn load_event_aggregations(
+        &self,
+        room_id: &RoomId,
+        event_ids: &[OwnedEventId],
+    ) -> Result<BTreeMap<OwnedEventId, EventAggregations>, Self::Error>;
+
+    /// Set the `EventRetentionPolicy` to use for deciding which events to keep
+    /// in the linked chunks.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The `EventRetentionPolicy` to use.
+    async fn set_event_retention_policy(
+        &self,
+        policy: EventRetentionPolicy,
+    ) -> Result<(), Self::Error>;
+
+    /// Get the current `EventRetentionPolicy`.
+    fn event_retention_policy(&self) -> EventRetentionPolicy;
+
+    /// Evict the oldest chunks of the linked chunks with the current
+    /// `EventRetentionPolicy`.
+    ///
+    /// The evicted chunks are removed with their events' content and
+    /// aggregations, and replaced by a gap, as computed by
+    /// [`EventRetentionPolicy::compute_evictions()`]. The content of an evicted
+    /// event is kept if another linked chunk still uses it.
+    ///
+    /// Only the linked chunks of the rooms are subject to the policy, the
+    /// [`LinkedChunkId::Detached`] linked chunks are left untouched.
+    ///
+    /// ⚠ This is meant only for super specific use cases, where there shouldn't
+    /// be any live in-memory linked chunks. In general, prefer using
+    /// `EventCache::apply_event_retention_policy()` from the common SDK crate.
+    async fn clean_up_linked_chunks(&self) -> Result<(), Self::Error>;
+
     /// Add a media file's content in the media store.
     ///
     /// # Arguments
@@ -286,6 +346,47 @@ pub trait EventCacheStore: AsyncTraitDeps {
     ///
     /// If there is already an ongoing cleanup, this is a noop.
     async fn clean_up_media_cache(&self) -> Result<(), Self::Error>;
+
+    /// Get the content of all the media files in the media store, along with
+    /// the request they were stored with and whether they ignore the
+    /// [`MediaRetentionPolicy`].
+    ///
+    /// The media files whose request isn't known by the store, because they
+    /// were stored before it was recorded, are skipped.
+    async fn get_all_media_content(
+        &self,
+    ) -> Result<Vec<(MediaRequestParameters, Vec<u8>, IgnoreMediaRetentionPolicy)>, Self::Error>;
+
+    /// Get how much space the store is using.
+    async fn storage_usage(&self) -> Result<StoreUsage, Self::Error>;
+
+    /// Get how many events of the given room are stored, and how much space
+    /// their content is using.
+    ///
+    /// This accounts for all the events of the room, whether they are in a
+    /// linked chunk or not. The size is the one of the events as they are
+    /// stored, so it may include the overhead of their serialization and
+    /// encryption by the store.
+    async fn room_events_usage(&self, room_id: &RoomId) -> Result<ItemsUsage, Self::Error>;
+
+    /// Get how many media files are stored, and how much space they are
+    /// using, grouped by MIME type.
+    ///
+    /// The MIME type of a media is guessed from its content when it is stored,
+    /// with [`guess_mime_type()`](crate::media::guess_mime_type), so that
+    /// this doesn't need to load the media. A store may report the media
+    /// stored before it recorded their MIME type with
+    /// [`UNKNOWN_MIME_TYPE`](crate::media::UNKNOWN_MIME_TYPE). Like for
+    /// [`Self::room_events_usage()`], the size may include the overhead of the
+    /// encryption by the store.
+    async fn media_usage(&self) -> Result<BTreeMap<String, ItemsUsage>, Self::Error>;
+
+    /// Compact the store, to give back to the system the space that isn't
+    /// used anymore.
+    ///
+    /// This may take a while on big stores, and may block other accesses to
+    /// the store in the meantime.
+    async fn compact(&self) -> Result<(), Self::Error>;
 }
 
 #[repr(transparent)]
@@ -385,6 +486,42 @@ impl<T: EventCacheStore> EventCacheStore for EraseEventCacheStoreError<T> {
         self.0.save_event(room_id, event).await.map_err(Into::into)
     }
 
+    async fn get_all_room_events(&self, room_id: &RoomId) -> Result<Vec<Event>, Self::Error> {
+        self.0.get_all_room_events(room_id).await.map_err(Into::into)
+    }
+
+    async fn save_event_aggregations(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        aggregations: EventAggregations,
+    ) -> Result<(), Self::Error> {
+        self.0.save_event_aggregations(room_id, event_id, aggregations).await.map_err(Into::into)
+    }
+
+    async fn load_event_aggregations(
+        &self,
+        room_id: &RoomId,
+        event_ids: &[OwnedEventId],
+    ) -> Result<BTreeMap<OwnedEventId, EventAggregations>, Self::Error> {
+        self.0.load_event_aggregations(room_id, event_ids).await.map_err(Into::into)
+    }
+
+    async fn set_event_retention_policy(
+        &self,
+        policy: EventRetentionPolicy,
+    ) -> Result<(), Self::Error> {
+        self.0.set_event_retention_policy(policy).await.map_err(Into::into)
+    }
+
+    fn event_retention_policy(&self) -> EventRetentionPolicy {
+        self.0.event_retention_policy()
+    }
+
+    async fn clean_up_linked_chunks(&self) -> Result<(), Self::Error> {
+        self.0.clean_up_linked_chunks().await.map_err(Into::into)
+    }
+
     async fn add_media_content(
         &self,
         request: &MediaRequestParameters,
@@ -449,6 +586,29 @@ impl<T: EventCacheStore> EventCacheStore for EraseEventCacheStoreError<T> {
     async fn clean_up_media_cache(&self) -> Result<(), Self::Error> {
         self.0.clean_up_media_cache().await.map_err(Into::into)
     }
+
+    async fn get_all_media_content(
+        &self,
+    ) -> Result<Vec<(MediaRequestParameters, Vec<u8>, IgnoreMediaRetentionPolicy)>, Self::Error>
+    {
+        self.0.get_all_media_content().await.map_err(Into::into)
+    }
+
+    async fn storage_usage(&self) -> Result<StoreUsage, Self::Error> {
+        self.0.storage_usage().await.map_err(Into::into)
+    }
+
+    async fn room_events_usage(&self, room_id: &RoomId) -> Result<ItemsUsage, Self::Error> {
+        self.0.room_events_usage(room_id).await.map_err(Into::into)
+    }
+
+    async fn media_usage(&self) -> Result<BTreeMap<String, ItemsUsage>, Self::Error> {
+        self.0.media_usage().await.map_err(Into::into)
+    }
+
+    async fn compact(&self) -> Result<(), Self::Error> {
+        self.0.compact().await.map_err(Into::into)
+    }
 }
 
 /// A type-erased [`EventCacheStore`].
@@ -487,3 +647,9 @@ where
         unsafe { Arc::from_raw(ptr_erased) }
     }
 }
+
+impl IntoEventCacheStore for Arc<DynEventCacheStore> {
+    fn into_event_cache_store(self) -> Arc<DynEventCacheStore> {
+        self
+    }
+}
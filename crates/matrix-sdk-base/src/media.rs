@@ -214,6 +214,65 @@ impl MediaEventContent for LocationMessageEventContent {
     }
 }
 
+/// The MIME type reported by [`guess_mime_type()`] when the format of the data
+/// couldn't be recognized.
+pub const UNKNOWN_MIME_TYPE: &str = "application/octet-stream";
+
+/// Guess the MIME type of a media from the first bytes of its content.
+///
+/// The media cache doesn't keep the MIME type of the media it stores, so this
+/// looks for the signatures of the most common formats sent over Matrix. It
+/// returns [`UNKNOWN_MIME_TYPE`] if the format isn't recognized.
+pub fn guess_mime_type(data: &[u8]) -> &'static str {
+    // Signatures at the start of the data.
+    const PREFIXES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"BM", "image/bmp"),
+        (b"\x1a\x45\xdf\xa3", "video/webm"),
+        (b"OggS", "audio/ogg"),
+        (b"fLaC", "audio/flac"),
+        (b"ID3", "audio/mpeg"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"<svg", "image/svg+xml"),
+    ];
+
+    if let Some((_, mime_type)) = PREFIXES.iter().find(|(prefix, _)| data.starts_with(prefix)) {
+        return mime_type;
+    }
+
+    // RIFF containers have their format after the size of the chunk.
+    if data.len() >= 12 && data.starts_with(b"RIFF") {
+        match &data[8..12] {
+            b"WEBP" => return "image/webp",
+            b"WAVE" => return "audio/wav",
+            _ => {}
+        }
+    }
+
+    // ISO base media files have their brand after the size of the box.
+    if data.len() >= 12 && &data[4..8] == b"ftyp" {
+        return match &data[8..12] {
+            b"avif" | b"avis" => "image/avif",
+            b"heic" | b"heix" | b"mif1" => "image/heic",
+            b"qt  " => "video/quicktime",
+            b"M4A " => "audio/mp4",
+            _ => "video/mp4",
+        };
+    }
+
+    // MPEG audio frames start with a sync word.
+    if data.len() >= 2 && data[0] == 0xff && data[1] & 0xe0 == 0xe0 {
+        return "audio/mpeg";
+    }
+
+    UNKNOWN_MIME_TYPE
+}
+
 #[cfg(test)]
 mod tests {
     use ruma::mxc_uri;
@@ -256,4 +315,20 @@ mod tests {
 
         assert_eq!(file.uri(), mxc_uri);
     }
+
+    #[test]
+    fn test_guess_mime_type() {
+        assert_eq!(guess_mime_type(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), "image/png");
+        assert_eq!(guess_mime_type(b"\xff\xd8\xff\xe0\0\x10JFIF"), "image/jpeg");
+        assert_eq!(guess_mime_type(b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(guess_mime_type(b"RIFF\0\0\0\0WAVEfmt "), "audio/wav");
+        assert_eq!(guess_mime_type(b"\0\0\0\x18ftypmp42"), "video/mp4");
+        assert_eq!(guess_mime_type(b"\0\0\0\x1cftypavif"), "image/avif");
+        assert_eq!(guess_mime_type(b"OggS\0\x02"), "audio/ogg");
+
+        // Unknown formats, even when they look like a container.
+        assert_eq!(guess_mime_type(b""), UNKNOWN_MIME_TYPE);
+        assert_eq!(guess_mime_type(b"hello world"), UNKNOWN_MIME_TYPE);
+        assert_eq!(guess_mime_type(b"RIFF\0\0\0\0AVI LIST"), UNKNOWN_MIME_TYPE);
+    }
 }
@@ -40,7 +40,7 @@ use super::{
     RoomLoadSettings, ServerInfo, WellKnownResponse,
 };
 use crate::{
-    deserialized_responses::MemberEvent,
+    deserialized_responses::{MemberEvent, RawAnySyncOrStrippedState},
     store::{ChildTransactionId, QueueWedgeError, Result, SerializableEventContent, StateStoreExt},
     RoomInfo, RoomMemberships, RoomState, StateChanges, StateStoreDataKey, StateStoreDataValue,
 };
@@ -97,6 +97,11 @@ pub trait StateStoreIntegrationTests {
     async fn test_server_info_saving(&self);
     /// Test fetching room infos based on [`RoomLoadSettings`].
     async fn test_get_room_infos(&self);
+    /// Test that compacting the store keeps its data around.
+    async fn test_compact(&self) -> Result<()>;
+    /// Test listing all the state and account data events, whatever their
+    /// type.
+    async fn test_get_all_state_and_account_data(&self) -> Result<()>;
 }
 
 impl StateStoreIntegrationTests for DynStateStore {
@@ -931,6 +936,12 @@ impl StateStoreIntegrationTests for DynStateStore {
 
         assert_eq!(Some(value.as_ref()), read.as_deref());
 
+        self.set_custom_value(b"other_key", b"other_value".to_vec()).await?;
+        self.remove_custom_value(b"other_key").await?;
+
+        let all_values = self.get_all_custom_values().await?;
+        assert_eq!(all_values, [(key.as_bytes().to_vec(), value.to_vec())]);
+
         Ok(())
     }
 
@@ -1747,6 +1758,116 @@ impl StateStoreIntegrationTests for DynStateStore {
             assert_eq!(all_rooms.len(), 0);
         }
     }
+
+    async fn test_compact(&self) -> Result<()> {
+        self.populate().await?;
+
+        let usage_before = self.storage_usage().await?;
+        self.compact().await?;
+        let usage_after = self.storage_usage().await?;
+
+        // The data is still there after a compaction.
+        assert_eq!(usage_after.tables, usage_before.tables);
+        assert!(!self.get_room_infos(&RoomLoadSettings::All).await?.is_empty());
+        assert_eq!(
+            self.get_kv_data(StateStoreDataKey::SyncToken).await?.and_then(|v| v.into_sync_token()),
+            Some("t392-516_47314_0_7_1_1_1_11444_1".to_owned())
+        );
+
+        // A compaction never makes a store bigger.
+        if let (Some(before), Some(after)) = (usage_before.total_size, usage_after.total_size) {
+            assert!(after <= before);
+        }
+
+        Ok(())
+    }
+
+    async fn test_get_all_state_and_account_data(&self) -> Result<()> {
+        let room_id = room_id!("!all_state:localhost");
+        let stripped_room_id = room_id!("!all_stripped_state:localhost");
+
+        let mut changes = StateChanges::default();
+        changes.add_room(RoomInfo::new(room_id, RoomState::Joined));
+        changes.add_room(RoomInfo::new(stripped_room_id, RoomState::Invited));
+
+        let custom_state = Raw::new(&json!({
+            "type": "org.example.custom",
+            "state_key": "key",
+            "sender": user_id(),
+            "event_id": "$custom_state",
+            "origin_server_ts": 0,
+            "content": { "custom": true },
+        }))
+        .unwrap()
+        .cast();
+        changes
+            .state
+            .entry(room_id.to_owned())
+            .or_default()
+            .entry("org.example.custom".into())
+            .or_default()
+            .insert("key".to_owned(), custom_state);
+        changes
+            .state
+            .entry(room_id.to_owned())
+            .or_default()
+            .entry(StateEventType::RoomMember)
+            .or_default()
+            .insert(user_id().into(), membership_event().cast());
+        changes
+            .stripped_state
+            .entry(stripped_room_id.to_owned())
+            .or_default()
+            .entry(StateEventType::RoomMember)
+            .or_default()
+            .insert(user_id().into(), stripped_membership_event().cast());
+
+        let custom_account_data = Raw::new(&json!({
+            "type": "org.example.custom",
+            "content": { "custom": true },
+        }))
+        .unwrap()
+        .cast();
+        changes.account_data.insert("org.example.custom".into(), custom_account_data);
+        let custom_room_account_data = Raw::new(&json!({
+            "type": "org.example.custom",
+            "content": { "custom": true },
+        }))
+        .unwrap()
+        .cast();
+        changes
+            .room_account_data
+            .entry(room_id.to_owned())
+            .or_default()
+            .insert("org.example.custom".into(), custom_room_account_data);
+
+        self.save_changes(&changes).await?;
+
+        let state_events = self.get_all_state_events(room_id).await?;
+        assert_eq!(state_events.len(), 2);
+        assert!(state_events.iter().any(|event| {
+            matches!(event, RawAnySyncOrStrippedState::Sync(raw)
+                if raw.get_field::<String>("type").unwrap().as_deref() == Some("org.example.custom"))
+        }));
+
+        assert_matches!(
+            self.get_all_state_events(stripped_room_id).await?.as_slice(),
+            [RawAnySyncOrStrippedState::Stripped(_)]
+        );
+
+        let account_data = self.get_all_account_data_events().await?;
+        assert_eq!(account_data.len(), 1);
+        assert_eq!(
+            account_data[0].get_field::<String>("type").unwrap().as_deref(),
+            Some("org.example.custom")
+        );
+
+        let room_account_data = self.get_all_room_account_data_events(room_id).await?;
+        assert_eq!(room_account_data.len(), 1);
+        assert!(self.get_all_room_account_data_events(stripped_room_id).await?.is_empty());
+
+        Ok(())
+    }
 }
 
 /// Macro building to allow your StateStore implementation to run the entire
@@ -1917,6 +2038,18 @@ macro_rules! statestore_integration_tests {
                 let store = get_store().await.expect("creating store failed").into_state_store();
                 store.test_get_room_infos().await;
             }
+
+            #[async_test]
+            async fn test_compact() -> StoreResult<()> {
+                let store = get_store().await?.into_state_store();
+                store.test_compact().await
+            }
+
+            #[async_test]
+            async fn test_get_all_state_and_account_data() -> StoreResult<()> {
+                let store = get_store().await?.into_state_store();
+                store.test_get_all_state_and_account_data().await
+            }
         }
     };
 }
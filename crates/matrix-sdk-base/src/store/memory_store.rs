@@ -19,7 +19,7 @@ use std::{
 
 use async_trait::async_trait;
 use growable_bloom_filter::GrowableBloom;
-use matrix_sdk_common::ROOM_VERSION_FALLBACK;
+use matrix_sdk_common::{store_usage::StoreUsage, ROOM_VERSION_FALLBACK};
 use ruma::{
     canonical_json::{redact, RedactedBecause},
     events::{
@@ -565,6 +565,28 @@ impl StateStore for MemoryStore {
         }
     }
 
+    async fn get_all_state_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<RawAnySyncOrStrippedState>, Self::Error> {
+        let inner = self.inner.read().unwrap();
+
+        let stripped_events = inner
+            .stripped_room_state
+            .get(room_id)
+            .into_iter()
+            .flat_map(|events| events.values().flat_map(|events| events.values()))
+            .map(|event| RawAnySyncOrStrippedState::Stripped(event.clone()));
+        let sync_events = inner
+            .room_state
+            .get(room_id)
+            .into_iter()
+            .flat_map(|events| events.values().flat_map(|events| events.values()))
+            .map(|event| RawAnySyncOrStrippedState::Sync(event.clone()));
+
+        Ok(stripped_events.chain(sync_events).collect())
+    }
+
     async fn get_profile(
         &self,
         room_id: &RoomId,
@@ -705,6 +727,24 @@ impl StateStore for MemoryStore {
             .cloned())
     }
 
+    async fn get_all_account_data_events(&self) -> Result<Vec<Raw<AnyGlobalAccountDataEvent>>> {
+        Ok(self.inner.read().unwrap().account_data.values().cloned().collect())
+    }
+
+    async fn get_all_room_account_data_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<Raw<AnyRoomAccountDataEvent>>> {
+        Ok(self
+            .inner
+            .read()
+            .unwrap()
+            .room_account_data
+            .get(room_id)
+            .map(|events| events.values().cloned().collect())
+            .unwrap_or_default())
+    }
+
     async fn get_user_room_receipt_event(
         &self,
         room_id: &RoomId,
@@ -739,6 +779,10 @@ impl StateStore for MemoryStore {
         Ok(self.inner.write().unwrap().custom.remove(key))
     }
 
+    async fn get_all_custom_values(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        Ok(self.inner.read().unwrap().custom.clone().into_iter().collect())
+    }
+
     async fn remove_room(&self, room_id: &RoomId) -> Result<()> {
         let mut inner = self.inner.write().unwrap();
 
@@ -955,6 +999,15 @@ impl StateStore for MemoryStore {
             .cloned()
             .unwrap_or_default())
     }
+
+    async fn storage_usage(&self) -> Result<StoreUsage, Self::Error> {
+        // Nothing is stored on disk, so there's nothing to report.
+        Ok(StoreUsage::default())
+    }
+
+    async fn compact(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,1439 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Copy the content of a set of stores into another set of stores, e.g. to
+//! switch a session from one storage backend to another one without logging
+//! out.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    sync::Arc,
+};
+
+use matrix_sdk_common::linked_chunk::{
+    ChunkContent, ChunkIdentifier, LinkedChunkId, Position, RawChunk, Update,
+};
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk_crypto::store::{
+    types::{Changes, PendingChanges},
+    CryptoStoreError, DynCryptoStore, IntoCryptoStore,
+};
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk_crypto::types::events::room_key_withheld::{
+    MegolmV1AesSha2WithheldContent, RoomKeyWithheldContent,
+};
+#[cfg(feature = "e2e-encryption")]
+use ruma::events::secret::request::SecretName;
+use ruma::{
+    events::{
+        receipt::{Receipt, ReceiptEventContent, ReceiptThread, ReceiptType},
+        GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType,
+    },
+    OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UserId,
+};
+use tracing::{debug, info, instrument, warn};
+
+use super::{
+    DynStateStore, IntoStateStore, RoomLoadSettings, StateChanges, StateStoreDataKey, StoreError,
+};
+use crate::{
+    deserialized_responses::{DisplayName, RawAnySyncOrStrippedState},
+    event_cache::{
+        store::{
+            extract_event_relation, DynEventCacheStore, EventCacheStoreError, IntoEventCacheStore,
+        },
+        Event, Gap,
+    },
+    RoomMemberships,
+};
+
+/// The default number of records written to the target store at once, for
+/// the records that can be written in batches.
+const DEFAULT_BATCH_SIZE: usize = 500;
+
+/// Copies the content of stores into other stores, possibly using other
+/// backends.
+///
+/// Every record is read through the [`StateStore`], [`EventCacheStore`] and
+/// [`CryptoStore`] traits, so any implementation of these traits can be used
+/// as the source or the target of the migration. To migrate to an encrypted
+/// store, open the target store with a passphrase before giving it to the
+/// migrator.
+///
+/// The number of records in the target stores is checked against the source
+/// stores once they have been copied, and [`StoreMigrator::migrate`] fails with
+/// [`StoreMigrationError::CountMismatch`] if they differ.
+///
+/// The target stores are expected to be empty, and nothing must use the source
+/// or the target stores while the migration is running.
+///
+/// # Limitations
+///
+/// The traits don't allow to list every record, so the records that can only
+/// be looked up by a key that isn't stored anywhere else aren't migrated:
+///
+/// - the composer drafts of threads and the cached filters of the state store,
+/// - the outgoing secret requests that have already been sent, of the crypto
+///   store.
+///
+/// Some records can't be listed by older stores either:
+///
+/// - the custom values of an encrypted SQLite state store that haven't been
+///   read since the store was upgraded,
+/// - the media of an SQLite event cache store that were cached before the store
+///   was upgraded,
+/// - the hashes of the known Olm messages of an IndexedDB crypto store that
+///   were saved before the store was upgraded.
+///
+/// All of them are either caches or data that is fetched again from the
+/// homeserver when it's missing.
+///
+/// [`StateStore`]: super::StateStore
+/// [`EventCacheStore`]: crate::event_cache::store::EventCacheStore
+/// [`CryptoStore`]: matrix_sdk_crypto::store::CryptoStore
+///
+/// # Examples
+///
+/// ```
+/// # async {
+/// use matrix_sdk_base::{
+///     event_cache::store::MemoryStore as MemoryEventCacheStore,
+///     store::{MemoryStore, StoreMigrator},
+/// };
+///
+/// let report = StoreMigrator::new()
+///     .state_store(MemoryStore::new(), MemoryStore::new())
+///     .event_cache_store(
+///         MemoryEventCacheStore::new(),
+///         MemoryEventCacheStore::new(),
+///     )
+///     .migrate()
+///     .await?;
+///
+/// println!("Migrated {} rooms", report.rooms);
+/// # anyhow::Ok(()) };
+/// ```
+pub struct StoreMigrator {
+    state_stores: Option<(Arc<DynStateStore>, Arc<DynStateStore>)>,
+    event_cache_stores: Option<(Arc<DynEventCacheStore>, Arc<DynEventCacheStore>)>,
+    #[cfg(feature = "e2e-encryption")]
+    crypto_stores: Option<(Arc<DynCryptoStore>, Arc<DynCryptoStore>)>,
+    own_user_id: Option<OwnedUserId>,
+    batch_size: usize,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for StoreMigrator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StoreMigrator")
+            .field("own_user_id", &self.own_user_id)
+            .field("batch_size", &self.batch_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for StoreMigrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StoreMigrator {
+    /// Create a new `StoreMigrator` that doesn't migrate any store.
+    pub fn new() -> Self {
+        Self {
+            state_stores: None,
+            event_cache_stores: None,
+            #[cfg(feature = "e2e-encryption")]
+            crypto_stores: None,
+            own_user_id: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    /// Migrate the content of the `from` state store into the `to` state
+    /// store.
+    pub fn state_store(mut self, from: impl IntoStateStore, to: impl IntoStateStore) -> Self {
+        self.state_stores = Some((from.into_state_store(), to.into_state_store()));
+        self
+    }
+
+    /// Migrate the content of the `from` event cache store into the `to` event
+    /// cache store.
+    ///
+    /// The linked chunks of the rooms are listed from the state store, so a
+    /// state store must be migrated along with the event cache store.
+    pub fn event_cache_store(
+        mut self,
+        from: impl IntoEventCacheStore,
+        to: impl IntoEventCacheStore,
+    ) -> Self {
+        self.event_cache_stores =
+            Some((from.into_event_cache_store(), to.into_event_cache_store()));
+        self
+    }
+
+    /// Migrate the content of the `from` crypto store into the `to` crypto
+    /// store.
+    #[cfg(feature = "e2e-encryption")]
+    pub fn crypto_store(mut self, from: impl IntoCryptoStore, to: impl IntoCryptoStore) -> Self {
+        self.crypto_stores = Some((from.into_crypto_store(), to.into_crypto_store()));
+        self
+    }
+
+    /// Set the ID of the user of the session, to also migrate the data of the
+    /// state store that is keyed by user, like the recently visited rooms.
+    pub fn own_user_id(mut self, user_id: &UserId) -> Self {
+        self.own_user_id = Some(user_id.to_owned());
+        self
+    }
+
+    /// Set the maximum number of room keys written to the target crypto store
+    /// at once.
+    ///
+    /// Defaults to 500.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Copy the content of the source stores into the target stores, and
+    /// verify that the target stores contain as many records as the source
+    /// stores.
+    #[instrument(skip(self))]
+    pub async fn migrate(self) -> Result<StoreMigrationReport, StoreMigrationError> {
+        let mut report = StoreMigrationReport::default();
+
+        if self.event_cache_stores.is_some() && self.state_stores.is_none() {
+            return Err(StoreMigrationError::MissingStateStore);
+        }
+
+        let room_ids = match &self.state_stores {
+            Some((from, to)) => self.migrate_state_store(&**from, &**to, &mut report).await?,
+            None => Vec::new(),
+        };
+
+        if let Some((from, to)) = &self.event_cache_stores {
+            self.migrate_event_cache_store(&**from, &**to, &room_ids, &mut report).await?;
+        }
+
+        #[cfg(feature = "e2e-encryption")]
+        if let Some((from, to)) = &self.crypto_stores {
+            self.migrate_crypto_store(&**from, &**to, &room_ids, &mut report).await?;
+        }
+
+        info!(?report, "Stores migrated");
+
+        Ok(report)
+    }
+
+    /// Migrate the state store, and return the list of the rooms it knows.
+    async fn migrate_state_store(
+        &self,
+        from: &DynStateStore,
+        to: &DynStateStore,
+        report: &mut StoreMigrationReport,
+    ) -> Result<Vec<OwnedRoomId>, StoreMigrationError> {
+        let mut kv_keys = vec![
+            StateStoreDataKey::SyncToken,
+            StateStoreDataKey::ServerInfo,
+            StateStoreDataKey::UtdHookManagerData,
+        ];
+
+        if let Some(user_id) = &self.own_user_id {
+            kv_keys.push(StateStoreDataKey::UserAvatarUrl(user_id));
+            kv_keys.push(StateStoreDataKey::RecentlyVisitedRooms(user_id));
+        }
+
+        for key in kv_keys {
+            copy_kv_data(from, to, key).await?;
+        }
+
+        // Global account data.
+        let mut changes = StateChanges::default();
+
+        for event in from.get_all_account_data_events().await? {
+            match event.get_field::<String>("type") {
+                Ok(Some(event_type)) => {
+                    changes
+                        .account_data
+                        .insert(GlobalAccountDataEventType::from(event_type), event);
+                }
+                _ => debug!("Skipping an account data event without a valid type"),
+            }
+        }
+
+        report.account_data_events += changes.account_data.len();
+
+        // Rooms.
+        let room_infos = from.get_room_infos(&RoomLoadSettings::All).await?;
+        let mut room_ids = Vec::with_capacity(room_infos.len());
+        let mut user_ids = BTreeSet::new();
+
+        for room_info in room_infos {
+            let room_id = room_info.room_id.clone();
+            let room_user_ids = from.get_user_ids(&room_id, RoomMemberships::empty()).await?;
+
+            let mut room_changes = StateChanges::default();
+            self.collect_room_state(from, &room_id, &room_user_ids, &mut room_changes, report)
+                .await?;
+            room_changes.add_room(room_info);
+            to.save_changes(&room_changes).await?;
+
+            copy_kv_data(from, to, StateStoreDataKey::ComposerDraft(&room_id, None)).await?;
+            copy_kv_data(from, to, StateStoreDataKey::SeenKnockRequests(&room_id)).await?;
+
+            user_ids.extend(room_user_ids);
+            room_ids.push(room_id);
+            report.rooms += 1;
+        }
+
+        // Presence.
+        let user_ids = user_ids.into_iter().collect::<Vec<_>>();
+
+        for event in from.get_presence_events(&user_ids).await? {
+            match event.get_field::<OwnedUserId>("sender") {
+                Ok(Some(sender)) => {
+                    changes.presence.insert(sender, event);
+                }
+                _ => debug!("Skipping a presence event without a valid sender"),
+            }
+        }
+
+        to.save_changes(&changes).await?;
+
+        // Custom values.
+        for (key, value) in from.get_all_custom_values().await? {
+            to.set_custom_value_no_read(&key, value).await?;
+            report.custom_values += 1;
+        }
+
+        // Send queue.
+        let mut send_queue_room_ids = room_ids.iter().cloned().collect::<BTreeSet<_>>();
+        send_queue_room_ids.extend(from.load_rooms_with_unsent_requests().await?);
+
+        for room_id in &send_queue_room_ids {
+            for request in from.load_send_queue_requests(room_id).await? {
+                to.save_send_queue_request(
+                    room_id,
+                    request.transaction_id.clone(),
+                    request.created_at,
+                    request.kind,
+                    request.priority,
+                )
+                .await?;
+
+                if let Some(error) = request.error {
+                    to.update_send_queue_request_status(
+                        room_id,
+                        &request.transaction_id,
+                        Some(error),
+                    )
+                    .await?;
+                }
+
+                report.send_queue_requests += 1;
+            }
+
+            for request in from.load_dependent_queued_requests(room_id).await? {
+                to.save_dependent_queued_request(
+                    room_id,
+                    &request.parent_transaction_id,
+                    request.own_transaction_id,
+                    request.created_at,
+                    request.kind,
+                )
+                .await?;
+
+                if let Some(parent_key) = request.parent_key {
+                    to.mark_dependent_queued_requests_as_ready(
+                        room_id,
+                        &request.parent_transaction_id,
+                        parent_key,
+                    )
+                    .await?;
+                }
+
+                report.send_queue_requests += 1;
+            }
+        }
+
+        // Verification.
+        check_count(
+            "rooms",
+            room_ids.len(),
+            to.get_room_infos(&RoomLoadSettings::All).await?.len(),
+        )?;
+
+        for room_id in &room_ids {
+            check_count(
+                "room members",
+                from.get_user_ids(room_id, RoomMemberships::empty()).await?.len(),
+                to.get_user_ids(room_id, RoomMemberships::empty()).await?.len(),
+            )?;
+        }
+
+        for room_id in &send_queue_room_ids {
+            check_count(
+                "send queue requests",
+                from.load_send_queue_requests(room_id).await?.len(),
+                to.load_send_queue_requests(room_id).await?.len(),
+            )?;
+            check_count(
+                "dependent send queue requests",
+                from.load_dependent_queued_requests(room_id).await?.len(),
+                to.load_dependent_queued_requests(room_id).await?.len(),
+            )?;
+        }
+
+        Ok(room_ids)
+    }
+
+    /// Collect the state, members, account data and receipts of a room from
+    /// the source state store.
+    async fn collect_room_state(
+        &self,
+        from: &DynStateStore,
+        room_id: &RoomId,
+        user_ids: &[OwnedUserId],
+        changes: &mut StateChanges,
+        report: &mut StoreMigrationReport,
+    ) -> Result<(), StoreMigrationError> {
+        for event in from.get_all_state_events(room_id).await? {
+            let (event_type, state_key) = match &event {
+                RawAnySyncOrStrippedState::Sync(raw) => {
+                    (raw.get_field::<String>("type"), raw.get_field::<String>("state_key"))
+                }
+                RawAnySyncOrStrippedState::Stripped(raw) => {
+                    (raw.get_field::<String>("type"), raw.get_field::<String>("state_key"))
+                }
+            };
+
+            let (Ok(Some(event_type)), Ok(Some(state_key))) = (event_type, state_key) else {
+                debug!(?room_id, "Skipping a state event without a valid type or state key");
+                continue;
+            };
+            let event_type = StateEventType::from(event_type);
+
+            match event {
+                RawAnySyncOrStrippedState::Sync(raw) => {
+                    changes
+                        .state
+                        .entry(room_id.to_owned())
+                        .or_default()
+                        .entry(event_type)
+                        .or_default()
+                        .insert(state_key, raw);
+                }
+                RawAnySyncOrStrippedState::Stripped(raw) => {
+                    changes
+                        .stripped_state
+                        .entry(room_id.to_owned())
+                        .or_default()
+                        .entry(event_type)
+                        .or_default()
+                        .insert(state_key, raw);
+                }
+            }
+
+            report.state_events += 1;
+        }
+
+        // Profiles and the display names they use.
+        let profiles = from.get_profiles(room_id, user_ids).await?;
+        let mut display_names = Vec::<DisplayName>::new();
+
+        for display_name in profiles
+            .values()
+            .filter_map(|profile| profile.as_original()?.content.displayname.as_deref())
+            .map(DisplayName::new)
+        {
+            if !display_names.contains(&display_name) {
+                display_names.push(display_name);
+            }
+        }
+
+        changes.profiles.insert(
+            room_id.to_owned(),
+            profiles.into_iter().map(|(user_id, profile)| (user_id.to_owned(), profile)).collect(),
+        );
+
+        let ambiguity_map = from
+            .get_users_with_display_names(room_id, &display_names)
+            .await?
+            .into_iter()
+            .map(|(display_name, user_ids)| (display_name.clone(), user_ids))
+            .collect::<HashMap<_, _>>();
+        changes.ambiguity_maps.insert(room_id.to_owned(), ambiguity_map);
+
+        // Room account data.
+        for event in from.get_all_room_account_data_events(room_id).await? {
+            let Ok(Some(event_type)) = event.get_field::<String>("type") else {
+                debug!(?room_id, "Skipping a room account data event without a valid type");
+                continue;
+            };
+
+            changes
+                .room_account_data
+                .entry(room_id.to_owned())
+                .or_default()
+                .insert(RoomAccountDataEventType::from(event_type), event);
+            report.account_data_events += 1;
+        }
+
+        // Receipts, only the unthreaded and main thread ones can be listed.
+        let mut receipts = BTreeMap::<OwnedEventId, BTreeMap<_, BTreeMap<_, Receipt>>>::new();
+
+        for receipt_type in [ReceiptType::Read, ReceiptType::ReadPrivate] {
+            for thread in [ReceiptThread::Unthreaded, ReceiptThread::Main] {
+                for user_id in user_ids {
+                    let Some((event_id, receipt)) = from
+                        .get_user_room_receipt_event(
+                            room_id,
+                            receipt_type.clone(),
+                            thread.clone(),
+                            user_id,
+                        )
+                        .await?
+                    else {
+                        continue;
+                    };
+
+                    receipts
+                        .entry(event_id)
+                        .or_default()
+                        .entry(receipt_type.clone())
+                        .or_default()
+                        .insert(user_id.clone(), receipt);
+                }
+            }
+        }
+
+        if !receipts.is_empty() {
+            changes.receipts.insert(room_id.to_owned(), ReceiptEventContent(receipts));
+        }
+
+        Ok(())
+    }
+
+    /// Migrate the retention policies, the media cache, and the linked chunks,
+    /// events and aggregations of the given rooms.
+    async fn migrate_event_cache_store(
+        &self,
+        from: &DynEventCacheStore,
+        to: &DynEventCacheStore,
+        room_ids: &[OwnedRoomId],
+        report: &mut StoreMigrationReport,
+    ) -> Result<(), StoreMigrationError> {
+        to.set_event_retention_policy(from.event_retention_policy()).await?;
+        to.set_media_retention_policy(from.media_retention_policy()).await?;
+
+        for room_id in room_ids {
+            let mut linked_chunk_event_ids = BTreeSet::new();
+
+            for linked_chunk_id in [LinkedChunkId::Room(room_id), LinkedChunkId::Detached(room_id)]
+            {
+                migrate_linked_chunk(
+                    from,
+                    to,
+                    linked_chunk_id,
+                    &mut linked_chunk_event_ids,
+                    report,
+                )
+                .await?;
+            }
+
+            // The events that aren't part of a linked chunk, and the aggregations, which
+            // are keyed by the event they relate to, even if it isn't known yet.
+            let events = from.get_all_room_events(room_id).await?;
+            let mut event_ids = BTreeSet::new();
+
+            for event in events {
+                if let Some((related_event_id, _)) = extract_event_relation(event.raw()) {
+                    event_ids.insert(related_event_id);
+                }
+
+                let Some(event_id) = event.event_id() else {
+                    continue;
+                };
+
+                if !linked_chunk_event_ids.contains(&event_id) {
+                    to.save_event(room_id, event).await?;
+                    report.out_of_band_events += 1;
+                }
+
+                event_ids.insert(event_id);
+            }
+
+            let event_ids = event_ids.into_iter().collect::<Vec<_>>();
+
+            for (event_id, aggregations) in
+                from.load_event_aggregations(room_id, &event_ids).await?
+            {
+                to.save_event_aggregations(room_id, &event_id, aggregations).await?;
+            }
+
+            // Verification.
+            check_count(
+                "events",
+                from.get_all_room_events(room_id).await?.len(),
+                to.get_all_room_events(room_id).await?.len(),
+            )?;
+        }
+
+        // Media.
+        let media = from.get_all_media_content().await?;
+        let num_media = media.len();
+
+        for (request, content, ignore_policy) in media {
+            to.add_media_content(&request, content, ignore_policy).await?;
+        }
+
+        report.media += num_media;
+
+        check_count("media", num_media, to.get_all_media_content().await?.len())?;
+
+        Ok(())
+    }
+
+    /// Migrate the crypto store, using the given rooms to find the outbound
+    /// group sessions and room settings.
+    #[cfg(feature = "e2e-encryption")]
+    async fn migrate_crypto_store(
+        &self,
+        from: &DynCryptoStore,
+        to: &DynCryptoStore,
+        room_ids: &[OwnedRoomId],
+        report: &mut StoreMigrationReport,
+    ) -> Result<(), StoreMigrationError> {
+        let Some(account) = from.load_account().await? else {
+            warn!("The source crypto store doesn't contain an account, nothing to migrate");
+            return Ok(());
+        };
+
+        let own_user_id = account.user_id().to_owned();
+
+        // The account is needed by the target store to save all the other records.
+        to.save_pending_changes(PendingChanges { account: Some(account) }).await?;
+
+        let backup_keys = from.load_backup_keys().await?;
+        let backup_version = backup_keys.backup_version;
+
+        let mut changes = Changes {
+            private_identity: from.load_identity().await?,
+            backup_version: backup_version.clone(),
+            backup_decryption_key: backup_keys.decryption_key,
+            dehydrated_device_pickle_key: from.load_dehydrated_device_pickle_key().await?,
+            next_batch_token: from.next_batch_token().await?,
+            key_requests: from.get_unsent_secret_requests().await?,
+            message_hashes: from.get_all_olm_message_hashes().await?,
+            received_room_key_bundles: from.get_all_received_room_key_bundle_data().await?,
+            ..Default::default()
+        };
+
+        // Only the withheld info of the Megolm v1 sessions are stored, which all
+        // contain the room and session IDs.
+        for event in from.get_all_withheld_info().await? {
+            if let RoomKeyWithheldContent::MegolmV1AesSha2(
+                MegolmV1AesSha2WithheldContent::BlackListed(content)
+                | MegolmV1AesSha2WithheldContent::Unverified(content),
+            ) = &event.content
+            {
+                changes
+                    .withheld_session_info
+                    .entry(content.room_id.clone())
+                    .or_default()
+                    .insert(content.session_id.clone(), event.clone());
+            }
+        }
+
+        // Users and their devices.
+        let tracked_users = from.load_tracked_users().await?;
+        to.save_tracked_users(
+            &tracked_users.iter().map(|user| (&*user.user_id, user.dirty)).collect::<Vec<_>>(),
+        )
+        .await?;
+
+        let mut user_ids = tracked_users.iter().map(|user| &*user.user_id).collect::<BTreeSet<_>>();
+        user_ids.insert(&own_user_id);
+
+        for &user_id in &user_ids {
+            let devices = from.get_user_devices(user_id).await?;
+            report.devices += devices.len();
+            changes.devices.new.extend(devices.into_values());
+
+            if let Some(identity) = from.get_user_identity(user_id).await? {
+                changes.identities.new.push(identity);
+                report.user_identities += 1;
+            }
+        }
+
+        // All the Olm sessions, including the ones with devices of users we don't
+        // track anymore.
+        let sessions = from.get_all_sessions().await?;
+        report.olm_sessions += sessions.len();
+        changes.sessions.extend(sessions);
+
+        for secret_name in [
+            SecretName::CrossSigningMasterKey,
+            SecretName::CrossSigningUserSigningKey,
+            SecretName::CrossSigningSelfSigningKey,
+            SecretName::RecoveryKey,
+        ] {
+            changes.secrets.extend(from.get_secrets_from_inbox(&secret_name).await?);
+        }
+
+        // Room keys.
+        let inbound_group_sessions = from.get_inbound_group_sessions().await?;
+
+        let mut room_ids = room_ids.iter().map(|room_id| &**room_id).collect::<BTreeSet<_>>();
+        room_ids.extend(inbound_group_sessions.iter().map(|session| session.room_id()));
+
+        for &room_id in &room_ids {
+            if let Some(session) = from.get_outbound_group_session(room_id).await? {
+                changes.outbound_group_sessions.push(session);
+                report.outbound_group_sessions += 1;
+            }
+
+            if let Some(settings) = from.get_room_settings(room_id).await? {
+                changes.room_settings.insert(room_id.to_owned(), settings);
+            }
+        }
+
+        to.save_changes(changes).await?;
+
+        for (key, value) in from.get_all_custom_values().await? {
+            to.set_custom_value(&key, value).await?;
+            report.custom_values += 1;
+        }
+
+        let (backed_up, not_backed_up): (Vec<_>, Vec<_>) =
+            inbound_group_sessions.into_iter().partition(|session| session.backed_up());
+
+        for (sessions, backed_up_to_version) in
+            [(backed_up, backup_version.as_deref()), (not_backed_up, None)]
+        {
+            let mut sessions = sessions.into_iter().peekable();
+
+            while sessions.peek().is_some() {
+                let batch = sessions.by_ref().take(self.batch_size).collect::<Vec<_>>();
+                report.inbound_group_sessions += batch.len();
+                to.save_inbound_group_sessions(batch, backed_up_to_version).await?;
+            }
+        }
+
+        // Verification.
+        let expected = from.inbound_group_session_counts(backup_version.as_deref()).await?;
+        let found = to.inbound_group_session_counts(backup_version.as_deref()).await?;
+        check_count("inbound group sessions", expected.total, found.total)?;
+        check_count("backed up inbound group sessions", expected.backed_up, found.backed_up)?;
+
+        check_count("tracked users", tracked_users.len(), to.load_tracked_users().await?.len())?;
+
+        for &user_id in &user_ids {
+            check_count(
+                "devices",
+                from.get_user_devices(user_id).await?.len(),
+                to.get_user_devices(user_id).await?.len(),
+            )?;
+        }
+
+        check_count(
+            "Olm sessions",
+            from.get_all_sessions().await?.len(),
+            to.get_all_sessions().await?.len(),
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Copy a key-value entry of the state store, if it exists.
+async fn copy_kv_data(
+    from: &DynStateStore,
+    to: &DynStateStore,
+    key: StateStoreDataKey<'_>,
+) -> Result<(), StoreError> {
+    if let Some(value) = from.get_kv_data(key).await? {
+        to.set_kv_data(key, value).await?;
+    }
+
+    Ok(())
+}
+
+/// Migrate a linked chunk, and add the IDs of its events to `event_ids`.
+async fn migrate_linked_chunk(
+    from: &DynEventCacheStore,
+    to: &DynEventCacheStore,
+    linked_chunk_id: LinkedChunkId<'_>,
+    event_ids: &mut BTreeSet<OwnedEventId>,
+    report: &mut StoreMigrationReport,
+) -> Result<(), StoreMigrationError> {
+    let chunks = from.load_all_chunks(linked_chunk_id).await?;
+
+    if chunks.is_empty() {
+        return Ok(());
+    }
+
+    // Clear the target linked chunk first, so running the migration again gives the
+    // same result.
+    let mut updates = vec![Update::Clear];
+
+    for fragment in sort_chunks(linked_chunk_id.room_id(), chunks) {
+        let mut previous = None;
+
+        for chunk in fragment {
+            let identifier = chunk.identifier;
+
+            match chunk.content {
+                ChunkContent::Gap(gap) => {
+                    updates.push(Update::NewGapChunk {
+                        previous,
+                        new: identifier,
+                        next: None,
+                        gap,
+                    });
+                }
+                ChunkContent::Items(items) => {
+                    updates.push(Update::NewItemsChunk { previous, new: identifier, next: None });
+
+                    if !items.is_empty() {
+                        report.events += items.len();
+                        event_ids.extend(items.iter().filter_map(|event| event.event_id()));
+                        updates.push(Update::PushItems { at: Position::new(identifier, 0), items });
+                    }
+                }
+            }
+
+            previous = Some(identifier);
+            report.chunks += 1;
+        }
+    }
+
+    to.handle_linked_chunk_updates(linked_chunk_id, updates).await?;
+
+    // Verification.
+    let expected = from.load_all_chunks_metadata(linked_chunk_id).await?;
+    let found = to.load_all_chunks_metadata(linked_chunk_id).await?;
+
+    check_count("linked chunks", expected.len(), found.len())?;
+    check_count(
+        "events",
+        expected.iter().map(|chunk| chunk.num_items).sum(),
+        found.iter().map(|chunk| chunk.num_items).sum(),
+    )?;
+
+    Ok(())
+}
+
+/// Sort the chunks of a linked chunk into its fragments, each one from its
+/// first chunk to its last chunk.
+///
+/// A linked chunk can be made of several fragments, for example after some
+/// chunks were evicted. The first chunk of a fragment has no previous chunk,
+/// or a previous chunk that isn't in the store. The fragments are sorted by
+/// the identifier of their first chunk.
+///
+/// Chunks that can't be reached from the first chunk of a fragment, like the
+/// chunks of a cycle, are dropped, which is caught by the verification of the
+/// number of chunks.
+fn sort_chunks(
+    room_id: &RoomId,
+    chunks: Vec<RawChunk<Event, Gap>>,
+) -> Vec<Vec<RawChunk<Event, Gap>>> {
+    let mut chunks = chunks
+        .into_iter()
+        .map(|chunk| (chunk.identifier, chunk))
+        .collect::<HashMap<ChunkIdentifier, _>>();
+
+    let mut firsts = chunks
+        .values()
+        .filter(|chunk| chunk.previous.is_none_or(|previous| !chunks.contains_key(&previous)))
+        .map(|chunk| chunk.identifier)
+        .collect::<Vec<_>>();
+    firsts.sort();
+
+    let mut fragments = Vec::with_capacity(firsts.len());
+
+    for first in firsts {
+        let mut fragment = Vec::new();
+        let mut next = Some(first);
+
+        while let Some(chunk) = next.and_then(|identifier| chunks.remove(&identifier)) {
+            next = chunk.next;
+            fragment.push(chunk);
+        }
+
+        fragments.push(fragment);
+    }
+
+    if !chunks.is_empty() {
+        warn!(?room_id, "Some chunks aren't linked to the first chunk of a fragment");
+    }
+
+    fragments
+}
+
+fn check_count(
+    records: &'static str,
+    expected: usize,
+    found: usize,
+) -> Result<(), StoreMigrationError> {
+    if expected == found {
+        Ok(())
+    } else {
+        Err(StoreMigrationError::CountMismatch { records, expected, found })
+    }
+}
+
+/// The number of records copied by a [`StoreMigrator`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StoreMigrationReport {
+    /// The number of rooms.
+    pub rooms: usize,
+
+    /// The number of state events, stripped or not.
+    pub state_events: usize,
+
+    /// The number of global and room account data events.
+    pub account_data_events: usize,
+
+    /// The number of send queue requests, dependent or not.
+    pub send_queue_requests: usize,
+
+    /// The number of linked chunks of the event cache, gaps included.
+    pub chunks: usize,
+
+    /// The number of events in the linked chunks of the event cache.
+    pub events: usize,
+
+    /// The number of events of the event cache that aren't part of a linked
+    /// chunk.
+    pub out_of_band_events: usize,
+
+    /// The number of media files of the event cache.
+    pub media: usize,
+
+    /// The number of custom values of the state and crypto stores.
+    pub custom_values: usize,
+
+    /// The number of devices.
+    pub devices: usize,
+
+    /// The number of cross-signing identities of users.
+    pub user_identities: usize,
+
+    /// The number of Olm sessions.
+    pub olm_sessions: usize,
+
+    /// The number of room keys.
+    pub inbound_group_sessions: usize,
+
+    /// The number of outbound group sessions.
+    pub outbound_group_sessions: usize,
+}
+
+/// An error that can happen while migrating stores with a [`StoreMigrator`].
+#[derive(Debug, thiserror::Error)]
+pub enum StoreMigrationError {
+    /// An error happened in one of the state stores.
+    #[error(transparent)]
+    StateStore(#[from] StoreError),
+
+    /// An error happened in one of the event cache stores.
+    #[error(transparent)]
+    EventCacheStore(#[from] EventCacheStoreError),
+
+    /// An error happened in one of the crypto stores.
+    #[cfg(feature = "e2e-encryption")]
+    #[error(transparent)]
+    CryptoStore(#[from] CryptoStoreError),
+
+    /// An event cache store was given without a state store, which is needed
+    /// to know the rooms to migrate.
+    #[error("Migrating the event cache store requires migrating the state store too")]
+    MissingStateStore,
+
+    /// The target store doesn't contain the same number of records as the
+    /// source store after the migration.
+    #[error("Expected {expected} {records} in the target store, found {found}")]
+    CountMismatch {
+        /// The kind of records that were counted.
+        records: &'static str,
+        /// The number of records in the source store.
+        expected: usize,
+        /// The number of records in the target store.
+        found: usize,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use assert_matches::assert_matches;
+    use matrix_sdk_common::linked_chunk::{
+        ChunkContent, ChunkIdentifier as CId, LinkedChunkId, Position, Update,
+    };
+    use matrix_sdk_test::async_test;
+    use ruma::{
+        event_id,
+        events::{
+            receipt::{ReceiptThread, ReceiptType},
+            room::{message::RoomMessageEventContent, MediaSource},
+            GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType,
+        },
+        mxc_uri, room_id,
+        serde::Raw,
+        user_id, MilliSecondsSinceUnixEpoch, TransactionId,
+    };
+    use serde_json::json;
+
+    use super::{sort_chunks, StoreMigrationError, StoreMigrator};
+    use crate::{
+        deserialized_responses::{DisplayName, RawAnySyncOrStrippedState},
+        event_cache::{
+            store::{
+                integration_tests::{check_test_event, make_test_event},
+                media::IgnoreMediaRetentionPolicy,
+                EventCacheStore, MemoryStore as MemoryEventCacheStore,
+            },
+            Gap,
+        },
+        media::{MediaFormat, MediaRequestParameters},
+        store::{
+            IntoStateStore, MemoryStore, SerializableEventContent, StateChanges, StateStore,
+            StateStoreDataKey, StateStoreIntegrationTests,
+        },
+        RoomMemberships,
+    };
+
+    #[async_test]
+    async fn test_migrate_state_store() {
+        let room_id = room_id!("!test:localhost");
+        let stripped_room_id = room_id!("!stripped:localhost");
+        let user_id = user_id!("@example:localhost");
+
+        let from = MemoryStore::new().into_state_store();
+        from.populate().await.unwrap();
+
+        let content =
+            SerializableEventContent::new(&RoomMessageEventContent::text_plain("Hello").into())
+                .unwrap();
+        from.save_send_queue_request(
+            room_id,
+            TransactionId::new(),
+            MilliSecondsSinceUnixEpoch::now(),
+            content.into(),
+            0,
+        )
+        .await
+        .unwrap();
+
+        let to = Arc::new(MemoryStore::new());
+        let report =
+            StoreMigrator::new().state_store(from.clone(), to.clone()).migrate().await.unwrap();
+
+        assert_eq!(report.rooms, 2);
+        assert_eq!(report.send_queue_requests, 1);
+        assert!(report.state_events > 0);
+
+        assert_eq!(
+            to.get_kv_data(StateStoreDataKey::SyncToken).await.unwrap().unwrap().into_sync_token(),
+            from.get_kv_data(StateStoreDataKey::SyncToken)
+                .await
+                .unwrap()
+                .unwrap()
+                .into_sync_token(),
+        );
+        assert!(to
+            .get_account_data_event(GlobalAccountDataEventType::PushRules)
+            .await
+            .unwrap()
+            .is_some());
+        assert!(to
+            .get_room_account_data_event(room_id, RoomAccountDataEventType::Tag)
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(to.get_presence_events(&[user_id.to_owned()]).await.unwrap().len(), 1);
+
+        // The state of the joined and the invited rooms was copied.
+        assert_eq!(to.get_user_ids(room_id, RoomMemberships::empty()).await.unwrap().len(), 2);
+        assert_eq!(to.get_profiles(room_id, &[user_id.to_owned()]).await.unwrap().len(), 1);
+        assert_eq!(
+            to.get_users_with_display_name(room_id, &DisplayName::new("example"))
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_matches!(
+            to.get_state_events(stripped_room_id, StateEventType::RoomName)
+                .await
+                .unwrap()
+                .as_slice(),
+            [RawAnySyncOrStrippedState::Stripped(_)]
+        );
+
+        let (receipt_event_id, _) = to
+            .get_user_room_receipt_event(
+                room_id,
+                ReceiptType::Read,
+                ReceiptThread::Unthreaded,
+                user_id,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(receipt_event_id, event_id!("$example"));
+
+        assert_eq!(to.load_send_queue_requests(room_id).await.unwrap().len(), 1);
+    }
+
+    #[async_test]
+    async fn test_migrate_custom_state_and_values() {
+        let room_id = room_id!("!test:localhost");
+
+        let from = MemoryStore::new().into_state_store();
+        from.populate().await.unwrap();
+
+        let custom_state = Raw::new(&json!({
+            "type": "org.example.custom",
+            "state_key": "",
+            "event_id": "$custom",
+            "sender": "@example:localhost",
+            "origin_server_ts": 0,
+            "content": { "custom": true },
+        }))
+        .unwrap()
+        .cast();
+        let custom_account_data =
+            Raw::new(&json!({ "type": "org.example.custom", "content": { "custom": true } }))
+                .unwrap();
+
+        let mut changes = StateChanges::default();
+        changes
+            .state
+            .entry(room_id.to_owned())
+            .or_default()
+            .entry("org.example.custom".into())
+            .or_default()
+            .insert(String::new(), custom_state);
+        changes
+            .account_data
+            .insert("org.example.custom".into(), custom_account_data.clone().cast());
+        changes
+            .room_account_data
+            .entry(room_id.to_owned())
+            .or_default()
+            .insert("org.example.custom".into(), custom_account_data.cast());
+        from.save_changes(&changes).await.unwrap();
+
+        from.set_custom_value(b"custom-key", b"custom-value".to_vec()).await.unwrap();
+
+        let to = Arc::new(MemoryStore::new());
+        let report =
+            StoreMigrator::new().state_store(from.clone(), to.clone()).migrate().await.unwrap();
+
+        assert_eq!(report.custom_values, 1);
+        assert_eq!(
+            to.get_custom_value(b"custom-key").await.unwrap().as_deref(),
+            Some(b"custom-value".as_slice())
+        );
+
+        assert_matches!(
+            to.get_state_events(room_id, "org.example.custom".into()).await.unwrap().as_slice(),
+            [RawAnySyncOrStrippedState::Sync(_)]
+        );
+        assert!(to.get_account_data_event("org.example.custom".into()).await.unwrap().is_some());
+        assert!(to
+            .get_room_account_data_event(room_id, "org.example.custom".into())
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[async_test]
+    async fn test_migrate_event_cache_store() {
+        let room_id = room_id!("!test:localhost");
+        let linked_chunk_id = LinkedChunkId::Room(room_id);
+
+        let state_store = MemoryStore::new().into_state_store();
+        state_store.populate().await.unwrap();
+
+        // The chunks are created out of order: the chunk 2 is inserted between the
+        // chunks 0 and 1. The chunk 3 is a second fragment of the linked chunk.
+        let from = MemoryEventCacheStore::new();
+        from.handle_linked_chunk_updates(
+            linked_chunk_id,
+            vec![
+                Update::NewItemsChunk { previous: None, new: CId::new(0), next: None },
+                Update::PushItems {
+                    at: Position::new(CId::new(0), 0),
+                    items: vec![
+                        make_test_event(room_id, "hello"),
+                        make_test_event(room_id, "world"),
+                    ],
+                },
+                Update::NewItemsChunk { previous: Some(CId::new(0)), new: CId::new(1), next: None },
+                Update::PushItems {
+                    at: Position::new(CId::new(1), 0),
+                    items: vec![make_test_event(room_id, "last")],
+                },
+                Update::NewGapChunk {
+                    previous: Some(CId::new(0)),
+                    new: CId::new(2),
+                    next: Some(CId::new(1)),
+                    gap: Gap::new("prev-token"),
+                },
+                Update::NewItemsChunk { previous: None, new: CId::new(3), next: None },
+                Update::PushItems {
+                    at: Position::new(CId::new(3), 0),
+                    items: vec![make_test_event(room_id, "fragment")],
+                },
+            ],
+        )
+        .await
+        .unwrap();
+
+        from.handle_linked_chunk_updates(
+            LinkedChunkId::Detached(room_id),
+            vec![
+                Update::NewItemsChunk { previous: None, new: CId::new(0), next: None },
+                Update::PushItems {
+                    at: Position::new(CId::new(0), 0),
+                    items: vec![make_test_event(room_id, "detached")],
+                },
+            ],
+        )
+        .await
+        .unwrap();
+
+        let out_of_band_event = make_test_event(room_id, "out of band");
+        let out_of_band_event_id = out_of_band_event.event_id().unwrap();
+        from.save_event(room_id, out_of_band_event).await.unwrap();
+
+        let media_request = MediaRequestParameters {
+            source: MediaSource::Plain(mxc_uri!("mxc://localhost/media").to_owned()),
+            format: MediaFormat::File,
+        };
+        from.add_media_content(&media_request, b"media".to_vec(), IgnoreMediaRetentionPolicy::No)
+            .await
+            .unwrap();
+
+        let to = Arc::new(MemoryEventCacheStore::new());
+        let report = StoreMigrator::new()
+            .state_store(state_store, MemoryStore::new())
+            .event_cache_store(from, to.clone())
+            .migrate()
+            .await
+            .unwrap();
+
+        assert_eq!(report.chunks, 5);
+        assert_eq!(report.events, 5);
+        assert_eq!(report.out_of_band_events, 1);
+        assert_eq!(report.media, 1);
+
+        let fragments = sort_chunks(room_id, to.load_all_chunks(linked_chunk_id).await.unwrap());
+        assert_eq!(fragments.len(), 2);
+
+        let chunks = &fragments[0];
+        assert_eq!(chunks.len(), 3);
+
+        assert_eq!(chunks[0].identifier, CId::new(0));
+        assert_matches!(&chunks[0].content, ChunkContent::Items(events) => {
+            assert_eq!(events.len(), 2);
+            check_test_event(&events[0], "hello");
+            check_test_event(&events[1], "world");
+        });
+
+        assert_eq!(chunks[1].identifier, CId::new(2));
+        assert_matches!(&chunks[1].content, ChunkContent::Gap(gap) => {
+            assert_eq!(gap.prev_token.as_deref(), Some("prev-token"));
+        });
+
+        assert_eq!(chunks[2].identifier, CId::new(1));
+        assert_matches!(&chunks[2].content, ChunkContent::Items(events) => {
+            assert_eq!(events.len(), 1);
+            check_test_event(&events[0], "last");
+        });
+
+        assert_matches!(&fragments[1][..], [chunk] => {
+            assert_eq!(chunk.identifier, CId::new(3));
+            assert_matches!(&chunk.content, ChunkContent::Items(events) => {
+                check_test_event(&events[0], "fragment");
+            });
+        });
+
+        let detached = to.load_all_chunks(LinkedChunkId::Detached(room_id)).await.unwrap();
+        assert_matches!(&detached[..], [chunk] => {
+            assert_matches!(&chunk.content, ChunkContent::Items(events) => {
+                check_test_event(&events[0], "detached");
+            });
+        });
+
+        assert!(to.find_event(room_id, &out_of_band_event_id).await.unwrap().is_some());
+        assert_eq!(
+            to.get_media_content(&media_request).await.unwrap().as_deref(),
+            Some(b"media".as_slice())
+        );
+    }
+
+    #[async_test]
+    async fn test_event_cache_store_requires_state_store() {
+        let result = StoreMigrator::new()
+            .event_cache_store(MemoryEventCacheStore::new(), MemoryEventCacheStore::new())
+            .migrate()
+            .await;
+
+        assert_matches!(result, Err(StoreMigrationError::MissingStateStore));
+    }
+
+    #[cfg(feature = "e2e-encryption")]
+    #[async_test]
+    async fn test_migrate_crypto_store() {
+        use matrix_sdk_crypto::{
+            olm::{Account, OlmMessageHash, SenderData},
+            store::{
+                types::{BackupDecryptionKey, Changes, DeviceChanges, PendingChanges},
+                CryptoStore, MemoryStore as MemoryCryptoStore,
+            },
+            DeviceData, EncryptionSettings,
+        };
+        use ruma::device_id;
+
+        let room_id = room_id!("!test:localhost");
+        let user_id = user_id!("@example:localhost");
+        let other_user_id = user_id!("@other:localhost");
+
+        let account = Account::with_device_id(user_id, device_id!("DEVICE"));
+        let mut other_account = Account::with_device_id(other_user_id, device_id!("OTHER"));
+
+        other_account.generate_one_time_keys(1);
+        let other_device = DeviceData::from_account(&other_account);
+        let session = account
+            .create_outbound_session(
+                &other_device,
+                &other_account.signed_one_time_keys(),
+                account.device_keys(),
+            )
+            .unwrap();
+        let sender_key = session.sender_key().to_base64();
+
+        // A session with a device of a user that isn't tracked anymore.
+        let mut untracked_account =
+            Account::with_device_id(user_id!("@untracked:localhost"), device_id!("UNTRACKED"));
+        untracked_account.generate_one_time_keys(1);
+        let untracked_session = account
+            .create_outbound_session(
+                &DeviceData::from_account(&untracked_account),
+                &untracked_account.signed_one_time_keys(),
+                account.device_keys(),
+            )
+            .unwrap();
+
+        let message_hash =
+            OlmMessageHash { sender_key: sender_key.clone(), hash: "hash".to_owned() };
+
+        let mut inbound_group_sessions = Vec::new();
+        for _ in 0..3 {
+            let (_, session) = account
+                .create_group_session_pair(
+                    room_id,
+                    EncryptionSettings::default(),
+                    SenderData::unknown(),
+                )
+                .await
+                .unwrap();
+            inbound_group_sessions.push(session);
+        }
+        let (outbound, backed_up) = account
+            .create_group_session_pair(
+                room_id,
+                EncryptionSettings::default(),
+                SenderData::unknown(),
+            )
+            .await
+            .unwrap();
+        backed_up.mark_as_backed_up();
+
+        let from = MemoryCryptoStore::new();
+        from.save_pending_changes(PendingChanges { account: Some(account.deep_clone()) })
+            .await
+            .unwrap();
+        from.save_changes(Changes {
+            backup_version: Some("1".to_owned()),
+            backup_decryption_key: Some(BackupDecryptionKey::new().unwrap()),
+            devices: DeviceChanges {
+                new: vec![DeviceData::from_account(&account), other_device],
+                ..Default::default()
+            },
+            sessions: vec![session, untracked_session],
+            message_hashes: vec![message_hash.clone()],
+            outbound_group_sessions: vec![outbound],
+            inbound_group_sessions,
+            next_batch_token: Some("next-batch".to_owned()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        from.save_inbound_group_sessions(vec![backed_up], Some("1")).await.unwrap();
+        from.save_tracked_users(&[(user_id, false), (other_user_id, true)]).await.unwrap();
+        from.set_custom_value("custom-key", b"custom-value".to_vec()).await.unwrap();
+
+        let to = Arc::new(MemoryCryptoStore::new());
+        let report = StoreMigrator::new()
+            .crypto_store(from, to.clone())
+            .batch_size(2)
+            .migrate()
+            .await
+            .unwrap();
+
+        assert_eq!(report.devices, 2);
+        assert_eq!(report.inbound_group_sessions, 4);
+        assert_eq!(report.outbound_group_sessions, 1);
+        assert_eq!(report.olm_sessions, 2);
+        assert_eq!(to.get_all_sessions().await.unwrap().len(), 2);
+        assert_eq!(report.custom_values, 1);
+
+        let migrated_account = to.load_account().await.unwrap().unwrap();
+        assert_eq!(migrated_account.identity_keys(), account.identity_keys());
+
+        let backup_keys = to.load_backup_keys().await.unwrap();
+        assert_eq!(backup_keys.backup_version.as_deref(), Some("1"));
+        assert!(backup_keys.decryption_key.is_some());
+        assert_eq!(to.next_batch_token().await.unwrap().as_deref(), Some("next-batch"));
+
+        let counts = to.inbound_group_session_counts(Some("1")).await.unwrap();
+        assert_eq!(counts.total, 4);
+        assert_eq!(counts.backed_up, 1);
+
+        assert!(to.get_outbound_group_session(room_id).await.unwrap().is_some());
+        assert_eq!(to.get_user_devices(other_user_id).await.unwrap().len(), 1);
+
+        let tracked_users = to.load_tracked_users().await.unwrap();
+        assert_eq!(tracked_users.len(), 2);
+        assert!(tracked_users.iter().any(|user| user.user_id == other_user_id && user.dirty));
+
+        assert_eq!(to.get_sessions(&sender_key).await.unwrap().unwrap().len(), 1);
+        assert!(to.is_message_known(&message_hash).await.unwrap());
+        assert_eq!(
+            to.get_custom_value("custom-key").await.unwrap().as_deref(),
+            Some(b"custom-value".as_slice())
+        );
+    }
+}
@@ -75,6 +75,7 @@ use crate::{
 pub(crate) mod ambiguity_map;
 mod memory_store;
 pub mod migration_helpers;
+mod migrator;
 mod send_queue;
 
 #[cfg(any(test, feature = "testing"))]
@@ -83,6 +84,7 @@ pub use self::integration_tests::StateStoreIntegrationTests;
 pub use self::send_queue::{AccumulatedSentMediaInfo, FinishGalleryItemInfo};
 pub use self::{
     memory_store::MemoryStore,
+    migrator::{StoreMigrationError, StoreMigrationReport, StoreMigrator},
     send_queue::{
         ChildTransactionId, DependentQueuedRequest, DependentQueuedRequestKind,
         FinishUploadThumbnailInfo, QueueWedgeError, QueuedRequest, QueuedRequestKind,
@@ -23,8 +23,8 @@ use ruma::{
         AnyMessageLikeEventContent, EventContent as _, RawExt as _,
     },
     serde::Raw,
-    MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedEventId, OwnedTransactionId, OwnedUserId,
-    TransactionId, UInt,
+    MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedEventId, OwnedMxcUri, OwnedTransactionId,
+    OwnedUserId, TransactionId, UInt,
 };
 use serde::{Deserialize, Serialize};
 
@@ -104,6 +104,15 @@ pub enum QueuedRequestKind {
         /// To which media event transaction does this upload relate?
         related_to: OwnedTransactionId,
 
+        /// An MXC URI that has been reserved on the server for this upload, if
+        /// any.
+        ///
+        /// When set, the upload targets this URI, so that retrying it after
+        /// a previous attempt succeeded (but couldn't be recorded as such)
+        /// doesn't upload the same bytes a second time.
+        #[serde(default)]
+        preallocated_uri: Option<OwnedMxcUri>,
+
         /// Accumulated list of infos for previously uploaded files and
         /// thumbnails if used during a gallery transaction. Otherwise empty.
         #[cfg(feature = "unstable-msc4274")]
@@ -198,6 +207,10 @@ pub enum QueueWedgeError {
         mime_type: String,
     },
 
+    /// The media upload has been paused by the user.
+    #[error("The media upload has been paused")]
+    UploadPaused,
+
     /// Other errors.
     #[error("Other unrecoverable error: {msg}")]
     GenericApiError {
@@ -22,7 +22,7 @@ use std::{
 use as_variant::as_variant;
 use async_trait::async_trait;
 use growable_bloom_filter::GrowableBloom;
-use matrix_sdk_common::AsyncTraitDeps;
+use matrix_sdk_common::{store_usage::StoreUsage, AsyncTraitDeps};
 use ruma::{
     api::{
         client::discovery::discover_homeserver::{
@@ -165,6 +165,16 @@ pub trait StateStore: AsyncTraitDeps {
         state_keys: &[&str],
     ) -> Result<Vec<RawAnySyncOrStrippedState>, Self::Error>;
 
+    /// Get all the state events of a given room, whatever their type.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room to find events for.
+    async fn get_all_state_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<RawAnySyncOrStrippedState>, Self::Error>;
+
     /// Get the current profile for the given user in the given room.
     ///
     /// # Arguments
@@ -257,6 +267,23 @@ pub trait StateStore: AsyncTraitDeps {
         event_type: RoomAccountDataEventType,
     ) -> Result<Option<Raw<AnyRoomAccountDataEvent>>, Self::Error>;
 
+    /// Get all the events out of the account data store.
+    async fn get_all_account_data_events(
+        &self,
+    ) -> Result<Vec<Raw<AnyGlobalAccountDataEvent>>, Self::Error>;
+
+    /// Get all the events out of the room account data store, for the given
+    /// room.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room for which the room account data events
+    ///   should be fetched.
+    async fn get_all_room_account_data_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<Raw<AnyRoomAccountDataEvent>>, Self::Error>;
+
     /// Get an event out of the user room receipt store.
     ///
     /// # Arguments
@@ -347,6 +374,9 @@ pub trait StateStore: AsyncTraitDeps {
     /// * `key` - The key to remove data from
     async fn remove_custom_value(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error>;
 
+    /// Get all the keys and values of the custom store.
+    async fn get_all_custom_values(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Self::Error>;
+
     /// Remove a room and all elements associated from the state store.
     ///
     /// # Arguments
@@ -478,6 +508,16 @@ pub trait StateStore: AsyncTraitDeps {
         &self,
         room: &RoomId,
     ) -> Result<Vec<DependentQueuedRequest>, Self::Error>;
+
+    /// Get how much space the store is using.
+    async fn storage_usage(&self) -> Result<StoreUsage, Self::Error>;
+
+    /// Compact the store, to give back to the system the space that isn't
+    /// used anymore.
+    ///
+    /// This may take a while on big stores, and may block other accesses to
+    /// the store in the meantime.
+    async fn compact(&self) -> Result<(), Self::Error>;
 }
 
 #[repr(transparent)]
@@ -558,6 +598,13 @@ impl<T: StateStore> StateStore for EraseStateStoreError<T> {
         self.0.get_state_events_for_keys(room_id, event_type, state_keys).await.map_err(Into::into)
     }
 
+    async fn get_all_state_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<RawAnySyncOrStrippedState>, Self::Error> {
+        self.0.get_all_state_events(room_id).await.map_err(Into::into)
+    }
+
     async fn get_profile(
         &self,
         room_id: &RoomId,
@@ -620,6 +667,19 @@ impl<T: StateStore> StateStore for EraseStateStoreError<T> {
         self.0.get_room_account_data_event(room_id, event_type).await.map_err(Into::into)
     }
 
+    async fn get_all_account_data_events(
+        &self,
+    ) -> Result<Vec<Raw<AnyGlobalAccountDataEvent>>, Self::Error> {
+        self.0.get_all_account_data_events().await.map_err(Into::into)
+    }
+
+    async fn get_all_room_account_data_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<Raw<AnyRoomAccountDataEvent>>, Self::Error> {
+        self.0.get_all_room_account_data_events(room_id).await.map_err(Into::into)
+    }
+
     async fn get_user_room_receipt_event(
         &self,
         room_id: &RoomId,
@@ -662,6 +722,10 @@ impl<T: StateStore> StateStore for EraseStateStoreError<T> {
         self.0.remove_custom_value(key).await.map_err(Into::into)
     }
 
+    async fn get_all_custom_values(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Self::Error> {
+        self.0.get_all_custom_values().await.map_err(Into::into)
+    }
+
     async fn remove_room(&self, room_id: &RoomId) -> Result<(), Self::Error> {
         self.0.remove_room(room_id).await.map_err(Into::into)
     }
@@ -772,6 +836,14 @@ impl<T: StateStore> StateStore for EraseStateStoreError<T> {
             .await
             .map_err(Into::into)
     }
+
+    async fn storage_usage(&self) -> Result<StoreUsage, Self::Error> {
+        self.0.storage_usage().await.map_err(Into::into)
+    }
+
+    async fn compact(&self) -> Result<(), Self::Error> {
+        self.0.compact().await.map_err(Into::into)
+    }
 }
 
 /// Convenience functionality for state stores.
@@ -955,6 +1027,12 @@ where
     }
 }
 
+impl IntoStateStore for Arc<DynStateStore> {
+    fn into_state_store(self) -> Arc<DynStateStore> {
+        self
+    }
+}
+
 /// Useful server info such as data returned by the /client/versions and
 /// .well-known/client/matrix endpoints.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -31,6 +31,7 @@ pub mod ring_buffer;
 pub mod serde_helpers;
 pub mod sleep;
 pub mod store_locks;
+pub mod store_usage;
 pub mod stream;
 pub mod timeout;
 pub mod tracing_timer;
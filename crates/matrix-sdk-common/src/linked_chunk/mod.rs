@@ -98,6 +98,7 @@ pub mod relational;
 mod updates;
 
 use std::{
+    borrow::Cow,
     fmt::{self, Display},
     marker::PhantomData,
     ptr::NonNull,
@@ -113,6 +114,9 @@ pub use updates::*;
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LinkedChunkId<'a> {
     Room(&'a RoomId),
+    /// Events of a room that aren't connected (yet) to the room's linked
+    /// chunk, e.g. the context around an event the user jumped to.
+    Detached(&'a RoomId),
     // TODO(bnjbvr): Soon™.
     // Thread(&'a RoomId, &'a EventId),
 }
@@ -120,13 +124,23 @@ pub enum LinkedChunkId<'a> {
 impl LinkedChunkId<'_> {
     pub fn storage_key(&self) -> impl '_ + AsRef<[u8]> {
         match self {
-            LinkedChunkId::Room(room_id) => room_id,
+            LinkedChunkId::Room(room_id) => Cow::Borrowed(room_id.as_bytes()),
+            LinkedChunkId::Detached(room_id) => {
+                Cow::Owned(format!("detached:{room_id}").into_bytes())
+            }
         }
     }
 
     pub fn to_owned(&self) -> OwnedLinkedChunkId {
         match self {
             LinkedChunkId::Room(room_id) => OwnedLinkedChunkId::Room((*room_id).to_owned()),
+            LinkedChunkId::Detached(room_id) => OwnedLinkedChunkId::Detached((*room_id).to_owned()),
+        }
+    }
+
+    pub fn room_id(&self) -> &RoomId {
+        match self {
+            LinkedChunkId::Room(room_id) | LinkedChunkId::Detached(room_id) => room_id,
         }
     }
 }
@@ -134,7 +148,10 @@ impl LinkedChunkId<'_> {
 impl PartialEq<&OwnedLinkedChunkId> for LinkedChunkId<'_> {
     fn eq(&self, other: &&OwnedLinkedChunkId) -> bool {
         match (self, other) {
-            (LinkedChunkId::Room(a), OwnedLinkedChunkId::Room(b)) => *a == b,
+            (LinkedChunkId::Room(a), OwnedLinkedChunkId::Room(b))
+            | (LinkedChunkId::Detached(a), OwnedLinkedChunkId::Detached(b)) => *a == b,
+            (LinkedChunkId::Room(_), OwnedLinkedChunkId::Detached(_))
+            | (LinkedChunkId::Detached(_), OwnedLinkedChunkId::Room(_)) => false,
         }
     }
 }
@@ -149,6 +166,8 @@ impl PartialEq<LinkedChunkId<'_>> for OwnedLinkedChunkId {
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum OwnedLinkedChunkId {
     Room(OwnedRoomId),
+    /// See [`LinkedChunkId::Detached`].
+    Detached(OwnedRoomId),
     // TODO(bnjbvr): Soon™.
     // Thread(OwnedRoomId, OwnedEventId),
 }
@@ -157,21 +176,23 @@ impl Display for OwnedLinkedChunkId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             OwnedLinkedChunkId::Room(room_id) => write!(f, "{room_id}"),
+            OwnedLinkedChunkId::Detached(room_id) => write!(f, "detached:{room_id}"),
         }
     }
 }
 
 impl OwnedLinkedChunkId {
-    #[cfg(test)]
-    fn as_ref(&self) -> LinkedChunkId<'_> {
+    /// Borrow this identifier as a [`LinkedChunkId`].
+    pub fn as_ref(&self) -> LinkedChunkId<'_> {
         match self {
             OwnedLinkedChunkId::Room(room_id) => LinkedChunkId::Room(room_id.as_ref()),
+            OwnedLinkedChunkId::Detached(room_id) => LinkedChunkId::Detached(room_id.as_ref()),
         }
     }
 
     pub fn room_id(&self) -> &RoomId {
         match self {
-            OwnedLinkedChunkId::Room(room_id) => room_id,
+            OwnedLinkedChunkId::Room(room_id) | OwnedLinkedChunkId::Detached(room_id) => room_id,
         }
     }
 }
@@ -393,6 +393,32 @@ where
             .flat_map(|items| items.values().map(|(item, pos)| (item, *pos)))
     }
 
+    /// Return the identifiers of all the linked chunks that have at least one
+    /// chunk.
+    pub fn linked_chunk_ids(&self) -> Vec<OwnedLinkedChunkId> {
+        let mut linked_chunk_ids = Vec::new();
+
+        for chunk in &self.chunks {
+            if !linked_chunk_ids.contains(&chunk.linked_chunk_id) {
+                linked_chunk_ids.push(chunk.linked_chunk_id.clone());
+            }
+        }
+
+        linked_chunk_ids
+    }
+
+    /// Remove the given items from the items collection of a linked chunk.
+    ///
+    /// This is meant to be used after the chunks containing these items have
+    /// been removed, since their content is deliberately kept otherwise.
+    pub fn remove_items(&mut self, linked_chunk_id: LinkedChunkId<'_>, item_ids: &[ItemId]) {
+        if let Some(items) = self.items.get_mut(&linked_chunk_id.to_owned()) {
+            for item_id in item_ids {
+                items.remove(item_id);
+            }
+        }
+    }
+
     /// Save a single item "out-of-band" in the relational linked chunk.
     pub fn save_item(&mut self, room_id: OwnedRoomId, item: Item) {
         let id = item.id();
@@ -0,0 +1,366 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A [`BackingStore`] for the cross-process lock using files of a shared
+//! directory.
+//!
+//! Each lock is a file in the directory, containing the current holder of the
+//! lock and the expiration of its lease. This file is only read and written
+//! while holding an advisory lock from the OS on it, so the lease is taken
+//! atomically by a single process at a time.
+//!
+//! The OS lock is only held while a lease is being taken, and it is released
+//! by the OS if a process crashes, so a crashed process can't block the other
+//! ones: its lease simply expires.
+
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    panic,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use ruma::MilliSecondsSinceUnixEpoch;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::BackingStore;
+
+/// A [`BackingStore`] using OS advisory locks on files of a directory.
+///
+/// It can be used by several processes that only share a directory, whatever
+/// the stores they use.
+#[derive(Clone)]
+pub struct FileLockStore {
+    /// The directory containing the lock files.
+    directory: Arc<PathBuf>,
+}
+
+impl fmt::Debug for FileLockStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileLockStore").field("directory", &self.directory).finish()
+    }
+}
+
+/// The content of a lock file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Lease {
+    /// The current holder of the lock.
+    holder: String,
+    /// The expiration of the lease, in milliseconds since the Unix epoch.
+    expiration: u64,
+}
+
+impl FileLockStore {
+    /// Create a new `FileLockStore` using the given directory.
+    ///
+    /// The directory is created when a lock is first taken, if it doesn't
+    /// exist. All the processes that need to coordinate must use the same
+    /// directory.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self { directory: Arc::new(directory.into()) }
+    }
+
+    /// The directory containing the lock files.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// The path of the file for the lock with the given key.
+    fn lock_file_path(&self, key: &str) -> PathBuf {
+        // Escape the characters that might not be allowed in a file name.
+        let mut file_name = String::with_capacity(key.len() + 5);
+
+        for byte in key.bytes() {
+            if byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-' {
+                file_name.push(byte.into());
+            } else {
+                file_name.push_str(&format!("%{byte:02X}"));
+            }
+        }
+
+        file_name.push_str(".lock");
+
+        self.directory.join(file_name)
+    }
+
+    /// Try to take the lease of the lock with the given key for the given
+    /// holder.
+    ///
+    /// Like the leased locks of the stores, this succeeds if the lock has no
+    /// holder, if it's already held by the same holder, or if the lease of the
+    /// previous holder has expired.
+    ///
+    /// This doesn't wait if another process is currently taking the same
+    /// lock: the lock is considered as taken.
+    ///
+    /// This blocks on file system operations, so it must not be called from
+    /// an async context; [`BackingStore::try_lock()`] runs it on the blocking
+    /// thread pool.
+    pub fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool, FileLockStoreError> {
+        fs::create_dir_all(&*self.directory)?;
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.lock_file_path(key))?;
+
+        let mut file = fd_lock::RwLock::new(file);
+        let mut file = match file.try_write() {
+            Ok(guard) => guard,
+            // Another process is taking the lock right now.
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+
+        let now: u64 = MilliSecondsSinceUnixEpoch::now().get().into();
+
+        if !content.is_empty() {
+            match serde_json::from_slice::<Lease>(&content) {
+                Ok(lease) => {
+                    if lease.holder != holder && lease.expiration >= now {
+                        // The lease of another holder is still valid.
+                        return Ok(false);
+                    }
+                }
+                Err(err) => {
+                    // A process might have crashed while writing the file, the lock is
+                    // considered as free.
+                    warn!("Ignoring the invalid content of the lock file for {key}: {err}");
+                }
+            }
+        }
+
+        let lease =
+            Lease { holder: holder.to_owned(), expiration: now + u64::from(lease_duration_ms) };
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&serde_json::to_vec(&lease)?)?;
+
+        Ok(true)
+    }
+}
+
+impl BackingStore for FileLockStore {
+    type LockError = FileLockStoreError;
+
+    async fn try_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool, Self::LockError> {
+        let this = self.clone();
+        let key = key.to_owned();
+        let holder = holder.to_owned();
+
+        // Accessing the file system is blocking, so don't do it on the executor.
+        tokio::task::spawn_blocking(move || {
+            this.try_take_leased_lock(lease_duration_ms, &key, &holder)
+        })
+        .await
+        .unwrap_or_else(|error| panic::resume_unwind(error.into_panic()))
+    }
+}
+
+/// An error that can occur when using a [`FileLockStore`].
+#[derive(Debug, thiserror::Error)]
+pub enum FileLockStoreError {
+    /// An error occurred when accessing the lock file.
+    #[error("failed to access the lock file: {0}")]
+    Io(#[from] io::Error),
+
+    /// An error occurred when serializing the lease.
+    #[error("failed to serialize the lease: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        time::Duration,
+    };
+
+    use assert_matches::assert_matches;
+    use matrix_sdk_test_macros::async_test;
+    use tokio::time::sleep;
+
+    use super::FileLockStore;
+    use crate::store_locks::{
+        CrossProcessStoreLock, LockStoreError, EXTEND_LEASE_EVERY_MS, LEASE_DURATION_MS,
+    };
+
+    /// The environment variable containing the lock directory, to run
+    /// [`lock_holder_process`] as a child process.
+    const CHILD_DIRECTORY_VAR: &str = "MATRIX_SDK_FILE_LOCK_TEST_DIRECTORY";
+    /// The environment variable containing how long the child process holds
+    /// the lock, in milliseconds.
+    const CHILD_HOLD_MS_VAR: &str = "MATRIX_SDK_FILE_LOCK_TEST_HOLD_MS";
+
+    /// The message printed by the child process once it has the lock.
+    const LOCKED_MESSAGE: &str = "file lock acquired";
+
+    /// The body of the child processes of the tests: take the lock, hold it for
+    /// a while, then release it.
+    ///
+    /// This does nothing when the tests are run normally.
+    #[test]
+    fn lock_holder_process() {
+        let Ok(directory) = env::var(CHILD_DIRECTORY_VAR) else {
+            return;
+        };
+        let hold_ms: u64 = env::var(CHILD_HOLD_MS_VAR).unwrap().parse().unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+
+        runtime.block_on(async move {
+            let lock = CrossProcessStoreLock::new(
+                FileLockStore::new(directory),
+                "key".to_owned(),
+                "child".to_owned(),
+            );
+
+            let guard = lock.spin_lock(Some(10_000)).await.unwrap();
+            println!("{LOCKED_MESSAGE}");
+
+            sleep(Duration::from_millis(hold_ms)).await;
+
+            drop(guard);
+            // Let the lease extension task release the lease.
+            sleep(Duration::from_millis(EXTEND_LEASE_EVERY_MS * 2)).await;
+        });
+    }
+
+    /// Spawn a child process holding the lock in the given directory for the
+    /// given time, and wait until it has taken the lock.
+    fn spawn_lock_holder(directory: &std::path::Path, hold_ms: u64) -> Child {
+        let mut child = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "store_locks::file_store::tests::lock_holder_process", "--nocapture"])
+            .env(CHILD_DIRECTORY_VAR, directory)
+            .env(CHILD_HOLD_MS_VAR, hold_ms.to_string())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let mut lines = BufReader::new(child.stdout.take().unwrap()).lines().map_while(Result::ok);
+        let locked = lines.any(|line| line.contains(LOCKED_MESSAGE));
+        assert!(locked, "the child process should have taken the lock");
+
+        // Keep reading the output of the child process, so it doesn't fail to write
+        // to it.
+        std::thread::spawn(move || lines.for_each(drop));
+
+        child
+    }
+
+    #[test]
+    fn test_lease() {
+        let directory = tempfile::tempdir().unwrap();
+        let store = FileLockStore::new(directory.path().join("locks"));
+
+        // The lock is free.
+        assert!(store.try_take_leased_lock(1000, "key", "first").unwrap());
+        // The holder can extend its lease.
+        assert!(store.try_take_leased_lock(1000, "key", "first").unwrap());
+        // Another holder can't take it.
+        assert!(!store.try_take_leased_lock(1000, "key", "second").unwrap());
+        // But it can take another lock.
+        assert!(store.try_take_leased_lock(1000, "other/key", "second").unwrap());
+
+        // Once the lease has expired, another holder can take it.
+        assert!(store.try_take_leased_lock(0, "key", "first").unwrap());
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(store.try_take_leased_lock(1000, "key", "second").unwrap());
+        assert!(!store.try_take_leased_lock(1000, "key", "first").unwrap());
+    }
+
+    #[test]
+    fn test_invalid_lock_file_is_ignored() {
+        let directory = tempfile::tempdir().unwrap();
+        let store = FileLockStore::new(directory.path());
+
+        std::fs::write(store.lock_file_path("key"), b"{\"holder\":\"fir").unwrap();
+
+        assert!(store.try_take_leased_lock(1000, "key", "second").unwrap());
+    }
+
+    #[async_test]
+    async fn test_lock_is_shared_across_processes() {
+        let directory = tempfile::tempdir().unwrap();
+        let lock = CrossProcessStoreLock::new(
+            FileLockStore::new(directory.path()),
+            "key".to_owned(),
+            "parent".to_owned(),
+        );
+
+        let mut child = spawn_lock_holder(directory.path(), 1000);
+
+        // The lock is held by the child process.
+        assert!(lock.try_lock_once().await.unwrap().is_none());
+
+        // It is released when the child process is done with it.
+        let guard = lock.spin_lock(Some(4000)).await.unwrap();
+        assert!(child.wait().unwrap().success());
+
+        drop(guard);
+    }
+
+    #[async_test]
+    async fn test_lock_is_recovered_after_crash() {
+        let directory = tempfile::tempdir().unwrap();
+        let lock = CrossProcessStoreLock::new(
+            FileLockStore::new(directory.path()),
+            "key".to_owned(),
+            "parent".to_owned(),
+        );
+
+        let mut child = spawn_lock_holder(directory.path(), 60_000);
+
+        // The child process crashes while holding the lock.
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        // The lease is still valid for a while, so the lock can't be taken
+        // immediately.
+        assert!(lock.try_lock_once().await.unwrap().is_none());
+
+        // But it can be taken once the lease expires.
+        sleep(Duration::from_millis(LEASE_DURATION_MS.into())).await;
+        let _guard = lock.spin_lock(Some(1000)).await.unwrap();
+
+        // And it can't be taken by another process anymore.
+        let other = CrossProcessStoreLock::new(
+            FileLockStore::new(directory.path()),
+            "key".to_owned(),
+            "other".to_owned(),
+        );
+        assert_matches!(other.spin_lock(Some(100)).await, Err(LockStoreError::LockTimeout));
+    }
+}
@@ -36,6 +36,9 @@
 //!
 //! Releasing the lock happens naturally, by not renewing a lease. It happens
 //! automatically after the duration of the last lease, at most.
+//!
+//! Outside of the stores, the [`FileLockStore`] implements the same leases with
+//! files of a directory shared by the processes.
 
 use std::{
     error::Error,
@@ -56,6 +59,12 @@ use crate::{
     SendOutsideWasm,
 };
 
+#[cfg(not(target_family = "wasm"))]
+mod file_store;
+
+#[cfg(not(target_family = "wasm"))]
+pub use self::file_store::{FileLockStore, FileLockStoreError};
+
 /// Backing store for a cross-process lock.
 pub trait BackingStore {
     #[cfg(not(target_family = "wasm"))]
@@ -0,0 +1,95 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Types describing how much space the stores are using.
+//!
+//! These are shared by all the store traits, so that a client can build a
+//! single report out of the state store, the crypto store and the event cache
+//! store.
+
+use std::collections::BTreeMap;
+
+/// How much space a store is using.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StoreUsage {
+    /// The total size of the store, in bytes, if the store is backed by
+    /// something that has a size, like a file.
+    ///
+    /// This is `None` for in-memory stores.
+    pub total_size: Option<u64>,
+
+    /// The size of the allocated but unused space in the store, in bytes, if
+    /// known.
+    ///
+    /// This is the space that compacting the store may give back to the
+    /// system.
+    pub free_size: Option<u64>,
+
+    /// The size of the data held by each table of the store, in bytes.
+    ///
+    /// What a table is depends on the store implementation; the sizes are an
+    /// estimate of the payloads, and don't include the bookkeeping the
+    /// underlying storage engine may need.
+    pub tables: BTreeMap<String, u64>,
+}
+
+impl StoreUsage {
+    /// The sum of the sizes of all the tables, in bytes.
+    pub fn tables_size(&self) -> u64 {
+        self.tables.values().sum()
+    }
+}
+
+/// How many items of some kind a store is holding, and how much space they
+/// are using.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ItemsUsage {
+    /// The number of items.
+    pub count: u64,
+
+    /// The total size of the items, in bytes.
+    pub size: u64,
+}
+
+impl ItemsUsage {
+    /// Account for one more item of the given size, in bytes.
+    pub fn add(&mut self, size: u64) {
+        self.count += 1;
+        self.size += size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ItemsUsage, StoreUsage};
+
+    #[test]
+    fn test_tables_size() {
+        let mut usage = StoreUsage::default();
+        assert_eq!(usage.tables_size(), 0);
+
+        usage.tables.insert("a".to_owned(), 40);
+        usage.tables.insert("b".to_owned(), 2);
+        assert_eq!(usage.tables_size(), 42);
+    }
+
+    #[test]
+    fn test_items_usage_add() {
+        let mut usage = ItemsUsage::default();
+        usage.add(10);
+        usage.add(32);
+
+        assert_eq!(usage, ItemsUsage { count: 2, size: 42 });
+    }
+}
@@ -130,28 +130,90 @@ impl<'a, R: Read + 'a> AttachmentDecryptor<'a, R> {
         input: &'a mut R,
         info: MediaEncryptionInfo,
     ) -> Result<AttachmentDecryptor<'a, R>, DecryptorError> {
-        if info.version != VERSION {
-            return Err(DecryptorError::UnknownVersion);
-        }
+        let (expected_hash, aes) = decryption_state(info)?;
+        Ok(AttachmentDecryptor { inner: input, expected_hash, sha: Sha256::default(), aes })
+    }
+}
 
-        let hash =
-            info.hashes.get("sha256").ok_or(DecryptorError::MissingHash)?.as_bytes().to_owned();
-        let mut key = info.key.k.into_inner();
-        let iv = info.iv.into_inner();
+/// Get the expected hash of an encrypted attachment and the cipher to decrypt
+/// it from its encryption info.
+fn decryption_state(info: MediaEncryptionInfo) -> Result<(Vec<u8>, Aes256Ctr), DecryptorError> {
+    if info.version != VERSION {
+        return Err(DecryptorError::UnknownVersion);
+    }
 
-        if key.len() != KEY_SIZE {
-            return Err(DecryptorError::KeyNonceLength);
-        }
+    let hash = info.hashes.get("sha256").ok_or(DecryptorError::MissingHash)?.as_bytes().to_owned();
+    let mut key = info.key.k.into_inner();
+    let iv = info.iv.into_inner();
 
-        let key_array = GenericArray::from_slice(&key);
-        let iv = GenericArray::from_exact_iter(iv).ok_or(DecryptorError::KeyNonceLength)?;
+    if key.len() != KEY_SIZE {
+        return Err(DecryptorError::KeyNonceLength);
+    }
 
-        let sha = Sha256::default();
+    let key_array = GenericArray::from_slice(&key);
+    let iv = GenericArray::from_exact_iter(iv).ok_or(DecryptorError::KeyNonceLength)?;
 
-        let aes = Aes256Ctr::new(key_array, &iv);
-        key.zeroize();
+    let aes = Aes256Ctr::new(key_array, &iv);
+    key.zeroize();
+
+    Ok((hash, aes))
+}
 
-        Ok(AttachmentDecryptor { inner: input, expected_hash: hash, sha, aes })
+/// A decryptor of an attachment received in chunks, for example from a network
+/// stream.
+///
+/// Contrary to [`AttachmentDecryptor`], it doesn't wrap a reader: each chunk
+/// is decrypted in place as it's received, and the hash of the whole
+/// attachment is checked by [`StreamingAttachmentDecryptor::finish()`].
+pub struct StreamingAttachmentDecryptor {
+    expected_hash: Vec<u8>,
+    sha: Sha256,
+    aes: Aes256Ctr,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl std::fmt::Debug for StreamingAttachmentDecryptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamingAttachmentDecryptor")
+            .field("expected_hash", &self.expected_hash)
+            .finish()
+    }
+}
+
+impl StreamingAttachmentDecryptor {
+    /// Create a decryptor for the attachment with the given encryption info.
+    pub fn new(info: MediaEncryptionInfo) -> Result<Self, DecryptorError> {
+        let (expected_hash, aes) = decryption_state(info)?;
+        Ok(Self { expected_hash, sha: Sha256::default(), aes })
+    }
+
+    /// Decrypt in place the next chunk of the attachment.
+    pub fn decrypt_chunk(&mut self, chunk: &mut [u8]) {
+        self.sha.update(&*chunk);
+        self.aes.apply_keystream(chunk);
+    }
+
+    /// Skip the next chunk of the attachment, that was already decrypted.
+    ///
+    /// This is used to resume the decryption of an attachment: the chunk is
+    /// encrypted again to compute the hash of the attachment, and the
+    /// following chunks are decrypted at the right position.
+    pub fn skip_decrypted_chunk(&mut self, decrypted_chunk: &[u8]) {
+        let mut chunk = decrypted_chunk.to_owned();
+        self.aes.apply_keystream(&mut chunk);
+        self.sha.update(&chunk);
+    }
+
+    /// Check that the hash of all the chunks matches the one of the
+    /// encryption info.
+    pub fn finish(mut self) -> std::io::Result<()> {
+        let hash = self.sha.finalize_reset();
+
+        if hash.as_slice() == self.expected_hash.as_slice() {
+            Ok(())
+        } else {
+            Err(IoError::other("Hash mismatch while decrypting"))
+        }
     }
 }
 
@@ -305,7 +367,9 @@ mod tests {
 
     use serde_json::json;
 
-    use super::{AttachmentDecryptor, AttachmentEncryptor, MediaEncryptionInfo};
+    use super::{
+        AttachmentDecryptor, AttachmentEncryptor, MediaEncryptionInfo, StreamingAttachmentDecryptor,
+    };
 
     const EXAMPLE_DATA: &[u8] = &[
         179, 154, 118, 127, 186, 127, 110, 33, 203, 33, 33, 134, 67, 100, 173, 46, 235, 27, 215,
@@ -379,4 +443,38 @@ mod tests {
 
         decryptor.read_to_end(&mut decrypted_data).unwrap_err();
     }
+
+    #[test]
+    fn streaming_decrypt() {
+        let mut decryptor = StreamingAttachmentDecryptor::new(example_key()).unwrap();
+        let mut decrypted_data = Vec::new();
+
+        for chunk in EXAMPLE_DATA.chunks(5) {
+            let mut chunk = chunk.to_vec();
+            decryptor.decrypt_chunk(&mut chunk);
+            decrypted_data.extend(chunk);
+        }
+
+        decryptor.finish().unwrap();
+        assert_eq!(decrypted_data, b"It's a secret to everybody");
+    }
+
+    #[test]
+    fn streaming_decrypt_resumed() {
+        let (start, end) = EXAMPLE_DATA.split_at(10);
+
+        let mut decryptor = StreamingAttachmentDecryptor::new(example_key()).unwrap();
+        decryptor.skip_decrypted_chunk(b"It's a sec");
+
+        let mut chunk = end.to_vec();
+        decryptor.decrypt_chunk(&mut chunk);
+        decryptor.finish().unwrap();
+        assert_eq!(chunk, b"ret to everybody");
+
+        // Skipping the wrong data is detected by the hash.
+        let mut decryptor = StreamingAttachmentDecryptor::new(example_key()).unwrap();
+        decryptor.skip_decrypted_chunk(start);
+        decryptor.decrypt_chunk(&mut end.to_vec());
+        decryptor.finish().unwrap_err();
+    }
 }
@@ -3,5 +3,6 @@ mod key_export;
 
 pub use attachments::{
     AttachmentDecryptor, AttachmentEncryptor, DecryptorError, MediaEncryptionInfo,
+    StreamingAttachmentDecryptor,
 };
 pub use key_export::{decrypt_room_key_export, encrypt_room_key_export, KeyExportError};
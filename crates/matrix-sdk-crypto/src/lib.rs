@@ -83,7 +83,7 @@ pub use error::{
 };
 pub use file_encryption::{
     decrypt_room_key_export, encrypt_room_key_export, AttachmentDecryptor, AttachmentEncryptor,
-    DecryptorError, KeyExportError, MediaEncryptionInfo,
+    DecryptorError, KeyExportError, MediaEncryptionInfo, StreamingAttachmentDecryptor,
 };
 pub use gossiping::{GossipRequest, GossippedSecret};
 pub use identities::{
@@ -397,7 +397,11 @@ impl CryptoStoreWrapper {
         lock_key: String,
         lock_value: String,
     ) -> CrossProcessStoreLock<LockableCryptoStore> {
-        CrossProcessStoreLock::new(LockableCryptoStore(self.store.clone()), lock_key, lock_value)
+        CrossProcessStoreLock::new(
+            LockableCryptoStore::new(self.store.clone()),
+            lock_key,
+            lock_value,
+        )
     }
 }
 
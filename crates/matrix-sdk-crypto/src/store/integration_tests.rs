@@ -239,6 +239,9 @@ macro_rules! cryptostore_integration_tests {
                 let loaded_session = sessions.get(0).cloned().expect("We should find the session in the store.");
 
                 assert_eq!(&session, &loaded_session, "The loaded session should be the same one we put into the store.");
+
+                let all_sessions = store.get_all_sessions().await.expect("Can't load all the sessions");
+                assert_eq!(all_sessions, vec![session]);
             }
 
             #[async_test]
@@ -937,6 +940,11 @@ macro_rules! cryptostore_integration_tests {
                 assert!(!store.is_message_known(&hash).await.unwrap());
                 store.save_changes(changes).await.unwrap();
                 assert!(store.is_message_known(&hash).await.unwrap());
+
+                let hashes = store.get_all_olm_message_hashes().await.unwrap();
+                assert_eq!(hashes.len(), 1);
+                assert_eq!(hashes[0].sender_key, hash.sender_key);
+                assert_eq!(hashes[0].hash, hash.hash);
             }
 
             #[async_test]
@@ -1154,6 +1162,8 @@ macro_rules! cryptostore_integration_tests {
                     store.get_withheld_info(other_room_id, session_id_2).await.unwrap();
 
                 assert!(is_withheld.is_none());
+
+                assert_eq!(store.get_all_withheld_info().await.unwrap().len(), 2);
             }
 
             #[async_test]
@@ -1278,6 +1288,34 @@ macro_rules! cryptostore_integration_tests {
 
                 let loaded_2 = store.get_custom_value("B").await.unwrap();
                 assert_eq!(None, loaded_2);
+
+                // The values used internally by the store aren't custom values.
+                let all = store.get_all_custom_values().await.unwrap();
+                assert_eq!(all, vec![("A".to_owned(), "Hello".as_bytes().to_vec())]);
+            }
+
+            #[async_test]
+            async fn test_compact() {
+                let (account, store) = get_loaded_store("compact").await;
+                store.set_custom_value("A", "Hello".as_bytes().to_vec()).await.unwrap();
+
+                let usage_before = store.storage_usage().await.unwrap();
+                store.compact().await.unwrap();
+                let usage_after = store.storage_usage().await.unwrap();
+
+                // The data is still there after a compaction.
+                assert_eq!(usage_after.tables, usage_before.tables);
+                let loaded_account = store.load_account().await.unwrap().unwrap();
+                assert_eq!(account, loaded_account);
+                assert_eq!(
+                    store.get_custom_value("A").await.unwrap(),
+                    Some("Hello".as_bytes().to_vec())
+                );
+
+                // A compaction never makes a store bigger.
+                if let (Some(before), Some(after)) = (usage_before.total_size, usage_after.total_size) {
+                    assert!(after <= before);
+                }
             }
 
             #[async_test]
@@ -1328,6 +1366,9 @@ macro_rules! cryptostore_integration_tests {
                     test_room, user_id!("@alice:example.com")
                 ).await.unwrap().expect("Did not get any bundle data");
                 assert_eq!(bundle.bundle_data.file.url.to_string(), "alice2");
+
+                // The second bundle from Alice replaced the first one.
+                assert_eq!(store.get_all_received_room_key_bundle_data().await.unwrap().len(), 2);
             }
 
             fn session_info(session: &InboundGroupSession) -> (&RoomId, &str) {
@@ -21,6 +21,7 @@ use std::{
 use async_trait::async_trait;
 use matrix_sdk_common::{
     locks::RwLock as StdRwLock, store_locks::memory_store_helper::try_take_leased_lock,
+    store_usage::StoreUsage,
 };
 use ruma::{
     events::secret::request::SecretName, time::Instant, DeviceId, OwnedDeviceId, OwnedRoomId,
@@ -397,6 +398,23 @@ impl CryptoStore for MemoryStore {
         }
     }
 
+    async fn get_all_sessions(&self) -> Result<Vec<Session>> {
+        let device_keys = self.get_own_device().await?.as_device_keys().clone();
+
+        Ok(self
+            .sessions
+            .read()
+            .values()
+            .flat_map(|pickles| pickles.values())
+            .map(|serialized_pickle| {
+                let pickle: PickledSession = serde_json::from_str(serialized_pickle.as_str())
+                    .expect("Pickle pickle deserialization should work");
+                Session::from_pickle(device_keys.clone(), pickle)
+                    .expect("Expect from pickle to always work")
+            })
+            .collect())
+    }
+
     async fn get_inbound_group_session(
         &self,
         room_id: &RoomId,
@@ -428,6 +446,15 @@ impl CryptoStore for MemoryStore {
             .and_then(|e| Some(e.get(session_id)?.to_owned())))
     }
 
+    async fn get_all_withheld_info(&self) -> Result<Vec<RoomKeyWithheldEvent>> {
+        Ok(self
+            .direct_withheld_info
+            .read()
+            .values()
+            .flat_map(|sessions| sessions.values().cloned())
+            .collect())
+    }
+
     async fn get_inbound_group_sessions(&self) -> Result<Vec<InboundGroupSession>> {
         let inbounds = self
             .inbound_group_sessions
@@ -649,6 +676,20 @@ impl CryptoStore for MemoryStore {
             .contains(&message_hash.hash))
     }
 
+    async fn get_all_olm_message_hashes(&self) -> Result<Vec<crate::olm::OlmMessageHash>> {
+        Ok(self
+            .olm_hashes
+            .read()
+            .iter()
+            .flat_map(|(sender_key, hashes)| {
+                hashes.iter().map(|hash| crate::olm::OlmMessageHash {
+                    sender_key: sender_key.clone(),
+                    hash: hash.clone(),
+                })
+            })
+            .collect())
+    }
+
     async fn get_outgoing_secret_requests(
         &self,
         request_id: &TransactionId,
@@ -718,6 +759,15 @@ impl CryptoStore for MemoryStore {
         Ok(result)
     }
 
+    async fn get_all_received_room_key_bundle_data(&self) -> Result<Vec<StoredRoomKeyBundleData>> {
+        Ok(self
+            .room_key_bundles
+            .read()
+            .values()
+            .flat_map(|bundles| bundles.values().cloned())
+            .collect())
+    }
+
     async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
         Ok(self.custom_values.read().get(key).cloned())
     }
@@ -732,6 +782,15 @@ impl CryptoStore for MemoryStore {
         Ok(())
     }
 
+    async fn get_all_custom_values(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        Ok(self
+            .custom_values
+            .read()
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
     async fn try_take_leased_lock(
         &self,
         lease_duration_ms: u32,
@@ -740,6 +799,15 @@ impl CryptoStore for MemoryStore {
     ) -> Result<bool> {
         Ok(try_take_leased_lock(&mut self.leases.write(), lease_duration_ms, key, holder))
     }
+
+    async fn storage_usage(&self) -> Result<StoreUsage> {
+        // Nothing is stored on disk, so there's nothing to report.
+        Ok(StoreUsage::default())
+    }
+
+    async fn compact(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -1238,6 +1306,7 @@ mod integration_tests {
     };
 
     use async_trait::async_trait;
+    use matrix_sdk_common::store_usage::StoreUsage;
     use ruma::{
         events::secret::request::SecretName, DeviceId, OwnedDeviceId, RoomId, TransactionId, UserId,
     };
@@ -1341,6 +1410,10 @@ mod integration_tests {
             self.0.get_sessions(sender_key).await
         }
 
+        async fn get_all_sessions(&self) -> Result<Vec<Session>, Self::Error> {
+            self.0.get_all_sessions().await
+        }
+
         async fn get_inbound_group_session(
             &self,
             room_id: &RoomId,
@@ -1357,6 +1430,10 @@ mod integration_tests {
             self.0.get_withheld_info(room_id, session_id).await
         }
 
+        async fn get_all_withheld_info(&self) -> Result<Vec<RoomKeyWithheldEvent>, Self::Error> {
+            self.0.get_all_withheld_info().await
+        }
+
         async fn get_inbound_group_sessions(
             &self,
         ) -> Result<Vec<InboundGroupSession>, Self::Error> {
@@ -1471,6 +1548,10 @@ mod integration_tests {
             self.0.is_message_known(message_hash).await
         }
 
+        async fn get_all_olm_message_hashes(&self) -> Result<Vec<OlmMessageHash>, Self::Error> {
+            self.0.get_all_olm_message_hashes().await
+        }
+
         async fn get_outgoing_secret_requests(
             &self,
             request_id: &TransactionId,
@@ -1525,6 +1606,12 @@ mod integration_tests {
             self.0.get_received_room_key_bundle_data(room_id, user_id).await
         }
 
+        async fn get_all_received_room_key_bundle_data(
+            &self,
+        ) -> Result<Vec<StoredRoomKeyBundleData>, Self::Error> {
+            self.0.get_all_received_room_key_bundle_data().await
+        }
+
         async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>, Self::Error> {
             self.0.get_custom_value(key).await
         }
@@ -1537,6 +1624,10 @@ mod integration_tests {
             self.0.remove_custom_value(key).await
         }
 
+        async fn get_all_custom_values(&self) -> Result<Vec<(String, Vec<u8>)>, Self::Error> {
+            self.0.get_all_custom_values().await
+        }
+
         async fn try_take_leased_lock(
             &self,
             lease_duration_ms: u32,
@@ -1549,6 +1640,14 @@ mod integration_tests {
         async fn next_batch_token(&self) -> Result<Option<String>, Self::Error> {
             self.0.next_batch_token().await
         }
+
+        async fn storage_usage(&self) -> Result<StoreUsage, Self::Error> {
+            self.0.storage_usage().await
+        }
+
+        async fn compact(&self) -> Result<(), Self::Error> {
+            self.0.compact().await
+        }
     }
 
     cryptostore_integration_tests!();
@@ -1683,8 +1683,37 @@ impl Deref for Store {
 }
 
 /// A crypto store that implements primitives for cross-process locking.
+///
+/// The leases of the locks are kept in the crypto store itself, or in the
+/// files of a [`FileLockStore`] when created from one.
+///
+/// [`FileLockStore`]: matrix_sdk_common::store_locks::FileLockStore
 #[derive(Clone, Debug)]
-pub struct LockableCryptoStore(Arc<dyn CryptoStore<Error = CryptoStoreError>>);
+pub struct LockableCryptoStore(LockableCryptoStoreBackend);
+
+/// Where the leases of a [`LockableCryptoStore`] are kept.
+#[derive(Clone, Debug)]
+enum LockableCryptoStoreBackend {
+    /// In the crypto store.
+    Store(Arc<dyn CryptoStore<Error = CryptoStoreError>>),
+
+    /// In the files of a shared directory.
+    #[cfg(not(target_family = "wasm"))]
+    File(matrix_sdk_common::store_locks::FileLockStore),
+}
+
+impl LockableCryptoStore {
+    pub(crate) fn new(store: Arc<dyn CryptoStore<Error = CryptoStoreError>>) -> Self {
+        Self(LockableCryptoStoreBackend::Store(store))
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl From<matrix_sdk_common::store_locks::FileLockStore> for LockableCryptoStore {
+    fn from(store: matrix_sdk_common::store_locks::FileLockStore) -> Self {
+        Self(LockableCryptoStoreBackend::File(store))
+    }
+}
 
 impl matrix_sdk_common::store_locks::BackingStore for LockableCryptoStore {
     type LockError = CryptoStoreError;
@@ -1695,7 +1724,22 @@ impl matrix_sdk_common::store_locks::BackingStore for LockableCryptoStore {
         key: &str,
         holder: &str,
     ) -> std::result::Result<bool, Self::LockError> {
-        self.0.try_take_leased_lock(lease_duration_ms, key, holder).await
+        match &self.0 {
+            LockableCryptoStoreBackend::Store(store) => {
+                store.try_take_leased_lock(lease_duration_ms, key, holder).await
+            }
+            #[cfg(not(target_family = "wasm"))]
+            LockableCryptoStoreBackend::File(store) => {
+                matrix_sdk_common::store_locks::BackingStore::try_lock(
+                    store,
+                    lease_duration_ms,
+                    key,
+                    holder,
+                )
+                .await
+                .map_err(CryptoStoreError::backend)
+            }
+        }
     }
 }
 
@@ -15,7 +15,7 @@
 use std::{collections::HashMap, fmt, sync::Arc};
 
 use async_trait::async_trait;
-use matrix_sdk_common::AsyncTraitDeps;
+use matrix_sdk_common::{store_usage::StoreUsage, AsyncTraitDeps};
 use ruma::{
     events::secret::request::SecretName, DeviceId, OwnedDeviceId, RoomId, TransactionId, UserId,
 };
@@ -93,6 +93,9 @@ pub trait CryptoStore: AsyncTraitDeps {
     /// * `sender_key` - The sender key that was used to establish the sessions.
     async fn get_sessions(&self, sender_key: &str) -> Result<Option<Vec<Session>>, Self::Error>;
 
+    /// Get all the Olm sessions we have stored.
+    async fn get_all_sessions(&self) -> Result<Vec<Session>, Self::Error>;
+
     /// Get the inbound group session from our store.
     ///
     /// # Arguments
@@ -118,6 +121,9 @@ pub trait CryptoStore: AsyncTraitDeps {
         session_id: &str,
     ) -> Result<Option<RoomKeyWithheldEvent>, Self::Error>;
 
+    /// Get all the withheld info we have stored.
+    async fn get_all_withheld_info(&self) -> Result<Vec<RoomKeyWithheldEvent>, Self::Error>;
+
     /// Get all the inbound group sessions we have stored.
     async fn get_inbound_group_sessions(&self) -> Result<Vec<InboundGroupSession>, Self::Error>;
 
@@ -266,6 +272,9 @@ pub trait CryptoStore: AsyncTraitDeps {
     /// Check if a hash for an Olm message stored in the database.
     async fn is_message_known(&self, message_hash: &OlmMessageHash) -> Result<bool, Self::Error>;
 
+    /// Get all the hashes of the Olm messages stored in the database.
+    async fn get_all_olm_message_hashes(&self) -> Result<Vec<OlmMessageHash>, Self::Error>;
+
     /// Get an outgoing secret request that we created that matches the given
     /// request id.
     ///
@@ -334,6 +343,11 @@ pub trait CryptoStore: AsyncTraitDeps {
         user_id: &UserId,
     ) -> Result<Option<StoredRoomKeyBundleData>, Self::Error>;
 
+    /// Get the details about all the room key bundle data we have received.
+    async fn get_all_received_room_key_bundle_data(
+        &self,
+    ) -> Result<Vec<StoredRoomKeyBundleData>, Self::Error>;
+
     /// Get arbitrary data from the store
     ///
     /// # Arguments
@@ -357,6 +371,9 @@ pub trait CryptoStore: AsyncTraitDeps {
     /// * `key` - The key to insert data into
     async fn remove_custom_value(&self, key: &str) -> Result<(), Self::Error>;
 
+    /// Get all the arbitrary data put into the store, with their keys.
+    async fn get_all_custom_values(&self) -> Result<Vec<(String, Vec<u8>)>, Self::Error>;
+
     /// Try to take a leased lock.
     ///
     /// This attempts to take a lock for the given lease duration.
@@ -377,6 +394,16 @@ pub trait CryptoStore: AsyncTraitDeps {
 
     /// Load the next-batch token for a to-device query, if any.
     async fn next_batch_token(&self) -> Result<Option<String>, Self::Error>;
+
+    /// Get how much space the store is using.
+    async fn storage_usage(&self) -> Result<StoreUsage, Self::Error>;
+
+    /// Compact the store, to give back to the system the space that isn't
+    /// used anymore.
+    ///
+    /// This may take a while on big stores, and may block other accesses to
+    /// the store in the meantime.
+    async fn compact(&self) -> Result<(), Self::Error>;
 }
 
 #[repr(transparent)]
@@ -422,6 +449,10 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
         self.0.get_sessions(sender_key).await.map_err(Into::into)
     }
 
+    async fn get_all_sessions(&self) -> Result<Vec<Session>> {
+        self.0.get_all_sessions().await.map_err(Into::into)
+    }
+
     async fn get_inbound_group_session(
         &self,
         room_id: &RoomId,
@@ -430,6 +461,10 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
         self.0.get_inbound_group_session(room_id, session_id).await.map_err(Into::into)
     }
 
+    async fn get_all_withheld_info(&self) -> Result<Vec<RoomKeyWithheldEvent>> {
+        self.0.get_all_withheld_info().await.map_err(Into::into)
+    }
+
     async fn get_inbound_group_sessions(&self) -> Result<Vec<InboundGroupSession>> {
         self.0.get_inbound_group_sessions().await.map_err(Into::into)
     }
@@ -535,6 +570,10 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
         self.0.is_message_known(message_hash).await.map_err(Into::into)
     }
 
+    async fn get_all_olm_message_hashes(&self) -> Result<Vec<OlmMessageHash>> {
+        self.0.get_all_olm_message_hashes().await.map_err(Into::into)
+    }
+
     async fn get_outgoing_secret_requests(
         &self,
         request_id: &TransactionId,
@@ -588,6 +627,10 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
         self.0.get_received_room_key_bundle_data(room_id, user_id).await.map_err(Into::into)
     }
 
+    async fn get_all_received_room_key_bundle_data(&self) -> Result<Vec<StoredRoomKeyBundleData>> {
+        self.0.get_all_received_room_key_bundle_data().await.map_err(Into::into)
+    }
+
     async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>, Self::Error> {
         self.0.get_custom_value(key).await.map_err(Into::into)
     }
@@ -600,6 +643,10 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
         self.0.remove_custom_value(key).await.map_err(Into::into)
     }
 
+    async fn get_all_custom_values(&self) -> Result<Vec<(String, Vec<u8>)>, Self::Error> {
+        self.0.get_all_custom_values().await.map_err(Into::into)
+    }
+
     async fn try_take_leased_lock(
         &self,
         lease_duration_ms: u32,
@@ -612,6 +659,14 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
     async fn next_batch_token(&self) -> Result<Option<String>, Self::Error> {
         self.0.next_batch_token().await.map_err(Into::into)
     }
+
+    async fn storage_usage(&self) -> Result<StoreUsage, Self::Error> {
+        self.0.storage_usage().await.map_err(Into::into)
+    }
+
+    async fn compact(&self) -> Result<(), Self::Error> {
+        self.0.compact().await.map_err(Into::into)
+    }
 }
 
 /// A type-erased [`CryptoStore`].
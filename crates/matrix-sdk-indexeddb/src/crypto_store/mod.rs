@@ -22,6 +22,7 @@ use gloo_utils::format::JsValueSerdeExt;
 use hkdf::Hkdf;
 use indexed_db_futures::prelude::*;
 use js_sys::Array;
+use matrix_sdk_common::store_usage::StoreUsage;
 use matrix_sdk_crypto::{
     olm::{
         Curve25519PublicKey, InboundGroupSession, OlmMessageHash, OutboundGroupSession,
@@ -111,6 +112,18 @@ mod keys {
 
     /// Indexeddb key for the dehydrated device pickle key.
     pub const DEHYDRATION_PICKLE_KEY: &str = "dehydration_pickle_key";
+
+    /// The keys of the [`CORE`] store that are not custom values.
+    pub const INTERNAL_CORE_KEYS: &[&str] = &[
+        STORE_CIPHER,
+        ACCOUNT,
+        NEXT_BATCH_TOKEN,
+        PRIVATE_IDENTITY,
+        BACKUP_KEYS,
+        BACKUP_VERSION_V1,
+        RECOVERY_KEY_V1,
+        DEHYDRATION_PICKLE_KEY,
+    ];
 }
 
 /// An implementation of [CryptoStore] that uses [IndexedDB] for persistent
@@ -153,6 +166,8 @@ pub enum IndexeddbCryptoStoreError {
          Existing version: {current_version}; max supported version: {max_supported_version}"
     )]
     SchemaTooNewError { max_supported_version: u32, current_version: u32 },
+    #[error("The store is not encrypted")]
+    NotEncrypted,
 }
 
 impl From<IndexeddbSerializerError> for IndexeddbCryptoStoreError {
@@ -353,6 +368,49 @@ impl IndexeddbCryptoStore {
         IndexeddbCryptoStore::open_with_store_cipher(prefix, Some(store_cipher.into())).await
     }
 
+    /// Change the passphrase of an `IndexeddbCryptoStore` opened with
+    /// [`IndexeddbCryptoStore::open_with_passphrase`].
+    ///
+    /// Only the encryption cipher saved in the meta store is re-encrypted, in a
+    /// single transaction, so the store can always be opened with either the
+    /// old or the new passphrase.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - Common prefix for the names of the two IndexedDB stores.
+    /// * `old_passphrase` - The passphrase currently used to open the store.
+    /// * `new_passphrase` - The passphrase to use from now on.
+    pub async fn change_passphrase(
+        prefix: &str,
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<()> {
+        let db = open_meta_db(prefix).await?;
+
+        let result = async {
+            let cipher =
+                load_store_cipher(&db).await?.ok_or(IndexeddbCryptoStoreError::NotEncrypted)?;
+            let cipher = StoreCipher::import(old_passphrase, &cipher)
+                .map_err(|_| CryptoStoreError::UnpicklingError)?;
+
+            #[cfg(not(test))]
+            let export = cipher.export(new_passphrase);
+            #[cfg(test)]
+            let export = cipher._insecure_export_fast_for_testing(new_passphrase);
+
+            let export = export.map_err(CryptoStoreError::backend)?;
+
+            save_store_cipher(&db, &export).await
+        }
+        .await;
+
+        // Must release the database access manually as it's not done when
+        // dropping it.
+        db.close();
+
+        result
+    }
+
     /// Open an `IndexeddbCryptoStore` with given name and key.
     ///
     /// If the store previously existed, the encryption cipher is initialised
@@ -610,9 +668,11 @@ impl IndexeddbCryptoStore {
         if !olm_hashes.is_empty() {
             let mut hashes = indexeddb_changes.get(keys::OLM_HASHES);
             for hash in olm_hashes {
+                // The hash is stored as the value too, so the hashes can be listed even
+                // if the keys are hashed.
                 hashes.put(
                     self.serializer.encode_key(keys::OLM_HASHES, (&hash.sender_key, &hash.hash)),
-                    JsValue::TRUE,
+                    self.serializer.serialize_value(&hash)?,
                 );
             }
         }
@@ -945,6 +1005,24 @@ impl_crypto_store! {
         }
     }
 
+    async fn get_all_sessions(&self) -> Result<Vec<Session>> {
+        let device_keys = self.get_own_device().await?.as_device_keys().clone();
+
+        self.inner
+            .transaction_on_one_with_mode(keys::SESSION, IdbTransactionMode::Readonly)?
+            .object_store(keys::SESSION)?
+            .get_all()?
+            .await?
+            .iter()
+            .map(|value| {
+                let pickle = self.serializer.deserialize_value(value)?;
+                Session::from_pickle(device_keys.clone(), pickle).map_err(|_| {
+                    IndexeddbCryptoStoreError::CryptoStoreError(CryptoStoreError::AccountUnset)
+                })
+            })
+            .collect()
+    }
+
     async fn get_inbound_group_session(
         &self,
         room_id: &RoomId,
@@ -1225,6 +1303,21 @@ impl_crypto_store! {
             .is_some())
     }
 
+    async fn get_all_olm_message_hashes(&self) -> Result<Vec<OlmMessageHash>> {
+        Ok(self
+            .inner
+            .transaction_on_one_with_mode(keys::OLM_HASHES, IdbTransactionMode::Readonly)?
+            .object_store(keys::OLM_HASHES)?
+            .get_all()?
+            .await?
+            .iter()
+            // Hashes stored before the hash was used as the value only have `true` as
+            // their value, and can't be listed.
+            .filter(|value| value.as_bool().is_none())
+            .map(|value| self.serializer.deserialize_value(value))
+            .collect::<Result<_, _>>()?)
+    }
+
     async fn get_secrets_from_inbox(
         &self,
         secret_name: &SecretName,
@@ -1380,6 +1473,21 @@ impl_crypto_store! {
         }
     }
 
+    async fn get_all_withheld_info(&self) -> Result<Vec<RoomKeyWithheldEvent>> {
+        Ok(self
+            .inner
+            .transaction_on_one_with_mode(
+                keys::DIRECT_WITHHELD_INFO,
+                IdbTransactionMode::Readonly,
+            )?
+            .object_store(keys::DIRECT_WITHHELD_INFO)?
+            .get_all()?
+            .await?
+            .iter()
+            .map(|value| self.serializer.deserialize_value(value))
+            .collect::<Result<_, _>>()?)
+    }
+
     async fn get_room_settings(&self, room_id: &RoomId) -> Result<Option<RoomSettings>> {
         let key = self.serializer.encode_key(keys::ROOM_SETTINGS, room_id);
         self
@@ -1406,6 +1514,18 @@ impl_crypto_store! {
         Ok(result)
     }
 
+    async fn get_all_received_room_key_bundle_data(&self) -> Result<Vec<StoredRoomKeyBundleData>> {
+        Ok(self
+            .inner
+            .transaction_on_one_with_mode(keys::RECEIVED_ROOM_KEY_BUNDLES, IdbTransactionMode::Readonly)?
+            .object_store(keys::RECEIVED_ROOM_KEY_BUNDLES)?
+            .get_all()?
+            .await?
+            .iter()
+            .map(|value| self.serializer.deserialize_value(value))
+            .collect::<Result<_, _>>()?)
+    }
+
     async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
         self
             .inner
@@ -1437,6 +1557,32 @@ impl_crypto_store! {
         Ok(())
     }
 
+    async fn get_all_custom_values(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        let tx = self.inner.transaction_on_one_with_mode(keys::CORE, IdbTransactionMode::Readonly)?;
+        let store = tx.object_store(keys::CORE)?;
+
+        let mut values = Vec::new();
+
+        if let Some(cursor) = store.open_cursor()?.await? {
+            loop {
+                if let Some(key) = cursor.key().and_then(|key| key.as_string()) {
+                    // The leases are stored in the same store, but they aren't bytes.
+                    if !keys::INTERNAL_CORE_KEYS.contains(&key.as_str()) {
+                        if let Ok(value) = self.serializer.deserialize_value(cursor.value()) {
+                            values.push((key, value));
+                        }
+                    }
+                }
+
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+
+        Ok(values)
+    }
+
     async fn try_take_leased_lock(
         &self,
         lease_duration_ms: u32,
@@ -1477,6 +1623,16 @@ impl_crypto_store! {
             }
         }
     }
+
+    async fn storage_usage(&self) -> Result<StoreUsage> {
+        // The browser doesn't expose the space used by a single database.
+        Ok(StoreUsage::default())
+    }
+
+    async fn compact(&self) -> Result<()> {
+        // The browser takes care of compacting the databases itself.
+        Ok(())
+    }
 }
 
 impl Drop for IndexeddbCryptoStore {
@@ -2052,4 +2208,46 @@ mod encrypted_tests {
             store.load_account().await.expect("Can't load account").expect("Account was not saved");
         assert_eq!(loaded_account.user_id, user_id!("@alice:example.org"));
     }
+
+    /// Test that the data of a store can be read with its new passphrase after
+    /// a change, and that the old passphrase is rejected.
+    #[async_test]
+    async fn test_change_passphrase() {
+        let store_name = "test_change_passphrase";
+
+        IndexeddbCryptoStore::delete_stores(store_name).unwrap();
+        let store = IndexeddbCryptoStore::open_with_passphrase(store_name, "old_passphrase")
+            .await
+            .expect("Can't create a passphrase-protected store");
+
+        store
+            .save_pending_changes(PendingChanges {
+                account: Some(Account::with_device_id(
+                    user_id!("@alice:example.org"),
+                    device_id!("ALICEDEVICE"),
+                )),
+            })
+            .await
+            .expect("Can't save account");
+        drop(store);
+
+        IndexeddbCryptoStore::change_passphrase(store_name, "wrong_passphrase", "new_passphrase")
+            .await
+            .expect_err("The passphrase was changed with a wrong passphrase");
+        IndexeddbCryptoStore::change_passphrase(store_name, "old_passphrase", "new_passphrase")
+            .await
+            .expect("Can't change the passphrase");
+
+        let store = IndexeddbCryptoStore::open_with_passphrase(store_name, "new_passphrase")
+            .await
+            .expect("Can't open the store with the new passphrase");
+        let loaded_account =
+            store.load_account().await.expect("Can't load account").expect("Account was not saved");
+        assert_eq!(loaded_account.user_id, user_id!("@alice:example.org"));
+        drop(store);
+
+        IndexeddbCryptoStore::open_with_passphrase(store_name, "old_passphrase")
+            .await
+            .expect_err("The store was opened with the old passphrase");
+    }
 }
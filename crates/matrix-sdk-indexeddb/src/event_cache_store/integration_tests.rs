@@ -90,7 +90,7 @@ pub async fn test_add_gap_chunk_and_delete_it_immediately(store: IndexeddbEventC
         previous: None,
         new: ChunkIdentifier::new(1),
         next: None,
-        gap: Gap { prev_token: "cheese".to_owned() },
+        gap: Gap::new("cheese"),
     }];
     store.handle_linked_chunk_updates(linked_chunk_id, updates).await.unwrap();
 
@@ -99,7 +99,7 @@ pub async fn test_add_gap_chunk_and_delete_it_immediately(store: IndexeddbEventC
             previous: Some(ChunkIdentifier::new(1)),
             new: ChunkIdentifier::new(3),
             next: None,
-            gap: Gap { prev_token: "milk".to_owned() },
+            gap: Gap::new("milk"),
         },
         Update::RemoveChunk(ChunkIdentifier::new(3)),
     ];
@@ -116,7 +116,7 @@ pub async fn test_linked_chunk_new_gap_chunk(store: IndexeddbEventCacheStore) {
         previous: None,
         new: ChunkIdentifier::new(42),
         next: None,
-        gap: Gap { prev_token: "raclette".to_owned() },
+        gap: Gap::new("raclette"),
     }];
     store.handle_linked_chunk_updates(linked_chunk_id, updates).await.unwrap();
 
@@ -129,7 +129,7 @@ pub async fn test_linked_chunk_new_gap_chunk(store: IndexeddbEventCacheStore) {
     assert_eq!(c.previous, None);
     assert_eq!(c.next, None);
     assert_matches!(c.content, ChunkContent::Gap(gap) => {
-        assert_eq!(gap.prev_token, "raclette");
+        assert_eq!(gap.prev_token.as_deref(), Some("raclette"));
     });
 }
 
@@ -171,19 +171,19 @@ pub async fn test_linked_chunk_remove_chunk(store: IndexeddbEventCacheStore) {
             previous: None,
             new: ChunkIdentifier::new(42),
             next: None,
-            gap: Gap { prev_token: "raclette".to_owned() },
+            gap: Gap::new("raclette"),
         },
         Update::NewGapChunk {
             previous: Some(ChunkIdentifier::new(42)),
             new: ChunkIdentifier::new(43),
             next: None,
-            gap: Gap { prev_token: "fondue".to_owned() },
+            gap: Gap::new("fondue"),
         },
         Update::NewGapChunk {
             previous: Some(ChunkIdentifier::new(43)),
             new: ChunkIdentifier::new(44),
             next: None,
-            gap: Gap { prev_token: "tartiflette".to_owned() },
+            gap: Gap::new("tartiflette"),
         },
         Update::RemoveChunk(ChunkIdentifier::new(43)),
     ];
@@ -198,7 +198,7 @@ pub async fn test_linked_chunk_remove_chunk(store: IndexeddbEventCacheStore) {
     assert_eq!(c.previous, None);
     assert_eq!(c.next, Some(ChunkIdentifier::new(44)));
     assert_matches!(c.content, ChunkContent::Gap(gap) => {
-        assert_eq!(gap.prev_token, "raclette");
+        assert_eq!(gap.prev_token.as_deref(), Some("raclette"));
     });
 
     let c = chunks.remove(0);
@@ -206,7 +206,7 @@ pub async fn test_linked_chunk_remove_chunk(store: IndexeddbEventCacheStore) {
     assert_eq!(c.previous, Some(ChunkIdentifier::new(42)));
     assert_eq!(c.next, None);
     assert_matches!(c.content, ChunkContent::Gap(gap) => {
-        assert_eq!(gap.prev_token, "tartiflette");
+        assert_eq!(gap.prev_token.as_deref(), Some("tartiflette"));
     });
 }
 
@@ -342,7 +342,7 @@ pub async fn test_linked_chunk_clear(store: IndexeddbEventCacheStore) {
             previous: Some(ChunkIdentifier::new(42)),
             new: ChunkIdentifier::new(54),
             next: None,
-            gap: Gap { prev_token: "fondue".to_owned() },
+            gap: Gap::new("fondue"),
         },
         Update::PushItems {
             at: Position::new(ChunkIdentifier::new(42), 0),
@@ -14,12 +14,14 @@
 
 #![allow(unused)]
 
+use std::collections::BTreeMap;
+
 use indexed_db_futures::IdbDatabase;
 use matrix_sdk_base::{
     event_cache::{
         store::{
             media::{IgnoreMediaRetentionPolicy, MediaRetentionPolicy},
-            EventCacheStore, MemoryStore,
+            EventAggregations, EventCacheStore, EventRetentionPolicy, MemoryStore,
         },
         Event, Gap,
     },
@@ -28,6 +30,7 @@ use matrix_sdk_base::{
         RawChunk, Update,
     },
     media::MediaRequestParameters,
+    store_usage::{ItemsUsage, StoreUsage},
     timer,
 };
 use ruma::{events::relation::RelationType, EventId, MxcUri, OwnedEventId, RoomId};
@@ -146,6 +149,15 @@ impl_event_cache_store! {
     ) -> Result<(), IndexeddbEventCacheStoreError> {
         let _timer = timer!("method");
 
+        if matches!(linked_chunk_id, LinkedChunkId::Detached(_)) {
+            // Detached linked chunks are only kept in memory for the time being.
+            return self
+                .memory_store
+                .handle_linked_chunk_updates(linked_chunk_id, updates)
+                .await
+                .map_err(IndexeddbEventCacheStoreError::MemoryStore);
+        }
+
         let linked_chunk_id = linked_chunk_id.to_owned();
         let room_id = linked_chunk_id.room_id();
 
@@ -178,6 +190,8 @@ impl_event_cache_store! {
                             &types::Gap {
                                 chunk_identifier: new.index(),
                                 prev_token: gap.prev_token,
+                                next_token: gap.next_token,
+                                evicted_before: gap.evicted_before,
                             },
                         )
                         .await?;
@@ -257,6 +271,13 @@ impl_event_cache_store! {
                     transaction.delete_chunks_in_room(room_id).await?;
                     transaction.delete_events_in_room(room_id).await?;
                     transaction.delete_gaps_in_room(room_id).await?;
+
+                    // The aggregations are still kept in memory, and must be cleared along
+                    // with the room's linked chunk.
+                    self.memory_store
+                        .handle_linked_chunk_updates(linked_chunk_id.as_ref(), vec![Update::Clear])
+                        .await
+                        .map_err(IndexeddbEventCacheStoreError::MemoryStore)?;
                 }
             }
         }
@@ -271,6 +292,15 @@ impl_event_cache_store! {
     ) -> Result<Vec<RawChunk<Event, Gap>>, IndexeddbEventCacheStoreError> {
         let _ = timer!("method");
 
+        if matches!(linked_chunk_id, LinkedChunkId::Detached(_)) {
+            // Detached linked chunks are only kept in memory for the time being.
+            return self
+                .memory_store
+                .load_all_chunks(linked_chunk_id)
+                .await
+                .map_err(IndexeddbEventCacheStoreError::MemoryStore);
+        }
+
         let linked_chunk_id = linked_chunk_id.to_owned();
         let room_id = linked_chunk_id.room_id();
 
@@ -309,6 +339,15 @@ impl_event_cache_store! {
         // https://github.com/matrix-org/matrix-rust-sdk/pull/5382.
         let _ = timer!("method");
 
+        if matches!(linked_chunk_id, LinkedChunkId::Detached(_)) {
+            // Detached linked chunks are only kept in memory for the time being.
+            return self
+                .memory_store
+                .load_all_chunks_metadata(linked_chunk_id)
+                .await
+                .map_err(IndexeddbEventCacheStoreError::MemoryStore);
+        }
+
         let linked_chunk_id = linked_chunk_id.to_owned();
         let room_id = linked_chunk_id.room_id();
 
@@ -342,6 +381,15 @@ impl_event_cache_store! {
     > {
         let _timer = timer!("method");
 
+        if matches!(linked_chunk_id, LinkedChunkId::Detached(_)) {
+            // Detached linked chunks are only kept in memory for the time being.
+            return self
+                .memory_store
+                .load_last_chunk(linked_chunk_id)
+                .await
+                .map_err(IndexeddbEventCacheStoreError::MemoryStore);
+        }
+
         let linked_chunk_id = linked_chunk_id.to_owned();
         let room_id = linked_chunk_id.room_id();
         let transaction = self.transaction(
@@ -398,6 +446,15 @@ impl_event_cache_store! {
     ) -> Result<Option<RawChunk<Event, Gap>>, IndexeddbEventCacheStoreError> {
         let _timer = timer!("method");
 
+        if matches!(linked_chunk_id, LinkedChunkId::Detached(_)) {
+            // Detached linked chunks are only kept in memory for the time being.
+            return self
+                .memory_store
+                .load_previous_chunk(linked_chunk_id, before_chunk_identifier)
+                .await
+                .map_err(IndexeddbEventCacheStoreError::MemoryStore);
+        }
+
         let linked_chunk_id = linked_chunk_id.to_owned();
         let room_id = linked_chunk_id.room_id();
         let transaction = self.transaction(
@@ -425,7 +482,12 @@ impl_event_cache_store! {
         transaction.clear::<types::Event>().await?;
         transaction.clear::<types::Gap>().await?;
         transaction.commit().await?;
-        Ok(())
+
+        // The aggregations are still kept in memory.
+        self.memory_store
+            .clear_all_linked_chunks()
+            .await
+            .map_err(IndexeddbEventCacheStoreError::MemoryStore)
     }
 
     #[instrument(skip(self, events))]
@@ -481,6 +543,72 @@ impl_event_cache_store! {
             .map_err(IndexeddbEventCacheStoreError::MemoryStore)
     }
 
+    #[instrument(skip(self))]
+    async fn get_all_room_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<Event>, IndexeddbEventCacheStoreError> {
+        let _timer = timer!("method");
+        self.memory_store
+            .get_all_room_events(room_id)
+            .await
+            .map_err(IndexeddbEventCacheStoreError::MemoryStore)
+    }
+
+    #[instrument(skip(self, aggregations))]
+    async fn save_event_aggregations(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        aggregations: EventAggregations,
+    ) -> Result<(), IndexeddbEventCacheStoreError> {
+        let _timer = timer!("method");
+        self.memory_store
+            .save_event_aggregations(room_id, event_id, aggregations)
+            .await
+            .map_err(IndexeddbEventCacheStoreError::MemoryStore)
+    }
+
+    #[instrument(skip(self, event_ids))]
+    async fn load_event_aggregations(
+        &self,
+        room_id: &RoomId,
+        event_ids: &[OwnedEventId],
+    ) -> Result<BTreeMap<OwnedEventId, EventAggregations>, IndexeddbEventCacheStoreError> {
+        let _timer = timer!("method");
+        self.memory_store
+            .load_event_aggregations(room_id, event_ids)
+            .await
+            .map_err(IndexeddbEventCacheStoreError::MemoryStore)
+    }
+
+    #[instrument(skip_all)]
+    async fn set_event_retention_policy(
+        &self,
+        policy: EventRetentionPolicy,
+    ) -> Result<(), IndexeddbEventCacheStoreError> {
+        let _timer = timer!("method");
+        self.memory_store
+            .set_event_retention_policy(policy)
+            .await
+            .map_err(IndexeddbEventCacheStoreError::MemoryStore)
+    }
+
+    #[instrument(skip_all)]
+    fn event_retention_policy(&self) -> EventRetentionPolicy {
+        let _timer = timer!("method");
+        self.memory_store.event_retention_policy()
+    }
+
+    #[instrument(skip_all)]
+    async fn clean_up_linked_chunks(&self) -> Result<(), IndexeddbEventCacheStoreError> {
+        let _timer = timer!("method");
+        self.memory_store
+            .clean_up_linked_chunks()
+            .await
+            .map_err(IndexeddbEventCacheStoreError::MemoryStore)
+    }
+
     #[instrument(skip_all)]
     async fn add_media_content(
         &self,
@@ -595,6 +723,52 @@ impl_event_cache_store! {
             .await
             .map_err(IndexeddbEventCacheStoreError::MemoryStore)
     }
+
+    #[instrument(skip_all)]
+    async fn get_all_media_content(
+        &self,
+    ) -> Result<
+        Vec<(MediaRequestParameters, Vec<u8>, IgnoreMediaRetentionPolicy)>,
+        IndexeddbEventCacheStoreError,
+    > {
+        let _timer = timer!("method");
+        self.memory_store
+            .get_all_media_content()
+            .await
+            .map_err(IndexeddbEventCacheStoreError::MemoryStore)
+    }
+
+    #[instrument(skip_all)]
+    async fn storage_usage(&self) -> Result<StoreUsage, IndexeddbEventCacheStoreError> {
+        let _timer = timer!("method");
+        self.memory_store.storage_usage().await.map_err(IndexeddbEventCacheStoreError::MemoryStore)
+    }
+
+    #[instrument(skip_all)]
+    async fn room_events_usage(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<ItemsUsage, IndexeddbEventCacheStoreError> {
+        let _timer = timer!("method");
+        self.memory_store
+            .room_events_usage(room_id)
+            .await
+            .map_err(IndexeddbEventCacheStoreError::MemoryStore)
+    }
+
+    #[instrument(skip_all)]
+    async fn media_usage(
+        &self,
+    ) -> Result<BTreeMap<String, ItemsUsage>, IndexeddbEventCacheStoreError> {
+        let _timer = timer!("method");
+        self.memory_store.media_usage().await.map_err(IndexeddbEventCacheStoreError::MemoryStore)
+    }
+
+    #[instrument(skip_all)]
+    async fn compact(&self) -> Result<(), IndexeddbEventCacheStoreError> {
+        let _timer = timer!("method");
+        self.memory_store.compact().await.map_err(IndexeddbEventCacheStoreError::MemoryStore)
+    }
 }
 
 #[cfg(test)]
@@ -479,7 +479,11 @@ impl<'a> IndexeddbEventCacheStoreTransaction<'a> {
                         .get_gap_by_id(room_id, &ChunkIdentifier::new(chunk.identifier))
                         .await?
                         .ok_or(IndexeddbEventCacheStoreTransactionError::ItemNotFound)?;
-                    ChunkContent::Gap(RawGap { prev_token: gap.prev_token })
+                    ChunkContent::Gap(RawGap {
+                        prev_token: gap.prev_token,
+                        next_token: gap.next_token,
+                        evicted_before: gap.evicted_before,
+                    })
                 }
             };
             return Ok(Some(RawChunk {
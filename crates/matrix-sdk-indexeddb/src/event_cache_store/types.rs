@@ -162,6 +162,15 @@ pub struct Gap {
     /// The identifier of the chunk containing this gap.
     pub chunk_identifier: u64,
     /// The token to use in the query, extracted from a previous "from" /
-    /// "end" field of a `/messages` response.
-    pub prev_token: String,
+    /// "end" field of a `/messages` response, if the gap doesn't stand for
+    /// evicted events.
+    pub prev_token: Option<String>,
+    /// The token to paginate forwards from this gap, if it's the gap after
+    /// the events of a detached linked chunk.
+    #[serde(default)]
+    pub next_token: Option<String>,
+    /// The ID of the event that was right after the events this gap stands
+    /// for, if they were evicted from the store.
+    #[serde(default)]
+    pub evicted_before: Option<OwnedEventId>,
 }
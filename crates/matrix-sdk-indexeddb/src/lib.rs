@@ -62,6 +62,21 @@ pub async fn open_state_store(
     Ok(state_store)
 }
 
+/// Change the passphrase of the stores opened with the given name by
+/// [`open_stores_with_name`] or [`open_state_store`].
+///
+/// Only the key encrypting the data of the stores is re-encrypted, in a single
+/// transaction, so the stores can always be opened with either the old or the
+/// new passphrase, even if the change is interrupted.
+#[cfg(feature = "state-store")]
+pub async fn change_stores_passphrase(
+    name: &str,
+    old_passphrase: &str,
+    new_passphrase: &str,
+) -> Result<(), IndexeddbStateStoreError> {
+    IndexeddbStateStore::change_passphrase_with_name(name, old_passphrase, new_passphrase).await
+}
+
 /// All the errors that can occur when opening an IndexedDB store.
 #[derive(Error, Debug)]
 pub enum OpenStoreError {
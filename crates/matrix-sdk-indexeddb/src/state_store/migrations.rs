@@ -136,6 +136,42 @@ pub async fn upgrade_meta_db(
     Ok((meta_db, store_cipher))
 }
 
+/// Re-encrypt the store cipher saved in the meta database with a new
+/// passphrase.
+///
+/// The store cipher is replaced in a single transaction, so the database can
+/// always be opened with either the old or the new passphrase.
+pub async fn change_meta_db_passphrase(
+    meta_db: &IdbDatabase,
+    old_passphrase: &str,
+    new_passphrase: &str,
+) -> Result<()> {
+    let tx: IdbTransaction<'_> = meta_db
+        .transaction_on_one_with_mode(keys::INTERNAL_STATE, IdbTransactionMode::Readwrite)?;
+    let ob = tx.object_store(keys::INTERNAL_STATE)?;
+
+    let Some(StoreKeyWrapper(inner)) =
+        ob.get(&JsValue::from_str(keys::STORE_KEY))?.await?.map(|v| v.into_serde()).transpose()?
+    else {
+        return Err(IndexeddbStateStoreError::NotEncrypted);
+    };
+
+    let cipher = StoreCipher::import(old_passphrase, &inner)?;
+    #[cfg(not(test))]
+    let export = cipher.export(new_passphrase)?;
+    #[cfg(test)]
+    let export = cipher._insecure_export_fast_for_testing(new_passphrase)?;
+
+    ob.put_key_val(
+        &JsValue::from_str(keys::STORE_KEY),
+        &JsValue::from_serde(&StoreKeyWrapper(export))?,
+    )?;
+
+    tx.await.into_result()?;
+
+    Ok(())
+}
+
 /// Helper struct for upgrading the inner DB.
 #[derive(Debug, Clone, Default)]
 pub struct OngoingMigration {
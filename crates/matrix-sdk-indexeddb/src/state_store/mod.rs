@@ -29,6 +29,7 @@ use matrix_sdk_base::{
         QueuedRequest, QueuedRequestKind, RoomLoadSettings, SentRequestKey,
         SerializableEventContent, ServerInfo, StateChanges, StateStore, StoreError,
     },
+    store_usage::StoreUsage,
     MinimalRoomMemberEvent, RoomInfo, RoomMemberships, StateStoreDataKey, StateStoreDataValue,
     ROOM_VERSION_FALLBACK,
 };
@@ -56,7 +57,7 @@ use web_sys::IdbKeyRange;
 mod migrations;
 
 pub use self::migrations::MigrationConflictStrategy;
-use self::migrations::{upgrade_inner_db, upgrade_meta_db};
+use self::migrations::{change_meta_db_passphrase, upgrade_inner_db, upgrade_meta_db};
 use crate::safe_encode::SafeEncode;
 
 #[derive(Debug, thiserror::Error)]
@@ -74,6 +75,8 @@ pub enum IndexeddbStateStoreError {
          See MigrationConflictStrategy for ways to configure."
     )]
     MigrationConflict { name: String, old_version: u32, new_version: u32 },
+    #[error("The store is not encrypted")]
+    NotEncrypted,
 }
 
 impl From<web_sys::DomException> for IndexeddbStateStoreError {
@@ -292,6 +295,39 @@ impl IndexeddbStateStore {
         self.meta.version() as u32
     }
 
+    /// Change the passphrase used to encrypt this store.
+    ///
+    /// Only the key encrypting the data is re-encrypted, in a single
+    /// transaction. The crypto store opened with the same name by
+    /// `open_stores_with_name` uses the same key, so its passphrase is changed
+    /// too.
+    pub async fn change_passphrase(
+        &self,
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<()> {
+        change_meta_db_passphrase(&self.meta, old_passphrase, new_passphrase).await
+    }
+
+    /// Change the passphrase of the state store with the given name, without
+    /// opening it.
+    pub(crate) async fn change_passphrase_with_name(
+        name: &str,
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<()> {
+        let meta_name = format!("{name}::{}", keys::INTERNAL_STATE);
+        let (meta, _) = upgrade_meta_db(&meta_name, None).await?;
+
+        let result = change_meta_db_passphrase(&meta, old_passphrase, new_passphrase).await;
+
+        // Must release the database access manually as it's not done when
+        // dropping it.
+        meta.close();
+
+        result
+    }
+
     /// Whether this database has any migration backups
     pub async fn has_backups(&self) -> Result<bool> {
         Ok(self
@@ -1046,6 +1082,36 @@ impl_state_store!({
             .collect::<Vec<_>>())
     }
 
+    async fn get_all_state_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<RawAnySyncOrStrippedState>> {
+        let stripped_range = self.encode_to_range(keys::STRIPPED_ROOM_STATE, room_id)?;
+        let mut events = self
+            .inner
+            .transaction_on_one_with_mode(keys::STRIPPED_ROOM_STATE, IdbTransactionMode::Readonly)?
+            .object_store(keys::STRIPPED_ROOM_STATE)?
+            .get_all_with_key(&stripped_range)?
+            .await?
+            .iter()
+            .map(|f| self.deserialize_value(&f).map(RawAnySyncOrStrippedState::Stripped))
+            .collect::<Result<Vec<_>>>()?;
+
+        let range = self.encode_to_range(keys::ROOM_STATE, room_id)?;
+        let sync_events = self
+            .inner
+            .transaction_on_one_with_mode(keys::ROOM_STATE, IdbTransactionMode::Readonly)?
+            .object_store(keys::ROOM_STATE)?
+            .get_all_with_key(&range)?
+            .await?
+            .iter()
+            .map(|f| self.deserialize_value(&f).map(RawAnySyncOrStrippedState::Sync))
+            .collect::<Result<Vec<_>>>()?;
+        events.extend(sync_events);
+
+        Ok(events)
+    }
+
     async fn get_state_events_for_keys(
         &self,
         room_id: &RoomId,
@@ -1258,6 +1324,32 @@ impl_state_store!({
             .transpose()
     }
 
+    async fn get_all_account_data_events(&self) -> Result<Vec<Raw<AnyGlobalAccountDataEvent>>> {
+        self.inner
+            .transaction_on_one_with_mode(keys::ACCOUNT_DATA, IdbTransactionMode::Readonly)?
+            .object_store(keys::ACCOUNT_DATA)?
+            .get_all()?
+            .await?
+            .iter()
+            .map(|f| self.deserialize_value(&f))
+            .collect()
+    }
+
+    async fn get_all_room_account_data_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<Raw<AnyRoomAccountDataEvent>>> {
+        let range = self.encode_to_range(keys::ROOM_ACCOUNT_DATA, room_id)?;
+        self.inner
+            .transaction_on_one_with_mode(keys::ROOM_ACCOUNT_DATA, IdbTransactionMode::Readonly)?
+            .object_store(keys::ROOM_ACCOUNT_DATA)?
+            .get_all_with_key(&range)?
+            .await?
+            .iter()
+            .map(|f| self.deserialize_value(&f))
+            .collect()
+    }
+
     async fn get_user_room_receipt_event(
         &self,
         room_id: &RoomId,
@@ -1342,6 +1434,28 @@ impl_state_store!({
         Ok(prev)
     }
 
+    async fn get_all_custom_values(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let tx =
+            self.inner.transaction_on_one_with_mode(keys::CUSTOM, IdbTransactionMode::Readonly)?;
+        let store = tx.object_store(keys::CUSTOM)?;
+
+        let mut values = Vec::new();
+
+        if let Some(cursor) = store.open_cursor()?.await? {
+            loop {
+                if let Some(key) = cursor.key().and_then(|key| key.as_string()) {
+                    values.push((key.into_bytes(), self.deserialize_value(&cursor.value())?));
+                }
+
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+
+        Ok(values)
+    }
+
     async fn remove_room(&self, room_id: &RoomId) -> Result<()> {
         // All the stores which use a RoomId as their key (and nothing additional).
         let direct_stores = [keys::ROOM_INFOS, keys::ROOM_SEND_QUEUE, keys::DEPENDENT_SEND_QUEUE];
@@ -1780,6 +1894,16 @@ impl_state_store!({
             |val| self.deserialize_value::<Vec<DependentQueuedRequest>>(&val),
         )
     }
+
+    async fn storage_usage(&self) -> Result<StoreUsage> {
+        // The browser doesn't expose the space used by a single database.
+        Ok(StoreUsage::default())
+    }
+
+    async fn compact(&self) -> Result<()> {
+        // The browser takes care of compacting the databases itself.
+        Ok(())
+    }
 });
 
 /// A room member.
@@ -1881,7 +2005,8 @@ mod encrypted_tests {
     #[cfg(target_family = "wasm")]
     wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
 
-    use matrix_sdk_base::statestore_integration_tests;
+    use matrix_sdk_base::{statestore_integration_tests, StateStore};
+    use matrix_sdk_test::async_test;
     use uuid::Uuid;
 
     use super::{IndexeddbStateStore, Result};
@@ -1893,4 +2018,60 @@ mod encrypted_tests {
     }
 
     statestore_integration_tests!();
+
+    async fn open_store(name: &str, passphrase: &str) -> Result<IndexeddbStateStore> {
+        IndexeddbStateStore::builder()
+            .name(name.to_owned())
+            .passphrase(passphrase.to_owned())
+            .build()
+            .await
+    }
+
+    #[async_test]
+    async fn test_change_passphrase() -> Result<()> {
+        let name = format!("test-state-change-passphrase-{}", Uuid::new_v4().as_hyphenated());
+
+        let store = open_store(&name, "old_passphrase").await?;
+        store.set_custom_value(b"key", b"value".to_vec()).await?;
+
+        store.change_passphrase("old_passphrase", "new_passphrase").await?;
+
+        // The passphrase can't be changed with the old passphrase anymore.
+        assert!(store.change_passphrase("old_passphrase", "other_passphrase").await.is_err());
+        drop(store);
+
+        // The data is still readable with the new passphrase.
+        let store = open_store(&name, "new_passphrase").await?;
+        assert_eq!(store.get_custom_value(b"key").await?.as_deref(), Some(&b"value"[..]));
+        drop(store);
+
+        // The old passphrase is rejected.
+        assert!(open_store(&name, "old_passphrase").await.is_err());
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_change_stores_passphrase() -> Result<()> {
+        let name = format!("test-state-change-stores-passphrase-{}", Uuid::new_v4().as_hyphenated());
+
+        let store = open_store(&name, "old_passphrase").await?;
+        store.set_custom_value(b"key", b"value".to_vec()).await?;
+        drop(store);
+
+        // A wrong passphrase is rejected.
+        assert!(crate::change_stores_passphrase(&name, "wrong_passphrase", "new_passphrase")
+            .await
+            .is_err());
+
+        crate::change_stores_passphrase(&name, "old_passphrase", "new_passphrase").await?;
+
+        let store = open_store(&name, "new_passphrase").await?;
+        assert_eq!(store.get_custom_value(b"key").await?.as_deref(), Some(&b"value"[..]));
+        drop(store);
+
+        assert!(open_store(&name, "old_passphrase").await.is_err());
+
+        Ok(())
+    }
 }
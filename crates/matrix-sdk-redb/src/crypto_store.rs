@@ -0,0 +1,1204 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+use matrix_sdk_common::store_usage::StoreUsage;
+use matrix_sdk_crypto::{
+    olm::{
+        InboundGroupSession, OlmMessageHash, OutboundGroupSession, PickledInboundGroupSession,
+        PrivateCrossSigningIdentity, SenderDataType, Session, StaticAccountData,
+    },
+    store::{
+        types::{
+            BackupKeys, Changes, DehydratedDeviceKey, PendingChanges, RoomKeyCounts, RoomSettings,
+            StoredRoomKeyBundleData,
+        },
+        CryptoStore,
+    },
+    types::events::room_key_withheld::RoomKeyWithheldEvent,
+    Account, DeviceData, GossipRequest, GossippedSecret, SecretInfo, TrackedUser, UserIdentityData,
+};
+use matrix_sdk_store_encryption::StoreCipher;
+use redb::{ReadableTable, ReadableTableMetadata, TableDefinition};
+use ruma::{
+    events::secret::request::SecretName, DeviceId, OwnedDeviceId, RoomId, TransactionId, UserId,
+};
+use serde::de::DeserializeOwned;
+use tokio::sync::Mutex;
+use tracing::{instrument, warn};
+use vodozemac::Curve25519PublicKey;
+
+use crate::{
+    error::{Error, Result},
+    utils::{
+        get, get_prefixed, key, remove_prefixed, split_value, EncryptableStore, RedbDatabase, Table,
+    },
+    OpenStoreError,
+};
+
+mod keys {
+    // Tables
+    pub const KV_BLOB: &str = "kv_blob";
+    pub const SESSION: &str = "session";
+    pub const INBOUND_GROUP_SESSION: &str = "inbound_group_session";
+    pub const INBOUND_GROUP_SESSION_BY_DEVICE: &str = "inbound_group_session_by_device";
+    pub const INBOUND_GROUP_SESSION_NOT_BACKED_UP: &str = "inbound_group_session_not_backed_up";
+    pub const OUTBOUND_GROUP_SESSION: &str = "outbound_group_session";
+    pub const DEVICE: &str = "device";
+    pub const IDENTITY: &str = "identity";
+    pub const OLM_HASH: &str = "olm_hash";
+    pub const TRACKED_USER: &str = "tracked_user";
+    pub const KEY_REQUEST: &str = "key_request";
+    pub const DIRECT_WITHHELD_INFO: &str = "direct_withheld_info";
+    pub const ROOM_SETTINGS: &str = "room_settings";
+    pub const SECRET: &str = "secret";
+    pub const RECEIVED_ROOM_KEY_BUNDLE: &str = "received_room_key_bundle";
+    pub const LEASE_LOCK: &str = "lease_lock";
+
+    // Keys of the `kv_blob` table
+    pub const ACCOUNT: &str = "account";
+    pub const PRIVATE_IDENTITY: &str = "identity";
+    pub const NEXT_BATCH_TOKEN: &str = "next_batch_token";
+    pub const RECOVERY_KEY: &str = "recovery_key_v1";
+    pub const BACKUP_VERSION: &str = "backup_version_v1";
+    pub const DEHYDRATED_DEVICE_PICKLE_KEY: &str = "dehydrated_device_pickle_key";
+    pub const SECRET_SEQUENCE: &str = "secret_sequence";
+
+    /// The keys of the `kv_blob` table that are not custom values.
+    pub const INTERNAL: &[&str] = &[
+        ACCOUNT,
+        PRIVATE_IDENTITY,
+        NEXT_BATCH_TOKEN,
+        RECOVERY_KEY,
+        BACKUP_VERSION,
+        DEHYDRATED_DEVICE_PICKLE_KEY,
+        SECRET_SEQUENCE,
+    ];
+}
+
+mod tables {
+    use super::{keys, Table, TableDefinition};
+
+    pub const KV_BLOB: Table = TableDefinition::new(keys::KV_BLOB);
+    /// The Olm sessions, keyed by sender key and session ID.
+    pub const SESSION: Table = TableDefinition::new(keys::SESSION);
+    /// The inbound group sessions, keyed by session ID.
+    ///
+    /// The values are made of the backed-up flag, the room ID, the key of the
+    /// session in [`INBOUND_GROUP_SESSION_BY_DEVICE`] and the pickled session.
+    /// The backed-up flag is the source of truth, the one of the pickle isn't
+    /// updated when the session is marked as backed up.
+    pub const INBOUND_GROUP_SESSION: Table = TableDefinition::new(keys::INBOUND_GROUP_SESSION);
+    /// An index of the inbound group sessions by sender key, sender data type
+    /// and session ID.
+    pub const INBOUND_GROUP_SESSION_BY_DEVICE: Table =
+        TableDefinition::new(keys::INBOUND_GROUP_SESSION_BY_DEVICE);
+    /// The IDs of the inbound group sessions that are not backed up yet.
+    pub const INBOUND_GROUP_SESSION_NOT_BACKED_UP: Table =
+        TableDefinition::new(keys::INBOUND_GROUP_SESSION_NOT_BACKED_UP);
+    pub const OUTBOUND_GROUP_SESSION: Table = TableDefinition::new(keys::OUTBOUND_GROUP_SESSION);
+    /// The devices, keyed by user ID and device ID.
+    pub const DEVICE: Table = TableDefinition::new(keys::DEVICE);
+    pub const IDENTITY: Table = TableDefinition::new(keys::IDENTITY);
+    pub const OLM_HASH: Table = TableDefinition::new(keys::OLM_HASH);
+    pub const TRACKED_USER: Table = TableDefinition::new(keys::TRACKED_USER);
+    /// The secret requests, keyed by request ID.
+    ///
+    /// The values are made of the sent-out flag and the serialized request.
+    /// Like for [`INBOUND_GROUP_SESSION`], the flag is the source of truth.
+    pub const KEY_REQUEST: Table = TableDefinition::new(keys::KEY_REQUEST);
+    /// The withheld info, keyed by room ID and session ID.
+    pub const DIRECT_WITHHELD_INFO: Table = TableDefinition::new(keys::DIRECT_WITHHELD_INFO);
+    pub const ROOM_SETTINGS: Table = TableDefinition::new(keys::ROOM_SETTINGS);
+    /// The secrets of the inbox, keyed by secret name and insertion order.
+    pub const SECRET: Table = TableDefinition::new(keys::SECRET);
+    /// The received room key bundles, keyed by room ID and sender user ID.
+    pub const RECEIVED_ROOM_KEY_BUNDLE: Table =
+        TableDefinition::new(keys::RECEIVED_ROOM_KEY_BUNDLE);
+    pub const LEASE_LOCK: Table = TableDefinition::new(keys::LEASE_LOCK);
+
+    pub const ALL: &[Table] = &[
+        KV_BLOB,
+        SESSION,
+        INBOUND_GROUP_SESSION,
+        INBOUND_GROUP_SESSION_BY_DEVICE,
+        INBOUND_GROUP_SESSION_NOT_BACKED_UP,
+        OUTBOUND_GROUP_SESSION,
+        DEVICE,
+        IDENTITY,
+        OLM_HASH,
+        TRACKED_USER,
+        KEY_REQUEST,
+        DIRECT_WITHHELD_INFO,
+        ROOM_SETTINGS,
+        SECRET,
+        RECEIVED_ROOM_KEY_BUNDLE,
+        LEASE_LOCK,
+    ];
+}
+
+/// The filename used for the redb database file used by the crypto store.
+pub(crate) const DATABASE_NAME: &str = "matrix-sdk-crypto.redb";
+
+/// A redb-based crypto store.
+#[derive(Clone)]
+pub struct RedbCryptoStore {
+    store_cipher: Option<Arc<StoreCipher>>,
+    db: RedbDatabase,
+
+    // DB values cached in memory
+    static_account: Arc<RwLock<Option<StaticAccountData>>>,
+    save_changes_lock: Arc<Mutex<()>>,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for RedbCryptoStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedbCryptoStore").finish_non_exhaustive()
+    }
+}
+
+impl EncryptableStore for RedbCryptoStore {
+    fn get_cypher(&self) -> Option<&StoreCipher> {
+        self.store_cipher.as_deref()
+    }
+}
+
+impl RedbCryptoStore {
+    /// Open the redb-based crypto store at the given path using the given
+    /// passphrase to encrypt private data.
+    pub async fn open(
+        path: impl AsRef<Path>,
+        passphrase: Option<&str>,
+    ) -> Result<Self, OpenStoreError> {
+        let db = RedbDatabase::open(path.as_ref(), DATABASE_NAME, tables::ALL).await?;
+
+        let store_cipher = match passphrase {
+            Some(p) => Some(Arc::new(db.get_or_create_store_cipher(p).await?)),
+            None => None,
+        };
+
+        Ok(Self {
+            store_cipher,
+            db,
+            static_account: Arc::new(RwLock::new(None)),
+            save_changes_lock: Default::default(),
+        })
+    }
+
+    fn deserialize_and_unpickle_inbound_group_session(
+        &self,
+        value: &[u8],
+    ) -> Result<InboundGroupSession> {
+        let [backed_up, _room_id, _by_device_key, data] = split_value(value)?;
+        let mut pickle: PickledInboundGroupSession = self.deserialize_value(data)?;
+
+        // The backed-up flag of the value is the source of truth, see
+        // `tables::INBOUND_GROUP_SESSION`.
+        pickle.backed_up = backed_up == [1];
+
+        Ok(InboundGroupSession::from_pickle(pickle)?)
+    }
+
+    fn deserialize_key_request(&self, value: &[u8]) -> Result<GossipRequest> {
+        let [sent_out, data] = split_value(value)?;
+        let mut request: GossipRequest = self.deserialize_value(data)?;
+        // The sent-out flag of the value is the source of truth, see
+        // `tables::KEY_REQUEST`.
+        request.sent_out = sent_out == [1];
+        Ok(request)
+    }
+
+    /// The key of an inbound group session in
+    /// `tables::INBOUND_GROUP_SESSION_BY_DEVICE`.
+    ///
+    /// The session ID isn't prefixed with its length, so the sessions of a
+    /// device are ordered by session ID.
+    fn inbound_group_session_by_device_key(
+        &self,
+        sender_key: Curve25519PublicKey,
+        sender_data_type: SenderDataType,
+        session_id: &str,
+    ) -> Vec<u8> {
+        let mut by_device_key =
+            self.inbound_group_session_by_device_prefix(sender_key, sender_data_type);
+        by_device_key.extend(self.encode_key(keys::INBOUND_GROUP_SESSION, session_id));
+        by_device_key
+    }
+
+    fn inbound_group_session_by_device_prefix(
+        &self,
+        sender_key: Curve25519PublicKey,
+        sender_data_type: SenderDataType,
+    ) -> Vec<u8> {
+        key([
+            self.encode_key(keys::INBOUND_GROUP_SESSION, sender_key.to_base64()).as_slice(),
+            &[sender_data_type as u8],
+        ])
+    }
+
+    /// Get the value of the given key of the `kv_blob` table.
+    async fn get_kv<T: DeserializeOwned>(&self, key: &'static str) -> Result<Option<T>> {
+        self.db
+            .read(move |txn| get(&txn.open_table(tables::KV_BLOB)?, key.as_bytes()))
+            .await?
+            .map(|value| self.deserialize_value(&value))
+            .transpose()
+    }
+
+    fn get_static_account(&self) -> Option<StaticAccountData> {
+        self.static_account.read().unwrap().clone()
+    }
+}
+
+/// Build a value out of a flag and several other parts.
+fn flagged_value<'a>(flag: bool, parts: impl IntoIterator<Item = &'a [u8]>) -> Vec<u8> {
+    let flag: &[u8] = if flag { &[1] } else { &[0] };
+    key([flag].into_iter().chain(parts))
+}
+
+/// Save the given inbound group session in the given transaction, keeping the
+/// indexes up to date.
+fn save_inbound_group_session(
+    txn: &redb::WriteTransaction,
+    session_id: &[u8],
+    value: &[u8],
+    by_device_key: &[u8],
+    backed_up: bool,
+) -> Result<()> {
+    let mut sessions = txn.open_table(tables::INBOUND_GROUP_SESSION)?;
+    let mut by_device = txn.open_table(tables::INBOUND_GROUP_SESSION_BY_DEVICE)?;
+    let mut not_backed_up = txn.open_table(tables::INBOUND_GROUP_SESSION_NOT_BACKED_UP)?;
+
+    if let Some(previous) = sessions.insert(session_id, value)? {
+        let [_, _, previous_by_device_key, _] = split_value(previous.value())?;
+        by_device.remove(previous_by_device_key)?;
+    }
+
+    by_device.insert(by_device_key, session_id)?;
+
+    if backed_up {
+        not_backed_up.remove(session_id)?;
+    } else {
+        not_backed_up.insert(session_id, [].as_slice())?;
+    }
+
+    Ok(())
+}
+
+/// Set the backed-up flag of the inbound group session with the given ID, if
+/// it exists.
+fn set_inbound_group_session_backed_up(
+    txn: &redb::WriteTransaction,
+    session_id: &[u8],
+    backed_up: bool,
+) -> Result<()> {
+    let Some(value) = get(&txn.open_table(tables::INBOUND_GROUP_SESSION)?, session_id)? else {
+        return Ok(());
+    };
+
+    let [_, room_id, by_device_key, data] = split_value(&value)?;
+    let value = flagged_value(backed_up, [room_id, by_device_key, data]);
+
+    save_inbound_group_session(txn, session_id, &value, by_device_key, backed_up)
+}
+
+#[async_trait]
+impl CryptoStore for RedbCryptoStore {
+    type Error = Error;
+
+    async fn load_account(&self) -> Result<Option<Account>> {
+        let Some(pickle) = self.get_kv(keys::ACCOUNT).await? else {
+            return Ok(None);
+        };
+
+        let account = Account::from_pickle(pickle).map_err(|_| Error::Unpickle)?;
+
+        *self.static_account.write().unwrap() = Some(account.static_data().clone());
+
+        Ok(Some(account))
+    }
+
+    async fn load_identity(&self) -> Result<Option<PrivateCrossSigningIdentity>> {
+        let Some(pickle) = self.get_kv(keys::PRIVATE_IDENTITY).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(PrivateCrossSigningIdentity::from_pickle(pickle).map_err(|_| Error::Unpickle)?))
+    }
+
+    async fn save_pending_changes(&self, changes: PendingChanges) -> Result<()> {
+        // Serialize calls to `save_pending_changes`, like in `save_changes`.
+        let _guard = self.save_changes_lock.lock().await;
+
+        let Some(account) = changes.account else {
+            return Ok(());
+        };
+
+        *self.static_account.write().unwrap() = Some(account.static_data().clone());
+        let serialized_account = self.serialize_value(&account.pickle())?;
+
+        self.db
+            .write(move |txn| {
+                txn.open_table(tables::KV_BLOB)?
+                    .insert(keys::ACCOUNT.as_bytes(), serialized_account.as_slice())?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn save_changes(&self, changes: Changes) -> Result<()> {
+        // Serialize calls to `save_changes`; there are multiple await points below, and
+        // we're pickling data as we go, so we don't want to invalidate data
+        // we've previously read and overwrite it in the store.
+        let _guard = self.save_changes_lock.lock().await;
+
+        let mut kv_changes = Vec::new();
+
+        if let Some(identity) = changes.private_identity {
+            kv_changes
+                .push((keys::PRIVATE_IDENTITY, self.serialize_value(&identity.pickle().await)?));
+        }
+        if let Some(token) = &changes.next_batch_token {
+            kv_changes.push((keys::NEXT_BATCH_TOKEN, self.serialize_value(token)?));
+        }
+        if let Some(decryption_key) = &changes.backup_decryption_key {
+            kv_changes.push((keys::RECOVERY_KEY, self.serialize_value(decryption_key)?));
+        }
+        if let Some(backup_version) = &changes.backup_version {
+            kv_changes.push((keys::BACKUP_VERSION, self.serialize_value(backup_version)?));
+        }
+        if let Some(pickle_key) = &changes.dehydrated_device_pickle_key {
+            kv_changes
+                .push((keys::DEHYDRATED_DEVICE_PICKLE_KEY, self.serialize_value(pickle_key)?));
+        }
+
+        let mut session_changes = Vec::new();
+        for session in changes.sessions {
+            let key = key([
+                self.encode_key(keys::SESSION, session.sender_key().to_base64()).as_slice(),
+                &self.encode_key(keys::SESSION, session.session_id()),
+            ]);
+            session_changes.push((key, self.serialize_value(&session.pickle().await)?));
+        }
+
+        let mut inbound_session_changes = Vec::new();
+        for session in changes.inbound_group_sessions {
+            let session_id = self.encode_key(keys::INBOUND_GROUP_SESSION, session.session_id());
+            let room_id = self.encode_key(keys::INBOUND_GROUP_SESSION, session.room_id());
+            let pickle = session.pickle().await;
+            let by_device_key = self.inbound_group_session_by_device_key(
+                session.sender_key(),
+                pickle.sender_data.to_type(),
+                session.session_id(),
+            );
+            let value = flagged_value(
+                pickle.backed_up,
+                [room_id.as_slice(), &by_device_key, &self.serialize_value(&pickle)?],
+            );
+            inbound_session_changes.push((session_id, value, by_device_key, pickle.backed_up));
+        }
+
+        let mut outbound_session_changes = Vec::new();
+        for session in changes.outbound_group_sessions {
+            let room_id = self.encode_key(keys::OUTBOUND_GROUP_SESSION, session.room_id());
+            outbound_session_changes.push((room_id, self.serialize_json(&session.pickle().await)?));
+        }
+
+        let mut device_changes = Vec::new();
+        for device in changes.devices.new.iter().chain(&changes.devices.changed) {
+            let key = key([
+                self.encode_key(keys::DEVICE, device.user_id()).as_slice(),
+                &self.encode_key(keys::DEVICE, device.device_id()),
+            ]);
+            device_changes.push((key, Some(self.serialize_value(device)?)));
+        }
+        for device in &changes.devices.deleted {
+            let key = key([
+                self.encode_key(keys::DEVICE, device.user_id()).as_slice(),
+                &self.encode_key(keys::DEVICE, device.device_id()),
+            ]);
+            device_changes.push((key, None));
+        }
+
+        let mut identity_changes = Vec::new();
+        for identity in changes.identities.changed.iter().chain(&changes.identities.new) {
+            let user_id = self.encode_key(keys::IDENTITY, identity.user_id());
+            identity_changes.push((user_id, self.serialize_value(identity)?));
+        }
+
+        let message_hashes =
+            changes.message_hashes.iter().map(rmp_serde::to_vec).collect::<Result<Vec<_>, _>>()?;
+
+        let mut key_request_changes = Vec::new();
+        for request in &changes.key_requests {
+            let request_id = self.encode_key(keys::KEY_REQUEST, request.request_id.as_bytes());
+            let value =
+                flagged_value(request.sent_out, [self.serialize_value(request)?.as_slice()]);
+            key_request_changes.push((request_id, value));
+        }
+
+        let mut withheld_changes = Vec::new();
+        for (room_id, data) in &changes.withheld_session_info {
+            for (session_id, event) in data {
+                let key = key([
+                    self.encode_key(keys::DIRECT_WITHHELD_INFO, room_id).as_slice(),
+                    &self.encode_key(keys::DIRECT_WITHHELD_INFO, session_id),
+                ]);
+                withheld_changes.push((key, self.serialize_json(event)?));
+            }
+        }
+
+        let mut room_settings_changes = Vec::new();
+        for (room_id, settings) in &changes.room_settings {
+            let room_id = self.encode_key(keys::ROOM_SETTINGS, room_id);
+            room_settings_changes.push((room_id, self.serialize_value(settings)?));
+        }
+
+        let mut secret_changes = Vec::new();
+        for secret in &changes.secrets {
+            let secret_name =
+                key([self.encode_key(keys::SECRET, secret.secret_name.to_string()).as_slice()]);
+            secret_changes.push((secret_name, self.serialize_json(secret)?));
+        }
+
+        let mut bundle_changes = Vec::new();
+        for bundle in &changes.received_room_key_bundles {
+            let key = key([
+                self.encode_key(keys::RECEIVED_ROOM_KEY_BUNDLE, &bundle.bundle_data.room_id)
+                    .as_slice(),
+                &self.encode_key(keys::RECEIVED_ROOM_KEY_BUNDLE, &bundle.sender_user),
+            ]);
+            bundle_changes.push((key, self.serialize_value(bundle)?));
+        }
+
+        self.db
+            .write(move |txn| {
+                {
+                    let mut kv_blob = txn.open_table(tables::KV_BLOB)?;
+                    for (key, value) in &kv_changes {
+                        kv_blob.insert(key.as_bytes(), value.as_slice())?;
+                    }
+                }
+
+                {
+                    let mut table = txn.open_table(tables::DEVICE)?;
+                    for (key, value) in &device_changes {
+                        match value {
+                            Some(value) => table.insert(key.as_slice(), value.as_slice())?,
+                            None => table.remove(key.as_slice())?,
+                        };
+                    }
+                }
+
+                for (table, changes) in [
+                    (tables::IDENTITY, &identity_changes),
+                    (tables::SESSION, &session_changes),
+                    (tables::OUTBOUND_GROUP_SESSION, &outbound_session_changes),
+                    (tables::KEY_REQUEST, &key_request_changes),
+                    (tables::DIRECT_WITHHELD_INFO, &withheld_changes),
+                    (tables::ROOM_SETTINGS, &room_settings_changes),
+                    (tables::RECEIVED_ROOM_KEY_BUNDLE, &bundle_changes),
+                ] {
+                    let mut table = txn.open_table(table)?;
+                    for (key, value) in changes {
+                        table.insert(key.as_slice(), value.as_slice())?;
+                    }
+                }
+
+                for (session_id, value, by_device_key, backed_up) in &inbound_session_changes {
+                    save_inbound_group_session(txn, session_id, value, by_device_key, *backed_up)?;
+                }
+
+                {
+                    let mut table = txn.open_table(tables::OLM_HASH)?;
+                    for hash in &message_hashes {
+                        table.insert(hash.as_slice(), [].as_slice())?;
+                    }
+                }
+
+                if !secret_changes.is_empty() {
+                    let mut kv_blob = txn.open_table(tables::KV_BLOB)?;
+                    let mut table = txn.open_table(tables::SECRET)?;
+
+                    let mut sequence = match get(&kv_blob, keys::SECRET_SEQUENCE.as_bytes())? {
+                        Some(bytes) => u64::from_be_bytes(bytes.try_into().map_err(|_| {
+                            Error::InvalidData { details: "invalid secret sequence".to_owned() }
+                        })?),
+                        None => 0,
+                    };
+
+                    for (secret_name, value) in &secret_changes {
+                        let mut key = secret_name.clone();
+                        key.extend(sequence.to_be_bytes());
+                        table.insert(key.as_slice(), value.as_slice())?;
+                        sequence += 1;
+                    }
+
+                    kv_blob.insert(
+                        keys::SECRET_SEQUENCE.as_bytes(),
+                        sequence.to_be_bytes().as_slice(),
+                    )?;
+                }
+
+                Ok(())
+            })
+            .await
+    }
+
+    async fn save_inbound_group_sessions(
+        &self,
+        sessions: Vec<InboundGroupSession>,
+        backed_up_to_version: Option<&str>,
+    ) -> matrix_sdk_crypto::store::Result<(), Self::Error> {
+        // Sanity-check that the data in the sessions corresponds to backed_up_version
+        sessions.iter().for_each(|s| {
+            let backed_up = s.backed_up();
+            if backed_up != backed_up_to_version.is_some() {
+                warn!(
+                    backed_up,
+                    backed_up_to_version,
+                    "Session backed-up flag does not correspond to backup version setting",
+                );
+            }
+        });
+
+        // Currently, this store doesn't save the backup version separately, so this
+        // just delegates to save_changes.
+        self.save_changes(Changes { inbound_group_sessions: sessions, ..Changes::default() }).await
+    }
+
+    async fn get_sessions(&self, sender_key: &str) -> Result<Option<Vec<Session>>> {
+        let device_keys = self.get_own_device().await?.as_device_keys().clone();
+        let prefix = key([self.encode_key(keys::SESSION, sender_key).as_slice()]);
+
+        let sessions: Vec<_> = self
+            .db
+            .read(move |txn| get_prefixed(&txn.open_table(tables::SESSION)?, &prefix))
+            .await?
+            .into_iter()
+            .map(|(_, value)| {
+                let pickle = self.deserialize_value(&value)?;
+                Session::from_pickle(device_keys.clone(), pickle).map_err(|_| Error::AccountUnset)
+            })
+            .collect::<Result<_>>()?;
+
+        if sessions.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(sessions))
+        }
+    }
+
+    async fn get_all_sessions(&self) -> Result<Vec<Session>> {
+        let device_keys = self.get_own_device().await?.as_device_keys().clone();
+
+        self.db
+            .read(|txn| get_prefixed(&txn.open_table(tables::SESSION)?, &[]))
+            .await?
+            .into_iter()
+            .map(|(_, value)| {
+                let pickle = self.deserialize_value(&value)?;
+                Session::from_pickle(device_keys.clone(), pickle).map_err(|_| Error::AccountUnset)
+            })
+            .collect()
+    }
+
+    #[instrument(skip(self))]
+    async fn get_inbound_group_session(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Result<Option<InboundGroupSession>> {
+        let session_id = self.encode_key(keys::INBOUND_GROUP_SESSION, session_id);
+        let Some(value) = self
+            .db
+            .read(move |txn| get(&txn.open_table(tables::INBOUND_GROUP_SESSION)?, &session_id))
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let [_, room_id_from_db, _, _] = split_value(&value)?;
+        if self.encode_key(keys::INBOUND_GROUP_SESSION, room_id) != room_id_from_db {
+            warn!("expected room_id for session_id doesn't match what's in the DB");
+            return Ok(None);
+        }
+
+        Ok(Some(self.deserialize_and_unpickle_inbound_group_session(&value)?))
+    }
+
+    async fn get_inbound_group_sessions(&self) -> Result<Vec<InboundGroupSession>> {
+        self.db
+            .read(|txn| get_prefixed(&txn.open_table(tables::INBOUND_GROUP_SESSION)?, &[]))
+            .await?
+            .into_iter()
+            .map(|(_, value)| self.deserialize_and_unpickle_inbound_group_session(&value))
+            .collect()
+    }
+
+    async fn get_inbound_group_sessions_for_device_batch(
+        &self,
+        sender_key: Curve25519PublicKey,
+        sender_data_type: SenderDataType,
+        after_session_id: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<InboundGroupSession>, Self::Error> {
+        let prefix = self.inbound_group_session_by_device_prefix(sender_key, sender_data_type);
+        let start = match &after_session_id {
+            Some(session_id) => {
+                self.inbound_group_session_by_device_key(sender_key, sender_data_type, session_id)
+            }
+            None => prefix.clone(),
+        };
+
+        self.db
+            .read(move |txn| {
+                let by_device = txn.open_table(tables::INBOUND_GROUP_SESSION_BY_DEVICE)?;
+                let sessions = txn.open_table(tables::INBOUND_GROUP_SESSION)?;
+
+                let mut values = Vec::new();
+                for entry in by_device.range(start.as_slice()..)? {
+                    if values.len() >= limit {
+                        break;
+                    }
+
+                    let (key, session_id) = entry?;
+                    if !key.value().starts_with(&prefix) {
+                        break;
+                    }
+                    if after_session_id.is_some() && key.value() == start.as_slice() {
+                        continue;
+                    }
+
+                    if let Some(value) = get(&sessions, session_id.value())? {
+                        values.push(value);
+                    }
+                }
+
+                Ok(values)
+            })
+            .await?
+            .iter()
+            .map(|value| self.deserialize_and_unpickle_inbound_group_session(value))
+            .collect()
+    }
+
+    async fn inbound_group_session_counts(
+        &self,
+        _backup_version: Option<&str>,
+    ) -> Result<RoomKeyCounts> {
+        let (total, not_backed_up) = self
+            .db
+            .read(|txn| {
+                let total = txn.open_table(tables::INBOUND_GROUP_SESSION)?.len()?;
+                let not_backed_up =
+                    txn.open_table(tables::INBOUND_GROUP_SESSION_NOT_BACKED_UP)?.len()?;
+                Ok((total, not_backed_up))
+            })
+            .await?;
+
+        Ok(RoomKeyCounts {
+            total: total as usize,
+            backed_up: total.saturating_sub(not_backed_up) as usize,
+        })
+    }
+
+    async fn inbound_group_sessions_for_backup(
+        &self,
+        _backup_version: &str,
+        limit: usize,
+    ) -> Result<Vec<InboundGroupSession>> {
+        self.db
+            .read(move |txn| {
+                let not_backed_up = txn.open_table(tables::INBOUND_GROUP_SESSION_NOT_BACKED_UP)?;
+                let sessions = txn.open_table(tables::INBOUND_GROUP_SESSION)?;
+
+                let mut values = Vec::new();
+                for entry in not_backed_up.iter()?.take(limit) {
+                    let (session_id, _) = entry?;
+                    if let Some(value) = get(&sessions, session_id.value())? {
+                        values.push(value);
+                    }
+                }
+
+                Ok(values)
+            })
+            .await?
+            .iter()
+            .map(|value| self.deserialize_and_unpickle_inbound_group_session(value))
+            .collect()
+    }
+
+    async fn mark_inbound_group_sessions_as_backed_up(
+        &self,
+        _backup_version: &str,
+        session_ids: &[(&RoomId, &str)],
+    ) -> Result<()> {
+        let session_ids: Vec<_> = session_ids
+            .iter()
+            .map(|(_, session_id)| self.encode_key(keys::INBOUND_GROUP_SESSION, session_id))
+            .collect();
+
+        self.db
+            .write(move |txn| {
+                for session_id in &session_ids {
+                    set_inbound_group_session_backed_up(txn, session_id, true)?;
+                }
+                Ok(())
+            })
+            .await
+    }
+
+    async fn reset_backup_state(&self) -> Result<()> {
+        self.db
+            .write(|txn| {
+                let session_ids: Vec<_> =
+                    get_prefixed(&txn.open_table(tables::INBOUND_GROUP_SESSION)?, &[])?
+                        .into_iter()
+                        .map(|(session_id, _)| session_id)
+                        .collect();
+
+                for session_id in &session_ids {
+                    set_inbound_group_session_backed_up(txn, session_id, false)?;
+                }
+                Ok(())
+            })
+            .await
+    }
+
+    async fn load_backup_keys(&self) -> Result<BackupKeys> {
+        Ok(BackupKeys {
+            backup_version: self.get_kv(keys::BACKUP_VERSION).await?,
+            decryption_key: self.get_kv(keys::RECOVERY_KEY).await?,
+        })
+    }
+
+    async fn load_dehydrated_device_pickle_key(&self) -> Result<Option<DehydratedDeviceKey>> {
+        self.get_kv(keys::DEHYDRATED_DEVICE_PICKLE_KEY).await
+    }
+
+    async fn delete_dehydrated_device_pickle_key(&self) -> Result<(), Self::Error> {
+        self.db
+            .write(|txn| {
+                txn.open_table(tables::KV_BLOB)?
+                    .remove(keys::DEHYDRATED_DEVICE_PICKLE_KEY.as_bytes())?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn get_outbound_group_session(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<OutboundGroupSession>> {
+        let room_id = self.encode_key(keys::OUTBOUND_GROUP_SESSION, room_id);
+        let Some(value) = self
+            .db
+            .read(move |txn| get(&txn.open_table(tables::OUTBOUND_GROUP_SESSION)?, &room_id))
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let account_info = self.get_static_account().ok_or(Error::AccountUnset)?;
+
+        let pickle = self.deserialize_json(&value)?;
+        let session = OutboundGroupSession::from_pickle(
+            account_info.device_id,
+            account_info.identity_keys,
+            pickle,
+        )
+        .map_err(|_| Error::Unpickle)?;
+
+        Ok(Some(session))
+    }
+
+    async fn load_tracked_users(&self) -> Result<Vec<TrackedUser>> {
+        self.db
+            .read(|txn| get_prefixed(&txn.open_table(tables::TRACKED_USER)?, &[]))
+            .await?
+            .iter()
+            .map(|(_, value)| self.deserialize_value(value))
+            .collect()
+    }
+
+    async fn save_tracked_users(&self, tracked_users: &[(&UserId, bool)]) -> Result<()> {
+        let users: Vec<_> = tracked_users
+            .iter()
+            .map(|(user_id, dirty)| {
+                let key = self.encode_key(keys::TRACKED_USER, user_id);
+                let value = self
+                    .serialize_value(&TrackedUser { user_id: (*user_id).into(), dirty: *dirty })?;
+                Ok((key, value))
+            })
+            .collect::<Result<_>>()?;
+
+        self.db
+            .write(move |txn| {
+                let mut table = txn.open_table(tables::TRACKED_USER)?;
+                for (key, value) in &users {
+                    table.insert(key.as_slice(), value.as_slice())?;
+                }
+                Ok(())
+            })
+            .await
+    }
+
+    async fn get_device(
+        &self,
+        user_id: &UserId,
+        device_id: &DeviceId,
+    ) -> Result<Option<DeviceData>> {
+        let key = key([
+            self.encode_key(keys::DEVICE, user_id).as_slice(),
+            &self.encode_key(keys::DEVICE, device_id),
+        ]);
+
+        self.db
+            .read(move |txn| get(&txn.open_table(tables::DEVICE)?, &key))
+            .await?
+            .map(|value| self.deserialize_value(&value))
+            .transpose()
+    }
+
+    async fn get_user_devices(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<OwnedDeviceId, DeviceData>> {
+        let prefix = key([self.encode_key(keys::DEVICE, user_id).as_slice()]);
+
+        self.db
+            .read(move |txn| get_prefixed(&txn.open_table(tables::DEVICE)?, &prefix))
+            .await?
+            .into_iter()
+            .map(|(_, value)| {
+                let device: DeviceData = self.deserialize_value(&value)?;
+                Ok((device.device_id().to_owned(), device))
+            })
+            .collect()
+    }
+
+    async fn get_own_device(&self) -> Result<DeviceData> {
+        let account_info = self.get_static_account().ok_or(Error::AccountUnset)?;
+
+        Ok(self
+            .get_device(&account_info.user_id, &account_info.device_id)
+            .await?
+            .expect("We should be able to find our own device."))
+    }
+
+    async fn get_user_identity(&self, user_id: &UserId) -> Result<Option<UserIdentityData>> {
+        let user_id = self.encode_key(keys::IDENTITY, user_id);
+
+        self.db
+            .read(move |txn| get(&txn.open_table(tables::IDENTITY)?, &user_id))
+            .await?
+            .map(|value| self.deserialize_value(&value))
+            .transpose()
+    }
+
+    async fn is_message_known(&self, message_hash: &OlmMessageHash) -> Result<bool> {
+        let hash = rmp_serde::to_vec(message_hash)?;
+
+        self.db.read(move |txn| Ok(get(&txn.open_table(tables::OLM_HASH)?, &hash)?.is_some())).await
+    }
+
+    async fn get_all_olm_message_hashes(&self) -> Result<Vec<OlmMessageHash>> {
+        self.db
+            .read(|txn| get_prefixed(&txn.open_table(tables::OLM_HASH)?, &[]))
+            .await?
+            .iter()
+            .map(|(hash, _)| Ok(rmp_serde::from_slice(hash)?))
+            .collect()
+    }
+
+    async fn get_outgoing_secret_requests(
+        &self,
+        request_id: &TransactionId,
+    ) -> Result<Option<GossipRequest>> {
+        let request_id = self.encode_key(keys::KEY_REQUEST, request_id.as_bytes());
+
+        self.db
+            .read(move |txn| get(&txn.open_table(tables::KEY_REQUEST)?, &request_id))
+            .await?
+            .map(|value| self.deserialize_key_request(&value))
+            .transpose()
+    }
+
+    async fn get_secret_request_by_info(
+        &self,
+        key_info: &SecretInfo,
+    ) -> Result<Option<GossipRequest>> {
+        let requests =
+            self.db.read(|txn| get_prefixed(&txn.open_table(tables::KEY_REQUEST)?, &[])).await?;
+
+        for (_, value) in requests {
+            let request = self.deserialize_key_request(&value)?;
+            if request.info == *key_info {
+                return Ok(Some(request));
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn get_unsent_secret_requests(&self) -> Result<Vec<GossipRequest>> {
+        let requests =
+            self.db.read(|txn| get_prefixed(&txn.open_table(tables::KEY_REQUEST)?, &[])).await?;
+
+        let mut unsent = Vec::new();
+        for (_, value) in requests {
+            let request = self.deserialize_key_request(&value)?;
+            if !request.sent_out {
+                unsent.push(request);
+            }
+        }
+
+        Ok(unsent)
+    }
+
+    async fn delete_outgoing_secret_requests(&self, request_id: &TransactionId) -> Result<()> {
+        let request_id = self.encode_key(keys::KEY_REQUEST, request_id.as_bytes());
+
+        self.db
+            .write(move |txn| {
+                txn.open_table(tables::KEY_REQUEST)?.remove(request_id.as_slice())?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn get_secrets_from_inbox(
+        &self,
+        secret_name: &SecretName,
+    ) -> Result<Vec<GossippedSecret>> {
+        let prefix = key([self.encode_key(keys::SECRET, secret_name.to_string()).as_slice()]);
+
+        self.db
+            .read(move |txn| get_prefixed(&txn.open_table(tables::SECRET)?, &prefix))
+            .await?
+            .iter()
+            .map(|(_, value)| self.deserialize_json(value))
+            .collect()
+    }
+
+    async fn delete_secrets_from_inbox(&self, secret_name: &SecretName) -> Result<()> {
+        let prefix = key([self.encode_key(keys::SECRET, secret_name.to_string()).as_slice()]);
+
+        self.db
+            .write(move |txn| {
+                remove_prefixed(&mut txn.open_table(tables::SECRET)?, &prefix)?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn get_withheld_info(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Result<Option<RoomKeyWithheldEvent>> {
+        let key = key([
+            self.encode_key(keys::DIRECT_WITHHELD_INFO, room_id).as_slice(),
+            &self.encode_key(keys::DIRECT_WITHHELD_INFO, session_id),
+        ]);
+
+        self.db
+            .read(move |txn| get(&txn.open_table(tables::DIRECT_WITHHELD_INFO)?, &key))
+            .await?
+            .map(|value| self.deserialize_json(&value))
+            .transpose()
+    }
+
+    async fn get_all_withheld_info(&self) -> Result<Vec<RoomKeyWithheldEvent>> {
+        self.db
+            .read(|txn| get_prefixed(&txn.open_table(tables::DIRECT_WITHHELD_INFO)?, &[]))
+            .await?
+            .iter()
+            .map(|(_, value)| self.deserialize_json(value))
+            .collect()
+    }
+
+    async fn get_room_settings(&self, room_id: &RoomId) -> Result<Option<RoomSettings>> {
+        let room_id = self.encode_key(keys::ROOM_SETTINGS, room_id);
+
+        self.db
+            .read(move |txn| get(&txn.open_table(tables::ROOM_SETTINGS)?, &room_id))
+            .await?
+            .map(|value| self.deserialize_value(&value))
+            .transpose()
+    }
+
+    async fn get_received_room_key_bundle_data(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+    ) -> Result<Option<StoredRoomKeyBundleData>> {
+        let key = key([
+            self.encode_key(keys::RECEIVED_ROOM_KEY_BUNDLE, room_id).as_slice(),
+            &self.encode_key(keys::RECEIVED_ROOM_KEY_BUNDLE, user_id),
+        ]);
+
+        self.db
+            .read(move |txn| get(&txn.open_table(tables::RECEIVED_ROOM_KEY_BUNDLE)?, &key))
+            .await?
+            .map(|value| self.deserialize_value(&value))
+            .transpose()
+    }
+
+    async fn get_all_received_room_key_bundle_data(&self) -> Result<Vec<StoredRoomKeyBundleData>> {
+        self.db
+            .read(|txn| get_prefixed(&txn.open_table(tables::RECEIVED_ROOM_KEY_BUNDLE)?, &[]))
+            .await?
+            .iter()
+            .map(|(_, value)| self.deserialize_value(value))
+            .collect()
+    }
+
+    async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let key = key.to_owned();
+
+        self.db
+            .read(move |txn| get(&txn.open_table(tables::KV_BLOB)?, key.as_bytes()))
+            .await?
+            .map(|value| Ok(self.decode_value(&value)?.into_owned()))
+            .transpose()
+    }
+
+    async fn set_custom_value(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        let key = key.to_owned();
+        let value = self.encode_value(value)?;
+
+        self.db
+            .write(move |txn| {
+                txn.open_table(tables::KV_BLOB)?.insert(key.as_bytes(), value.as_slice())?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn remove_custom_value(&self, key: &str) -> Result<()> {
+        let key = key.to_owned();
+
+        self.db
+            .write(move |txn| {
+                txn.open_table(tables::KV_BLOB)?.remove(key.as_bytes())?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn get_all_custom_values(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        let mut values = Vec::new();
+
+        for (key, value) in
+            self.db.read(|txn| get_prefixed(&txn.open_table(tables::KV_BLOB)?, &[])).await?
+        {
+            let Ok(key) = String::from_utf8(key) else {
+                continue;
+            };
+            if keys::INTERNAL.contains(&key.as_str()) {
+                continue;
+            }
+
+            values.push((key, self.decode_value(&value)?.into_owned()));
+        }
+
+        Ok(values)
+    }
+
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool> {
+        self.db.try_take_leased_lock(tables::LEASE_LOCK, lease_duration_ms, key, holder).await
+    }
+
+    async fn next_batch_token(&self) -> Result<Option<String>, Self::Error> {
+        self.get_kv(keys::NEXT_BATCH_TOKEN).await
+    }
+
+    async fn storage_usage(&self) -> Result<StoreUsage, Self::Error> {
+        self.db.storage_usage(tables::ALL).await
+    }
+
+    async fn compact(&self) -> Result<(), Self::Error> {
+        self.db.compact().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_crypto::{cryptostore_integration_tests, cryptostore_integration_tests_time};
+    use once_cell::sync::Lazy;
+    use tempfile::{tempdir, TempDir};
+    use tokio::fs;
+
+    use super::RedbCryptoStore;
+
+    static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
+
+    async fn get_store(name: &str, passphrase: Option<&str>, clear_data: bool) -> RedbCryptoStore {
+        let tmpdir_path = TMP_DIR.path().join(name);
+
+        if clear_data {
+            let _ = fs::remove_dir_all(&tmpdir_path).await;
+        }
+
+        RedbCryptoStore::open(tmpdir_path.to_str().unwrap(), passphrase)
+            .await
+            .expect("Can't create a store")
+    }
+
+    cryptostore_integration_tests!();
+    cryptostore_integration_tests_time!();
+}
+
+#[cfg(test)]
+mod encrypted_tests {
+    use matrix_sdk_crypto::{cryptostore_integration_tests, cryptostore_integration_tests_time};
+    use once_cell::sync::Lazy;
+    use tempfile::{tempdir, TempDir};
+    use tokio::fs;
+
+    use super::RedbCryptoStore;
+
+    static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
+
+    async fn get_store(name: &str, passphrase: Option<&str>, clear_data: bool) -> RedbCryptoStore {
+        let tmpdir_path = TMP_DIR.path().join(name);
+        let pass = passphrase.unwrap_or("default_test_password");
+
+        if clear_data {
+            let _ = fs::remove_dir_all(&tmpdir_path).await;
+        }
+
+        RedbCryptoStore::open(tmpdir_path.to_str().unwrap(), Some(pass))
+            .await
+            .expect("Can't create a passphrase protected store")
+    }
+
+    cryptostore_integration_tests!();
+    cryptostore_integration_tests_time!();
+}
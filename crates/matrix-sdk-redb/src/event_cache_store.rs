@@ -0,0 +1,1803 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A redb-based backend for the [`EventCacheStore`].
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    path::Path,
+    sync::{Arc, RwLock as StdRwLock},
+};
+
+use async_trait::async_trait;
+use matrix_sdk_base::{
+    deserialized_responses::TimelineEvent,
+    event_cache::{
+        store::{
+            compute_filters_string, extract_event_relation,
+            media::{
+                EventCacheStoreMedia, IgnoreMediaRetentionPolicy, MediaRetentionPolicy,
+                MediaService,
+            },
+            EventAggregations, EventCacheStore, EventRetentionPolicy,
+        },
+        Event, Gap,
+    },
+    linked_chunk::{
+        ChunkContent, ChunkIdentifier, ChunkIdentifierGenerator, ChunkMetadata, LinkedChunkId,
+        Position, RawChunk, Update,
+    },
+    media::{guess_mime_type, MediaRequestParameters, UniqueKey},
+    store_usage::{ItemsUsage, StoreUsage},
+};
+use matrix_sdk_store_encryption::StoreCipher;
+use redb::{ReadableTable, TableDefinition, WriteTransaction};
+use ruma::{
+    events::relation::RelationType, time::SystemTime, EventId, MilliSecondsSinceUnixEpoch, MxcUri,
+    OwnedEventId, RoomId,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{error, instrument, trace};
+
+use crate::{
+    error::{Error, Result},
+    utils::{
+        get, get_prefixed, key, remove_prefixed, split_value, time_to_timestamp, EncryptableStore,
+        RedbDatabase, Table,
+    },
+    OpenStoreError,
+};
+
+mod keys {
+    // Entries in Key-value store
+    pub const MEDIA_RETENTION_POLICY: &str = "media_retention_policy";
+    pub const EVENT_RETENTION_POLICY: &str = "event_retention_policy";
+    pub const LAST_MEDIA_CLEANUP_TIME: &str = "last_media_cleanup_time";
+
+    // Tables
+    pub const KV_BLOB: &str = "kv_blob";
+    pub const LINKED_CHUNKS: &str = "linked_chunks";
+    pub const LINKED_CHUNK_ROOMS: &str = "linked_chunk_rooms";
+    pub const GAP_CHUNKS: &str = "gap_chunks";
+    pub const EVENT_CHUNKS: &str = "event_chunks";
+    pub const EVENT_POSITIONS: &str = "event_positions";
+    pub const EVENTS: &str = "events";
+    pub const EVENT_RELATIONS: &str = "event_relations";
+    pub const EVENT_AGGREGATIONS: &str = "event_aggregations";
+    pub const MEDIA: &str = "media";
+    pub const LEASE_LOCKS: &str = "lease_locks";
+}
+
+mod tables {
+    use super::{keys, Table, TableDefinition};
+
+    pub const KV_BLOB: Table = TableDefinition::new(keys::KV_BLOB);
+    /// The chunks, keyed by linked chunk ID and chunk identifier.
+    pub const LINKED_CHUNKS: Table = TableDefinition::new(keys::LINKED_CHUNKS);
+    /// The room of each linked chunk, keyed by linked chunk ID.
+    ///
+    /// The values are made of the key of the room in [`EVENTS`], and of whether
+    /// the linked chunk is the one of the room itself, rather than a detached
+    /// one.
+    pub const LINKED_CHUNK_ROOMS: Table = TableDefinition::new(keys::LINKED_CHUNK_ROOMS);
+    /// The previous tokens of the gaps, keyed like [`LINKED_CHUNKS`].
+    pub const GAP_CHUNKS: Table = TableDefinition::new(keys::GAP_CHUNKS);
+    /// The IDs of the events of the chunks, keyed by linked chunk ID, chunk
+    /// identifier and position in the chunk.
+    pub const EVENT_CHUNKS: Table = TableDefinition::new(keys::EVENT_CHUNKS);
+    /// An index of [`EVENT_CHUNKS`] by linked chunk ID and event ID, pointing
+    /// to the position of the event.
+    pub const EVENT_POSITIONS: Table = TableDefinition::new(keys::EVENT_POSITIONS);
+    /// The events, keyed by room ID and event ID.
+    ///
+    /// The values are made of the content of the event, and of the ID of the
+    /// event it relates to and the relation type, if any.
+    pub const EVENTS: Table = TableDefinition::new(keys::EVENTS);
+    /// An index of the relations of [`EVENTS`], keyed by room ID, related event
+    /// ID and event ID, with the relation type as value.
+    pub const EVENT_RELATIONS: Table = TableDefinition::new(keys::EVENT_RELATIONS);
+    /// The aggregated relations of the events, keyed like [`EVENTS`].
+    pub const EVENT_AGGREGATIONS: Table = TableDefinition::new(keys::EVENT_AGGREGATIONS);
+    /// The media, keyed by URI and format.
+    ///
+    /// The values are made of the ignore-policy flag, the last access
+    /// timestamp, the content of the media and the request it was stored
+    /// with.
+    pub const MEDIA: Table = TableDefinition::new(keys::MEDIA);
+    pub const LEASE_LOCKS: Table = TableDefinition::new(keys::LEASE_LOCKS);
+
+    pub const ALL: &[Table] = &[
+        KV_BLOB,
+        LINKED_CHUNKS,
+        LINKED_CHUNK_ROOMS,
+        GAP_CHUNKS,
+        EVENT_CHUNKS,
+        EVENT_POSITIONS,
+        EVENTS,
+        EVENT_RELATIONS,
+        EVENT_AGGREGATIONS,
+        MEDIA,
+        LEASE_LOCKS,
+    ];
+}
+
+/// The filename used for the redb database file used by the event cache
+/// store.
+pub(crate) const DATABASE_NAME: &str = "matrix-sdk-event-cache.redb";
+
+/// A redb-based event cache store.
+#[derive(Clone)]
+pub struct RedbEventCacheStore {
+    store_cipher: Option<Arc<StoreCipher>>,
+    db: RedbDatabase,
+
+    /// The current event retention policy, cached from the database.
+    event_retention_policy: Arc<StdRwLock<EventRetentionPolicy>>,
+
+    media_service: MediaService,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for RedbEventCacheStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedbEventCacheStore").finish_non_exhaustive()
+    }
+}
+
+impl EncryptableStore for RedbEventCacheStore {
+    fn get_cypher(&self) -> Option<&StoreCipher> {
+        self.store_cipher.as_deref()
+    }
+}
+
+/// A chunk of a linked chunk.
+#[derive(Debug, Serialize, Deserialize)]
+struct ChunkEntry {
+    previous: Option<u64>,
+    next: Option<u64>,
+    is_gap: bool,
+}
+
+impl RedbEventCacheStore {
+    /// Open the redb-based event cache store at the given path using the
+    /// given passphrase to encrypt private data.
+    pub async fn open(
+        path: impl AsRef<Path>,
+        passphrase: Option<&str>,
+    ) -> Result<Self, OpenStoreError> {
+        let db = RedbDatabase::open(path.as_ref(), DATABASE_NAME, tables::ALL).await?;
+
+        let store_cipher = match passphrase {
+            Some(p) => Some(Arc::new(db.get_or_create_store_cipher(p).await?)),
+            None => None,
+        };
+
+        let (media_retention_policy, last_media_cleanup_time, event_retention_policy) = db
+            .read(|txn| {
+                let kv_blob = txn.open_table(tables::KV_BLOB)?;
+                Ok((
+                    get_serialized_kv(&kv_blob, keys::MEDIA_RETENTION_POLICY)?,
+                    get_serialized_kv(&kv_blob, keys::LAST_MEDIA_CLEANUP_TIME)?,
+                    get_serialized_kv(&kv_blob, keys::EVENT_RETENTION_POLICY)?,
+                ))
+            })
+            .await?;
+
+        let media_service = MediaService::new();
+        media_service.restore(media_retention_policy, last_media_cleanup_time);
+
+        Ok(Self {
+            store_cipher,
+            db,
+            event_retention_policy: Arc::new(StdRwLock::new(
+                event_retention_policy.unwrap_or_default(),
+            )),
+            media_service,
+        })
+    }
+
+    fn linked_chunk_key(&self, linked_chunk_id: LinkedChunkId<'_>) -> Vec<u8> {
+        self.encode_key(keys::LINKED_CHUNKS, linked_chunk_id.storage_key())
+    }
+
+    fn room_key(&self, room_id: &RoomId) -> Vec<u8> {
+        self.encode_key(keys::EVENTS, room_id)
+    }
+
+    fn media_key(&self, request: &MediaRequestParameters) -> Vec<u8> {
+        key([
+            self.encode_key(keys::MEDIA, request.source.unique_key()).as_slice(),
+            &self.encode_key(keys::MEDIA, request.format.unique_key()),
+        ])
+    }
+
+    fn media_uri_prefix(&self, uri: &MxcUri) -> Vec<u8> {
+        key([self.encode_key(keys::MEDIA, uri).as_slice()])
+    }
+
+    /// Encode the given event, as a value of `tables::EVENTS`.
+    fn encode_event(&self, event: &TimelineEvent) -> Result<Vec<u8>> {
+        // Extract the relationship info here.
+        let (relates_to, rel_type) = extract_event_relation(event.raw()).unzip();
+
+        // The content may be encrypted.
+        let content = self.serialize_json(event)?;
+
+        Ok(key([
+            content.as_slice(),
+            relates_to.as_ref().map_or(b"".as_slice(), |event_id| event_id.as_bytes()),
+            rel_type.as_ref().map_or(b"".as_slice(), |rel_type| rel_type.as_bytes()),
+        ]))
+    }
+
+    fn decode_event(&self, value: &[u8]) -> Result<Event> {
+        let [content, _, _] = split_value(value)?;
+        self.deserialize_json(content)
+    }
+
+    fn encode_gap(&self, gap: &Gap) -> Result<Vec<u8>> {
+        self.serialize_json(gap)
+    }
+
+    /// Rebuild the given chunk of the given linked chunk.
+    fn rebuild_chunk(
+        &self,
+        txn: &impl TableReader,
+        linked_chunk_key: &[u8],
+        id: u64,
+        chunk: ChunkEntry,
+    ) -> Result<RawChunk<Event, Gap>> {
+        let identifier = ChunkIdentifier::new(id);
+        let previous = chunk.previous.map(ChunkIdentifier::new);
+        let next = chunk.next.map(ChunkIdentifier::new);
+
+        let content = if chunk.is_gap {
+            let value = get(&txn.table(tables::GAP_CHUNKS)?, &chunk_key(linked_chunk_key, id))?
+                .ok_or_else(|| Error::InvalidData {
+                    details: format!("the content of the gap {id} is missing"),
+                })?;
+            ChunkContent::Gap(self.deserialize_json(&value)?)
+        } else {
+            let event_chunks = txn.table(tables::EVENT_CHUNKS)?;
+            let events = txn.table(tables::EVENTS)?;
+            let room_key = match get(&txn.table(tables::LINKED_CHUNK_ROOMS)?, linked_chunk_key)? {
+                Some(value) => split_value::<2>(&value)?[0].to_vec(),
+                None => Vec::new(),
+            };
+
+            let mut items = Vec::new();
+            for (_, event_id) in get_prefixed(&event_chunks, &chunk_key(linked_chunk_key, id))? {
+                let value =
+                    get(&events, &key([room_key.as_slice(), &event_id]))?.ok_or_else(|| {
+                        Error::InvalidData {
+                            details: "the content of an event of a chunk is missing".to_owned(),
+                        }
+                    })?;
+                items.push(self.decode_event(&value)?);
+            }
+
+            ChunkContent::Items(items)
+        };
+
+        Ok(RawChunk { content, previous, identifier, next })
+    }
+
+    /// Load all the chunks of the given linked chunk, ordered by identifier.
+    fn load_chunks(
+        &self,
+        txn: &impl TableReader,
+        linked_chunk_key: &[u8],
+    ) -> Result<Vec<RawChunk<Event, Gap>>> {
+        let prefix = key([linked_chunk_key]);
+
+        get_prefixed(&txn.table(tables::LINKED_CHUNKS)?, &prefix)?
+            .into_iter()
+            .map(|(key, value)| {
+                let chunk = rmp_serde::from_slice(&value)?;
+                self.rebuild_chunk(txn, linked_chunk_key, chunk_id_from_key(&key)?, chunk)
+            })
+            .collect()
+    }
+
+    /// Apply the given updates to the given linked chunk, in the given
+    /// transaction.
+    ///
+    /// `is_room_linked_chunk` tells whether the linked chunk is the one of the
+    /// room itself, rather than a detached one.
+    fn apply_updates(
+        &self,
+        txn: &WriteTransaction,
+        linked_chunk_key: &[u8],
+        room_key: &[u8],
+        is_room_linked_chunk: bool,
+        updates: Vec<Update<Event, Gap>>,
+    ) -> Result<()> {
+        let room_value = key([room_key, &[u8::from(is_room_linked_chunk)]]);
+        let mut linked_chunks = txn.open_table(tables::LINKED_CHUNKS)?;
+        let mut gap_chunks = txn.open_table(tables::GAP_CHUNKS)?;
+        let mut event_chunks = txn.open_table(tables::EVENT_CHUNKS)?;
+        let mut event_positions = txn.open_table(tables::EVENT_POSITIONS)?;
+
+        for update in updates {
+            match update {
+                Update::NewItemsChunk { previous, new, next } => {
+                    trace!("new events chunk (prev={previous:?}, i={new:?}, next={next:?})");
+
+                    insert_chunk(&mut linked_chunks, linked_chunk_key, previous, new, next, false)?;
+                    txn.open_table(tables::LINKED_CHUNK_ROOMS)?
+                        .insert(linked_chunk_key, room_value.as_slice())?;
+                }
+
+                Update::NewGapChunk { previous, new, next, gap } => {
+                    trace!("new gap chunk (prev={previous:?}, i={new:?}, next={next:?})");
+
+                    insert_chunk(&mut linked_chunks, linked_chunk_key, previous, new, next, true)?;
+                    txn.open_table(tables::LINKED_CHUNK_ROOMS)?
+                        .insert(linked_chunk_key, room_value.as_slice())?;
+
+                    gap_chunks.insert(
+                        chunk_key(linked_chunk_key, new.index()).as_slice(),
+                        self.encode_gap(&gap)?.as_slice(),
+                    )?;
+                }
+
+                Update::RemoveChunk(chunk_identifier) => {
+                    let id = chunk_identifier.index();
+
+                    trace!("removing chunk @ {id}");
+
+                    let removed_chunk_key = chunk_key(linked_chunk_key, id);
+                    let chunk: ChunkEntry = rmp_serde::from_slice(
+                        &get(&linked_chunks, &removed_chunk_key)?.ok_or_else(|| {
+                            Error::InvalidData {
+                                details: format!("the chunk {id} to remove doesn't exist"),
+                            }
+                        })?,
+                    )?;
+
+                    // Replace its previous' next to its own next.
+                    if let Some(previous) = chunk.previous {
+                        update_chunk(&mut linked_chunks, linked_chunk_key, previous, |c| {
+                            c.next = chunk.next
+                        })?;
+                    }
+
+                    // Replace its next' previous to its own previous.
+                    if let Some(next) = chunk.next {
+                        update_chunk(&mut linked_chunks, linked_chunk_key, next, |c| {
+                            c.previous = chunk.previous
+                        })?;
+                    }
+
+                    linked_chunks.remove(removed_chunk_key.as_slice())?;
+                    gap_chunks.remove(removed_chunk_key.as_slice())?;
+
+                    for (_, event_id) in
+                        remove_prefixed_entries(&mut event_chunks, &removed_chunk_key)?
+                    {
+                        event_positions.remove(key([linked_chunk_key, &event_id]).as_slice())?;
+                    }
+                }
+
+                Update::PushItems { at, items } => {
+                    let chunk_id = at.chunk_identifier().index();
+
+                    trace!("pushing {} items @ {chunk_id}", items.len());
+
+                    let mut events = txn.open_table(tables::EVENTS)?;
+                    let mut relations = txn.open_table(tables::EVENT_RELATIONS)?;
+
+                    let items = items.into_iter().filter_map(|event| {
+                        let Some(event_id) = event.event_id() else {
+                            error!("Trying to push an event with no ID");
+                            return None;
+                        };
+
+                        Some((event_id, event))
+                    });
+
+                    for (i, (event_id, event)) in items.enumerate() {
+                        let position = Position::new(at.chunk_identifier(), at.index() + i);
+
+                        set_event_position(
+                            &mut event_chunks,
+                            &mut event_positions,
+                            linked_chunk_key,
+                            position,
+                            event_id.as_bytes(),
+                        )?;
+
+                        // The event might have been already inserted in the database: when
+                        // it is deduplicated and moved to another position, or because it
+                        // was inserted outside the context of a linked chunk.
+                        save_event(
+                            &mut events,
+                            &mut relations,
+                            room_key,
+                            &event_id,
+                            &self.encode_event(&event)?,
+                        )?;
+                    }
+                }
+
+                Update::ReplaceItem { at, item: event } => {
+                    trace!("replacing item @ {at:?}");
+
+                    // The event id should be the same, but just in case it changed…
+                    let Some(event_id) = event.event_id() else {
+                        error!("Trying to replace an event with a new one that has no ID");
+                        continue;
+                    };
+
+                    save_event(
+                        &mut txn.open_table(tables::EVENTS)?,
+                        &mut txn.open_table(tables::EVENT_RELATIONS)?,
+                        room_key,
+                        &event_id,
+                        &self.encode_event(&event)?,
+                    )?;
+
+                    // Replace the event id in the linked chunk, in case it changed.
+                    if let Some(previous_event_id) = event_chunks
+                        .remove(position_key(linked_chunk_key, at).as_slice())?
+                        .map(|value| value.value().to_vec())
+                    {
+                        remove_event_position(
+                            &mut event_positions,
+                            linked_chunk_key,
+                            &previous_event_id,
+                            at,
+                        )?;
+                    }
+
+                    set_event_position(
+                        &mut event_chunks,
+                        &mut event_positions,
+                        linked_chunk_key,
+                        at,
+                        event_id.as_bytes(),
+                    )?;
+                }
+
+                Update::RemoveItem { at } => {
+                    trace!("removing item @ {at:?}");
+
+                    if let Some(event_id) = event_chunks
+                        .remove(position_key(linked_chunk_key, at).as_slice())?
+                        .map(|value| value.value().to_vec())
+                    {
+                        remove_event_position(
+                            &mut event_positions,
+                            linked_chunk_key,
+                            &event_id,
+                            at,
+                        )?;
+                    }
+
+                    // Shift the position of the following items of the chunk, in ascending
+                    // order so they don't overwrite each other.
+                    let prefix = chunk_key(linked_chunk_key, at.chunk_identifier().index());
+                    for (key, event_id) in get_prefixed(&event_chunks, &prefix)? {
+                        let index = position_index_from_key(&key)?;
+                        if index <= at.index() {
+                            continue;
+                        }
+
+                        let position = Position::new(at.chunk_identifier(), index);
+                        event_chunks.remove(key.as_slice())?;
+                        remove_event_position(
+                            &mut event_positions,
+                            linked_chunk_key,
+                            &event_id,
+                            position,
+                        )?;
+
+                        set_event_position(
+                            &mut event_chunks,
+                            &mut event_positions,
+                            linked_chunk_key,
+                            Position::new(at.chunk_identifier(), index - 1),
+                            &event_id,
+                        )?;
+                    }
+                }
+
+                Update::DetachLastItems { at } => {
+                    trace!("truncating items >= {at:?}");
+
+                    let prefix = chunk_key(linked_chunk_key, at.chunk_identifier().index());
+                    for (key, event_id) in get_prefixed(&event_chunks, &prefix)? {
+                        let index = position_index_from_key(&key)?;
+                        if index < at.index() {
+                            continue;
+                        }
+
+                        event_chunks.remove(key.as_slice())?;
+                        remove_event_position(
+                            &mut event_positions,
+                            linked_chunk_key,
+                            &event_id,
+                            Position::new(at.chunk_identifier(), index),
+                        )?;
+                    }
+                }
+
+                Update::Clear => {
+                    trace!("clearing items");
+
+                    let prefix = key([linked_chunk_key]);
+                    remove_prefixed(&mut linked_chunks, &prefix)?;
+                    remove_prefixed(&mut gap_chunks, &prefix)?;
+                    remove_prefixed(&mut event_chunks, &prefix)?;
+                    remove_prefixed(&mut event_positions, &prefix)?;
+                    txn.open_table(tables::LINKED_CHUNK_ROOMS)?.remove(linked_chunk_key)?;
+                }
+
+                Update::StartReattachItems | Update::EndReattachItems => {
+                    // Nothing.
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the media matching the given key prefix, and update its last
+    /// access time.
+    async fn get_media_and_update_last_access(
+        &self,
+        prefix: Vec<u8>,
+        current_time: SystemTime,
+    ) -> Result<Option<Vec<u8>>> {
+        let timestamp = time_to_timestamp(current_time);
+
+        let data = self
+            .db
+            .write(move |txn| -> Result<_> {
+                let mut media = txn.open_table(tables::MEDIA)?;
+
+                let Some((key, value)) = get_prefixed(&media, &prefix)?.into_iter().next() else {
+                    return Ok(None);
+                };
+
+                let [ignore_policy, _, data, request, mime_type] = split_value(&value)?;
+                let value = media_value(ignore_policy == [1], timestamp, data, request, mime_type);
+                media.insert(key.as_slice(), value.as_slice())?;
+
+                Ok(Some(data.to_vec()))
+            })
+            .await?;
+
+        data.map(|data| Ok(self.decode_value(&data)?.into_owned())).transpose()
+    }
+}
+
+/// A way to open the tables of a read or a write transaction.
+trait TableReader {
+    type Table: ReadableTable<&'static [u8], &'static [u8]>;
+
+    fn table(&self, table: Table) -> Result<Self::Table>;
+}
+
+impl TableReader for redb::ReadTransaction {
+    type Table = redb::ReadOnlyTable<&'static [u8], &'static [u8]>;
+
+    fn table(&self, table: Table) -> Result<Self::Table> {
+        Ok(self.open_table(table)?)
+    }
+}
+
+impl<'txn> TableReader for &'txn WriteTransaction {
+    type Table = redb::Table<'txn, &'static [u8], &'static [u8]>;
+
+    fn table(&self, table: Table) -> Result<Self::Table> {
+        Ok(self.open_table(table)?)
+    }
+}
+
+/// The key of a chunk in `tables::LINKED_CHUNKS` and `tables::GAP_CHUNKS`,
+/// which is also the prefix of the keys of its events in
+/// `tables::EVENT_CHUNKS`.
+fn chunk_key(linked_chunk_key: &[u8], id: u64) -> Vec<u8> {
+    key([linked_chunk_key, &id.to_be_bytes()])
+}
+
+/// The key of the event at the given position in `tables::EVENT_CHUNKS`.
+fn position_key(linked_chunk_key: &[u8], position: Position) -> Vec<u8> {
+    key([
+        linked_chunk_key,
+        &position.chunk_identifier().index().to_be_bytes(),
+        &(position.index() as u64).to_be_bytes(),
+    ])
+}
+
+/// Parse the given big-endian integer.
+fn parse_u64(bytes: &[u8]) -> Result<u64> {
+    Ok(u64::from_be_bytes(
+        bytes
+            .try_into()
+            .map_err(|_| Error::InvalidData { details: "invalid integer in a key".to_owned() })?,
+    ))
+}
+
+/// Get the chunk identifier out of a key of `tables::LINKED_CHUNKS`.
+fn chunk_id_from_key(key: &[u8]) -> Result<u64> {
+    let [_, id] = split_value(key)?;
+    parse_u64(id)
+}
+
+/// Get the index of an event out of a key of `tables::EVENT_CHUNKS`.
+fn position_index_from_key(key: &[u8]) -> Result<usize> {
+    let [_, _, index] = split_value(key)?;
+    Ok(parse_u64(index)? as usize)
+}
+
+/// Build a value of `tables::MEDIA`.
+///
+/// The MIME type is guessed from the content of the media when it is stored,
+/// so the media can be grouped by MIME type without decrypting them.
+fn media_value(
+    ignore_policy: bool,
+    last_access: u64,
+    data: &[u8],
+    request: &[u8],
+    mime_type: &[u8],
+) -> Vec<u8> {
+    key([
+        [u8::from(ignore_policy)].as_slice(),
+        &last_access.to_be_bytes(),
+        data,
+        request,
+        mime_type,
+    ])
+}
+
+/// Get the value of the given key in the `kv_blob` table, serialized with
+/// MessagePack.
+fn get_serialized_kv<T: DeserializeOwned>(
+    kv_blob: &impl ReadableTable<&'static [u8], &'static [u8]>,
+    key: &str,
+) -> Result<Option<T>> {
+    get(kv_blob, key.as_bytes())?.map(|value| Ok(rmp_serde::from_slice(&value)?)).transpose()
+}
+
+/// Remove all the entries whose key starts with the given prefix, and return
+/// them.
+fn remove_prefixed_entries(
+    table: &mut redb::Table<'_, &'static [u8], &'static [u8]>,
+    prefix: &[u8],
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let entries = get_prefixed(table, prefix)?;
+
+    for (key, _) in &entries {
+        table.remove(key.as_slice())?;
+    }
+
+    Ok(entries)
+}
+
+/// Insert a new chunk, and link it to its previous and next chunks.
+fn insert_chunk(
+    linked_chunks: &mut redb::Table<'_, &'static [u8], &'static [u8]>,
+    linked_chunk_key: &[u8],
+    previous: Option<ChunkIdentifier>,
+    new: ChunkIdentifier,
+    next: Option<ChunkIdentifier>,
+    is_gap: bool,
+) -> Result<()> {
+    let previous = previous.as_ref().map(ChunkIdentifier::index);
+    let new = new.index();
+    let next = next.as_ref().map(ChunkIdentifier::index);
+
+    let chunk = rmp_serde::to_vec_named(&ChunkEntry { previous, next, is_gap })?;
+    linked_chunks.insert(chunk_key(linked_chunk_key, new).as_slice(), chunk.as_slice())?;
+
+    // If this chunk has a previous one, update its `next` field.
+    if let Some(previous) = previous {
+        update_chunk(linked_chunks, linked_chunk_key, previous, |c| c.next = Some(new))?;
+    }
+
+    // If this chunk has a next one, update its `previous` field.
+    if let Some(next) = next {
+        update_chunk(linked_chunks, linked_chunk_key, next, |c| c.previous = Some(new))?;
+    }
+
+    Ok(())
+}
+
+/// Update the given chunk, if it exists.
+fn update_chunk(
+    linked_chunks: &mut redb::Table<'_, &'static [u8], &'static [u8]>,
+    linked_chunk_key: &[u8],
+    id: u64,
+    f: impl FnOnce(&mut ChunkEntry),
+) -> Result<()> {
+    let key = chunk_key(linked_chunk_key, id);
+
+    let Some(value) = get(linked_chunks, &key)? else {
+        return Ok(());
+    };
+
+    let mut chunk: ChunkEntry = rmp_serde::from_slice(&value)?;
+    f(&mut chunk);
+    linked_chunks.insert(key.as_slice(), rmp_serde::to_vec_named(&chunk)?.as_slice())?;
+
+    Ok(())
+}
+
+/// Put the event with the given ID at the given position of the linked
+/// chunk.
+fn set_event_position(
+    event_chunks: &mut redb::Table<'_, &'static [u8], &'static [u8]>,
+    event_positions: &mut redb::Table<'_, &'static [u8], &'static [u8]>,
+    linked_chunk_key: &[u8],
+    position: Position,
+    event_id: &[u8],
+) -> Result<()> {
+    let position = position_key(linked_chunk_key, position);
+    event_chunks.insert(position.as_slice(), event_id)?;
+    event_positions.insert(key([linked_chunk_key, event_id]).as_slice(), position.as_slice())?;
+
+    Ok(())
+}
+
+/// Remove the entry of the given event from the index of the positions, if it
+/// points to the given position.
+fn remove_event_position(
+    event_positions: &mut redb::Table<'_, &'static [u8], &'static [u8]>,
+    linked_chunk_key: &[u8],
+    event_id: &[u8],
+    position: Position,
+) -> Result<()> {
+    let key = key([linked_chunk_key, event_id]);
+
+    if get(event_positions, &key)?
+        .is_some_and(|value| value == position_key(linked_chunk_key, position))
+    {
+        event_positions.remove(key.as_slice())?;
+    }
+
+    Ok(())
+}
+
+/// Get the position of the given event in the linked chunk, if any.
+fn get_event_position(
+    event_positions: &impl ReadableTable<&'static [u8], &'static [u8]>,
+    linked_chunk_key: &[u8],
+    event_id: &[u8],
+) -> Result<Option<Position>> {
+    let Some(value) = get(event_positions, &key([linked_chunk_key, event_id]))? else {
+        return Ok(None);
+    };
+
+    let [_, chunk_id, index] = split_value(&value)?;
+
+    Ok(Some(Position::new(ChunkIdentifier::new(parse_u64(chunk_id)?), parse_u64(index)? as usize)))
+}
+
+/// Save the given encoded event, replacing the previous one with the same ID
+/// if any, and keeping the index of the relations up to date.
+fn save_event(
+    events: &mut redb::Table<'_, &'static [u8], &'static [u8]>,
+    relations: &mut redb::Table<'_, &'static [u8], &'static [u8]>,
+    room_key: &[u8],
+    event_id: &EventId,
+    value: &[u8],
+) -> Result<()> {
+    if let Some(previous) = events
+        .insert(key([room_key, event_id.as_bytes()]).as_slice(), value)?
+        .map(|previous| previous.value().to_vec())
+    {
+        remove_event_relation(relations, room_key, event_id, &previous)?;
+    }
+
+    let [_, relates_to, rel_type] = split_value(value)?;
+    if !relates_to.is_empty() {
+        relations.insert(key([room_key, relates_to, event_id.as_bytes()]).as_slice(), rel_type)?;
+    }
+
+    Ok(())
+}
+
+/// Remove the entry of the given event from the index of the relations.
+fn remove_event_relation(
+    relations: &mut redb::Table<'_, &'static [u8], &'static [u8]>,
+    room_key: &[u8],
+    event_id: &EventId,
+    value: &[u8],
+) -> Result<()> {
+    let [_, relates_to, _] = split_value(value)?;
+    if !relates_to.is_empty() {
+        relations.remove(key([room_key, relates_to, event_id.as_bytes()]).as_slice())?;
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl EventCacheStore for RedbEventCacheStore {
+    type Error = Error;
+
+    #[instrument(skip(self))]
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool> {
+        self.db.try_take_leased_lock(tables::LEASE_LOCKS, lease_duration_ms, key, holder).await
+    }
+
+    #[instrument(skip(self, updates))]
+    async fn handle_linked_chunk_updates(
+        &self,
+        linked_chunk_id: LinkedChunkId<'_>,
+        updates: Vec<Update<Event, Gap>>,
+    ) -> Result<(), Self::Error> {
+        // Use a single transaction throughout this function, so that either all updates
+        // work, or none is taken into account.
+        let linked_chunk_key = self.linked_chunk_key(linked_chunk_id);
+        let room_key = self.room_key(linked_chunk_id.room_id());
+        let is_room_linked_chunk = matches!(linked_chunk_id, LinkedChunkId::Room(_));
+        // The aggregations are indexed per room, so they're cleared along with the
+        // room's linked chunk.
+        let clear_aggregations =
+            is_room_linked_chunk && updates.iter().any(|update| matches!(update, Update::Clear));
+        let this = self.clone();
+
+        self.db
+            .write(move |txn| {
+                this.apply_updates(
+                    txn,
+                    &linked_chunk_key,
+                    &room_key,
+                    is_room_linked_chunk,
+                    updates,
+                )?;
+
+                if clear_aggregations {
+                    remove_prefixed(
+                        &mut txn.open_table(tables::EVENT_AGGREGATIONS)?,
+                        &key([room_key.as_slice()]),
+                    )?;
+                }
+
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip(self))]
+    async fn load_all_chunks(
+        &self,
+        linked_chunk_id: LinkedChunkId<'_>,
+    ) -> Result<Vec<RawChunk<Event, Gap>>, Self::Error> {
+        let linked_chunk_key = self.linked_chunk_key(linked_chunk_id);
+        let this = self.clone();
+
+        self.db.read(move |txn| this.load_chunks(txn, &linked_chunk_key)).await
+    }
+
+    #[instrument(skip(self))]
+    async fn load_all_chunks_metadata(
+        &self,
+        linked_chunk_id: LinkedChunkId<'_>,
+    ) -> Result<Vec<ChunkMetadata>, Self::Error> {
+        let linked_chunk_key = self.linked_chunk_key(linked_chunk_id);
+
+        self.db
+            .read(move |txn| {
+                let event_chunks = txn.open_table(tables::EVENT_CHUNKS)?;
+
+                get_prefixed(
+                    &txn.open_table(tables::LINKED_CHUNKS)?,
+                    &key([linked_chunk_key.as_slice()]),
+                )?
+                .into_iter()
+                .map(|(key, value)| {
+                    let id = chunk_id_from_key(&key)?;
+                    let chunk: ChunkEntry = rmp_serde::from_slice(&value)?;
+                    let num_items =
+                        get_prefixed(&event_chunks, &chunk_key(&linked_chunk_key, id))?.len();
+
+                    Ok(ChunkMetadata {
+                        identifier: ChunkIdentifier::new(id),
+                        previous: chunk.previous.map(ChunkIdentifier::new),
+                        next: chunk.next.map(ChunkIdentifier::new),
+                        num_items,
+                    })
+                })
+                .collect()
+            })
+            .await
+    }
+
+    #[instrument(skip(self))]
+    async fn load_last_chunk(
+        &self,
+        linked_chunk_id: LinkedChunkId<'_>,
+    ) -> Result<(Option<RawChunk<Event, Gap>>, ChunkIdentifierGenerator), Self::Error> {
+        let linked_chunk_key = self.linked_chunk_key(linked_chunk_id);
+        let this = self.clone();
+
+        self.db
+            .read(move |txn| {
+                let chunks = get_prefixed(
+                    &txn.open_table(tables::LINKED_CHUNKS)?,
+                    &key([linked_chunk_key.as_slice()]),
+                )?;
+
+                // The chunks are ordered by identifier, so the last one has the biggest
+                // identifier.
+                let chunk_identifier_generator = match chunks.last() {
+                    Some((key, _)) => ChunkIdentifierGenerator::new_from_previous_chunk_identifier(
+                        ChunkIdentifier::new(chunk_id_from_key(key)?),
+                    ),
+                    None => ChunkIdentifierGenerator::new_from_scratch(),
+                };
+
+                let number_of_chunks = chunks.len();
+
+                // Find the last chunk.
+                let mut last_chunk = None;
+                for (key, value) in chunks {
+                    let chunk: ChunkEntry = rmp_serde::from_slice(&value)?;
+                    if chunk.next.is_none() {
+                        last_chunk = Some((chunk_id_from_key(&key)?, chunk));
+                        break;
+                    }
+                }
+
+                let Some((id, chunk)) = last_chunk else {
+                    // Chunk is not found and there are zero chunks for this room, this is
+                    // consistent, all good.
+                    if number_of_chunks == 0 {
+                        return Ok((None, chunk_identifier_generator));
+                    }
+
+                    // Chunk is not found **but** there are chunks for this room, this is
+                    // inconsistent. The linked chunk is malformed.
+                    return Err(Error::InvalidData {
+                        details:
+                            "last chunk is not found but chunks exist: the linked chunk contains a cycle"
+                                .to_owned(),
+                    });
+                };
+
+                let last_chunk = this.rebuild_chunk(txn, &linked_chunk_key, id, chunk)?;
+
+                Ok((Some(last_chunk), chunk_identifier_generator))
+            })
+            .await
+    }
+
+    #[instrument(skip(self))]
+    async fn load_previous_chunk(
+        &self,
+        linked_chunk_id: LinkedChunkId<'_>,
+        before_chunk_identifier: ChunkIdentifier,
+    ) -> Result<Option<RawChunk<Event, Gap>>, Self::Error> {
+        let linked_chunk_key = self.linked_chunk_key(linked_chunk_id);
+        let this = self.clone();
+
+        self.db
+            .read(move |txn| {
+                let linked_chunks = txn.open_table(tables::LINKED_CHUNKS)?;
+                let before = before_chunk_identifier.index();
+
+                // Find the chunk before the chunk identified by `before_chunk_identifier`.
+                let Some(value) = get(&linked_chunks, &chunk_key(&linked_chunk_key, before))?
+                else {
+                    return Ok(None);
+                };
+                let Some(id) = rmp_serde::from_slice::<ChunkEntry>(&value)?.previous else {
+                    return Ok(None);
+                };
+                let Some(value) = get(&linked_chunks, &chunk_key(&linked_chunk_key, id))? else {
+                    return Ok(None);
+                };
+
+                let chunk: ChunkEntry = rmp_serde::from_slice(&value)?;
+                if chunk.next != Some(before) {
+                    return Ok(None);
+                }
+
+                Ok(Some(this.rebuild_chunk(txn, &linked_chunk_key, id, chunk)?))
+            })
+            .await
+    }
+
+    #[instrument(skip(self))]
+    async fn clear_all_linked_chunks(&self) -> Result<(), Self::Error> {
+        self.db
+            .write(|txn| -> Result<_> {
+                for table in [
+                    tables::LINKED_CHUNKS,
+                    tables::LINKED_CHUNK_ROOMS,
+                    tables::GAP_CHUNKS,
+                    tables::EVENT_CHUNKS,
+                    tables::EVENT_POSITIONS,
+                    // Also clear all the events' contents.
+                    tables::EVENTS,
+                    tables::EVENT_RELATIONS,
+                    tables::EVENT_AGGREGATIONS,
+                ] {
+                    remove_prefixed(&mut txn.open_table(table)?, &[])?;
+                }
+
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip(self, events))]
+    async fn filter_duplicated_events(
+        &self,
+        linked_chunk_id: LinkedChunkId<'_>,
+        events: Vec<OwnedEventId>,
+    ) -> Result<Vec<(OwnedEventId, Position)>, Self::Error> {
+        if events.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let linked_chunk_key = self.linked_chunk_key(linked_chunk_id);
+
+        self.db
+            .read(move |txn| {
+                let event_positions = txn.open_table(tables::EVENT_POSITIONS)?;
+
+                let mut duplicated_events = Vec::new();
+                for event_id in events {
+                    if let Some(position) = get_event_position(
+                        &event_positions,
+                        &linked_chunk_key,
+                        event_id.as_bytes(),
+                    )? {
+                        duplicated_events.push((event_id, position));
+                    }
+                }
+
+                duplicated_events.sort_by_key(|(_, position)| {
+                    (position.chunk_identifier().index(), position.index())
+                });
+
+                Ok(duplicated_events)
+            })
+            .await
+    }
+
+    #[instrument(skip(self, event_id))]
+    async fn find_event(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+    ) -> Result<Option<Event>, Self::Error> {
+        let key = key([self.room_key(room_id).as_slice(), event_id.as_bytes()]);
+
+        self.db
+            .read(move |txn| get(&txn.open_table(tables::EVENTS)?, &key))
+            .await?
+            .map(|value| self.decode_event(&value))
+            .transpose()
+    }
+
+    #[instrument(skip(self, event_id, filters))]
+    async fn find_event_relations(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        filters: Option<&[RelationType]>,
+    ) -> Result<Vec<(Event, Option<Position>)>, Self::Error> {
+        let room_key = self.room_key(room_id);
+        let linked_chunk_key = self.linked_chunk_key(LinkedChunkId::Room(room_id));
+        let filters = compute_filters_string(filters);
+        let prefix = key([room_key.as_slice(), event_id.as_bytes()]);
+
+        let related = self
+            .db
+            .read(move |txn| {
+                let events = txn.open_table(tables::EVENTS)?;
+                let event_positions = txn.open_table(tables::EVENT_POSITIONS)?;
+
+                let mut related = Vec::new();
+                for (relation_key, rel_type) in
+                    get_prefixed(&txn.open_table(tables::EVENT_RELATIONS)?, &prefix)?
+                {
+                    if filters.as_ref().is_some_and(|filters| {
+                        !filters.iter().any(|filter| filter.as_bytes() == rel_type)
+                    }) {
+                        continue;
+                    }
+
+                    let [_, _, related_event_id] = split_value(&relation_key)?;
+                    let Some(value) = get(&events, &key([&room_key, related_event_id]))? else {
+                        continue;
+                    };
+                    let position =
+                        get_event_position(&event_positions, &linked_chunk_key, related_event_id)?;
+
+                    related.push((value, position));
+                }
+
+                Ok(related)
+            })
+            .await?;
+
+        related
+            .into_iter()
+            .map(|(value, position)| Ok((self.decode_event(&value)?, position)))
+            .collect()
+    }
+
+    #[instrument(skip(self, event))]
+    async fn save_event(&self, room_id: &RoomId, event: Event) -> Result<(), Self::Error> {
+        let Some(event_id) = event.event_id() else {
+            error!(%room_id, "Trying to save an event with no ID");
+            return Ok(());
+        };
+
+        let room_key = self.room_key(room_id);
+        let value = self.encode_event(&event)?;
+
+        self.db
+            .write(move |txn| -> Result<_> {
+                save_event(
+                    &mut txn.open_table(tables::EVENTS)?,
+                    &mut txn.open_table(tables::EVENT_RELATIONS)?,
+                    &room_key,
+                    &event_id,
+                    &value,
+                )
+            })
+            .await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_all_room_events(&self, room_id: &RoomId) -> Result<Vec<Event>, Self::Error> {
+        let prefix = key([self.room_key(room_id).as_slice()]);
+
+        self.db
+            .read(move |txn| get_prefixed(&txn.open_table(tables::EVENTS)?, &prefix))
+            .await?
+            .iter()
+            .map(|(_, value)| self.decode_event(value))
+            .collect()
+    }
+
+    #[instrument(skip(self, aggregations))]
+    async fn save_event_aggregations(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        aggregations: EventAggregations,
+    ) -> Result<(), Self::Error> {
+        let key = key([self.room_key(room_id).as_slice(), event_id.as_bytes()]);
+        let value =
+            (!aggregations.is_empty()).then(|| self.serialize_json(&aggregations)).transpose()?;
+
+        self.db
+            .write(move |txn| -> Result<_> {
+                let mut event_aggregations = txn.open_table(tables::EVENT_AGGREGATIONS)?;
+
+                if let Some(value) = value {
+                    event_aggregations.insert(key.as_slice(), value.as_slice())?;
+                } else {
+                    event_aggregations.remove(key.as_slice())?;
+                }
+
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip(self, event_ids))]
+    async fn load_event_aggregations(
+        &self,
+        room_id: &RoomId,
+        event_ids: &[OwnedEventId],
+    ) -> Result<BTreeMap<OwnedEventId, EventAggregations>, Self::Error> {
+        let room_key = self.room_key(room_id);
+        let event_ids = event_ids.to_vec();
+
+        let values = self
+            .db
+            .read(move |txn| {
+                let event_aggregations = txn.open_table(tables::EVENT_AGGREGATIONS)?;
+
+                let mut values = Vec::new();
+                for event_id in event_ids {
+                    let key = key([room_key.as_slice(), event_id.as_bytes()]);
+                    if let Some(value) = get(&event_aggregations, &key)? {
+                        values.push((event_id, value));
+                    }
+                }
+
+                Ok(values)
+            })
+            .await?;
+
+        values
+            .into_iter()
+            .map(|(event_id, value)| Ok((event_id, self.deserialize_json(&value)?)))
+            .collect()
+    }
+
+    #[instrument(skip_all)]
+    async fn set_event_retention_policy(
+        &self,
+        policy: EventRetentionPolicy,
+    ) -> Result<(), Self::Error> {
+        let value = rmp_serde::to_vec_named(&policy)?;
+
+        self.db
+            .write(move |txn| -> Result<_> {
+                txn.open_table(tables::KV_BLOB)?
+                    .insert(keys::EVENT_RETENTION_POLICY.as_bytes(), value.as_slice())?;
+                Ok(())
+            })
+            .await?;
+
+        *self.event_retention_policy.write().unwrap() = policy;
+
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    fn event_retention_policy(&self) -> EventRetentionPolicy {
+        *self.event_retention_policy.read().unwrap()
+    }
+
+    #[instrument(skip_all)]
+    async fn clean_up_linked_chunks(&self) -> Result<(), Self::Error> {
+        let policy = self.event_retention_policy();
+
+        if !policy.has_limitations() {
+            // We can safely skip all the checks.
+            return Ok(());
+        }
+
+        let this = self.clone();
+
+        self.db
+            .write(move |txn| -> Result<_> {
+                let linked_chunk_rooms =
+                    get_prefixed(&txn.open_table(tables::LINKED_CHUNK_ROOMS)?, &[])?;
+
+                let mut linked_chunks = Vec::with_capacity(linked_chunk_rooms.len());
+                // All the linked chunks of each room, to know whether an evicted event is
+                // still used by another one.
+                let mut room_linked_chunks = HashMap::<Vec<u8>, Vec<Vec<u8>>>::new();
+
+                for (linked_chunk_key, value) in linked_chunk_rooms {
+                    let [room_key, is_room_linked_chunk] = split_value(&value)?;
+
+                    room_linked_chunks
+                        .entry(room_key.to_vec())
+                        .or_default()
+                        .push(linked_chunk_key.clone());
+
+                    // The policy only applies to the linked chunks of the rooms, not to the
+                    // detached ones.
+                    if is_room_linked_chunk == [1] {
+                        let chunks = this.load_chunks(&txn, &linked_chunk_key)?;
+                        linked_chunks.push(((linked_chunk_key, room_key.to_vec()), chunks));
+                    }
+                }
+
+                let evictions =
+                    policy.compute_evictions(linked_chunks, MilliSecondsSinceUnixEpoch::now());
+
+                for ((linked_chunk_key, room_key), eviction) in evictions {
+                    trace!(
+                        "evicting {} chunks and {} events",
+                        eviction.evicted_chunks.len(),
+                        eviction.evicted_events.len()
+                    );
+
+                    this.apply_updates(
+                        txn,
+                        &linked_chunk_key,
+                        &room_key,
+                        true,
+                        eviction.updates(),
+                    )?;
+
+                    // Also remove the content of the evicted events, along with their
+                    // aggregations, unless another linked chunk of the room still uses them.
+                    let event_positions = txn.open_table(tables::EVENT_POSITIONS)?;
+                    let mut events = txn.open_table(tables::EVENTS)?;
+                    let mut relations = txn.open_table(tables::EVENT_RELATIONS)?;
+                    let mut aggregations = txn.open_table(tables::EVENT_AGGREGATIONS)?;
+
+                    'events: for event_id in &eviction.evicted_events {
+                        for other_linked_chunk_key in &room_linked_chunks[&room_key] {
+                            if get_event_position(
+                                &event_positions,
+                                other_linked_chunk_key,
+                                event_id.as_bytes(),
+                            )?
+                            .is_some()
+                            {
+                                continue 'events;
+                            }
+                        }
+
+                        let event_key = key([&room_key, event_id.as_bytes()]);
+
+                        if let Some(value) =
+                            events.remove(event_key.as_slice())?.map(|value| value.value().to_vec())
+                        {
+                            remove_event_relation(&mut relations, &room_key, event_id, &value)?;
+                        }
+
+                        aggregations.remove(event_key.as_slice())?;
+                    }
+                }
+
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn add_media_content(
+        &self,
+        request: &MediaRequestParameters,
+        content: Vec<u8>,
+        ignore_policy: IgnoreMediaRetentionPolicy,
+    ) -> Result<()> {
+        self.media_service.add_media_content(self, request, content, ignore_policy).await
+    }
+
+    #[instrument(skip_all)]
+    async fn replace_media_key(
+        &self,
+        from: &MediaRequestParameters,
+        to: &MediaRequestParameters,
+    ) -> Result<(), Self::Error> {
+        let from = self.media_key(from);
+        let request = self.serialize_value(to)?;
+        let to = self.media_key(to);
+
+        self.db
+            .write(move |txn| -> Result<_> {
+                let mut media = txn.open_table(tables::MEDIA)?;
+
+                let value = media.remove(from.as_slice())?.map(|value| value.value().to_vec());
+                if let Some(value) = value {
+                    let [ignore_policy, last_access, data, _, mime_type] = split_value(&value)?;
+                    let value = media_value(
+                        ignore_policy == [1],
+                        parse_u64(last_access)?,
+                        data,
+                        &request,
+                        mime_type,
+                    );
+                    media.insert(to.as_slice(), value.as_slice())?;
+                }
+
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_media_content(&self, request: &MediaRequestParameters) -> Result<Option<Vec<u8>>> {
+        self.media_service.get_media_content(self, request).await
+    }
+
+    #[instrument(skip_all)]
+    async fn remove_media_content(&self, request: &MediaRequestParameters) -> Result<()> {
+        let key = self.media_key(request);
+
+        self.db
+            .write(move |txn| -> Result<_> {
+                txn.open_table(tables::MEDIA)?.remove(key.as_slice())?;
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_media_content_for_uri(
+        &self,
+        uri: &MxcUri,
+    ) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.media_service.get_media_content_for_uri(self, uri).await
+    }
+
+    #[instrument(skip(self))]
+    async fn remove_media_content_for_uri(&self, uri: &MxcUri) -> Result<()> {
+        let prefix = self.media_uri_prefix(uri);
+
+        self.db
+            .write(move |txn| -> Result<_> {
+                remove_prefixed(&mut txn.open_table(tables::MEDIA)?, &prefix)?;
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn set_media_retention_policy(
+        &self,
+        policy: MediaRetentionPolicy,
+    ) -> Result<(), Self::Error> {
+        self.media_service.set_media_retention_policy(self, policy).await
+    }
+
+    #[instrument(skip_all)]
+    fn media_retention_policy(&self) -> MediaRetentionPolicy {
+        self.media_service.media_retention_policy()
+    }
+
+    #[instrument(skip_all)]
+    async fn set_ignore_media_retention_policy(
+        &self,
+        request: &MediaRequestParameters,
+        ignore_policy: IgnoreMediaRetentionPolicy,
+    ) -> Result<(), Self::Error> {
+        self.media_service.set_ignore_media_retention_policy(self, request, ignore_policy).await
+    }
+
+    #[instrument(skip_all)]
+    async fn clean_up_media_cache(&self) -> Result<(), Self::Error> {
+        self.media_service.clean_up_media_cache(self).await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_all_media_content(
+        &self,
+    ) -> Result<Vec<(MediaRequestParameters, Vec<u8>, IgnoreMediaRetentionPolicy)>, Self::Error>
+    {
+        let values = self.db.read(|txn| get_prefixed(&txn.open_table(tables::MEDIA)?, &[])).await?;
+
+        values
+            .iter()
+            .map(|(_, value)| {
+                let [ignore_policy, _, data, request, _] = split_value(value)?;
+                let ignore_policy = if ignore_policy == [1] {
+                    IgnoreMediaRetentionPolicy::Yes
+                } else {
+                    IgnoreMediaRetentionPolicy::No
+                };
+                Ok((
+                    self.deserialize_value(request)?,
+                    self.decode_value(data)?.into_owned(),
+                    ignore_policy,
+                ))
+            })
+            .collect()
+    }
+
+    #[instrument(skip_all)]
+    async fn storage_usage(&self) -> Result<StoreUsage, Self::Error> {
+        self.db.storage_usage(tables::ALL).await
+    }
+
+    #[instrument(skip_all)]
+    async fn room_events_usage(&self, room_id: &RoomId) -> Result<ItemsUsage, Self::Error> {
+        let prefix = key([self.room_key(room_id).as_slice()]);
+
+        let values = self
+            .db
+            .read(move |txn| get_prefixed(&txn.open_table(tables::EVENTS)?, &prefix))
+            .await?;
+
+        let mut usage = ItemsUsage::default();
+
+        for (_, value) in values {
+            let [content, _, _] = split_value(&value)?;
+            usage.add(content.len() as u64);
+        }
+
+        Ok(usage)
+    }
+
+    #[instrument(skip_all)]
+    async fn media_usage(&self) -> Result<BTreeMap<String, ItemsUsage>, Self::Error> {
+        let values = self.db.read(|txn| get_prefixed(&txn.open_table(tables::MEDIA)?, &[])).await?;
+
+        let mut usage = BTreeMap::<String, ItemsUsage>::new();
+
+        for (_, value) in values {
+            let [_, _, data, _, mime_type] = split_value(&value)?;
+            let mime_type = String::from_utf8_lossy(mime_type).into_owned();
+            usage.entry(mime_type).or_default().add(data.len() as u64);
+        }
+
+        Ok(usage)
+    }
+
+    #[instrument(skip_all)]
+    async fn compact(&self) -> Result<(), Self::Error> {
+        self.db.compact().await
+    }
+}
+
+#[async_trait]
+impl EventCacheStoreMedia for RedbEventCacheStore {
+    type Error = Error;
+
+    async fn media_retention_policy_inner(
+        &self,
+    ) -> Result<Option<MediaRetentionPolicy>, Self::Error> {
+        self.db
+            .read(|txn| {
+                get_serialized_kv(&txn.open_table(tables::KV_BLOB)?, keys::MEDIA_RETENTION_POLICY)
+            })
+            .await
+    }
+
+    async fn set_media_retention_policy_inner(
+        &self,
+        policy: MediaRetentionPolicy,
+    ) -> Result<(), Self::Error> {
+        let value = rmp_serde::to_vec_named(&policy)?;
+
+        self.db
+            .write(move |txn| -> Result<_> {
+                txn.open_table(tables::KV_BLOB)?
+                    .insert(keys::MEDIA_RETENTION_POLICY.as_bytes(), value.as_slice())?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn add_media_content_inner(
+        &self,
+        request: &MediaRequestParameters,
+        data: Vec<u8>,
+        last_access: SystemTime,
+        policy: MediaRetentionPolicy,
+        ignore_policy: IgnoreMediaRetentionPolicy,
+    ) -> Result<(), Self::Error> {
+        let ignore_policy = ignore_policy.is_yes();
+        let mime_type = guess_mime_type(&data);
+        let data = self.encode_value(data)?;
+
+        if !ignore_policy && policy.exceeds_max_file_size(data.len() as u64) {
+            return Ok(());
+        }
+
+        let key = self.media_key(request);
+        let request = self.serialize_value(request)?;
+        let value = media_value(
+            ignore_policy,
+            time_to_timestamp(last_access),
+            &data,
+            &request,
+            mime_type.as_bytes(),
+        );
+
+        self.db
+            .write(move |txn| -> Result<_> {
+                txn.open_table(tables::MEDIA)?.insert(key.as_slice(), value.as_slice())?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn set_ignore_media_retention_policy_inner(
+        &self,
+        request: &MediaRequestParameters,
+        ignore_policy: IgnoreMediaRetentionPolicy,
+    ) -> Result<(), Self::Error> {
+        let key = self.media_key(request);
+        let ignore_policy = ignore_policy.is_yes();
+
+        self.db
+            .write(move |txn| -> Result<_> {
+                let mut media = txn.open_table(tables::MEDIA)?;
+
+                let Some(value) = get(&media, &key)? else {
+                    return Ok(());
+                };
+
+                let [_, last_access, data, request, mime_type] = split_value(&value)?;
+                let value =
+                    media_value(ignore_policy, parse_u64(last_access)?, data, request, mime_type);
+                media.insert(key.as_slice(), value.as_slice())?;
+
+                Ok(())
+            })
+            .await
+    }
+
+    async fn get_media_content_inner(
+        &self,
+        request: &MediaRequestParameters,
+        current_time: SystemTime,
+    ) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.get_media_and_update_last_access(self.media_key(request), current_time).await
+    }
+
+    async fn get_media_content_for_uri_inner(
+        &self,
+        uri: &MxcUri,
+        current_time: SystemTime,
+    ) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.get_media_and_update_last_access(self.media_uri_prefix(uri), current_time).await
+    }
+
+    async fn clean_up_media_cache_inner(
+        &self,
+        policy: MediaRetentionPolicy,
+        current_time: SystemTime,
+    ) -> Result<(), Self::Error> {
+        if !policy.has_limitations() {
+            // We can safely skip all the checks.
+            return Ok(());
+        }
+
+        let last_media_cleanup_time = rmp_serde::to_vec_named(&current_time)?;
+
+        let removed = self
+            .db
+            .write(move |txn| -> Result<_> {
+                let mut media = txn.open_table(tables::MEDIA)?;
+
+                // The media that don't ignore the policy, with their size and last access.
+                let mut candidates = Vec::new();
+                for (key, value) in get_prefixed(&media, &[])? {
+                    let [ignore_policy, last_access, data, _, _] = split_value(&value)?;
+                    if ignore_policy != [1] {
+                        candidates.push((key, data.len() as u64, parse_u64(last_access)?));
+                    }
+                }
+
+                let mut keys_to_remove = Vec::new();
+
+                // First, check media content that exceed the max filesize, then clean up
+                // expired media content.
+                let current_timestamp = time_to_timestamp(current_time);
+                candidates.retain(|(key, size, last_access)| {
+                    let exceeds_max_file_size = policy.exceeds_max_file_size(*size);
+                    let expired = policy.last_access_expiry.is_some_and(|expiry| {
+                        current_timestamp.saturating_sub(*last_access) >= expiry.as_secs()
+                    });
+
+                    if exceeds_max_file_size || expired {
+                        keys_to_remove.push(key.clone());
+                        false
+                    } else {
+                        true
+                    }
+                });
+
+                // Finally, if the cache size is too big, remove old items until it fits.
+                if let Some(max_cache_size) = policy.max_cache_size {
+                    let cache_size = candidates
+                        .iter()
+                        .fold(0u64, |cache_size, (_, size, _)| cache_size.saturating_add(*size));
+
+                    if cache_size > max_cache_size {
+                        // Keep the most recently accessed media.
+                        candidates
+                            .sort_by_key(|(_, _, last_access)| std::cmp::Reverse(*last_access));
+
+                        let mut accumulated_items_size = 0u64;
+                        let mut limit_reached = false;
+
+                        for (key, size, _) in candidates {
+                            if !limit_reached {
+                                match accumulated_items_size.checked_add(size) {
+                                    Some(acc) if acc <= max_cache_size => {
+                                        accumulated_items_size = acc;
+                                        continue;
+                                    }
+                                    // The accumulated size is too big or overflowing, we can
+                                    // stop accumulating.
+                                    _ => limit_reached = true,
+                                }
+                            }
+
+                            keys_to_remove.push(key);
+                        }
+                    }
+                }
+
+                for key in &keys_to_remove {
+                    media.remove(key.as_slice())?;
+                }
+
+                txn.open_table(tables::KV_BLOB)?.insert(
+                    keys::LAST_MEDIA_CLEANUP_TIME.as_bytes(),
+                    last_media_cleanup_time.as_slice(),
+                )?;
+
+                Ok(!keys_to_remove.is_empty())
+            })
+            .await?;
+
+        // If we removed media, compact the database and free space on the
+        // filesystem.
+        if removed {
+            self.db.compact().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn last_media_cleanup_time_inner(&self) -> Result<Option<SystemTime>, Self::Error> {
+        self.db
+            .read(|txn| {
+                get_serialized_kv(&txn.open_table(tables::KV_BLOB)?, keys::LAST_MEDIA_CLEANUP_TIME)
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+    use matrix_sdk_base::{
+        event_cache::store::EventCacheStoreError, event_cache_store_integration_tests,
+        event_cache_store_integration_tests_time, event_cache_store_media_integration_tests,
+    };
+    use once_cell::sync::Lazy;
+    use tempfile::{tempdir, TempDir};
+
+    use super::RedbEventCacheStore;
+
+    static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
+    static NUM: AtomicU32 = AtomicU32::new(0);
+
+    async fn get_event_cache_store() -> Result<RedbEventCacheStore, EventCacheStoreError> {
+        let name = NUM.fetch_add(1, SeqCst).to_string();
+        let tmpdir_path = TMP_DIR.path().join(name);
+
+        tracing::info!("using event cache store @ {}", tmpdir_path.to_str().unwrap());
+
+        Ok(RedbEventCacheStore::open(tmpdir_path.to_str().unwrap(), None).await.unwrap())
+    }
+
+    event_cache_store_integration_tests!();
+    event_cache_store_integration_tests_time!();
+    event_cache_store_media_integration_tests!(with_media_size_tests);
+}
+
+#[cfg(test)]
+mod encrypted_tests {
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+    use matrix_sdk_base::{
+        event_cache::store::EventCacheStoreError, event_cache_store_integration_tests,
+        event_cache_store_integration_tests_time, event_cache_store_media_integration_tests,
+    };
+    use once_cell::sync::Lazy;
+    use tempfile::{tempdir, TempDir};
+
+    use super::RedbEventCacheStore;
+
+    static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
+    static NUM: AtomicU32 = AtomicU32::new(0);
+
+    async fn get_event_cache_store() -> Result<RedbEventCacheStore, EventCacheStoreError> {
+        let name = NUM.fetch_add(1, SeqCst).to_string();
+        let tmpdir_path = TMP_DIR.path().join(name);
+
+        tracing::info!("using event cache store @ {}", tmpdir_path.to_str().unwrap());
+
+        Ok(RedbEventCacheStore::open(tmpdir_path.to_str().unwrap(), Some("default_test_password"))
+            .await
+            .unwrap())
+    }
+
+    event_cache_store_integration_tests!();
+    event_cache_store_integration_tests_time!();
+    event_cache_store_media_integration_tests!();
+}
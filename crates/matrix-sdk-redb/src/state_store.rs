@@ -0,0 +1,1597 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    path::Path,
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use matrix_sdk_base::{
+    deserialized_responses::{DisplayName, RawAnySyncOrStrippedState},
+    store::{
+        ChildTransactionId, DependentQueuedRequest, DependentQueuedRequestKind, QueueWedgeError,
+        QueuedRequest, QueuedRequestKind, RoomLoadSettings, SentRequestKey,
+    },
+    store_usage::StoreUsage,
+    MinimalRoomMemberEvent, RoomInfo, RoomMemberships, RoomState, StateChanges, StateStore,
+    StateStoreDataKey, StateStoreDataValue, ROOM_VERSION_FALLBACK,
+};
+use matrix_sdk_store_encryption::StoreCipher;
+use redb::{ReadableTable, TableDefinition};
+use ruma::{
+    canonical_json::{redact, RedactedBecause},
+    events::{
+        presence::PresenceEvent,
+        receipt::{Receipt, ReceiptThread, ReceiptType},
+        room::member::{MembershipState, StrippedRoomMemberEvent, SyncRoomMemberEvent},
+        AnyGlobalAccountDataEvent, AnyRoomAccountDataEvent, AnySyncStateEvent,
+        GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType,
+    },
+    serde::Raw,
+    CanonicalJsonObject, EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId,
+    OwnedTransactionId, OwnedUserId, RoomId, TransactionId, UserId,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{
+    error::{Error, Result},
+    utils::{get, get_prefixed, key, remove_prefixed, EncryptableStore, RedbDatabase, Table},
+    OpenStoreError,
+};
+
+mod keys {
+    // Tables
+    pub const KV_BLOB: &str = "kv_blob";
+    pub const ROOM_INFO: &str = "room_info";
+    pub const STATE_EVENT: &str = "state_event";
+    pub const STATE_EVENT_ID: &str = "state_event_id";
+    pub const GLOBAL_ACCOUNT_DATA: &str = "global_account_data";
+    pub const ROOM_ACCOUNT_DATA: &str = "room_account_data";
+    pub const MEMBER: &str = "member";
+    pub const PROFILE: &str = "profile";
+    pub const RECEIPT: &str = "receipt";
+    pub const EVENT_RECEIPT: &str = "event_receipt";
+    pub const DISPLAY_NAME: &str = "display_name";
+    pub const SEND_QUEUE: &str = "send_queue_events";
+    pub const DEPENDENTS_SEND_QUEUE: &str = "dependent_send_queue_events";
+    pub const CUSTOM_VALUE_KEY: &str = "custom_value_key";
+}
+
+mod tables {
+    use super::{keys, Table, TableDefinition};
+
+    pub const KV_BLOB: Table = TableDefinition::new(keys::KV_BLOB);
+    pub const ROOM_INFO: Table = TableDefinition::new(keys::ROOM_INFO);
+    /// The state events, keyed by room ID, event type and state key.
+    ///
+    /// The first byte of the values is 1 if the event is stripped, 0
+    /// otherwise, so the stripped events of a room can be removed without
+    /// decrypting them.
+    pub const STATE_EVENT: Table = TableDefinition::new(keys::STATE_EVENT);
+    /// An index of the state events by room ID and event ID, pointing to the
+    /// keys of [`STATE_EVENT`].
+    pub const STATE_EVENT_ID: Table = TableDefinition::new(keys::STATE_EVENT_ID);
+    pub const GLOBAL_ACCOUNT_DATA: Table = TableDefinition::new(keys::GLOBAL_ACCOUNT_DATA);
+    pub const ROOM_ACCOUNT_DATA: Table = TableDefinition::new(keys::ROOM_ACCOUNT_DATA);
+    /// The members of the rooms, keyed by room ID and user ID.
+    ///
+    /// Like for [`STATE_EVENT`], the first byte of the values is the stripped
+    /// flag.
+    pub const MEMBER: Table = TableDefinition::new(keys::MEMBER);
+    pub const PROFILE: Table = TableDefinition::new(keys::PROFILE);
+    /// The receipts, keyed by room ID, receipt type, thread and user ID.
+    pub const RECEIPT: Table = TableDefinition::new(keys::RECEIPT);
+    /// The same receipts as [`RECEIPT`], keyed by room ID, receipt type,
+    /// thread, event ID and user ID.
+    pub const EVENT_RECEIPT: Table = TableDefinition::new(keys::EVENT_RECEIPT);
+    pub const DISPLAY_NAME: Table = TableDefinition::new(keys::DISPLAY_NAME);
+    pub const SEND_QUEUE: Table = TableDefinition::new(keys::SEND_QUEUE);
+    pub const DEPENDENTS_SEND_QUEUE: Table = TableDefinition::new(keys::DEPENDENTS_SEND_QUEUE);
+    /// The keys of the custom values, keyed like the custom values in
+    /// [`KV_BLOB`], so they can be listed.
+    pub const CUSTOM_VALUE_KEY: Table = TableDefinition::new(keys::CUSTOM_VALUE_KEY);
+
+    pub const ALL: &[Table] = &[
+        KV_BLOB,
+        ROOM_INFO,
+        STATE_EVENT,
+        STATE_EVENT_ID,
+        GLOBAL_ACCOUNT_DATA,
+        ROOM_ACCOUNT_DATA,
+        MEMBER,
+        PROFILE,
+        RECEIPT,
+        EVENT_RECEIPT,
+        DISPLAY_NAME,
+        SEND_QUEUE,
+        DEPENDENTS_SEND_QUEUE,
+        CUSTOM_VALUE_KEY,
+    ];
+}
+
+/// The filename used for the redb database file used by the state store.
+pub const DATABASE_NAME: &str = "matrix-sdk-state.redb";
+
+/// The key of the counter used to keep the insertion order of the send queue
+/// requests, in the `kv_blob` table.
+const QUEUE_SEQUENCE: &str = "queue_sequence";
+
+/// A redb-based state store.
+#[derive(Clone)]
+pub struct RedbStateStore {
+    store_cipher: Option<Arc<StoreCipher>>,
+    db: RedbDatabase,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for RedbStateStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedbStateStore").finish_non_exhaustive()
+    }
+}
+
+impl RedbStateStore {
+    /// Open the redb-based state store at the given path using the given
+    /// passphrase to encrypt private data.
+    pub async fn open(
+        path: impl AsRef<Path>,
+        passphrase: Option<&str>,
+    ) -> Result<Self, OpenStoreError> {
+        let db = RedbDatabase::open(path.as_ref(), DATABASE_NAME, tables::ALL).await?;
+
+        let store_cipher = match passphrase {
+            Some(p) => Some(Arc::new(db.get_or_create_store_cipher(p).await?)),
+            None => None,
+        };
+
+        Ok(Self { store_cipher, db })
+    }
+
+    fn encode_state_store_data_key(&self, key: StateStoreDataKey<'_>) -> Vec<u8> {
+        let key_s = match key {
+            StateStoreDataKey::SyncToken => Cow::Borrowed(StateStoreDataKey::SYNC_TOKEN),
+            StateStoreDataKey::ServerInfo => Cow::Borrowed(StateStoreDataKey::SERVER_INFO),
+            StateStoreDataKey::Filter(f) => {
+                Cow::Owned(format!("{}:{f}", StateStoreDataKey::FILTER))
+            }
+            StateStoreDataKey::UserAvatarUrl(u) => {
+                Cow::Owned(format!("{}:{u}", StateStoreDataKey::USER_AVATAR_URL))
+            }
+            StateStoreDataKey::RecentlyVisitedRooms(b) => {
+                Cow::Owned(format!("{}:{b}", StateStoreDataKey::RECENTLY_VISITED_ROOMS))
+            }
+            StateStoreDataKey::UtdHookManagerData => {
+                Cow::Borrowed(StateStoreDataKey::UTD_HOOK_MANAGER_DATA)
+            }
+            StateStoreDataKey::ComposerDraft(room_id, thread_root) => {
+                if let Some(thread_root) = thread_root {
+                    Cow::Owned(format!(
+                        "{}:{room_id}:{thread_root}",
+                        StateStoreDataKey::COMPOSER_DRAFT
+                    ))
+                } else {
+                    Cow::Owned(format!("{}:{room_id}", StateStoreDataKey::COMPOSER_DRAFT))
+                }
+            }
+            StateStoreDataKey::SeenKnockRequests(room_id) => {
+                Cow::Owned(format!("{}:{room_id}", StateStoreDataKey::SEEN_KNOCK_REQUESTS))
+            }
+        };
+
+        self.encode_key(keys::KV_BLOB, &*key_s)
+    }
+
+    fn encode_presence_key(&self, user_id: &UserId) -> Vec<u8> {
+        self.encode_key(keys::KV_BLOB, format!("presence:{user_id}"))
+    }
+
+    fn encode_custom_key(&self, key: &[u8]) -> Vec<u8> {
+        let mut full_key = b"custom:".to_vec();
+        full_key.extend(key);
+        self.encode_key(keys::KV_BLOB, full_key)
+    }
+
+    /// The key of all the entries of the given room in the given table.
+    fn room_key(&self, table_name: &str, room_id: &RoomId) -> Vec<u8> {
+        key([self.encode_key(table_name, room_id).as_slice()])
+    }
+
+    fn state_event_key(&self, room_id: &RoomId, event_type: &str, state_key: &str) -> Vec<u8> {
+        key([
+            self.encode_key(keys::STATE_EVENT, room_id).as_slice(),
+            &self.encode_key(keys::STATE_EVENT, event_type),
+            &self.encode_key(keys::STATE_EVENT, state_key),
+        ])
+    }
+
+    fn state_event_id_key(&self, room_id: &RoomId, event_id: &str) -> Vec<u8> {
+        key([
+            self.encode_key(keys::STATE_EVENT, room_id).as_slice(),
+            &self.encode_key(keys::STATE_EVENT, event_id),
+        ])
+    }
+
+    fn room_user_key(&self, table_name: &str, room_id: &RoomId, user_id: &str) -> Vec<u8> {
+        key([
+            self.encode_key(table_name, room_id).as_slice(),
+            &self.encode_key(table_name, user_id),
+        ])
+    }
+
+    /// The key of a receipt of a room with the given type and thread, followed
+    /// by the given parts.
+    fn receipt_key(
+        &self,
+        room_id: &RoomId,
+        receipt_type: &str,
+        thread: &ReceiptThread,
+        parts: &[&[u8]],
+    ) -> Result<Vec<u8>> {
+        let room_id = self.encode_key(keys::RECEIPT, room_id);
+        let receipt_type = self.encode_key(keys::RECEIPT, receipt_type);
+        // We rely on serialization instead of the string representation, to distinguish
+        // the unthreaded receipts.
+        let thread = self.encode_key(keys::RECEIPT, rmp_serde::to_vec_named(thread)?);
+
+        Ok(key([room_id.as_slice(), &receipt_type, &thread]
+            .into_iter()
+            .chain(parts.iter().copied())))
+    }
+
+    fn send_queue_key(&self, table_name: &str, room_id: &RoomId, transaction_id: &str) -> Vec<u8> {
+        // The transaction id carries no personal information, so it's kept as is, like
+        // in the other stores.
+        key([self.encode_key(table_name, room_id).as_slice(), transaction_id.as_bytes()])
+    }
+
+    /// Remove the stripped or non-stripped state events and members of the
+    /// given room.
+    fn remove_maybe_stripped_room_data(
+        &self,
+        txn: &redb::WriteTransaction,
+        room_id: &RoomId,
+        stripped: bool,
+    ) -> Result<()> {
+        for (table, table_name) in
+            [(tables::STATE_EVENT, keys::STATE_EVENT), (tables::MEMBER, keys::MEMBER)]
+        {
+            let mut table = txn.open_table(table)?;
+
+            for (key, value) in get_prefixed(&table, &self.room_key(table_name, room_id))? {
+                if split_stripped_flag(&value)?.0 == stripped {
+                    table.remove(key.as_slice())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn deserialize_state_event(&self, value: &[u8]) -> Result<RawAnySyncOrStrippedState> {
+        let (stripped, data) = split_stripped_flag(value)?;
+
+        Ok(if stripped {
+            RawAnySyncOrStrippedState::Stripped(self.deserialize_json(data)?)
+        } else {
+            RawAnySyncOrStrippedState::Sync(self.deserialize_json(data)?)
+        })
+    }
+
+    /// Get the next value of the counter keeping the insertion order of the
+    /// send queue requests.
+    fn next_queue_sequence(&self, txn: &redb::WriteTransaction) -> Result<u64> {
+        let mut kv_blob = txn.open_table(tables::KV_BLOB)?;
+        let key = self.encode_key(keys::KV_BLOB, QUEUE_SEQUENCE);
+
+        let sequence = match get(&kv_blob, &key)? {
+            Some(bytes) => u64::from_be_bytes(bytes.try_into().map_err(|_| {
+                Error::InvalidData { details: "invalid send queue sequence".to_owned() }
+            })?),
+            None => 0,
+        };
+        kv_blob.insert(key.as_slice(), (sequence + 1).to_be_bytes().as_slice())?;
+
+        Ok(sequence)
+    }
+}
+
+impl EncryptableStore for RedbStateStore {
+    fn get_cypher(&self) -> Option<&StoreCipher> {
+        self.store_cipher.as_deref()
+    }
+}
+
+/// Prefix the given data with the stripped flag.
+fn with_stripped_flag(stripped: bool, data: Vec<u8>) -> Vec<u8> {
+    let mut value = Vec::with_capacity(data.len() + 1);
+    value.push(stripped.into());
+    value.extend(data);
+    value
+}
+
+/// Split the stripped flag from the rest of the given value.
+fn split_stripped_flag(value: &[u8]) -> Result<(bool, &[u8])> {
+    match value.split_first() {
+        Some((0, data)) => Ok((false, data)),
+        Some((1, data)) => Ok((true, data)),
+        _ => Err(Error::InvalidData { details: "invalid stripped flag".to_owned() }),
+    }
+}
+
+#[async_trait]
+impl StateStore for RedbStateStore {
+    type Error = Error;
+
+    async fn get_kv_data(&self, key: StateStoreDataKey<'_>) -> Result<Option<StateStoreDataValue>> {
+        let encoded_key = self.encode_state_store_data_key(key);
+
+        self.db
+            .read(move |txn| get(&txn.open_table(tables::KV_BLOB)?, &encoded_key))
+            .await?
+            .map(|data| {
+                Ok(match key {
+                    StateStoreDataKey::SyncToken => {
+                        StateStoreDataValue::SyncToken(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::ServerInfo => {
+                        StateStoreDataValue::ServerInfo(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::Filter(_) => {
+                        StateStoreDataValue::Filter(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::UserAvatarUrl(_) => {
+                        StateStoreDataValue::UserAvatarUrl(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::RecentlyVisitedRooms(_) => {
+                        StateStoreDataValue::RecentlyVisitedRooms(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::UtdHookManagerData => {
+                        StateStoreDataValue::UtdHookManagerData(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::ComposerDraft(_, _) => {
+                        StateStoreDataValue::ComposerDraft(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::SeenKnockRequests(_) => {
+                        StateStoreDataValue::SeenKnockRequests(self.deserialize_value(&data)?)
+                    }
+                })
+            })
+            .transpose()
+    }
+
+    async fn set_kv_data(
+        &self,
+        key: StateStoreDataKey<'_>,
+        value: StateStoreDataValue,
+    ) -> Result<()> {
+        let serialized_value = match key {
+            StateStoreDataKey::SyncToken => self.serialize_value(
+                &value.into_sync_token().expect("Session data not a sync token"),
+            )?,
+            StateStoreDataKey::ServerInfo => self.serialize_value(
+                &value.into_server_info().expect("Session data not containing server info"),
+            )?,
+            StateStoreDataKey::Filter(_) => {
+                self.serialize_value(&value.into_filter().expect("Session data not a filter"))?
+            }
+            StateStoreDataKey::UserAvatarUrl(_) => self.serialize_value(
+                &value.into_user_avatar_url().expect("Session data not an user avatar url"),
+            )?,
+            StateStoreDataKey::RecentlyVisitedRooms(_) => self.serialize_value(
+                &value.into_recently_visited_rooms().expect("Session data not breadcrumbs"),
+            )?,
+            StateStoreDataKey::UtdHookManagerData => self.serialize_value(
+                &value.into_utd_hook_manager_data().expect("Session data not UtdHookManagerData"),
+            )?,
+            StateStoreDataKey::ComposerDraft(_, _) => self.serialize_value(
+                &value.into_composer_draft().expect("Session data not a composer draft"),
+            )?,
+            StateStoreDataKey::SeenKnockRequests(_) => self.serialize_value(
+                &value
+                    .into_seen_knock_requests()
+                    .expect("Session data is not a set of seen knock request ids"),
+            )?,
+        };
+
+        let key = self.encode_state_store_data_key(key);
+        self.db
+            .write(move |txn| {
+                txn.open_table(tables::KV_BLOB)?
+                    .insert(key.as_slice(), serialized_value.as_slice())?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn remove_kv_data(&self, key: StateStoreDataKey<'_>) -> Result<()> {
+        let key = self.encode_state_store_data_key(key);
+        self.db
+            .write(move |txn| {
+                txn.open_table(tables::KV_BLOB)?.remove(key.as_slice())?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn save_changes(&self, changes: &StateChanges) -> Result<()> {
+        let changes = changes.to_owned();
+        let this = self.clone();
+
+        self.db
+            .write(move |txn| {
+                let StateChanges {
+                    sync_token,
+                    account_data,
+                    presence,
+                    profiles,
+                    profiles_to_delete,
+                    state,
+                    room_account_data,
+                    room_infos,
+                    receipts,
+                    redactions,
+                    stripped_state,
+                    ambiguity_maps,
+                } = changes;
+
+                {
+                    let mut kv_blob = txn.open_table(tables::KV_BLOB)?;
+
+                    if let Some(sync_token) = sync_token {
+                        let key = this.encode_state_store_data_key(StateStoreDataKey::SyncToken);
+                        let value = this.serialize_value(&sync_token)?;
+                        kv_blob.insert(key.as_slice(), value.as_slice())?;
+                    }
+
+                    for (user_id, event) in presence {
+                        let key = this.encode_presence_key(&user_id);
+                        let value = this.serialize_json(&event)?;
+                        kv_blob.insert(key.as_slice(), value.as_slice())?;
+                    }
+                }
+
+                {
+                    let mut table = txn.open_table(tables::GLOBAL_ACCOUNT_DATA)?;
+
+                    for (event_type, event) in account_data {
+                        let key = key([this
+                            .encode_key(keys::GLOBAL_ACCOUNT_DATA, event_type.to_string())
+                            .as_slice()]);
+                        let data = this.serialize_json(&event)?;
+                        table.insert(key.as_slice(), data.as_slice())?;
+                    }
+                }
+
+                {
+                    let mut table = txn.open_table(tables::ROOM_ACCOUNT_DATA)?;
+
+                    for (room_id, events) in room_account_data {
+                        let room_id = this.encode_key(keys::ROOM_ACCOUNT_DATA, room_id);
+
+                        for (event_type, event) in events {
+                            let key = key([
+                                room_id.as_slice(),
+                                &this.encode_key(keys::ROOM_ACCOUNT_DATA, event_type.to_string()),
+                            ]);
+                            let data = this.serialize_json(&event)?;
+                            table.insert(key.as_slice(), data.as_slice())?;
+                        }
+                    }
+                }
+
+                for (room_id, room_info) in room_infos {
+                    let stripped = room_info.state() == RoomState::Invited;
+                    // Remove non-stripped data for stripped rooms and vice-versa.
+                    this.remove_maybe_stripped_room_data(txn, &room_id, !stripped)?;
+
+                    let key = this.room_key(keys::ROOM_INFO, &room_id);
+                    let data = this.serialize_json(&room_info)?;
+                    txn.open_table(tables::ROOM_INFO)?.insert(key.as_slice(), data.as_slice())?;
+                }
+
+                {
+                    let mut state_event_table = txn.open_table(tables::STATE_EVENT)?;
+                    let mut state_event_id_table = txn.open_table(tables::STATE_EVENT_ID)?;
+                    let mut member_table = txn.open_table(tables::MEMBER)?;
+                    let mut profile_table = txn.open_table(tables::PROFILE)?;
+
+                    for (room_id, user_ids) in profiles_to_delete {
+                        for user_id in user_ids {
+                            let key = this.room_user_key(keys::PROFILE, &room_id, user_id.as_str());
+                            profile_table.remove(key.as_slice())?;
+                        }
+                    }
+
+                    for (room_id, state_event_types) in state {
+                        let profiles = profiles.get(&room_id);
+
+                        for (event_type, state_events) in state_event_types {
+                            let encoded_event_type = event_type.to_string();
+
+                            for (state_key, raw_state_event) in state_events {
+                                let key =
+                                    this.state_event_key(&room_id, &encoded_event_type, &state_key);
+                                let data = this.serialize_json(&raw_state_event)?;
+                                state_event_table.insert(
+                                    key.as_slice(),
+                                    with_stripped_flag(false, data).as_slice(),
+                                )?;
+
+                                let event_id: Option<String> =
+                                    raw_state_event.get_field("event_id").ok().flatten();
+
+                                if let Some(event_id) = &event_id {
+                                    let id_key = this.state_event_id_key(&room_id, event_id);
+                                    state_event_id_table
+                                        .insert(id_key.as_slice(), key.as_slice())?;
+                                }
+
+                                if event_type == StateEventType::RoomMember {
+                                    let member_event = match raw_state_event
+                                        .deserialize_as::<SyncRoomMemberEvent>()
+                                    {
+                                        Ok(ev) => ev,
+                                        Err(e) => {
+                                            debug!(
+                                                event_id,
+                                                "Failed to deserialize member event: {e}"
+                                            );
+                                            continue;
+                                        }
+                                    };
+
+                                    let key = this.room_user_key(keys::MEMBER, &room_id, &state_key);
+                                    let data = this.serialize_value(&MemberData {
+                                        user_id: member_event.state_key().to_owned(),
+                                        membership: member_event.membership().clone(),
+                                    })?;
+                                    member_table.insert(
+                                        key.as_slice(),
+                                        with_stripped_flag(false, data).as_slice(),
+                                    )?;
+
+                                    if let Some(profile) =
+                                        profiles.and_then(|p| p.get(member_event.state_key()))
+                                    {
+                                        let key =
+                                            this.room_user_key(keys::PROFILE, &room_id, &state_key);
+                                        let data = this.serialize_json(&profile)?;
+                                        profile_table.insert(key.as_slice(), data.as_slice())?;
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    for (room_id, stripped_state_event_types) in stripped_state {
+                        for (event_type, stripped_state_events) in stripped_state_event_types {
+                            let encoded_event_type = event_type.to_string();
+
+                            for (state_key, raw_stripped_state_event) in stripped_state_events {
+                                let key =
+                                    this.state_event_key(&room_id, &encoded_event_type, &state_key);
+                                let data = this.serialize_json(&raw_stripped_state_event)?;
+                                state_event_table.insert(
+                                    key.as_slice(),
+                                    with_stripped_flag(true, data).as_slice(),
+                                )?;
+
+                                if event_type == StateEventType::RoomMember {
+                                    let member_event = match raw_stripped_state_event
+                                        .deserialize_as::<StrippedRoomMemberEvent>(
+                                    ) {
+                                        Ok(ev) => ev,
+                                        Err(e) => {
+                                            debug!(
+                                                "Failed to deserialize stripped member event: {e}"
+                                            );
+                                            continue;
+                                        }
+                                    };
+
+                                    let key = this.room_user_key(keys::MEMBER, &room_id, &state_key);
+                                    let data = this.serialize_value(&MemberData {
+                                        user_id: member_event.state_key,
+                                        membership: member_event.content.membership,
+                                    })?;
+                                    member_table.insert(
+                                        key.as_slice(),
+                                        with_stripped_flag(true, data).as_slice(),
+                                    )?;
+                                }
+                            }
+                        }
+                    }
+                }
+
+                {
+                    let mut receipt_table = txn.open_table(tables::RECEIPT)?;
+                    let mut event_receipt_table = txn.open_table(tables::EVENT_RECEIPT)?;
+
+                    for (room_id, receipt_event) in receipts {
+                        for (event_id, receipt_types) in receipt_event {
+                            for (receipt_type, receipt_users) in receipt_types {
+                                for (user_id, receipt) in receipt_users {
+                                    let receipt_type = receipt_type.as_str();
+                                    let thread = &receipt.thread;
+                                    let encoded_user_id = this.encode_key(keys::RECEIPT, &user_id);
+                                    let user_key = this.receipt_key(
+                                        &room_id,
+                                        receipt_type,
+                                        thread,
+                                        &[&encoded_user_id],
+                                    )?;
+
+                                    // Remove the previous receipt of the user from the index by
+                                    // event.
+                                    if let Some(previous) = get(&receipt_table, &user_key)? {
+                                        let previous: ReceiptData =
+                                            this.deserialize_json(&previous)?;
+                                        let previous_key = this.receipt_key(
+                                            &room_id,
+                                            receipt_type,
+                                            thread,
+                                            &[
+                                                &this.encode_key(keys::RECEIPT, &previous.event_id),
+                                                &encoded_user_id,
+                                            ],
+                                        )?;
+                                        event_receipt_table.remove(previous_key.as_slice())?;
+                                    }
+
+                                    let event_key = this.receipt_key(
+                                        &room_id,
+                                        receipt_type,
+                                        thread,
+                                        &[&this.encode_key(keys::RECEIPT, &event_id), &encoded_user_id],
+                                    )?;
+                                    let data = this.serialize_json(&ReceiptData {
+                                        receipt,
+                                        event_id: event_id.clone(),
+                                        user_id,
+                                    })?;
+
+                                    receipt_table.insert(user_key.as_slice(), data.as_slice())?;
+                                    event_receipt_table
+                                        .insert(event_key.as_slice(), data.as_slice())?;
+                                }
+                            }
+                        }
+                    }
+                }
+
+                for (room_id, redactions) in redactions {
+                    let make_room_version = || {
+                        txn.open_table(tables::ROOM_INFO)
+                            .ok()
+                            .and_then(|table| {
+                                get(&table, &this.room_key(keys::ROOM_INFO, &room_id)).ok()
+                            })
+                            .flatten()
+                            .and_then(|v| this.deserialize_json::<RoomInfo>(&v).ok())
+                            .map(|info| info.room_version_or_default())
+                            .unwrap_or_else(|| {
+                                warn!(
+                                    ?room_id,
+                                    "Unable to find the room version, assuming {ROOM_VERSION_FALLBACK}"
+                                );
+                                ROOM_VERSION_FALLBACK
+                            })
+                    };
+
+                    let mut room_version = None;
+
+                    for (event_id, redaction) in redactions {
+                        let Some(key) = get(
+                            &txn.open_table(tables::STATE_EVENT_ID)?,
+                            &this.state_event_id_key(&room_id, event_id.as_str()),
+                        )?
+                        else {
+                            continue;
+                        };
+
+                        let Some(value) = get(&txn.open_table(tables::STATE_EVENT)?, &key)? else {
+                            continue;
+                        };
+                        let (stripped, data) = split_stripped_flag(&value)?;
+                        if stripped {
+                            continue;
+                        }
+
+                        let Ok(raw_event) = this.deserialize_json::<Raw<AnySyncStateEvent>>(data)
+                        else {
+                            continue;
+                        };
+
+                        // The index may point to a state event that was replaced since then.
+                        if raw_event.get_field::<OwnedEventId>("event_id").ok().flatten().as_ref()
+                            != Some(&event_id)
+                        {
+                            continue;
+                        }
+
+                        let redacted = redact(
+                            raw_event.deserialize_as::<CanonicalJsonObject>()?,
+                            room_version.get_or_insert_with(make_room_version),
+                            Some(RedactedBecause::from_raw_event(&redaction)?),
+                        )
+                        .map_err(Error::Redaction)?;
+                        let data = this.serialize_json(&redacted)?;
+
+                        txn.open_table(tables::STATE_EVENT)?
+                            .insert(key.as_slice(), with_stripped_flag(false, data).as_slice())?;
+                    }
+                }
+
+                {
+                    let mut table = txn.open_table(tables::DISPLAY_NAME)?;
+
+                    for (room_id, display_names) in ambiguity_maps {
+                        let room_id = this.encode_key(keys::DISPLAY_NAME, room_id);
+
+                        for (name, user_ids) in display_names {
+                            let encoded_name = this.encode_key(
+                                keys::DISPLAY_NAME,
+                                name.as_normalized_str().unwrap_or_else(|| name.as_raw_str()),
+                            );
+                            let name_key = key([room_id.as_slice(), &encoded_name]);
+
+                            if user_ids.is_empty() {
+                                table.remove(name_key.as_slice())?;
+
+                                // Like in the other stores, the raw display names may have been
+                                // used as keys before the normalized ones, so remove both.
+                                let raw_key = key([
+                                    room_id.as_slice(),
+                                    &this.encode_key(keys::DISPLAY_NAME, name.as_raw_str()),
+                                ]);
+                                table.remove(raw_key.as_slice())?;
+                            } else {
+                                let data = this.serialize_json(&user_ids)?;
+                                table.insert(name_key.as_slice(), data.as_slice())?;
+                            }
+                        }
+                    }
+                }
+
+                Ok(())
+            })
+            .await
+    }
+
+    async fn get_presence_event(&self, user_id: &UserId) -> Result<Option<Raw<PresenceEvent>>> {
+        let key = self.encode_presence_key(user_id);
+
+        self.db
+            .read(move |txn| get(&txn.open_table(tables::KV_BLOB)?, &key))
+            .await?
+            .map(|data| self.deserialize_json(&data))
+            .transpose()
+    }
+
+    async fn get_presence_events(
+        &self,
+        user_ids: &[OwnedUserId],
+    ) -> Result<Vec<Raw<PresenceEvent>>> {
+        if user_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let keys: Vec<_> = user_ids.iter().map(|u| self.encode_presence_key(u)).collect();
+
+        self.db
+            .read(move |txn| {
+                let table = txn.open_table(tables::KV_BLOB)?;
+                keys.iter()
+                    .filter_map(|key| get(&table, key).transpose())
+                    .collect::<Result<Vec<_>>>()
+            })
+            .await?
+            .iter()
+            .map(|data| self.deserialize_json(data))
+            .collect()
+    }
+
+    async fn get_state_event(
+        &self,
+        room_id: &RoomId,
+        event_type: StateEventType,
+        state_key: &str,
+    ) -> Result<Option<RawAnySyncOrStrippedState>> {
+        Ok(self
+            .get_state_events_for_keys(room_id, event_type, &[state_key])
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    async fn get_state_events(
+        &self,
+        room_id: &RoomId,
+        event_type: StateEventType,
+    ) -> Result<Vec<RawAnySyncOrStrippedState>> {
+        let prefix = key([
+            self.encode_key(keys::STATE_EVENT, room_id).as_slice(),
+            &self.encode_key(keys::STATE_EVENT, event_type.to_string()),
+        ]);
+
+        self.db
+            .read(move |txn| get_prefixed(&txn.open_table(tables::STATE_EVENT)?, &prefix))
+            .await?
+            .iter()
+            .map(|(_, value)| self.deserialize_state_event(value))
+            .collect()
+    }
+
+    async fn get_all_state_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<RawAnySyncOrStrippedState>> {
+        let prefix = self.room_key(keys::STATE_EVENT, room_id);
+
+        self.db
+            .read(move |txn| get_prefixed(&txn.open_table(tables::STATE_EVENT)?, &prefix))
+            .await?
+            .iter()
+            .map(|(_, value)| self.deserialize_state_event(value))
+            .collect()
+    }
+
+    async fn get_state_events_for_keys(
+        &self,
+        room_id: &RoomId,
+        event_type: StateEventType,
+        state_keys: &[&str],
+    ) -> Result<Vec<RawAnySyncOrStrippedState>, Self::Error> {
+        if state_keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let event_type = event_type.to_string();
+        let keys: Vec<_> =
+            state_keys.iter().map(|k| self.state_event_key(room_id, &event_type, k)).collect();
+
+        self.db
+            .read(move |txn| {
+                let table = txn.open_table(tables::STATE_EVENT)?;
+                keys.iter()
+                    .filter_map(|key| get(&table, key).transpose())
+                    .collect::<Result<Vec<_>>>()
+            })
+            .await?
+            .iter()
+            .map(|value| self.deserialize_state_event(value))
+            .collect()
+    }
+
+    async fn get_profile(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+    ) -> Result<Option<MinimalRoomMemberEvent>> {
+        let key = self.room_user_key(keys::PROFILE, room_id, user_id.as_str());
+
+        self.db
+            .read(move |txn| get(&txn.open_table(tables::PROFILE)?, &key))
+            .await?
+            .map(|data| self.deserialize_json(&data))
+            .transpose()
+    }
+
+    async fn get_profiles<'a>(
+        &self,
+        room_id: &RoomId,
+        user_ids: &'a [OwnedUserId],
+    ) -> Result<BTreeMap<&'a UserId, MinimalRoomMemberEvent>> {
+        if user_ids.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+
+        let keys: Vec<_> = user_ids
+            .iter()
+            .map(|user_id| self.room_user_key(keys::PROFILE, room_id, user_id.as_str()))
+            .collect();
+
+        let values = self
+            .db
+            .read(move |txn| {
+                let table = txn.open_table(tables::PROFILE)?;
+                keys.iter().map(|key| get(&table, key)).collect::<Result<Vec<_>>>()
+            })
+            .await?;
+
+        user_ids
+            .iter()
+            .zip(values)
+            .filter_map(|(user_id, data)| {
+                data.map(|data| Ok((&**user_id, self.deserialize_json(&data)?)))
+            })
+            .collect()
+    }
+
+    async fn get_user_ids(
+        &self,
+        room_id: &RoomId,
+        memberships: RoomMemberships,
+    ) -> Result<Vec<OwnedUserId>> {
+        let prefix = self.room_key(keys::MEMBER, room_id);
+
+        let mut user_ids = Vec::new();
+
+        for (_, value) in
+            self.db.read(move |txn| get_prefixed(&txn.open_table(tables::MEMBER)?, &prefix)).await?
+        {
+            let member: MemberData = self.deserialize_value(split_stripped_flag(&value)?.1)?;
+
+            if memberships.matches(&member.membership) {
+                user_ids.push(member.user_id);
+            }
+        }
+
+        Ok(user_ids)
+    }
+
+    async fn get_room_infos(&self, room_load_settings: &RoomLoadSettings) -> Result<Vec<RoomInfo>> {
+        let room_key = match room_load_settings {
+            RoomLoadSettings::All => None,
+            RoomLoadSettings::One(room_id) => Some(self.room_key(keys::ROOM_INFO, room_id)),
+        };
+
+        self.db
+            .read(move |txn| {
+                let table = txn.open_table(tables::ROOM_INFO)?;
+
+                match room_key {
+                    Some(key) => Ok(get(&table, &key)?.into_iter().collect()),
+                    None => table
+                        .iter()?
+                        .map(|entry| Ok(entry?.1.value().to_vec()))
+                        .collect::<Result<Vec<_>>>(),
+                }
+            })
+            .await?
+            .iter()
+            .map(|data| self.deserialize_json(data))
+            .collect()
+    }
+
+    async fn get_users_with_display_name(
+        &self,
+        room_id: &RoomId,
+        display_name: &DisplayName,
+    ) -> Result<BTreeSet<OwnedUserId>> {
+        let key = key([
+            self.encode_key(keys::DISPLAY_NAME, room_id).as_slice(),
+            &self.encode_key(
+                keys::DISPLAY_NAME,
+                display_name.as_normalized_str().unwrap_or_else(|| display_name.as_raw_str()),
+            ),
+        ]);
+
+        Ok(self
+            .db
+            .read(move |txn| get(&txn.open_table(tables::DISPLAY_NAME)?, &key))
+            .await?
+            .map(|data| self.deserialize_json(&data))
+            .transpose()?
+            .unwrap_or_default())
+    }
+
+    async fn get_users_with_display_names<'a>(
+        &self,
+        room_id: &RoomId,
+        display_names: &'a [DisplayName],
+    ) -> Result<HashMap<&'a DisplayName, BTreeSet<OwnedUserId>>> {
+        let mut result = HashMap::new();
+
+        if display_names.is_empty() {
+            return Ok(result);
+        }
+
+        let room_id = self.encode_key(keys::DISPLAY_NAME, room_id);
+
+        // Like in the other stores, the users are looked up with both the raw and the
+        // normalized display name, and the results are merged.
+        let mut names = Vec::new();
+        for display_name in display_names {
+            let raw = display_name.as_raw_str();
+            names.push((display_name, raw));
+
+            if let Some(normalized) = display_name.as_normalized_str() {
+                if normalized != raw {
+                    names.push((display_name, normalized));
+                }
+            }
+        }
+
+        let keys: Vec<_> = names
+            .iter()
+            .map(|(_, name)| key([room_id.as_slice(), &self.encode_key(keys::DISPLAY_NAME, name)]))
+            .collect();
+
+        let values = self
+            .db
+            .read(move |txn| {
+                let table = txn.open_table(tables::DISPLAY_NAME)?;
+                keys.iter().map(|key| get(&table, key)).collect::<Result<Vec<_>>>()
+            })
+            .await?;
+
+        for ((display_name, _), data) in names.into_iter().zip(values) {
+            if let Some(data) = data {
+                let user_ids: BTreeSet<_> = self.deserialize_json(&data)?;
+                result.entry(display_name).or_insert_with(BTreeSet::new).extend(user_ids);
+            }
+        }
+
+        Ok(result)
+    }
+
+    async fn get_account_data_event(
+        &self,
+        event_type: GlobalAccountDataEventType,
+    ) -> Result<Option<Raw<AnyGlobalAccountDataEvent>>> {
+        let key =
+            key([self.encode_key(keys::GLOBAL_ACCOUNT_DATA, event_type.to_string()).as_slice()]);
+
+        self.db
+            .read(move |txn| get(&txn.open_table(tables::GLOBAL_ACCOUNT_DATA)?, &key))
+            .await?
+            .map(|value| self.deserialize_json(&value))
+            .transpose()
+    }
+
+    async fn get_room_account_data_event(
+        &self,
+        room_id: &RoomId,
+        event_type: RoomAccountDataEventType,
+    ) -> Result<Option<Raw<AnyRoomAccountDataEvent>>> {
+        let key = key([
+            self.encode_key(keys::ROOM_ACCOUNT_DATA, room_id).as_slice(),
+            &self.encode_key(keys::ROOM_ACCOUNT_DATA, event_type.to_string()),
+        ]);
+
+        self.db
+            .read(move |txn| get(&txn.open_table(tables::ROOM_ACCOUNT_DATA)?, &key))
+            .await?
+            .map(|value| self.deserialize_json(&value))
+            .transpose()
+    }
+
+    async fn get_all_account_data_events(&self) -> Result<Vec<Raw<AnyGlobalAccountDataEvent>>> {
+        self.db
+            .read(move |txn| get_prefixed(&txn.open_table(tables::GLOBAL_ACCOUNT_DATA)?, &[]))
+            .await?
+            .iter()
+            .map(|(_, value)| self.deserialize_json(value))
+            .collect()
+    }
+
+    async fn get_all_room_account_data_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<Raw<AnyRoomAccountDataEvent>>> {
+        let prefix = self.room_key(keys::ROOM_ACCOUNT_DATA, room_id);
+
+        self.db
+            .read(move |txn| get_prefixed(&txn.open_table(tables::ROOM_ACCOUNT_DATA)?, &prefix))
+            .await?
+            .iter()
+            .map(|(_, value)| self.deserialize_json(value))
+            .collect()
+    }
+
+    async fn get_user_room_receipt_event(
+        &self,
+        room_id: &RoomId,
+        receipt_type: ReceiptType,
+        thread: ReceiptThread,
+        user_id: &UserId,
+    ) -> Result<Option<(OwnedEventId, Receipt)>> {
+        let key = self.receipt_key(
+            room_id,
+            receipt_type.as_ref(),
+            &thread,
+            &[&self.encode_key(keys::RECEIPT, user_id)],
+        )?;
+
+        self.db
+            .read(move |txn| get(&txn.open_table(tables::RECEIPT)?, &key))
+            .await?
+            .map(|value| {
+                self.deserialize_json::<ReceiptData>(&value).map(|d| (d.event_id, d.receipt))
+            })
+            .transpose()
+    }
+
+    async fn get_event_room_receipt_events(
+        &self,
+        room_id: &RoomId,
+        receipt_type: ReceiptType,
+        thread: ReceiptThread,
+        event_id: &EventId,
+    ) -> Result<Vec<(OwnedUserId, Receipt)>> {
+        let prefix = self.receipt_key(
+            room_id,
+            receipt_type.as_ref(),
+            &thread,
+            &[&self.encode_key(keys::RECEIPT, event_id)],
+        )?;
+
+        self.db
+            .read(move |txn| get_prefixed(&txn.open_table(tables::EVENT_RECEIPT)?, &prefix))
+            .await?
+            .iter()
+            .map(|(_, value)| {
+                self.deserialize_json::<ReceiptData>(value).map(|d| (d.user_id, d.receipt))
+            })
+            .collect()
+    }
+
+    async fn get_custom_value(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let key = self.encode_custom_key(key);
+        self.db.read(move |txn| get(&txn.open_table(tables::KV_BLOB)?, &key)).await
+    }
+
+    async fn set_custom_value_no_read(&self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.set_custom_value(key, value).await.map(|_| ())
+    }
+
+    async fn set_custom_value(&self, key: &[u8], value: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        let name = self.encode_value(key.to_owned())?;
+        let key = self.encode_custom_key(key);
+        self.db
+            .write(move |txn| {
+                txn.open_table(tables::CUSTOM_VALUE_KEY)?
+                    .insert(key.as_slice(), name.as_slice())?;
+                Ok(txn
+                    .open_table(tables::KV_BLOB)?
+                    .insert(key.as_slice(), value.as_slice())?
+                    .map(|previous| previous.value().to_vec()))
+            })
+            .await
+    }
+
+    async fn remove_custom_value(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let key = self.encode_custom_key(key);
+        self.db
+            .write(move |txn| {
+                txn.open_table(tables::CUSTOM_VALUE_KEY)?.remove(key.as_slice())?;
+                Ok(txn
+                    .open_table(tables::KV_BLOB)?
+                    .remove(key.as_slice())?
+                    .map(|previous| previous.value().to_vec()))
+            })
+            .await
+    }
+
+    async fn get_all_custom_values(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.db
+            .read(move |txn| {
+                let names = txn.open_table(tables::CUSTOM_VALUE_KEY)?;
+                let values = txn.open_table(tables::KV_BLOB)?;
+
+                get_prefixed(&names, &[])?
+                    .into_iter()
+                    .filter_map(|(key, name)| {
+                        get(&values, &key).map(|value| value.map(|value| (name, value))).transpose()
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .await?
+            .into_iter()
+            .map(|(name, value)| Ok((self.decode_value(&name)?.into_owned(), value)))
+            .collect()
+    }
+
+    async fn remove_room(&self, room_id: &RoomId) -> Result<()> {
+        let prefixes: Vec<_> = [
+            (tables::ROOM_INFO, keys::ROOM_INFO),
+            (tables::STATE_EVENT, keys::STATE_EVENT),
+            (tables::STATE_EVENT_ID, keys::STATE_EVENT),
+            (tables::MEMBER, keys::MEMBER),
+            (tables::PROFILE, keys::PROFILE),
+            (tables::ROOM_ACCOUNT_DATA, keys::ROOM_ACCOUNT_DATA),
+            (tables::RECEIPT, keys::RECEIPT),
+            (tables::EVENT_RECEIPT, keys::RECEIPT),
+            (tables::DISPLAY_NAME, keys::DISPLAY_NAME),
+            (tables::SEND_QUEUE, keys::SEND_QUEUE),
+            (tables::DEPENDENTS_SEND_QUEUE, keys::DEPENDENTS_SEND_QUEUE),
+        ]
+        .into_iter()
+        .map(|(table, table_name)| (table, self.room_key(table_name, room_id)))
+        .collect();
+
+        self.db
+            .write(move |txn| {
+                for (table, prefix) in prefixes {
+                    remove_prefixed(&mut txn.open_table(table)?, &prefix)?;
+                }
+                Ok(())
+            })
+            .await
+    }
+
+    async fn save_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: OwnedTransactionId,
+        created_at: MilliSecondsSinceUnixEpoch,
+        kind: QueuedRequestKind,
+        priority: usize,
+    ) -> Result<(), Self::Error> {
+        let key = self.send_queue_key(keys::SEND_QUEUE, room_id, transaction_id.as_str());
+        let room_id = room_id.to_owned();
+        let this = self.clone();
+
+        self.db
+            .write(move |txn| {
+                let entry = SendQueueEntry {
+                    sequence: this.next_queue_sequence(txn)?,
+                    room_id,
+                    transaction_id,
+                    kind,
+                    error: None,
+                    priority,
+                    created_at,
+                };
+                let data = this.serialize_json(&entry)?;
+                txn.open_table(tables::SEND_QUEUE)?.insert(key.as_slice(), data.as_slice())?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn update_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+        kind: QueuedRequestKind,
+    ) -> Result<bool, Self::Error> {
+        let key = self.send_queue_key(keys::SEND_QUEUE, room_id, transaction_id.as_str());
+        let this = self.clone();
+
+        self.db
+            .write(move |txn| {
+                let mut table = txn.open_table(tables::SEND_QUEUE)?;
+
+                let Some(data) = get(&table, &key)? else {
+                    return Ok(false);
+                };
+
+                let mut entry: SendQueueEntry = this.deserialize_json(&data)?;
+                entry.kind = kind;
+                entry.error = None;
+
+                let data = this.serialize_json(&entry)?;
+                table.insert(key.as_slice(), data.as_slice())?;
+
+                Ok(true)
+            })
+            .await
+    }
+
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool, Self::Error> {
+        let key = self.send_queue_key(keys::SEND_QUEUE, room_id, transaction_id.as_str());
+
+        self.db
+            .write(move |txn| {
+                Ok(txn.open_table(tables::SEND_QUEUE)?.remove(key.as_slice())?.is_some())
+            })
+            .await
+    }
+
+    async fn load_send_queue_requests(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<QueuedRequest>, Self::Error> {
+        let prefix = self.room_key(keys::SEND_QUEUE, room_id);
+
+        let mut entries = self
+            .db
+            .read(move |txn| get_prefixed(&txn.open_table(tables::SEND_QUEUE)?, &prefix))
+            .await?
+            .iter()
+            .map(|(_, data)| self.deserialize_json::<SendQueueEntry>(data))
+            .collect::<Result<Vec<_>>>()?;
+
+        entries.sort_by_key(|entry| (Reverse(entry.priority), entry.sequence));
+
+        Ok(entries
+            .into_iter()
+            .map(|entry| QueuedRequest {
+                transaction_id: entry.transaction_id,
+                kind: entry.kind,
+                error: entry.error,
+                priority: entry.priority,
+                created_at: entry.created_at,
+            })
+            .collect())
+    }
+
+    async fn update_send_queue_request_status(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+        error: Option<QueueWedgeError>,
+    ) -> Result<(), Self::Error> {
+        let key = self.send_queue_key(keys::SEND_QUEUE, room_id, transaction_id.as_str());
+        let this = self.clone();
+
+        self.db
+            .write(move |txn| {
+                let mut table = txn.open_table(tables::SEND_QUEUE)?;
+
+                if let Some(data) = get(&table, &key)? {
+                    let mut entry: SendQueueEntry = this.deserialize_json(&data)?;
+                    entry.error = error;
+
+                    let data = this.serialize_json(&entry)?;
+                    table.insert(key.as_slice(), data.as_slice())?;
+                }
+
+                Ok(())
+            })
+            .await
+    }
+
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>, Self::Error> {
+        // The room IDs are only hashed in the keys, so they have to be read from the
+        // values, and deduplicated.
+        Ok(self
+            .db
+            .read(|txn| get_prefixed(&txn.open_table(tables::SEND_QUEUE)?, &[]))
+            .await?
+            .iter()
+            .map(|(_, data)| {
+                self.deserialize_json::<SendQueueEntry>(data).map(|entry| entry.room_id)
+            })
+            .collect::<Result<BTreeSet<_>>>()?
+            .into_iter()
+            .collect())
+    }
+
+    async fn save_dependent_queued_request(
+        &self,
+        room_id: &RoomId,
+        parent_txn_id: &TransactionId,
+        own_txn_id: ChildTransactionId,
+        created_at: MilliSecondsSinceUnixEpoch,
+        content: DependentQueuedRequestKind,
+    ) -> Result<()> {
+        let key = self.send_queue_key(keys::DEPENDENTS_SEND_QUEUE, room_id, own_txn_id.as_str());
+        let parent_transaction_id = parent_txn_id.to_owned();
+        let this = self.clone();
+
+        self.db
+            .write(move |txn| {
+                let entry = DependentQueueEntry {
+                    sequence: this.next_queue_sequence(txn)?,
+                    request: DependentQueuedRequest {
+                        own_transaction_id: own_txn_id,
+                        kind: content,
+                        parent_transaction_id,
+                        parent_key: None,
+                        created_at,
+                    },
+                };
+                let data = this.serialize_json(&entry)?;
+                txn.open_table(tables::DEPENDENTS_SEND_QUEUE)?
+                    .insert(key.as_slice(), data.as_slice())?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn update_dependent_queued_request(
+        &self,
+        room_id: &RoomId,
+        own_transaction_id: &ChildTransactionId,
+        new_content: DependentQueuedRequestKind,
+    ) -> Result<bool> {
+        let key =
+            self.send_queue_key(keys::DEPENDENTS_SEND_QUEUE, room_id, own_transaction_id.as_str());
+        let this = self.clone();
+
+        self.db
+            .write(move |txn| {
+                let mut table = txn.open_table(tables::DEPENDENTS_SEND_QUEUE)?;
+
+                let Some(data) = get(&table, &key)? else {
+                    return Ok(false);
+                };
+
+                let mut entry: DependentQueueEntry = this.deserialize_json(&data)?;
+                entry.request.kind = new_content;
+
+                let data = this.serialize_json(&entry)?;
+                table.insert(key.as_slice(), data.as_slice())?;
+
+                Ok(true)
+            })
+            .await
+    }
+
+    async fn mark_dependent_queued_requests_as_ready(
+        &self,
+        room_id: &RoomId,
+        parent_txn_id: &TransactionId,
+        parent_key: SentRequestKey,
+    ) -> Result<usize> {
+        let prefix = self.room_key(keys::DEPENDENTS_SEND_QUEUE, room_id);
+        let parent_txn_id = parent_txn_id.to_owned();
+        let this = self.clone();
+
+        self.db
+            .write(move |txn| {
+                let mut table = txn.open_table(tables::DEPENDENTS_SEND_QUEUE)?;
+                let mut num_updated = 0;
+
+                for (key, data) in get_prefixed(&table, &prefix)? {
+                    let mut entry: DependentQueueEntry = this.deserialize_json(&data)?;
+
+                    if entry.request.parent_transaction_id != parent_txn_id {
+                        continue;
+                    }
+
+                    entry.request.parent_key = Some(parent_key.clone());
+
+                    let data = this.serialize_json(&entry)?;
+                    table.insert(key.as_slice(), data.as_slice())?;
+                    num_updated += 1;
+                }
+
+                Ok(num_updated)
+            })
+            .await
+    }
+
+    async fn remove_dependent_queued_request(
+        &self,
+        room_id: &RoomId,
+        txn_id: &ChildTransactionId,
+    ) -> Result<bool> {
+        let key = self.send_queue_key(keys::DEPENDENTS_SEND_QUEUE, room_id, txn_id.as_str());
+
+        self.db
+            .write(move |txn| {
+                Ok(txn.open_table(tables::DEPENDENTS_SEND_QUEUE)?.remove(key.as_slice())?.is_some())
+            })
+            .await
+    }
+
+    async fn load_dependent_queued_requests(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<DependentQueuedRequest>> {
+        let prefix = self.room_key(keys::DEPENDENTS_SEND_QUEUE, room_id);
+
+        let mut entries = self
+            .db
+            .read(move |txn| get_prefixed(&txn.open_table(tables::DEPENDENTS_SEND_QUEUE)?, &prefix))
+            .await?
+            .iter()
+            .map(|(_, data)| self.deserialize_json::<DependentQueueEntry>(data))
+            .collect::<Result<Vec<_>>>()?;
+
+        entries.sort_by_key(|entry| entry.sequence);
+
+        Ok(entries.into_iter().map(|entry| entry.request).collect())
+    }
+
+    async fn storage_usage(&self) -> Result<StoreUsage, Self::Error> {
+        self.db.storage_usage(tables::ALL).await
+    }
+
+    async fn compact(&self) -> Result<(), Self::Error> {
+        self.db.compact().await
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ReceiptData {
+    receipt: Receipt,
+    event_id: OwnedEventId,
+    user_id: OwnedUserId,
+}
+
+/// The data of a member of a room.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MemberData {
+    user_id: OwnedUserId,
+    membership: MembershipState,
+}
+
+/// A request of a send queue.
+#[derive(Serialize, Deserialize)]
+struct SendQueueEntry {
+    /// The position of the request in the insertion order.
+    sequence: u64,
+    room_id: OwnedRoomId,
+    transaction_id: OwnedTransactionId,
+    kind: QueuedRequestKind,
+    error: Option<QueueWedgeError>,
+    priority: usize,
+    created_at: MilliSecondsSinceUnixEpoch,
+}
+
+/// A dependent request of a send queue.
+#[derive(Serialize, Deserialize)]
+struct DependentQueueEntry {
+    /// The position of the request in the insertion order.
+    sequence: u64,
+    request: DependentQueuedRequest,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+    use matrix_sdk_base::{statestore_integration_tests, StateStore, StoreError};
+    use once_cell::sync::Lazy;
+    use tempfile::{tempdir, TempDir};
+
+    use super::RedbStateStore;
+
+    static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
+    static NUM: AtomicU32 = AtomicU32::new(0);
+
+    async fn get_store() -> Result<impl StateStore, StoreError> {
+        let name = NUM.fetch_add(1, SeqCst).to_string();
+        let tmpdir_path = TMP_DIR.path().join(name);
+
+        tracing::info!("using store @ {}", tmpdir_path.to_str().unwrap());
+
+        Ok(RedbStateStore::open(tmpdir_path.to_str().unwrap(), None).await.unwrap())
+    }
+
+    statestore_integration_tests!();
+}
+
+#[cfg(test)]
+mod encrypted_tests {
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+    use matrix_sdk_base::{statestore_integration_tests, StateStore, StoreError};
+    use once_cell::sync::Lazy;
+    use tempfile::{tempdir, TempDir};
+
+    use super::RedbStateStore;
+
+    static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
+    static NUM: AtomicU32 = AtomicU32::new(0);
+
+    async fn get_store() -> Result<impl StateStore, StoreError> {
+        let name = NUM.fetch_add(1, SeqCst).to_string();
+        let tmpdir_path = TMP_DIR.path().join(name);
+
+        tracing::info!("using store @ {}", tmpdir_path.to_str().unwrap());
+
+        Ok(RedbStateStore::open(tmpdir_path.to_str().unwrap(), Some("default_test_password"))
+            .await
+            .unwrap())
+    }
+
+    statestore_integration_tests!();
+}
@@ -0,0 +1,514 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    panic,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock as StdRwLock, Weak},
+};
+
+use matrix_sdk_common::store_usage::StoreUsage;
+use matrix_sdk_store_encryption::StoreCipher;
+use redb::{
+    ReadTransaction, ReadableTable, ReadableTableMetadata, TableDefinition, TableHandle,
+    WriteTransaction,
+};
+#[cfg(feature = "event-cache")]
+use ruma::time::SystemTime;
+use ruma::{serde::Raw, OwnedEventId, OwnedRoomId};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::fs;
+use tracing::{error, warn};
+
+use crate::{
+    error::{Error, Result},
+    OpenStoreError,
+};
+
+/// The definition of all the tables of the stores: both their keys and their
+/// values are byte strings.
+pub(crate) type Table = TableDefinition<'static, &'static [u8], &'static [u8]>;
+
+/// The table holding the metadata of a database, like its version or its
+/// store cipher.
+///
+/// It is created in every database, and its values are never encrypted.
+pub(crate) const KV: Table = TableDefinition::new("kv");
+
+/// The current version of the databases.
+const DATABASE_VERSION: u8 = 1;
+
+/// The databases that are currently open in this process.
+///
+/// A redb database can only be opened once per process, so a store opened
+/// twice with the same path shares the handle of the first one.
+static OPEN_DATABASES: Mutex<BTreeMap<PathBuf, Weak<StdRwLock<redb::Database>>>> =
+    Mutex::new(BTreeMap::new());
+
+/// A handle to a redb database, that runs the transactions on the blocking
+/// thread pool.
+#[derive(Clone)]
+pub(crate) struct RedbDatabase {
+    path: PathBuf,
+    /// The database.
+    ///
+    /// Transactions only need a read lock. The write lock is only taken to
+    /// compact the database, which requires that no transaction is running.
+    inner: Arc<StdRwLock<redb::Database>>,
+}
+
+impl RedbDatabase {
+    /// Open the database with the given file name in the given directory, or
+    /// create it if it doesn't exist, along with the given tables.
+    pub(crate) async fn open(
+        dir: &Path,
+        name: &str,
+        tables: &'static [Table],
+    ) -> Result<Self, OpenStoreError> {
+        fs::create_dir_all(dir).await.map_err(OpenStoreError::CreateDir)?;
+        let path = fs::canonicalize(dir).await.map_err(OpenStoreError::CreateDir)?.join(name);
+
+        run_blocking(move || Self::open_blocking(path, tables)).await
+    }
+
+    fn open_blocking(path: PathBuf, tables: &'static [Table]) -> Result<Self, OpenStoreError> {
+        let mut open_databases = OPEN_DATABASES.lock().unwrap();
+        open_databases.retain(|_, database| database.strong_count() > 0);
+
+        if let Some(inner) = open_databases.get(&path).and_then(Weak::upgrade) {
+            return Ok(Self { path, inner });
+        }
+
+        let database = redb::Database::create(&path)?;
+
+        let txn = database.begin_write().map_err(Error::from)?;
+        let version = {
+            let mut kv = txn.open_table(KV).map_err(Error::from)?;
+            let version = get(&kv, b"version")?.and_then(|version| version.first().copied());
+
+            if version.is_none() {
+                kv.insert(b"version".as_slice(), [DATABASE_VERSION].as_slice())
+                    .map_err(Error::from)?;
+            }
+
+            version.unwrap_or(DATABASE_VERSION)
+        };
+
+        if version > DATABASE_VERSION {
+            return Err(OpenStoreError::UnsupportedVersion(version));
+        }
+
+        for table in tables {
+            txn.open_table(*table).map_err(Error::from)?;
+        }
+
+        txn.commit().map_err(Error::from)?;
+
+        let inner = Arc::new(StdRwLock::new(database));
+        open_databases.insert(path.clone(), Arc::downgrade(&inner));
+
+        Ok(Self { path, inner })
+    }
+
+    /// Run the given function in a read transaction.
+    pub(crate) async fn read<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&ReadTransaction) -> Result<T> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+
+        run_blocking(move || {
+            let database = inner.read().unwrap();
+            let txn = database.begin_read()?;
+            f(&txn)
+        })
+        .await
+    }
+
+    /// Run the given function in a write transaction.
+    ///
+    /// The transaction is committed if the function succeeds, and aborted
+    /// otherwise.
+    pub(crate) async fn write<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        T: Send + 'static,
+        E: From<Error> + Send + 'static,
+        F: FnOnce(&WriteTransaction) -> Result<T, E> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+
+        run_blocking(move || {
+            let database = inner.read().unwrap();
+            let txn = database.begin_write().map_err(Error::from)?;
+
+            match f(&txn) {
+                Ok(value) => {
+                    txn.commit().map_err(Error::from)?;
+                    Ok(value)
+                }
+                Err(error) => {
+                    if let Err(abort_error) = txn.abort() {
+                        warn!("Failed to abort a transaction: {abort_error}");
+                    }
+                    Err(error)
+                }
+            }
+        })
+        .await
+    }
+
+    /// Load the [`StoreCipher`] of the database, or create one if there is
+    /// none yet.
+    pub(crate) async fn get_or_create_store_cipher(
+        &self,
+        passphrase: &str,
+    ) -> Result<StoreCipher, OpenStoreError> {
+        let passphrase = passphrase.to_owned();
+
+        self.write(move |txn| {
+            let mut kv = txn.open_table(KV).map_err(Error::from)?;
+
+            if let Some(encrypted) = get(&kv, b"cipher")? {
+                return Ok(StoreCipher::import(&passphrase, &encrypted)?);
+            }
+
+            let cipher = StoreCipher::new()?;
+            let encrypted = if cfg!(test) {
+                cipher._insecure_export_fast_for_testing(&passphrase)
+            } else {
+                cipher.export(&passphrase)
+            }?;
+            kv.insert(b"cipher".as_slice(), encrypted.as_slice()).map_err(Error::from)?;
+
+            Ok(cipher)
+        })
+        .await
+    }
+
+    /// Try to take the lease lock with the given key in the given table, for
+    /// the given holder.
+    ///
+    /// The lock is taken if it is free, expired, or already held by the same
+    /// holder, in which case its lease is renewed.
+    #[cfg(any(feature = "crypto-store", feature = "event-cache"))]
+    pub(crate) async fn try_take_leased_lock(
+        &self,
+        table: Table,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool> {
+        let key = key.to_owned();
+        let holder = holder.to_owned();
+
+        let now: u64 = ruma::MilliSecondsSinceUnixEpoch::now().get().into();
+        let expiration = now + lease_duration_ms as u64;
+
+        self.write(move |txn| {
+            let mut table = txn.open_table(table)?;
+
+            if let Some(value) = get(&table, key.as_bytes())? {
+                let (current_holder, current_expiration): (String, u64) =
+                    rmp_serde::from_slice(&value)?;
+
+                if current_holder != holder && current_expiration >= now {
+                    return Ok(false);
+                }
+            }
+
+            let value = rmp_serde::to_vec(&(holder, expiration))?;
+            table.insert(key.as_bytes(), value.as_slice())?;
+
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Get how much space the database is using.
+    ///
+    /// The total size is the size of the database file, and the free size is
+    /// the size of the pages that are allocated but unused. The size of a
+    /// table is the size of its keys and values.
+    pub(crate) async fn storage_usage(&self, tables: &'static [Table]) -> Result<StoreUsage> {
+        let total_size = fs::metadata(&self.path).await.ok().map(|metadata| metadata.len());
+
+        let (free_size, tables) = self
+            .write(move |txn| {
+                let free_size = txn.stats()?.fragmented_bytes();
+
+                let mut sizes = BTreeMap::new();
+                for table in tables.iter().chain([&KV]) {
+                    let stats = txn.open_table(*table)?.stats()?;
+                    sizes.insert(table.name().to_owned(), stats.stored_bytes());
+                }
+
+                Ok::<_, Error>((free_size, sizes))
+            })
+            .await?;
+
+        Ok(StoreUsage { total_size, free_size: Some(free_size), tables })
+    }
+
+    /// Compact the database, to give back to the system the pages that aren't
+    /// used anymore.
+    ///
+    /// This waits for the running transactions to finish, and blocks the new
+    /// ones until the compaction is done.
+    pub(crate) async fn compact(&self) -> Result<()> {
+        let inner = self.inner.clone();
+
+        run_blocking(move || {
+            inner.write().unwrap().compact()?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Run the given blocking function on the blocking thread pool, and resume
+/// its panic if it panicked.
+async fn run_blocking<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|error| panic::resume_unwind(error.into_panic()))
+}
+
+/// Get the value of the given key in the given table.
+pub(crate) fn get(
+    table: &impl ReadableTable<&'static [u8], &'static [u8]>,
+    key: &[u8],
+) -> Result<Option<Vec<u8>>> {
+    Ok(table.get(key)?.map(|value| value.value().to_vec()))
+}
+
+/// Get all the entries of the given table whose key starts with the given
+/// prefix, ordered by key.
+pub(crate) fn get_prefixed(
+    table: &impl ReadableTable<&'static [u8], &'static [u8]>,
+    prefix: &[u8],
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut entries = Vec::new();
+
+    for entry in table.range(prefix..)? {
+        let (key, value) = entry?;
+
+        if !key.value().starts_with(prefix) {
+            break;
+        }
+
+        entries.push((key.value().to_vec(), value.value().to_vec()));
+    }
+
+    Ok(entries)
+}
+
+/// Remove all the entries of the given table whose key starts with the given
+/// prefix.
+///
+/// Returns the number of removed entries.
+pub(crate) fn remove_prefixed(
+    table: &mut redb::Table<'_, &'static [u8], &'static [u8]>,
+    prefix: &[u8],
+) -> Result<usize> {
+    let entries = get_prefixed(table, prefix)?;
+
+    for (key, _) in &entries {
+        table.remove(key.as_slice())?;
+    }
+
+    Ok(entries.len())
+}
+
+/// Build a key out of several parts.
+///
+/// Each part is prefixed with its length, so the key made of the first parts
+/// of another key is a prefix of that key, and can be used to iterate over
+/// all the keys sharing these first parts.
+pub(crate) fn key<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> Vec<u8> {
+    let mut key = Vec::new();
+
+    for part in parts {
+        let len = u32::try_from(part.len()).expect("a key part should fit in 4 GiB");
+        key.extend_from_slice(&len.to_be_bytes());
+        key.extend_from_slice(part);
+    }
+
+    key
+}
+
+#[cfg(any(feature = "crypto-store", feature = "event-cache"))]
+/// Split a byte string built with [`key()`] back into its parts.
+pub(crate) fn split_key(mut bytes: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut parts = Vec::new();
+
+    while !bytes.is_empty() {
+        let invalid = || Error::InvalidData { details: "invalid composite key".to_owned() };
+
+        let (len, rest) = bytes.split_first_chunk::<4>().ok_or_else(invalid)?;
+        let len = u32::from_be_bytes(*len) as usize;
+        if rest.len() < len {
+            return Err(invalid());
+        }
+
+        let (part, rest) = rest.split_at(len);
+        parts.push(part);
+        bytes = rest;
+    }
+
+    Ok(parts)
+}
+
+#[cfg(any(feature = "crypto-store", feature = "event-cache"))]
+/// Split a byte string built with [`key()`] into its expected number of
+/// parts.
+pub(crate) fn split_value<const N: usize>(value: &[u8]) -> Result<[&[u8]; N]> {
+    split_key(value)?
+        .try_into()
+        .map_err(|_| Error::InvalidData { details: "unexpected number of parts".to_owned() })
+}
+
+/// Convert the given `SystemTime` to a timestamp, as the number of seconds
+/// since Unix Epoch.
+#[cfg(feature = "event-cache")]
+pub(crate) fn time_to_timestamp(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        // It is unlikely to happen unless the time on the system is seriously wrong, but we always
+        // need a value.
+        .unwrap_or(0)
+}
+
+/// Trait for a store that can encrypt its values, based on the presence of a
+/// cipher or not.
+///
+/// A single method must be implemented: `get_cypher`, which returns an optional
+/// cipher.
+///
+/// All the other methods come for free, based on the implementation of
+/// `get_cypher`.
+pub(crate) trait EncryptableStore {
+    fn get_cypher(&self) -> Option<&StoreCipher>;
+
+    /// If the store is using encryption, this will hash the given key. This is
+    /// useful when we need to do queries against a given key, but we don't
+    /// need to store the key in plain text (i.e. it's not both a key and a
+    /// value).
+    fn encode_key(&self, table_name: &str, key: impl AsRef<[u8]>) -> Vec<u8> {
+        let bytes = key.as_ref();
+        if let Some(store_cipher) = self.get_cypher() {
+            store_cipher.hash_key(table_name, bytes).to_vec()
+        } else {
+            bytes.to_owned()
+        }
+    }
+
+    fn encode_value(&self, value: Vec<u8>) -> Result<Vec<u8>> {
+        if let Some(key) = self.get_cypher() {
+            let encrypted = key.encrypt_value_data(value)?;
+            Ok(rmp_serde::to_vec_named(&encrypted)?)
+        } else {
+            Ok(value)
+        }
+    }
+
+    fn decode_value<'a>(&self, value: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        if let Some(key) = self.get_cypher() {
+            let encrypted = rmp_serde::from_slice(value)?;
+            let decrypted = key.decrypt_value_data(encrypted)?;
+            Ok(Cow::Owned(decrypted))
+        } else {
+            Ok(Cow::Borrowed(value))
+        }
+    }
+
+    #[cfg(any(feature = "state-store", feature = "crypto-store"))]
+    fn serialize_value(&self, value: &impl Serialize) -> Result<Vec<u8>> {
+        let serialized = rmp_serde::to_vec_named(value)?;
+        self.encode_value(serialized)
+    }
+
+    #[cfg(any(feature = "state-store", feature = "crypto-store"))]
+    fn deserialize_value<T: DeserializeOwned>(&self, value: &[u8]) -> Result<T> {
+        let decoded = self.decode_value(value)?;
+        Ok(rmp_serde::from_slice(&decoded)?)
+    }
+
+    fn serialize_json(&self, value: &impl Serialize) -> Result<Vec<u8>> {
+        let serialized = serde_json::to_vec(value)?;
+        self.encode_value(serialized)
+    }
+
+    fn deserialize_json<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T> {
+        let decoded = self.decode_value(data)?;
+
+        let json_deserializer = &mut serde_json::Deserializer::from_slice(&decoded);
+
+        serde_path_to_error::deserialize(json_deserializer).map_err(|err| {
+            let raw_json: Option<Raw<serde_json::Value>> = serde_json::from_slice(&decoded).ok();
+
+            let target_type = std::any::type_name::<T>();
+            let serde_path = err.path().to_string();
+
+            error!(
+                sentry = true,
+                %err,
+                "Failed to deserialize {target_type} in a store: {serde_path}",
+            );
+
+            if let Some(raw) = raw_json {
+                if let Some(room_id) = raw.get_field::<OwnedRoomId>("room_id").ok().flatten() {
+                    warn!("Found a room id in the source data to deserialize: {room_id}");
+                }
+                if let Some(event_id) = raw.get_field::<OwnedEventId>("event_id").ok().flatten() {
+                    warn!("Found an event id in the source data to deserialize: {event_id}");
+                }
+            }
+
+            err.into_inner().into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::key;
+
+    #[test]
+    fn test_key_parts_are_prefixes() {
+        let room = key([b"!room".as_slice()]);
+        let event = key([b"!room".as_slice(), b"$event"]);
+        let other_room = key([b"!room2".as_slice(), b"$event"]);
+
+        assert!(event.starts_with(&room));
+        assert!(!other_room.starts_with(&room));
+    }
+
+    #[test]
+    #[cfg(any(feature = "crypto-store", feature = "event-cache"))]
+    fn test_split_key() {
+        use super::split_key;
+
+        let parts = [b"!room".as_slice(), b"", b"$event"];
+        assert_eq!(split_key(&key(parts)).unwrap(), parts);
+
+        assert!(split_key(&[0, 0, 0, 2, 1]).is_err());
+    }
+}
@@ -0,0 +1,12 @@
+-- Index of the relations aggregated on events.
+CREATE TABLE "event_aggregations" (
+    -- Which room does this event belong to? (hashed key shared with events)
+    "room_id" BLOB NOT NULL,
+    -- `OwnedEventId` of the event the relations are aggregated on.
+    "event_id" BLOB NOT NULL,
+    -- JSON serialized `EventAggregations` (encrypted value).
+    "content" BLOB NOT NULL,
+
+    PRIMARY KEY (room_id, event_id)
+)
+WITHOUT ROWID;
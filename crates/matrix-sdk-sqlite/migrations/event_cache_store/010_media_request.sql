@@ -0,0 +1,3 @@
+-- The serialized `MediaRequestParameters` of the media (encrypted value), so
+-- the media can be listed. Null for the media stored before this migration.
+ALTER TABLE "media" ADD COLUMN "request" BLOB DEFAULT NULL;
@@ -0,0 +1,4 @@
+-- The MIME type of the media, guessed from its content when it is stored, so
+-- the media can be grouped by MIME type without decrypting them. Null for the
+-- media stored before this migration.
+ALTER TABLE "media" ADD COLUMN "mime_type" TEXT DEFAULT NULL;
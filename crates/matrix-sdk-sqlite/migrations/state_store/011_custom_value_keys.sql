@@ -0,0 +1,8 @@
+-- The keys of the custom values, so they can be listed.
+--
+-- `key` is the (possibly hashed) key of the value in the `kv_blob` table, and
+-- `name` is the (possibly encrypted) key that was used to store the value.
+CREATE TABLE "custom_value_key" (
+    "key" BLOB PRIMARY KEY NOT NULL,
+    "name" BLOB NOT NULL
+);
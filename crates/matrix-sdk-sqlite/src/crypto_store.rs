@@ -21,6 +21,7 @@ use std::{
 
 use async_trait::async_trait;
 use deadpool_sqlite::{Object as SqliteAsyncConn, Pool as SqlitePool, Runtime};
+use matrix_sdk_common::store_usage::StoreUsage;
 use matrix_sdk_crypto::{
     olm::{
         InboundGroupSession, OutboundGroupSession, PickledInboundGroupSession,
@@ -47,16 +48,38 @@ use tracing::{debug, instrument, warn};
 use vodozemac::Curve25519PublicKey;
 
 use crate::{
-    error::{Error, Result},
+    error::{Error, IntegrityError, Result},
+    integrity::{open_connection, CipherDecoder, EncryptedColumn},
     utils::{
         repeat_vars, EncryptableStore, Key, SqliteAsyncConnExt, SqliteKeyValueStoreAsyncConnExt,
         SqliteKeyValueStoreConnExt,
     },
-    OpenStoreError, SqliteStoreConfig,
+    ChangePassphraseError, OpenStoreError, SqliteStoreConfig,
 };
 
 /// The database name.
-const DATABASE_NAME: &str = "matrix-sdk-crypto.sqlite3";
+pub(crate) const DATABASE_NAME: &str = "matrix-sdk-crypto.sqlite3";
+
+/// The columns holding encrypted values, checked by
+/// [`crate::check_integrity()`].
+pub(crate) const ENCRYPTED_COLUMNS: &[EncryptedColumn] = &[
+    EncryptedColumn {
+        table: "kv",
+        column: "value",
+        condition: Some("key NOT IN ('version', 'cipher', 'cipher_next')"),
+    },
+    EncryptedColumn { table: "session", column: "data", condition: None },
+    EncryptedColumn { table: "inbound_group_session", column: "data", condition: None },
+    EncryptedColumn { table: "outbound_group_session", column: "data", condition: None },
+    EncryptedColumn { table: "device", column: "data", condition: None },
+    EncryptedColumn { table: "identity", column: "data", condition: None },
+    EncryptedColumn { table: "tracked_user", column: "data", condition: None },
+    EncryptedColumn { table: "key_requests", column: "data", condition: None },
+    EncryptedColumn { table: "room_settings", column: "data", condition: None },
+    EncryptedColumn { table: "direct_withheld_info", column: "data", condition: None },
+    EncryptedColumn { table: "secrets", column: "data", condition: None },
+    EncryptedColumn { table: "received_room_key_bundle", column: "bundle_data", condition: None },
+];
 
 /// An SQLite-based crypto store.
 #[derive(Clone)]
@@ -76,6 +99,28 @@ impl fmt::Debug for SqliteCryptoStore {
     }
 }
 
+/// What [`SqliteCryptoStore::salvage()`] could save from a corrupted store.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SalvageReport {
+    /// Whether the account was salvaged.
+    pub account: bool,
+
+    /// Whether the private cross-signing identity was salvaged.
+    pub private_identity: bool,
+
+    /// The number of inbound group sessions that were salvaged.
+    pub inbound_group_sessions: usize,
+
+    /// The number of inbound group sessions that couldn't be read or
+    /// decrypted.
+    pub lost_inbound_group_sessions: usize,
+
+    /// The error that prevented reading the table of the inbound group
+    /// sessions at all, in which case none of them were salvaged and the
+    /// number of lost sessions is unknown.
+    pub inbound_group_sessions_error: Option<String>,
+}
+
 impl EncryptableStore for SqliteCryptoStore {
     fn get_cypher(&self) -> Option<&StoreCipher> {
         self.store_cipher.as_deref()
@@ -109,6 +154,175 @@ impl SqliteCryptoStore {
         Ok(this)
     }
 
+    /// Prepare a change of the passphrase used to encrypt this store.
+    ///
+    /// The store can be opened with either passphrase until
+    /// [`SqliteCryptoStore::commit_passphrase_change`] is called, which allows
+    /// to change the passphrase of several stores consistently.
+    pub async fn prepare_passphrase_change(
+        &self,
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<(), ChangePassphraseError> {
+        if self.store_cipher.is_none() {
+            return Err(ChangePassphraseError::NotEncrypted);
+        }
+
+        self.pool.get().await?.prepare_store_cipher_change(old_passphrase, new_passphrase).await
+    }
+
+    /// Commit the change of passphrase prepared with
+    /// [`SqliteCryptoStore::prepare_passphrase_change`].
+    pub async fn commit_passphrase_change(&self) -> Result<(), ChangePassphraseError> {
+        self.pool.get().await?.commit_store_cipher_change().await
+    }
+
+    /// Change the passphrase used to encrypt this store, without re-encrypting
+    /// its data.
+    pub async fn change_passphrase(
+        &self,
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<(), ChangePassphraseError> {
+        self.prepare_passphrase_change(old_passphrase, new_passphrase).await?;
+        self.commit_passphrase_change().await
+    }
+
+    /// Salvage the account, the private cross-signing identity and the
+    /// inbound group sessions of a crypto store that can't be opened anymore,
+    /// into a new crypto store.
+    ///
+    /// The corrupted store, at the path of `corrupted`, is read as it is,
+    /// without running the migrations, and is left untouched. Only the values
+    /// that can still be read and decrypted are saved into the new store,
+    /// which is opened with `config` and must be at a different path.
+    ///
+    /// Use [`crate::check_integrity()`] to find out whether a store is
+    /// corrupted.
+    pub async fn salvage(
+        corrupted: SqliteStoreConfig,
+        config: SqliteStoreConfig,
+    ) -> Result<(Self, SalvageReport), IntegrityError> {
+        let path = corrupted.path.join(DATABASE_NAME);
+        if !path.exists() {
+            return Err(IntegrityError::MissingDatabase(path));
+        }
+
+        // The paths are canonicalized so different ways to write the same path are
+        // caught. The path of the new store may not exist yet, in which case it
+        // can't be the one of the corrupted store.
+        let corrupted_path = corrupted.path.canonicalize().map_err(IntegrityError::Io)?;
+        if config.path.canonicalize().is_ok_and(|path| path == corrupted_path) {
+            return Err(IntegrityError::SameStore);
+        }
+
+        let conn = open_connection(&path).await?;
+
+        let cipher = match corrupted.passphrase.as_deref() {
+            Some(passphrase) => conn
+                .load_store_cipher(passphrase)
+                .await
+                .map_err(IntegrityError::LoadCipher)?
+                .map(Arc::new),
+            None => None,
+        };
+        let decoder = CipherDecoder(cipher);
+
+        let account = conn
+            .get_kv("account")
+            .await
+            .ok()
+            .flatten()
+            .and_then(|value| decoder.deserialize_value(&value).ok())
+            .and_then(|pickle| Account::from_pickle(pickle).ok());
+
+        let private_identity = conn
+            .get_kv("identity")
+            .await
+            .ok()
+            .flatten()
+            .and_then(|value| decoder.deserialize_value(&value).ok())
+            .and_then(|pickle| PrivateCrossSigningIdentity::from_pickle(pickle).ok());
+
+        let inbound_group_sessions = conn
+            .interact(|conn| {
+                let mut stmt = conn.prepare("SELECT data, backed_up FROM inbound_group_session")?;
+                let mut rows = stmt.query(())?;
+
+                let mut values = Vec::new();
+                let mut unreadable = 0;
+
+                // Stop at the first row that can't be reached, but skip the rows whose
+                // values can't be read.
+                while let Ok(Some(row)) = rows.next() {
+                    match (row.get::<_, Vec<u8>>(0), row.get::<_, bool>(1)) {
+                        (Ok(value), Ok(backed_up)) => values.push((value, backed_up)),
+                        _ => unreadable += 1,
+                    }
+                }
+
+                Ok::<_, rusqlite::Error>((values, unreadable))
+            })
+            .await
+            .map_err(|error| IntegrityError::ReadDatabase(error.to_string()))?;
+
+        let mut report = SalvageReport {
+            account: account.is_some(),
+            private_identity: private_identity.is_some(),
+            ..Default::default()
+        };
+
+        // If the table can't be read at all, the number of lost sessions is unknown.
+        let values = match inbound_group_sessions {
+            Ok((values, unreadable)) => {
+                report.lost_inbound_group_sessions = unreadable;
+                values
+            }
+            Err(error) => {
+                report.inbound_group_sessions_error = Some(error.to_string());
+                Vec::new()
+            }
+        };
+
+        let mut inbound_group_sessions = Vec::with_capacity(values.len());
+        for (value, backed_up) in values {
+            let session = decoder
+                .deserialize_value::<PickledInboundGroupSession>(&value)
+                .ok()
+                .and_then(|mut pickle| {
+                    // See `deserialize_and_unpickle_inbound_group_session`.
+                    pickle.backed_up = backed_up;
+                    InboundGroupSession::from_pickle(pickle).ok()
+                });
+
+            match session {
+                Some(session) => inbound_group_sessions.push(session),
+                None => report.lost_inbound_group_sessions += 1,
+            }
+        }
+        report.inbound_group_sessions = inbound_group_sessions.len();
+
+        let store = Self::open_with_config(config).await.map_err(IntegrityError::OpenStore)?;
+
+        if account.is_some() {
+            store
+                .save_pending_changes(PendingChanges { account })
+                .await
+                .map_err(IntegrityError::Save)?;
+        }
+
+        store
+            .save_changes(Changes {
+                private_identity,
+                inbound_group_sessions,
+                ..Default::default()
+            })
+            .await
+            .map_err(IntegrityError::Save)?;
+
+        Ok((store, report))
+    }
+
     /// Create an SQLite-based crypto store using the given SQLite database
     /// pool. The given passphrase will be used to encrypt private data.
     async fn open_with_pool(
@@ -172,6 +386,20 @@ const DATABASE_VERSION: u8 = 10;
 /// key for the dehydrated device pickle key in the key/value table.
 const DEHYDRATED_DEVICE_PICKLE_KEY: &str = "dehydrated_device_pickle_key";
 
+/// Keys of the key/value table that are used internally by the store, and
+/// thus are not custom values.
+const INTERNAL_KV_KEYS: &[&str] = &[
+    "account",
+    "identity",
+    "next_batch_token",
+    "recovery_key_v1",
+    "backup_version_v1",
+    DEHYDRATED_DEVICE_PICKLE_KEY,
+    "version",
+    "cipher",
+    "cipher_next",
+];
+
 /// Run migrations for the given version of the database.
 async fn run_migrations(conn: &SqliteAsyncConn, version: u8) -> Result<()> {
     if version == 0 {
@@ -482,6 +710,14 @@ trait SqliteObjectCryptoStoreExt: SqliteAsyncConnExt {
             .await?)
     }
 
+    async fn get_all_sessions(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .prepare("SELECT data FROM session", |mut stmt| {
+                stmt.query(())?.mapped(|row| row.get(0)).collect()
+            })
+            .await?)
+    }
+
     async fn get_inbound_group_session(
         &self,
         session_id: Key,
@@ -728,6 +964,30 @@ trait SqliteObjectCryptoStoreExt: SqliteAsyncConnExt {
             .optional()?)
     }
 
+    async fn get_all_direct_withheld_info(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .prepare("SELECT data FROM direct_withheld_info", |mut stmt| {
+                stmt.query(())?.mapped(|row| row.get(0)).collect()
+            })
+            .await?)
+    }
+
+    async fn get_olm_hashes(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .prepare("SELECT data FROM olm_hash", |mut stmt| {
+                stmt.query(())?.mapped(|row| row.get(0)).collect()
+            })
+            .await?)
+    }
+
+    async fn get_all_kv(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        Ok(self
+            .prepare("SELECT key, value FROM kv", |mut stmt| {
+                stmt.query(())?.mapped(|row| Ok((row.get(0)?, row.get(1)?))).collect()
+            })
+            .await?)
+    }
+
     async fn get_room_settings(&self, room_id: Key) -> Result<Option<Vec<u8>>> {
         Ok(self
             .query_row("SELECT data FROM room_settings WHERE room_id = ?", (room_id,), |row| {
@@ -751,6 +1011,14 @@ trait SqliteObjectCryptoStoreExt: SqliteAsyncConnExt {
             .await
             .optional()?)
     }
+
+    async fn get_received_room_key_bundles(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .prepare("SELECT bundle_data FROM received_room_key_bundle", |mut stmt| {
+                stmt.query(())?.mapped(|row| row.get(0)).collect()
+            })
+            .await?)
+    }
 }
 
 #[async_trait]
@@ -1013,6 +1281,21 @@ impl CryptoStore for SqliteCryptoStore {
         }
     }
 
+    async fn get_all_sessions(&self) -> Result<Vec<Session>> {
+        let device_keys = self.get_own_device().await?.as_device_keys().clone();
+
+        self.acquire()
+            .await?
+            .get_all_sessions()
+            .await?
+            .into_iter()
+            .map(|bytes| {
+                let pickle = self.deserialize_value(&bytes)?;
+                Session::from_pickle(device_keys.clone(), pickle).map_err(|_| Error::AccountUnset)
+            })
+            .collect()
+    }
+
     #[instrument(skip(self))]
     async fn get_inbound_group_session(
         &self,
@@ -1256,6 +1539,18 @@ impl CryptoStore for SqliteCryptoStore {
         Ok(self.acquire().await?.has_olm_hash(value).await?)
     }
 
+    async fn get_all_olm_message_hashes(
+        &self,
+    ) -> Result<Vec<matrix_sdk_crypto::olm::OlmMessageHash>> {
+        self.acquire()
+            .await?
+            .get_olm_hashes()
+            .await?
+            .iter()
+            .map(|value| Ok(rmp_serde::from_slice(value)?))
+            .collect()
+    }
+
     async fn get_outgoing_secret_requests(
         &self,
         request_id: &TransactionId,
@@ -1341,6 +1636,16 @@ impl CryptoStore for SqliteCryptoStore {
             .transpose()
     }
 
+    async fn get_all_withheld_info(&self) -> Result<Vec<RoomKeyWithheldEvent>> {
+        self.acquire()
+            .await?
+            .get_all_direct_withheld_info()
+            .await?
+            .iter()
+            .map(|value| self.deserialize_json(value))
+            .collect()
+    }
+
     async fn get_room_settings(&self, room_id: &RoomId) -> Result<Option<RoomSettings>> {
         let room_id = self.encode_key("room_settings", room_id.as_bytes());
         let Some(value) = self.acquire().await?.get_room_settings(room_id).await? else {
@@ -1367,6 +1672,16 @@ impl CryptoStore for SqliteCryptoStore {
             .transpose()
     }
 
+    async fn get_all_received_room_key_bundle_data(&self) -> Result<Vec<StoredRoomKeyBundleData>> {
+        self.acquire()
+            .await?
+            .get_received_room_key_bundles()
+            .await?
+            .iter()
+            .map(|value| self.deserialize_value(value))
+            .collect()
+    }
+
     async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
         let Some(serialized) = self.acquire().await?.get_kv(key).await? else {
             return Ok(None);
@@ -1403,6 +1718,27 @@ impl CryptoStore for SqliteCryptoStore {
         Ok(())
     }
 
+    async fn get_all_custom_values(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        let mut values = Vec::new();
+
+        for (key, serialized) in self.acquire().await?.get_all_kv().await? {
+            if INTERNAL_KV_KEYS.contains(&key.as_str()) {
+                continue;
+            }
+
+            let value = if let Some(cipher) = &self.store_cipher {
+                let encrypted = rmp_serde::from_slice(&serialized)?;
+                cipher.decrypt_value_data(encrypted)?
+            } else {
+                serialized
+            };
+
+            values.push((key, value));
+        }
+
+        Ok(values)
+    }
+
     async fn try_take_leased_lock(
         &self,
         lease_duration_ms: u32,
@@ -1445,6 +1781,14 @@ impl CryptoStore for SqliteCryptoStore {
             Ok(None)
         }
     }
+
+    async fn storage_usage(&self) -> Result<StoreUsage, Self::Error> {
+        self.acquire().await?.storage_usage().await
+    }
+
+    async fn compact(&self) -> Result<(), Self::Error> {
+        self.acquire().await?.compact().await
+    }
 }
 
 #[cfg(test)]
@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::path::PathBuf;
+
 use deadpool_sqlite::{CreatePoolError, PoolError};
 #[cfg(feature = "event-cache")]
 use matrix_sdk_base::event_cache::store::EventCacheStoreError;
@@ -67,6 +69,77 @@ pub enum OpenStoreError {
     SaveCipher(#[source] rusqlite::Error),
 }
 
+/// All the errors that can occur when changing the passphrase of an SQLite
+/// store.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ChangePassphraseError {
+    /// The store wasn't opened with a passphrase, so there is no passphrase to
+    /// change.
+    #[error("The store is not encrypted")]
+    NotEncrypted,
+
+    /// No passphrase change was prepared before trying to commit it.
+    #[error("No passphrase change was prepared")]
+    NotPrepared,
+
+    /// Failed to import or export the store cipher, most likely because the
+    /// old passphrase is wrong.
+    #[error("Failed to re-encrypt the store cipher: {0}")]
+    Cipher(#[from] matrix_sdk_store_encryption::Error),
+
+    /// Failed to load or save the store cipher in the DB.
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+
+    /// Failed to get a DB connection from the pool.
+    #[error(transparent)]
+    Pool(#[from] PoolError),
+}
+
+/// All the errors that can occur when checking the integrity of an SQLite
+/// store, or when salvaging its data.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum IntegrityError {
+    /// Failed to create the DB pool.
+    #[error(transparent)]
+    CreatePool(#[from] CreatePoolError),
+
+    /// Failed to get a DB connection from the pool.
+    #[error(transparent)]
+    Pool(#[from] PoolError),
+
+    /// The database to salvage doesn't exist.
+    #[error("The database to salvage doesn't exist: {0}")]
+    MissingDatabase(PathBuf),
+
+    /// The data can't be salvaged into the store it comes from.
+    #[error("The data must be salvaged into a different store")]
+    SameStore,
+
+    /// Failed to access the directory of the store to salvage.
+    #[error("Failed to access the store to salvage: {0}")]
+    Io(#[source] std::io::Error),
+
+    /// Failed to run a query on the database to salvage.
+    #[error("Failed to read the database to salvage: {0}")]
+    ReadDatabase(String),
+
+    /// Failed to load the store cipher of the database to salvage, so none of
+    /// its data can be read.
+    #[error("Failed to load the store cipher: {0}")]
+    LoadCipher(#[source] OpenStoreError),
+
+    /// Failed to open the store where the data is salvaged.
+    #[error("Failed to open the new store: {0}")]
+    OpenStore(#[source] OpenStoreError),
+
+    /// Failed to save the salvaged data in the new store.
+    #[error("Failed to save the salvaged data: {0}")]
+    Save(#[source] Error),
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error(transparent)]
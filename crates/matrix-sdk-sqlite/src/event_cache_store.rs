@@ -14,7 +14,13 @@
 
 //! An SQLite-based backend for the [`EventCacheStore`].
 
-use std::{fmt, iter::once, path::Path, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    iter::once,
+    path::Path,
+    sync::{Arc, RwLock as StdRwLock},
+};
 
 use async_trait::async_trait;
 use deadpool_sqlite::{Object as SqliteAsyncConn, Pool as SqlitePool, Runtime};
@@ -27,15 +33,16 @@ use matrix_sdk_base::{
                 EventCacheStoreMedia, IgnoreMediaRetentionPolicy, MediaRetentionPolicy,
                 MediaService,
             },
-            EventCacheStore,
+            EventAggregations, EventCacheStore, EventRetentionPolicy,
         },
         Event, Gap,
     },
     linked_chunk::{
         ChunkContent, ChunkIdentifier, ChunkIdentifierGenerator, ChunkMetadata, LinkedChunkId,
-        Position, RawChunk, Update,
+        OwnedLinkedChunkId, Position, RawChunk, Update,
     },
-    media::{MediaRequestParameters, UniqueKey},
+    media::{guess_mime_type, MediaRequestParameters, UniqueKey, UNKNOWN_MIME_TYPE},
+    store_usage::{ItemsUsage, StoreUsage},
     timer,
 };
 use matrix_sdk_store_encryption::StoreCipher;
@@ -52,16 +59,18 @@ use tracing::{debug, error, instrument, trace};
 
 use crate::{
     error::{Error, Result},
+    integrity::{EncryptedColumn, OrphanedRowsQuery},
     utils::{
         repeat_vars, time_to_timestamp, EncryptableStore, Key, SqliteAsyncConnExt,
         SqliteKeyValueStoreAsyncConnExt, SqliteKeyValueStoreConnExt, SqliteTransactionExt,
     },
-    OpenStoreError, SqliteStoreConfig,
+    ChangePassphraseError, OpenStoreError, SqliteStoreConfig,
 };
 
 mod keys {
     // Entries in Key-value store
     pub const MEDIA_RETENTION_POLICY: &str = "media_retention_policy";
+    pub const EVENT_RETENTION_POLICY: &str = "event_retention_policy";
     pub const LAST_MEDIA_CLEANUP_TIME: &str = "last_media_cleanup_time";
 
     // Tables
@@ -70,14 +79,54 @@ mod keys {
 }
 
 /// The database name.
-const DATABASE_NAME: &str = "matrix-sdk-event-cache.sqlite3";
+pub(crate) const DATABASE_NAME: &str = "matrix-sdk-event-cache.sqlite3";
+
+/// The columns holding encrypted values, checked by
+/// [`crate::check_integrity()`].
+pub(crate) const ENCRYPTED_COLUMNS: &[EncryptedColumn] = &[
+    EncryptedColumn { table: "events", column: "content", condition: None },
+    EncryptedColumn { table: "event_aggregations", column: "content", condition: None },
+    EncryptedColumn { table: "gap_chunks", column: "prev_token", condition: None },
+    EncryptedColumn { table: "media", column: "data", condition: None },
+    EncryptedColumn { table: "media", column: "request", condition: Some("request IS NOT NULL") },
+];
+
+/// The queries looking for the rows of the linked chunks that reference chunks
+/// or events that don't exist, checked by [`crate::check_integrity()`].
+pub(crate) const ORPHANED_ROWS_QUERIES: &[OrphanedRowsQuery] = &[
+    OrphanedRowsQuery {
+        name: "event_chunks.chunk_id",
+        query: "SELECT COUNT(*) FROM event_chunks AS ec WHERE NOT EXISTS \
+                (SELECT 1 FROM linked_chunks AS lc WHERE lc.linked_chunk_id = ec.linked_chunk_id AND lc.id = ec.chunk_id)",
+    },
+    OrphanedRowsQuery {
+        name: "event_chunks.event_id",
+        query: "SELECT COUNT(*) FROM event_chunks AS ec WHERE NOT EXISTS \
+                (SELECT 1 FROM events WHERE events.event_id = ec.event_id)",
+    },
+    OrphanedRowsQuery {
+        name: "gap_chunks.chunk_id",
+        query: "SELECT COUNT(*) FROM gap_chunks AS gc WHERE NOT EXISTS \
+                (SELECT 1 FROM linked_chunks AS lc WHERE lc.linked_chunk_id = gc.linked_chunk_id AND lc.id = gc.chunk_id)",
+    },
+    OrphanedRowsQuery {
+        name: "linked_chunks.previous",
+        query: "SELECT COUNT(*) FROM linked_chunks AS lc WHERE lc.previous IS NOT NULL AND NOT EXISTS \
+                (SELECT 1 FROM linked_chunks AS prev WHERE prev.linked_chunk_id = lc.linked_chunk_id AND prev.id = lc.previous)",
+    },
+    OrphanedRowsQuery {
+        name: "linked_chunks.next",
+        query: "SELECT COUNT(*) FROM linked_chunks AS lc WHERE lc.next IS NOT NULL AND NOT EXISTS \
+                (SELECT 1 FROM linked_chunks AS next WHERE next.linked_chunk_id = lc.linked_chunk_id AND next.id = lc.next)",
+    },
+];
 
 /// Identifier of the latest database version.
 ///
 /// This is used to figure whether the SQLite database requires a migration.
 /// Every new SQL migration should imply a bump of this number, and changes in
 /// the [`run_migrations`] function.
-const DATABASE_VERSION: u8 = 8;
+const DATABASE_VERSION: u8 = 11;
 
 /// The string used to identify a chunk of type events, in the `type` field in
 /// the database.
@@ -100,6 +149,9 @@ pub struct SqliteEventCacheStore {
     /// lock is used to ensure there is one owner at a time.
     write_connection: Arc<Mutex<SqliteAsyncConn>>,
 
+    /// The current event retention policy, cached from the database.
+    event_retention_policy: Arc<StdRwLock<EventRetentionPolicy>>,
+
     media_service: MediaService,
 }
 
@@ -148,6 +200,44 @@ impl SqliteEventCacheStore {
         Ok(this)
     }
 
+    /// Prepare a change of the passphrase used to encrypt this store.
+    ///
+    /// The store can be opened with either passphrase until
+    /// [`SqliteEventCacheStore::commit_passphrase_change`] is called, which
+    /// allows to change the passphrase of several stores consistently.
+    pub async fn prepare_passphrase_change(
+        &self,
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<(), ChangePassphraseError> {
+        if self.store_cipher.is_none() {
+            return Err(ChangePassphraseError::NotEncrypted);
+        }
+
+        self.write_connection
+            .lock()
+            .await
+            .prepare_store_cipher_change(old_passphrase, new_passphrase)
+            .await
+    }
+
+    /// Commit the change of passphrase prepared with
+    /// [`SqliteEventCacheStore::prepare_passphrase_change`].
+    pub async fn commit_passphrase_change(&self) -> Result<(), ChangePassphraseError> {
+        self.write_connection.lock().await.commit_store_cipher_change().await
+    }
+
+    /// Change the passphrase used to encrypt this store, without re-encrypting
+    /// its data.
+    pub async fn change_passphrase(
+        &self,
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<(), ChangePassphraseError> {
+        self.prepare_passphrase_change(old_passphrase, new_passphrase).await?;
+        self.commit_passphrase_change().await
+    }
+
     /// Open an SQLite-based event cache store using the given SQLite database
     /// pool. The given passphrase will be used to encrypt private data.
     async fn open_with_pool(
@@ -169,11 +259,15 @@ impl SqliteEventCacheStore {
         let last_media_cleanup_time = conn.get_serialized_kv(keys::LAST_MEDIA_CLEANUP_TIME).await?;
         media_service.restore(media_retention_policy, last_media_cleanup_time);
 
+        let event_retention_policy =
+            conn.get_serialized_kv(keys::EVENT_RETENTION_POLICY).await?.unwrap_or_default();
+
         Ok(Self {
             store_cipher,
             pool,
             // Use `conn` as our selected write connections.
             write_connection: Arc::new(Mutex::new(conn)),
+            event_retention_policy: Arc::new(StdRwLock::new(event_retention_policy)),
             media_service,
         })
     }
@@ -328,8 +422,7 @@ impl TransactionExtForLinkedChunks for Transaction<'_> {
             |row| row.get(0),
         )?;
         let prev_token_bytes = store.decode_value(&encoded_prev_token)?;
-        let prev_token = serde_json::from_slice(&prev_token_bytes)?;
-        Ok(Gap { prev_token })
+        Ok(serde_json::from_slice(&prev_token_bytes)?)
     }
 
     fn load_events_content(
@@ -451,6 +544,36 @@ async fn run_migrations(conn: &SqliteAsyncConn, version: u8) -> Result<()> {
         .await?;
     }
 
+    if version < 9 {
+        conn.with_transaction(|txn| {
+            txn.execute_batch(include_str!(
+                "../migrations/event_cache_store/009_event_aggregations.sql"
+            ))?;
+            txn.set_db_version(9)
+        })
+        .await?;
+    }
+
+    if version < 10 {
+        conn.with_transaction(|txn| {
+            txn.execute_batch(include_str!(
+                "../migrations/event_cache_store/010_media_request.sql"
+            ))?;
+            txn.set_db_version(10)
+        })
+        .await?;
+    }
+
+    if version < 11 {
+        conn.with_transaction(|txn| {
+            txn.execute_batch(include_str!(
+                "../migrations/event_cache_store/011_media_mime_type.sql"
+            ))?;
+            txn.set_db_version(11)
+        })
+        .await?;
+    }
+
     Ok(())
 }
 
@@ -533,7 +656,7 @@ impl EventCacheStore for SqliteEventCacheStore {
                     }
 
                     Update::NewGapChunk { previous, new, next, gap } => {
-                        let serialized = serde_json::to_vec(&gap.prev_token)?;
+                        let serialized = serde_json::to_vec(&gap)?;
                         let prev_token = this.encode_value(serialized)?;
 
                         let previous = previous.as_ref().map(ChunkIdentifier::index);
@@ -810,6 +933,18 @@ impl EventCacheStore for SqliteEventCacheStore {
                             "DELETE FROM linked_chunks WHERE linked_chunk_id = ?",
                             (&hashed_linked_chunk_id,),
                         )?;
+
+                        // The aggregations are indexed per room, so they're cleared along with
+                        // the room's linked chunk.
+                        match linked_chunk_id {
+                            OwnedLinkedChunkId::Room(_) => {
+                                txn.execute(
+                                    "DELETE FROM event_aggregations WHERE room_id = ?",
+                                    (&hashed_linked_chunk_id,),
+                                )?;
+                            }
+                            OwnedLinkedChunkId::Detached(_) => {}
+                        }
                     }
 
                     Update::StartReattachItems | Update::EndReattachItems => {
@@ -1090,8 +1225,9 @@ impl EventCacheStore for SqliteEventCacheStore {
             .with_transaction(move |txn| {
                 // Remove all the chunks, and let cascading do its job.
                 txn.execute("DELETE FROM linked_chunks", ())?;
-                // Also clear all the events' contents.
-                txn.execute("DELETE FROM events", ())
+                // Also clear all the events' contents, and their aggregations.
+                txn.execute("DELETE FROM events", ())?;
+                txn.execute("DELETE FROM event_aggregations", ())
             })
             .await?;
 
@@ -1270,6 +1406,282 @@ impl EventCacheStore for SqliteEventCacheStore {
             .await
     }
 
+    #[instrument(skip(self))]
+    async fn get_all_room_events(&self, room_id: &RoomId) -> Result<Vec<Event>, Self::Error> {
+        let _timer = timer!("method");
+
+        let hashed_room_id = self.encode_key(keys::LINKED_CHUNKS, room_id);
+
+        let contents: Vec<Vec<u8>> = self
+            .read()
+            .await?
+            .prepare("SELECT content FROM events WHERE room_id = ?", move |mut stmt| {
+                stmt.query_map((hashed_room_id,), |row| row.get::<_, Vec<u8>>(0))?.collect()
+            })
+            .await?;
+
+        contents
+            .iter()
+            .map(|content| Ok(serde_json::from_slice(&self.decode_value(content)?)?))
+            .collect()
+    }
+
+    #[instrument(skip(self, aggregations))]
+    async fn save_event_aggregations(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        aggregations: EventAggregations,
+    ) -> Result<(), Self::Error> {
+        let _timer = timer!("method");
+
+        let hashed_room_id = self.encode_key(keys::LINKED_CHUNKS, room_id);
+        let event_id = event_id.to_string();
+        let encoded_aggregations =
+            (!aggregations.is_empty()).then(|| self.serialize_json(&aggregations)).transpose()?;
+
+        self.write()
+            .await?
+            .with_transaction(move |txn| -> Result<_> {
+                if let Some(encoded_aggregations) = encoded_aggregations {
+                    txn.execute(
+                        "INSERT OR REPLACE INTO event_aggregations(room_id, event_id, content) VALUES (?, ?, ?)",
+                        (&hashed_room_id, &event_id, encoded_aggregations),
+                    )?;
+                } else {
+                    txn.execute(
+                        "DELETE FROM event_aggregations WHERE room_id = ? AND event_id = ?",
+                        (&hashed_room_id, &event_id),
+                    )?;
+                }
+
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip(self, event_ids))]
+    async fn load_event_aggregations(
+        &self,
+        room_id: &RoomId,
+        event_ids: &[OwnedEventId],
+    ) -> Result<BTreeMap<OwnedEventId, EventAggregations>, Self::Error> {
+        let _timer = timer!("method");
+
+        // It's required to return early, otherwise the `repeat_vars` call below will
+        // panic.
+        if event_ids.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+
+        let hashed_room_id = self.encode_key(keys::LINKED_CHUNKS, room_id);
+        let event_ids = event_ids.to_vec();
+        let this = self.clone();
+
+        let aggregations = self
+            .read()
+            .await?
+            .with_transaction(move |txn| -> Result<_> {
+                txn.chunk_large_query_over(event_ids, None, move |txn, event_ids| {
+                    let query = format!(
+                        "SELECT event_id, content FROM event_aggregations WHERE room_id = ? AND event_id IN ({})",
+                        repeat_vars(event_ids.len()),
+                    );
+
+                    let parameters = params_from_iter(
+                        // parameter for `room_id = ?`
+                        once(
+                            hashed_room_id
+                                .to_sql()
+                                // SAFETY: it cannot fail since `Key::to_sql` never fails
+                                .unwrap(),
+                        )
+                        // parameters for `event_id IN (…)`
+                        .chain(event_ids.iter().map(|event_id| {
+                            event_id
+                                .as_str()
+                                .to_sql()
+                                // SAFETY: it cannot fail since `str::to_sql` never fails
+                                .unwrap()
+                        })),
+                    );
+
+                    let mut aggregations = Vec::new();
+
+                    for row in txn.prepare(&query)?.query_map(parameters, |row| {
+                        Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+                    })? {
+                        let (event_id, content) = row?;
+
+                        let Ok(event_id) = EventId::parse(event_id.clone()) else {
+                            // Normally unreachable, but the event ID has been stored even if it is
+                            // malformed, let's skip it.
+                            error!(%event_id, "Reading an malformed event ID");
+                            continue;
+                        };
+
+                        aggregations.push((event_id, this.deserialize_json(&content)?));
+                    }
+
+                    Ok(aggregations)
+                })
+            })
+            .await?;
+
+        Ok(aggregations.into_iter().collect())
+    }
+
+    #[instrument(skip_all)]
+    async fn set_event_retention_policy(
+        &self,
+        policy: EventRetentionPolicy,
+    ) -> Result<(), Self::Error> {
+        let _timer = timer!("method");
+
+        let conn = self.write().await?;
+        conn.set_serialized_kv(keys::EVENT_RETENTION_POLICY, policy).await?;
+        *self.event_retention_policy.write().unwrap() = policy;
+
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    fn event_retention_policy(&self) -> EventRetentionPolicy {
+        let _timer = timer!("method");
+
+        *self.event_retention_policy.read().unwrap()
+    }
+
+    #[instrument(skip_all)]
+    async fn clean_up_linked_chunks(&self) -> Result<(), Self::Error> {
+        let _timer = timer!("method");
+
+        let policy = self.event_retention_policy();
+
+        if !policy.has_limitations() {
+            // We can safely skip all the checks.
+            return Ok(());
+        }
+
+        let this = self.clone();
+
+        with_immediate_transaction(self, move |txn| {
+            // The policy only applies to the linked chunks of the rooms, not to the
+            // detached ones. The key of the linked chunk of a room is the same as the key
+            // of the room in the `events` table, which is never the case for a detached
+            // linked chunk.
+            let hashed_linked_chunk_ids = txn
+                .prepare(
+                    r#"
+                        SELECT DISTINCT linked_chunk_id FROM linked_chunks
+                        WHERE linked_chunk_id IN (SELECT room_id FROM events)
+                    "#,
+                )?
+                .query_map((), |row| row.get::<_, Vec<u8>>(0))?
+                .map(|data| data.map(Key::Plain))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let mut linked_chunks = Vec::with_capacity(hashed_linked_chunk_ids.len());
+
+            for hashed_linked_chunk_id in hashed_linked_chunk_ids {
+                let mut chunks = Vec::new();
+
+                for data in txn
+                    .prepare(
+                        "SELECT id, previous, next, type FROM linked_chunks WHERE linked_chunk_id = ?",
+                    )?
+                    .query_map((&hashed_linked_chunk_id,), Self::map_row_to_chunk)?
+                {
+                    let (id, previous, next, chunk_type) = data?;
+                    chunks.push(txn.rebuild_chunk(
+                        &this,
+                        &hashed_linked_chunk_id,
+                        previous,
+                        id,
+                        next,
+                        chunk_type.as_str(),
+                    )?);
+                }
+
+                linked_chunks.push((hashed_linked_chunk_id, chunks));
+            }
+
+            let evictions =
+                policy.compute_evictions(linked_chunks, MilliSecondsSinceUnixEpoch::now());
+
+            for (hashed_linked_chunk_id, eviction) in evictions {
+                trace!(
+                    "evicting {} chunks and {} events",
+                    eviction.evicted_chunks.len(),
+                    eviction.evicted_events.len()
+                );
+
+                // Remove the evicted chunks, and let cascading do its job.
+                for chunk_identifier in &eviction.evicted_chunks {
+                    txn.execute(
+                        "DELETE FROM linked_chunks WHERE id = ? AND linked_chunk_id = ?",
+                        (chunk_identifier.index(), &hashed_linked_chunk_id),
+                    )?;
+                }
+
+                // Also remove the content of the evicted events, along with their
+                // aggregations, unless another linked chunk of the room still uses them.
+                for event_id in &eviction.evicted_events {
+                    let removed = txn.execute(
+                        r#"
+                            DELETE FROM events
+                            WHERE room_id = ?1 AND event_id = ?2
+                            AND NOT EXISTS (SELECT 1 FROM event_chunks WHERE event_id = ?2)
+                        "#,
+                        (&hashed_linked_chunk_id, event_id.as_str()),
+                    )?;
+
+                    if removed > 0 {
+                        txn.execute(
+                            "DELETE FROM event_aggregations WHERE room_id = ? AND event_id = ?",
+                            (&hashed_linked_chunk_id, event_id.as_str()),
+                        )?;
+                    }
+                }
+
+                let first_kept_chunk = eviction.first_kept_chunk.index();
+
+                if let Some(gap) = &eviction.gap {
+                    let serialized = serde_json::to_vec(&gap)?;
+                    let prev_token = this.encode_value(serialized)?;
+                    let new = eviction.gap_identifier().index();
+
+                    // Insert the gap before the first kept chunk.
+                    insert_chunk(
+                        txn,
+                        &hashed_linked_chunk_id,
+                        None,
+                        new,
+                        Some(first_kept_chunk),
+                        CHUNK_TYPE_GAP_TYPE_STRING,
+                    )?;
+
+                    txn.execute(
+                        r#"
+                            INSERT INTO gap_chunks(chunk_id, linked_chunk_id, prev_token)
+                            VALUES (?, ?, ?)
+                        "#,
+                        (new, &hashed_linked_chunk_id, prev_token),
+                    )?;
+                } else {
+                    // The first kept chunk is now the first chunk of the linked chunk.
+                    txn.execute(
+                        "UPDATE linked_chunks SET previous = NULL WHERE id = ? AND linked_chunk_id = ?",
+                        (first_kept_chunk, &hashed_linked_chunk_id),
+                    )?;
+                }
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
     #[instrument(skip_all)]
     async fn add_media_content(
         &self,
@@ -1295,11 +1707,12 @@ impl EventCacheStore for SqliteEventCacheStore {
 
         let new_uri = self.encode_key(keys::MEDIA, to.source.unique_key());
         let new_format = self.encode_key(keys::MEDIA, to.format.unique_key());
+        let new_request = self.serialize_value(to)?;
 
         let conn = self.write().await?;
         conn.execute(
-            r#"UPDATE media SET uri = ?, format = ? WHERE uri = ? AND format = ?"#,
-            (new_uri, new_format, prev_uri, prev_format),
+            r#"UPDATE media SET uri = ?, format = ?, request = ? WHERE uri = ? AND format = ?"#,
+            (new_uri, new_format, new_request, prev_uri, prev_format),
         )
         .await?;
 
@@ -1382,6 +1795,98 @@ impl EventCacheStore for SqliteEventCacheStore {
 
         self.media_service.clean_up_media_cache(self).await
     }
+
+    #[instrument(skip_all)]
+    async fn get_all_media_content(
+        &self,
+    ) -> Result<Vec<(MediaRequestParameters, Vec<u8>, IgnoreMediaRetentionPolicy)>, Self::Error>
+    {
+        let _timer = timer!("method");
+
+        let rows: Vec<(Vec<u8>, Vec<u8>, bool)> = self
+            .read()
+            .await?
+            .prepare(
+                "SELECT request, data, ignore_policy FROM media WHERE request IS NOT NULL",
+                |mut stmt| {
+                    stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?.collect()
+                },
+            )
+            .await?;
+
+        rows.into_iter()
+            .map(|(request, data, ignore_policy)| {
+                let ignore_policy = if ignore_policy {
+                    IgnoreMediaRetentionPolicy::Yes
+                } else {
+                    IgnoreMediaRetentionPolicy::No
+                };
+                Ok((
+                    self.deserialize_value(&request)?,
+                    self.decode_value(&data)?.into_owned(),
+                    ignore_policy,
+                ))
+            })
+            .collect()
+    }
+
+    #[instrument(skip_all)]
+    async fn storage_usage(&self) -> Result<StoreUsage, Self::Error> {
+        let _timer = timer!("method");
+
+        self.read().await?.storage_usage().await
+    }
+
+    #[instrument(skip_all)]
+    async fn room_events_usage(&self, room_id: &RoomId) -> Result<ItemsUsage, Self::Error> {
+        let _timer = timer!("method");
+
+        let hashed_room_id = self.encode_key(keys::LINKED_CHUNKS, room_id);
+
+        let (count, size) = self
+            .read()
+            .await?
+            .query_row(
+                "SELECT COUNT(*), COALESCE(SUM(length(content)), 0) FROM events WHERE room_id = ?",
+                (hashed_room_id,),
+                |row| Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?)),
+            )
+            .await?;
+
+        Ok(ItemsUsage { count, size })
+    }
+
+    #[instrument(skip_all)]
+    async fn media_usage(&self) -> Result<BTreeMap<String, ItemsUsage>, Self::Error> {
+        let _timer = timer!("method");
+
+        // The media stored before the MIME type was saved along with them have an
+        // unknown MIME type.
+        Ok(self
+            .read()
+            .await?
+            .prepare(
+                "SELECT COALESCE(mime_type, ?), COUNT(*), SUM(length(data)) FROM media
+                GROUP BY 1",
+                |mut stmt| {
+                    stmt.query_map((UNKNOWN_MIME_TYPE,), |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            ItemsUsage { count: row.get(1)?, size: row.get(2)? },
+                        ))
+                    })?
+                    .collect()
+                },
+            )
+            .await?)
+    }
+
+    #[instrument(skip_all)]
+    async fn compact(&self) -> Result<(), Self::Error> {
+        let _timer = timer!("method");
+
+        self.write().await?.compact().await
+    }
 }
 
 #[cfg_attr(target_family = "wasm", async_trait(?Send))]
@@ -1414,6 +1919,7 @@ impl EventCacheStoreMedia for SqliteEventCacheStore {
         ignore_policy: IgnoreMediaRetentionPolicy,
     ) -> Result<(), Self::Error> {
         let ignore_policy = ignore_policy.is_yes();
+        let mime_type = guess_mime_type(&data);
         let data = self.encode_value(data)?;
 
         if !ignore_policy && policy.exceeds_max_file_size(data.len() as u64) {
@@ -1424,10 +1930,12 @@ impl EventCacheStoreMedia for SqliteEventCacheStore {
         let format = self.encode_key(keys::MEDIA, request.format.unique_key());
         let timestamp = time_to_timestamp(last_access);
 
+        let request = self.serialize_value(request)?;
+
         let conn = self.write().await?;
         conn.execute(
-            "INSERT OR REPLACE INTO media (uri, format, data, last_access, ignore_policy) VALUES (?, ?, ?, ?, ?)",
-            (uri, format, data, timestamp, ignore_policy),
+            "INSERT OR REPLACE INTO media (uri, format, data, last_access, ignore_policy, request, mime_type) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            (uri, format, data, timestamp, ignore_policy, request, mime_type),
         )
         .await?;
 
@@ -1894,6 +2402,37 @@ mod tests {
         assert_eq!(store.pool.status().max_size, 42);
     }
 
+    #[async_test]
+    async fn test_storage_usage_reports_tables() {
+        let store = get_event_cache_store().await.expect("creating cache store failed");
+
+        let empty_usage = store.storage_usage().await.unwrap();
+        assert!(empty_usage.total_size.unwrap() > 0);
+        assert_eq!(empty_usage.tables["events"], 0);
+        assert_eq!(empty_usage.tables["media"], 0);
+
+        let room_id = room_id!("!r0:matrix.org");
+        store.save_event(room_id, make_test_event(room_id, "hello")).await.unwrap();
+
+        let request = MediaRequestParameters {
+            source: MediaSource::Plain(mxc_uri!("mxc://localhost/media").to_owned()),
+            format: MediaFormat::File,
+        };
+        store
+            .add_media_content(&request, b"hello".to_vec(), IgnoreMediaRetentionPolicy::No)
+            .await
+            .unwrap();
+
+        // The tables of the store are all reported, including the ones that are
+        // empty.
+        let usage = store.storage_usage().await.unwrap();
+        assert!(usage.tables["events"] > 0);
+        assert!(usage.tables["media"] > 0);
+        assert!(usage.tables.contains_key("linked_chunks"));
+        assert_eq!(usage.tables["linked_chunks"], 0);
+        assert!(usage.free_size.is_some());
+    }
+
     #[async_test]
     async fn test_last_access() {
         let event_cache_store = get_event_cache_store().await.expect("creating media cache failed");
@@ -2039,7 +2578,7 @@ mod tests {
                     previous: None,
                     new: ChunkIdentifier::new(42),
                     next: None,
-                    gap: Gap { prev_token: "raclette".to_owned() },
+                    gap: Gap::new("raclette"),
                 }],
             )
             .await
@@ -2055,7 +2594,7 @@ mod tests {
         assert_eq!(c.previous, None);
         assert_eq!(c.next, None);
         assert_matches!(c.content, ChunkContent::Gap(gap) => {
-            assert_eq!(gap.prev_token, "raclette");
+            assert_eq!(gap.prev_token.as_deref(), Some("raclette"));
         });
     }
 
@@ -2122,19 +2661,19 @@ mod tests {
                         previous: None,
                         new: ChunkIdentifier::new(42),
                         next: None,
-                        gap: Gap { prev_token: "raclette".to_owned() },
+                        gap: Gap::new("raclette"),
                     },
                     Update::NewGapChunk {
                         previous: Some(ChunkIdentifier::new(42)),
                         new: ChunkIdentifier::new(43),
                         next: None,
-                        gap: Gap { prev_token: "fondue".to_owned() },
+                        gap: Gap::new("fondue"),
                     },
                     Update::NewGapChunk {
                         previous: Some(ChunkIdentifier::new(43)),
                         new: ChunkIdentifier::new(44),
                         next: None,
-                        gap: Gap { prev_token: "tartiflette".to_owned() },
+                        gap: Gap::new("tartiflette"),
                     },
                     Update::RemoveChunk(ChunkIdentifier::new(43)),
                 ],
@@ -2152,7 +2691,7 @@ mod tests {
         assert_eq!(c.previous, None);
         assert_eq!(c.next, Some(ChunkIdentifier::new(44)));
         assert_matches!(c.content, ChunkContent::Gap(gap) => {
-            assert_eq!(gap.prev_token, "raclette");
+            assert_eq!(gap.prev_token.as_deref(), Some("raclette"));
         });
 
         let c = chunks.remove(0);
@@ -2160,7 +2699,7 @@ mod tests {
         assert_eq!(c.previous, Some(ChunkIdentifier::new(42)));
         assert_eq!(c.next, None);
         assert_matches!(c.content, ChunkContent::Gap(gap) => {
-            assert_eq!(gap.prev_token, "tartiflette");
+            assert_eq!(gap.prev_token.as_deref(), Some("tartiflette"));
         });
 
         // Check that cascading worked. Yes, SQLite, I doubt you.
@@ -2419,7 +2958,7 @@ mod tests {
                         previous: Some(ChunkIdentifier::new(42)),
                         new: ChunkIdentifier::new(54),
                         next: None,
-                        gap: Gap { prev_token: "fondue".to_owned() },
+                        gap: Gap::new("fondue"),
                     },
                     Update::PushItems {
                         at: Position::new(ChunkIdentifier::new(42), 0),
@@ -2841,6 +3380,29 @@ mod encrypted_tests {
     event_cache_store_integration_tests_time!();
     event_cache_store_media_integration_tests!();
 
+    #[async_test]
+    async fn test_change_passphrase() {
+        let room_id = room_id!("!test:localhost");
+        let event_id = event_id!("$event:localhost");
+        let tmpdir_path = TMP_DIR.path().join("change_passphrase");
+
+        let store = SqliteEventCacheStore::open(&tmpdir_path, Some("old")).await.unwrap();
+        let event = EventFactory::new()
+            .room(room_id)
+            .sender(user_id!("@alice:localhost"))
+            .text_msg("Hello")
+            .event_id(event_id)
+            .into_event();
+        store.save_event(room_id, event).await.unwrap();
+        store.change_passphrase("old", "new").await.unwrap();
+        drop(store);
+
+        SqliteEventCacheStore::open(&tmpdir_path, Some("old")).await.unwrap_err();
+
+        let store = SqliteEventCacheStore::open(&tmpdir_path, Some("new")).await.unwrap();
+        assert!(store.find_event(room_id, event_id).await.unwrap().is_some());
+    }
+
     #[async_test]
     async fn test_no_sqlite_injection_in_find_event_relations() {
         let room_id = room_id!("!test:localhost");
@@ -0,0 +1,514 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checking the integrity of the SQLite stores.
+//!
+//! A store that got corrupted, for example because the process was killed in
+//! the middle of a migration, can't be opened anymore. The functions in this
+//! module read the databases as they are, without running any migration, to
+//! tell what is wrong with them.
+
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+use deadpool_sqlite::{Object as SqliteAsyncConn, PoolConfig, Runtime};
+use matrix_sdk_store_encryption::StoreCipher;
+
+use crate::{
+    error::IntegrityError,
+    utils::{EncryptableStore, SqliteAsyncConnExt, SqliteKeyValueStoreAsyncConnExt},
+    SqliteStoreConfig,
+};
+
+/// A column of a table whose values are encrypted with the store cipher, when
+/// the store has a passphrase.
+#[derive(Debug)]
+pub(crate) struct EncryptedColumn {
+    /// The name of the table.
+    pub table: &'static str,
+
+    /// The name of the column.
+    pub column: &'static str,
+
+    /// An SQL condition selecting the rows of the table whose value is
+    /// encrypted, if it's not all of them.
+    pub condition: Option<&'static str>,
+}
+
+/// A query counting the orphaned rows of a table, i.e. the rows that
+/// reference something that doesn't exist anymore.
+#[derive(Debug)]
+pub(crate) struct OrphanedRowsQuery {
+    /// The name under which the orphaned rows are reported.
+    pub name: &'static str,
+
+    /// The SQL query, returning a single count.
+    pub query: &'static str,
+}
+
+/// The result of [`check_integrity()`].
+#[derive(Clone, Debug, Default)]
+pub struct IntegrityReport {
+    /// The integrity of the state store database, if it exists.
+    pub state_store: Option<DatabaseIntegrity>,
+
+    /// The integrity of the crypto store database, if it exists.
+    pub crypto_store: Option<DatabaseIntegrity>,
+
+    /// The integrity of the event cache store database, if it exists.
+    pub event_cache_store: Option<DatabaseIntegrity>,
+}
+
+impl IntegrityReport {
+    /// Whether no problem was found in any of the databases.
+    pub fn is_ok(&self) -> bool {
+        [&self.state_store, &self.crypto_store, &self.event_cache_store]
+            .into_iter()
+            .flatten()
+            .all(DatabaseIntegrity::is_ok)
+    }
+}
+
+/// The problems found in a single database.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DatabaseIntegrity {
+    /// The problems reported by SQLite itself, as returned by [`PRAGMA
+    /// integrity_check`], or the error that prevented the database from being
+    /// read.
+    ///
+    /// [`PRAGMA integrity_check`]: https://www.sqlite.org/pragma.html#pragma_integrity_check
+    pub sqlite_errors: Vec<String>,
+
+    /// Why the store cipher couldn't be loaded, e.g. because the passphrase
+    /// is wrong.
+    ///
+    /// If this is set, the encrypted values couldn't be checked.
+    pub cipher_error: Option<String>,
+
+    /// The number of values that can't be decrypted, for each column of
+    /// each table, as `table.column`.
+    pub undecryptable_values: BTreeMap<String, u64>,
+
+    /// The number of rows that reference something that doesn't exist
+    /// anymore, for each kind of reference.
+    pub orphaned_rows: BTreeMap<String, u64>,
+}
+
+impl DatabaseIntegrity {
+    /// Whether no problem was found in this database.
+    pub fn is_ok(&self) -> bool {
+        self.sqlite_errors.is_empty()
+            && self.cipher_error.is_none()
+            && self.undecryptable_values.is_empty()
+            && self.orphaned_rows.is_empty()
+    }
+}
+
+/// Check the integrity of the SQLite stores found at the path of the given
+/// configuration.
+///
+/// The databases are checked as they are, without running the migrations, so
+/// this can be used on stores that fail to open. For each database, this:
+///
+/// * runs [`PRAGMA integrity_check`],
+/// * if the configuration has a passphrase, checks that the store cipher can be
+///   loaded, and that every encrypted value can be decrypted with it,
+/// * for the event cache store, looks for the rows of the linked chunks that
+///   reference chunks or events that don't exist.
+///
+/// Only the databases of the stores enabled with the features of this crate
+/// are checked.
+///
+/// [`PRAGMA integrity_check`]: https://www.sqlite.org/pragma.html#pragma_integrity_check
+pub async fn check_integrity(
+    config: &SqliteStoreConfig,
+) -> Result<IntegrityReport, IntegrityError> {
+    #[allow(unused_mut)]
+    let mut report = IntegrityReport::default();
+
+    #[cfg(feature = "state-store")]
+    {
+        report.state_store = check_database(
+            &config.path.join(crate::state_store::DATABASE_NAME),
+            config.passphrase.as_deref(),
+            crate::state_store::ENCRYPTED_COLUMNS,
+            &[],
+        )
+        .await?;
+    }
+
+    #[cfg(feature = "crypto-store")]
+    {
+        report.crypto_store = check_database(
+            &config.path.join(crate::crypto_store::DATABASE_NAME),
+            config.passphrase.as_deref(),
+            crate::crypto_store::ENCRYPTED_COLUMNS,
+            &[],
+        )
+        .await?;
+    }
+
+    #[cfg(feature = "event-cache")]
+    {
+        report.event_cache_store = check_database(
+            &config.path.join(crate::event_cache_store::DATABASE_NAME),
+            config.passphrase.as_deref(),
+            crate::event_cache_store::ENCRYPTED_COLUMNS,
+            crate::event_cache_store::ORPHANED_ROWS_QUERIES,
+        )
+        .await?;
+    }
+
+    Ok(report)
+}
+
+/// Check the integrity of the database at the given path, if it exists.
+async fn check_database(
+    path: &Path,
+    passphrase: Option<&str>,
+    encrypted_columns: &'static [EncryptedColumn],
+    orphaned_rows_queries: &'static [OrphanedRowsQuery],
+) -> Result<Option<DatabaseIntegrity>, IntegrityError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let conn = open_connection(path).await?;
+    let mut integrity = DatabaseIntegrity::default();
+
+    let check = conn
+        .prepare("PRAGMA integrity_check", |mut stmt| {
+            stmt.query_map((), |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>()
+        })
+        .await;
+
+    match check {
+        Ok(lines) => integrity.sqlite_errors.extend(lines.into_iter().filter(|line| line != "ok")),
+        Err(error) => {
+            // Nothing else can be read from the database.
+            integrity.sqlite_errors.push(error.to_string());
+            return Ok(Some(integrity));
+        }
+    }
+
+    if let Some(passphrase) = passphrase {
+        match conn.load_store_cipher(passphrase).await {
+            Ok(Some(cipher)) => {
+                let decoder = CipherDecoder(Some(Arc::new(cipher)));
+
+                for encrypted_column in encrypted_columns {
+                    let decoder = decoder.clone();
+                    let count = conn
+                        .interact(move |conn| {
+                            count_undecryptable_values(conn, &decoder, encrypted_column)
+                        })
+                        .await
+                        .unwrap();
+
+                    let name = format!("{}.{}", encrypted_column.table, encrypted_column.column);
+                    match count {
+                        Ok(0) => {}
+                        Ok(count) => {
+                            integrity.undecryptable_values.insert(name, count);
+                        }
+                        Err(error) => integrity.sqlite_errors.push(format!("{name}: {error}")),
+                    }
+                }
+            }
+            Ok(None) => integrity.cipher_error = Some("The store cipher is missing".to_owned()),
+            Err(error) => integrity.cipher_error = Some(error.to_string()),
+        }
+    }
+
+    for orphaned_rows_query in orphaned_rows_queries {
+        match conn.query_row(orphaned_rows_query.query, (), |row| row.get::<_, u64>(0)).await {
+            Ok(0) => {}
+            Ok(count) => {
+                integrity.orphaned_rows.insert(orphaned_rows_query.name.to_owned(), count);
+            }
+            Err(error) => {
+                integrity.sqlite_errors.push(format!("{}: {error}", orphaned_rows_query.name))
+            }
+        }
+    }
+
+    Ok(Some(integrity))
+}
+
+/// Count the values of the given column that can't be decrypted.
+fn count_undecryptable_values(
+    conn: &rusqlite::Connection,
+    decoder: &CipherDecoder,
+    encrypted_column: &EncryptedColumn,
+) -> rusqlite::Result<u64> {
+    let EncryptedColumn { table, column, condition } = encrypted_column;
+    let condition = condition.map(|condition| format!(" AND {condition}")).unwrap_or_default();
+
+    let mut stmt = conn.prepare(&format!(
+        r#"SELECT "{column}" FROM "{table}" WHERE "{column}" IS NOT NULL{condition}"#
+    ))?;
+    let mut rows = stmt.query(())?;
+
+    let mut count = 0;
+    while let Some(row) = rows.next()? {
+        let decrypts =
+            row.get::<_, Vec<u8>>(0).is_ok_and(|value| decoder.decode_value(&value).is_ok());
+
+        if !decrypts {
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Open a connection to the database at the given path, without running the
+/// migrations.
+pub(crate) async fn open_connection(path: &Path) -> Result<SqliteAsyncConn, IntegrityError> {
+    let mut config = deadpool_sqlite::Config::new(path);
+    config.pool = Some(PoolConfig::new(1));
+
+    Ok(config.create_pool(Runtime::Tokio1)?.get().await?)
+}
+
+/// Decodes the values of a database opened with [`open_connection`].
+#[derive(Clone)]
+pub(crate) struct CipherDecoder(pub Option<Arc<StoreCipher>>);
+
+impl EncryptableStore for CipherDecoder {
+    fn get_cypher(&self) -> Option<&StoreCipher> {
+        self.0.as_deref()
+    }
+}
+
+#[cfg(all(test, feature = "state-store", feature = "crypto-store", feature = "event-cache"))]
+mod tests {
+    use std::{collections::BTreeMap, path::Path};
+
+    use matrix_sdk_base::{
+        event_cache::store::{
+            integration_tests::{make_test_event, make_test_event_with_event_id},
+            EventCacheStore,
+        },
+        linked_chunk::{ChunkIdentifier, LinkedChunkId, Position, Update},
+    };
+    use matrix_sdk_crypto::{
+        olm::{InboundGroupSession, PrivateCrossSigningIdentity},
+        store::{
+            types::{Changes, PendingChanges},
+            CryptoStore,
+        },
+        Account,
+    };
+    use matrix_sdk_test::async_test;
+    use ruma::{device_id, event_id, room_id, user_id};
+    use tempfile::tempdir;
+
+    use super::check_integrity;
+    use crate::{
+        IntegrityError, SqliteCryptoStore, SqliteEventCacheStore, SqliteStateStore,
+        SqliteStoreConfig,
+    };
+
+    const PASSPHRASE: &str = "passphrase";
+
+    /// Create the three stores in the given directory, with some data in the
+    /// crypto store and the event cache store.
+    async fn populate_stores(path: &Path) -> Vec<InboundGroupSession> {
+        SqliteStateStore::open(path, Some(PASSPHRASE)).await.unwrap();
+
+        let crypto_store = SqliteCryptoStore::open(path, Some(PASSPHRASE)).await.unwrap();
+        let account = Account::with_device_id(user_id!("@alice:localhost"), device_id!("ALICE"));
+
+        let mut sessions = Vec::new();
+        for room_id in [room_id!("!a:localhost"), room_id!("!b:localhost")] {
+            let (_, session) =
+                account.static_data().create_group_session_pair_with_defaults(room_id).await;
+            sessions.push(session);
+        }
+
+        crypto_store.save_pending_changes(PendingChanges { account: Some(account) }).await.unwrap();
+        crypto_store
+            .save_changes(Changes {
+                private_identity: Some(PrivateCrossSigningIdentity::empty(user_id!(
+                    "@alice:localhost"
+                ))),
+                inbound_group_sessions: sessions.clone(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let event_cache_store = SqliteEventCacheStore::open(path, Some(PASSPHRASE)).await.unwrap();
+        let room_id = room_id!("!a:localhost");
+        event_cache_store
+            .handle_linked_chunk_updates(
+                LinkedChunkId::Room(room_id),
+                vec![
+                    Update::NewItemsChunk {
+                        previous: None,
+                        new: ChunkIdentifier::new(0),
+                        next: None,
+                    },
+                    Update::PushItems {
+                        at: Position::new(ChunkIdentifier::new(0), 0),
+                        items: vec![
+                            make_test_event(room_id, "hello"),
+                            make_test_event_with_event_id(
+                                room_id,
+                                "world",
+                                Some(event_id!("$world")),
+                            ),
+                        ],
+                    },
+                ],
+            )
+            .await
+            .unwrap();
+
+        sessions
+    }
+
+    #[async_test]
+    async fn test_check_integrity_of_healthy_stores() {
+        let dir = tempdir().unwrap();
+
+        // There's nothing to check yet.
+        let report =
+            check_integrity(&SqliteStoreConfig::new(dir.path()).passphrase(Some(PASSPHRASE)))
+                .await
+                .unwrap();
+        assert!(report.state_store.is_none());
+        assert!(report.crypto_store.is_none());
+        assert!(report.event_cache_store.is_none());
+        assert!(report.is_ok());
+
+        populate_stores(dir.path()).await;
+
+        let report =
+            check_integrity(&SqliteStoreConfig::new(dir.path()).passphrase(Some(PASSPHRASE)))
+                .await
+                .unwrap();
+        assert!(report.is_ok(), "{report:?}");
+        assert!(report.state_store.is_some());
+        assert!(report.crypto_store.is_some());
+        assert!(report.event_cache_store.is_some());
+
+        // With the wrong passphrase, the encrypted values can't be checked.
+        let report = check_integrity(&SqliteStoreConfig::new(dir.path()).passphrase(Some("wrong")))
+            .await
+            .unwrap();
+        assert!(!report.is_ok());
+
+        let crypto_store = report.crypto_store.unwrap();
+        assert!(crypto_store.sqlite_errors.is_empty());
+        assert!(crypto_store.cipher_error.is_some());
+        assert!(crypto_store.undecryptable_values.is_empty());
+    }
+
+    #[async_test]
+    async fn test_check_integrity_of_corrupted_stores() {
+        let dir = tempdir().unwrap();
+        let sessions = populate_stores(dir.path()).await;
+
+        // Corrupt one of the inbound group sessions.
+        let conn = rusqlite::Connection::open(dir.path().join(crate::crypto_store::DATABASE_NAME))
+            .unwrap();
+        conn.execute("UPDATE inbound_group_session SET data = x'C0FFEE' WHERE rowid = 1", ())
+            .unwrap();
+
+        // Add an event to a chunk that doesn't exist, and an event that doesn't exist
+        // to a chunk.
+        let conn =
+            rusqlite::Connection::open(dir.path().join(crate::event_cache_store::DATABASE_NAME))
+                .unwrap();
+        conn.execute(
+            "INSERT INTO event_chunks(linked_chunk_id, chunk_id, event_id, position)
+             SELECT linked_chunk_id, 42, '$orphan', 0 FROM linked_chunks",
+            (),
+        )
+        .unwrap();
+        conn.execute("DELETE FROM events WHERE event_id = '$world'", ()).unwrap();
+
+        let report =
+            check_integrity(&SqliteStoreConfig::new(dir.path()).passphrase(Some(PASSPHRASE)))
+                .await
+                .unwrap();
+        assert!(!report.is_ok());
+        assert!(report.state_store.unwrap().is_ok());
+
+        let crypto_store = report.crypto_store.unwrap();
+        assert!(crypto_store.sqlite_errors.is_empty());
+        assert_eq!(
+            crypto_store.undecryptable_values,
+            BTreeMap::from([("inbound_group_session.data".to_owned(), 1)])
+        );
+
+        let event_cache_store = report.event_cache_store.unwrap();
+        assert!(event_cache_store.sqlite_errors.is_empty());
+        assert!(event_cache_store.undecryptable_values.is_empty());
+        assert_eq!(
+            event_cache_store.orphaned_rows,
+            BTreeMap::from([
+                ("event_chunks.chunk_id".to_owned(), 1),
+                ("event_chunks.event_id".to_owned(), 2),
+            ])
+        );
+
+        // Salvage what can be from the crypto store.
+        let new_dir = tempdir().unwrap();
+        let (store, salvage_report) = SqliteCryptoStore::salvage(
+            SqliteStoreConfig::new(dir.path()).passphrase(Some(PASSPHRASE)),
+            SqliteStoreConfig::new(new_dir.path()).passphrase(Some("new passphrase")),
+        )
+        .await
+        .unwrap();
+
+        assert!(salvage_report.account);
+        assert!(salvage_report.private_identity);
+        assert_eq!(salvage_report.inbound_group_sessions, 1);
+        assert_eq!(salvage_report.lost_inbound_group_sessions, 1);
+        assert_eq!(salvage_report.inbound_group_sessions_error, None);
+
+        let account = store.load_account().await.unwrap().unwrap();
+        assert_eq!(account.user_id(), user_id!("@alice:localhost"));
+        assert!(store.load_identity().await.unwrap().is_some());
+
+        let salvaged = store.get_inbound_group_sessions().await.unwrap();
+        assert_eq!(salvaged.len(), 1);
+        assert!(sessions.iter().any(|session| session.session_id() == salvaged[0].session_id()));
+
+        let report = check_integrity(
+            &SqliteStoreConfig::new(new_dir.path()).passphrase(Some("new passphrase")),
+        )
+        .await
+        .unwrap();
+        assert!(report.crypto_store.unwrap().is_ok());
+
+        // The data can't be salvaged into the corrupted store itself.
+        let result = SqliteCryptoStore::salvage(
+            SqliteStoreConfig::new(dir.path()).passphrase(Some(PASSPHRASE)),
+            SqliteStoreConfig::new(dir.path()).passphrase(Some(PASSPHRASE)),
+        )
+        .await;
+        assert!(matches!(result, Err(IntegrityError::SameStore)));
+
+        // Not even when the same path is written differently.
+        let result = SqliteCryptoStore::salvage(
+            SqliteStoreConfig::new(dir.path()).passphrase(Some(PASSPHRASE)),
+            SqliteStoreConfig::new(dir.path().join(".")).passphrase(Some(PASSPHRASE)),
+        )
+        .await;
+        assert!(matches!(result, Err(IntegrityError::SameStore)));
+    }
+}
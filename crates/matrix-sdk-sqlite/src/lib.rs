@@ -21,6 +21,7 @@ mod crypto_store;
 mod error;
 #[cfg(feature = "event-cache")]
 mod event_cache_store;
+mod integrity;
 #[cfg(feature = "state-store")]
 mod state_store;
 mod utils;
@@ -33,12 +34,15 @@ use std::{
 use deadpool_sqlite::PoolConfig;
 
 #[cfg(feature = "crypto-store")]
-pub use self::crypto_store::SqliteCryptoStore;
-pub use self::error::OpenStoreError;
+pub use self::crypto_store::{SalvageReport, SqliteCryptoStore};
 #[cfg(feature = "event-cache")]
 pub use self::event_cache_store::SqliteEventCacheStore;
 #[cfg(feature = "state-store")]
 pub use self::state_store::{SqliteStateStore, DATABASE_NAME as STATE_STORE_DATABASE_NAME};
+pub use self::{
+    error::{ChangePassphraseError, IntegrityError, OpenStoreError},
+    integrity::{check_integrity, DatabaseIntegrity, IntegrityReport},
+};
 
 #[cfg(test)]
 matrix_sdk_test::init_tracing_for_tests!();
@@ -15,6 +15,7 @@ use matrix_sdk_base::{
         DependentQueuedRequestKind, QueueWedgeError, QueuedRequest, QueuedRequestKind,
         RoomLoadSettings, SentRequestKey,
     },
+    store_usage::StoreUsage,
     MinimalRoomMemberEvent, RoomInfo, RoomMemberships, RoomState, StateChanges, StateStore,
     StateStoreDataKey, StateStoreDataValue, ROOM_VERSION_FALLBACK,
 };
@@ -42,11 +43,12 @@ use tracing::{debug, warn};
 
 use crate::{
     error::{Error, Result},
+    integrity::EncryptedColumn,
     utils::{
         repeat_vars, EncryptableStore, Key, SqliteAsyncConnExt, SqliteKeyValueStoreAsyncConnExt,
         SqliteKeyValueStoreConnExt,
     },
-    OpenStoreError, SqliteStoreConfig,
+    ChangePassphraseError, OpenStoreError, SqliteStoreConfig,
 };
 
 mod keys {
@@ -67,12 +69,34 @@ mod keys {
 /// The filename used for the SQLITE database file used by the state store.
 pub const DATABASE_NAME: &str = "matrix-sdk-state.sqlite3";
 
+/// The columns holding encrypted values, checked by
+/// [`crate::check_integrity()`].
+///
+/// The `kv_blob` table isn't listed, because the custom values are stored
+/// as-is next to the encrypted values.
+pub(crate) const ENCRYPTED_COLUMNS: &[EncryptedColumn] = &[
+    EncryptedColumn { table: "room_info", column: "data", condition: None },
+    EncryptedColumn { table: "state_event", column: "data", condition: None },
+    EncryptedColumn { table: "global_account_data", column: "data", condition: None },
+    EncryptedColumn { table: "room_account_data", column: "data", condition: None },
+    EncryptedColumn { table: "member", column: "data", condition: None },
+    EncryptedColumn { table: "profile", column: "data", condition: None },
+    EncryptedColumn { table: "receipt", column: "data", condition: None },
+    EncryptedColumn { table: "display_name", column: "data", condition: None },
+    EncryptedColumn { table: "send_queue_events", column: "room_id_val", condition: None },
+    EncryptedColumn { table: "send_queue_events", column: "content", condition: None },
+    EncryptedColumn { table: "send_queue_events", column: "wedge_reason", condition: None },
+    EncryptedColumn { table: "dependent_send_queue_events", column: "parent_key", condition: None },
+    EncryptedColumn { table: "dependent_send_queue_events", column: "content", condition: None },
+    EncryptedColumn { table: "custom_value_key", column: "name", condition: None },
+];
+
 /// Identifier of the latest database version.
 ///
 /// This is used to figure whether the SQLite database requires a migration.
 /// Every new SQL migration should imply a bump of this number, and changes in
 /// the [`SqliteStateStore::run_migrations`] function.
-const DATABASE_VERSION: u8 = 12;
+const DATABASE_VERSION: u8 = 13;
 
 /// An SQLite-based state store.
 #[derive(Clone)]
@@ -115,6 +139,51 @@ impl SqliteStateStore {
         Ok(this)
     }
 
+    /// Prepare a change of the passphrase used to encrypt this store.
+    ///
+    /// The key encrypting the data of the store is exported with
+    /// `new_passphrase` and saved next to the current export, so the store
+    /// can be opened with either passphrase until
+    /// [`SqliteStateStore::commit_passphrase_change`] is called.
+    ///
+    /// Splitting the change in two steps allows to change the passphrase of
+    /// several stores consistently: if the change is interrupted after it was
+    /// prepared on all the stores, they can all be opened with the new
+    /// passphrase.
+    pub async fn prepare_passphrase_change(
+        &self,
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<(), ChangePassphraseError> {
+        if self.store_cipher.is_none() {
+            return Err(ChangePassphraseError::NotEncrypted);
+        }
+
+        self.pool.get().await?.prepare_store_cipher_change(old_passphrase, new_passphrase).await
+    }
+
+    /// Commit the change of passphrase prepared with
+    /// [`SqliteStateStore::prepare_passphrase_change`].
+    ///
+    /// Once this returns, the store can only be opened with the new
+    /// passphrase.
+    pub async fn commit_passphrase_change(&self) -> Result<(), ChangePassphraseError> {
+        self.pool.get().await?.commit_store_cipher_change().await
+    }
+
+    /// Change the passphrase used to encrypt this store.
+    ///
+    /// Only the key encrypting the data is re-encrypted, atomically, so the
+    /// data itself is left untouched.
+    pub async fn change_passphrase(
+        &self,
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<(), ChangePassphraseError> {
+        self.prepare_passphrase_change(old_passphrase, new_passphrase).await?;
+        self.commit_passphrase_change().await
+    }
+
     /// Create an SQLite-based state store using the given SQLite database pool.
     /// The given passphrase will be used to encrypt private data.
     async fn open_with_pool(
@@ -356,6 +425,36 @@ impl SqliteStateStore {
             conn.set_kv("version", vec![12]).await?;
         }
 
+        if from < 13 && to >= 13 {
+            let this = self.clone();
+            conn.with_transaction(move |txn| {
+                // Create the table listing the keys of the custom values.
+                txn.execute_batch(include_str!(
+                    "../migrations/state_store/011_custom_value_keys.sql"
+                ))?;
+
+                // Without a cipher, the keys of the custom values are stored in clear, so we
+                // can backfill the new table. With a cipher, the keys are hashed and will
+                // only be recorded the next time they are read or written.
+                if this.store_cipher.is_none() {
+                    for key in
+                        txn.prepare("SELECT key FROM kv_blob WHERE substr(key, 1, 7) = ?")?
+                            .query_map((b"custom:".as_slice(),), |row| row.get::<_, Vec<u8>>(0))?
+                    {
+                        let key = key?;
+                        let name = key[b"custom:".len()..].to_vec();
+                        txn.prepare_cached(
+                            "INSERT OR REPLACE INTO custom_value_key (key, name) VALUES (?, ?)",
+                        )?
+                        .execute((key, name))?;
+                    }
+                }
+
+                txn.set_db_version(13)
+            })
+            .await?;
+        }
+
         Ok(())
     }
 
@@ -761,6 +860,30 @@ trait SqliteObjectStateStoreExt: SqliteAsyncConnExt {
         Ok(())
     }
 
+    async fn set_custom_value_key(&self, key: Key, name: Vec<u8>) -> Result<()> {
+        self.execute(
+            "INSERT OR REPLACE INTO custom_value_key (key, name) VALUES (?, ?)",
+            (key, name),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_custom_value_key(&self, key: Key) -> Result<()> {
+        self.execute("DELETE FROM custom_value_key WHERE key = ?", (key,)).await?;
+        Ok(())
+    }
+
+    async fn get_custom_values(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        Ok(self
+            .prepare(
+                "SELECT custom_value_key.name, kv_blob.value FROM custom_value_key
+                 INNER JOIN kv_blob ON kv_blob.key = custom_value_key.key",
+                |mut stmt| stmt.query(())?.mapped(|row| Ok((row.get(0)?, row.get(1)?))).collect(),
+            )
+            .await?)
+    }
+
     async fn get_room_infos(&self, room_id: Option<Key>) -> Result<Vec<Vec<u8>>> {
         Ok(match room_id {
             None => {
@@ -823,6 +946,17 @@ trait SqliteObjectStateStoreExt: SqliteAsyncConnExt {
             .await?)
     }
 
+    async fn get_all_maybe_stripped_state_events(
+        &self,
+        room_id: Key,
+    ) -> Result<Vec<(bool, Vec<u8>)>> {
+        Ok(self
+            .prepare("SELECT stripped, data FROM state_event WHERE room_id = ?", |mut stmt| {
+                stmt.query((room_id,))?.mapped(|row| Ok((row.get(0)?, row.get(1)?))).collect()
+            })
+            .await?)
+    }
+
     async fn get_profiles(
         &self,
         room_id: Key,
@@ -886,6 +1020,22 @@ trait SqliteObjectStateStoreExt: SqliteAsyncConnExt {
             .optional()?)
     }
 
+    async fn get_all_global_account_data(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .prepare("SELECT data FROM global_account_data", |mut stmt| {
+                stmt.query(())?.mapped(|row| row.get(0)).collect()
+            })
+            .await?)
+    }
+
+    async fn get_all_room_account_data(&self, room_id: Key) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .prepare("SELECT data FROM room_account_data WHERE room_id = ?", |mut stmt| {
+                stmt.query((room_id,))?.mapped(|row| row.get(0)).collect()
+            })
+            .await?)
+    }
+
     async fn get_room_account_data(
         &self,
         room_id: Key,
@@ -1459,6 +1609,28 @@ impl StateStore for SqliteStateStore {
             .collect()
     }
 
+    async fn get_all_state_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<RawAnySyncOrStrippedState>> {
+        let room_id = self.encode_key(keys::STATE_EVENT, room_id);
+        self.acquire()
+            .await?
+            .get_all_maybe_stripped_state_events(room_id)
+            .await?
+            .into_iter()
+            .map(|(stripped, data)| {
+                let ev = if stripped {
+                    RawAnySyncOrStrippedState::Stripped(self.deserialize_json(&data)?)
+                } else {
+                    RawAnySyncOrStrippedState::Sync(self.deserialize_json(&data)?)
+                };
+
+                Ok(ev)
+            })
+            .collect()
+    }
+
     async fn get_profile(
         &self,
         room_id: &RoomId,
@@ -1641,6 +1813,30 @@ impl StateStore for SqliteStateStore {
             .transpose()
     }
 
+    async fn get_all_account_data_events(&self) -> Result<Vec<Raw<AnyGlobalAccountDataEvent>>> {
+        self.acquire()
+            .await?
+            .get_all_global_account_data()
+            .await?
+            .iter()
+            .map(|value| self.deserialize_json(value))
+            .collect()
+    }
+
+    async fn get_all_room_account_data_events(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<Raw<AnyRoomAccountDataEvent>>> {
+        let room_id = self.encode_key(keys::ROOM_ACCOUNT_DATA, room_id);
+        self.acquire()
+            .await?
+            .get_all_room_account_data(room_id)
+            .await?
+            .iter()
+            .map(|value| self.deserialize_json(value))
+            .collect()
+    }
+
     async fn get_user_room_receipt_event(
         &self,
         room_id: &RoomId,
@@ -1696,16 +1892,20 @@ impl StateStore for SqliteStateStore {
 
     async fn set_custom_value_no_read(&self, key: &[u8], value: Vec<u8>) -> Result<()> {
         let conn = self.acquire().await?;
+        let name = self.encode_value(key.to_owned())?;
         let key = self.encode_custom_key(key);
-        conn.set_kv_blob(key, value).await?;
+        conn.set_kv_blob(key.clone(), value).await?;
+        conn.set_custom_value_key(key, name).await?;
         Ok(())
     }
 
     async fn set_custom_value(&self, key: &[u8], value: Vec<u8>) -> Result<Option<Vec<u8>>> {
         let conn = self.acquire().await?;
+        let name = self.encode_value(key.to_owned())?;
         let key = self.encode_custom_key(key);
         let previous = conn.get_kv_blob(key.clone()).await?;
-        conn.set_kv_blob(key, value).await?;
+        conn.set_kv_blob(key.clone(), value).await?;
+        conn.set_custom_value_key(key, name).await?;
         Ok(previous)
     }
 
@@ -1714,11 +1914,22 @@ impl StateStore for SqliteStateStore {
         let key = self.encode_custom_key(key);
         let previous = conn.get_kv_blob(key.clone()).await?;
         if previous.is_some() {
-            conn.delete_kv_blob(key).await?;
+            conn.delete_kv_blob(key.clone()).await?;
         }
+        conn.delete_custom_value_key(key).await?;
         Ok(previous)
     }
 
+    async fn get_all_custom_values(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.acquire()
+            .await?
+            .get_custom_values()
+            .await?
+            .into_iter()
+            .map(|(name, value)| Ok((self.decode_value(&name)?.into_owned(), value)))
+            .collect()
+    }
+
     async fn remove_room(&self, room_id: &RoomId) -> Result<()> {
         let this = self.clone();
         let room_id = room_id.to_owned();
@@ -2074,6 +2285,14 @@ impl StateStore for SqliteStateStore {
 
         Ok(dependent_events)
     }
+
+    async fn storage_usage(&self) -> Result<StoreUsage, Self::Error> {
+        self.acquire().await?.storage_usage().await
+    }
+
+    async fn compact(&self) -> Result<(), Self::Error> {
+        self.acquire().await?.compact().await
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -2115,13 +2334,23 @@ mod encrypted_tests {
         sync::atomic::{AtomicU32, Ordering::SeqCst},
     };
 
-    use matrix_sdk_base::{statestore_integration_tests, StateStore, StoreError};
+    use assert_matches::assert_matches;
+    use matrix_sdk_base::{
+        statestore_integration_tests,
+        store::{
+            IntoStateStore, MemoryStore, RoomLoadSettings, StateStoreDataKey,
+            StateStoreIntegrationTests, StoreMigrator,
+        },
+        StateStore, StoreError,
+    };
     use matrix_sdk_test::async_test;
     use once_cell::sync::Lazy;
     use tempfile::{tempdir, TempDir};
 
     use super::SqliteStateStore;
-    use crate::{utils::SqliteAsyncConnExt, SqliteStoreConfig};
+    use crate::{
+        utils::SqliteAsyncConnExt, ChangePassphraseError, OpenStoreError, SqliteStoreConfig,
+    };
 
     static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
     static NUM: AtomicU32 = AtomicU32::new(0);
@@ -2186,6 +2415,124 @@ mod encrypted_tests {
         assert_eq!(journal_size_limit, 1500);
     }
 
+    #[async_test]
+    async fn test_change_passphrase() {
+        let tmpdir_path = new_state_store_workspace();
+
+        let store = SqliteStateStore::open(&tmpdir_path, Some("old")).await.unwrap();
+        store.set_custom_value(b"key", b"value".to_vec()).await.unwrap();
+        store.change_passphrase("old", "new").await.unwrap();
+
+        // The data is still readable with the current connection.
+        assert_eq!(store.get_custom_value(b"key").await.unwrap().unwrap(), b"value");
+        drop(store);
+
+        // The old passphrase doesn't work anymore.
+        assert_matches!(
+            SqliteStateStore::open(&tmpdir_path, Some("old")).await,
+            Err(OpenStoreError::InitCipher(_))
+        );
+
+        // The new passphrase decrypts the same data.
+        let store = SqliteStateStore::open(&tmpdir_path, Some("new")).await.unwrap();
+        assert_eq!(store.get_custom_value(b"key").await.unwrap().unwrap(), b"value");
+    }
+
+    #[async_test]
+    async fn test_change_passphrase_with_wrong_old_passphrase() {
+        let tmpdir_path = new_state_store_workspace();
+
+        let store = SqliteStateStore::open(&tmpdir_path, Some("old")).await.unwrap();
+        assert_matches!(
+            store.change_passphrase("wrong", "new").await,
+            Err(ChangePassphraseError::Cipher(_))
+        );
+        assert_matches!(
+            store.commit_passphrase_change().await,
+            Err(ChangePassphraseError::NotPrepared)
+        );
+        drop(store);
+
+        SqliteStateStore::open(&tmpdir_path, Some("old")).await.unwrap();
+        SqliteStateStore::open(&tmpdir_path, Some("new")).await.unwrap_err();
+    }
+
+    #[async_test]
+    async fn test_change_passphrase_of_unencrypted_store() {
+        let store = SqliteStateStore::open(new_state_store_workspace(), None).await.unwrap();
+
+        assert_matches!(
+            store.change_passphrase("old", "new").await,
+            Err(ChangePassphraseError::NotEncrypted)
+        );
+    }
+
+    #[async_test]
+    async fn test_interrupted_passphrase_change() {
+        let tmpdir_path = new_state_store_workspace();
+
+        let store = SqliteStateStore::open(&tmpdir_path, Some("old")).await.unwrap();
+        store.set_custom_value(b"key", b"value".to_vec()).await.unwrap();
+        store.prepare_passphrase_change("old", "new").await.unwrap();
+        drop(store);
+
+        // Until the change is committed, both passphrases work.
+        SqliteStateStore::open(&tmpdir_path, Some("old")).await.unwrap();
+        let store = SqliteStateStore::open(&tmpdir_path, Some("new")).await.unwrap();
+        assert_eq!(store.get_custom_value(b"key").await.unwrap().unwrap(), b"value");
+
+        // Retrying the whole change completes it.
+        store.change_passphrase("old", "new").await.unwrap();
+        drop(store);
+
+        SqliteStateStore::open(&tmpdir_path, Some("old")).await.unwrap_err();
+        SqliteStateStore::open(&tmpdir_path, Some("new")).await.unwrap();
+    }
+
+    #[async_test]
+    async fn test_retry_committed_passphrase_change() {
+        let tmpdir_path = new_state_store_workspace();
+
+        let store = SqliteStateStore::open(&tmpdir_path, Some("old")).await.unwrap();
+        store.change_passphrase("old", "new").await.unwrap();
+
+        // Another store could have failed to change its passphrase, so retrying
+        // the change on a store which already uses the new passphrase succeeds.
+        store.change_passphrase("old", "new").await.unwrap();
+        drop(store);
+
+        SqliteStateStore::open(&tmpdir_path, Some("new")).await.unwrap();
+    }
+
+    #[async_test]
+    async fn test_migrate_from_memory_store() {
+        let tmpdir_path = new_state_store_workspace();
+
+        let from = MemoryStore::new().into_state_store();
+        from.populate().await.unwrap();
+
+        let to = SqliteStateStore::open(&tmpdir_path, Some("passphrase")).await.unwrap();
+        let report = StoreMigrator::new().state_store(from.clone(), to).migrate().await.unwrap();
+        assert_eq!(report.rooms, 2);
+
+        // The migrated data can be read back with the passphrase.
+        let store = SqliteStateStore::open(&tmpdir_path, Some("passphrase")).await.unwrap();
+        assert_eq!(store.get_room_infos(&RoomLoadSettings::All).await.unwrap().len(), 2);
+        assert_eq!(
+            store
+                .get_kv_data(StateStoreDataKey::SyncToken)
+                .await
+                .unwrap()
+                .unwrap()
+                .into_sync_token(),
+            from.get_kv_data(StateStoreDataKey::SyncToken)
+                .await
+                .unwrap()
+                .unwrap()
+                .into_sync_token(),
+        );
+    }
+
     statestore_integration_tests!();
 }
 
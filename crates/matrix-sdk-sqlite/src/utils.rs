@@ -16,6 +16,7 @@ use core::fmt;
 use std::{
     borrow::{Borrow, Cow},
     cmp::min,
+    collections::BTreeMap,
     iter,
     ops::Deref,
 };
@@ -23,6 +24,7 @@ use std::{
 use async_trait::async_trait;
 use deadpool_sqlite::Object as SqliteAsyncConn;
 use itertools::Itertools;
+use matrix_sdk_common::store_usage::StoreUsage;
 use matrix_sdk_store_encryption::StoreCipher;
 use ruma::{serde::Raw, time::SystemTime, OwnedEventId, OwnedRoomId};
 use rusqlite::{limits::Limit, OptionalExtension, Params, Row, Statement, Transaction};
@@ -31,7 +33,7 @@ use tracing::{error, warn};
 
 use crate::{
     error::{Error, Result},
-    OpenStoreError, RuntimeConfig,
+    ChangePassphraseError, OpenStoreError, RuntimeConfig,
 };
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -199,6 +201,104 @@ pub(crate) trait SqliteAsyncConnExt {
 
         Ok(())
     }
+
+    /// Compact the database: defragment it, give the free space back to the
+    /// filesystem, and truncate the WAL file.
+    ///
+    /// See [`Self::vacuum`] and [`Self::optimize`], and
+    /// [`PRAGMA wal_checkpoint`] to learn more.
+    ///
+    /// [`PRAGMA wal_checkpoint`]: https://www.sqlite.org/pragma.html#pragma_wal_checkpoint
+    async fn compact(&self) -> Result<()> {
+        self.vacuum().await?;
+        self.optimize().await?;
+        self.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);").await?;
+        Ok(())
+    }
+
+    /// Get how much space the database is using.
+    ///
+    /// The total and free sizes are computed from the number of pages, see
+    /// [`PRAGMA page_count`] and [`PRAGMA freelist_count`]. The total size
+    /// includes the size of the WAL file. The size of a table is the size of
+    /// the payloads of its pages, as reported by the [`dbstat`] virtual table,
+    /// and doesn't include the size of its indexes.
+    ///
+    /// This only reads the database, outside of a transaction, so it doesn't
+    /// block the writers.
+    ///
+    /// [`PRAGMA page_count`]: https://www.sqlite.org/pragma.html#pragma_page_count
+    /// [`PRAGMA freelist_count`]: https://www.sqlite.org/pragma.html#pragma_freelist_count
+    /// [`dbstat`]: https://www.sqlite.org/dbstat.html
+    async fn storage_usage(&self) -> Result<StoreUsage> {
+        let page_size: u64 = self.query_row("PRAGMA page_size", (), |row| row.get(0)).await?;
+        let page_count: u64 = self.query_row("PRAGMA page_count", (), |row| row.get(0)).await?;
+        let freelist_count: u64 =
+            self.query_row("PRAGMA freelist_count", (), |row| row.get(0)).await?;
+
+        // The pages that weren't checkpointed yet are in the WAL file. The closure
+        // runs on the thread of the connection, so reading the size of the file
+        // doesn't block the executor.
+        let wal_size = self
+            .prepare("SELECT file FROM pragma_database_list WHERE name = 'main'", |mut stmt| {
+                let path: String = stmt.query_row((), |row| row.get(0))?;
+
+                // The path is empty for in-memory databases.
+                Ok((!path.is_empty())
+                    .then(|| std::fs::metadata(format!("{path}-wal")).ok())
+                    .flatten()
+                    .map_or(0, |metadata| metadata.len()))
+            })
+            .await?;
+
+        let tables = match self.table_sizes().await {
+            Ok(tables) => tables,
+            Err(error) => {
+                warn!("Failed to compute the size of the tables: {error}");
+                BTreeMap::new()
+            }
+        };
+
+        Ok(StoreUsage {
+            total_size: Some(page_count * page_size + wal_size),
+            free_size: Some(freelist_count * page_size),
+            tables,
+        })
+    }
+
+    /// Get the size of the payloads of each table of the database, with the
+    /// [`dbstat`] virtual table.
+    ///
+    /// [`dbstat`]: https://www.sqlite.org/dbstat.html
+    async fn table_sizes(&self) -> rusqlite::Result<BTreeMap<String, u64>> {
+        let mut tables = self
+            .prepare(
+                "SELECT name FROM sqlite_schema WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+                |mut stmt| {
+                    stmt.query_map((), |row| Ok((row.get::<_, String>(0)?, 0))).and_then(|rows| {
+                        rows.collect::<rusqlite::Result<BTreeMap<_, _>>>()
+                    })
+                },
+            )
+            .await?;
+
+        // With `aggregate = TRUE`, there is a single row for each table or index,
+        // with the sum of the payloads of all its pages.
+        let sizes = self
+            .prepare("SELECT name, payload FROM dbstat WHERE aggregate = TRUE", |mut stmt| {
+                stmt.query_map((), |row| Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?)))
+                    .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+            })
+            .await?;
+
+        for (name, size) in sizes {
+            if let Some(table_size) = tables.get_mut(&name) {
+                *table_size = size;
+            }
+        }
+
+        Ok(tables)
+    }
 }
 
 #[async_trait]
@@ -455,26 +555,124 @@ pub(crate) trait SqliteKeyValueStoreAsyncConnExt: SqliteAsyncConnExt {
     }
 
     /// Get the [`StoreCipher`] of the database or create it.
+    ///
+    /// If a passphrase change was prepared but not committed, the store cipher
+    /// can be imported with either the old or the new passphrase.
     async fn get_or_create_store_cipher(
         &self,
         passphrase: &str,
     ) -> Result<StoreCipher, OpenStoreError> {
-        let encrypted_cipher = self.get_kv("cipher").await.map_err(OpenStoreError::LoadCipher)?;
-
-        let cipher = if let Some(encrypted) = encrypted_cipher {
-            StoreCipher::import(passphrase, &encrypted)?
+        let cipher = if let Some(cipher) = self.load_store_cipher(passphrase).await? {
+            cipher
         } else {
             let cipher = StoreCipher::new()?;
-            #[cfg(not(test))]
-            let export = cipher.export(passphrase);
-            #[cfg(test)]
-            let export = cipher._insecure_export_fast_for_testing(passphrase);
-            self.set_kv("cipher", export?).await.map_err(OpenStoreError::SaveCipher)?;
+            self.set_kv("cipher", export_store_cipher(&cipher, passphrase)?)
+                .await
+                .map_err(OpenStoreError::SaveCipher)?;
             cipher
         };
 
         Ok(cipher)
     }
+
+    /// Get the [`StoreCipher`] of the database, if it has one.
+    ///
+    /// If a passphrase change was prepared but not committed, the store cipher
+    /// can be imported with either the old or the new passphrase.
+    async fn load_store_cipher(
+        &self,
+        passphrase: &str,
+    ) -> Result<Option<StoreCipher>, OpenStoreError> {
+        let Some(encrypted) = self.get_kv("cipher").await.map_err(OpenStoreError::LoadCipher)?
+        else {
+            return Ok(None);
+        };
+
+        let cipher = match StoreCipher::import(passphrase, &encrypted) {
+            Ok(cipher) => cipher,
+            Err(error) => {
+                let next_encrypted = self
+                    .get_kv("cipher_next")
+                    .await
+                    .map_err(OpenStoreError::LoadCipher)?
+                    .ok_or(error)?;
+
+                StoreCipher::import(passphrase, &next_encrypted)?
+            }
+        };
+
+        Ok(Some(cipher))
+    }
+
+    /// Export the [`StoreCipher`] of the database with a new passphrase, and
+    /// save it next to the current one.
+    ///
+    /// The new passphrase only replaces the current one once
+    /// [`Self::commit_store_cipher_change`] is called. Until then, the store
+    /// cipher can be imported with both passphrases.
+    ///
+    /// If the store cipher can already be imported with the new passphrase,
+    /// e.g. because a previous change was interrupted, the change is prepared
+    /// as well, so it can be retried.
+    async fn prepare_store_cipher_change(
+        &self,
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<(), ChangePassphraseError> {
+        let encrypted = self.get_kv("cipher").await?.ok_or(ChangePassphraseError::NotEncrypted)?;
+        let next_encrypted = self.get_kv("cipher_next").await?;
+
+        let cipher = match StoreCipher::import(old_passphrase, &encrypted) {
+            Ok(cipher) => cipher,
+            Err(error) => {
+                match next_encrypted
+                    .and_then(|next| StoreCipher::import(old_passphrase, &next).ok())
+                {
+                    Some(cipher) => cipher,
+                    None if StoreCipher::import(new_passphrase, &encrypted).is_ok() => {
+                        self.set_kv("cipher_next", encrypted).await?;
+                        return Ok(());
+                    }
+                    None => return Err(error.into()),
+                }
+            }
+        };
+
+        self.set_kv("cipher_next", export_store_cipher(&cipher, new_passphrase)?).await?;
+
+        Ok(())
+    }
+
+    /// Replace the [`StoreCipher`] of the database with the one saved by
+    /// [`Self::prepare_store_cipher_change`], atomically.
+    async fn commit_store_cipher_change(&self) -> Result<(), ChangePassphraseError> {
+        self.with_transaction(|txn| {
+            let next_encrypted = txn
+                .query_row("SELECT value FROM kv WHERE key = 'cipher_next'", (), |row| {
+                    row.get::<_, Vec<u8>>(0)
+                })
+                .optional()?
+                .ok_or(ChangePassphraseError::NotPrepared)?;
+
+            txn.set_kv("cipher", &next_encrypted)?;
+            txn.clear_kv("cipher_next")?;
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Export the given [`StoreCipher`] with the given passphrase.
+fn export_store_cipher(
+    cipher: &StoreCipher,
+    passphrase: &str,
+) -> Result<Vec<u8>, matrix_sdk_store_encryption::Error> {
+    if cfg!(test) {
+        cipher._insecure_export_fast_for_testing(passphrase)
+    } else {
+        cipher.export(passphrase)
+    }
 }
 
 #[async_trait]
@@ -30,7 +30,8 @@ use ruma::{
     directory::RoomTypeFilter,
     events::{
         AnyFullStateEventContent, AnyMessageLikeEventContent, AnyStateEvent,
-        AnySyncMessageLikeEvent, AnySyncTimelineEvent, FullStateEventContent, StateEventType,
+        AnySyncMessageLikeEvent, AnySyncStateEvent, AnySyncTimelineEvent, FullStateEventContent,
+        StateEventType,
         TimelineEventType,
         room::{
             join_rules::JoinRule,
@@ -53,6 +54,10 @@ use crate::{
     sync_service::SyncService,
 };
 
+mod push;
+
+pub use self::push::{PushDevice, PushNotification, PushNotificationCounts, PushPriority};
+
 /// What kind of process setup do we have for this notification client?
 #[derive(Clone)]
 pub enum NotificationProcessSetup {
@@ -196,6 +201,25 @@ impl NotificationClient {
         Ok(notifications)
     }
 
+    /// Fetches the content of the event a push notification is about.
+    ///
+    /// This works the same way as [`Self::get_notification`], for
+    /// notifications in both the full and the `event_id_only` formats.
+    ///
+    /// Returns `None` if the notification isn't about an event, e.g. if it only
+    /// updates the counters of the user.
+    pub async fn get_notification_for_push(
+        &self,
+        notification: &PushNotification,
+    ) -> Result<Option<NotificationStatus>, Error> {
+        let Some((room_id, event_id)) = notification.event() else {
+            debug!("the push notification isn't about an event");
+            return Ok(None);
+        };
+
+        self.get_notification(room_id, event_id).await.map(Some)
+    }
+
     /// Run an encryption sync loop, in case an event is still encrypted.
     ///
     /// Will return `Ok(Some)` if and only if:
@@ -699,7 +723,9 @@ impl NotificationClient {
 
             match status {
                 NotificationStatus::Event(event) => {
-                    if self.client.is_user_ignored(event.event.sender()).await {
+                    if self.client.is_user_ignored(event.event.sender()).await
+                        || self.is_filtered_invite(&room_id, &event.event).await
+                    {
                         batch_result.insert(event_id, Ok(NotificationStatus::EventFilteredOut));
                     } else {
                         batch_result.insert(event_id, Ok(NotificationStatus::Event(event)));
@@ -714,6 +740,44 @@ impl NotificationClient {
         Ok(batch_result)
     }
 
+    /// Whether the given event is an invite blocked by the invite filter of
+    /// the parent client (see [`Client::set_invite_filter`]), so it must not
+    /// trigger a notification.
+    ///
+    /// The parent client is used because the shared rooms with the inviter
+    /// are only known by its store. The filter and the quarantined invites are
+    /// loaded from that store by [`Client::check_invite`], so they're the ones
+    /// set by the main process of the app, even if this runs in another
+    /// process.
+    async fn is_filtered_invite(&self, room_id: &RoomId, event: &NotificationEvent) -> bool {
+        let inviter = match event {
+            NotificationEvent::Invite(invite) => &invite.sender,
+            // An invite found with a `/context` query is a member event of the timeline.
+            NotificationEvent::Timeline(event) => {
+                let AnySyncTimelineEvent::State(AnySyncStateEvent::RoomMember(member)) =
+                    event.as_ref()
+                else {
+                    return false;
+                };
+
+                let is_own_invite = *member.membership() == MembershipState::Invite
+                    && self
+                        .parent_client
+                        .user_id()
+                        .is_some_and(|own_user_id| member.state_key() == own_user_id);
+                if !is_own_invite {
+                    return false;
+                }
+
+                member.sender()
+            }
+        };
+
+        // Check the invite first, to load the quarantined invites.
+        self.parent_client.check_invite(inviter, room_id).await.is_some()
+            || self.parent_client.is_invite_quarantined(room_id)
+    }
+
     /// Retrieve a notification using a `/context` query.
     ///
     /// This is for clients that are already running other sliding syncs in the
@@ -762,7 +826,9 @@ impl NotificationClient {
         )
         .await?;
 
-        if self.client.is_user_ignored(notification_item.event.sender()).await {
+        if self.client.is_user_ignored(notification_item.event.sender()).await
+            || self.is_filtered_invite(room_id, &notification_item.event).await
+        {
             Ok(NotificationStatus::EventFilteredOut)
         } else {
             Ok(NotificationStatus::Event(Box::new(notification_item)))
@@ -0,0 +1,279 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of the notifications a homeserver sends to a [push gateway].
+//!
+//! [push gateway]: https://spec.matrix.org/latest/push-gateway-api/
+
+use ruma::{
+    EventId, OwnedEventId, OwnedRoomAliasId, OwnedRoomId, OwnedUserId, RoomId,
+    SecondsSinceUnixEpoch, UInt, events::TimelineEventType, serde::JsonObject,
+};
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+
+use super::NotificationItemsRequest;
+
+/// A notification sent by a homeserver to a push gateway, in either the full
+/// format or the `event_id_only` format.
+///
+/// Use [`NotificationClient::get_notification_for_push`] to fetch the full
+/// content of the event it's about.
+///
+/// [`NotificationClient::get_notification_for_push`]: super::NotificationClient::get_notification_for_push
+#[derive(Clone, Debug, Deserialize)]
+pub struct PushNotification {
+    /// The ID of the event that triggered the notification, if any.
+    pub event_id: Option<OwnedEventId>,
+
+    /// The ID of the room the event was sent in, if any.
+    pub room_id: Option<OwnedRoomId>,
+
+    /// The type of the event, if not in the `event_id_only` format.
+    #[serde(rename = "type")]
+    pub event_type: Option<TimelineEventType>,
+
+    /// The sender of the event, if not in the `event_id_only` format.
+    pub sender: Option<OwnedUserId>,
+
+    /// The display name of the sender in the room, if any.
+    pub sender_display_name: Option<String>,
+
+    /// The name of the room, if any.
+    pub room_name: Option<String>,
+
+    /// The canonical alias of the room, if any.
+    pub room_alias: Option<OwnedRoomAliasId>,
+
+    /// Whether the user receiving the notification is the subject of a member
+    /// event.
+    #[serde(default)]
+    pub user_is_target: bool,
+
+    /// The priority of the notification.
+    #[serde(default)]
+    pub prio: PushPriority,
+
+    /// The content of the event, if not in the `event_id_only` format.
+    pub content: Option<JsonValue>,
+
+    /// The counters of the user, to display as a badge on the app icon.
+    #[serde(default)]
+    pub counts: PushNotificationCounts,
+
+    /// The devices the notification should be delivered to.
+    #[serde(default)]
+    pub devices: Vec<PushDevice>,
+}
+
+impl PushNotification {
+    /// Parse the body of a `POST /_matrix/push/v1/notify` request, as sent by
+    /// a homeserver to a push gateway.
+    pub fn from_request_body(body: &[u8]) -> Result<Self, serde_json::Error> {
+        #[derive(Deserialize)]
+        struct RequestBody {
+            notification: PushNotification,
+        }
+
+        Ok(serde_json::from_slice::<RequestBody>(body)?.notification)
+    }
+
+    /// Whether this notification uses the `event_id_only` format, i.e. it
+    /// refers to an event but doesn't contain any of its details.
+    pub fn is_event_id_only(&self) -> bool {
+        self.event_id.is_some()
+            && self.event_type.is_none()
+            && self.sender.is_none()
+            && self.content.is_none()
+    }
+
+    /// The room and event this notification is about.
+    ///
+    /// Returns `None` if the notification isn't about an event, e.g. if it only
+    /// updates the counters of the user.
+    pub fn event(&self) -> Option<(&RoomId, &EventId)> {
+        Some((self.room_id.as_deref()?, self.event_id.as_deref()?))
+    }
+
+    /// Group the events of the given notifications by room, to fetch them in a
+    /// single [`NotificationClient::get_notifications`] call.
+    ///
+    /// The notifications that aren't about an event are skipped.
+    ///
+    /// [`NotificationClient::get_notifications`]: super::NotificationClient::get_notifications
+    pub fn to_items_requests<'a>(
+        notifications: impl IntoIterator<Item = &'a PushNotification>,
+    ) -> Vec<NotificationItemsRequest> {
+        let mut requests: Vec<NotificationItemsRequest> = Vec::new();
+
+        for (room_id, event_id) in notifications.into_iter().filter_map(Self::event) {
+            match requests.iter_mut().find(|request| request.room_id == room_id) {
+                Some(request) => request.event_ids.push(event_id.to_owned()),
+                None => requests.push(NotificationItemsRequest {
+                    room_id: room_id.to_owned(),
+                    event_ids: vec![event_id.to_owned()],
+                }),
+            }
+        }
+
+        requests
+    }
+}
+
+/// The priority of a [`PushNotification`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PushPriority {
+    /// The notification should be delivered as soon as possible.
+    #[default]
+    High,
+
+    /// The notification can be delivered with a delay, to save battery.
+    Low,
+}
+
+/// The counters sent along with a [`PushNotification`].
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PushNotificationCounts {
+    /// The number of unread messages of the user.
+    pub unread: Option<UInt>,
+
+    /// The number of unacknowledged missed calls of the user.
+    pub missed_calls: Option<UInt>,
+}
+
+/// A device a [`PushNotification`] should be delivered to.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PushDevice {
+    /// The app ID of the pusher.
+    pub app_id: String,
+
+    /// The push key of the pusher.
+    pub pushkey: String,
+
+    /// When the push key was last updated.
+    pub pushkey_ts: Option<SecondsSinceUnixEpoch>,
+
+    /// The data of the pusher, without its URL.
+    #[serde(default)]
+    pub data: JsonObject,
+
+    /// The tweaks of the push rule that triggered the notification.
+    #[serde(default)]
+    pub tweaks: JsonObject,
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_test::push_gateway::PushNotificationBuilder;
+    use ruma::{event_id, events::TimelineEventType, room_id, uint, user_id};
+    use serde_json::json;
+
+    use super::{PushNotification, PushPriority};
+
+    #[test]
+    fn test_parse_full_notification() {
+        let body = PushNotificationBuilder::new(room_id!("!room:localhost"), event_id!("$event"))
+            .event_type("m.room.message")
+            .sender(user_id!("@alice:localhost"))
+            .sender_display_name("Alice")
+            .room_name("Wonderland")
+            .content(json!({ "msgtype": "m.text", "body": "Hello!" }))
+            .unread_count(2)
+            .device("io.element.app", "push-key", json!({ "format": "full" }))
+            .into_request_body();
+
+        let notification = PushNotification::from_request_body(body.to_string().as_bytes())
+            .expect("the notification should be parsed");
+
+        assert!(!notification.is_event_id_only());
+        assert_eq!(notification.event(), Some((room_id!("!room:localhost"), event_id!("$event"))));
+        assert_eq!(notification.event_type, Some(TimelineEventType::RoomMessage));
+        assert_eq!(notification.sender.as_deref(), Some(user_id!("@alice:localhost")));
+        assert_eq!(notification.sender_display_name.as_deref(), Some("Alice"));
+        assert_eq!(notification.room_name.as_deref(), Some("Wonderland"));
+        assert_eq!(notification.content.unwrap()["body"], "Hello!");
+        assert_eq!(notification.prio, PushPriority::High);
+        assert_eq!(notification.counts.unread, Some(uint!(2)));
+        assert_eq!(notification.counts.missed_calls, None);
+
+        assert_eq!(notification.devices.len(), 1);
+        assert_eq!(notification.devices[0].app_id, "io.element.app");
+        assert_eq!(notification.devices[0].pushkey, "push-key");
+        assert_eq!(notification.devices[0].data["format"], "full");
+    }
+
+    #[test]
+    fn test_parse_event_id_only_notification() {
+        let body = PushNotificationBuilder::new(room_id!("!room:localhost"), event_id!("$event"))
+            .event_type("m.room.message")
+            .sender(user_id!("@alice:localhost"))
+            .content(json!({ "msgtype": "m.text", "body": "Hello!" }))
+            .unread_count(1)
+            .low_priority()
+            .event_id_only()
+            .into_request_body();
+
+        let notification = PushNotification::from_request_body(body.to_string().as_bytes())
+            .expect("the notification should be parsed");
+
+        assert!(notification.is_event_id_only());
+        assert_eq!(notification.event(), Some((room_id!("!room:localhost"), event_id!("$event"))));
+        assert_eq!(notification.prio, PushPriority::Low);
+        assert_eq!(notification.counts.unread, Some(uint!(1)));
+        assert!(notification.devices.is_empty());
+    }
+
+    #[test]
+    fn test_parse_counts_only_notification() {
+        let body = json!({
+            "notification": {
+                "counts": { "unread": 0, "missed_calls": 1 },
+                "devices": [{ "app_id": "io.element.app", "pushkey": "push-key" }],
+            }
+        });
+
+        let notification = PushNotification::from_request_body(body.to_string().as_bytes())
+            .expect("the notification should be parsed");
+
+        assert!(!notification.is_event_id_only());
+        assert_eq!(notification.event(), None);
+        assert_eq!(notification.counts.unread, Some(uint!(0)));
+        assert_eq!(notification.counts.missed_calls, Some(uint!(1)));
+    }
+
+    #[test]
+    fn test_to_items_requests_groups_by_room() {
+        let room_a = room_id!("!a:localhost");
+        let room_b = room_id!("!b:localhost");
+
+        let notifications = [
+            PushNotificationBuilder::new(room_a, event_id!("$1")).build(),
+            PushNotificationBuilder::new(room_b, event_id!("$2")).build(),
+            json!({ "counts": { "unread": 3 } }),
+            PushNotificationBuilder::new(room_a, event_id!("$3")).build(),
+        ]
+        .into_iter()
+        .map(|json| serde_json::from_value::<PushNotification>(json).unwrap())
+        .collect::<Vec<_>>();
+
+        let requests = PushNotification::to_items_requests(&notifications);
+
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].room_id, room_a);
+        assert_eq!(requests[0].event_ids, [event_id!("$1"), event_id!("$3")]);
+        assert_eq!(requests[1].room_id, room_b);
+        assert_eq!(requests[1].event_ids, [event_id!("$2")]);
+    }
+}
@@ -63,6 +63,7 @@ mod non_left;
 mod none;
 mod normalized_match_room_name;
 mod not;
+mod quarantined_invite;
 mod unread;
 
 pub use all::new_filter as new_filter_all;
@@ -82,6 +83,7 @@ pub use non_left::new_filter as new_filter_non_left;
 pub use none::new_filter as new_filter_none;
 pub use normalized_match_room_name::new_filter as new_filter_normalized_match_room_name;
 pub use not::new_filter as new_filter_not;
+pub use quarantined_invite::new_filter as new_filter_quarantined_invite;
 #[cfg(test)]
 use ruma::RoomId;
 use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
@@ -0,0 +1,85 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use matrix_sdk_base::RoomState;
+
+use super::{super::Room, Filter};
+
+struct QuarantinedInviteMatcher<F>
+where
+    F: Fn(&Room) -> (RoomState, bool),
+{
+    state_and_quarantine: F,
+}
+
+impl<F> QuarantinedInviteMatcher<F>
+where
+    F: Fn(&Room) -> (RoomState, bool),
+{
+    fn matches(&self, room: &Room) -> bool {
+        let (state, is_quarantined) = (self.state_and_quarantine)(room);
+
+        state == RoomState::Invited && is_quarantined
+    }
+}
+
+/// Create a new filter that will filter out rooms that are not invites
+/// quarantined by the invite filter of the client (see
+/// [`matrix_sdk::Client::is_invite_quarantined`]).
+///
+/// It's meant to be negated with [`super::new_filter_not`] to hide the
+/// quarantined invites from the room list.
+pub fn new_filter() -> impl Filter {
+    let matcher = QuarantinedInviteMatcher {
+        state_and_quarantine: move |room| {
+            (room.state(), room.client().is_invite_quarantined(room.room_id()))
+        },
+    };
+
+    move |room| -> bool { matcher.matches(room) }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::test_utils::logged_in_client_with_server;
+    use matrix_sdk_base::RoomState;
+    use matrix_sdk_test::async_test;
+    use ruma::room_id;
+
+    use super::{super::new_rooms, *};
+
+    #[async_test]
+    async fn test_quarantined_invite() {
+        let (client, server) = logged_in_client_with_server().await;
+        let [room] = new_rooms([room_id!("!a:b.c")], &client, &server).await;
+
+        // When a room is an invite that isn't quarantined, it doesn't match.
+        let matcher =
+            QuarantinedInviteMatcher { state_and_quarantine: |_| (RoomState::Invited, false) };
+        assert!(!matcher.matches(&room));
+
+        // When a room has been joined, it doesn't match, even if it was quarantined.
+        let matcher =
+            QuarantinedInviteMatcher { state_and_quarantine: |_| (RoomState::Joined, true) };
+        assert!(!matcher.matches(&room));
+
+        // When a room is a quarantined invite, it does match.
+        let matcher =
+            QuarantinedInviteMatcher { state_and_quarantine: |_| (RoomState::Invited, true) };
+        assert!(matcher.matches(&room));
+
+        // With the real filter, a room that isn't quarantined doesn't match.
+        assert!(!new_filter()(&room));
+    }
+}
@@ -25,8 +25,8 @@ use matrix_sdk::crypto::OlmMachine;
 use matrix_sdk::{
     Result, Room,
     deserialized_responses::TimelineEvent,
-    event_cache::{RoomEventCache, RoomPaginationStatus},
-    paginators::{PaginationResult, Paginator},
+    event_cache::{DetachedContext, RoomEventCache, RoomPaginationStatus},
+    paginators::{PaginationResult, Paginator, PaginatorError},
     send_queue::{
         LocalEcho, LocalEchoContent, RoomSendQueueUpdate, SendHandle, SendReactionHandle,
     },
@@ -34,7 +34,7 @@ use matrix_sdk::{
 use ruma::{
     EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedTransactionId, RoomVersionId,
     TransactionId, UserId,
-    api::client::receipt::create_receipt::v3::ReceiptType as SendReceiptType,
+    api::{Direction, client::receipt::create_receipt::v3::ReceiptType as SendReceiptType},
     events::{
         AnyMessageLikeEventContent, AnySyncEphemeralRoomEvent, AnySyncMessageLikeEvent,
         AnySyncTimelineEvent, MessageLikeEventType,
@@ -304,7 +304,8 @@ impl<P: RoomDataProvider, D: Decryptor> TimelineController<P, D> {
                 TimelineFocusKind::Live { hide_threaded_events }
             }
 
-            TimelineFocus::Event { hide_threaded_events, .. } => {
+            TimelineFocus::Event { hide_threaded_events, .. }
+            | TimelineFocus::Date { hide_threaded_events, .. } => {
                 let paginator = Paginator::new(room_data_provider.clone());
                 TimelineFocusKind::Event { paginator, hide_threaded_events }
             }
@@ -340,6 +341,35 @@ impl<P: RoomDataProvider, D: Decryptor> TimelineController<P, D> {
         Self { state, focus, room_data_provider, settings, decryption_retry_task }
     }
 
+    /// Adds the initial events to the timeline, along with their
+    /// aggregations.
+    ///
+    /// Returns whether there were any events added to the timeline.
+    async fn init_events_with_aggregations(
+        &self,
+        events: Vec<TimelineEvent>,
+        origin: RemoteEventOrigin,
+        room_event_cache: &RoomEventCache,
+    ) -> bool {
+        let has_events = !events.is_empty();
+
+        // The events may miss the edits and reactions that aren't loaded along with
+        // them; retrieve them from the index of the event cache.
+        let related_events = load_indexed_aggregations(room_event_cache, &events).await;
+
+        self.replace_with_initial_remote_events(events.into_iter(), origin).await;
+
+        if !related_events.is_empty() {
+            self.handle_remote_aggregations(
+                vec![VectorDiff::Append { values: related_events }],
+                RemoteEventOrigin::Cache,
+            )
+            .await;
+        }
+
+        has_events
+    }
+
     /// Initializes the configured focus with appropriate data.
     ///
     /// Should be called only once after creation of the [`TimelineInner`], with
@@ -356,13 +386,13 @@ impl<P: RoomDataProvider, D: Decryptor> TimelineController<P, D> {
                 // Retrieve the cached events, and add them to the timeline.
                 let events = room_event_cache.events().await;
 
-                let has_events = !events.is_empty();
-
-                self.replace_with_initial_remote_events(
-                    events.into_iter(),
-                    RemoteEventOrigin::Cache,
-                )
-                .await;
+                let has_events = self
+                    .init_events_with_aggregations(
+                        events,
+                        RemoteEventOrigin::Cache,
+                        room_event_cache,
+                    )
+                    .await;
 
                 match room_event_cache.pagination().status().get() {
                     RoomPaginationStatus::Idle { hit_timeline_start } => {
@@ -390,15 +420,71 @@ impl<P: RoomDataProvider, D: Decryptor> TimelineController<P, D> {
                     .await
                     .map_err(PaginationError::Paginator)?;
 
-                let has_events = !start_from_result.events.is_empty();
+                Ok(self
+                    .init_events_with_aggregations(
+                        start_from_result.events,
+                        RemoteEventOrigin::Pagination,
+                        room_event_cache,
+                    )
+                    .await)
+            }
 
-                self.replace_with_initial_remote_events(
-                    start_from_result.events.into_iter(),
-                    RemoteEventOrigin::Pagination,
-                )
-                .await;
+            TimelineFocus::Date { date, num_context_events, .. } => {
+                let TimelineFocusKind::Event { paginator, .. } = &*self.focus else {
+                    // Note: this is sync'd with code in the ctor.
+                    unreachable!();
+                };
 
-                Ok(has_events)
+                // Reuse the context saved by a previous jump to this date, if any.
+                if let Some(context) = room_event_cache.detached_context_at(*date).await {
+                    trace!("reusing the detached context from the event cache");
+
+                    paginator
+                        .start_from_tokens(context.prev_batch_token, context.next_batch_token)
+                        .map_err(PaginationError::Paginator)?;
+
+                    return Ok(self
+                        .init_events_with_aggregations(
+                            context.events,
+                            RemoteEventOrigin::Pagination,
+                            room_event_cache,
+                        )
+                        .await);
+                }
+
+                let event_id = self
+                    .room_data_provider
+                    .event_id_for_timestamp(*date, Direction::Forward)
+                    .await
+                    .map_err(|err| {
+                        PaginationError::Paginator(PaginatorError::SdkError(Box::new(err)))
+                    })?;
+
+                // Start a /context request, and append the results (in order) to the timeline.
+                let start_from_result = paginator
+                    .start_from(&event_id, (*num_context_events).into())
+                    .await
+                    .map_err(PaginationError::Paginator)?;
+
+                // Save the context in the event cache, so it can be reused later, and joined
+                // with the room's timeline once a back-pagination reaches it.
+                let context = DetachedContext {
+                    events: start_from_result.events.clone(),
+                    prev_batch_token: start_from_result.prev_batch_token,
+                    next_batch_token: start_from_result.next_batch_token,
+                };
+
+                if let Err(err) = room_event_cache.save_detached_context(context).await {
+                    warn!("couldn't save the context around the date in the event cache: {err}");
+                }
+
+                Ok(self
+                    .init_events_with_aggregations(
+                        start_from_result.events,
+                        RemoteEventOrigin::Pagination,
+                        room_event_cache,
+                    )
+                    .await)
             }
 
             TimelineFocus::Thread { root_event_id, .. } => {
@@ -1366,6 +1452,10 @@ impl<P: RoomDataProvider, D: Decryptor> TimelineController<P, D> {
                 // TODO(bnjbvr): Do something else?
                 info!(txn_id = %related_to, "some media for a media event has been uploaded");
             }
+
+            RoomSendQueueUpdate::MediaUpload { .. } => {
+                // Upload progress isn't reflected in the timeline items (yet).
+            }
         }
     }
 
@@ -1634,6 +1724,25 @@ impl<P: RoomDataProvider> TimelineController<P, (OlmMachine, OwnedRoomId)> {
     }
 }
 
+/// Load the latest edits and the reactions of the given events, as indexed by
+/// the event cache, skipping the ones that are among the given events.
+async fn load_indexed_aggregations(
+    room_event_cache: &RoomEventCache,
+    events: &[TimelineEvent],
+) -> Vector<TimelineEvent> {
+    let event_ids = events.iter().filter_map(|event| event.event_id()).collect::<Vec<_>>();
+    let known_event_ids = event_ids.iter().collect::<BTreeSet<_>>();
+
+    room_event_cache
+        .find_aggregated_events(&event_ids)
+        .await
+        .into_iter()
+        .filter(|event| {
+            event.event_id().is_none_or(|event_id| !known_event_ids.contains(&event_id))
+        })
+        .collect()
+}
+
 #[allow(clippy::too_many_arguments)]
 async fn fetch_replied_to_event<P: RoomDataProvider>(
     mut state_guard: RwLockWriteGuard<'_, TimelineState<P>>,
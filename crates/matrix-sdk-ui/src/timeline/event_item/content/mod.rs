@@ -63,8 +63,10 @@ mod msg_like;
 pub(crate) mod pinned_events;
 mod polls;
 mod reply;
+mod server_acl;
 
 pub use pinned_events::RoomPinnedEventsChange;
+pub use server_acl::RoomServerAclChange;
 
 pub(in crate::timeline) use self::message::{
     extract_bundled_edit_event_json, extract_poll_edit_content, extract_room_msg_edit_content,
@@ -1042,6 +1044,17 @@ impl OtherState {
         &self.content
     }
 
+    /// The changes made to the server ACL of the room, if this is an
+    /// unredacted `m.room.server_acl` event.
+    pub fn server_acl_change(&self) -> Option<RoomServerAclChange> {
+        match &self.content {
+            AnyOtherFullStateEventContent::RoomServerAcl(content) => {
+                RoomServerAclChange::new(content)
+            }
+            _ => None,
+        }
+    }
+
     fn redact(&self, room_version: &RoomVersionId) -> Self {
         Self { state_key: self.state_key.clone(), content: self.content.redact(room_version) }
     }
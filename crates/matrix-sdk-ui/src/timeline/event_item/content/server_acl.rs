@@ -0,0 +1,130 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use ruma::events::{FullStateEventContent, room::server_acl::RoomServerAclEventContent};
+
+/// The changes between the previous and current server ACL of a room.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct RoomServerAclChange {
+    /// The globs that were added to the allowed servers.
+    pub allowed_added: Vec<String>,
+    /// The globs that were removed from the allowed servers.
+    pub allowed_removed: Vec<String>,
+    /// The globs that were added to the denied servers.
+    pub denied_added: Vec<String>,
+    /// The globs that were removed from the denied servers.
+    pub denied_removed: Vec<String>,
+    /// The new value of `allow_ip_literals`, if it changed.
+    pub allow_ip_literals: Option<bool>,
+}
+
+impl RoomServerAclChange {
+    /// Whether nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Compute the changes of the server ACL, if it wasn't redacted.
+    ///
+    /// When the previous server ACL is unknown, everything in the current one
+    /// is considered as added.
+    pub(super) fn new(content: &FullStateEventContent<RoomServerAclEventContent>) -> Option<Self> {
+        let FullStateEventContent::Original { content, prev_content } = content else {
+            return None;
+        };
+
+        let (prev_allow, prev_deny, prev_allow_ip_literals) = match prev_content {
+            Some(prev) => {
+                (prev.allow.as_slice(), prev.deny.as_slice(), Some(prev.allow_ip_literals))
+            }
+            None => (&[][..], &[][..], None),
+        };
+
+        Some(Self {
+            allowed_added: difference(&content.allow, prev_allow),
+            allowed_removed: difference(prev_allow, &content.allow),
+            denied_added: difference(&content.deny, prev_deny),
+            denied_removed: difference(prev_deny, &content.deny),
+            allow_ip_literals: (prev_allow_ip_literals != Some(content.allow_ip_literals))
+                .then_some(content.allow_ip_literals),
+        })
+    }
+}
+
+/// The globs of `globs` that aren't in `other`, in order.
+fn difference(globs: &[String], other: &[String]) -> Vec<String> {
+    globs.iter().filter(|glob| !other.contains(glob)).cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::events::{
+        FullStateEventContent,
+        room::server_acl::{RedactedRoomServerAclEventContent, RoomServerAclEventContent},
+    };
+
+    use super::RoomServerAclChange;
+
+    fn acl(allow: &[&str], deny: &[&str], allow_ip_literals: bool) -> RoomServerAclEventContent {
+        RoomServerAclEventContent::new(
+            allow_ip_literals,
+            allow.iter().map(|glob| (*glob).to_owned()).collect(),
+            deny.iter().map(|glob| (*glob).to_owned()).collect(),
+        )
+    }
+
+    #[test]
+    fn test_redacted_server_acl_has_no_change() {
+        let content = FullStateEventContent::Redacted(RedactedRoomServerAclEventContent::new());
+        assert_eq!(RoomServerAclChange::new(&content), None);
+    }
+
+    #[test]
+    fn test_server_acl_without_prev_content_is_all_added() {
+        let content = FullStateEventContent::Original {
+            content: acl(&["*"], &["evil.org"], false),
+            prev_content: None,
+        };
+
+        let change = RoomServerAclChange::new(&content).unwrap();
+        assert_eq!(change.allowed_added, ["*"]);
+        assert!(change.allowed_removed.is_empty());
+        assert_eq!(change.denied_added, ["evil.org"]);
+        assert!(change.denied_removed.is_empty());
+        assert_eq!(change.allow_ip_literals, Some(false));
+    }
+
+    #[test]
+    fn test_server_acl_changes() {
+        let content = FullStateEventContent::Original {
+            content: acl(&["*"], &["evil.org", "*.spam.org"], false),
+            prev_content: Some(acl(&["*"], &["evil.org", "bad.org"], false)),
+        };
+
+        let change = RoomServerAclChange::new(&content).unwrap();
+        assert!(change.allowed_added.is_empty());
+        assert!(change.allowed_removed.is_empty());
+        assert_eq!(change.denied_added, ["*.spam.org"]);
+        assert_eq!(change.denied_removed, ["bad.org"]);
+        assert_eq!(change.allow_ip_literals, None);
+        assert!(!change.is_empty());
+
+        let content = FullStateEventContent::Original {
+            content: acl(&["*"], &[], true),
+            prev_content: Some(acl(&["*"], &[], true)),
+        };
+        assert!(RoomServerAclChange::new(&content).unwrap().is_empty());
+    }
+}
@@ -46,8 +46,8 @@ pub use self::{
     content::{
         AnyOtherFullStateEventContent, EmbeddedEvent, EncryptedMessage, InReplyToDetails,
         MemberProfileChange, MembershipChange, Message, MsgLikeContent, MsgLikeKind, OtherState,
-        PollResult, PollState, RoomMembershipChange, RoomPinnedEventsChange, Sticker,
-        ThreadSummary, TimelineItemContent,
+        PollResult, PollState, RoomMembershipChange, RoomPinnedEventsChange, RoomServerAclChange,
+        Sticker, ThreadSummary, TimelineItemContent,
     },
     local::EventSendState,
 };
@@ -39,7 +39,7 @@ use matrix_sdk::{
 use mime::Mime;
 use pinned_events_loader::PinnedEventsRoom;
 use ruma::{
-    EventId, OwnedEventId, RoomVersionId, UserId,
+    EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, RoomVersionId, UserId,
     api::client::receipt::create_receipt::v3::ReceiptType,
     events::{
         AnyMessageLikeEventContent, AnySyncTimelineEvent,
@@ -94,8 +94,8 @@ pub use self::{
         EventSendState, EventTimelineItem, InReplyToDetails, MemberProfileChange, MembershipChange,
         Message, MsgLikeContent, MsgLikeKind, OtherState, PollResult, PollState, Profile,
         ReactionInfo, ReactionStatus, ReactionsByKeyBySender, RoomMembershipChange,
-        RoomPinnedEventsChange, Sticker, ThreadSummary, TimelineDetails, TimelineEventItemId,
-        TimelineItemContent,
+        RoomPinnedEventsChange, RoomServerAclChange, Sticker, ThreadSummary, TimelineDetails,
+        TimelineEventItemId, TimelineItemContent,
     },
     event_type_filter::TimelineEventTypeFilter,
     item::{TimelineItem, TimelineItemKind, TimelineUniqueId},
@@ -145,6 +145,21 @@ pub enum TimelineFocus {
         hide_threaded_events: bool,
     },
 
+    /// Focus on a specific date, e.g. after jumping to a date.
+    ///
+    /// The timeline is opened around the first event sent at or after this
+    /// date, reusing the context fetched beforehand from the event cache when
+    /// possible.
+    Date {
+        date: MilliSecondsSinceUnixEpoch,
+        num_context_events: u16,
+        /// Whether to hide in-thread replies from the live timeline.
+        ///
+        /// This should be set to true when the client can create
+        /// [`Self::Thread`]-focused timelines from the thread roots themselves.
+        hide_threaded_events: bool,
+    },
+
     /// Focus on a specific thread
     Thread { root_event_id: OwnedEventId },
 
@@ -157,6 +172,7 @@ impl TimelineFocus {
         match self {
             TimelineFocus::Live { .. } => "live".to_owned(),
             TimelineFocus::Event { target, .. } => format!("permalink:{target}"),
+            TimelineFocus::Date { date, .. } => format!("date:{}", date.0),
             TimelineFocus::Thread { root_event_id, .. } => format!("thread:{root_event_id}"),
             TimelineFocus::PinnedEvents { .. } => "pinned-events".to_owned(),
         }
@@ -42,6 +42,7 @@ use matrix_sdk_test::{ALICE, DEFAULT_TEST_ROOM_ID, event_factory::EventFactory};
 use ruma::{
     EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedTransactionId,
     OwnedUserId, RoomVersionId, TransactionId, UInt, UserId,
+    api::Direction,
     events::{
         AnyMessageLikeEventContent, AnyTimelineEvent,
         reaction::ReactionEventContent,
@@ -456,4 +457,12 @@ impl RoomDataProvider for TestRoomDataProvider {
     async fn load_event<'a>(&'a self, _event_id: &'a EventId) -> matrix_sdk::Result<TimelineEvent> {
         unimplemented!();
     }
+
+    async fn event_id_for_timestamp(
+        &self,
+        _timestamp: MilliSecondsSinceUnixEpoch,
+        _direction: Direction,
+    ) -> matrix_sdk::Result<OwnedEventId> {
+        unimplemented!();
+    }
 }
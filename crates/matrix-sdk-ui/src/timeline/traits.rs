@@ -27,7 +27,9 @@ use matrix_sdk::{
 };
 use matrix_sdk_base::{RoomInfo, latest_event::LatestEvent};
 use ruma::{
-    EventId, OwnedEventId, OwnedTransactionId, OwnedUserId, RoomVersionId, UserId,
+    EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedTransactionId, OwnedUserId,
+    RoomVersionId, UserId,
+    api::Direction,
     events::{
         AnyMessageLikeEventContent, AnySyncTimelineEvent,
         fully_read::FullyReadEventContent,
@@ -150,6 +152,14 @@ pub(super) trait RoomDataProvider:
         &'a self,
         event_id: &'a EventId,
     ) -> impl Future<Output = Result<TimelineEvent>> + SendOutsideWasm + 'a;
+
+    /// Finds the ID of the event closest to the given date, in the given
+    /// direction.
+    fn event_id_for_timestamp(
+        &self,
+        timestamp: MilliSecondsSinceUnixEpoch,
+        direction: Direction,
+    ) -> impl Future<Output = Result<OwnedEventId>> + SendOutsideWasm + '_;
 }
 
 impl RoomDataProvider for Room {
@@ -304,6 +314,14 @@ impl RoomDataProvider for Room {
     async fn load_event<'a>(&'a self, event_id: &'a EventId) -> Result<TimelineEvent> {
         self.load_or_fetch_event(event_id, None).await
     }
+
+    async fn event_id_for_timestamp(
+        &self,
+        timestamp: MilliSecondsSinceUnixEpoch,
+        direction: Direction,
+    ) -> Result<OwnedEventId> {
+        Ok(self.event_for_timestamp(timestamp, direction).await?.event_id)
+    }
 }
 
 // Internal helper to make most of retry_event_decryption independent of a room
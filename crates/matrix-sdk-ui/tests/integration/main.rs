@@ -26,6 +26,7 @@ mod encryption_sync_service;
 mod notification_client;
 mod room_list_service;
 mod sliding_sync;
+mod sync_replay;
 mod sync_service;
 mod timeline;
 
@@ -1,5 +1,5 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     sync::{Arc, Mutex},
     time::Duration,
 };
@@ -8,23 +8,27 @@ use assert_matches::assert_matches;
 use assert_matches2::assert_let;
 use matrix_sdk::{
     config::SyncSettings,
+    invite_filter::InviteFilter,
+    pusher::HttpPusherBuilder,
     test_utils::{logged_in_client_with_server, mocks::MatrixMockServer},
 };
 use matrix_sdk_test::{
-    JoinedRoomBuilder, SyncResponseBuilder, async_test, event_factory::EventFactory,
+    JoinedRoomBuilder, SyncResponseBuilder, async_test,
+    event_factory::EventFactory,
     mocks::mock_encryption_state,
+    push_gateway::{PushGateway, PushNotificationBuilder},
 };
 use matrix_sdk_ui::{
     notification_client::{
         NotificationClient, NotificationEvent, NotificationItemsRequest, NotificationProcessSetup,
-        NotificationStatus,
+        NotificationStatus, PushNotification,
     },
     sync_service::SyncService,
 };
 use ruma::{
     event_id,
     events::{AnyStateEvent, TimelineEventType, room::member::MembershipState},
-    mxc_uri, room_id, user_id,
+    mxc_uri, owned_server_name, room_id, user_id,
 };
 use serde_json::json;
 use wiremock::{
@@ -574,6 +578,137 @@ async fn test_notification_client_sliding_sync_invites_with_event_id() {
     assert_eq!(item.is_noisy, Some(false));
 }
 
+#[async_test]
+async fn test_notification_client_sliding_sync_filters_out_blocked_invites() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client_with_server().await;
+
+    let invite_event_id = event_id!("$invite_event_id");
+    let sender = user_id!("@spammer:spam.org");
+    let my_user_id = client.user_id().unwrap().to_owned();
+
+    client
+        .set_invite_filter(InviteFilter {
+            blocked_servers: BTreeSet::from([owned_server_name!("spam.org")]),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let event_factory = EventFactory::new().room(room_id);
+
+    let sender_member_event =
+        event_factory.member(sender).membership(MembershipState::Join).into_raw_sync();
+
+    let invite_member_event = event_factory
+        .member(&my_user_id)
+        .membership(MembershipState::Invite)
+        .sender(sender)
+        .event_id(invite_event_id)
+        .into_raw_sync();
+
+    let pos = Mutex::new(0);
+    Mock::given(SlidingSyncMatcher)
+        .respond_with(move |request: &Request| {
+            let partial_request: PartialSlidingSyncRequest = request.body_json().unwrap();
+            // Repeat the transaction id in the response, to validate sticky parameters.
+            let mut pos = pos.lock().unwrap();
+            *pos += 1;
+            let pos_as_str = (*pos).to_string();
+            ResponseTemplate::new(200).set_body_json(json!({
+                "txn_id": partial_request.txn_id,
+                "pos": pos_as_str,
+                "rooms": {
+                    room_id: {
+                        "name": "spam room",
+                        "initial": true,
+                        "invite_state": [
+                            // Sender's member information.
+                            sender_member_event,
+                            // Invite event
+                            invite_member_event,
+                        ],
+                        "timeline": [],
+                    }
+                },
+
+                "extensions": {
+                    "account_data": {}
+                }
+            }))
+        })
+        .mount(&server)
+        .await;
+
+    let dummy_sync_service = Arc::new(SyncService::builder(client.clone()).build().await.unwrap());
+    let process_setup =
+        NotificationProcessSetup::SingleProcess { sync_service: dummy_sync_service };
+    let notification_client = NotificationClient::new(client, process_setup).await.unwrap();
+    let mut result = notification_client
+        .get_notifications_with_sliding_sync(&[NotificationItemsRequest {
+            room_id: room_id.to_owned(),
+            event_ids: vec![invite_event_id.to_owned()],
+        }])
+        .await
+        .unwrap();
+
+    let Some(Ok(item)) = result.remove(invite_event_id) else {
+        panic!("fetching notification for {invite_event_id} failed");
+    };
+    let NotificationStatus::EventFilteredOut = item else {
+        panic!("notification for {invite_event_id} was not filtered out");
+    };
+}
+
+#[async_test]
+async fn test_notification_client_context_filters_out_blocked_invites() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let invite_event_id = event_id!("$invite_event_id");
+    let sender = user_id!("@spammer:spam.org");
+    let my_user_id = client.user_id().unwrap().to_owned();
+
+    server.sync_joined_room(&client, room_id).await;
+
+    client
+        .set_invite_filter(InviteFilter {
+            blocked_servers: BTreeSet::from([owned_server_name!("spam.org")]),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let invite_member_event = EventFactory::new()
+        .room(room_id)
+        .member(&my_user_id)
+        .membership(MembershipState::Invite)
+        .sender(sender)
+        .event_id(invite_event_id)
+        .into_event();
+
+    // Mock the /context response
+    server
+        .mock_room_event_context()
+        .ok(invite_member_event, "start", "end")
+        .mock_once()
+        .mount()
+        .await;
+
+    let dummy_sync_service = Arc::new(SyncService::builder(client.clone()).build().await.unwrap());
+    let process_setup =
+        NotificationProcessSetup::SingleProcess { sync_service: dummy_sync_service };
+    let notification_client = NotificationClient::new(client, process_setup).await.unwrap();
+
+    let result = notification_client
+        .get_notification_with_context(room_id, invite_event_id)
+        .await
+        .unwrap();
+
+    assert_matches!(result, NotificationStatus::EventFilteredOut);
+}
+
 #[async_test]
 async fn test_notification_client_mixed() {
     let room_id = room_id!("!a98sd12bjh:example.org");
@@ -947,3 +1082,102 @@ async fn test_notification_client_context_filters_out_events_from_ignored_users(
 
     assert_matches!(result, NotificationStatus::EventFilteredOut);
 }
+
+#[async_test]
+async fn test_notification_client_push_gateway_end_to_end() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let gateway = PushGateway::new().await;
+
+    let sender = user_id!("@user:example.org");
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let event_id = event_id!("$example_event_id");
+
+    server.sync_joined_room(&client, room_id).await;
+
+    // The app registers an `event_id_only` pusher pointing to the gateway.
+    server.mock_set_pusher().ok().mock_once().mount().await;
+    let pusher = HttpPusherBuilder::new("push-key", "io.element.app", gateway.notify_url())
+        .event_id_only()
+        .build();
+    client.pusher().set(pusher.clone()).await.unwrap();
+
+    // The homeserver notifies the gateway about a new event.
+    let rejected = server
+        .send_push_notification(
+            &pusher,
+            PushNotificationBuilder::new(room_id, event_id)
+                .event_type("m.room.message")
+                .sender(sender)
+                .content(json!({ "msgtype": "m.text", "body": "Heya" }))
+                .unread_count(1),
+        )
+        .await;
+    assert!(rejected.is_empty());
+
+    // The gateway forwards the notification to the app, which decodes it.
+    let received = gateway.received_notifications().await;
+    assert_eq!(received.len(), 1);
+    let notification = serde_json::from_value::<PushNotification>(received[0].clone()).unwrap();
+    assert!(notification.is_event_id_only());
+    assert_eq!(notification.devices[0].pushkey, "push-key");
+
+    // The sliding sync doesn't know about the event, so the notification client
+    // falls back to a `/context` query.
+    let pos = Mutex::new(0);
+    Mock::given(SlidingSyncMatcher)
+        .respond_with(move |request: &Request| {
+            let partial_request: PartialSlidingSyncRequest = request.body_json().unwrap();
+            let mut pos = pos.lock().unwrap();
+            *pos += 1;
+            ResponseTemplate::new(200).set_body_json(json!({
+                "txn_id": partial_request.txn_id,
+                "pos": pos.to_string(),
+                "rooms": {},
+            }))
+        })
+        .mount(server.server())
+        .await;
+
+    let event = EventFactory::new()
+        .room(room_id)
+        .sender(sender)
+        .text_msg("Heya")
+        .event_id(event_id)
+        .into_event();
+    server.mock_room_event_context().ok(event, "start", "end").mock_once().mount().await;
+
+    let dummy_sync_service = Arc::new(SyncService::builder(client.clone()).build().await.unwrap());
+    let process_setup =
+        NotificationProcessSetup::SingleProcess { sync_service: dummy_sync_service };
+    let notification_client = NotificationClient::new(client, process_setup).await.unwrap();
+
+    let status = notification_client.get_notification_for_push(&notification).await.unwrap();
+    assert_let!(Some(NotificationStatus::Event(item)) = status);
+    assert_eq!(item.event.sender(), sender);
+    assert_let!(NotificationEvent::Timeline(event) = &item.event);
+    assert_eq!(event.event_id(), event_id);
+
+    // A notification which only updates the counters doesn't fetch anything.
+    let counts_only =
+        serde_json::from_value::<PushNotification>(json!({ "counts": { "unread": 0 } })).unwrap();
+    assert!(notification_client.get_notification_for_push(&counts_only).await.unwrap().is_none());
+}
+
+#[async_test]
+async fn test_push_gateway_rejects_push_key() {
+    let server = MatrixMockServer::new().await;
+    let gateway = PushGateway::new().await;
+    gateway.reject_push_key("stale-key");
+
+    let pusher =
+        HttpPusherBuilder::new("stale-key", "io.element.app", gateway.notify_url()).build();
+    let rejected = server
+        .send_push_notification(
+            &pusher,
+            PushNotificationBuilder::new(room_id!("!room:localhost"), event_id!("$event")),
+        )
+        .await;
+
+    assert_eq!(rejected, ["stale-key"]);
+}
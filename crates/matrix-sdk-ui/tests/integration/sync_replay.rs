@@ -0,0 +1,98 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests replaying sync recordings made with
+//! `Client::start_recording_syncs()`, and checking the results against
+//! snapshots.
+
+use assert_matches2::assert_let;
+use eyeball_im::VectorDiff;
+use futures_util::{StreamExt as _, pin_mut};
+use insta::assert_json_snapshot;
+use matrix_sdk::test_utils::client::MockClientBuilder;
+use matrix_sdk_test::{
+    async_test,
+    sync_replay::{SyncRecording, SyncReplayServer},
+};
+use matrix_sdk_ui::{
+    RoomListService,
+    room_list_service::filters::new_filter_non_left,
+    timeline::{RoomExt as _, TimelineItemContent},
+};
+use ruma::room_id;
+use serde_json::{Value as JsonValue, json};
+
+#[async_test]
+async fn test_replay_sliding_sync_recording() {
+    let recording = SyncRecording::parse(include_str!("fixtures/sync_recording.jsonl"));
+    let server = SyncReplayServer::start(recording).await;
+
+    let client = MockClientBuilder::for_sync_replay(&server).build().await;
+    client.event_cache().subscribe().unwrap();
+
+    let room_list_service = RoomListService::new(client.clone()).await.unwrap();
+    let sync = room_list_service.sync();
+    pin_mut!(sync);
+
+    while server.remaining_syncs() > 0 {
+        sync.next().await.unwrap().unwrap();
+    }
+
+    // The room list, sorted by recency.
+    let all_rooms = room_list_service.all_rooms().await.unwrap();
+    let (entries, controller) = all_rooms.entries_with_dynamic_adapters(10);
+    controller.set_filter(Box::new(new_filter_non_left()));
+    pin_mut!(entries);
+
+    assert_let!(Some(diffs) = entries.next().await);
+    assert_let!(Some(VectorDiff::Reset { values: rooms }) = diffs.into_iter().next());
+
+    let room_list = rooms
+        .iter()
+        .map(|room| {
+            json!({
+                "room_id": room.room_id(),
+                "name": room.cached_display_name().map(|name| name.to_string()),
+                "num_unread_messages": room.num_unread_messages(),
+            })
+        })
+        .collect::<Vec<_>>();
+    assert_json_snapshot!("replayed_room_list", room_list);
+
+    // The timeline of a room, with its read receipts.
+    let room = client.get_room(room_id!("!project:example.org")).unwrap();
+    let timeline = room.timeline().await.unwrap();
+    let (items, _) = timeline.subscribe().await;
+
+    let timeline_items = items
+        .iter()
+        .filter_map(|item| item.as_event())
+        .map(|event| {
+            let body = match event.content() {
+                TimelineItemContent::MsgLike(msg_like) => {
+                    msg_like.as_message().map(|message| message.body().to_owned())
+                }
+                _ => None,
+            };
+
+            json!({
+                "event_id": event.event_id(),
+                "sender": event.sender(),
+                "body": body,
+                "read_receipts": event.read_receipts().keys().collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<JsonValue>>();
+    assert_json_snapshot!("replayed_timeline", timeline_items);
+}
@@ -19,13 +19,25 @@ use std::time::Duration;
 use assert_matches2::assert_let;
 use eyeball_im::VectorDiff;
 use futures_util::StreamExt;
-use matrix_sdk::{config::SyncSettings, test_utils::logged_in_client_with_server};
+use matrix_sdk::{
+    config::SyncSettings,
+    test_utils::{
+        logged_in_client_with_server,
+        mocks::{MatrixMockServer, RoomMessagesResponseTemplate},
+    },
+};
 use matrix_sdk_test::{
     ALICE, BOB, JoinedRoomBuilder, SyncResponseBuilder, async_test, event_factory::EventFactory,
     mocks::mock_encryption_state,
 };
 use matrix_sdk_ui::timeline::{TimelineBuilder, TimelineFocus};
-use ruma::{event_id, events::room::message::RoomMessageEventContent, room_id};
+use ruma::{
+    MilliSecondsSinceUnixEpoch,
+    api::Direction,
+    event_id,
+    events::room::message::{RoomMessageEventContent, RoomMessageEventContentWithoutRelation},
+    room_id, uint,
+};
 use stream_assert::assert_pending;
 use tokio::time::sleep;
 
@@ -268,6 +280,140 @@ async fn test_live_aggregations_are_reflected_on_focused_timelines() {
     let _ = reactions["👍"][*BOB];
 }
 
+#[async_test]
+async fn test_indexed_aggregations_are_reflected_on_focused_timelines() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    client.event_cache().subscribe().unwrap();
+
+    let f = EventFactory::new().room(room_id);
+    let target_event = event_id!("$1");
+
+    // The event, its edit and a reaction to it are received from sync, and
+    // indexed by the event cache.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_timeline_bulk([
+                f.text_msg("yolo").event_id(target_event).sender(*BOB).into_raw_sync(),
+                f.text_msg("* yolo!")
+                    .edit(target_event, RoomMessageEventContentWithoutRelation::text_plain("yolo!"))
+                    .event_id(event_id!("$2"))
+                    .sender(*BOB)
+                    .into_raw_sync(),
+                f.reaction(target_event, "👍")
+                    .event_id(event_id!("$3"))
+                    .sender(*ALICE)
+                    .into_raw_sync(),
+            ]),
+        )
+        .await;
+
+    // The context of the event doesn't include its edit nor its reaction.
+    server
+        .mock_room_event_context()
+        .ok(f.text_msg("yolo").event_id(target_event).sender(*BOB).into_event(), "start", "end")
+        .mock_once()
+        .mount()
+        .await;
+    server.mock_room_state_encryption().plain().mount().await;
+
+    let room = client.get_room(room_id).unwrap();
+    let timeline = TimelineBuilder::new(&room)
+        .with_focus(TimelineFocus::Event {
+            target: target_event.to_owned(),
+            num_context_events: 20,
+            hide_threaded_events: false,
+        })
+        .build()
+        .await
+        .unwrap();
+
+    let items = timeline.items().await;
+    assert_eq!(items.len(), 1 + 1); // event items + a date divider
+    assert!(items[0].is_date_divider());
+
+    // The edit and the reaction are retrieved from the event cache.
+    let event_item = items[1].as_event().unwrap();
+    assert_eq!(event_item.content().as_message().unwrap().body(), "yolo!");
+    assert!(event_item.content().as_message().unwrap().is_edited());
+    let reactions = event_item.content().reactions().cloned().unwrap_or_default();
+    assert_eq!(reactions.len(), 1);
+    let _ = reactions["👍"][*ALICE];
+}
+
+#[async_test]
+async fn test_new_focused_on_date() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    client.event_cache().subscribe().unwrap();
+
+    let room = server.sync_joined_room(&client, room_id).await;
+
+    let f = EventFactory::new().room(room_id);
+    let target_event = event_id!("$1");
+    let date = MilliSecondsSinceUnixEpoch(uint!(42));
+
+    // The event closest to the date is looked up once, and so is its context.
+    server
+        .mock_room_timestamp_to_event()
+        .match_direction(Direction::Forward)
+        .ok(target_event, date)
+        .mock_once()
+        .mount()
+        .await;
+    server
+        .mock_room_event_context()
+        .ok(
+            f.text_msg("yolo").event_id(target_event).sender(*BOB).server_ts(42).into_event(),
+            "start",
+            "end",
+        )
+        .mock_once()
+        .mount()
+        .await;
+    server.mock_room_state_encryption().plain().mount().await;
+
+    let focus = TimelineFocus::Date { date, num_context_events: 20, hide_threaded_events: false };
+
+    let timeline = TimelineBuilder::new(&room).with_focus(focus.clone()).build().await.unwrap();
+
+    let items = timeline.items().await;
+    assert_eq!(items.len(), 1 + 1); // event items + a date divider
+    assert!(items[0].is_date_divider());
+    assert_eq!(items[1].as_event().unwrap().content().as_message().unwrap().body(), "yolo");
+
+    // Jumping to the same date again reuses the context saved in the event cache,
+    // without hitting the network.
+    let timeline = TimelineBuilder::new(&room).with_focus(focus).build().await.unwrap();
+
+    let items = timeline.items().await;
+    assert_eq!(items.len(), 1 + 1); // event items + a date divider
+    assert!(items[0].is_date_divider());
+    assert_eq!(items[1].as_event().unwrap().content().as_message().unwrap().body(), "yolo");
+
+    // And the timeline paginates backwards from the saved token.
+    server
+        .mock_room_messages()
+        .match_from("start")
+        .ok(RoomMessagesResponseTemplate::default().events(vec![
+            f.text_msg("hello").event_id(event_id!("$0")).sender(*ALICE).server_ts(21),
+        ]))
+        .mock_once()
+        .mount()
+        .await;
+
+    let hit_start = timeline.paginate_backwards(20).await.unwrap();
+    assert!(hit_start);
+
+    let items = timeline.items().await;
+    assert_eq!(items.len(), 2 + 1); // event items + a date divider
+    assert_eq!(items[1].as_event().unwrap().content().as_message().unwrap().body(), "hello");
+    assert_eq!(items[2].as_event().unwrap().content().as_message().unwrap().body(), "yolo");
+}
+
 #[async_test]
 async fn test_focused_timeline_local_echoes() {
     let room_id = room_id!("!a98sd12bjh:example.org");
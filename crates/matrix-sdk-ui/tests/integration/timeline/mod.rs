@@ -54,6 +54,7 @@ mod echo;
 mod edit;
 mod focus_event;
 mod media;
+mod offline;
 mod pagination;
 mod pinned_event;
 mod profiles;
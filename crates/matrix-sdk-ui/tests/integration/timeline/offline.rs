@@ -0,0 +1,121 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests of timelines of previously visited rooms, opened while the homeserver
+//! can't be reached.
+
+use assert_matches2::assert_let;
+use matrix_sdk::{Client, test_utils::mocks::MatrixMockServer};
+use matrix_sdk_test::{BOB, JoinedRoomBuilder, async_test, event_factory::EventFactory};
+use matrix_sdk_ui::timeline::{RoomExt, TimelineBuilder, TimelineDetails, TimelineFocus};
+use ruma::{event_id, room_id};
+use tempfile::TempDir;
+use wiremock::MockServer;
+
+/// Visit a room with a first client, then return a new offline-first client
+/// using the same stores, after the homeserver has been switched off.
+async fn visited_room_offline_client(store_dir: &TempDir) -> Client {
+    // A mock server that isn't shared with other tests, so it can be switched off.
+    let server = MatrixMockServer::from_server(MockServer::builder().start().await);
+
+    let room_id = room_id!("!galette:saucisse.bzh");
+    let f = EventFactory::new().room(room_id).sender(*BOB);
+
+    let client = server.client_builder().sqlite_store(store_dir.path()).build().await;
+    client.event_cache().subscribe().unwrap();
+
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_state_event(f.member(*BOB).display_name("Bob"))
+                .set_timeline_limited()
+                .set_timeline_prev_batch("prev-batch")
+                .add_timeline_bulk([
+                    f.text_msg("hello").event_id(event_id!("$1")).into_raw_sync(),
+                    f.text_msg("world").event_id(event_id!("$2")).into_raw_sync(),
+                ]),
+        )
+        .await;
+
+    // Wait for the event cache to handle the sync, so the events are saved in the
+    // store before the client is closed.
+    let (room_event_cache, _drop_handles) =
+        client.get_room(room_id).unwrap().event_cache().await.unwrap();
+    let (events, mut subscriber) = room_event_cache.subscribe().await;
+    if events.is_empty() {
+        subscriber.recv().await.unwrap();
+    }
+
+    drop(client);
+
+    let client =
+        server.client_builder().sqlite_store(store_dir.path()).offline_first().build().await;
+
+    // The homeserver is switched off.
+    drop(server);
+
+    client
+}
+
+#[async_test]
+async fn test_live_timeline_offline() {
+    let store_dir = TempDir::new().unwrap();
+    let client = visited_room_offline_client(&store_dir).await;
+
+    let room = client.get_room(room_id!("!galette:saucisse.bzh")).unwrap();
+    let timeline = room.timeline().await.unwrap();
+
+    // The events are loaded from the event cache.
+    let items = timeline.items().await;
+    assert_eq!(items.len(), 3);
+    assert!(items[0].is_date_divider());
+    assert_eq!(items[1].as_event().unwrap().content().as_message().unwrap().body(), "hello");
+    assert_eq!(items[2].as_event().unwrap().content().as_message().unwrap().body(), "world");
+
+    // Fetching the members doesn't fail, but they are marked as stale.
+    timeline.fetch_members().await;
+    assert!(room.are_members_stale());
+
+    let items = timeline.items().await;
+    assert_let!(TimelineDetails::Ready(profile) = items[1].as_event().unwrap().sender_profile());
+    assert_eq!(profile.display_name.as_deref(), Some("Bob"));
+}
+
+#[async_test]
+async fn test_focused_timeline_offline() {
+    let store_dir = TempDir::new().unwrap();
+    let client = visited_room_offline_client(&store_dir).await;
+
+    let room = client.get_room(room_id!("!galette:saucisse.bzh")).unwrap();
+    let timeline = TimelineBuilder::new(&room)
+        .with_focus(TimelineFocus::Event {
+            target: event_id!("$2").to_owned(),
+            num_context_events: 20,
+            hide_threaded_events: false,
+        })
+        .build()
+        .await
+        .unwrap();
+
+    // The context of the event is loaded from the event cache.
+    let items = timeline.items().await;
+    assert_eq!(items.len(), 3);
+    assert!(items[0].is_date_divider());
+    assert_eq!(items[1].as_event().unwrap().content().as_message().unwrap().body(), "hello");
+    assert_eq!(items[2].as_event().unwrap().content().as_message().unwrap().body(), "world");
+
+    // The timeline can't be paginated while the homeserver can't be reached.
+    assert!(timeline.paginate_backwards(10).await.is_err());
+}
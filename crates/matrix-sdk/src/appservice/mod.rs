@@ -0,0 +1,488 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for running a [`Client`] as an application service.
+//!
+//! An application service doesn't sync: the homeserver pushes the events of
+//! its namespaces to it, in transactions sent to its HTTP server. The
+//! [`AppService`] receives those transactions and feeds them to its
+//! [`Client`], like sync responses, so the event handlers registered with
+//! [`Client::add_event_handler()`] are called for them.
+//!
+//! The users in the namespace of the application service, called virtual
+//! users, each have their own [`Client`], available with a [`VirtualUser`],
+//! whose requests assert their identity with the `user_id` query parameter.
+//! The events of a transaction are fed to the client of each user who is a
+//! member of their room: the rooms the user of the application service isn't a
+//! member of don't end up in its [`Client`].
+//!
+//! End-to-end encryption is supported for the user of the application service
+//! itself, as long as the homeserver sends the to-device events and the device
+//! list changes in the transactions, as defined in MSC2409, MSC3202 and
+//! MSC4203. The virtual users don't have a device, so their clients don't
+//! support it: the to-device events sent to them are dropped, they can't join
+//! encrypted rooms with [`VirtualUser::join_room_by_id()`], and the events of
+//! the rooms known to be encrypted aren't fed to their clients. Bridges that
+//! need end-to-end encryption must send and receive the messages of encrypted
+//! rooms with the client of the application service.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use matrix_sdk::{
+//!     appservice::{AppService, AppServiceRegistration},
+//!     ruma::{
+//!         events::room::message::OriginalSyncRoomMessageEvent, server_name,
+//!     },
+//! };
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let registration =
+//!     AppServiceRegistration::from_yaml_file("registration.yaml")?;
+//! let appservice = AppService::builder(
+//!     "http://localhost:8008",
+//!     server_name!("example.org"),
+//!     registration,
+//! )
+//! .build()
+//! .await?;
+//!
+//! appservice.client().add_event_handler(
+//!     |event: OriginalSyncRoomMessageEvent| async move {
+//!         println!("Received a message: {}", event.content.body());
+//!     },
+//! );
+//!
+//! appservice.run(("0.0.0.0", 9000)).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::BTreeMap, fmt, num::NonZeroUsize, sync::Arc};
+
+use matrix_sdk_common::ring_buffer::RingBuffer;
+use ruma::{
+    api::appservice::event::push_events, DeviceId, OwnedDeviceId, OwnedRoomId, OwnedServerName,
+    OwnedTransactionId, OwnedUserId, UserId,
+};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tracing::{debug, instrument, warn};
+
+use crate::{
+    authentication::matrix::MatrixSession, store::RoomLoadSettings, Client, ClientBuildError,
+    ClientBuilder, HttpError, IdParseError, SessionMeta, SessionTokens,
+};
+
+mod registration;
+mod server;
+mod transaction;
+mod virtual_user;
+
+pub use registration::AppServiceRegistration;
+pub use virtual_user::VirtualUser;
+
+/// The number of transaction IDs remembered to ignore the transactions that
+/// are sent several times.
+const SEEN_TRANSACTIONS_CAPACITY: NonZeroUsize = NonZeroUsize::new(100).unwrap();
+
+/// An error that can happen when running an application service.
+#[derive(Debug, Error)]
+pub enum AppServiceError {
+    /// The registration couldn't be parsed.
+    #[error("the registration couldn't be parsed: {0}")]
+    Yaml(Box<dyn std::error::Error + Send + Sync>),
+
+    /// A regular expression of the namespaces of the registration is invalid.
+    #[error("invalid namespace regex: {0}")]
+    Regex(#[from] regex::Error),
+
+    /// An I/O error, when reading the registration file or running the HTTP
+    /// server.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// A user ID couldn't be built from the registration.
+    #[error(transparent)]
+    IdParse(#[from] IdParseError),
+
+    /// The user isn't in the user namespace of the application service.
+    #[error("the user {0} isn't in the namespace of the application service")]
+    UserNotInNamespace(OwnedUserId),
+
+    /// The room is encrypted, which isn't supported for the virtual users.
+    #[error("the room {0} is encrypted, which isn't supported for the virtual users")]
+    EncryptedRoom(OwnedRoomId),
+
+    /// The client couldn't be built.
+    #[error(transparent)]
+    ClientBuild(Box<ClientBuildError>),
+
+    /// A request to the homeserver failed.
+    #[error(transparent)]
+    Http(Box<HttpError>),
+
+    /// An error from the client, when processing a transaction.
+    #[error(transparent)]
+    Sdk(#[from] crate::Error),
+}
+
+impl From<ClientBuildError> for AppServiceError {
+    fn from(error: ClientBuildError) -> Self {
+        AppServiceError::ClientBuild(Box::new(error))
+    }
+}
+
+impl From<HttpError> for AppServiceError {
+    fn from(error: HttpError) -> Self {
+        AppServiceError::Http(Box::new(error))
+    }
+}
+
+/// An application service, receiving the transactions of the homeserver and
+/// feeding them to its [`Client`].
+///
+/// Constructed with [`AppService::builder()`]. This type can be cloned
+/// freely.
+#[derive(Clone)]
+pub struct AppService {
+    inner: Arc<AppServiceInner>,
+}
+
+struct AppServiceInner {
+    client: Client,
+    registration: AppServiceRegistration,
+    server_name: OwnedServerName,
+
+    /// The virtual users whose client was built.
+    virtual_users: Mutex<BTreeMap<OwnedUserId, VirtualUser>>,
+
+    /// The function returning the builder of the client of a virtual user.
+    virtual_user_client_builder: Box<VirtualUserClientBuilder>,
+
+    /// The IDs of the last transactions that were processed.
+    ///
+    /// Also used to process the transactions one at a time.
+    seen_transactions: Mutex<RingBuffer<OwnedTransactionId>>,
+}
+
+/// A function returning the [`ClientBuilder`] of the client of a virtual user.
+type VirtualUserClientBuilder = dyn Fn(&UserId) -> ClientBuilder + Send + Sync;
+
+impl AppService {
+    /// Create a new [`AppServiceBuilder`], for an application service with
+    /// the given registration on the given homeserver.
+    pub fn builder(
+        homeserver_url: impl AsRef<str>,
+        server_name: impl Into<OwnedServerName>,
+        registration: AppServiceRegistration,
+    ) -> AppServiceBuilder {
+        AppServiceBuilder {
+            homeserver_url: homeserver_url.as_ref().to_owned(),
+            server_name: server_name.into(),
+            registration,
+            client_builder: None,
+            virtual_user_client_builder: None,
+            device_id: None,
+        }
+    }
+
+    /// The client of the application service, logged in as the user whose
+    /// localpart is the `sender_localpart` of the registration.
+    pub fn client(&self) -> &Client {
+        &self.inner.client
+    }
+
+    /// The registration of the application service.
+    pub fn registration(&self) -> &AppServiceRegistration {
+        &self.inner.registration
+    }
+
+    /// The ID of the user of the application service.
+    pub fn user_id(&self) -> &UserId {
+        self.client().user_id().expect("the client of an application service is logged in")
+    }
+
+    /// The ID of the device of the application service.
+    pub fn device_id(&self) -> &DeviceId {
+        self.client().device_id().expect("the client of an application service is logged in")
+    }
+
+    /// Whether the given user is in the user namespace of the application
+    /// service.
+    pub fn is_user_in_namespace(&self, user_id: &UserId) -> bool {
+        self.registration().is_user_in_namespace(user_id)
+    }
+
+    /// Get the virtual user with the given localpart, to make requests on
+    /// their behalf and receive the events of their rooms.
+    ///
+    /// Their client is built the first time they're requested, or when a
+    /// transaction contains a membership event for them.
+    ///
+    /// Returns an error if the user isn't in the user namespace of the
+    /// application service.
+    pub async fn virtual_user(&self, localpart: &str) -> Result<VirtualUser, AppServiceError> {
+        let user_id = UserId::parse_with_server_name(localpart, &self.inner.server_name)?;
+
+        if !self.is_user_in_namespace(&user_id) {
+            return Err(AppServiceError::UserNotInNamespace(user_id));
+        }
+
+        self.virtual_user_by_id(user_id).await
+    }
+
+    /// Get the virtual user with the given ID, building their client if
+    /// needed.
+    async fn virtual_user_by_id(
+        &self,
+        user_id: OwnedUserId,
+    ) -> Result<VirtualUser, AppServiceError> {
+        let mut virtual_users = self.inner.virtual_users.lock().await;
+
+        if let Some(virtual_user) = virtual_users.get(&user_id) {
+            return Ok(virtual_user.clone());
+        }
+
+        let client = (self.inner.virtual_user_client_builder)(&user_id)
+            .homeserver_url(self.client().homeserver())
+            .build()
+            .await?;
+
+        // The virtual users don't have a device, but a session needs one.
+        client
+            .matrix_auth()
+            .restore_session(
+                MatrixSession {
+                    meta: SessionMeta {
+                        user_id: user_id.clone(),
+                        device_id: self.registration().id.as_str().into(),
+                    },
+                    tokens: SessionTokens {
+                        access_token: self.registration().as_token.clone(),
+                        refresh_token: None,
+                    },
+                },
+                RoomLoadSettings::default(),
+            )
+            .await?;
+
+        let _ = client.inner.asserted_identity.set(user_id.clone());
+
+        let virtual_user = VirtualUser::new(self.client().clone(), client, user_id.clone());
+        virtual_users.insert(user_id, virtual_user.clone());
+
+        Ok(virtual_user)
+    }
+
+    /// Get the virtual users who may be concerned by the given transaction:
+    /// the ones whose client was already built, and the ones whose membership
+    /// changes in the transaction.
+    async fn virtual_users_in_transaction(
+        &self,
+        transaction: &push_events::v1::Request,
+    ) -> Result<Vec<VirtualUser>, AppServiceError> {
+        for user_id in transaction::members_in_transaction(transaction) {
+            if user_id != self.user_id() && self.is_user_in_namespace(&user_id) {
+                self.virtual_user_by_id(user_id).await?;
+            }
+        }
+
+        Ok(self.inner.virtual_users.lock().await.values().cloned().collect())
+    }
+
+    /// Process a transaction pushed by the homeserver.
+    ///
+    /// This is called by the HTTP server of [`AppService::router()`], but can
+    /// also be used to plug the application service in another HTTP server.
+    ///
+    /// The transactions that were already processed are ignored, as the
+    /// homeserver retries sending a transaction until it succeeds.
+    #[instrument(skip_all, fields(txn_id = %transaction.txn_id))]
+    pub async fn receive_transaction(
+        &self,
+        transaction: push_events::v1::Request,
+    ) -> Result<(), AppServiceError> {
+        let mut seen_transactions = self.inner.seen_transactions.lock().await;
+
+        if seen_transactions.iter().any(|txn_id| *txn_id == transaction.txn_id) {
+            debug!("Ignoring a transaction that was already processed");
+            return Ok(());
+        }
+
+        // Each client only receives the rooms its user is a member of.
+        let response = transaction::transaction_to_sync_response(
+            &transaction,
+            self.user_id(),
+            Some(self.device_id()),
+            |room_id| self.client().get_room(room_id).map(|room| room.state()),
+        );
+
+        self.client().process_sync(response).await?;
+
+        #[cfg(feature = "e2e-encryption")]
+        if let Err(e) = self.client().send_outgoing_requests().await {
+            tracing::error!(error = ?e, "Error while sending outgoing E2EE requests");
+        }
+
+        for virtual_user in self.virtual_users_in_transaction(&transaction).await? {
+            let client = virtual_user.client();
+            let mut response = transaction::transaction_to_sync_response(
+                &transaction,
+                virtual_user.user_id(),
+                None,
+                |room_id| client.get_room(room_id).map(|room| room.state()),
+            );
+
+            // The virtual users don't support end-to-end encryption.
+            let encrypted_rooms =
+                transaction::remove_encrypted_rooms(&mut response, |room_id| {
+                    virtual_user.is_room_encrypted(room_id)
+                });
+            if !encrypted_rooms.is_empty() {
+                warn!(
+                    user_id = %virtual_user.user_id(),
+                    ?encrypted_rooms,
+                    "Ignoring the encrypted rooms of a virtual user"
+                );
+            }
+
+            if !response.rooms.is_empty() {
+                client.process_sync(response).await?;
+            }
+        }
+
+        seen_transactions.push(transaction.txn_id);
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for AppService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppService")
+            .field("user_id", &self.user_id())
+            .field("registration", &self.inner.registration.id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Builder for an [`AppService`].
+///
+/// Constructed with [`AppService::builder()`].
+pub struct AppServiceBuilder {
+    homeserver_url: String,
+    server_name: OwnedServerName,
+    registration: AppServiceRegistration,
+    client_builder: Option<ClientBuilder>,
+    virtual_user_client_builder: Option<Box<VirtualUserClientBuilder>>,
+    device_id: Option<OwnedDeviceId>,
+}
+
+impl fmt::Debug for AppServiceBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppServiceBuilder")
+            .field("homeserver_url", &self.homeserver_url)
+            .field("server_name", &self.server_name)
+            .field("registration", &self.registration.id)
+            .field("client_builder", &self.client_builder)
+            .field("device_id", &self.device_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AppServiceBuilder {
+    /// Use the given [`ClientBuilder`] to build the client of the application
+    /// service, e.g. to configure its stores.
+    ///
+    /// Its homeserver is replaced by the one given to
+    /// [`AppService::builder()`].
+    pub fn client_builder(mut self, client_builder: ClientBuilder) -> Self {
+        self.client_builder = Some(client_builder);
+        self
+    }
+
+    /// Use the given function to get the [`ClientBuilder`] of the client of
+    /// each virtual user, e.g. to configure their stores.
+    ///
+    /// The stores of the virtual users must be distinct from the ones of the
+    /// application service, and from each other. Defaults to
+    /// [`Client::builder()`], with in-memory stores. The homeservers of the
+    /// builders are replaced by the one given to [`AppService::builder()`].
+    pub fn virtual_user_client_builder(
+        mut self,
+        builder: impl Fn(&UserId) -> ClientBuilder + Send + Sync + 'static,
+    ) -> Self {
+        self.virtual_user_client_builder = Some(Box::new(builder));
+        self
+    }
+
+    /// Set the ID of the device of the application service.
+    ///
+    /// Defaults to the ID of the registration. To use end-to-end encryption,
+    /// the device must exist on the homeserver.
+    pub fn device_id(mut self, device_id: OwnedDeviceId) -> Self {
+        self.device_id = Some(device_id);
+        self
+    }
+
+    /// Build the [`AppService`], and log its client in with the access token
+    /// of the registration.
+    pub async fn build(self) -> Result<AppService, AppServiceError> {
+        let Self {
+            homeserver_url,
+            server_name,
+            registration,
+            client_builder,
+            virtual_user_client_builder,
+            device_id,
+        } = self;
+
+        let user_id =
+            UserId::parse_with_server_name(registration.sender_localpart.as_str(), &server_name)?;
+        let device_id = device_id.unwrap_or_else(|| registration.id.as_str().into());
+
+        let client = client_builder
+            .unwrap_or_else(Client::builder)
+            .homeserver_url(homeserver_url)
+            .build()
+            .await?;
+
+        client
+            .matrix_auth()
+            .restore_session(
+                MatrixSession {
+                    meta: SessionMeta { user_id, device_id },
+                    tokens: SessionTokens {
+                        access_token: registration.as_token.clone(),
+                        refresh_token: None,
+                    },
+                },
+                RoomLoadSettings::default(),
+            )
+            .await?;
+
+        Ok(AppService {
+            inner: Arc::new(AppServiceInner {
+                client,
+                registration,
+                server_name,
+                virtual_users: Default::default(),
+                virtual_user_client_builder: virtual_user_client_builder
+                    .unwrap_or_else(|| Box::new(|_| Client::builder())),
+                seen_transactions: Mutex::new(RingBuffer::new(SEEN_TRANSACTIONS_CAPACITY)),
+            }),
+        })
+    }
+}
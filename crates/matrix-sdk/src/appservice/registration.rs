@@ -0,0 +1,222 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{ops::Deref, path::Path};
+
+use regex::Regex;
+use ruma::{
+    api::appservice::{Namespace, Registration},
+    RoomAliasId, RoomId, UserId,
+};
+
+use super::AppServiceError;
+
+/// The registration of an application service, as configured on the
+/// homeserver.
+///
+/// It's usually loaded from the YAML registration file given to the
+/// homeserver, with [`AppServiceRegistration::from_yaml_file()`], or built
+/// from a [`Registration`] with [`AppServiceRegistration::try_from()`]. It
+/// derefs to that [`Registration`], and additionally compiles the regular
+/// expressions of its namespaces.
+#[derive(Clone, Debug)]
+pub struct AppServiceRegistration {
+    registration: Registration,
+    users: Vec<Regex>,
+    aliases: Vec<Regex>,
+    rooms: Vec<Regex>,
+}
+
+impl AppServiceRegistration {
+    /// Parse a registration in the YAML format used by the homeservers.
+    pub fn from_yaml_str(yaml: &str) -> Result<Self, AppServiceError> {
+        serde_yaml_ng::from_str::<Registration>(yaml)
+            .map_err(|error| AppServiceError::Yaml(Box::new(error)))?
+            .try_into()
+    }
+
+    /// Load the registration in the YAML file at the given path.
+    pub fn from_yaml_file(path: impl AsRef<Path>) -> Result<Self, AppServiceError> {
+        Self::from_yaml_str(&std::fs::read_to_string(path)?)
+    }
+
+    /// Get the underlying [`Registration`].
+    pub fn registration(&self) -> &Registration {
+        &self.registration
+    }
+
+    /// Whether the given user is in the user namespace of the application
+    /// service.
+    ///
+    /// The user whose localpart is the `sender_localpart` of the registration
+    /// isn't in the namespace, unless it matches one of its regular
+    /// expressions.
+    pub fn is_user_in_namespace(&self, user_id: &UserId) -> bool {
+        is_match(&self.users, user_id.as_str())
+    }
+
+    /// Whether the given room alias is in the alias namespace of the
+    /// application service.
+    pub fn is_alias_in_namespace(&self, alias: &RoomAliasId) -> bool {
+        is_match(&self.aliases, alias.as_str())
+    }
+
+    /// Whether the given room is in the room namespace of the application
+    /// service.
+    pub fn is_room_in_namespace(&self, room_id: &RoomId) -> bool {
+        is_match(&self.rooms, room_id.as_str())
+    }
+}
+
+impl TryFrom<Registration> for AppServiceRegistration {
+    type Error = AppServiceError;
+
+    fn try_from(registration: Registration) -> Result<Self, Self::Error> {
+        let namespaces = &registration.namespaces;
+        let users = compile(&namespaces.users)?;
+        let aliases = compile(&namespaces.aliases)?;
+        let rooms = compile(&namespaces.rooms)?;
+
+        Ok(Self { registration, users, aliases, rooms })
+    }
+}
+
+impl Deref for AppServiceRegistration {
+    type Target = Registration;
+
+    fn deref(&self) -> &Self::Target {
+        &self.registration
+    }
+}
+
+/// Compile the regular expressions of the given namespaces.
+///
+/// Like in the homeservers, the expressions must match from the start of the
+/// identifiers, but not necessarily until their end.
+fn compile(namespaces: &[Namespace]) -> Result<Vec<Regex>, regex::Error> {
+    namespaces.iter().map(|namespace| Regex::new(&format!("^(?:{})", namespace.regex))).collect()
+}
+
+fn is_match(regexes: &[Regex], id: &str) -> bool {
+    regexes.iter().any(|regex| regex.is_match(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::assert_matches;
+    use ruma::{api::appservice::Registration, room_alias_id, room_id, user_id};
+    use serde_json::{from_value as from_json_value, json};
+
+    use super::AppServiceRegistration;
+    use crate::appservice::AppServiceError;
+
+    fn registration(users_regex: &str) -> Registration {
+        from_json_value(json!({
+            "id": "bridge",
+            "url": "http://localhost:9000",
+            "as_token": "as_token",
+            "hs_token": "hs_token",
+            "sender_localpart": "_bridge_bot",
+            "namespaces": {
+                "users": [{ "exclusive": true, "regex": users_regex }],
+                "aliases": [{ "exclusive": false, "regex": "#_bridge_.*" }],
+                "rooms": [],
+            },
+            "rate_limited": false,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_registration() {
+        let registration =
+            AppServiceRegistration::try_from(registration(r"@_bridge_.*:example\.org")).unwrap();
+
+        assert_eq!(registration.id, "bridge");
+        assert_eq!(registration.as_token, "as_token");
+        assert_eq!(registration.hs_token, "hs_token");
+        assert_eq!(registration.sender_localpart, "_bridge_bot");
+        assert_eq!(registration.rate_limited, Some(false));
+
+        assert!(registration.is_user_in_namespace(user_id!("@_bridge_alice:example.org")));
+        assert!(!registration.is_user_in_namespace(user_id!("@_bridge_alice:example.com")));
+        assert!(!registration.is_user_in_namespace(user_id!("@alice:example.org")));
+
+        assert!(registration.is_alias_in_namespace(room_alias_id!("#_bridge_room:example.org")));
+        assert!(!registration.is_alias_in_namespace(room_alias_id!("#room:example.org")));
+
+        assert!(!registration.is_room_in_namespace(room_id!("!room:example.org")));
+    }
+
+    /// A registration file as generated by a bridge, with fields unknown to
+    /// the SDK.
+    const REGISTRATION_FILE: &str = r#"
+id: telegram
+url: http://localhost:29317
+as_token: wfghWEGh3wgWHEf3478sHFWE
+hs_token: ugw8243igya57aaABGFfgeyu
+sender_localpart: telegrambot
+namespaces:
+  users:
+    - exclusive: true
+      regex: '@telegram_.*:example\.org'
+    - exclusive: true
+      regex: '@telegrambot:example\.org'
+  aliases:
+    - exclusive: true
+      regex: '#telegram_.*:example\.org'
+  rooms: []
+rate_limited: false
+de.sorunome.msc2409.push_ephemeral: true
+receive_ephemeral: true
+org.matrix.msc3202: true
+"#;
+
+    #[test]
+    fn test_parse_registration_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("registration.yaml");
+        std::fs::write(&path, REGISTRATION_FILE).unwrap();
+
+        let registration = AppServiceRegistration::from_yaml_file(&path).unwrap();
+
+        assert_eq!(registration.id, "telegram");
+        assert_eq!(registration.url.as_deref(), Some("http://localhost:29317"));
+        assert_eq!(registration.as_token, "wfghWEGh3wgWHEf3478sHFWE");
+        assert_eq!(registration.hs_token, "ugw8243igya57aaABGFfgeyu");
+        assert_eq!(registration.sender_localpart, "telegrambot");
+        assert_eq!(registration.rate_limited, Some(false));
+        assert!(registration.receive_ephemeral);
+
+        assert!(registration.is_user_in_namespace(user_id!("@telegram_123:example.org")));
+        assert!(registration.is_user_in_namespace(user_id!("@telegrambot:example.org")));
+        assert!(!registration.is_user_in_namespace(user_id!("@alice:example.org")));
+        assert!(registration.is_alias_in_namespace(room_alias_id!("#telegram_chat:example.org")));
+    }
+
+    #[test]
+    fn test_parse_invalid_registration() {
+        let result = AppServiceRegistration::from_yaml_str("id: bridge");
+        assert_matches!(result, Err(AppServiceError::Yaml(_)));
+
+        let result = AppServiceRegistration::from_yaml_file("/does/not/exist.yaml");
+        assert_matches!(result, Err(AppServiceError::Io(_)));
+    }
+
+    #[test]
+    fn test_registration_with_invalid_regex() {
+        let result = AppServiceRegistration::try_from(registration("@_bridge_(.*"));
+        assert_matches!(result, Err(AppServiceError::Regex(_)));
+    }
+}
@@ -0,0 +1,144 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The HTTP server receiving the transactions of the homeserver.
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Path, Request, State},
+    http::{header::AUTHORIZATION, StatusCode},
+    response::{IntoResponse, Response},
+    routing::put,
+    Json, Router,
+};
+use ruma::api::{appservice::event::push_events, IncomingRequest};
+use serde_json::json;
+use subtle::ConstantTimeEq;
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tracing::{error, warn};
+
+use super::{AppService, AppServiceError};
+
+/// The maximum size of the body of a transaction.
+const MAX_TRANSACTION_SIZE: usize = 64 * 1024 * 1024;
+
+impl AppService {
+    /// Get an [`axum::Router`] serving the endpoint of the application
+    /// service API receiving the transactions of the homeserver.
+    ///
+    /// It can be merged with other routes, or served with [`axum::serve()`].
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/_matrix/app/v1/transactions/{txn_id}", put(receive_transaction))
+            .with_state(self.clone())
+    }
+
+    /// Serve the [`AppService::router()`] at the given address, until an
+    /// error happens.
+    pub async fn run(&self, address: impl ToSocketAddrs) -> Result<(), AppServiceError> {
+        let listener = TcpListener::bind(address).await?;
+        axum::serve(listener, self.router()).await?;
+        Ok(())
+    }
+}
+
+async fn receive_transaction(
+    State(appservice): State<AppService>,
+    Path(txn_id): Path<String>,
+    request: Request,
+) -> Response {
+    match check_hs_token(&appservice, &request) {
+        Ok(()) => {}
+        Err(TokenError::Missing) => {
+            return error_response(StatusCode::UNAUTHORIZED, "M_UNAUTHORIZED", "Missing token");
+        }
+        Err(TokenError::Invalid) => {
+            return error_response(StatusCode::FORBIDDEN, "M_FORBIDDEN", "Invalid token");
+        }
+    }
+
+    let (parts, body) = request.into_parts();
+    let body = match to_bytes(body, MAX_TRANSACTION_SIZE).await {
+        Ok(body) => body,
+        Err(error) => {
+            warn!(?error, "Couldn't read the body of a transaction");
+            return error_response(StatusCode::BAD_REQUEST, "M_BAD_JSON", "Invalid body");
+        }
+    };
+
+    let transaction = match push_events::v1::Request::try_from_http_request(
+        http::Request::from_parts(parts, body),
+        &[txn_id],
+    ) {
+        Ok(transaction) => transaction,
+        Err(error) => {
+            warn!(?error, "Couldn't parse a transaction");
+            return error_response(StatusCode::BAD_REQUEST, "M_BAD_JSON", &error.to_string());
+        }
+    };
+
+    match appservice.receive_transaction(transaction).await {
+        Ok(()) => Json(json!({})).into_response(),
+        Err(error) => {
+            // The homeserver will send the transaction again.
+            error!(?error, "Couldn't process a transaction");
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "M_UNKNOWN", &error.to_string())
+        }
+    }
+}
+
+/// An error when checking the token of a request.
+enum TokenError {
+    /// The request doesn't have a token.
+    Missing,
+    /// The token of the request isn't the `hs_token` of the registration.
+    Invalid,
+}
+
+/// Check that the request was sent by the homeserver, with the `hs_token` of
+/// the registration.
+fn check_hs_token(appservice: &AppService, request: &Request<Body>) -> Result<(), TokenError> {
+    let header_token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    // Older homeservers send the token in the query string.
+    let query_token = || {
+        request.uri().query().and_then(|query| {
+            url::form_urlencoded::parse(query.as_bytes())
+                .find(|(key, _)| key == "access_token")
+                .map(|(_, value)| value.into_owned())
+        })
+    };
+
+    let Some(token) = header_token.map(ToOwned::to_owned).or_else(query_token) else {
+        return Err(TokenError::Missing);
+    };
+
+    // Compare the tokens in constant time, to not leak the `hs_token` through the
+    // response time.
+    let hs_token = appservice.registration().hs_token.as_bytes();
+    if !bool::from(token.as_bytes().ct_eq(hs_token)) {
+        return Err(TokenError::Invalid);
+    }
+
+    Ok(())
+}
+
+/// An error response, in the format of the application service API.
+fn error_response(status: StatusCode, errcode: &str, message: &str) -> Response {
+    (status, Json(json!({ "errcode": errcode, "error": message }))).into_response()
+}
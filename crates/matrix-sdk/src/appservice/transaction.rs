@@ -0,0 +1,541 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversion of the transactions pushed by the homeserver into sync
+//! responses, so they go through the same processing as the events received
+//! by regular clients.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use matrix_sdk_base::RoomState;
+use ruma::{
+    api::{
+        appservice::event::push_events::v1::{EphemeralData, Request as Transaction},
+        client::sync::sync_events::{v3::Response as SyncResponse, DeviceLists},
+    },
+    assign,
+    events::{AnyTimelineEvent, AnyToDeviceEvent},
+    serde::Raw,
+    DeviceId, OwnedDeviceId, OwnedRoomId, OwnedUserId, RoomId, UserId,
+};
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+/// The membership of a user in a room.
+#[derive(Debug, PartialEq)]
+enum Membership {
+    /// The user is invited to the room.
+    Invited,
+    /// The user left the room, or was kicked or banned from it.
+    Left,
+    /// The user joined the room.
+    Joined,
+    /// The user knocked on the room.
+    Knocked,
+}
+
+impl Membership {
+    /// The membership of a user in a room already known by their client.
+    ///
+    /// The rooms that the user left aren't updated anymore, unless the user
+    /// becomes a member of them again.
+    fn from_room_state(state: RoomState) -> Option<Self> {
+        match state {
+            RoomState::Joined => Some(Self::Joined),
+            RoomState::Invited => Some(Self::Invited),
+            RoomState::Knocked => Some(Self::Knocked),
+            RoomState::Left | RoomState::Banned => None,
+        }
+    }
+}
+
+/// Get the IDs of the users whose membership changes in the given
+/// transaction.
+pub(super) fn members_in_transaction(transaction: &Transaction) -> BTreeSet<OwnedUserId> {
+    transaction
+        .events
+        .iter()
+        .filter_map(|event| event.deserialize_as::<TimelineEventInfo>().ok())
+        .filter(|info| info.event_type == "m.room.member")
+        .filter_map(|info| UserId::parse(info.state_key?).ok())
+        .collect()
+}
+
+/// Convert a transaction into a sync response for the given user and device.
+///
+/// The events of the transaction are sorted by room, and the rooms by the
+/// membership of the user in them: it's taken from the membership events of
+/// the user in the transaction, or from the `room_state` of the rooms already
+/// known by their client. The rooms the user isn't a member of are dropped.
+///
+/// The to-device events and the one-time key counts of the other users and
+/// devices of the application service are dropped; a user without a device
+/// doesn't receive any of them.
+pub(super) fn transaction_to_sync_response(
+    transaction: &Transaction,
+    user_id: &UserId,
+    device_id: Option<&DeviceId>,
+    room_state: impl Fn(&RoomId) -> Option<RoomState>,
+) -> SyncResponse {
+    // The base client ignores the sync responses with the same `next_batch` as
+    // the previous one, and the transaction IDs are unique.
+    let mut response = SyncResponse::new(transaction.txn_id.to_string());
+
+    let mut timelines =
+        BTreeMap::<OwnedRoomId, (Option<Membership>, Vec<Raw<AnyTimelineEvent>>)>::new();
+
+    for event in &transaction.events {
+        let info = match event.deserialize_as::<TimelineEventInfo>() {
+            Ok(info) => info,
+            Err(error) => {
+                warn!(?error, "Ignoring a timeline event without a room ID in a transaction");
+                continue;
+            }
+        };
+
+        let (membership, room_events) = timelines.entry(info.room_id).or_default();
+
+        if info.event_type == "m.room.member" && info.state_key.as_deref() == Some(user_id.as_str())
+        {
+            match info.content.membership.as_deref() {
+                Some("join") => *membership = Some(Membership::Joined),
+                Some("invite") => *membership = Some(Membership::Invited),
+                Some("knock") => *membership = Some(Membership::Knocked),
+                Some("leave" | "ban") => *membership = Some(Membership::Left),
+                value => warn!(membership = ?value, "Ignoring an unknown membership of the user"),
+            }
+        }
+
+        room_events.push(event.clone());
+    }
+
+    for (room_id, (membership, events)) in timelines {
+        let Some(membership) =
+            membership.or_else(|| room_state(&room_id).and_then(Membership::from_room_state))
+        else {
+            // The user isn't a member of this room.
+            continue;
+        };
+
+        match membership {
+            Membership::Invited => {
+                let room = response.rooms.invite.entry(room_id).or_default();
+                room.invite_state.events.extend(events.into_iter().map(Raw::cast));
+            }
+            Membership::Knocked => {
+                let room = response.rooms.knock.entry(room_id).or_default();
+                room.knock_state.events.extend(events.into_iter().map(Raw::cast));
+            }
+            Membership::Left => {
+                let room = response.rooms.leave.entry(room_id).or_default();
+                room.timeline.events.extend(events.into_iter().map(Raw::cast));
+            }
+            Membership::Joined => {
+                let room = response.rooms.join.entry(room_id).or_default();
+                room.timeline.events.extend(events.into_iter().map(Raw::cast));
+            }
+        }
+    }
+
+    for data in &transaction.ephemeral {
+        let (room_id, event) = match data {
+            EphemeralData::Presence(event) => {
+                match Raw::new(event) {
+                    Ok(event) => response.presence.events.push(event),
+                    Err(error) => warn!(?error, "Couldn't serialize a presence event"),
+                }
+                continue;
+            }
+            EphemeralData::Receipt(event) => (&event.room_id, Raw::new(event).map(Raw::cast)),
+            EphemeralData::Typing(event) => (&event.room_id, Raw::new(event).map(Raw::cast)),
+            _ => {
+                debug!(data_type = data.data_type(), "Ignoring unknown ephemeral data");
+                continue;
+            }
+        };
+
+        // Ephemeral events are only relevant in joined rooms.
+        if !response.rooms.join.contains_key(room_id)
+            && room_state(room_id).and_then(Membership::from_room_state) != Some(Membership::Joined)
+        {
+            continue;
+        }
+
+        match event {
+            Ok(event) => {
+                response.rooms.join.entry(room_id.clone()).or_default().ephemeral.events.push(event)
+            }
+            Err(error) => warn!(?error, "Couldn't serialize an ephemeral event"),
+        }
+    }
+
+    let Some(device_id) = device_id else {
+        return response;
+    };
+
+    response.to_device.events = transaction
+        .to_device
+        .iter()
+        .filter(|event| is_to_device_event_for(event, user_id, device_id))
+        .cloned()
+        .collect();
+
+    let device_lists = &transaction.device_lists;
+    response.device_lists = assign!(DeviceLists::new(), {
+        changed: device_lists.changed.clone(),
+        left: device_lists.left.clone(),
+    });
+
+    response.device_one_time_keys_count = transaction
+        .device_one_time_keys_count
+        .get(user_id)
+        .and_then(|devices| devices.get(device_id))
+        .cloned()
+        .unwrap_or_default();
+
+    response.device_unused_fallback_key_types = transaction
+        .device_unused_fallback_key_types
+        .get(user_id)
+        .and_then(|devices| devices.get(device_id))
+        .cloned();
+
+    response
+}
+
+/// Remove the rooms for which `is_encrypted` returns `true` from the given sync
+/// response.
+///
+/// Returns the IDs of the removed rooms.
+pub(super) fn remove_encrypted_rooms(
+    response: &mut SyncResponse,
+    is_encrypted: impl Fn(&RoomId) -> bool,
+) -> Vec<OwnedRoomId> {
+    let rooms = &mut response.rooms;
+    let mut removed = Vec::new();
+
+    rooms.join.retain(|room_id, _| keep_unencrypted(room_id, &is_encrypted, &mut removed));
+    rooms.leave.retain(|room_id, _| keep_unencrypted(room_id, &is_encrypted, &mut removed));
+    rooms.invite.retain(|room_id, _| keep_unencrypted(room_id, &is_encrypted, &mut removed));
+    rooms.knock.retain(|room_id, _| keep_unencrypted(room_id, &is_encrypted, &mut removed));
+
+    removed
+}
+
+fn keep_unencrypted(
+    room_id: &RoomId,
+    is_encrypted: impl Fn(&RoomId) -> bool,
+    removed: &mut Vec<OwnedRoomId>,
+) -> bool {
+    if is_encrypted(room_id) {
+        removed.push(room_id.to_owned());
+        false
+    } else {
+        true
+    }
+}
+
+/// Whether the given to-device event is sent to the given user and device.
+///
+/// The to-device events of the transactions are annotated with their
+/// recipient, as defined in MSC4203.
+fn is_to_device_event_for(
+    event: &Raw<AnyToDeviceEvent>,
+    user_id: &UserId,
+    device_id: &DeviceId,
+) -> bool {
+    #[derive(Deserialize)]
+    struct Recipient {
+        to_user_id: OwnedUserId,
+        to_device_id: OwnedDeviceId,
+    }
+
+    match event.deserialize_as::<Recipient>() {
+        Ok(recipient) => recipient.to_user_id == user_id && recipient.to_device_id == device_id,
+        Err(error) => {
+            warn!(?error, "Ignoring a to-device event without a recipient in a transaction");
+            false
+        }
+    }
+}
+
+/// The fields of a timeline event needed to sort it.
+#[derive(Deserialize)]
+struct TimelineEventInfo {
+    room_id: OwnedRoomId,
+    #[serde(rename = "type")]
+    event_type: String,
+    state_key: Option<String>,
+    #[serde(default)]
+    content: MembershipContent,
+}
+
+#[derive(Default, Deserialize)]
+struct MembershipContent {
+    membership: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::assert_let;
+    use matrix_sdk_base::RoomState;
+    use ruma::{
+        api::appservice::event::push_events::v1::{EphemeralData, Request as Transaction},
+        device_id, owned_user_id, room_id,
+        serde::Raw,
+        user_id, OneTimeKeyAlgorithm,
+    };
+    use serde_json::{from_value as from_json_value, json};
+
+    use super::{members_in_transaction, remove_encrypted_rooms, transaction_to_sync_response};
+
+    fn event(json: serde_json::Value) -> Raw<ruma::events::AnyTimelineEvent> {
+        from_json_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_transaction_to_sync_response() {
+        let user_id = user_id!("@bot:example.org");
+        let device_id = device_id!("BOTDEVICE");
+
+        let mut transaction = Transaction::new(
+            "txn1".into(),
+            vec![
+                event(json!({
+                    "type": "m.room.message",
+                    "event_id": "$message",
+                    "room_id": "!joined:example.org",
+                    "sender": "@alice:example.org",
+                    "origin_server_ts": 1,
+                    "content": { "msgtype": "m.text", "body": "Hello" },
+                })),
+                event(json!({
+                    "type": "m.room.member",
+                    "event_id": "$invite",
+                    "room_id": "!invited:example.org",
+                    "sender": "@alice:example.org",
+                    "state_key": "@bot:example.org",
+                    "origin_server_ts": 2,
+                    "content": { "membership": "invite" },
+                })),
+                event(json!({
+                    "type": "m.room.member",
+                    "event_id": "$kick",
+                    "room_id": "!left:example.org",
+                    "sender": "@alice:example.org",
+                    "state_key": "@bot:example.org",
+                    "origin_server_ts": 3,
+                    "content": { "membership": "leave" },
+                })),
+                event(json!({
+                    "type": "m.room.member",
+                    "event_id": "$join",
+                    "room_id": "!other:example.org",
+                    "sender": "@_bridge_bob:example.org",
+                    "state_key": "@_bridge_bob:example.org",
+                    "origin_server_ts": 4,
+                    "content": { "membership": "join" },
+                })),
+            ],
+        );
+        transaction.ephemeral = vec![from_json_value::<EphemeralData>(json!({
+            "type": "m.typing",
+            "room_id": "!joined:example.org",
+            "content": { "user_ids": ["@alice:example.org"] },
+        }))
+        .unwrap()];
+        transaction.to_device = vec![
+            from_json_value(json!({
+                "type": "m.dummy",
+                "sender": "@alice:example.org",
+                "to_user_id": "@bot:example.org",
+                "to_device_id": "BOTDEVICE",
+                "content": {},
+            }))
+            .unwrap(),
+            from_json_value(json!({
+                "type": "m.dummy",
+                "sender": "@alice:example.org",
+                "to_user_id": "@_bridge_bob:example.org",
+                "to_device_id": "BOBDEVICE",
+                "content": {},
+            }))
+            .unwrap(),
+        ];
+        transaction.device_lists.changed = vec![owned_user_id!("@alice:example.org")];
+        transaction.device_one_time_keys_count = [
+            (
+                user_id.to_owned(),
+                [(
+                    device_id.to_owned(),
+                    [(OneTimeKeyAlgorithm::SignedCurve25519, 50u32.into())].into(),
+                )]
+                .into(),
+            ),
+            (
+                owned_user_id!("@_bridge_bob:example.org"),
+                [(
+                    "BOBDEVICE".into(),
+                    [(OneTimeKeyAlgorithm::SignedCurve25519, 10u32.into())].into(),
+                )]
+                .into(),
+            ),
+        ]
+        .into();
+
+        assert_eq!(
+            members_in_transaction(&transaction).into_iter().collect::<Vec<_>>(),
+            [owned_user_id!("@_bridge_bob:example.org"), owned_user_id!("@bot:example.org")]
+        );
+
+        // The room where the bot is joined is only known by its client.
+        let room_state = |room_id: &ruma::RoomId| {
+            (room_id == room_id!("!joined:example.org")).then_some(RoomState::Joined)
+        };
+        let response =
+            transaction_to_sync_response(&transaction, user_id, Some(device_id), room_state);
+
+        assert_eq!(response.next_batch, "txn1");
+        assert!(!response.rooms.join.contains_key(room_id!("!other:example.org")));
+
+        assert_let!(Some(joined) = response.rooms.join.get(room_id!("!joined:example.org")));
+        assert_eq!(joined.timeline.events.len(), 1);
+        assert_eq!(joined.ephemeral.events.len(), 1);
+
+        assert_let!(Some(invited) = response.rooms.invite.get(room_id!("!invited:example.org")));
+        assert_eq!(invited.invite_state.events.len(), 1);
+
+        assert_let!(Some(left) = response.rooms.leave.get(room_id!("!left:example.org")));
+        assert_eq!(left.timeline.events.len(), 1);
+
+        assert_eq!(response.to_device.events.len(), 1);
+        assert_eq!(response.device_lists.changed, vec![owned_user_id!("@alice:example.org")]);
+        assert_eq!(
+            response.device_one_time_keys_count.get(&OneTimeKeyAlgorithm::SignedCurve25519),
+            Some(&50u32.into())
+        );
+        assert_eq!(response.device_unused_fallback_key_types, None);
+
+        // The virtual user only receives the room they joined, without any to-device
+        // event.
+        let response = transaction_to_sync_response(
+            &transaction,
+            user_id!("@_bridge_bob:example.org"),
+            None,
+            |_| None,
+        );
+
+        assert_eq!(response.rooms.join.len(), 1);
+        assert_let!(Some(joined) = response.rooms.join.get(room_id!("!other:example.org")));
+        assert_eq!(joined.timeline.events.len(), 1);
+        assert!(response.rooms.invite.is_empty());
+        assert!(response.rooms.leave.is_empty());
+        assert!(response.to_device.events.is_empty());
+        assert!(response.device_one_time_keys_count.is_empty());
+    }
+
+    #[test]
+    fn test_remove_encrypted_rooms() {
+        let user_id = user_id!("@_bridge_bob:example.org");
+
+        let transaction = Transaction::new(
+            "txn1".into(),
+            ["!encrypted:example.org", "!unencrypted:example.org"]
+                .into_iter()
+                .map(|room_id| {
+                    event(json!({
+                        "type": "m.room.message",
+                        "event_id": format!("$message{room_id}"),
+                        "room_id": room_id,
+                        "sender": "@alice:example.org",
+                        "origin_server_ts": 1,
+                        "content": { "msgtype": "m.text", "body": "Hello" },
+                    }))
+                })
+                .collect(),
+        );
+
+        let mut response =
+            transaction_to_sync_response(&transaction, user_id, None, |_| Some(RoomState::Joined));
+        assert_eq!(response.rooms.join.len(), 2);
+
+        let removed = remove_encrypted_rooms(&mut response, |room_id| {
+            room_id == room_id!("!encrypted:example.org")
+        });
+
+        assert_eq!(removed, [room_id!("!encrypted:example.org").to_owned()]);
+        assert_eq!(response.rooms.join.len(), 1);
+        assert!(response.rooms.join.contains_key(room_id!("!unencrypted:example.org")));
+    }
+
+    #[test]
+    fn test_transaction_with_knock() {
+        let user_id = user_id!("@_bridge_bob:example.org");
+
+        let transaction = Transaction::new(
+            "txn1".into(),
+            vec![
+                event(json!({
+                    "type": "m.room.member",
+                    "event_id": "$knock",
+                    "room_id": "!knocked:example.org",
+                    "sender": "@_bridge_bob:example.org",
+                    "state_key": "@_bridge_bob:example.org",
+                    "origin_server_ts": 1,
+                    "content": { "membership": "knock" },
+                })),
+                event(json!({
+                    "type": "m.room.member",
+                    "event_id": "$unknown",
+                    "room_id": "!unknown:example.org",
+                    "sender": "@_bridge_bob:example.org",
+                    "state_key": "@_bridge_bob:example.org",
+                    "origin_server_ts": 2,
+                    "content": { "membership": "custom" },
+                })),
+            ],
+        );
+
+        let response = transaction_to_sync_response(&transaction, user_id, None, |_| None);
+
+        // The knocked room isn't a joined room, and the room with an unknown
+        // membership is dropped.
+        assert!(response.rooms.join.is_empty());
+        assert!(response.rooms.invite.is_empty());
+        assert!(response.rooms.leave.is_empty());
+        assert_eq!(response.rooms.knock.len(), 1);
+        assert_let!(Some(knocked) = response.rooms.knock.get(room_id!("!knocked:example.org")));
+        assert_eq!(knocked.knock_state.events.len(), 1);
+
+        // Once the room is known as knocked, the next events go to the same room.
+        let transaction = Transaction::new(
+            "txn2".into(),
+            vec![event(json!({
+                "type": "m.room.topic",
+                "event_id": "$topic",
+                "room_id": "!knocked:example.org",
+                "sender": "@alice:example.org",
+                "state_key": "",
+                "origin_server_ts": 3,
+                "content": { "topic": "Knock knock" },
+            }))],
+        );
+
+        let response = transaction_to_sync_response(&transaction, user_id, None, |room_id| {
+            (room_id == room_id!("!knocked:example.org")).then_some(RoomState::Knocked)
+        });
+
+        assert!(response.rooms.join.is_empty());
+        assert!(response.rooms.knock.contains_key(room_id!("!knocked:example.org")));
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+
+use ruma::{
+    api::{
+        client::{
+            account::register::{self, LoginType},
+            error::ErrorKind,
+            membership::join_room_by_id,
+            profile::set_display_name,
+        },
+        error::FromHttpResponseError,
+        OutgoingRequest,
+    },
+    assign, OwnedUserId, RoomId, UserId,
+};
+
+use super::AppServiceError;
+use crate::{client::futures::SendRequest, Client, HttpError, HttpResult};
+
+/// A user in the namespace of an application service.
+///
+/// Each virtual user has their own [`Client`], logged in with the access token
+/// of the application service, whose requests are all sent on behalf of the
+/// user. It receives the events of the transactions in the rooms the user is a
+/// member of, so the event handlers registered on it are called for them.
+///
+/// The client of a virtual user doesn't have a device on the homeserver, so it
+/// doesn't support end-to-end encryption: the to-device events sent to the
+/// virtual users are dropped, [`VirtualUser::join_room_by_id()`] refuses to
+/// join encrypted rooms, and the events of the rooms known to be encrypted
+/// aren't fed to its client.
+///
+/// Constructed with [`AppService::virtual_user()`].
+///
+/// [`AppService::virtual_user()`]: super::AppService::virtual_user
+#[derive(Clone, Debug)]
+pub struct VirtualUser {
+    /// The client of the application service user, to register this user.
+    appservice_client: Client,
+    /// The client of this user.
+    client: Client,
+    user_id: OwnedUserId,
+}
+
+impl VirtualUser {
+    pub(super) fn new(appservice_client: Client, client: Client, user_id: OwnedUserId) -> Self {
+        Self { appservice_client, client, user_id }
+    }
+
+    /// The ID of this user.
+    pub fn user_id(&self) -> &UserId {
+        &self.user_id
+    }
+
+    /// The client of this user.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Send an arbitrary request on behalf of this user.
+    ///
+    /// See [`Client::send()`] for more information.
+    pub fn send<Request>(&self, request: Request) -> SendRequest<Request>
+    where
+        Request: OutgoingRequest + Clone + Debug,
+        HttpError: From<FromHttpResponseError<Request::EndpointError>>,
+    {
+        self.client.send(request)
+    }
+
+    /// Register this user on the homeserver, if they aren't registered yet.
+    pub async fn register(&self) -> HttpResult<()> {
+        let request = assign!(register::v3::Request::new(), {
+            username: Some(self.user_id.localpart().to_owned()),
+            login_type: Some(LoginType::ApplicationService),
+            inhibit_login: true,
+        });
+
+        // The user doesn't exist yet, so their identity can't be asserted.
+        let config = self.appservice_client.request_config().force_auth();
+
+        match self.appservice_client.send(request).with_request_config(config).await {
+            Ok(_) => Ok(()),
+            Err(error) if error.client_api_error_kind() == Some(&ErrorKind::UserInUse) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Set the display name of this user.
+    pub async fn set_display_name(&self, name: Option<&str>) -> HttpResult<()> {
+        let request =
+            set_display_name::v3::Request::new(self.user_id.clone(), name.map(ToOwned::to_owned));
+        self.send(request).await?;
+        Ok(())
+    }
+
+    /// Join the room with the given ID as this user.
+    ///
+    /// Returns [`AppServiceError::EncryptedRoom`] if the room is known to be
+    /// encrypted, since the virtual users don't support end-to-end
+    /// encryption.
+    pub async fn join_room_by_id(&self, room_id: &RoomId) -> Result<(), AppServiceError> {
+        if self.is_room_encrypted(room_id) {
+            return Err(AppServiceError::EncryptedRoom(room_id.to_owned()));
+        }
+
+        self.send(join_room_by_id::v3::Request::new(room_id.to_owned())).await?;
+        Ok(())
+    }
+
+    /// Whether the room with the given ID is known to be encrypted, by the
+    /// client of this user or by the client of the application service.
+    pub(super) fn is_room_encrypted(&self, room_id: &RoomId) -> bool {
+        [&self.client, &self.appservice_client].into_iter().any(|client| {
+            client.get_room(room_id).is_some_and(|room| room.encryption_state().is_encrypted())
+        })
+    }
+}
@@ -175,10 +175,12 @@ use error::{
     OAuthTokenRevocationError, RedirectUriQueryParseError,
 };
 #[cfg(feature = "e2e-encryption")]
-use matrix_sdk_base::crypto::types::qr_login::QrCodeData;
+use matrix_sdk_base::crypto::{store::LockableCryptoStore, types::qr_login::QrCodeData};
 #[cfg(feature = "e2e-encryption")]
 use matrix_sdk_base::once_cell::sync::OnceCell;
 use matrix_sdk_base::{store::RoomLoadSettings, SessionMeta};
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk_common::store_locks::CrossProcessStoreLock;
 use oauth2::{
     basic::BasicClient as OAuthClient, AccessToken, PkceCodeVerifier, RedirectUrl, RefreshToken,
     RevocationUrl, Scope, StandardErrorResponse, StandardRevocableToken, TokenResponse, TokenUrl,
@@ -231,12 +233,13 @@ pub(crate) struct OAuthCtx {
     #[cfg(feature = "e2e-encryption")]
     cross_process_token_refresh_manager: OnceCell<CrossProcessRefreshManager>,
 
-    /// Deferred cross-process lock initializer.
+    /// Deferred cross-process lock initializer: the value of the lock holder,
+    /// and the store to use for the lock if it's not the crypto store.
     ///
     /// Note: only required because we're using the crypto store that might not
     /// be present before reloading a session.
     #[cfg(feature = "e2e-encryption")]
-    deferred_cross_process_lock_init: Mutex<Option<String>>,
+    deferred_cross_process_lock_init: Mutex<Option<(String, Option<LockableCryptoStore>)>>,
 
     /// Whether to allow HTTP issuer URLs.
     insecure_discover: bool,
@@ -311,6 +314,31 @@ impl OAuth {
     pub async fn enable_cross_process_refresh_lock(
         &self,
         lock_value: String,
+    ) -> Result<(), OAuthError> {
+        self.defer_cross_process_refresh_lock(lock_value, None).await
+    }
+
+    /// Enable a cross-process lock to coordinate refreshes across different
+    /// processes, keeping the leases of the lock in the files of the given
+    /// [`FileLockStore`] rather than in the crypto store.
+    ///
+    /// All the processes must use a `FileLockStore` with the same directory.
+    ///
+    /// [`FileLockStore`]: crate::store_locks::FileLockStore
+    #[cfg(all(feature = "e2e-encryption", not(target_family = "wasm")))]
+    pub async fn enable_cross_process_refresh_file_lock(
+        &self,
+        lock_value: String,
+        lock_store: crate::store_locks::FileLockStore,
+    ) -> Result<(), OAuthError> {
+        self.defer_cross_process_refresh_lock(lock_value, Some(lock_store.into())).await
+    }
+
+    #[cfg(feature = "e2e-encryption")]
+    async fn defer_cross_process_refresh_lock(
+        &self,
+        lock_value: String,
+        lock_store: Option<LockableCryptoStore>,
     ) -> Result<(), OAuthError> {
         // FIXME: it must be deferred only because we're using the crypto store and it's
         // initialized only in `set_or_reload_session`, not if we use a dedicated store.
@@ -318,7 +346,7 @@ impl OAuth {
         if lock.is_some() {
             return Err(CrossProcessRefreshLockError::DuplicatedLock.into());
         }
-        *lock = Some(lock_value);
+        *lock = Some((lock_value, lock_store));
 
         Ok(())
     }
@@ -334,7 +362,7 @@ impl OAuth {
         // Don't `take()` the value, so that subsequent calls to
         // `enable_cross_process_refresh_lock` will keep on failing if we've enabled the
         // lock at least once.
-        let Some(lock_value) = deferred_init_lock.as_ref() else {
+        let Some((lock_value, lock_store)) = deferred_init_lock.as_ref() else {
             return;
         };
 
@@ -343,8 +371,13 @@ impl OAuth {
         let olm_machine =
             olm_machine_lock.as_ref().expect("there has to be an olm machine, hopefully?");
         let store = olm_machine.store();
-        let lock =
-            store.create_store_lock("oidc_session_refresh_lock".to_owned(), lock_value.clone());
+        let lock_key = "oidc_session_refresh_lock".to_owned();
+        let lock = match lock_store {
+            Some(lock_store) => {
+                CrossProcessStoreLock::new(lock_store.clone(), lock_key, lock_value.clone())
+            }
+            None => store.create_store_lock(lock_key, lock_value.clone()),
+        };
 
         let manager = CrossProcessRefreshManager::new(store.clone(), lock);
 
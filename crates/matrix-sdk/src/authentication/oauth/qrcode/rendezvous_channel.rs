@@ -118,7 +118,7 @@ impl RendezvousChannel {
 
         let request = create_rendezvous_session::unstable::Request::default();
         let response = client
-            .send(request, None, rendezvous_server.to_string(), None, &[], Default::default())
+            .send(request, None, rendezvous_server.to_string(), None, None, &[], Default::default())
             .await?;
 
         let rendezvous_url = response.url;
@@ -185,6 +185,7 @@ async fn discover_homeserver(
             Some(RequestConfig::short_retry()),
             server.to_string(),
             None,
+            None,
             &[MatrixVersion::V1_0],
             Default::default(),
         )
@@ -209,6 +210,7 @@ pub(super) async fn get_supported_versions(
             Some(RequestConfig::short_retry()),
             homeserver_url.to_string(),
             None,
+            None,
             &[MatrixVersion::V1_0],
             Default::default(),
         )
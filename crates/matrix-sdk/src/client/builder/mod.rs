@@ -47,7 +47,7 @@ use crate::{
         ClientServerInfo,
     },
     config::RequestConfig,
-    error::RumaApiError,
+    error::{ChangeStorePassphraseError, RumaApiError},
     http_client::HttpClient,
     send_queue::SendQueueData,
     sliding_sync::VersionBuilder as SlidingSyncVersionBuilder,
@@ -114,6 +114,7 @@ pub struct ClientBuilder {
     enable_share_history_on_invite: bool,
     cross_process_store_locks_holder_name: String,
     threading_support: ThreadingSupport,
+    offline_first: bool,
 }
 
 impl ClientBuilder {
@@ -145,6 +146,7 @@ impl ClientBuilder {
             cross_process_store_locks_holder_name:
                 Self::DEFAULT_CROSS_PROCESS_STORE_LOCKS_HOLDER_NAME.to_owned(),
             threading_support: ThreadingSupport::Disabled,
+            offline_first: false,
         }
     }
 
@@ -467,6 +469,28 @@ impl ClientBuilder {
         self
     }
 
+    /// Whether previously visited rooms should be served from the local stores
+    /// when the homeserver can't be reached.
+    ///
+    /// When enabled, [`Room::members`], [`Room::get_member`] and
+    /// [`Room::event_with_context`] don't fail when their request fails
+    /// because of a network error: they use the data from the state store and
+    /// the event cache instead, and mark it as stale (see
+    /// [`Room::are_members_stale`] and
+    /// [`EventWithContextResponse::is_stale`]).
+    ///
+    /// The default is `false`.
+    ///
+    /// [`Room::members`]: crate::Room::members
+    /// [`Room::get_member`]: crate::Room::get_member
+    /// [`Room::event_with_context`]: crate::Room::event_with_context
+    /// [`Room::are_members_stale`]: crate::Room::are_members_stale
+    /// [`EventWithContextResponse::is_stale`]: crate::room::EventWithContextResponse::is_stale
+    pub fn with_offline_first(mut self, offline_first: bool) -> Self {
+        self.offline_first = offline_first;
+        self
+    }
+
     /// Set the cross-process store locks holder name.
     ///
     /// The SDK provides cross-process store locks (see
@@ -518,23 +542,26 @@ impl ClientBuilder {
             HttpConfig::Custom(c) => c,
         };
 
-        let base_client = if let Some(base_client) = self.base_client {
-            base_client
-        } else {
-            #[allow(unused_mut)]
-            let mut client = BaseClient::new(
-                build_store_config(self.store_config, &self.cross_process_store_locks_holder_name)
-                    .await?,
-                self.threading_support,
-            );
+        let (base_client, passphrase_protected_stores) = match self.base_client {
+            Some(base_client) => (base_client, None),
+            None => {
+                let (store_config, passphrase_protected_stores) = build_store_config(
+                    self.store_config,
+                    &self.cross_process_store_locks_holder_name,
+                )
+                .await?;
 
-            #[cfg(feature = "e2e-encryption")]
-            {
-                client.room_key_recipient_strategy = self.room_key_recipient_strategy;
-                client.decryption_settings = self.decryption_settings;
-            }
+                #[allow(unused_mut)]
+                let mut client = BaseClient::new(store_config, self.threading_support);
+
+                #[cfg(feature = "e2e-encryption")]
+                {
+                    client.room_key_recipient_strategy = self.room_key_recipient_strategy;
+                    client.decryption_settings = self.decryption_settings;
+                }
 
-            client
+                (client, passphrase_protected_stores)
+            }
         };
 
         let http_client = HttpClient::new(inner_http_client.clone(), self.request_config);
@@ -601,12 +628,16 @@ impl ClientBuilder {
             self.respect_login_well_known,
             event_cache,
             send_queue,
+            Default::default(),
+            Default::default(),
             latest_events,
             #[cfg(feature = "e2e-encryption")]
             self.encryption_settings,
             #[cfg(feature = "e2e-encryption")]
             self.enable_share_history_on_invite,
             self.cross_process_store_locks_holder_name,
+            passphrase_protected_stores,
+            self.offline_first,
         )
         .await;
 
@@ -629,44 +660,56 @@ pub fn sanitize_server_name(s: &str) -> crate::Result<OwnedServerName, IdParseEr
 async fn build_store_config(
     builder_config: BuilderStoreConfig,
     cross_process_store_locks_holder_name: &str,
-) -> Result<StoreConfig, ClientBuildError> {
+) -> Result<(StoreConfig, Option<PassphraseProtectedStores>), ClientBuildError> {
     #[allow(clippy::infallible_destructuring_match)]
     let store_config = match builder_config {
         #[cfg(feature = "sqlite")]
         BuilderStoreConfig::Sqlite { config, cache_path } => {
-            let store_config = StoreConfig::new(cross_process_store_locks_holder_name.to_owned())
-                .state_store(
-                    matrix_sdk_sqlite::SqliteStateStore::open_with_config(config.clone()).await?,
-                )
-                .event_cache_store({
-                    let mut config = config.clone();
+            let state_store =
+                matrix_sdk_sqlite::SqliteStateStore::open_with_config(config.clone()).await?;
+            let event_cache_store = {
+                let mut config = config.clone();
 
-                    if let Some(cache_path) = cache_path {
-                        config = config.path(cache_path);
-                    }
+                if let Some(cache_path) = cache_path {
+                    config = config.path(cache_path);
+                }
 
-                    matrix_sdk_sqlite::SqliteEventCacheStore::open_with_config(config).await?
-                });
+                matrix_sdk_sqlite::SqliteEventCacheStore::open_with_config(config).await?
+            };
+
+            let store_config = StoreConfig::new(cross_process_store_locks_holder_name.to_owned())
+                .state_store(state_store.clone())
+                .event_cache_store(event_cache_store.clone());
 
             #[cfg(feature = "e2e-encryption")]
-            let store_config = store_config.crypto_store(
-                matrix_sdk_sqlite::SqliteCryptoStore::open_with_config(config).await?,
-            );
+            let crypto_store =
+                matrix_sdk_sqlite::SqliteCryptoStore::open_with_config(config).await?;
+            #[cfg(feature = "e2e-encryption")]
+            let store_config = store_config.crypto_store(crypto_store.clone());
 
-            store_config
+            let stores = PassphraseProtectedStores::Sqlite {
+                state_store,
+                event_cache_store,
+                #[cfg(feature = "e2e-encryption")]
+                crypto_store,
+            };
+
+            (store_config, Some(stores))
         }
 
         #[cfg(feature = "indexeddb")]
         BuilderStoreConfig::IndexedDb { name, passphrase } => {
-            build_indexeddb_store_config(
+            let store_config = build_indexeddb_store_config(
                 &name,
                 passphrase.as_deref(),
                 cross_process_store_locks_holder_name,
             )
-            .await?
+            .await?;
+
+            (store_config, Some(PassphraseProtectedStores::IndexedDb { name }))
         }
 
-        BuilderStoreConfig::Custom(config) => config,
+        BuilderStoreConfig::Custom(config) => (config, None),
     };
     Ok(store_config)
 }
@@ -787,6 +830,71 @@ impl fmt::Debug for BuilderStoreConfig {
     }
 }
 
+/// The stores opened by the [`ClientBuilder`] from a [`BuilderStoreConfig`],
+/// kept around to be able to change their passphrase.
+#[derive(Clone, Debug)]
+pub(crate) enum PassphraseProtectedStores {
+    #[cfg(feature = "sqlite")]
+    Sqlite {
+        state_store: matrix_sdk_sqlite::SqliteStateStore,
+        event_cache_store: matrix_sdk_sqlite::SqliteEventCacheStore,
+        #[cfg(feature = "e2e-encryption")]
+        crypto_store: matrix_sdk_sqlite::SqliteCryptoStore,
+    },
+    #[cfg(feature = "indexeddb")]
+    IndexedDb { name: String },
+}
+
+impl PassphraseProtectedStores {
+    /// Change the passphrase used to encrypt all the stores.
+    // False positive when building with !sqlite & !indexeddb.
+    #[allow(clippy::unused_async, unused)]
+    pub(crate) async fn change_passphrase(
+        &self,
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<(), ChangeStorePassphraseError> {
+        match *self {
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite {
+                ref state_store,
+                ref event_cache_store,
+                #[cfg(feature = "e2e-encryption")]
+                ref crypto_store,
+            } => {
+                // Prepare the change on all the stores before committing it on any of them,
+                // so that they can all be opened with the new passphrase if the change is
+                // interrupted halfway through.
+                state_store.prepare_passphrase_change(old_passphrase, new_passphrase).await?;
+                event_cache_store.prepare_passphrase_change(old_passphrase, new_passphrase).await?;
+                #[cfg(feature = "e2e-encryption")]
+                crypto_store.prepare_passphrase_change(old_passphrase, new_passphrase).await?;
+
+                state_store.commit_passphrase_change().await?;
+                event_cache_store.commit_passphrase_change().await?;
+                #[cfg(feature = "e2e-encryption")]
+                crypto_store.commit_passphrase_change().await?;
+
+                Ok(())
+            }
+
+            #[cfg(feature = "indexeddb")]
+            Self::IndexedDb { ref name } => {
+                // The state and crypto stores share the same store cipher, which is changed
+                // in a single transaction.
+                matrix_sdk_indexeddb::change_stores_passphrase(
+                    name,
+                    old_passphrase,
+                    new_passphrase,
+                )
+                .await?;
+
+                Ok(())
+            }
+        }
+    }
+}
+
 /// Errors that can happen in [`ClientBuilder::build`].
 #[derive(Debug, Error)]
 pub enum ClientBuildError {
@@ -20,20 +20,23 @@ use eyeball::{SharedObservable, Subscriber};
 use js_int::UInt;
 use matrix_sdk_common::{boxed_into_future, SendOutsideWasm, SyncOutsideWasm};
 use oauth2::{basic::BasicErrorResponseType, RequestTokenError};
-use ruma::api::{
-    client::{error::ErrorKind, media},
-    error::FromHttpResponseError,
-    OutgoingRequest,
+use ruma::{
+    api::{
+        client::{error::ErrorKind, media},
+        error::FromHttpResponseError,
+        OutgoingRequest,
+    },
+    OwnedUserId,
 };
 use tracing::{error, trace};
 
-use super::super::Client;
+use super::{super::Client, storage::CompactionProgress};
 use crate::{
     authentication::oauth::OAuthError,
     config::RequestConfig,
     error::{HttpError, HttpResult},
     media::MediaError,
-    Error, RefreshTokenError, TransmissionProgress,
+    Error, RefreshTokenError, Result, TransmissionProgress,
 };
 
 /// `IntoFuture` returned by [`Client::send`].
@@ -42,6 +45,7 @@ pub struct SendRequest<R> {
     pub(crate) client: Client,
     pub(crate) request: R,
     pub(crate) config: Option<RequestConfig>,
+    pub(crate) assert_identity: Option<OwnedUserId>,
     pub(crate) send_progress: SharedObservable<TransmissionProgress>,
 }
 
@@ -67,6 +71,19 @@ impl<R> SendRequest<R> {
         self
     }
 
+    /// Send this request on behalf of the given user, by [asserting their
+    /// identity] as an application service.
+    ///
+    /// The user must be in the namespace of the application service whose
+    /// access token is used by the client.
+    ///
+    /// [asserting their identity]: https://spec.matrix.org/latest/application-service-api/#identity-assertion
+    #[cfg(feature = "appservice")]
+    pub fn assert_identity(mut self, user_id: OwnedUserId) -> Self {
+        self.assert_identity = Some(user_id);
+        self
+    }
+
     /// Get a subscriber to observe the progress of sending the request
     /// body.
     pub fn subscribe_to_send_progress(&self) -> Subscriber<TransmissionProgress> {
@@ -84,11 +101,16 @@ where
     boxed_into_future!();
 
     fn into_future(self) -> Self::IntoFuture {
-        let Self { client, request, config, send_progress } = self;
+        let Self { client, request, config, assert_identity, send_progress } = self;
 
         Box::pin(async move {
-            let res =
-                Box::pin(client.send_inner(request.clone(), config, send_progress.clone())).await;
+            let res = Box::pin(client.send_inner(
+                request.clone(),
+                config,
+                assert_identity.as_deref(),
+                send_progress.clone(),
+            ))
+            .await;
 
             // An `M_UNKNOWN_TOKEN` error can potentially be fixed with a token refresh.
             if let Err(Some(ErrorKind::UnknownToken { soft_logout })) =
@@ -147,7 +169,13 @@ where
                     }
                 } else {
                     trace!("Token refresh: Refresh succeeded, retrying request.");
-                    return Box::pin(client.send_inner(request, config, send_progress)).await;
+                    return Box::pin(client.send_inner(
+                        request,
+                        config,
+                        assert_identity.as_deref(),
+                        send_progress,
+                    ))
+                    .await;
                 }
             }
 
@@ -213,3 +241,41 @@ impl IntoFuture for SendMediaUploadRequest {
         })
     }
 }
+
+/// `IntoFuture` returned by [`Client::compact_stores`].
+#[allow(missing_debug_implementations)]
+pub struct CompactStores {
+    pub(crate) client: Client,
+    pub(crate) progress: SharedObservable<CompactionProgress>,
+}
+
+impl CompactStores {
+    /// Replace the default `SharedObservable` used for tracking the progress
+    /// of the compaction.
+    ///
+    /// Note that any subscribers obtained from
+    /// [`subscribe_to_progress`][Self::subscribe_to_progress] will be
+    /// invalidated by this.
+    pub fn with_progress_observable(
+        mut self,
+        progress: SharedObservable<CompactionProgress>,
+    ) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Get a subscriber to observe the progress of the compaction.
+    pub fn subscribe_to_progress(&self) -> Subscriber<CompactionProgress> {
+        self.progress.subscribe()
+    }
+}
+
+impl IntoFuture for CompactStores {
+    type Output = Result<()>;
+    boxed_into_future!();
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { client, progress } = self;
+        Box::pin(async move { client.compact_stores_inner(progress).await })
+    }
+}
@@ -75,7 +75,7 @@ use tokio::sync::{broadcast, Mutex, OnceCell, RwLock, RwLockReadGuard};
 use tracing::{debug, error, instrument, trace, warn, Instrument, Span};
 use url::Url;
 
-use self::futures::SendRequest;
+use self::futures::{CompactStores, SendRequest};
 use crate::{
     authentication::{
         matrix::MatrixAuth, oauth::OAuth, AuthCtx, AuthData, ReloadSessionCallback,
@@ -83,17 +83,22 @@ use crate::{
     },
     config::RequestConfig,
     deduplicating_handler::DeduplicatingHandler,
-    error::HttpResult,
+    error::{ChangeStorePassphraseError, HttpResult},
     event_cache::EventCache,
     event_handler::{
         EventHandler, EventHandlerContext, EventHandlerDropGuard, EventHandlerHandle,
         EventHandlerStore, ObservableEventHandler, SyncEvent,
     },
     http_client::HttpClient,
+    invite_filter::InviteFilterData,
     latest_events::LatestEvents,
     media::MediaError,
     notification_settings::NotificationSettings,
-    room::RoomMember,
+    policy_list::PolicyListData,
+    room::{
+        knock_requests::{own_knocks_stream, OwnKnock},
+        RoomMember,
+    },
     room_preview::RoomPreview,
     send_queue::{SendQueue, SendQueueData},
     sliding_sync::Version as SlidingSyncVersion,
@@ -110,8 +115,13 @@ use crate::{
 mod builder;
 pub(crate) mod caches;
 pub(crate) mod futures;
+mod storage;
 
-pub use self::builder::{sanitize_server_name, ClientBuildError, ClientBuilder};
+use self::builder::PassphraseProtectedStores;
+pub use self::{
+    builder::{sanitize_server_name, ClientBuildError, ClientBuilder},
+    storage::{CompactionProgress, StorageReport, StoreKind},
+};
 
 #[cfg(not(target_family = "wasm"))]
 type NotificationHandlerFut = Pin<Box<dyn Future<Output = ()> + Send>>;
@@ -165,6 +175,12 @@ pub(crate) struct ClientLocks {
     /// explanation.
     pub(crate) mark_as_dm_lock: Mutex<()>,
 
+    /// Lock ensuring that only a single report is added to the local records
+    /// of the reports at once, see [`Moderation`].
+    ///
+    /// [`Moderation`]: crate::moderation::Moderation
+    pub(crate) moderation_reports_lock: Mutex<()>,
+
     /// Lock ensuring that only a single secret store is getting opened at the
     /// same time.
     ///
@@ -341,14 +357,41 @@ pub(crate) struct ClientInner {
     /// [`SendQueue`]: crate::send_queue::SendQueue
     pub(crate) send_queue_data: Arc<SendQueueData>,
 
+    /// Data related to the [`PolicyListManager`].
+    ///
+    /// [`PolicyListManager`]: crate::policy_list::PolicyListManager
+    pub(crate) policy_list_data: Arc<PolicyListData>,
+
+    /// Data related to the [`InviteFilter`].
+    ///
+    /// [`InviteFilter`]: crate::invite_filter::InviteFilter
+    pub(crate) invite_filter_data: Arc<InviteFilterData>,
+
     /// The `max_upload_size` value of the homeserver, it contains the max
     /// request size you can send.
     pub(crate) server_max_upload_size: Mutex<OnceCell<UInt>>,
 
+    /// The stores opened by the [`ClientBuilder`], if their passphrase can be
+    /// changed with [`Client::change_store_passphrase`].
+    passphrase_protected_stores: Option<PassphraseProtectedStores>,
+
     /// The entry point to get the [`LatestEvent`] of rooms and threads.
     ///
     /// [`LatestEvent`]: crate::latest_event::LatestEvent
     latest_events: OnceCell<LatestEvents>,
+
+    /// Whether previously visited rooms are served from the local stores when
+    /// the homeserver can't be reached.
+    ///
+    /// See [`ClientBuilder::with_offline_first`].
+    pub(crate) offline_first: bool,
+
+    /// The user on whose behalf all the requests are sent, if this is the
+    /// client of a virtual user of an application service.
+    ///
+    /// See [`VirtualUser`](crate::appservice::VirtualUser).
+    #[cfg(all(feature = "appservice", not(target_family = "wasm")))]
+    pub(crate) asserted_identity: std::sync::OnceLock<ruma::OwnedUserId>,
 }
 
 impl ClientInner {
@@ -369,10 +412,14 @@ impl ClientInner {
         respect_login_well_known: bool,
         event_cache: OnceCell<EventCache>,
         send_queue: Arc<SendQueueData>,
+        policy_list_data: Arc<PolicyListData>,
+        invite_filter_data: Arc<InviteFilterData>,
         latest_events: OnceCell<LatestEvents>,
         #[cfg(feature = "e2e-encryption")] encryption_settings: EncryptionSettings,
         #[cfg(feature = "e2e-encryption")] enable_share_history_on_invite: bool,
         cross_process_store_locks_holder_name: String,
+        passphrase_protected_stores: Option<PassphraseProtectedStores>,
+        offline_first: bool,
     ) -> Arc<Self> {
         let caches = ClientCaches {
             server_info: server_info.into(),
@@ -400,6 +447,8 @@ impl ClientInner {
             sync_beat: event_listener::Event::new(),
             event_cache,
             send_queue_data: send_queue,
+            policy_list_data,
+            invite_filter_data,
             latest_events,
             #[cfg(feature = "e2e-encryption")]
             e2ee: EncryptionData::new(encryption_settings),
@@ -408,6 +457,10 @@ impl ClientInner {
             #[cfg(feature = "e2e-encryption")]
             enable_share_history_on_invite,
             server_max_upload_size: Mutex::new(OnceCell::new()),
+            passphrase_protected_stores,
+            offline_first,
+            #[cfg(all(feature = "appservice", not(target_family = "wasm")))]
+            asserted_identity: Default::default(),
         };
 
         #[allow(clippy::let_and_return)]
@@ -485,6 +538,14 @@ impl Client {
         &self.inner.cross_process_store_locks_holder_name
     }
 
+    /// Whether previously visited rooms are served from the local stores when
+    /// the homeserver can't be reached.
+    ///
+    /// See [`ClientBuilder::with_offline_first`].
+    pub fn is_offline_first(&self) -> bool {
+        self.inner.offline_first
+    }
+
     /// Change the homeserver URL used by this client.
     ///
     /// # Arguments
@@ -669,6 +730,150 @@ impl Client {
         self.base_client().event_cache_store()
     }
 
+    /// Change the passphrase used to encrypt the stores of this client.
+    ///
+    /// This is only supported for the stores configured with
+    /// `ClientBuilder::sqlite_store` and its variants, or with
+    /// `ClientBuilder::indexeddb_store`.
+    ///
+    /// Only the key encrypting the data of the stores is re-encrypted, so this
+    /// doesn't depend on the amount of data in the stores.
+    ///
+    /// The change is crash-safe: it is first prepared on all the stores, then
+    /// committed on all of them. If it is interrupted before being committed,
+    /// the stores can still be opened with the old passphrase, and with the new
+    /// one after that. In both cases, calling this method again with the same
+    /// passphrases completes the change.
+    pub async fn change_store_passphrase(
+        &self,
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<(), ChangeStorePassphraseError> {
+        let Some(stores) = &self.inner.passphrase_protected_stores else {
+            return Err(ChangeStorePassphraseError::Unsupported);
+        };
+
+        stores.change_passphrase(old_passphrase, new_passphrase).await
+    }
+
+    /// Get a report of how much space the stores of this client are using.
+    ///
+    /// The report has the size of each store and of their tables, as well as
+    /// the space used by the events of each known room and by the media,
+    /// grouped by MIME type, in the event cache store.
+    ///
+    /// Gathering the report asks each store for the size of its tables, and
+    /// the event cache store for the size of the events of each known room and
+    /// of the media. The SQLite stores compute them from the pages of their
+    /// databases without loading the events or the media, but other stores
+    /// may have to go through all of them, so this can take a while on big
+    /// stores.
+    pub async fn storage_report(&self) -> Result<StorageReport> {
+        let state_store = self.state_store().storage_usage().await?;
+
+        #[cfg(feature = "e2e-encryption")]
+        let crypto_store = match self.olm_machine().await.as_ref() {
+            Some(olm_machine) => Some(olm_machine.store().storage_usage().await?),
+            None => None,
+        };
+
+        let event_cache_store = self.event_cache_store().lock().await?;
+
+        let mut room_events = BTreeMap::new();
+
+        for room in self.rooms() {
+            let usage = event_cache_store.room_events_usage(room.room_id()).await?;
+
+            if usage.count > 0 {
+                room_events.insert(room.room_id().to_owned(), usage);
+            }
+        }
+
+        Ok(StorageReport {
+            state_store,
+            #[cfg(feature = "e2e-encryption")]
+            crypto_store,
+            room_events,
+            media: event_cache_store.media_usage().await?,
+            event_cache_store: event_cache_store.storage_usage().await?,
+        })
+    }
+
+    /// Compact the stores of this client, to give back to the system the space
+    /// that they don't use anymore.
+    ///
+    /// The stores are compacted one after the other. Compacting a store may
+    /// block the other accesses to it until it is done, so this is best run in
+    /// the background, at a time when the client isn't busy, e.g. after
+    /// [`EventCache::apply_event_retention_policy()`] or after cleaning up the
+    /// media cache.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::future::IntoFuture;
+    /// # use futures_util::StreamExt;
+    /// # use matrix_sdk::{Client, CompactionProgress};
+    /// # async {
+    /// # let client: Client = unimplemented!();
+    /// let compact_stores = client.compact_stores();
+    /// let mut progress = compact_stores.subscribe_to_progress();
+    ///
+    /// let task = matrix_sdk::executor::spawn(compact_stores.into_future());
+    ///
+    /// while let Some(progress) = progress.next().await {
+    ///     println!("Compaction progress: {progress:?}");
+    ///
+    ///     if progress == CompactionProgress::Done {
+    ///         break;
+    ///     }
+    /// }
+    ///
+    /// task.await??;
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn compact_stores(&self) -> CompactStores {
+        CompactStores { client: self.clone(), progress: Default::default() }
+    }
+
+    pub(crate) async fn compact_stores_inner(
+        &self,
+        progress: SharedObservable<CompactionProgress>,
+    ) -> Result<()> {
+        #[cfg(feature = "e2e-encryption")]
+        let crypto_store =
+            self.olm_machine().await.as_ref().map(|olm_machine| olm_machine.store().clone());
+
+        #[allow(unused_mut)]
+        let mut stores = vec![StoreKind::State];
+        #[cfg(feature = "e2e-encryption")]
+        if crypto_store.is_some() {
+            stores.push(StoreKind::Crypto);
+        }
+        stores.push(StoreKind::EventCache);
+
+        let total = stores.len();
+
+        for (compacted, store) in stores.into_iter().enumerate() {
+            progress.set(CompactionProgress::Compacting { store, compacted, total });
+
+            match store {
+                StoreKind::State => self.state_store().compact().await?,
+                #[cfg(feature = "e2e-encryption")]
+                StoreKind::Crypto => {
+                    if let Some(crypto_store) = &crypto_store {
+                        crypto_store.compact().await?;
+                    }
+                }
+                StoreKind::EventCache => self.event_cache_store().lock().await?.compact().await?,
+            }
+        }
+
+        progress.set(CompactionProgress::Done);
+
+        Ok(())
+    }
+
     /// Access the native Matrix authentication API with this client.
     pub fn matrix_auth(&self) -> MatrixAuth {
         MatrixAuth::new(self.clone())
@@ -1775,6 +1980,7 @@ impl Client {
             client: self.clone(),
             request,
             config: None,
+            assert_identity: None,
             send_progress: Default::default(),
         }
     }
@@ -1783,6 +1989,7 @@ impl Client {
         &self,
         request: Request,
         config: Option<RequestConfig>,
+        assert_identity: Option<&UserId>,
         send_progress: SharedObservable<TransmissionProgress>,
     ) -> HttpResult<Request::IncomingResponse>
     where
@@ -1792,6 +1999,10 @@ impl Client {
         let homeserver = self.homeserver().to_string();
         let access_token = self.access_token();
 
+        #[cfg(all(feature = "appservice", not(target_family = "wasm")))]
+        let assert_identity =
+            assert_identity.or(self.inner.asserted_identity.get().map(|user_id| &**user_id));
+
         self.inner
             .http_client
             .send(
@@ -1799,12 +2010,44 @@ impl Client {
                 config,
                 homeserver,
                 access_token.as_deref(),
+                assert_identity,
                 &self.server_versions().await?,
                 send_progress,
             )
             .await
     }
 
+    /// Send a request whose response body isn't buffered in memory, but
+    /// returned as a [`reqwest::Response`] so it can be streamed.
+    ///
+    /// The given `headers` are added to the request.
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) async fn send_streaming<Request>(
+        &self,
+        request: Request,
+        config: Option<RequestConfig>,
+        headers: http::HeaderMap,
+    ) -> HttpResult<reqwest::Response>
+    where
+        Request: OutgoingRequest + Debug,
+        HttpError: From<FromHttpResponseError<Request::EndpointError>>,
+    {
+        let homeserver = self.homeserver().to_string();
+        let access_token = self.access_token();
+
+        self.inner
+            .http_client
+            .send_streaming(
+                request,
+                config,
+                homeserver,
+                access_token.as_deref(),
+                &self.server_versions().await?,
+                headers,
+            )
+            .await
+    }
+
     fn broadcast_unknown_token(&self, soft_logout: &bool) {
         _ = self
             .inner
@@ -1826,6 +2069,7 @@ impl Client {
                 request_config,
                 self.homeserver().to_string(),
                 None,
+                None,
                 &[MatrixVersion::V1_0],
                 Default::default(),
             )
@@ -1853,6 +2097,7 @@ impl Client {
                 Some(RequestConfig::short_retry()),
                 server_url_string,
                 None,
+                None,
                 &[MatrixVersion::V1_0],
                 Default::default(),
             )
@@ -2188,6 +2433,55 @@ impl Client {
         self.send(request).await
     }
 
+    /// Start recording the requests to the sync endpoints and their responses
+    /// to the file at the given path, to reproduce sync processing issues.
+    ///
+    /// Both the `/sync` requests and the sliding sync requests are recorded,
+    /// until [`Client::stop_recording_syncs()`] is called. The file is
+    /// overwritten if it already exists. The syncs are written by a
+    /// dedicated thread, so recording doesn't block the sync loop.
+    ///
+    /// The recording is a [JSON Lines] file: the first line contains the
+    /// `user_id` and `device_id` of the session, and every following line
+    /// contains a `request`, with its `method`, `path`, `query` parameters
+    /// and JSON `body`, and its `response`, with its `status` and JSON `body`.
+    /// The access and refresh tokens are redacted.
+    ///
+    /// Recordings can be replayed in tests with the `SyncReplayServer` of the
+    /// `matrix-sdk-test` crate.
+    ///
+    /// **Warning**: the recordings contain all the data received by the
+    /// client, including the content of unencrypted messages. They must be
+    /// handled with the same care as the stores of the client.
+    ///
+    /// [JSON Lines]: https://jsonlines.org/
+    #[cfg(not(target_family = "wasm"))]
+    pub fn start_recording_syncs(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let session_meta = self.session_meta().ok_or(Error::AuthenticationRequired)?;
+        let recorder = crate::http_client::SyncRecorder::new(
+            path.as_ref(),
+            &session_meta.user_id,
+            &session_meta.device_id,
+        )?;
+
+        *self.inner.http_client.sync_recorder.lock().unwrap() = Some(recorder);
+
+        Ok(())
+    }
+
+    /// Stop recording the sync requests, after a call to
+    /// [`Client::start_recording_syncs()`].
+    ///
+    /// Returns once all the recorded syncs are written to the file.
+    #[cfg(not(target_family = "wasm"))]
+    pub async fn stop_recording_syncs(&self) {
+        let recorder = self.inner.http_client.sync_recorder.lock().unwrap().take();
+
+        if let Some(recorder) = recorder {
+            recorder.stop().await;
+        }
+    }
+
     /// Synchronize the client's state with the latest state on the server.
     ///
     /// ## Syncing Events
@@ -2690,12 +2984,16 @@ impl Client {
                 self.inner.respect_login_well_known,
                 self.inner.event_cache.clone(),
                 self.inner.send_queue_data.clone(),
+                self.inner.policy_list_data.clone(),
+                self.inner.invite_filter_data.clone(),
                 self.inner.latest_events.clone(),
                 #[cfg(feature = "e2e-encryption")]
                 self.inner.e2ee.encryption_settings,
                 #[cfg(feature = "e2e-encryption")]
                 self.inner.enable_share_history_on_invite,
                 cross_process_store_locks_holder_name,
+                self.inner.passphrase_protected_stores.clone(),
+                self.inner.offline_first,
             )
             .await,
         };
@@ -2758,6 +3056,19 @@ impl Client {
         Ok(Room::new(self.clone(), base_room))
     }
 
+    /// Subscribe to the knocks of the current user.
+    ///
+    /// The rooms the user has currently knocked on are emitted immediately as
+    /// [`OwnKnockState::Pending`] knocks, then an update is emitted each time
+    /// the user knocks on a room, or a knock gets an answer: the user has been
+    /// invited, has joined the room, or has been kicked or banned from it. An
+    /// answered knock isn't tracked anymore, unless the user knocks again.
+    ///
+    /// [`OwnKnockState::Pending`]: crate::room::knock_requests::OwnKnockState::Pending
+    pub fn subscribe_to_own_knocks(&self) -> impl Stream<Item = OwnKnock> {
+        own_knocks_stream(self.clone())
+    }
+
     /// Checks whether the provided `user_id` belongs to an ignored user.
     pub async fn is_user_ignored(&self, user_id: &UserId) -> bool {
         self.base_client().is_user_ignored(user_id).await
@@ -2946,14 +3257,14 @@ pub(crate) mod tests {
     };
     use url::Url;
 
-    use super::Client;
+    use super::{Client, ClientBuildError};
     use crate::{
         client::{futures::SendMediaUploadRequest, WeakClient},
         config::RequestConfig,
         futures::SendRequest,
         media::MediaError,
         test_utils::{client::MockClientBuilder, mocks::MatrixMockServer},
-        Error, TransmissionProgress,
+        ChangeStorePassphraseError, Error, TransmissionProgress,
     };
 
     #[async_test]
@@ -3787,6 +4098,7 @@ pub(crate) mod tests {
             client: client.clone(),
             request: upload_request,
             config: None,
+            assert_identity: None,
             send_progress: SharedObservable::new(TransmissionProgress::default()),
         };
         let media_request = SendMediaUploadRequest::new(request);
@@ -3796,4 +4108,41 @@ pub(crate) mod tests {
         assert_eq!(max, uint!(1));
         assert_eq!(current, UInt::new_wrapping(data.len() as u64));
     }
+
+    #[cfg(all(feature = "sqlite", not(target_family = "wasm")))]
+    #[async_test]
+    async fn test_change_store_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let build_client = |passphrase| {
+            Client::builder()
+                .homeserver_url("http://localhost:1234")
+                .server_versions([MatrixVersion::V1_0])
+                .sqlite_store(dir.path(), Some(passphrase))
+                .build()
+        };
+
+        let client = build_client("old").await.unwrap();
+        client.state_store().set_custom_value(b"key", b"value".to_vec()).await.unwrap();
+
+        client.change_store_passphrase("old", "new").await.unwrap();
+        drop(client);
+
+        assert_matches!(build_client("old").await, Err(ClientBuildError::SqliteStore(_)));
+
+        let client = build_client("new").await.unwrap();
+        assert_eq!(
+            client.state_store().get_custom_value(b"key").await.unwrap().as_deref(),
+            Some(b"value".as_slice())
+        );
+    }
+
+    #[async_test]
+    async fn test_change_store_passphrase_of_custom_stores() {
+        let client = MockClientBuilder::new(None).build().await;
+
+        assert_matches!(
+            client.change_store_passphrase("old", "new").await,
+            Err(ChangeStorePassphraseError::Unsupported)
+        );
+    }
 }
@@ -0,0 +1,104 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Types to report on, and maintain, the stores of a [`Client`].
+
+use std::collections::BTreeMap;
+
+use matrix_sdk_common::store_usage::{ItemsUsage, StoreUsage};
+use ruma::OwnedRoomId;
+
+#[cfg(doc)]
+use crate::Client;
+
+/// A kind of store used by a [`Client`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StoreKind {
+    /// The state store.
+    State,
+
+    /// The crypto store.
+    #[cfg(feature = "e2e-encryption")]
+    Crypto,
+
+    /// The event cache store, which also holds the media cache.
+    EventCache,
+}
+
+/// How much space the stores of a [`Client`] are using, as returned by
+/// [`Client::storage_report()`].
+#[derive(Clone, Debug, Default)]
+pub struct StorageReport {
+    /// How much space the state store is using.
+    pub state_store: StoreUsage,
+
+    /// How much space the crypto store is using.
+    ///
+    /// This is `None` if the crypto store isn't open yet, i.e. before the
+    /// client is logged in.
+    #[cfg(feature = "e2e-encryption")]
+    pub crypto_store: Option<StoreUsage>,
+
+    /// How much space the event cache store is using.
+    pub event_cache_store: StoreUsage,
+
+    /// The events held by the event cache store, for each room that has at
+    /// least one.
+    pub room_events: BTreeMap<OwnedRoomId, ItemsUsage>,
+
+    /// The media held by the event cache store, grouped by MIME type.
+    pub media: BTreeMap<String, ItemsUsage>,
+}
+
+impl StorageReport {
+    /// The total size of the stores, in bytes.
+    ///
+    /// Only the stores that know their size are accounted for, so this is `0`
+    /// for in-memory stores.
+    pub fn total_size(&self) -> u64 {
+        #[allow(unused_mut)]
+        let mut total_size = self.state_store.total_size.unwrap_or_default()
+            + self.event_cache_store.total_size.unwrap_or_default();
+
+        #[cfg(feature = "e2e-encryption")]
+        if let Some(crypto_store) = &self.crypto_store {
+            total_size += crypto_store.total_size.unwrap_or_default();
+        }
+
+        total_size
+    }
+}
+
+/// The progress of a [`Client::compact_stores()`] call.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompactionProgress {
+    /// The compaction hasn't started yet.
+    #[default]
+    NotStarted,
+
+    /// A store is being compacted.
+    Compacting {
+        /// The store being compacted.
+        store: StoreKind,
+
+        /// How many stores were already compacted.
+        compacted: usize,
+
+        /// How many stores will be compacted in total.
+        total: usize,
+    },
+
+    /// All the stores have been compacted.
+    Done,
+}
@@ -0,0 +1,312 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed parsing of the arguments of the commands.
+
+use std::str::FromStr;
+
+use ruma::{OwnedEventId, OwnedRoomAliasId, OwnedRoomId, OwnedRoomOrAliasId, OwnedUserId};
+use thiserror::Error;
+
+/// An error when parsing the arguments of a command.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum ArgError {
+    /// A required argument is missing.
+    #[error("missing argument <{0}>")]
+    Missing(&'static str),
+
+    /// An argument couldn't be parsed.
+    #[error("invalid argument <{name}> `{value}`: {reason}")]
+    Invalid {
+        /// The name of the argument.
+        name: &'static str,
+        /// The value of the argument.
+        value: String,
+        /// Why the value is invalid.
+        reason: String,
+    },
+
+    /// There are more arguments than the command takes.
+    #[error("unexpected argument `{0}`")]
+    Unexpected(String),
+
+    /// A quoted argument isn't terminated.
+    #[error("unterminated quote")]
+    UnterminatedQuote,
+}
+
+/// A type that can be parsed from a single argument of a command.
+///
+/// The arguments are separated by whitespace, and can be quoted with double
+/// quotes to contain whitespace.
+pub trait CommandArg: Sized {
+    /// The name of the argument, in the usage of the commands.
+    const NAME: &'static str;
+
+    /// Parse the argument.
+    ///
+    /// The error is the reason why the argument is invalid.
+    fn parse_arg(arg: &str) -> Result<Self, String>;
+}
+
+impl CommandArg for String {
+    const NAME: &'static str = "text";
+
+    fn parse_arg(arg: &str) -> Result<Self, String> {
+        Ok(arg.to_owned())
+    }
+}
+
+impl CommandArg for bool {
+    const NAME: &'static str = "yes|no";
+
+    fn parse_arg(arg: &str) -> Result<Self, String> {
+        match arg.to_lowercase().as_str() {
+            "yes" | "y" | "true" | "on" => Ok(true),
+            "no" | "n" | "false" | "off" => Ok(false),
+            _ => Err("expected yes or no".to_owned()),
+        }
+    }
+}
+
+macro_rules! impl_command_arg_from_str {
+    ($name:literal => $($ty:ty),* $(,)?) => {
+        $(
+            impl CommandArg for $ty {
+                const NAME: &'static str = $name;
+
+                fn parse_arg(arg: &str) -> Result<Self, String> {
+                    <$ty>::from_str(arg).map_err(|error| error.to_string())
+                }
+            }
+        )*
+    };
+}
+
+impl_command_arg_from_str!("number" => i8, i16, i32, i64, u8, u16, u32, u64, usize, f32, f64);
+impl_command_arg_from_str!("user" => OwnedUserId);
+impl_command_arg_from_str!("room" => OwnedRoomId, OwnedRoomOrAliasId);
+impl_command_arg_from_str!("alias" => OwnedRoomAliasId);
+impl_command_arg_from_str!("event" => OwnedEventId);
+
+/// The rest of the arguments of a command, as they were written.
+///
+/// It must be the last argument of a command.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rest(pub String);
+
+/// An argument of a command, which can be required, optional or the rest of
+/// the arguments.
+pub trait CommandArgSlot: Sized {
+    /// Take the argument from the parser.
+    fn take(parser: &mut ArgParser<'_>) -> Result<Self, ArgError>;
+
+    /// The usage of the argument, like `<user>` or `[text]`.
+    fn usage() -> String;
+}
+
+impl<T: CommandArg> CommandArgSlot for T {
+    fn take(parser: &mut ArgParser<'_>) -> Result<Self, ArgError> {
+        let arg = parser.next_arg()?.ok_or(ArgError::Missing(T::NAME))?;
+        T::parse_arg(&arg).map_err(|reason| ArgError::Invalid { name: T::NAME, value: arg, reason })
+    }
+
+    fn usage() -> String {
+        format!("<{}>", T::NAME)
+    }
+}
+
+impl<T: CommandArg> CommandArgSlot for Option<T> {
+    fn take(parser: &mut ArgParser<'_>) -> Result<Self, ArgError> {
+        parser
+            .next_arg()?
+            .map(|arg| {
+                T::parse_arg(&arg).map_err(|reason| ArgError::Invalid {
+                    name: T::NAME,
+                    value: arg,
+                    reason,
+                })
+            })
+            .transpose()
+    }
+
+    fn usage() -> String {
+        format!("[{}]", T::NAME)
+    }
+}
+
+impl CommandArgSlot for Rest {
+    fn take(parser: &mut ArgParser<'_>) -> Result<Self, ArgError> {
+        Ok(Rest(parser.take_rest().to_owned()))
+    }
+
+    fn usage() -> String {
+        "[text…]".to_owned()
+    }
+}
+
+/// The arguments of a command.
+///
+/// It's implemented for `()`, for the types implementing [`CommandArgSlot`],
+/// and for the tuples of up to 4 of them.
+pub trait CommandArgs: Sized {
+    /// Parse the arguments of a command.
+    fn parse(parser: &mut ArgParser<'_>) -> Result<Self, ArgError>;
+
+    /// The usage of the arguments, like `<user> [text]`.
+    fn usage() -> String;
+}
+
+impl CommandArgs for () {
+    fn parse(_parser: &mut ArgParser<'_>) -> Result<Self, ArgError> {
+        Ok(())
+    }
+
+    fn usage() -> String {
+        String::new()
+    }
+}
+
+impl<T: CommandArgSlot> CommandArgs for T {
+    fn parse(parser: &mut ArgParser<'_>) -> Result<Self, ArgError> {
+        T::take(parser)
+    }
+
+    fn usage() -> String {
+        T::usage()
+    }
+}
+
+macro_rules! impl_command_args_for_tuple {
+    ( $( $ty:ident ),* $(,)? ) => {
+        #[allow(non_snake_case)]
+        impl< $( $ty ),* > CommandArgs for ( $( $ty ),* , )
+        where
+            $( $ty : CommandArgSlot, )*
+        {
+            fn parse(parser: &mut ArgParser<'_>) -> Result<Self, ArgError> {
+                $(
+                    let $ty = $ty::take(parser)?;
+                )*
+
+                Ok(( $( $ty ),* , ))
+            }
+
+            fn usage() -> String {
+                [$( $ty::usage() ),*].join(" ")
+            }
+        }
+    };
+}
+
+impl_command_args_for_tuple!(A);
+impl_command_args_for_tuple!(A, B);
+impl_command_args_for_tuple!(A, B, C);
+impl_command_args_for_tuple!(A, B, C, D);
+
+/// Parse the given arguments of a command, and check that all of them were
+/// used.
+pub(super) fn parse_args<Args: CommandArgs>(args: &str) -> Result<Args, ArgError> {
+    let mut parser = ArgParser { remaining: args };
+    let args = Args::parse(&mut parser)?;
+
+    match parser.next_arg()? {
+        Some(arg) => Err(ArgError::Unexpected(arg)),
+        None => Ok(args),
+    }
+}
+
+/// A parser splitting the arguments of a command.
+#[derive(Debug)]
+pub struct ArgParser<'a> {
+    remaining: &'a str,
+}
+
+impl ArgParser<'_> {
+    /// Get the next argument, if any.
+    ///
+    /// Arguments are separated by whitespace, unless they are in double
+    /// quotes.
+    pub fn next_arg(&mut self) -> Result<Option<String>, ArgError> {
+        let remaining = self.remaining.trim_start();
+
+        if remaining.is_empty() {
+            self.remaining = remaining;
+            return Ok(None);
+        }
+
+        if let Some(quoted) = remaining.strip_prefix('"') {
+            let end = quoted.find('"').ok_or(ArgError::UnterminatedQuote)?;
+            self.remaining = &quoted[end + 1..];
+            return Ok(Some(quoted[..end].to_owned()));
+        }
+
+        let end = remaining.find(char::is_whitespace).unwrap_or(remaining.len());
+        self.remaining = &remaining[end..];
+        Ok(Some(remaining[..end].to_owned()))
+    }
+
+    /// Take the rest of the arguments, trimmed, as they were written.
+    pub fn take_rest(&mut self) -> &str {
+        let rest = self.remaining.trim();
+        self.remaining = "";
+        rest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{owned_user_id, OwnedUserId};
+
+    use super::{parse_args, ArgError, CommandArgs, Rest};
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_args::<()>("  "), Ok(()));
+        assert_eq!(parse_args::<u32>(" 42 "), Ok(42));
+        assert_eq!(
+            parse_args::<(OwnedUserId, Option<u32>)>("@alice:example.org"),
+            Ok((owned_user_id!("@alice:example.org"), None))
+        );
+        assert_eq!(
+            parse_args::<(String, bool)>(r#""hello world" yes"#),
+            Ok(("hello world".to_owned(), true))
+        );
+        assert_eq!(
+            parse_args::<(OwnedUserId, Rest)>("@alice:example.org  spamming  the room "),
+            Ok((owned_user_id!("@alice:example.org"), Rest("spamming  the room".to_owned())))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_args() {
+        assert_eq!(parse_args::<u32>(""), Err(ArgError::Missing("number")));
+        assert_eq!(parse_args::<()>("hello"), Err(ArgError::Unexpected("hello".to_owned())));
+        assert_eq!(parse_args::<String>(r#""hello"#), Err(ArgError::UnterminatedQuote));
+        assert_eq!(
+            parse_args::<OwnedUserId>("alice"),
+            Err(ArgError::Invalid {
+                name: "user",
+                value: "alice".to_owned(),
+                reason: "leading sigil is incorrect or missing".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_usage() {
+        assert_eq!(<()>::usage(), "");
+        assert_eq!(<(OwnedUserId, Option<u32>, Rest)>::usage(), "<user> [number] [text…]");
+    }
+}
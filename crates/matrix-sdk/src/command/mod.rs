@@ -0,0 +1,669 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A router for the commands of bots.
+//!
+//! A [`CommandRouter`] registers itself as an event handler for the text
+//! messages, and calls the [`Command`] they trigger, either with a prefix like
+//! `!party` or by mentioning the bot like `bot: party`.
+//!
+//! The arguments of the commands are parsed into the types of the arguments
+//! of their handler, see [`CommandArgs`]. The router also checks the power
+//! level of the sender, limits the rate of the commands of each user, and
+//! answers the `help` command with the list of the commands.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use matrix_sdk::{
+//!     command::{Command, CommandContext, CommandRouter, Rest},
+//!     ruma::OwnedUserId,
+//!     Client,
+//! };
+//!
+//! # async fn example(client: Client) {
+//! CommandRouter::new()
+//!     .command(
+//!         Command::new("party", |ctx: CommandContext, ()| async move {
+//!             ctx.reply_text("🎉🎊🥳 let's PARTY!! 🥳🎊🎉").await
+//!         })
+//!         .description("Start a party"),
+//!     )
+//!     .command(
+//!         Command::new(
+//!             "kick",
+//!             |ctx: CommandContext,
+//!              (user_id, reason): (OwnedUserId, Rest)| {
+//!                 async move {
+//!                     let reason = (!reason.0.is_empty()).then_some(reason.0);
+//!                     ctx.room().kick_user(&user_id, reason.as_deref()).await
+//!                 }
+//!             },
+//!         )
+//!         .description("Kick a user from the room")
+//!         .min_power_level(50),
+//!     )
+//!     .register(&client);
+//! # }
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use matrix_sdk_common::{BoxFuture, SendOutsideWasm, SyncOutsideWasm};
+use ruma::{
+    api::client::message::send_message_event,
+    events::room::message::{
+        AddMentions, ForwardThread, MessageType, OriginalRoomMessageEvent,
+        OriginalSyncRoomMessageEvent, Relation, ReplyWithinThread, RoomMessageEventContent,
+        RoomMessageEventContentWithoutRelation,
+    },
+    time::Instant,
+    OwnedUserId, UserId,
+};
+use tracing::{debug, instrument, warn};
+
+use crate::{
+    event_handler::{EventHandlerHandle, EventHandlerResult},
+    Client, Result, Room, RoomState,
+};
+
+mod args;
+
+pub use self::args::{ArgError, ArgParser, CommandArg, CommandArgSlot, CommandArgs, Rest};
+
+/// The default prefix of the commands.
+const DEFAULT_PREFIX: &str = "!";
+
+/// The name of the command listing the other commands.
+const HELP_COMMAND: &str = "help";
+
+type CommandHandlerResult = std::result::Result<BoxFuture<'static, ()>, ArgError>;
+
+#[cfg(not(target_family = "wasm"))]
+type CommandHandlerFn = dyn Fn(CommandContext, &str) -> CommandHandlerResult + Send + Sync;
+#[cfg(target_family = "wasm")]
+type CommandHandlerFn = dyn Fn(CommandContext, &str) -> CommandHandlerResult;
+
+/// A command that can be called by the users of a bot.
+#[derive(Clone)]
+pub struct Command {
+    name: String,
+    aliases: Vec<String>,
+    description: Option<String>,
+    usage: String,
+    min_power_level: Option<i64>,
+    handler: Arc<CommandHandlerFn>,
+}
+
+impl Command {
+    /// Create a new command with the given name and handler.
+    ///
+    /// The handler receives a [`CommandContext`] and the arguments of the
+    /// command, parsed into any type implementing [`CommandArgs`]. Like event
+    /// handlers, it can return `()` or a `Result<(), E>`, whose errors are
+    /// logged.
+    pub fn new<Args, F, Fut>(name: impl Into<String>, handler: F) -> Self
+    where
+        Args: CommandArgs,
+        F: Fn(CommandContext, Args) -> Fut + SendOutsideWasm + SyncOutsideWasm + 'static,
+        Fut: Future + SendOutsideWasm + 'static,
+        Fut::Output: EventHandlerResult,
+    {
+        let name = name.into().to_lowercase();
+
+        let handler = {
+            let name = name.clone();
+            move |ctx: CommandContext, args: &str| -> CommandHandlerResult {
+                let args = args::parse_args::<Args>(args)?;
+                let future = handler(ctx, args);
+                let name = name.clone();
+
+                Ok(Box::pin(async move {
+                    future.await.print_error(Some(&format!("command `{name}`")));
+                }))
+            }
+        };
+
+        Self {
+            name,
+            aliases: Vec::new(),
+            description: None,
+            usage: Args::usage(),
+            min_power_level: None,
+            handler: Arc::new(handler),
+        }
+    }
+
+    /// Add an alias to this command.
+    pub fn alias(mut self, alias: impl Into<String>) -> Self {
+        self.aliases.push(alias.into().to_lowercase());
+        self
+    }
+
+    /// Set the description of this command, shown in the help.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Only allow the users with at least the given power level in the room
+    /// to call this command.
+    pub fn min_power_level(mut self, power_level: i64) -> Self {
+        self.min_power_level = Some(power_level);
+        self
+    }
+
+    /// The name of this command.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The usage of the arguments of this command, like `<user> [text…]`.
+    pub fn usage(&self) -> &str {
+        &self.usage
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.name == name || self.aliases.iter().any(|alias| alias == name)
+    }
+}
+
+impl fmt::Debug for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Command")
+            .field("name", &self.name)
+            .field("aliases", &self.aliases)
+            .field("usage", &self.usage)
+            .field("min_power_level", &self.min_power_level)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A limit of the number of commands a user can call in a period of time.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    /// The maximum number of commands in the period.
+    pub max_commands: usize,
+    /// The duration of the period.
+    pub period: Duration,
+}
+
+/// A router for the commands of a bot.
+///
+/// See the [module documentation](self) for more details.
+#[derive(Debug)]
+pub struct CommandRouter {
+    prefix: String,
+    mentions: bool,
+    threaded_replies: bool,
+    reply_to_unknown_commands: bool,
+    rate_limit: Option<RateLimit>,
+    commands: Vec<Command>,
+}
+
+impl Default for CommandRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommandRouter {
+    /// Create a new router, with the `!` prefix, and which also responds to
+    /// the mentions of the bot.
+    pub fn new() -> Self {
+        Self {
+            prefix: DEFAULT_PREFIX.to_owned(),
+            mentions: true,
+            threaded_replies: false,
+            reply_to_unknown_commands: false,
+            rate_limit: None,
+            commands: Vec::new(),
+        }
+    }
+
+    /// Set the prefix of the commands.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Whether the commands can be called by mentioning the bot at the start
+    /// of a message, like `bot: party`.
+    ///
+    /// Defaults to `true`.
+    pub fn respond_to_mentions(mut self, enabled: bool) -> Self {
+        self.mentions = enabled;
+        self
+    }
+
+    /// Whether the replies to the commands start a thread from the command
+    /// message.
+    ///
+    /// When disabled, the replies are only in a thread if the command was.
+    /// Defaults to `false`.
+    pub fn threaded_replies(mut self, enabled: bool) -> Self {
+        self.threaded_replies = enabled;
+        self
+    }
+
+    /// Whether the router replies to the unknown commands, pointing to the
+    /// `help` command.
+    ///
+    /// When disabled, the messages starting with the prefix that aren't
+    /// commands are ignored, since they might be meant for another bot.
+    /// Defaults to `false`.
+    pub fn reply_to_unknown_commands(mut self, enabled: bool) -> Self {
+        self.reply_to_unknown_commands = enabled;
+        self
+    }
+
+    /// Limit the number of commands each user can call in a period of time.
+    ///
+    /// The commands exceeding the limit are ignored.
+    pub fn rate_limit(mut self, max_commands: usize, period: Duration) -> Self {
+        self.rate_limit = Some(RateLimit { max_commands, period });
+        self
+    }
+
+    /// Add a command.
+    ///
+    /// A command named `help` replaces the one listing the commands.
+    pub fn command(mut self, command: Command) -> Self {
+        self.commands.push(command);
+        self
+    }
+
+    /// Generate the help text listing the commands.
+    pub fn help_text(&self) -> String {
+        let mut lines = vec!["Available commands:".to_owned()];
+
+        for command in &self.commands {
+            let mut line = format!("{}{}", self.prefix, command.name);
+
+            if !command.usage.is_empty() {
+                line.push(' ');
+                line.push_str(&command.usage);
+            }
+
+            if let Some(description) = &command.description {
+                line.push_str(" — ");
+                line.push_str(description);
+            }
+
+            if let Some(power_level) = command.min_power_level {
+                line.push_str(&format!(" (power level {power_level})"));
+            }
+
+            if !command.aliases.is_empty() {
+                line.push_str(&format!(" (aliases: {})", command.aliases.join(", ")));
+            }
+
+            lines.push(line);
+        }
+
+        lines.push(format!("{}{HELP_COMMAND} — Show this help", self.prefix));
+        lines.join("\n")
+    }
+
+    /// Register this router as an event handler of the given client.
+    ///
+    /// Returns the handle of the event handler, to remove it with
+    /// [`Client::remove_event_handler()`].
+    pub fn register(self, client: &Client) -> EventHandlerHandle {
+        let router = Arc::new(RegisteredRouter { router: self, rate_limiter: Default::default() });
+
+        client.add_event_handler(move |event: OriginalSyncRoomMessageEvent, room: Room| {
+            let router = router.clone();
+            async move { router.handle_message(event, room).await }
+        })
+    }
+}
+
+/// A [`CommandRouter`] registered as an event handler.
+struct RegisteredRouter {
+    router: CommandRouter,
+    rate_limiter: Mutex<HashMap<OwnedUserId, VecDeque<Instant>>>,
+}
+
+impl RegisteredRouter {
+    #[instrument(skip_all, fields(room_id = %room.room_id(), event_id = %event.event_id))]
+    async fn handle_message(&self, event: OriginalSyncRoomMessageEvent, room: Room) {
+        let client = room.client();
+
+        if room.state() != RoomState::Joined || client.user_id() == Some(&event.sender) {
+            return;
+        }
+
+        // The edits of the commands aren't commands.
+        if matches!(event.content.relates_to, Some(Relation::Replacement(_))) {
+            return;
+        }
+
+        let MessageType::Text(text) = &event.content.msgtype else {
+            return;
+        };
+
+        let Some(command_line) = self.strip_trigger(&text.body, &event, &room).await else {
+            return;
+        };
+
+        let command_line = command_line.trim_start();
+        let (name, args) =
+            command_line.split_once(char::is_whitespace).unwrap_or((command_line, ""));
+        let name = name.to_lowercase();
+
+        if name.is_empty() {
+            return;
+        }
+
+        let command = self.router.commands.iter().find(|command| command.matches(&name));
+
+        if command.is_none() && name != HELP_COMMAND && !self.router.reply_to_unknown_commands {
+            debug!(%name, "Ignoring an unknown command");
+            return;
+        }
+
+        if !self.check_rate_limit(&event.sender) {
+            debug!(sender = %event.sender, "Ignoring a command exceeding the rate limit");
+            return;
+        }
+
+        let prefix = &self.router.prefix;
+        let ctx = CommandContext {
+            room: room.clone(),
+            event: event.clone(),
+            threaded_replies: self.router.threaded_replies,
+        };
+
+        let Some(command) = command else {
+            let reply = if name == HELP_COMMAND {
+                self.router.help_text()
+            } else {
+                format!(
+                    "Unknown command `{name}`. Send `{prefix}{HELP_COMMAND}` to list the commands."
+                )
+            };
+
+            if let Err(error) = ctx.reply_notice(reply).await {
+                warn!(?error, "Couldn't reply to a command");
+            }
+            return;
+        };
+
+        if let Some(min_power_level) = command.min_power_level {
+            let power_level: i64 =
+                room.power_levels_or_default().await.for_user(&event.sender).into();
+
+            if power_level < min_power_level {
+                let reply =
+                    format!("You need power level {min_power_level} to use `{prefix}{name}`.");
+                if let Err(error) = ctx.reply_notice(reply).await {
+                    warn!(?error, "Couldn't reply to a command");
+                }
+                return;
+            }
+        }
+
+        match (command.handler)(ctx.clone(), args) {
+            Ok(future) => future.await,
+            Err(error) => {
+                let usage = format!("{prefix}{} {}", command.name, command.usage);
+                let reply = format!("Error: {error}.\nUsage: {}", usage.trim_end());
+                if let Err(error) = ctx.reply_notice(reply).await {
+                    warn!(?error, "Couldn't reply to a command");
+                }
+            }
+        }
+    }
+
+    /// Strip the prefix or the mention of the bot at the start of a message.
+    ///
+    /// Returns `None` if the message isn't a command.
+    async fn strip_trigger<'a>(
+        &self,
+        body: &'a str,
+        event: &OriginalSyncRoomMessageEvent,
+        room: &Room,
+    ) -> Option<&'a str> {
+        if let Some(command_line) = body.strip_prefix(&self.router.prefix) {
+            return Some(command_line);
+        }
+
+        if !self.router.mentions {
+            return None;
+        }
+
+        let own_user_id = room.own_user_id();
+        let mut mentions = vec![own_user_id.to_string()];
+
+        // The clients usually write the display name of the mentioned users in the
+        // plain text body.
+        let is_mentioned = event
+            .content
+            .mentions
+            .as_ref()
+            .is_some_and(|mentions| mentions.user_ids.contains(own_user_id));
+
+        if is_mentioned {
+            if let Ok(Some(member)) = room.get_member_no_sync(own_user_id).await {
+                if let Some(display_name) = member.display_name() {
+                    mentions.push(display_name.to_owned());
+                }
+            }
+            mentions.push(own_user_id.localpart().to_owned());
+        }
+
+        mentions.iter().find_map(|mention| strip_mention(body, mention))
+    }
+
+    /// Record a command of the given user, and check it doesn't exceed the
+    /// rate limit.
+    fn check_rate_limit(&self, user_id: &UserId) -> bool {
+        let Some(RateLimit { max_commands, period }) = self.router.rate_limit else {
+            return true;
+        };
+
+        let now = Instant::now();
+        let mut rate_limiter = self.rate_limiter.lock().unwrap();
+
+        // Forget the users whose commands are all out of the period.
+        rate_limiter.retain(|_, commands| {
+            commands.back().is_some_and(|time| now.duration_since(*time) < period)
+        });
+
+        let commands = rate_limiter.entry(user_id.to_owned()).or_default();
+
+        while commands.front().is_some_and(|time| now.duration_since(*time) >= period) {
+            commands.pop_front();
+        }
+
+        if commands.len() >= max_commands {
+            return false;
+        }
+
+        commands.push_back(now);
+        true
+    }
+}
+
+/// Strip the given mention, followed by a `:` or a `,`, at the start of the
+/// body of a message.
+fn strip_mention<'a>(body: &'a str, mention: &str) -> Option<&'a str> {
+    let start = body.get(..mention.len())?;
+
+    if !start.eq_ignore_ascii_case(mention) {
+        return None;
+    }
+
+    let rest = &body[mention.len()..];
+    let rest = rest.strip_prefix([':', ',']).unwrap_or(rest);
+
+    // The mention must be a whole word.
+    rest.starts_with(char::is_whitespace).then_some(rest)
+}
+
+/// The context of a call to a [`Command`].
+#[derive(Clone, Debug)]
+pub struct CommandContext {
+    room: Room,
+    event: OriginalSyncRoomMessageEvent,
+    threaded_replies: bool,
+}
+
+impl CommandContext {
+    /// The client receiving the command.
+    pub fn client(&self) -> Client {
+        self.room.client()
+    }
+
+    /// The room where the command was sent.
+    pub fn room(&self) -> &Room {
+        &self.room
+    }
+
+    /// The message of the command.
+    pub fn event(&self) -> &OriginalSyncRoomMessageEvent {
+        &self.event
+    }
+
+    /// The user who sent the command.
+    pub fn sender(&self) -> &UserId {
+        &self.event.sender
+    }
+
+    /// Get a value registered with [`Client::add_event_handler_context()`].
+    pub fn context<T>(&self) -> Option<T>
+    where
+        T: Clone + SendOutsideWasm + SyncOutsideWasm + 'static,
+    {
+        self.room.client().inner.event_handlers.context::<T>()
+    }
+
+    /// Reply to the command with the given content.
+    ///
+    /// The reply is in a thread if the command was, or if
+    /// [`CommandRouter::threaded_replies()`] is enabled.
+    pub async fn reply(
+        &self,
+        content: impl Into<RoomMessageEventContentWithoutRelation>,
+    ) -> Result<send_message_event::v3::Response> {
+        let event = OriginalRoomMessageEvent {
+            event_id: self.event.event_id.clone(),
+            sender: self.event.sender.clone(),
+            origin_server_ts: self.event.origin_server_ts,
+            room_id: self.room.room_id().to_owned(),
+            content: self.event.content.clone(),
+            unsigned: Default::default(),
+        };
+
+        let content: RoomMessageEventContentWithoutRelation = content.into();
+        let content = if self.threaded_replies {
+            content.make_for_thread(&event, ReplyWithinThread::No, AddMentions::Yes)
+        } else {
+            content.make_reply_to(&event, ForwardThread::Yes, AddMentions::Yes)
+        };
+
+        self.room.send(content).await
+    }
+
+    /// Reply to the command with the given plain text message.
+    pub async fn reply_text(&self, body: impl Into<String>) -> Result<()> {
+        self.reply(RoomMessageEventContent::text_plain(body)).await?;
+        Ok(())
+    }
+
+    /// Reply to the command with a notice, for the replies of the router
+    /// itself.
+    async fn reply_notice(&self, body: String) -> Result<()> {
+        self.reply(RoomMessageEventContent::notice_plain(body)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ruma::{user_id, OwnedUserId};
+
+    use super::{strip_mention, Command, CommandContext, CommandRouter, RegisteredRouter, Rest};
+
+    #[test]
+    fn test_strip_mention() {
+        assert_eq!(strip_mention("@bot:example.org: party", "@bot:example.org"), Some(" party"));
+        assert_eq!(strip_mention("Bot, party", "bot"), Some(" party"));
+        assert_eq!(strip_mention("bot party", "bot"), Some(" party"));
+        assert_eq!(strip_mention("botany is cool", "bot"), None);
+        assert_eq!(strip_mention("bo", "bot"), None);
+        assert_eq!(strip_mention("hey bot: party", "bot"), None);
+    }
+
+    #[test]
+    fn test_help_text() {
+        let router = CommandRouter::new()
+            .prefix("?")
+            .command(
+                Command::new("echo", |_ctx: CommandContext, _text: Rest| async {})
+                    .description("Repeat the text")
+                    .alias("say"),
+            )
+            .command(
+                Command::new("kick", |_ctx: CommandContext, _user_id: OwnedUserId| async {})
+                    .min_power_level(50),
+            );
+
+        assert_eq!(
+            router.help_text(),
+            "Available commands:\n\
+             ?echo [text…] — Repeat the text (aliases: say)\n\
+             ?kick <user> (power level 50)\n\
+             ?help — Show this help"
+        );
+    }
+
+    #[test]
+    fn test_rate_limiter_forgets_expired_users() {
+        let alice = user_id!("@alice:example.org");
+        let bob = user_id!("@bob:example.org");
+
+        let router = RegisteredRouter {
+            router: CommandRouter::new().rate_limit(1, Duration::from_secs(3600)),
+            rate_limiter: Default::default(),
+        };
+        assert!(router.check_rate_limit(alice));
+        assert!(router.check_rate_limit(bob));
+        assert!(!router.check_rate_limit(alice));
+        assert_eq!(router.rate_limiter.lock().unwrap().len(), 2);
+
+        // With an empty period, the commands are out of the period right away, so the
+        // users are forgotten.
+        let router = RegisteredRouter {
+            router: CommandRouter::new().rate_limit(1, Duration::ZERO),
+            rate_limiter: Default::default(),
+        };
+        assert!(router.check_rate_limit(alice));
+        assert!(router.check_rate_limit(bob));
+        assert!(router.check_rate_limit(alice));
+
+        let rate_limiter = router.rate_limiter.lock().unwrap();
+        assert_eq!(rate_limiter.len(), 1);
+        assert!(rate_limiter.contains_key(alice));
+    }
+}
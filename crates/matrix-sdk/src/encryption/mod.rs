@@ -33,7 +33,10 @@ use futures_util::{
     stream::{self, StreamExt},
 };
 use matrix_sdk_base::crypto::{
-    store::types::{RoomKeyBundleInfo, RoomKeyInfo},
+    store::{
+        types::{RoomKeyBundleInfo, RoomKeyInfo},
+        LockableCryptoStore,
+    },
     types::requests::{
         OutgoingRequest, OutgoingVerificationRequest, RoomMessageRequest, ToDeviceRequest,
     },
@@ -78,11 +81,13 @@ use self::{
     tasks::{BackupDownloadTask, BackupUploadingTask, ClientTasks},
     verification::{SasVerification, Verification, VerificationRequest},
 };
+#[cfg(not(target_family = "wasm"))]
+use crate::store_locks::FileLockStore;
 use crate::{
     attachment::Thumbnail,
     client::{ClientInner, WeakClient},
     error::HttpResult,
-    store_locks::CrossProcessStoreLockGuard,
+    store_locks::{CrossProcessStoreLock, CrossProcessStoreLockGuard},
     Client, Error, HttpError, Result, Room, TransmissionProgress,
 };
 
@@ -1543,6 +1548,43 @@ impl Encryption {
     /// Check [`Client::cross_process_store_locks_holder_name`] to
     /// get the global value.
     pub async fn enable_cross_process_store_lock(&self, lock_value: String) -> Result<(), Error> {
+        self.set_cross_process_store_lock(lock_value, |olm_machine, lock_key, lock_value| {
+            olm_machine.store().create_store_lock(lock_key, lock_value)
+        })
+        .await
+    }
+
+    /// Enables the crypto-store cross-process lock, keeping the leases of the
+    /// lock in the files of the given [`FileLockStore`] rather than in the
+    /// crypto store.
+    ///
+    /// This may be used when the processes share a data directory, but the
+    /// crypto store can't be used to coordinate them. See
+    /// [`Self::enable_cross_process_store_lock`] for more details; all the
+    /// processes must use a `FileLockStore` with the same directory.
+    #[cfg(not(target_family = "wasm"))]
+    pub async fn enable_cross_process_file_lock(
+        &self,
+        lock_value: String,
+        lock_store: FileLockStore,
+    ) -> Result<(), Error> {
+        self.set_cross_process_store_lock(lock_value, |_, lock_key, lock_value| {
+            CrossProcessStoreLock::new(lock_store.into(), lock_key, lock_value)
+        })
+        .await
+    }
+
+    /// Create the crypto-store cross-process lock with the given function, and
+    /// initialize it.
+    async fn set_cross_process_store_lock(
+        &self,
+        lock_value: String,
+        create_lock: impl FnOnce(
+            &OlmMachine,
+            String,
+            String,
+        ) -> CrossProcessStoreLock<LockableCryptoStore>,
+    ) -> Result<(), Error> {
         // If the lock has already been created, don't recreate it from scratch.
         if let Some(prev_lock) = self.client.locks().cross_process_crypto_store_lock.get() {
             let prev_holder = prev_lock.lock_holder();
@@ -1558,8 +1600,7 @@ impl Encryption {
         let olm_machine = self.client.base_client().olm_machine().await;
         let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
 
-        let lock =
-            olm_machine.store().create_store_lock("cross_process_lock".to_owned(), lock_value);
+        let lock = create_lock(olm_machine, "cross_process_lock".to_owned(), lock_value);
 
         // Gently try to initialize the crypto store generation counter.
         //
@@ -1845,7 +1886,12 @@ impl Encryption {
 
             let send_result = self
                 .client
-                .send_inner(ruma_request, Some(RequestConfig::short_retry()), Default::default())
+                .send_inner(
+                    ruma_request,
+                    Some(RequestConfig::short_retry()),
+                    None,
+                    Default::default(),
+                )
                 .await;
 
             // If the sending failed we need to collect the failures to report them
@@ -1900,6 +1946,7 @@ mod tests {
         assert_next_matches_with_timeout,
         config::RequestConfig,
         encryption::{OAuthCrossSigningResetInfo, VerificationState},
+        store_locks::FileLockStore,
         test_utils::{
             client::mock_matrix_session, logged_in_client, no_retry_test_client, set_client_session,
         },
@@ -2026,6 +2073,74 @@ mod tests {
         assert!(found_room.get_member_no_sync(user_id).await.unwrap().is_some());
     }
 
+    #[cfg(feature = "sqlite")]
+    #[async_test]
+    async fn test_cross_process_file_lock() {
+        use matrix_sdk_base::store::RoomLoadSettings;
+
+        let directory = tempfile::tempdir().unwrap();
+        let sqlite_path = directory.path().join("store");
+        let lock_directory = directory.path().join("locks");
+        let session = mock_matrix_session();
+
+        // Two clients using the same database, coordinated through lock files.
+        let client1 = Client::builder()
+            .homeserver_url("http://localhost:1234")
+            .request_config(RequestConfig::new().disable_retry())
+            .sqlite_store(&sqlite_path, None)
+            .build()
+            .await
+            .unwrap();
+        client1
+            .matrix_auth()
+            .restore_session(session.clone(), RoomLoadSettings::default())
+            .await
+            .unwrap();
+
+        let client2 = Client::builder()
+            .homeserver_url("http://localhost:1234")
+            .request_config(RequestConfig::new().disable_retry())
+            .sqlite_store(&sqlite_path, None)
+            .build()
+            .await
+            .unwrap();
+        client2.matrix_auth().restore_session(session, RoomLoadSettings::default()).await.unwrap();
+
+        client1
+            .encryption()
+            .enable_cross_process_file_lock(
+                "client1".to_owned(),
+                FileLockStore::new(&lock_directory),
+            )
+            .await
+            .unwrap();
+        client2
+            .encryption()
+            .enable_cross_process_file_lock(
+                "client2".to_owned(),
+                FileLockStore::new(&lock_directory),
+            )
+            .await
+            .unwrap();
+
+        // One client can take the lock.
+        let acquired1 = client1.encryption().try_lock_store_once().await.unwrap();
+        assert!(acquired1.is_some());
+
+        // The other client can't take the lock too, since they share the lock
+        // directory.
+        let acquired2 = client2.encryption().try_lock_store_once().await.unwrap();
+        assert!(acquired2.is_none());
+
+        // Now have the first client release the lock,
+        drop(acquired1);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // And the other client can take it.
+        let acquired2 = client2.encryption().try_lock_store_once().await.unwrap();
+        assert!(acquired2.is_some());
+    }
+
     #[cfg(feature = "sqlite")]
     #[async_test]
     async fn test_generation_counter_invalidates_olm_machine() {
@@ -46,8 +46,12 @@ use thiserror::Error;
 use url::ParseError as UrlParseError;
 
 use crate::{
-    authentication::oauth::OAuthError, event_cache::EventCacheError, media::MediaError,
-    room::reply::ReplyError, sliding_sync::Error as SlidingSyncError, store_locks::LockStoreError,
+    authentication::oauth::OAuthError,
+    event_cache::EventCacheError,
+    media::MediaError,
+    room::{reply::ReplyError, server_acl::ServerAclError},
+    sliding_sync::Error as SlidingSyncError,
+    store_locks::LockStoreError,
 };
 
 /// Result type of the matrix-sdk.
@@ -161,6 +165,12 @@ impl HttpError {
         self.as_ruma_api_error().and_then(as_variant!(RumaApiError::Uiaa))
     }
 
+    /// Whether the request failed at the network layer, e.g. because the
+    /// device is offline or the homeserver can't be reached.
+    pub fn is_network_error(&self) -> bool {
+        matches!(self.retry_kind(), RetryKind::NetworkFailure)
+    }
+
     /// Returns whether an HTTP error response should be qualified as transient
     /// or permanent.
     pub(crate) fn retry_kind(&self) -> RetryKind {
@@ -415,6 +425,10 @@ pub enum Error {
     /// An error happened while attempting to reply to an event.
     #[error(transparent)]
     ReplyError(#[from] ReplyError),
+
+    /// An error happened while attempting to update the server ACL of a room.
+    #[error(transparent)]
+    ServerAcl(#[from] ServerAclError),
 }
 
 #[rustfmt::skip] // stop rustfmt breaking the `<code>` in docs across multiple lines
@@ -456,6 +470,12 @@ impl Error {
     pub fn as_uiaa_response(&self) -> Option<&UiaaInfo> {
         self.as_ruma_api_error().and_then(as_variant!(RumaApiError::Uiaa))
     }
+
+    /// Whether `self` is an [`HttpError`] that happened at the network layer,
+    /// see [`HttpError::is_network_error`].
+    pub fn is_network_error(&self) -> bool {
+        as_variant!(self, Self::Http).is_some_and(|e| e.is_network_error())
+    }
 }
 
 impl From<HttpError> for Error {
@@ -718,6 +738,28 @@ impl From<RemovePushRuleError> for NotificationSettingsError {
     }
 }
 
+/// Errors that can occur when changing the passphrase of the stores of the
+/// [`Client`](crate::Client).
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ChangeStorePassphraseError {
+    /// The stores weren't opened by the [`ClientBuilder`](crate::ClientBuilder)
+    /// from an SQLite or IndexedDB configuration, so the client can't change
+    /// their passphrase.
+    #[error("The passphrase of the stores of this client can't be changed")]
+    Unsupported,
+
+    /// Failed to change the passphrase of the SQLite stores.
+    #[cfg(feature = "sqlite")]
+    #[error(transparent)]
+    Sqlite(#[from] matrix_sdk_sqlite::ChangePassphraseError),
+
+    /// Failed to change the passphrase of the IndexedDB stores.
+    #[cfg(feature = "indexeddb")]
+    #[error(transparent)]
+    IndexedDb(#[from] matrix_sdk_indexeddb::IndexeddbStateStoreError),
+}
+
 #[derive(Debug, Error)]
 #[error("expected: {expected}, got: {got:?}")]
 pub struct WrongRoomState {
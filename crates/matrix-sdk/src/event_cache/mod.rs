@@ -38,7 +38,7 @@ use eyeball_im::VectorDiff;
 use futures_util::future::{join_all, try_join_all};
 use matrix_sdk_base::{
     deserialized_responses::{AmbiguityChange, TimelineEvent},
-    event_cache::store::{EventCacheStoreError, EventCacheStoreLock},
+    event_cache::store::{EventCacheStoreError, EventCacheStoreLock, EventRetentionPolicy},
     linked_chunk::lazy_loader::LazyLoaderError,
     store_locks::LockStoreError,
     sync::RoomUpdates,
@@ -60,7 +60,9 @@ mod pagination;
 mod room;
 
 pub use pagination::{RoomPagination, RoomPaginationStatus};
-pub use room::{RoomEventCache, RoomEventCacheSubscriber, ThreadEventCacheUpdate};
+pub use room::{
+    DetachedContext, EventContext, RoomEventCache, RoomEventCacheSubscriber, ThreadEventCacheUpdate,
+};
 
 /// An error observed in the [`EventCache`].
 #[derive(thiserror::Error, Debug)]
@@ -373,6 +375,31 @@ impl EventCache {
         self.inner.clear_all_rooms().await
     }
 
+    /// Set the [`EventRetentionPolicy`] to use for deciding which events to
+    /// keep in the event cache store.
+    ///
+    /// The policy is applied when calling
+    /// [`EventCache::apply_event_retention_policy()`].
+    pub async fn set_event_retention_policy(&self, policy: EventRetentionPolicy) -> Result<()> {
+        self.inner.store.lock().await?.set_event_retention_policy(policy).await?;
+        Ok(())
+    }
+
+    /// Get the current [`EventRetentionPolicy`].
+    pub async fn event_retention_policy(&self) -> Result<EventRetentionPolicy> {
+        Ok(self.inner.store.lock().await?.event_retention_policy())
+    }
+
+    /// Cleanly evict the oldest events of all the rooms' event caches,
+    /// according to the current [`EventRetentionPolicy`].
+    ///
+    /// The evicted events are replaced by a gap, so they will be fetched again
+    /// from the homeserver when back-paginating. This will notify any live
+    /// observers that the room has been reloaded from its last chunk.
+    pub async fn apply_event_retention_policy(&self) -> Result<()> {
+        self.inner.apply_event_retention_policy().await
+    }
+
     /// Subscribe to room _generic_ updates.
     ///
     /// If one wants to listen what has changed in a specific room, the
@@ -509,6 +536,47 @@ impl EventCacheInner {
         Ok(())
     }
 
+    /// Evicts the oldest events of all the rooms, according to the event
+    /// retention policy.
+    async fn apply_event_retention_policy(&self) -> Result<()> {
+        // Like in `clear_all_rooms()`, nobody must touch the linked chunks while the
+        // storage is being cleaned up, so take the state locks of all the live rooms
+        // first.
+        let rooms = self.by_room.write().await;
+
+        let room_locks = join_all(
+            rooms.values().map(|room| async move { (room, room.inner.state.write().await) }),
+        )
+        .await;
+
+        self.store.lock().await?.clean_up_linked_chunks().await?;
+
+        // The in-memory linked chunks may now contain chunks that have been evicted
+        // from the storage. Resynchronize them by shrinking them to their last chunk,
+        // which is never evicted, and propagate updates to observers.
+        try_join_all(room_locks.into_iter().map(|(room, mut state_guard)| async move {
+            let updates_as_vector_diffs = state_guard.shrink_to_last_chunk_with_diffs().await?;
+
+            if !updates_as_vector_diffs.is_empty() {
+                let _ = room.inner.sender.send(RoomEventCacheUpdate::UpdateTimelineEvents {
+                    diffs: updates_as_vector_diffs,
+                    origin: EventsOrigin::Cache,
+                });
+
+                let _ = room.inner.generic_update_sender.send(
+                    RoomEventCacheGenericUpdate::UpdateTimeline {
+                        room_id: room.inner.room_id.clone(),
+                    },
+                );
+            }
+
+            Ok::<_, EventCacheError>(())
+        }))
+        .await?;
+
+        Ok(())
+    }
+
     /// Handles a single set of room updates at once.
     #[instrument(skip(self, updates))]
     async fn handle_room_updates(&self, updates: RoomUpdates) -> Result<()> {
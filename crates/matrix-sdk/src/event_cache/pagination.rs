@@ -17,8 +17,8 @@
 use std::{sync::Arc, time::Duration};
 
 use eyeball::{SharedObservable, Subscriber};
-use matrix_sdk_base::timeout::timeout;
-use ruma::api::Direction;
+use matrix_sdk_base::{event_cache::Gap, timeout::timeout};
+use ruma::{api::Direction, uint};
 use tracing::{debug, instrument, trace};
 
 use super::{
@@ -218,10 +218,10 @@ impl RoomPagination {
                     continue;
                 }
 
-                LoadMoreEventsBackwardsOutcome::Gap { prev_token } => {
+                LoadMoreEventsBackwardsOutcome::Gap { gap } => {
                     // We have a gap, so resolve it with a network back-pagination.
                     drop(state_guard);
-                    return self.paginate_backwards_with_network(batch_size, prev_token).await;
+                    return self.paginate_backwards_with_network(batch_size, gap).await;
                 }
 
                 LoadMoreEventsBackwardsOutcome::StartOfTimeline => {
@@ -261,9 +261,9 @@ impl RoomPagination {
     async fn paginate_backwards_with_network(
         &self,
         batch_size: u16,
-        prev_token: Option<String>,
+        prev_gap: Option<Gap>,
     ) -> Result<Option<BackPaginationOutcome>> {
-        let (events, new_token) = {
+        let (events, new_token) = 'request: {
             let Some(room) = self.inner.weak_room.get() else {
                 // The client is shutting down, return an empty default response.
                 return Ok(Some(BackPaginationOutcome {
@@ -272,7 +272,33 @@ impl RoomPagination {
                 }));
             };
 
-            let mut options = MessagesOptions::new(Direction::Backward).from(prev_token.as_deref());
+            // A gap replacing events evicted from the store doesn't hold a real token, so
+            // resolve one from the event that was right after the evicted events.
+            let from = match &prev_gap {
+                Some(Gap { evicted_before: Some(event_id), .. }) => {
+                    trace!(%event_id, "resolving the token of a gap of evicted events");
+
+                    let prev_batch_token = room
+                        .event_with_context(event_id, false, uint!(0), None)
+                        .await
+                        .map_err(|err| EventCacheError::BackpaginationError(Box::new(err)))?
+                        .prev_batch_token;
+
+                    if prev_batch_token.is_none() {
+                        // There's nothing before the evicted events. Paginating without a token
+                        // would start from the end of the room, so remove the gap instead, as if
+                        // the start of the room was reached.
+                        trace!("no token before the evicted events, reached the start of the room");
+                        break 'request (Vec::new(), None);
+                    }
+
+                    prev_batch_token
+                }
+                Some(gap) => gap.prev_token.clone(),
+                None => None,
+            };
+
+            let mut options = MessagesOptions::new(Direction::Backward).from(from.as_deref());
             options.limit = batch_size.into();
 
             let response = room
@@ -283,14 +309,18 @@ impl RoomPagination {
             (response.chunk, response.end)
         };
 
-        if let Some((outcome, timeline_event_diffs)) = self
+        let outcome = self
             .inner
             .state
             .write()
             .await
-            .handle_backpagination(events, new_token, prev_token)
-            .await?
-        {
+            .handle_backpagination(events, new_token, prev_gap)
+            .await?;
+
+        if let Some((outcome, timeline_event_diffs, aggregations_update)) = outcome {
+            // Index the relations of the new events once the state lock is released.
+            aggregations_update.apply().await?;
+
             if !timeline_event_diffs.is_empty() {
                 let _ = self.inner.sender.send(RoomEventCacheUpdate::UpdateTimelineEvents {
                     diffs: timeline_event_diffs,
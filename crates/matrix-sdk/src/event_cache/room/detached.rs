@@ -0,0 +1,260 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Events of a room that aren't connected (yet) to the room's linked chunk.
+//!
+//! When jumping to a date, or to an event, far in the past, the context around
+//! the target isn't contiguous with the room's linked chunk. It's then kept in
+//! a separate linked chunk, identified by [`LinkedChunkId::Detached`], so it
+//! can be reused without hitting the network, until a back-pagination of the
+//! room reaches it and both get joined.
+//!
+//! The detached linked chunk holds at most one context, with the following
+//! layout: an optional gap holding the token to paginate backwards from the
+//! first event, an items chunk with the events, and an optional gap holding
+//! the token to paginate forwards from the last event.
+//!
+//! [`LinkedChunkId::Detached`]: matrix_sdk_base::linked_chunk::LinkedChunkId::Detached
+
+use std::collections::{BTreeSet, HashMap};
+
+use matrix_sdk_base::{
+    event_cache::{Event, Gap},
+    linked_chunk::{ChunkContent, ChunkIdentifier, Position, RawChunk, Update},
+};
+use ruma::{MilliSecondsSinceUnixEpoch, OwnedEventId};
+
+/// Context around an event, which isn't connected to the room's linked chunk.
+///
+/// See [`RoomEventCache::save_detached_context`].
+///
+/// [`RoomEventCache::save_detached_context`]: super::RoomEventCache::save_detached_context
+#[derive(Clone, Debug)]
+pub struct DetachedContext {
+    /// The events of the context, in topological order.
+    pub events: Vec<Event>,
+
+    /// The token to paginate backwards from the first event.
+    ///
+    /// If `None`, the events reach the start of the room's timeline.
+    pub prev_batch_token: Option<String>,
+
+    /// The token to paginate forwards from the last event.
+    ///
+    /// If `None`, the events reach the end of the room's timeline.
+    pub next_batch_token: Option<String>,
+}
+
+impl DetachedContext {
+    /// Whether the given date falls within the events of this context.
+    pub fn contains_date(&self, date: MilliSecondsSinceUnixEpoch) -> bool {
+        let timestamp = |event: &Event| {
+            event.raw().get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts").ok().flatten()
+        };
+
+        let (Some(first), Some(last)) =
+            (self.events.first().and_then(timestamp), self.events.last().and_then(timestamp))
+        else {
+            return false;
+        };
+
+        first <= date && date <= last
+    }
+
+    /// Return the events of this context that come before the first of its
+    /// events included in `event_ids`, in topological order.
+    ///
+    /// Returns `None` if none of the events of this context are included in
+    /// `event_ids`.
+    pub(super) fn events_before_overlap(
+        &self,
+        event_ids: &BTreeSet<OwnedEventId>,
+    ) -> Option<&[Event]> {
+        let index = self.events.iter().position(|event| {
+            event.event_id().is_some_and(|event_id| event_ids.contains(&event_id))
+        })?;
+
+        Some(&self.events[..index])
+    }
+
+    /// Whether any of the given events is part of this context.
+    pub(super) fn overlaps<'a>(&self, events: impl IntoIterator<Item = &'a Event>) -> bool {
+        let event_ids =
+            self.events.iter().filter_map(|event| event.event_id()).collect::<BTreeSet<_>>();
+
+        events
+            .into_iter()
+            .any(|event| event.event_id().is_some_and(|event_id| event_ids.contains(&event_id)))
+    }
+
+    /// Rebuild a context from the raw chunks of a detached linked chunk.
+    ///
+    /// Returns `None` if the linked chunk is empty.
+    pub(super) fn from_raw_chunks(chunks: Vec<RawChunk<Event, Gap>>) -> Option<Self> {
+        let mut chunks =
+            chunks.into_iter().map(|chunk| (chunk.identifier, chunk)).collect::<HashMap<_, _>>();
+
+        let first = chunks.values().find(|chunk| chunk.previous.is_none())?.identifier;
+        let mut next = Some(first);
+
+        let mut events = Vec::new();
+        let mut prev_batch_token = None;
+        let mut next_batch_token = None;
+
+        while let Some(chunk) = next.and_then(|identifier| chunks.remove(&identifier)) {
+            match chunk.content {
+                ChunkContent::Items(items) => events.extend(items),
+                ChunkContent::Gap(gap) => {
+                    // A gap before the events is for back-paginations, one after them is for
+                    // forward paginations.
+                    if events.is_empty() {
+                        prev_batch_token = gap.prev_token;
+                    } else {
+                        next_batch_token = gap.next_token;
+                    }
+                }
+            }
+
+            next = chunk.next;
+        }
+
+        if events.is_empty() {
+            return None;
+        }
+
+        Some(Self { events, prev_batch_token, next_batch_token })
+    }
+
+    /// Return the updates replacing the content of a detached linked chunk
+    /// with this context.
+    pub(super) fn to_updates(&self) -> Vec<Update<Event, Gap>> {
+        let mut updates = vec![Update::Clear];
+        let mut previous = None;
+        let mut identifiers = (0..).map(ChunkIdentifier::new);
+        let mut next_identifier = || identifiers.next().expect("the identifiers are infinite");
+
+        if let Some(prev_token) = &self.prev_batch_token {
+            let new = next_identifier();
+            updates.push(Update::NewGapChunk {
+                previous,
+                new,
+                next: None,
+                gap: Gap::new(prev_token.clone()),
+            });
+            previous = Some(new);
+        }
+
+        let new = next_identifier();
+        updates.push(Update::NewItemsChunk { previous, new, next: None });
+        updates.push(Update::PushItems { at: Position::new(new, 0), items: self.events.clone() });
+        previous = Some(new);
+
+        if let Some(next_token) = &self.next_batch_token {
+            updates.push(Update::NewGapChunk {
+                previous,
+                new: next_identifier(),
+                next: None,
+                gap: Gap::forward(next_token.clone()),
+            });
+        }
+
+        updates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use matrix_sdk_base::linked_chunk::{LinkedChunkId, RawChunk};
+    use matrix_sdk_common::linked_chunk::relational::RelationalLinkedChunk;
+    use matrix_sdk_test::event_factory::EventFactory;
+    use ruma::{event_id, room_id, user_id, MilliSecondsSinceUnixEpoch};
+
+    use super::DetachedContext;
+
+    fn context(prev_batch_token: Option<&str>, next_batch_token: Option<&str>) -> DetachedContext {
+        let f = EventFactory::new()
+            .room(room_id!("!galette:saucisse.bzh"))
+            .sender(user_id!("@ben:saucisse.bzh"));
+
+        DetachedContext {
+            events: vec![
+                f.text_msg("hello").event_id(event_id!("$ev0")).server_ts(10).into_event(),
+                f.text_msg("world").event_id(event_id!("$ev1")).server_ts(20).into_event(),
+                f.text_msg("!").event_id(event_id!("$ev2")).server_ts(30).into_event(),
+            ],
+            prev_batch_token: prev_batch_token.map(ToOwned::to_owned),
+            next_batch_token: next_batch_token.map(ToOwned::to_owned),
+        }
+    }
+
+    fn raw_chunks(context: &DetachedContext) -> Vec<RawChunk<super::Event, super::Gap>> {
+        let room_id = room_id!("!galette:saucisse.bzh");
+        let linked_chunk_id = LinkedChunkId::Detached(room_id);
+
+        let mut relational = RelationalLinkedChunk::<_, super::Event, super::Gap>::new();
+        relational.apply_updates(linked_chunk_id, context.to_updates());
+        relational.load_all_chunks(linked_chunk_id).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_through_raw_chunks() {
+        for (prev, next) in
+            [(None, None), (Some("prev"), None), (None, Some("next")), (Some("prev"), Some("next"))]
+        {
+            let context = context(prev, next);
+            let rebuilt = DetachedContext::from_raw_chunks(raw_chunks(&context)).unwrap();
+
+            assert_eq!(rebuilt.prev_batch_token.as_deref(), prev);
+            assert_eq!(rebuilt.next_batch_token.as_deref(), next);
+            assert_eq!(
+                rebuilt.events.iter().map(|event| event.event_id().unwrap()).collect::<Vec<_>>(),
+                [event_id!("$ev0"), event_id!("$ev1"), event_id!("$ev2")]
+            );
+        }
+
+        assert!(DetachedContext::from_raw_chunks(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn test_contains_date() {
+        let context = context(None, None);
+
+        assert!(!context.contains_date(MilliSecondsSinceUnixEpoch(5u32.into())));
+        assert!(context.contains_date(MilliSecondsSinceUnixEpoch(10u32.into())));
+        assert!(context.contains_date(MilliSecondsSinceUnixEpoch(25u32.into())));
+        assert!(context.contains_date(MilliSecondsSinceUnixEpoch(30u32.into())));
+        assert!(!context.contains_date(MilliSecondsSinceUnixEpoch(31u32.into())));
+    }
+
+    #[test]
+    fn test_events_before_overlap() {
+        let context = context(Some("prev"), None);
+
+        let unrelated = BTreeSet::from([event_id!("$unrelated").to_owned()]);
+        assert!(context.events_before_overlap(&unrelated).is_none());
+
+        let overlapping =
+            BTreeSet::from([event_id!("$ev2").to_owned(), event_id!("$ev3").to_owned()]);
+        let before = context.events_before_overlap(&overlapping).unwrap();
+        assert_eq!(
+            before.iter().map(|event| event.event_id().unwrap()).collect::<Vec<_>>(),
+            [event_id!("$ev0"), event_id!("$ev1")]
+        );
+
+        let first = BTreeSet::from([event_id!("$ev0").to_owned()]);
+        assert!(context.events_before_overlap(&first).unwrap().is_empty());
+    }
+}
@@ -383,8 +383,11 @@ impl EventLinkedChunk {
 /// Create a debug string for a [`ChunkContent`] for an event/gap pair.
 fn chunk_debug_string(content: &ChunkContent<Event, Gap>) -> String {
     match content {
-        ChunkContent::Gap(Gap { prev_token }) => {
-            format!("gap['{prev_token}']")
+        ChunkContent::Gap(Gap { evicted_before: Some(event_id), .. }) => {
+            format!("gap[evicted before '{event_id}']")
+        }
+        ChunkContent::Gap(gap) => {
+            format!("gap['{}']", gap.token().unwrap_or_default())
         }
         ChunkContent::Items(vec) => {
             let items = vec
@@ -474,7 +477,7 @@ mod tests {
         let mut linked_chunk = EventLinkedChunk::new();
 
         linked_chunk.chunks.push_items_back([event_0]);
-        linked_chunk.chunks.push_gap_back(Gap { prev_token: "hello".to_owned() });
+        linked_chunk.chunks.push_gap_back(Gap::new("hello"));
 
         let gap_chunk_id = linked_chunk
             .chunks()
@@ -514,9 +517,9 @@ mod tests {
         let mut linked_chunk = EventLinkedChunk::new();
 
         linked_chunk.chunks.push_items_back([event_0, event_1]);
-        linked_chunk.chunks.push_gap_back(Gap { prev_token: "middle".to_owned() });
+        linked_chunk.chunks.push_gap_back(Gap::new("middle"));
         linked_chunk.chunks.push_items_back([event_2]);
-        linked_chunk.chunks.push_gap_back(Gap { prev_token: "end".to_owned() });
+        linked_chunk.chunks.push_gap_back(Gap::new("end"));
 
         // Remove the first gap.
         let first_gap_id = linked_chunk
@@ -549,7 +552,7 @@ mod tests {
         // Push some events.
         let mut linked_chunk = EventLinkedChunk::new();
         linked_chunk.chunks.push_items_back([event_0, event_1]);
-        linked_chunk.chunks.push_gap_back(Gap { prev_token: "hello".to_owned() });
+        linked_chunk.chunks.push_gap_back(Gap::new("hello"));
         linked_chunk.chunks.push_items_back([event_2, event_3]);
 
         assert_events_eq!(
@@ -622,7 +625,7 @@ mod tests {
         // Push some events.
         let mut linked_chunk = EventLinkedChunk::new();
         linked_chunk.chunks.push_items_back([event_0, event_1]);
-        linked_chunk.chunks.push_gap_back(Gap { prev_token: "raclette".to_owned() });
+        linked_chunk.chunks.push_gap_back(Gap::new("raclette"));
         linked_chunk.chunks.push_items_back([event_2]);
 
         // Read the updates as `VectorDiff`.
@@ -677,7 +680,7 @@ mod tests {
                 .into_event(),
             event_factory.text_msg("you").event_id(event_id!("$2")).into_event(),
         ]);
-        linked_chunk.chunks.push_gap_back(Gap { prev_token: "raclette".to_owned() });
+        linked_chunk.chunks.push_gap_back(Gap::new("raclette"));
 
         let output = linked_chunk.debug_string();
 
@@ -15,7 +15,7 @@
 //! All event cache types for a single room.
 
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fmt,
     ops::{Deref, DerefMut},
     sync::{
@@ -29,7 +29,10 @@ use eyeball::SharedObservable;
 use eyeball_im::VectorDiff;
 use matrix_sdk_base::{
     deserialized_responses::AmbiguityChange,
-    event_cache::Event,
+    event_cache::{
+        store::{extract_event_relation, EventAggregations, EventCacheStoreLock},
+        Event, Gap,
+    },
     linked_chunk::Position,
     sync::{JoinedRoomUpdate, LeftRoomUpdate, Timeline},
 };
@@ -37,11 +40,11 @@ use ruma::{
     api::Direction,
     events::{relation::RelationType, AnyRoomAccountDataEvent, AnySyncEphemeralRoomEvent},
     serde::Raw,
-    EventId, OwnedEventId, OwnedRoomId,
+    EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId,
 };
 use tokio::sync::{
     broadcast::{Receiver, Sender},
-    mpsc, Notify, RwLock,
+    mpsc, Mutex, Notify, RwLock,
 };
 use tracing::{instrument, trace, warn};
 
@@ -55,9 +58,11 @@ use crate::{
     room::{IncludeRelations, RelationsOptions, WeakRoom},
 };
 
+mod detached;
 pub(super) mod events;
 mod threads;
 
+pub use detached::DetachedContext;
 pub use threads::ThreadEventCacheUpdate;
 
 /// A subset of an event cache, for a room.
@@ -244,10 +249,11 @@ impl RoomEventCache {
 
         loop {
             match outcome {
-                LoadMoreEventsBackwardsOutcome::Gap { prev_token } => {
-                    // Start a threaded pagination from this gap.
+                LoadMoreEventsBackwardsOutcome::Gap { gap } => {
+                    // Start a threaded pagination from this gap. The linked chunks of threads
+                    // aren't persisted, so they never have gaps of evicted events.
                     let options = RelationsOptions {
-                        from: prev_token.clone(),
+                        from: gap.as_ref().and_then(Gap::token).map(ToOwned::to_owned),
                         dir: Direction::Backward,
                         limit: Some(num_events.into()),
                         include_relations: IncludeRelations::AllRelations,
@@ -280,7 +286,7 @@ impl RoomEventCache {
 
                     if let Some(outcome) = state.finish_thread_network_pagination(
                         thread_root.clone(),
-                        prev_token,
+                        gap,
                         result.next_batch_token,
                         result.chunk,
                     ) {
@@ -342,6 +348,38 @@ impl RoomEventCache {
             .map(|(_loc, event)| event)
     }
 
+    /// Try to find an event by ID in the storage of this room, along with the
+    /// events around it.
+    ///
+    /// The context contains all the stored events that are contiguous with the
+    /// target event, i.e. up to the previous and next gaps in the room's
+    /// timeline, so it can be used in place of a `/context` request when the
+    /// homeserver can't be reached.
+    pub async fn find_event_with_context(&self, event_id: &EventId) -> Option<EventContext> {
+        self.inner.state.read().await.find_event_with_context(event_id).await.ok().flatten()
+    }
+
+    /// Save the context around an event that isn't connected (yet) to this
+    /// room's timeline, e.g. the context around a date the user jumped to.
+    ///
+    /// It replaces the previously saved context, if any, and is joined with
+    /// the room's timeline once a back-pagination reaches it. It's ignored if
+    /// some of its events are already known to the room's timeline.
+    pub async fn save_detached_context(&self, context: DetachedContext) -> Result<()> {
+        self.inner.state.write().await.save_detached_context(context).await
+    }
+
+    /// Get the saved context that isn't connected to this room's timeline, if
+    /// it contains the given date.
+    ///
+    /// See [`Self::save_detached_context`].
+    pub async fn detached_context_at(
+        &self,
+        date: MilliSecondsSinceUnixEpoch,
+    ) -> Option<DetachedContext> {
+        self.inner.state.read().await.detached_context_at(date)
+    }
+
     /// Try to find an event by ID in this room, along with its related events.
     ///
     /// You can filter which types of related events to retrieve using
@@ -369,6 +407,39 @@ impl RoomEventCache {
             .flatten()
     }
 
+    /// Get the aggregated relations of the given events in this room, as
+    /// indexed in the storage.
+    ///
+    /// Events without any edit, reaction or redaction are missing from the
+    /// returned map.
+    pub async fn find_event_aggregations(
+        &self,
+        event_ids: &[OwnedEventId],
+    ) -> BTreeMap<OwnedEventId, EventAggregations> {
+        match self.inner.state.read().await.find_event_aggregations(event_ids).await {
+            Ok(aggregations) => aggregations,
+            Err(err) => {
+                warn!("couldn't load the event aggregations from the event cache: {err}");
+                BTreeMap::new()
+            }
+        }
+    }
+
+    /// Get the latest edits and the reactions of the given events in this
+    /// room, as indexed in the storage.
+    ///
+    /// The related events are all loaded at once, instead of one
+    /// [`Self::find_event`] per related event.
+    pub async fn find_aggregated_events(&self, event_ids: &[OwnedEventId]) -> Vec<Event> {
+        match self.inner.state.read().await.find_aggregated_events(event_ids).await {
+            Ok(events) => events,
+            Err(err) => {
+                warn!("couldn't load the aggregated events from the event cache: {err}");
+                Vec::new()
+            }
+        }
+    }
+
     /// Clear all the storage for this [`RoomEventCache`].
     ///
     /// This will get rid of all the events from the linked chunk and persisted
@@ -395,8 +466,22 @@ impl RoomEventCache {
     /// Save some events in the event cache, for further retrieval with
     /// [`Self::event`].
     pub(crate) async fn save_events(&self, events: impl IntoIterator<Item = Event>) {
-        if let Err(err) = self.inner.state.write().await.save_event(events).await {
-            warn!("couldn't save event in the event cache: {err}");
+        let events = events.into_iter().collect::<Vec<_>>();
+
+        let aggregations_update = {
+            let state = self.inner.state.write().await;
+            let aggregations_update = state.aggregations_update(&events, BTreeMap::new());
+
+            if let Err(err) = state.save_event(events).await {
+                warn!("couldn't save event in the event cache: {err}");
+                return;
+            }
+
+            aggregations_update
+        };
+
+        if let Err(err) = aggregations_update.apply().await {
+            warn!("couldn't update the event aggregations in the event cache: {err}");
         }
     }
 
@@ -542,9 +627,12 @@ impl RoomEventCacheInner {
         // Add all the events to the backend.
         trace!("adding new events");
 
-        let (stored_prev_batch_token, timeline_event_diffs) =
+        let (stored_prev_batch_token, timeline_event_diffs, aggregations_update) =
             self.state.write().await.handle_sync(timeline).await?;
 
+        // Index the relations of the new events once the state lock is released.
+        aggregations_update.apply().await?;
+
         // Now that all events have been added, we can trigger the
         // `pagination_token_notifier`.
         if stored_prev_batch_token {
@@ -591,9 +679,9 @@ impl RoomEventCacheInner {
 pub(super) enum LoadMoreEventsBackwardsOutcome {
     /// A gap has been inserted.
     Gap {
-        /// The previous batch token to be used as the "end" parameter in the
-        /// back-pagination request.
-        prev_token: Option<String>,
+        /// The gap to resolve with a back-pagination request, or `None` to
+        /// back-paginate from the end of the room.
+        gap: Option<Gap>,
     },
 
     /// The start of the timeline has been reached.
@@ -606,10 +694,125 @@ pub(super) enum LoadMoreEventsBackwardsOutcome {
     WaitForInitialPrevToken,
 }
 
+/// A redaction applied by the event cache.
+struct AppliedRedaction {
+    /// The ID of the redacted event.
+    redacted_event_id: OwnedEventId,
+
+    /// The ID of the redaction event.
+    redaction_event_id: OwnedEventId,
+
+    /// The event the redacted event was aggregated on, before it got
+    /// redacted.
+    aggregation_target: Option<OwnedEventId>,
+}
+
+/// Get the ID of the event the given event is aggregated on, i.e. the event it
+/// edits or reacts to.
+fn aggregation_target(event: &Event) -> Option<OwnedEventId> {
+    let (relates_to, rel_type) = extract_event_relation(event.raw())?;
+
+    match RelationType::from(rel_type.as_str()) {
+        RelationType::Replacement | RelationType::Annotation => Some(relates_to),
+        _ => None,
+    }
+}
+
+/// The aggregated relations to update in the storage, after some events have
+/// been added to the event cache of a room.
+///
+/// It's computed while holding the lock of the room's state, and applied with
+/// [`AggregationsUpdate::apply()`] once the lock is released, so the indexing
+/// of a whole batch of events doesn't block the other users of the state.
+#[must_use = "the update must be applied with `AggregationsUpdate::apply()`"]
+pub(super) struct AggregationsUpdate {
+    /// The store holding the aggregations.
+    store: EventCacheStoreLock,
+
+    /// The room of the events.
+    room_id: OwnedRoomId,
+
+    /// A lock to apply a single update of the room's aggregations at a time.
+    lock: Arc<Mutex<()>>,
+
+    /// The events whose edits and reactions must be recomputed.
+    targets: BTreeSet<OwnedEventId>,
+
+    /// The redacted events, with the ID of the event that redacted them.
+    redactions: BTreeMap<OwnedEventId, OwnedEventId>,
+
+    /// The new events, whose edits must be recomputed if some were indexed
+    /// before these events were known, since they couldn't be checked
+    /// against the sender of the edited event then.
+    new_event_ids: BTreeSet<OwnedEventId>,
+}
+
+impl AggregationsUpdate {
+    /// Update the aggregated relations of the events in the storage.
+    pub(super) async fn apply(self) -> Result<()> {
+        let Self { store, room_id, lock, mut targets, mut redactions, new_event_ids } = self;
+
+        if targets.is_empty() && redactions.is_empty() && new_event_ids.is_empty() {
+            return Ok(());
+        }
+
+        let event_ids = targets
+            .iter()
+            .chain(redactions.keys())
+            .chain(&new_event_ids)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let _guard = lock.lock().await;
+        let store = store.lock().await?;
+
+        // Load the current aggregations of the whole batch at once.
+        let mut previous_aggregations = store.load_event_aggregations(&room_id, &event_ids).await?;
+
+        targets.extend(new_event_ids.into_iter().filter(|event_id| {
+            previous_aggregations
+                .get(event_id)
+                .is_some_and(|aggregations| aggregations.latest_edit.is_some())
+        }));
+
+        let updated_event_ids =
+            targets.iter().chain(redactions.keys()).cloned().collect::<BTreeSet<_>>();
+
+        for event_id in updated_event_ids {
+            let mut aggregations = previous_aggregations.remove(&event_id).unwrap_or_default();
+
+            if let Some(redaction_event_id) = redactions.remove(&event_id) {
+                aggregations.redacted_by = Some(redaction_event_id);
+            }
+
+            if targets.contains(&event_id) {
+                let original = store.find_event(&room_id, &event_id).await?;
+                let related = store
+                    .find_event_relations(
+                        &room_id,
+                        &event_id,
+                        Some(&[RelationType::Replacement, RelationType::Annotation]),
+                    )
+                    .await?;
+
+                aggregations.set_relations(
+                    original.as_ref(),
+                    related.iter().map(|(event, _position)| event),
+                );
+            }
+
+            trace!(%event_id, "updating the aggregations of an event");
+            store.save_event_aggregations(&room_id, &event_id, aggregations).await?;
+        }
+
+        Ok(())
+    }
+}
+
 // Use a private module to hide `events` to this parent module.
 mod private {
     use std::{
-        collections::{BTreeMap, HashMap, HashSet},
+        collections::{BTreeMap, BTreeSet, HashMap, HashSet},
         sync::{atomic::AtomicUsize, Arc},
     };
 
@@ -618,10 +821,14 @@ mod private {
     use matrix_sdk_base::{
         apply_redaction,
         deserialized_responses::{ThreadSummary, ThreadSummaryStatus, TimelineEventKind},
-        event_cache::{store::EventCacheStoreLock, Event, Gap},
+        event_cache::{
+            store::{EventAggregations, EventCacheStoreLock},
+            Event, Gap,
+        },
         linked_chunk::{
             lazy_loader::{self},
-            ChunkContent, ChunkIdentifierGenerator, LinkedChunkId, Position, Update,
+            ChunkContent, ChunkIdentifier, ChunkIdentifierGenerator, LinkedChunkId, Position,
+            Update,
         },
         serde_helpers::extract_thread_root,
         sync::Timeline,
@@ -633,15 +840,18 @@ mod private {
             MessageLikeEventType,
         },
         serde::Raw,
-        EventId, OwnedEventId, OwnedRoomId, RoomVersionId,
+        EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, RoomVersionId,
     };
-    use tokio::sync::broadcast::Receiver;
+    use tokio::sync::{broadcast::Receiver, Mutex};
     use tracing::{debug, error, instrument, trace, warn};
 
     use super::{
         super::{deduplicator::DeduplicationOutcome, EventCacheError},
+        aggregation_target,
+        detached::DetachedContext,
         events::EventLinkedChunk,
-        sort_positions_descending, EventLocation, LoadMoreEventsBackwardsOutcome,
+        sort_positions_descending, AggregationsUpdate, AppliedRedaction, EventContext,
+        EventLocation, LoadMoreEventsBackwardsOutcome,
     };
     use crate::event_cache::{
         deduplicator::filter_duplicate_events, room::threads::ThreadEventCache,
@@ -671,6 +881,12 @@ mod private {
         /// Keyed by the thread root event ID.
         threads: HashMap<OwnedEventId, ThreadEventCache>,
 
+        /// Context around an event, which isn't connected (yet) to the room's
+        /// linked chunk.
+        ///
+        /// It's mirrored in the store under [`LinkedChunkId::Detached`].
+        detached_context: Option<DetachedContext>,
+
         /// Have we ever waited for a previous-batch-token to come from sync, in
         /// the context of pagination? We do this at most once per room,
         /// the first time we try to run backward pagination. We reset
@@ -682,6 +898,10 @@ mod private {
         /// An atomic count of the current number of subscriber of the
         /// [`super::RoomEventCache`].
         pub(super) subscriber_count: Arc<AtomicUsize>,
+
+        /// The lock of the updates of the aggregations of this room, see
+        /// [`AggregationsUpdate`].
+        aggregations_lock: Arc<Mutex<()>>,
     }
 
     impl RoomEventCacheState {
@@ -728,6 +948,23 @@ mod private {
 
             let room_linked_chunk = EventLinkedChunk::with_initial_linked_chunk(linked_chunk);
 
+            let detached_linked_chunk_id = LinkedChunkId::Detached(&room_id);
+
+            let detached_context = match store_lock.load_all_chunks(detached_linked_chunk_id).await
+            {
+                Ok(chunks) => DetachedContext::from_raw_chunks(chunks),
+                Err(err) => {
+                    error!("error when loading the detached linked chunk from the store: {err}");
+
+                    // Clear the detached context for this room.
+                    store_lock
+                        .handle_linked_chunk_updates(detached_linked_chunk_id, vec![Update::Clear])
+                        .await?;
+
+                    None
+                }
+            };
+
             // The threads mapping is intentionally empty at start, since we're going to
             // reload threads lazily, as soon as we need to (based on external
             // subscribers) or when we get new information about those (from
@@ -740,9 +977,11 @@ mod private {
                 store,
                 room_linked_chunk,
                 threads,
+                detached_context,
                 waited_for_initial_prev_token: false,
                 subscriber_count: Default::default(),
                 pagination_status,
+                aggregations_lock: Default::default(),
             })
         }
 
@@ -765,7 +1004,7 @@ mod private {
                 // Otherwise, we've already waited, *and* received no previous-batch token from
                 // the sync, *and* there are still no events in the fully-loaded
                 // chunk: start back-pagination from the end of the room.
-                LoadMoreEventsBackwardsOutcome::Gap { prev_token: None }
+                LoadMoreEventsBackwardsOutcome::Gap { gap: None }
             }
         }
 
@@ -775,8 +1014,8 @@ mod private {
         ) -> Result<LoadMoreEventsBackwardsOutcome, EventCacheError> {
             // If any in-memory chunk is a gap, don't load more events, and let the caller
             // resolve the gap.
-            if let Some(prev_token) = self.room_linked_chunk.rgap().map(|gap| gap.prev_token) {
-                return Ok(LoadMoreEventsBackwardsOutcome::Gap { prev_token: Some(prev_token) });
+            if let Some(gap) = self.room_linked_chunk.rgap() {
+                return Ok(LoadMoreEventsBackwardsOutcome::Gap { gap: Some(gap) });
             }
 
             // Because `first_chunk` is `not `Send`, get this information before the
@@ -846,7 +1085,7 @@ mod private {
             Ok(match chunk_content {
                 ChunkContent::Gap(gap) => {
                     trace!("reloaded chunk from disk (gap)");
-                    LoadMoreEventsBackwardsOutcome::Gap { prev_token: Some(gap.prev_token) }
+                    LoadMoreEventsBackwardsOutcome::Gap { gap: Some(gap) }
                 }
 
                 ChunkContent::Items(events) => {
@@ -934,8 +1173,10 @@ mod private {
             }
         }
 
-        #[cfg(test)]
-        pub(crate) async fn force_shrink_to_last_chunk(
+        /// Shrink the room to its last chunk, whether it has subscribers or
+        /// not.
+        #[must_use = "Propagate `VectorDiff` updates via `RoomEventCacheUpdate`"]
+        pub(crate) async fn shrink_to_last_chunk_with_diffs(
             &mut self,
         ) -> Result<Vec<VectorDiff<Event>>, EventCacheError> {
             self.shrink_to_last_chunk().await?;
@@ -1131,6 +1372,8 @@ mod private {
 
             self.propagate_changes().await?;
 
+            self.clear_detached_context().await?;
+
             // Reset the pagination state too: pretend we never waited for the initial
             // prev-batch token, and indicate that we're not at the start of the
             // timeline, since we don't know about that anymore.
@@ -1146,6 +1389,73 @@ mod private {
             &self.room_linked_chunk
         }
 
+        /// Save a context which isn't connected to the room's linked chunk,
+        /// replacing the previous one, if any.
+        ///
+        /// The context is ignored if it's empty, or if some of its events are
+        /// already part of the room's linked chunk.
+        pub async fn save_detached_context(
+            &mut self,
+            context: DetachedContext,
+        ) -> Result<(), EventCacheError> {
+            if context.events.is_empty() {
+                return Ok(());
+            }
+
+            if context.overlaps(self.room_linked_chunk.events().map(|(_position, event)| event)) {
+                trace!("the detached context overlaps the in-memory linked chunk, ignoring it");
+                return Ok(());
+            }
+
+            let store = self.store.lock().await?;
+
+            let event_ids = context.events.iter().filter_map(|event| event.event_id()).collect();
+            let duplicated_events =
+                store.filter_duplicated_events(LinkedChunkId::Room(&self.room), event_ids).await?;
+
+            if !duplicated_events.is_empty() {
+                trace!("the detached context overlaps the stored linked chunk, ignoring it");
+                return Ok(());
+            }
+
+            store
+                .handle_linked_chunk_updates(
+                    LinkedChunkId::Detached(&self.room),
+                    context.to_updates(),
+                )
+                .await?;
+
+            self.detached_context = Some(context);
+
+            Ok(())
+        }
+
+        /// Return the detached context containing the given date, if any.
+        pub fn detached_context_at(
+            &self,
+            date: MilliSecondsSinceUnixEpoch,
+        ) -> Option<DetachedContext> {
+            self.detached_context.as_ref().filter(|context| context.contains_date(date)).cloned()
+        }
+
+        /// Forget about the detached context, both in memory and in the store.
+        async fn clear_detached_context(&mut self) -> Result<(), EventCacheError> {
+            if self.detached_context.take().is_none() {
+                return Ok(());
+            }
+
+            self.store
+                .lock()
+                .await?
+                .handle_linked_chunk_updates(
+                    LinkedChunkId::Detached(&self.room),
+                    vec![Update::Clear],
+                )
+                .await?;
+
+            Ok(())
+        }
+
         //// Find a single event in this room, starting from the most recent event.
         ///
         /// **Warning**! It looks into the loaded events from the in-memory
@@ -1182,6 +1492,97 @@ mod private {
                 .map(|event| (EventLocation::Store, event)))
         }
 
+        /// Find an event and the events around it in the persisted storage.
+        ///
+        /// This finds the chunk of the target event from its position in the
+        /// storage, then only loads the chunks around it, up to the previous
+        /// and next gaps.
+        pub async fn find_event_with_context(
+            &self,
+            event_id: &EventId,
+        ) -> Result<Option<EventContext>, EventCacheError> {
+            let store = self.store.lock().await?;
+            let linked_chunk_id = LinkedChunkId::Room(&self.room);
+
+            let Some((_, position)) = store
+                .filter_duplicated_events(linked_chunk_id, vec![event_id.to_owned()])
+                .await?
+                .into_iter()
+                .next()
+            else {
+                return Ok(None);
+            };
+
+            // The metadata of the chunks is enough to know how they are linked, without
+            // loading the events.
+            let metadata = store
+                .load_all_chunks_metadata(linked_chunk_id)
+                .await?
+                .into_iter()
+                .map(|metadata| (metadata.identifier, metadata))
+                .collect::<HashMap<_, _>>();
+
+            // A chunk can only be loaded from the storage as the one before another chunk,
+            // or as the last chunk.
+            let load_chunk = async |identifier: ChunkIdentifier| {
+                let chunk = match metadata.get(&identifier).and_then(|metadata| metadata.next) {
+                    Some(next) => store.load_previous_chunk(linked_chunk_id, next).await?,
+                    None => store.load_last_chunk(linked_chunk_id).await?.0,
+                };
+                Ok::<_, EventCacheError>(chunk.filter(|chunk| chunk.identifier == identifier))
+            };
+
+            let Some(chunk) = load_chunk(position.chunk_identifier()).await? else {
+                return Ok(None);
+            };
+            let ChunkContent::Items(events) = chunk.content else {
+                return Ok(None);
+            };
+            let Some(event) = events.get(position.index()).cloned() else {
+                return Ok(None);
+            };
+
+            let mut events_before =
+                events[..position.index()].iter().rev().cloned().collect::<Vec<_>>();
+            let mut events_after = events[position.index() + 1..].to_vec();
+            let mut prev_batch_token = None;
+
+            // Walk backwards until the previous gap, or the start of the timeline.
+            let mut current = chunk.identifier;
+
+            while let Some(chunk) = store.load_previous_chunk(linked_chunk_id, current).await? {
+                match chunk.content {
+                    ChunkContent::Items(events) => events_before.extend(events.into_iter().rev()),
+                    ChunkContent::Gap(gap) => {
+                        // A gap of evicted events has no token: the caller must ask the
+                        // server for the context of the event.
+                        prev_batch_token = gap.token().map(ToOwned::to_owned);
+                        break;
+                    }
+                }
+
+                current = chunk.identifier;
+            }
+
+            // Walk forwards until the next gap, or the end of the timeline.
+            let mut next = chunk.next;
+
+            while let Some(identifier) = next {
+                let Some(chunk) = load_chunk(identifier).await? else {
+                    break;
+                };
+
+                match chunk.content {
+                    ChunkContent::Items(events) => events_after.extend(events),
+                    ChunkContent::Gap(_) => break,
+                }
+
+                next = chunk.next;
+            }
+
+            Ok(Some(EventContext { event, events_before, events_after, prev_batch_token }))
+        }
+
         /// Find an event and all its relations in the persisted storage.
         ///
         /// This goes straight to the database, as a simplification; we don't
@@ -1251,19 +1652,27 @@ mod private {
         /// Post-process new events, after they have been added to the in-memory
         /// linked chunk.
         ///
-        /// Flushes updates to disk first.
+        /// Flushes updates to disk first. Returns the update of the
+        /// aggregations of the events, to apply once the state lock is
+        /// released.
         async fn post_process_new_events(
             &mut self,
             events: Vec<Event>,
             is_sync: bool,
-        ) -> Result<(), EventCacheError> {
+        ) -> Result<AggregationsUpdate, EventCacheError> {
             // Update the store before doing the post-processing.
             self.propagate_changes().await?;
 
             let mut new_events_by_thread: BTreeMap<_, Vec<_>> = BTreeMap::new();
-
-            for event in events {
-                self.maybe_apply_new_redaction(&event).await?;
+            let mut redactions = BTreeMap::new();
+            let mut redacted_targets = Vec::new();
+
+            for event in &events {
+                if let Some(redaction) = self.maybe_apply_new_redaction(event).await? {
+                    // The redacted event may have been aggregated on another event.
+                    redacted_targets.extend(redaction.aggregation_target);
+                    redactions.insert(redaction.redacted_event_id, redaction.redaction_event_id);
+                }
 
                 if let Some(thread_root) = extract_thread_root(event.raw()) {
                     new_events_by_thread.entry(thread_root).or_default().push(event.clone());
@@ -1275,14 +1684,87 @@ mod private {
                 }
 
                 // Save a bundled thread event, if there was one.
-                if let Some(bundled_thread) = event.bundled_latest_thread_event {
-                    self.save_event([*bundled_thread]).await?;
+                if let Some(bundled_thread) = &event.bundled_latest_thread_event {
+                    self.save_event([(**bundled_thread).clone()]).await?;
                 }
             }
 
             self.update_threads(new_events_by_thread, is_sync).await?;
 
-            Ok(())
+            let mut aggregations_update = self.aggregations_update(&events, redactions);
+            aggregations_update.targets.extend(redacted_targets);
+
+            Ok(aggregations_update)
+        }
+
+        /// Get the aggregated relations of the given events from the storage.
+        pub async fn find_event_aggregations(
+            &self,
+            event_ids: &[OwnedEventId],
+        ) -> Result<BTreeMap<OwnedEventId, EventAggregations>, EventCacheError> {
+            let store = self.store.lock().await?;
+            Ok(store.load_event_aggregations(&self.room, event_ids).await?)
+        }
+
+        /// Get the latest edits and the reactions of the given events from the
+        /// storage.
+        pub async fn find_aggregated_events(
+            &self,
+            event_ids: &[OwnedEventId],
+        ) -> Result<Vec<Event>, EventCacheError> {
+            let store = self.store.lock().await?;
+            let aggregations = store.load_event_aggregations(&self.room, event_ids).await?;
+
+            let mut events = Vec::new();
+
+            for (event_id, aggregations) in aggregations {
+                let indexed_event_ids = aggregations
+                    .latest_edit
+                    .into_iter()
+                    .chain(
+                        aggregations
+                            .reactions
+                            .into_values()
+                            .flatten()
+                            .map(|reaction| reaction.event_id),
+                    )
+                    .collect::<BTreeSet<_>>();
+
+                if indexed_event_ids.is_empty() {
+                    continue;
+                }
+
+                let related = store
+                    .find_event_relations(
+                        &self.room,
+                        &event_id,
+                        Some(&[RelationType::Replacement, RelationType::Annotation]),
+                    )
+                    .await?;
+
+                events.extend(related.into_iter().map(|(event, _position)| event).filter(
+                    |event| event.event_id().is_some_and(|id| indexed_event_ids.contains(&id)),
+                ));
+            }
+
+            Ok(events)
+        }
+
+        /// Prepare the update of the aggregated relations of the given new
+        /// events, and of the events redacted by them.
+        pub fn aggregations_update(
+            &self,
+            events: &[Event],
+            redactions: BTreeMap<OwnedEventId, OwnedEventId>,
+        ) -> AggregationsUpdate {
+            AggregationsUpdate {
+                store: self.store.clone(),
+                room_id: self.room.clone(),
+                lock: self.aggregations_lock.clone(),
+                targets: events.iter().filter_map(aggregation_target).collect(),
+                redactions,
+                new_event_ids: events.iter().filter_map(|event| event.event_id()).collect(),
+            }
         }
 
         fn get_or_reload_thread(&mut self, root_event_id: OwnedEventId) -> &mut ThreadEventCache {
@@ -1395,11 +1877,14 @@ mod private {
         /// If the given event is a redaction, try to retrieve the
         /// to-be-redacted event in the chunk, and replace it by the
         /// redacted form.
+        ///
+        /// Returns the details of the redaction if the event is a redaction
+        /// event, even if the redacted event is unknown.
         #[instrument(skip_all)]
         async fn maybe_apply_new_redaction(
             &mut self,
             event: &Event,
-        ) -> Result<(), EventCacheError> {
+        ) -> Result<Option<AppliedRedaction>, EventCacheError> {
             let raw_event = event.raw();
 
             // Do not deserialise the entire event if we aren't certain it's a
@@ -1407,7 +1892,7 @@ mod private {
             let Ok(Some(MessageLikeEventType::RoomRedaction)) =
                 raw_event.get_field::<MessageLikeEventType>("type")
             else {
-                return Ok(());
+                return Ok(None);
             };
 
             // It is a `m.room.redaction`! We can deserialize it entirely.
@@ -1416,18 +1901,24 @@ mod private {
                 ruma::events::AnySyncMessageLikeEvent::RoomRedaction(redaction),
             )) = raw_event.deserialize()
             else {
-                return Ok(());
+                return Ok(None);
             };
 
             let Some(event_id) = redaction.redacts(&self.room_version) else {
                 warn!("missing target event id from the redaction event");
-                return Ok(());
+                return Ok(None);
+            };
+
+            let mut applied = AppliedRedaction {
+                redacted_event_id: event_id.to_owned(),
+                redaction_event_id: redaction.event_id().to_owned(),
+                aggregation_target: None,
             };
 
             // Replace the redacted event by a redacted form, if we knew about it.
             let Some((location, mut target_event)) = self.find_event(event_id).await? else {
                 trace!("redacted event is missing from the linked chunk");
-                return Ok(());
+                return Ok(Some(applied));
             };
 
             // Don't redact already redacted events.
@@ -1435,17 +1926,20 @@ mod private {
                 match deserialized {
                     AnySyncTimelineEvent::MessageLike(ev) => {
                         if ev.is_redacted() {
-                            return Ok(());
+                            return Ok(Some(applied));
                         }
                     }
                     AnySyncTimelineEvent::State(ev) => {
                         if ev.is_redacted() {
-                            return Ok(());
+                            return Ok(Some(applied));
                         }
                     }
                 }
             }
 
+            // The relation is lost once the event is redacted, so remember it now.
+            applied.aggregation_target = aggregation_target(&target_event);
+
             if let Some(redacted_event) = apply_redaction(
                 target_event.raw(),
                 event.raw().cast_ref::<SyncRoomRedactionEvent>(),
@@ -1460,7 +1954,7 @@ mod private {
                 self.replace_event_at(location, target_event).await?;
             }
 
-            Ok(())
+            Ok(Some(applied))
         }
 
         /// Save a single event into the database, without notifying observers.
@@ -1497,12 +1991,14 @@ mod private {
         /// generated any of those.
         ///
         /// Returns `true` for the first part of the tuple if a new gap
-        /// (previous-batch token) has been inserted, `false` otherwise.
+        /// (previous-batch token) has been inserted, `false` otherwise, and
+        /// the update of the aggregations to apply once the state lock is
+        /// released for the last part.
         #[must_use = "Propagate `VectorDiff` updates via `RoomEventCacheUpdate`"]
         pub async fn handle_sync(
             &mut self,
             mut timeline: Timeline,
-        ) -> Result<(bool, Vec<VectorDiff<Event>>), EventCacheError> {
+        ) -> Result<(bool, Vec<VectorDiff<Event>>, AggregationsUpdate), EventCacheError> {
             let mut prev_batch = timeline.prev_batch.take();
 
             let DeduplicationOutcome {
@@ -1518,6 +2014,12 @@ mod private {
             )
             .await?;
 
+            // The events are about to be part of the room's linked chunk, so the detached
+            // context they overlap with is now outdated.
+            if self.detached_context.as_ref().is_some_and(|context| context.overlaps(&events)) {
+                self.clear_detached_context().await?;
+            }
+
             // If the timeline isn't limited, and we already knew about some past events,
             // then this definitely knows what the timeline head is (either we know
             // about all the events persisted in storage, or we have a gap
@@ -1573,7 +2075,7 @@ mod private {
             if all_duplicates {
                 // No new events and no gap (per the previous check), thus no need to change the
                 // room state. We're done!
-                return Ok((false, Vec::new()));
+                return Ok((false, Vec::new(), self.aggregations_update(&[], BTreeMap::new())));
             }
 
             let has_new_gap = prev_batch.is_some();
@@ -1591,10 +2093,9 @@ mod private {
             self.remove_events(in_memory_duplicated_event_ids, in_store_duplicated_event_ids)
                 .await?;
 
-            self.room_linked_chunk
-                .push_live_events(prev_batch.map(|prev_token| Gap { prev_token }), &events);
+            self.room_linked_chunk.push_live_events(prev_batch.map(Gap::new), &events);
 
-            self.post_process_new_events(events, true).await?;
+            let aggregations_update = self.post_process_new_events(events, true).await?;
 
             if timeline.limited && has_new_gap {
                 // If there was a previous batch token for a limited timeline, unload the chunks
@@ -1608,13 +2109,13 @@ mod private {
 
             let timeline_event_diffs = self.room_linked_chunk.updates_as_vector_diffs();
 
-            Ok((has_new_gap, timeline_event_diffs))
+            Ok((has_new_gap, timeline_event_diffs, aggregations_update))
         }
 
         /// Handle the result of a single back-pagination request.
         ///
-        /// If the `prev_token` is set, then this function will check that the
-        /// corresponding gap is present in the in-memory linked chunk.
+        /// If the `prev_gap` is set, then this function will check that it is
+        /// still present in the in-memory linked chunk.
         /// If it's not the case, `Ok(None)` will be returned, and the
         /// caller may decide to do something based on that (e.g. restart a
         /// pagination).
@@ -1623,16 +2124,18 @@ mod private {
             &mut self,
             events: Vec<Event>,
             mut new_token: Option<String>,
-            prev_token: Option<String>,
-        ) -> Result<Option<(BackPaginationOutcome, Vec<VectorDiff<Event>>)>, EventCacheError>
-        {
-            // Check that the previous token still exists; otherwise it's a sign that the
+            prev_gap: Option<Gap>,
+        ) -> Result<
+            Option<(BackPaginationOutcome, Vec<VectorDiff<Event>>, AggregationsUpdate)>,
+            EventCacheError,
+        > {
+            // Check that the previous gap still exists; otherwise it's a sign that the
             // room's timeline has been cleared.
-            let prev_gap_id = if let Some(token) = prev_token {
+            let prev_gap_id = if let Some(prev_gap) = prev_gap {
                 // Find the corresponding gap in the in-memory linked chunk.
-                let gap_chunk_id = self.room_linked_chunk.chunk_identifier(|chunk| {
-                    matches!(chunk.content(), ChunkContent::Gap(Gap { ref prev_token }) if *prev_token == token)
-                });
+                let gap_chunk_id = self.room_linked_chunk.chunk_identifier(
+                    |chunk| matches!(chunk.content(), ChunkContent::Gap(gap) if *gap == prev_gap),
+                );
 
                 if gap_chunk_id.is_none() {
                     // We got a previous-batch token from the linked chunk *before* running the
@@ -1686,11 +2189,32 @@ mod private {
                 new_token = None;
             }
 
+            // If the back-pagination reached the detached context, join it to the room's
+            // linked chunk: its events that are older than the back-paginated ones are
+            // prepended, and the back-pagination resumes from its previous-batch token.
+            if let Some(context) =
+                self.detached_context.as_ref().filter(|context| context.overlaps(&events)).cloned()
+            {
+                let event_ids = events.iter().filter_map(|event| event.event_id()).collect();
+
+                if let Some(older_events) = context.events_before_overlap(&event_ids) {
+                    if !older_events.is_empty() {
+                        trace!(num_events = older_events.len(), "joining the detached context");
+
+                        events.extend(older_events.iter().rev().cloned());
+                        new_token = context.prev_batch_token.clone();
+                    }
+                }
+
+                // The events are about to be part of the room's linked chunk.
+                self.clear_detached_context().await?;
+            }
+
             // `/messages` has been called with `dir=b` (backwards), so the events are in
             // the inverted order; reorder them.
             let topo_ordered_events = events.iter().rev().cloned().collect::<Vec<_>>();
 
-            let new_gap = new_token.map(|prev_token| Gap { prev_token });
+            let new_gap = new_token.map(Gap::new);
             let reached_start = self.room_linked_chunk.finish_back_pagination(
                 prev_gap_id,
                 new_gap,
@@ -1698,11 +2222,16 @@ mod private {
             );
 
             // Note: this flushes updates to the store.
-            self.post_process_new_events(topo_ordered_events, false).await?;
+            let aggregations_update =
+                self.post_process_new_events(topo_ordered_events, false).await?;
 
             let event_diffs = self.room_linked_chunk.updates_as_vector_diffs();
 
-            Ok(Some((BackPaginationOutcome { events, reached_start }, event_diffs)))
+            Ok(Some((
+                BackPaginationOutcome { events, reached_start },
+                event_diffs,
+                aggregations_update,
+            )))
         }
 
         /// Subscribe to thread for a given root event, and get a (maybe empty)
@@ -1720,11 +2249,11 @@ mod private {
         pub fn finish_thread_network_pagination(
             &mut self,
             root: OwnedEventId,
-            prev_token: Option<String>,
+            prev_gap: Option<Gap>,
             new_token: Option<String>,
             events: Vec<Event>,
         ) -> Option<BackPaginationOutcome> {
-            self.get_or_reload_thread(root).finish_network_pagination(prev_token, new_token, events)
+            self.get_or_reload_thread(root).finish_network_pagination(prev_gap, new_token, events)
         }
 
         pub fn load_more_thread_events_backwards(
@@ -1736,6 +2265,30 @@ mod private {
     }
 }
 
+/// An event found in the storage of a [`RoomEventCache`], with the events
+/// around it.
+///
+/// See [`RoomEventCache::find_event_with_context`].
+#[derive(Debug)]
+pub struct EventContext {
+    /// The target event.
+    pub event: Event,
+
+    /// The events before the target event, in reverse chronological order.
+    pub events_before: Vec<Event>,
+
+    /// The events after the target event, in chronological order.
+    pub events_after: Vec<Event>,
+
+    /// The token to paginate backwards from the first event of
+    /// `events_before`, if there's a gap before it.
+    ///
+    /// If `None`, the events before reach the start of the room's timeline,
+    /// unless the events before them have been evicted from the store: there's
+    /// no token to paginate from then.
+    pub prev_batch_token: Option<String>,
+}
+
 /// An enum representing where an event has been found.
 pub(super) enum EventLocation {
     /// Event lives in memory (and likely in the store!).
@@ -2016,7 +2569,7 @@ mod timed_tests {
     use matrix_sdk_base::{
         event_cache::{
             store::{EventCacheStore as _, MemoryStore},
-            Gap,
+            Event, Gap,
         },
         linked_chunk::{
             lazy_loader::from_all_chunks, ChunkContent, ChunkIdentifier, LinkedChunkId, Position,
@@ -2099,7 +2652,7 @@ mod timed_tests {
 
         // We start with the gap.
         assert_matches!(chunks.next().unwrap().content(), ChunkContent::Gap(gap) => {
-            assert_eq!(gap.prev_token, "raclette");
+            assert_eq!(gap.prev_token.as_deref(), Some("raclette"));
         });
 
         // Then we have the stored event.
@@ -2203,6 +2756,123 @@ mod timed_tests {
         assert!(chunks.next().is_none());
     }
 
+    #[async_test]
+    async fn test_find_event_with_context() {
+        let room_id = room_id!("!galette:saucisse.bzh");
+        let f = EventFactory::new().room(room_id).sender(user_id!("@ben:saucisse.bzh"));
+
+        let event_cache_store = Arc::new(MemoryStore::new());
+
+        let ev0 = f.text_msg("0").event_id(event_id!("$0")).into_event();
+        let ev1 = f.text_msg("1").event_id(event_id!("$1")).into_event();
+        let ev2 = f.text_msg("2").event_id(event_id!("$2")).into_event();
+        let ev3 = f.text_msg("3").event_id(event_id!("$3")).into_event();
+        let ev4 = f.text_msg("4").event_id(event_id!("$4")).into_event();
+
+        // Prefill the store with chunks separated by gaps.
+        event_cache_store
+            .handle_linked_chunk_updates(
+                LinkedChunkId::Room(room_id),
+                vec![
+                    Update::NewItemsChunk {
+                        previous: None,
+                        new: ChunkIdentifier::new(0),
+                        next: None,
+                    },
+                    Update::PushItems {
+                        at: Position::new(ChunkIdentifier::new(0), 0),
+                        items: vec![ev0],
+                    },
+                    Update::NewGapChunk {
+                        previous: Some(ChunkIdentifier::new(0)),
+                        new: ChunkIdentifier::new(42),
+                        next: None,
+                        gap: Gap::new("comté"),
+                    },
+                    Update::NewItemsChunk {
+                        previous: Some(ChunkIdentifier::new(42)),
+                        new: ChunkIdentifier::new(1),
+                        next: None,
+                    },
+                    Update::PushItems {
+                        at: Position::new(ChunkIdentifier::new(1), 0),
+                        items: vec![ev1],
+                    },
+                    Update::NewItemsChunk {
+                        previous: Some(ChunkIdentifier::new(1)),
+                        new: ChunkIdentifier::new(2),
+                        next: None,
+                    },
+                    Update::PushItems {
+                        at: Position::new(ChunkIdentifier::new(2), 0),
+                        items: vec![ev2, ev3],
+                    },
+                    Update::NewGapChunk {
+                        previous: Some(ChunkIdentifier::new(2)),
+                        new: ChunkIdentifier::new(43),
+                        next: None,
+                        gap: Gap::new("raclette"),
+                    },
+                    Update::NewItemsChunk {
+                        previous: Some(ChunkIdentifier::new(43)),
+                        new: ChunkIdentifier::new(3),
+                        next: None,
+                    },
+                    Update::PushItems {
+                        at: Position::new(ChunkIdentifier::new(3), 0),
+                        items: vec![ev4],
+                    },
+                ],
+            )
+            .await
+            .unwrap();
+
+        let client = MockClientBuilder::new(None)
+            .store_config(
+                StoreConfig::new("hodlor".to_owned()).event_cache_store(event_cache_store.clone()),
+            )
+            .build()
+            .await;
+
+        let event_cache = client.event_cache();
+        event_cache.subscribe().unwrap();
+
+        client.base_client().get_or_create_room(room_id, matrix_sdk_base::RoomState::Joined);
+        let room = client.get_room(room_id).unwrap();
+
+        let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+
+        let event_ids = |events: Vec<_>| {
+            events.into_iter().map(|event: Event| event.event_id().unwrap()).collect::<Vec<_>>()
+        };
+
+        // The context spans several chunks, up to the previous and next gaps.
+        let context = room_event_cache.find_event_with_context(event_id!("$1")).await.unwrap();
+        assert_eq!(context.event.event_id().as_deref(), Some(event_id!("$1")));
+        assert!(context.events_before.is_empty());
+        assert_eq!(event_ids(context.events_after), [event_id!("$2"), event_id!("$3")]);
+        assert_eq!(context.prev_batch_token.as_deref(), Some("comté"));
+
+        let context = room_event_cache.find_event_with_context(event_id!("$3")).await.unwrap();
+        assert_eq!(event_ids(context.events_before), [event_id!("$2"), event_id!("$1")]);
+        assert!(context.events_after.is_empty());
+        assert_eq!(context.prev_batch_token.as_deref(), Some("comté"));
+
+        // The first event has no gap before it.
+        let context = room_event_cache.find_event_with_context(event_id!("$0")).await.unwrap();
+        assert!(context.events_before.is_empty());
+        assert!(context.events_after.is_empty());
+        assert!(context.prev_batch_token.is_none());
+
+        // The last event is right after a gap.
+        let context = room_event_cache.find_event_with_context(event_id!("$4")).await.unwrap();
+        assert!(context.events_before.is_empty());
+        assert_eq!(context.prev_batch_token.as_deref(), Some("raclette"));
+
+        // Unknown events aren't found.
+        assert!(room_event_cache.find_event_with_context(event_id!("$5")).await.is_none());
+    }
+
     #[async_test]
     async fn test_clear() {
         let room_id = room_id!("!galette:saucisse.bzh");
@@ -2233,7 +2903,7 @@ mod timed_tests {
                         // Chunk IDs aren't supposed to be ordered, so use a random value here.
                         new: ChunkIdentifier::new(42),
                         next: None,
-                        gap: Gap { prev_token: "comté".to_owned() },
+                        gap: Gap::new("comté"),
                     },
                     // Another items chunk, non-empty this time.
                     Update::NewItemsChunk {
@@ -2384,7 +3054,7 @@ mod timed_tests {
                         // Chunk IDs aren't supposed to be ordered, so use a random value here.
                         new: ChunkIdentifier::new(42),
                         next: None,
-                        gap: Gap { prev_token: "cheddar".to_owned() },
+                        gap: Gap::new("cheddar"),
                     },
                     // Another items chunk, non-empty this time.
                     Update::NewItemsChunk {
@@ -2672,7 +3342,7 @@ mod timed_tests {
                 match c.content() {
                     ChunkContent::Items(items) => num_events += items.len(),
                     ChunkContent::Gap(gap) => {
-                        assert_eq!(gap.prev_token, "raclette");
+                        assert_eq!(gap.prev_token.as_deref(), Some("raclette"));
                         num_gaps += 1;
                     }
                 }
@@ -2775,7 +3445,7 @@ mod timed_tests {
             .state
             .write()
             .await
-            .force_shrink_to_last_chunk()
+            .shrink_to_last_chunk_with_diffs()
             .await
             .expect("shrinking should succeed");
 
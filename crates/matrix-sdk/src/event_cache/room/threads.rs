@@ -117,9 +117,9 @@ impl ThreadEventCache {
     pub fn load_more_events_backwards(&self) -> LoadMoreEventsBackwardsOutcome {
         // If any in-memory chunk is a gap, don't load more events, and let the caller
         // resolve the gap.
-        if let Some(prev_token) = self.chunk.rgap().map(|gap| gap.prev_token) {
-            trace!(%prev_token, "thread chunk has at least a gap");
-            return LoadMoreEventsBackwardsOutcome::Gap { prev_token: Some(prev_token) };
+        if let Some(gap) = self.chunk.rgap() {
+            trace!(prev_token = ?gap.prev_token, "thread chunk has at least a gap");
+            return LoadMoreEventsBackwardsOutcome::Gap { gap: Some(gap) };
         }
 
         // If we don't have a gap, then the first event should be the the thread's root;
@@ -136,7 +136,7 @@ impl ThreadEventCache {
 
         // Otherwise, we don't have a gap nor events. We don't have anything. Poor us.
         // Well, is ok: start a pagination from the end.
-        LoadMoreEventsBackwardsOutcome::Gap { prev_token: None }
+        LoadMoreEventsBackwardsOutcome::Gap { gap: None }
     }
 
     /// Find duplicates in a thread, until there's persistent storage for
@@ -206,18 +206,18 @@ impl ThreadEventCache {
     /// thread has been reset while the pagination was ongoing).
     pub fn finish_network_pagination(
         &mut self,
-        prev_token: Option<String>,
+        prev_gap: Option<Gap>,
         new_token: Option<String>,
         events: Vec<Event>,
     ) -> Option<BackPaginationOutcome> {
         // TODO(bnjbvr): consider deduplicating this code (~same for room) at some
         // point.
-        let prev_gap_id = if let Some(token) = prev_token {
+        let prev_gap_id = if let Some(prev_gap) = prev_gap {
             // If the gap id is missing, it means that the gap disappeared during
             // pagination; in this case, early return to the caller.
-            let gap_id = self.chunk.chunk_identifier(|chunk| {
-                    matches!(chunk.content(), ChunkContent::Gap(Gap { ref prev_token }) if *prev_token == token)
-                })?;
+            let gap_id = self.chunk.chunk_identifier(
+                |chunk| matches!(chunk.content(), ChunkContent::Gap(gap) if *gap == prev_gap),
+            )?;
 
             Some(gap_id)
         } else {
@@ -227,7 +227,7 @@ impl ThreadEventCache {
         // This is a backwards pagination, so the events were returned in the reverse
         // topological order.
         let topo_ordered_events = events.iter().cloned().rev().collect::<Vec<_>>();
-        let new_gap = new_token.map(|token| Gap { prev_token: token });
+        let new_gap = new_token.map(Gap::new);
 
         let deduplication = self.filter_duplicate_events(topo_ordered_events);
 
@@ -96,8 +96,7 @@ pub struct Ctx<T>(pub T);
 
 impl<T: Clone + SendOutsideWasm + SyncOutsideWasm + 'static> EventHandlerContext for Ctx<T> {
     fn from_data(data: &EventHandlerData<'_>) -> Option<Self> {
-        let map = data.client.inner.event_handlers.context.read().unwrap();
-        map.get::<T>().cloned().map(Ctx)
+        data.client.inner.event_handlers.context::<T>().map(Ctx)
     }
 }
 
@@ -106,6 +106,13 @@ impl EventHandlerStore {
         self.context.write().unwrap().insert(ctx);
     }
 
+    pub fn context<T>(&self) -> Option<T>
+    where
+        T: Clone + SendOutsideWasm + SyncOutsideWasm + 'static,
+    {
+        self.context.read().unwrap().get::<T>().cloned()
+    }
+
     pub fn remove(&self, handle: EventHandlerHandle) {
         self.handlers.write().unwrap().remove(handle);
     }
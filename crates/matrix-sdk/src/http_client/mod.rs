@@ -27,9 +27,12 @@ use bytes::{Bytes, BytesMut};
 use bytesize::ByteSize;
 use eyeball::SharedObservable;
 use http::Method;
-use ruma::api::{
-    error::{FromHttpResponseError, IntoHttpError},
-    AuthScheme, MatrixVersion, OutgoingRequest, SendAccessToken,
+use ruma::{
+    api::{
+        error::{FromHttpResponseError, IntoHttpError},
+        AuthScheme, MatrixVersion, OutgoingRequest, OutgoingRequestAppserviceExt, SendAccessToken,
+    },
+    UserId,
 };
 use tokio::sync::{Semaphore, SemaphorePermit};
 use tracing::{debug, field::debug, instrument, trace};
@@ -38,11 +41,15 @@ use crate::{config::RequestConfig, error::HttpError};
 
 #[cfg(not(target_family = "wasm"))]
 mod native;
+#[cfg(not(target_family = "wasm"))]
+mod recorder;
 #[cfg(target_family = "wasm")]
 mod wasm;
 
 #[cfg(not(target_family = "wasm"))]
 pub(crate) use native::HttpSettings;
+#[cfg(not(target_family = "wasm"))]
+pub(crate) use recorder::SyncRecorder;
 
 pub(crate) const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
 
@@ -76,6 +83,9 @@ pub(crate) struct HttpClient {
     pub(crate) request_config: RequestConfig,
     concurrent_request_semaphore: MaybeSemaphore,
     next_request_id: Arc<AtomicU64>,
+    /// The recorder of the sync requests, if the sync is being recorded.
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) sync_recorder: Arc<std::sync::Mutex<Option<SyncRecorder>>>,
 }
 
 impl HttpClient {
@@ -87,6 +97,8 @@ impl HttpClient {
                 request_config.max_concurrent_requests,
             ),
             next_request_id: AtomicU64::new(0).into(),
+            #[cfg(not(target_family = "wasm"))]
+            sync_recorder: Default::default(),
         }
     }
 
@@ -101,6 +113,7 @@ impl HttpClient {
         config: RequestConfig,
         homeserver: String,
         access_token: Option<&str>,
+        assert_identity: Option<&UserId>,
         server_versions: &[MatrixVersion],
     ) -> Result<http::Request<Bytes>, IntoHttpError>
     where
@@ -118,6 +131,8 @@ impl HttpClient {
             Some(access_token) => {
                 if config.force_auth {
                     SendAccessToken::Always(access_token)
+                } else if assert_identity.is_some() {
+                    SendAccessToken::Appservice(access_token)
                 } else {
                     SendAccessToken::IfRequired(access_token)
                 }
@@ -125,16 +140,38 @@ impl HttpClient {
             None => SendAccessToken::None,
         };
 
-        let request = request
-            .try_into_http_request::<BytesMut>(&homeserver, send_access_token, server_versions)?
-            .map(|body| body.freeze());
+        // Application services can send requests on behalf of the users in their
+        // namespace, by asserting their identity in the query string.
+        let request = match assert_identity {
+            Some(user_id) => request.try_into_http_request_with_user_id::<BytesMut>(
+                &homeserver,
+                send_access_token,
+                user_id,
+                server_versions,
+            )?,
+            None => request.try_into_http_request::<BytesMut>(
+                &homeserver,
+                send_access_token,
+                server_versions,
+            )?,
+        }
+        .map(|body| body.freeze());
 
         Ok(request)
     }
 
     #[allow(clippy::too_many_arguments)]
     #[instrument(
-        skip(self, request, config, homeserver, access_token, server_versions, send_progress),
+        skip(
+            self,
+            request,
+            config,
+            homeserver,
+            access_token,
+            assert_identity,
+            server_versions,
+            send_progress
+        ),
         fields(
             uri,
             method,
@@ -152,6 +189,7 @@ impl HttpClient {
         config: Option<RequestConfig>,
         homeserver: String,
         access_token: Option<&str>,
+        assert_identity: Option<&UserId>,
         server_versions: &[MatrixVersion],
         send_progress: SharedObservable<TransmissionProgress>,
     ) -> Result<R::IncomingResponse, HttpError>
@@ -186,7 +224,14 @@ impl HttpClient {
             }
 
             let request = self
-                .serialize_request(request, config, homeserver, access_token, server_versions)
+                .serialize_request(
+                    request,
+                    config,
+                    homeserver,
+                    access_token,
+                    assert_identity,
+                    server_versions,
+                )
                 .map_err(HttpError::IntoHttp)?;
 
             let method = request.method();
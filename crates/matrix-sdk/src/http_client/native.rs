@@ -23,12 +23,15 @@ use backon::{ExponentialBuilder, Retryable};
 use bytes::Bytes;
 use bytesize::ByteSize;
 use eyeball::SharedObservable;
-use http::header::CONTENT_LENGTH;
+use http::{header::CONTENT_LENGTH, HeaderMap};
 use reqwest::{tls, Certificate};
-use ruma::api::{error::FromHttpResponseError, IncomingResponse, OutgoingRequest};
+use ruma::api::{error::FromHttpResponseError, IncomingResponse, MatrixVersion, OutgoingRequest};
 use tracing::{debug, info, warn};
 
-use super::{response_to_http_response, HttpClient, TransmissionProgress, DEFAULT_REQUEST_TIMEOUT};
+use super::{
+    response_to_http_response, HttpClient, SyncRecorder, TransmissionProgress,
+    DEFAULT_REQUEST_TIMEOUT,
+};
 use crate::{
     config::RequestConfig,
     error::{HttpError, RetryKind},
@@ -103,6 +106,8 @@ impl HttpClient {
                     }
                 }
 
+                self.maybe_record_sync(&request, &response);
+
                 R::IncomingResponse::try_from_http_response(response).map_err(HttpError::from)
             }
         };
@@ -141,6 +146,76 @@ impl HttpClient {
             })
             .await
     }
+
+    /// Record the given sync request and its response, if the sync is being
+    /// recorded.
+    fn maybe_record_sync(&self, request: &http::Request<Bytes>, response: &http::Response<Bytes>) {
+        if !SyncRecorder::is_sync_request(request) {
+            return;
+        }
+
+        // Recording only queues the sync for the writer thread of the recorder, so
+        // the lock isn't held during any I/O.
+        if let Some(recorder) = self.sync_recorder.lock().unwrap().as_ref() {
+            recorder.record(request, response);
+        }
+    }
+
+    /// Send a request without buffering the response body, so that the caller
+    /// can consume it chunk by chunk.
+    ///
+    /// The `headers` are added to the serialized request. Contrary to
+    /// [`HttpClient::send`], the request isn't retried on failures: since the
+    /// body may be partially consumed when an error happens, it is up to the
+    /// caller to decide how to resume.
+    ///
+    /// Responses with an error status code are turned into an [`HttpError`],
+    /// like for any other request.
+    pub(crate) async fn send_streaming<R>(
+        &self,
+        request: R,
+        config: Option<RequestConfig>,
+        homeserver: String,
+        access_token: Option<&str>,
+        server_versions: &[MatrixVersion],
+        headers: HeaderMap,
+    ) -> Result<reqwest::Response, HttpError>
+    where
+        R: OutgoingRequest + Debug,
+        HttpError: From<FromHttpResponseError<R::EndpointError>>,
+    {
+        let config = config.unwrap_or(self.request_config);
+
+        let mut request = self
+            .serialize_request(request, config, homeserver, access_token, None, server_versions)
+            .map_err(HttpError::IntoHttp)?;
+        request.headers_mut().extend(headers);
+
+        let mut request = reqwest::Request::try_from(request)?;
+        // The duration of the transfer depends on the size of the content, so no
+        // timeout is set for this request and the caller applies the timeout to
+        // each step of the response instead.
+        *request.timeout_mut() = None;
+
+        let response = {
+            let _handle = self.concurrent_request_semaphore.acquire().await;
+            debug!(uri = %request.url().path(), "Sending streaming request");
+            self.inner.execute(request).await?
+        };
+
+        if !response.status().is_client_error() && !response.status().is_server_error() {
+            return Ok(response);
+        }
+
+        // Let Ruma parse the error, so it's reported the same way as for
+        // buffered requests.
+        let response = response_to_http_response(response).await?;
+        let error = R::IncomingResponse::try_from_http_response(response)
+            .err()
+            .expect("a response with an error status code is never deserialized successfully");
+
+        Err(error.into())
+    }
 }
 
 #[cfg(not(target_family = "wasm"))]
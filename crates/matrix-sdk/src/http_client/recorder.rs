@@ -0,0 +1,225 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recording of the sync requests and responses, to replay them in tests.
+//!
+//! See [`Client::start_recording_syncs()`](crate::Client::start_recording_syncs)
+//! for the format of the recordings.
+//!
+//! The responses are written to the file by a dedicated thread, so the large
+//! responses, like the one of an initial sync, don't block the async runtime.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::mpsc,
+    thread::{self, JoinHandle},
+};
+
+use bytes::Bytes;
+use ruma::{DeviceId, UserId};
+use serde_json::{json, Value as JsonValue};
+use tracing::warn;
+
+/// The value replacing the tokens in the recordings.
+const REDACTED: &str = "<redacted>";
+
+/// The names of the query parameters and JSON fields holding tokens, which are
+/// redacted from the recordings.
+const TOKEN_FIELDS: &[&str] = &["access_token", "refresh_token"];
+
+/// A recorder of the requests to the sync endpoints and their responses,
+/// writing them to a file.
+#[derive(Debug)]
+pub(crate) struct SyncRecorder {
+    /// The sender of the syncs to the thread writing them.
+    sender: mpsc::Sender<RecordedSync>,
+    /// The thread writing the syncs to the file.
+    writer: JoinHandle<()>,
+}
+
+/// A request to a sync endpoint and its response, to be written by the writer
+/// thread.
+#[derive(Debug)]
+struct RecordedSync {
+    method: http::Method,
+    uri: http::Uri,
+    request_body: Bytes,
+    status: http::StatusCode,
+    response_body: Bytes,
+}
+
+impl SyncRecorder {
+    /// Create a new recording in the file at the given path, for the given
+    /// session.
+    ///
+    /// The file is overwritten if it already exists.
+    pub(crate) fn new(path: &Path, user_id: &UserId, device_id: &DeviceId) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        write_line(&mut file, &json!({ "user_id": user_id, "device_id": device_id }))?;
+
+        let (sender, receiver) = mpsc::channel();
+        let writer = thread::Builder::new()
+            .name("sync-recorder".to_owned())
+            .spawn(move || write_syncs(file, receiver))?;
+
+        Ok(Self { sender, writer })
+    }
+
+    /// Whether the given request is a request to one of the sync endpoints,
+    /// either the classic `/sync` or the sliding sync one.
+    pub(crate) fn is_sync_request(request: &http::Request<Bytes>) -> bool {
+        let path = request.uri().path();
+        path.contains("/_matrix/client/") && path.ends_with("/sync")
+    }
+
+    /// Record a request and its response.
+    ///
+    /// This only sends them to the writer thread, so it doesn't block.
+    pub(crate) fn record(&self, request: &http::Request<Bytes>, response: &http::Response<Bytes>) {
+        let sync = RecordedSync {
+            method: request.method().clone(),
+            uri: request.uri().clone(),
+            request_body: request.body().clone(),
+            status: response.status(),
+            response_body: response.body().clone(),
+        };
+
+        if self.sender.send(sync).is_err() {
+            warn!("Couldn't record the sync response: the writer thread stopped");
+        }
+    }
+
+    /// Stop the recording, and wait until all the recorded syncs are written
+    /// to the file.
+    pub(crate) async fn stop(self) {
+        let Self { sender, writer } = self;
+
+        // The writer thread stops once the channel is closed and empty.
+        drop(sender);
+
+        match tokio::task::spawn_blocking(move || writer.join()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(_)) => warn!("The thread of the sync recorder panicked"),
+            Err(error) => warn!("Couldn't wait for the sync recorder to stop: {error}"),
+        }
+    }
+}
+
+/// Write the syncs received from the given channel to the given file, until
+/// the channel is closed.
+fn write_syncs(mut file: BufWriter<File>, receiver: mpsc::Receiver<RecordedSync>) {
+    for sync in receiver {
+        if let Err(error) = write_sync(&mut file, &sync) {
+            warn!("Couldn't record the sync response: {error}");
+        }
+    }
+}
+
+/// Write a request and its response to the given file.
+fn write_sync(file: &mut BufWriter<File>, sync: &RecordedSync) -> io::Result<()> {
+    let query = sync
+        .uri
+        .query()
+        .map(|query| {
+            url::form_urlencoded::parse(query.as_bytes())
+                .map(|(key, value)| {
+                    let value = if TOKEN_FIELDS.contains(&key.as_ref()) {
+                        REDACTED.to_owned()
+                    } else {
+                        value.into_owned()
+                    };
+                    (key.into_owned(), value)
+                })
+                .collect::<BTreeMap<_, _>>()
+        })
+        .unwrap_or_default();
+
+    write_line(
+        file,
+        &json!({
+            "request": {
+                "method": sync.method.as_str(),
+                "path": sync.uri.path(),
+                "query": query,
+                "body": body_to_json(&sync.request_body),
+            },
+            "response": {
+                "status": sync.status.as_u16(),
+                "body": body_to_json(&sync.response_body),
+            },
+        }),
+    )
+}
+
+fn write_line(file: &mut BufWriter<File>, line: &JsonValue) -> io::Result<()> {
+    serde_json::to_writer(&mut *file, line)?;
+    file.write_all(b"\n")?;
+    file.flush()
+}
+
+/// Parse a body as JSON, with its tokens redacted.
+///
+/// Returns `null` if the body is empty or isn't valid JSON.
+fn body_to_json(body: &[u8]) -> JsonValue {
+    let mut json = serde_json::from_slice(body).unwrap_or(JsonValue::Null);
+    redact_tokens(&mut json);
+    json
+}
+
+/// Replace the values of the fields holding tokens, recursively.
+fn redact_tokens(json: &mut JsonValue) {
+    match json {
+        JsonValue::Object(object) => {
+            for (key, value) in object {
+                if TOKEN_FIELDS.contains(&key.as_str()) {
+                    *value = JsonValue::String(REDACTED.to_owned());
+                } else {
+                    redact_tokens(value);
+                }
+            }
+        }
+        JsonValue::Array(array) => array.iter_mut().for_each(redact_tokens),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::redact_tokens;
+
+    #[test]
+    fn test_redact_tokens() {
+        let mut json = json!({
+            "access_token": "secret",
+            "nested": [{ "refresh_token": "secret", "body": "access_token" }],
+            "next_batch": "s123",
+        });
+
+        redact_tokens(&mut json);
+
+        assert_eq!(
+            json,
+            json!({
+                "access_token": "<redacted>",
+                "nested": [{ "refresh_token": "<redacted>", "body": "access_token" }],
+                "next_batch": "s123",
+            })
+        );
+    }
+}
@@ -0,0 +1,448 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Filtering of the invites, to protect the user from invite spam.
+//!
+//! An [`InviteFilter`] set with [`Client::set_invite_filter()`] is checked
+//! against every invite received by the client. The invites it blocks are
+//! either declined, or quarantined: they stay in the invited state, but
+//! [`Client::is_invite_quarantined()`] returns `true` for them, so they can be
+//! hidden from the room list, and the notifications about them can be
+//! dropped.
+//!
+//! The filter and the quarantined invites are saved in the state store, so
+//! they're restored with the client, and shared with the other processes
+//! using the same store, like a notification service extension. The invites
+//! are forgotten once they're accepted, declined or forgotten.
+
+use std::{
+    collections::BTreeSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLock as StdRwLock,
+    },
+};
+
+use matrix_sdk_base::RoomInfoNotableUpdateReasons;
+use ruma::{
+    events::room::member::{
+        MembershipState, OriginalSyncRoomMemberEvent, StrippedRoomMemberEvent,
+    },
+    OwnedRoomId, OwnedServerName, RoomId, UserId,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{debug, instrument, warn};
+
+use crate::{Client, Result, Room, RoomState};
+
+/// The key of the custom value of the state store holding the filter of the
+/// invites and the quarantined invites.
+const INVITE_FILTER_KEY: &[u8] = b"invite_filter";
+
+/// The rules deciding which invites are blocked.
+///
+/// Nothing is blocked by default.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InviteFilter {
+    /// Block the invites sent by the users ignored by the account.
+    pub block_ignored_users: bool,
+
+    /// Block the invites sent by the users of these servers.
+    pub blocked_servers: BTreeSet<OwnedServerName>,
+
+    /// Block the invites sent by users with whom we don't share a joined
+    /// room.
+    pub block_without_shared_rooms: bool,
+
+    /// Block the invites sent by users, or to rooms, banned by the subscribed
+    /// policy lists.
+    ///
+    /// See [`Client::policy_lists()`].
+    pub block_banned_by_policy: bool,
+
+    /// What to do with the blocked invites.
+    pub action: FilteredInviteAction,
+}
+
+/// What to do with the invites blocked by an [`InviteFilter`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilteredInviteAction {
+    /// Keep the invite, but mark it as quarantined.
+    #[default]
+    Quarantine,
+
+    /// Decline the invite.
+    Decline,
+}
+
+/// Why an invite was blocked by an [`InviteFilter`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InviteFilterReason {
+    /// The inviter is ignored by the account.
+    IgnoredUser,
+
+    /// The server of the inviter is blocked.
+    BlockedServer,
+
+    /// We don't share a joined room with the inviter.
+    NoSharedRooms,
+
+    /// The inviter or the room is banned by a policy list.
+    BannedByPolicy,
+}
+
+/// The state of the invite filter, shared by a client and its notification
+/// clients.
+#[derive(Debug, Default)]
+pub(crate) struct InviteFilterData {
+    /// The filter, if one was set.
+    filter: StdRwLock<Option<InviteFilter>>,
+
+    /// The rooms whose invite is quarantined.
+    quarantined: StdRwLock<BTreeSet<OwnedRoomId>>,
+
+    /// The rooms whose invite was released from the quarantine, and must not
+    /// be quarantined again.
+    released: StdRwLock<BTreeSet<OwnedRoomId>>,
+
+    /// Whether the event handler applying the filter to the new invites was
+    /// registered.
+    handler_registered: AtomicBool,
+
+    /// A lock held while the state is updated and saved, or loaded, so the
+    /// state store always has the latest state.
+    save_lock: Mutex<()>,
+}
+
+/// The state of the invite filter, as saved in the state store.
+#[derive(Default, Serialize, Deserialize)]
+struct SavedInviteFilter {
+    filter: Option<InviteFilter>,
+    #[serde(default)]
+    quarantined: BTreeSet<OwnedRoomId>,
+    #[serde(default)]
+    released: BTreeSet<OwnedRoomId>,
+}
+
+impl Client {
+    /// Set the filter of the invites.
+    ///
+    /// The filter is applied to the invites the client is currently in, and
+    /// then to every new invite. It's saved in the state store, see
+    /// [`Client::restore_invite_filter()`].
+    pub async fn set_invite_filter(&self, filter: InviteFilter) -> Result<()> {
+        // The quarantine is recomputed with the new filter.
+        let mut previously_quarantined = BTreeSet::new();
+        self.update_invite_filter(|data| {
+            *data.filter.write().unwrap() = Some(filter);
+            previously_quarantined = std::mem::take(&mut *data.quarantined.write().unwrap());
+        })
+        .await?;
+
+        self.register_invite_filter_handler();
+
+        for room in self.invited_rooms() {
+            // The inviter is the sender of our own member event, even when their own member
+            // event isn't part of the stripped state.
+            let Some(invitee) = room.get_member_no_sync(room.own_user_id()).await? else {
+                continue;
+            };
+
+            self.apply_invite_filter(&room, invitee.event().sender()).await?;
+        }
+
+        // The invites that aren't quarantined anymore must appear in the room list
+        // again.
+        for room_id in previously_quarantined {
+            if !self.is_invite_quarantined(&room_id) {
+                self.notify_room_list(&room_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The filter of the invites, if one was set.
+    pub fn invite_filter(&self) -> Option<InviteFilter> {
+        self.inner.invite_filter_data.filter.read().unwrap().clone()
+    }
+
+    /// Remove the filter of the invites, and release all the quarantined
+    /// invites.
+    pub async fn remove_invite_filter(&self) -> Result<()> {
+        let mut quarantined = BTreeSet::new();
+        self.update_invite_filter(|data| {
+            data.filter.write().unwrap().take();
+            quarantined = std::mem::take(&mut *data.quarantined.write().unwrap());
+        })
+        .await?;
+
+        for room_id in quarantined {
+            self.notify_room_list(&room_id);
+        }
+
+        Ok(())
+    }
+
+    /// Restore the filter of the invites and the quarantined invites saved in
+    /// the state store, by a previous session or by another process using the
+    /// same store.
+    ///
+    /// This should be called once, after the client is restored, so
+    /// [`Client::is_invite_quarantined()`] knows about the quarantined invites
+    /// and the new invites are filtered. [`Client::check_invite()`] always
+    /// uses the saved state.
+    pub async fn restore_invite_filter(&self) -> Result<()> {
+        self.load_invite_filter().await?;
+
+        // The invites may have been accepted or declined while the client wasn't
+        // running. The rooms that aren't loaded are kept, since they may only be
+        // unknown to this client.
+        self.prune_filtered_invites(|room_id| {
+            self.get_room(room_id).is_some_and(|room| room.state() != RoomState::Invited)
+        })
+        .await?;
+
+        if self.invite_filter().is_some() {
+            self.register_invite_filter_handler();
+        }
+
+        Ok(())
+    }
+
+    /// Check whether the filter of the invites blocks an invite sent by the
+    /// given user to the given room.
+    ///
+    /// The filter is loaded from the state store first, so it's the one set
+    /// by any client using the same store.
+    ///
+    /// Returns why the invite is blocked, or `None` if it's not blocked, if it
+    /// was released from the quarantine, or if no filter was set.
+    pub async fn check_invite(
+        &self,
+        inviter: &UserId,
+        room_id: &RoomId,
+    ) -> Option<InviteFilterReason> {
+        if let Err(error) = self.load_invite_filter().await {
+            warn!(?error, "Couldn't load the invite filter from the state store");
+        }
+
+        let filter = self.invite_filter()?;
+
+        if self.inner.invite_filter_data.released.read().unwrap().contains(room_id) {
+            return None;
+        }
+
+        if filter.block_ignored_users && self.is_user_ignored(inviter).await {
+            return Some(InviteFilterReason::IgnoredUser);
+        }
+
+        if filter.blocked_servers.contains(inviter.server_name()) {
+            return Some(InviteFilterReason::BlockedServer);
+        }
+
+        if filter.block_banned_by_policy
+            && (self.is_banned_by_policy(inviter) || self.is_banned_by_policy(room_id))
+        {
+            return Some(InviteFilterReason::BannedByPolicy);
+        }
+
+        if filter.block_without_shared_rooms && !self.shares_joined_room_with(inviter).await {
+            return Some(InviteFilterReason::NoSharedRooms);
+        }
+
+        None
+    }
+
+    /// Whether the invite to the given room is quarantined by the filter of
+    /// the invites.
+    ///
+    /// The quarantined invites are loaded from the state store by
+    /// [`Client::restore_invite_filter()`] and [`Client::check_invite()`].
+    pub fn is_invite_quarantined(&self, room_id: &RoomId) -> bool {
+        self.inner.invite_filter_data.quarantined.read().unwrap().contains(room_id)
+    }
+
+    /// The rooms whose invite is quarantined by the filter of the invites.
+    pub fn quarantined_invites(&self) -> Vec<OwnedRoomId> {
+        self.inner.invite_filter_data.quarantined.read().unwrap().iter().cloned().collect()
+    }
+
+    /// Release the invite to the given room from the quarantine, e.g. when the
+    /// user wants to see it anyway.
+    ///
+    /// The invite isn't quarantined again.
+    pub async fn release_quarantined_invite(&self, room_id: &RoomId) -> Result<()> {
+        let mut was_quarantined = false;
+        self.update_invite_filter(|data| {
+            data.released.write().unwrap().insert(room_id.to_owned());
+            was_quarantined = data.quarantined.write().unwrap().remove(room_id);
+        })
+        .await?;
+
+        if was_quarantined {
+            self.notify_room_list(room_id);
+        }
+
+        Ok(())
+    }
+
+    /// Apply the filter of the invites to the invite to the given room.
+    #[instrument(skip(self, room), fields(room_id = %room.room_id()))]
+    async fn apply_invite_filter(&self, room: &Room, inviter: &UserId) -> Result<()> {
+        let Some(reason) = self.check_invite(inviter, room.room_id()).await else {
+            return Ok(());
+        };
+
+        let action = self.invite_filter().map(|filter| filter.action).unwrap_or_default();
+        debug!(?reason, ?action, "Blocked an invite");
+
+        match action {
+            FilteredInviteAction::Quarantine => {
+                self.update_invite_filter(|data| {
+                    data.quarantined.write().unwrap().insert(room.room_id().to_owned());
+                })
+                .await?;
+                self.notify_room_list(room.room_id());
+            }
+            FilteredInviteAction::Decline => {
+                if let Err(error) = room.leave().await {
+                    warn!(?error, "Couldn't decline a blocked invite");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Forget about the quarantine or the release of the invite to the given
+    /// room, once the invite was accepted, declined or forgotten.
+    pub(crate) async fn forget_filtered_invite(&self, room_id: &RoomId) -> Result<()> {
+        self.prune_filtered_invites(|filtered_room_id| filtered_room_id == room_id).await
+    }
+
+    /// Remove the quarantined and released invites for which the given
+    /// function returns `true`, so the saved state doesn't grow without bound.
+    ///
+    /// The state is only saved if an invite was removed.
+    async fn prune_filtered_invites(&self, is_stale: impl Fn(&RoomId) -> bool) -> Result<()> {
+        let data = &self.inner.invite_filter_data;
+        let has_stale = data.quarantined.read().unwrap().iter().any(|room_id| is_stale(room_id))
+            || data.released.read().unwrap().iter().any(|room_id| is_stale(room_id));
+
+        if !has_stale {
+            return Ok(());
+        }
+
+        self.update_invite_filter(|data| {
+            data.quarantined.write().unwrap().retain(|room_id| !is_stale(room_id));
+            data.released.write().unwrap().retain(|room_id| !is_stale(room_id));
+        })
+        .await
+    }
+
+    /// Register the event handlers applying the filter to the new invites, and
+    /// forgetting about the invites that were accepted or declined, if they
+    /// weren't registered yet.
+    fn register_invite_filter_handler(&self) {
+        if !self.inner.invite_filter_data.handler_registered.swap(true, Ordering::SeqCst) {
+            self.add_event_handler(on_stripped_member_event);
+            self.add_event_handler(on_member_event);
+        }
+    }
+
+    /// Update the state of the invite filter with the given function, and save
+    /// it in the state store.
+    async fn update_invite_filter(&self, update: impl FnOnce(&InviteFilterData)) -> Result<()> {
+        let data = &self.inner.invite_filter_data;
+        let _guard = data.save_lock.lock().await;
+
+        update(data);
+
+        let saved = SavedInviteFilter {
+            filter: data.filter.read().unwrap().clone(),
+            quarantined: data.quarantined.read().unwrap().clone(),
+            released: data.released.read().unwrap().clone(),
+        };
+        self.state_store().set_custom_value(INVITE_FILTER_KEY, serde_json::to_vec(&saved)?).await?;
+
+        Ok(())
+    }
+
+    /// Load the state of the invite filter saved in the state store.
+    async fn load_invite_filter(&self) -> Result<()> {
+        let data = &self.inner.invite_filter_data;
+        let _guard = data.save_lock.lock().await;
+
+        let Some(bytes) = self.state_store().get_custom_value(INVITE_FILTER_KEY).await? else {
+            return Ok(());
+        };
+        let saved: SavedInviteFilter = serde_json::from_slice(&bytes)?;
+
+        *data.filter.write().unwrap() = saved.filter;
+        *data.quarantined.write().unwrap() = saved.quarantined;
+        *data.released.write().unwrap() = saved.released;
+
+        Ok(())
+    }
+
+    /// Whether we share a joined room with the given user.
+    async fn shares_joined_room_with(&self, user_id: &UserId) -> bool {
+        for room in self.joined_rooms() {
+            if let Ok(Some(member)) = room.get_member_no_sync(user_id).await {
+                if *member.membership() == MembershipState::Join {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Notify the observers of the room list that the quarantine of the given
+    /// room changed, so it's filtered again.
+    fn notify_room_list(&self, room_id: &RoomId) {
+        if let Some(room) = self.get_room(room_id) {
+            room.set_room_info(room.clone_info(), RoomInfoNotableUpdateReasons::MEMBERSHIP);
+        }
+    }
+}
+
+/// Apply the filter of the invites to the new invites.
+async fn on_stripped_member_event(event: StrippedRoomMemberEvent, room: Room, client: Client) {
+    if room.state() != RoomState::Invited
+        || event.state_key != room.own_user_id()
+        || event.content.membership != MembershipState::Invite
+    {
+        return;
+    }
+
+    if let Err(error) = client.apply_invite_filter(&room, &event.sender).await {
+        warn!(?error, "Couldn't apply the invite filter to a new invite");
+    }
+}
+
+/// Forget about the filtered invites that were accepted or declined.
+async fn on_member_event(event: OriginalSyncRoomMemberEvent, room: Room, client: Client) {
+    if event.state_key != room.own_user_id() || event.content.membership == MembershipState::Invite
+    {
+        return;
+    }
+
+    if let Err(error) = client.forget_filtered_invite(room.room_id()).await {
+        warn!(?error, "Couldn't forget a filtered invite");
+    }
+}
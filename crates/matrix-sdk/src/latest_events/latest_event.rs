@@ -21,7 +21,7 @@ use ruma::{
         relation::RelationType,
         room::{
             member::{MembershipState, SyncRoomMemberEvent},
-            message::{MessageType, SyncRoomMessageEvent},
+            message::{MessageType, Relation, SyncRoomMessageEvent},
             power_levels::RoomPowerLevels,
         },
         sticker::SyncStickerEvent,
@@ -145,6 +145,46 @@ impl LatestEventValue {
             .rfind_map_event_in_memory_by(|event| find_and_map(event, power_levels))
             .await
             .unwrap_or_default()
+            .with_latest_edit(room_event_cache)
+            .await
+    }
+
+    /// Apply the latest edit of a room message, as indexed by the event cache,
+    /// if any, and if it was sent by the sender of the message.
+    async fn with_latest_edit(mut self, room_event_cache: &RoomEventCache) -> Self {
+        let Self::RoomMessage(SyncRoomMessageEvent::Original(message)) = &mut self else {
+            return self;
+        };
+
+        let Some(latest_edit) = room_event_cache
+            .find_event_aggregations(std::slice::from_ref(&message.event_id))
+            .await
+            .remove(&message.event_id)
+            .and_then(|aggregations| aggregations.latest_edit)
+        else {
+            return self;
+        };
+
+        let Some(edit) = room_event_cache.find_event(&latest_edit).await else {
+            warn!(%latest_edit, "Failed to find the latest edit of the latest event");
+            return self;
+        };
+
+        if let Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(
+            SyncRoomMessageEvent::Original(edit),
+        ))) = edit.raw().deserialize()
+        {
+            // Only the sender of the message can edit it.
+            if edit.sender != message.sender {
+                return self;
+            }
+
+            if let Some(Relation::Replacement(replacement)) = edit.content.relates_to {
+                message.content.apply_replacement(replacement.new_content);
+            }
+        }
+
+        self
     }
 }
 
@@ -596,4 +636,180 @@ mod tests_non_wasm {
             }
         );
     }
+
+    #[async_test]
+    async fn test_latest_event_value_has_the_latest_edit_from_event_cache() {
+        use matrix_sdk_base::{
+            event_cache::store::EventAggregations,
+            linked_chunk::{ChunkIdentifier, LinkedChunkId, Position, Update},
+            RoomState,
+        };
+        use ruma::events::room::message::RoomMessageEventContentWithoutRelation;
+
+        use crate::{client::WeakClient, room::WeakRoom};
+
+        let room_id = room_id!("!r0");
+        let user_id = user_id!("@mnt_io:matrix.org");
+        let event_factory = EventFactory::new().sender(user_id).room(room_id);
+        let event_id_0 = event_id!("$ev0");
+        let event_id_1 = event_id!("$ev1");
+
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        // Prelude.
+        {
+            // Create the room.
+            client.base_client().get_or_create_room(room_id, RoomState::Joined);
+
+            // Initialise the event cache store, with an edited message.
+            let store = client.event_cache_store().lock().await.unwrap();
+
+            store
+                .handle_linked_chunk_updates(
+                    LinkedChunkId::Room(room_id),
+                    vec![
+                        Update::NewItemsChunk {
+                            previous: None,
+                            new: ChunkIdentifier::new(0),
+                            next: None,
+                        },
+                        Update::PushItems {
+                            at: Position::new(ChunkIdentifier::new(0), 0),
+                            items: vec![
+                                event_factory.text_msg("hello").event_id(event_id_0).into(),
+                                event_factory
+                                    .text_msg("* hello!")
+                                    .edit(
+                                        event_id_0,
+                                        RoomMessageEventContentWithoutRelation::text_plain(
+                                            "hello!",
+                                        ),
+                                    )
+                                    .event_id(event_id_1)
+                                    .into(),
+                            ],
+                        },
+                    ],
+                )
+                .await
+                .unwrap();
+
+            store
+                .save_event_aggregations(
+                    room_id,
+                    event_id_0,
+                    EventAggregations {
+                        latest_edit: Some(event_id_1.to_owned()),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        let event_cache = client.event_cache();
+        event_cache.subscribe().unwrap();
+
+        let (room_event_cache, _) = event_cache.for_room(room_id).await.unwrap();
+        let weak_room = WeakRoom::new(WeakClient::from_client(&client), room_id.to_owned());
+
+        assert_matches!(
+            LatestEventValue::new(room_id, None, &room_event_cache, &weak_room).await,
+            LatestEventValue::RoomMessage(given_event) => {
+                // The edit isn't a candidate, but it's applied to the original event.
+                assert_eq!(given_event.event_id(), event_id_0);
+                assert_eq!(given_event.as_original().unwrap().content.body(), "hello!");
+            }
+        );
+    }
+
+    #[async_test]
+    async fn test_latest_event_value_ignores_an_edit_from_another_sender() {
+        use matrix_sdk_base::{
+            event_cache::store::EventAggregations,
+            linked_chunk::{ChunkIdentifier, LinkedChunkId, Position, Update},
+            RoomState,
+        };
+        use ruma::events::room::message::RoomMessageEventContentWithoutRelation;
+
+        use crate::{client::WeakClient, room::WeakRoom};
+
+        let room_id = room_id!("!r0");
+        let user_id = user_id!("@mnt_io:matrix.org");
+        let event_factory = EventFactory::new().sender(user_id).room(room_id);
+        let event_id_0 = event_id!("$ev0");
+        let event_id_1 = event_id!("$ev1");
+
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        // Prelude.
+        {
+            // Create the room.
+            client.base_client().get_or_create_room(room_id, RoomState::Joined);
+
+            // Initialise the event cache store, with a message edited by another sender;
+            // the edit has been indexed before the message was received.
+            let store = client.event_cache_store().lock().await.unwrap();
+
+            store
+                .handle_linked_chunk_updates(
+                    LinkedChunkId::Room(room_id),
+                    vec![
+                        Update::NewItemsChunk {
+                            previous: None,
+                            new: ChunkIdentifier::new(0),
+                            next: None,
+                        },
+                        Update::PushItems {
+                            at: Position::new(ChunkIdentifier::new(0), 0),
+                            items: vec![
+                                event_factory
+                                    .text_msg("* hijacked")
+                                    .edit(
+                                        event_id_0,
+                                        RoomMessageEventContentWithoutRelation::text_plain(
+                                            "hijacked",
+                                        ),
+                                    )
+                                    .sender(user_id!("@other:matrix.org"))
+                                    .event_id(event_id_1)
+                                    .into(),
+                                event_factory.text_msg("hello").event_id(event_id_0).into(),
+                            ],
+                        },
+                    ],
+                )
+                .await
+                .unwrap();
+
+            store
+                .save_event_aggregations(
+                    room_id,
+                    event_id_0,
+                    EventAggregations {
+                        latest_edit: Some(event_id_1.to_owned()),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        let event_cache = client.event_cache();
+        event_cache.subscribe().unwrap();
+
+        let (room_event_cache, _) = event_cache.for_room(room_id).await.unwrap();
+        let weak_room = WeakRoom::new(WeakClient::from_client(&client), room_id.to_owned());
+
+        assert_matches!(
+            LatestEventValue::new(room_id, None, &room_event_cache, &weak_room).await,
+            LatestEventValue::RoomMessage(given_event) => {
+                // The edit from another sender isn't applied.
+                assert_eq!(given_event.event_id(), event_id_0);
+                assert_eq!(given_event.as_original().unwrap().content.body(), "hello");
+            }
+        );
+    }
 }
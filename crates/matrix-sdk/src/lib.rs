@@ -34,9 +34,12 @@ pub use matrix_sdk_common::*;
 pub use reqwest;
 
 mod account;
+#[cfg(all(feature = "appservice", not(target_family = "wasm")))]
+pub mod appservice;
 pub mod attachment;
 pub mod authentication;
 mod client;
+pub mod command;
 pub mod config;
 mod deduplicating_handler;
 #[cfg(feature = "e2e-encryption")]
@@ -45,12 +48,16 @@ mod error;
 pub mod event_cache;
 pub mod event_handler;
 mod http_client;
+pub mod invite_filter;
 pub mod latest_events;
 pub mod media;
+pub mod moderation;
 pub mod notification_settings;
 pub mod paginators;
+pub mod policy_list;
 pub mod pusher;
 pub mod room;
+pub mod room_creator;
 pub mod room_directory_search;
 pub mod room_preview;
 pub mod send_queue;
@@ -58,7 +65,7 @@ pub mod utils;
 pub mod futures {
     //! Named futures returned from methods on types in [the crate root][crate].
 
-    pub use super::client::futures::SendRequest;
+    pub use super::client::futures::{CompactStores, SendRequest};
 }
 pub mod sliding_sync;
 pub mod sync;
@@ -68,11 +75,12 @@ pub mod widget;
 pub use account::Account;
 pub use authentication::{AuthApi, AuthSession, SessionTokens};
 pub use client::{
-    sanitize_server_name, Client, ClientBuildError, ClientBuilder, LoopCtrl, SessionChange,
+    sanitize_server_name, Client, ClientBuildError, ClientBuilder, CompactionProgress, LoopCtrl,
+    SessionChange, StorageReport, StoreKind,
 };
 pub use error::{
-    Error, HttpError, HttpResult, NotificationSettingsError, RefreshTokenError, Result,
-    RumaApiError,
+    ChangeStorePassphraseError, Error, HttpError, HttpResult, NotificationSettingsError,
+    RefreshTokenError, Result, RumaApiError,
 };
 pub use http_client::TransmissionProgress;
 #[cfg(all(feature = "e2e-encryption", feature = "sqlite"))]
@@ -19,10 +19,17 @@
 use std::io::Read;
 use std::time::Duration;
 #[cfg(not(target_family = "wasm"))]
-use std::{fmt, fs::File, path::Path};
+use std::{
+    fmt,
+    fs::File,
+    future::{Future, IntoFuture},
+    path::{Path, PathBuf},
+};
 
-use eyeball::SharedObservable;
+use eyeball::{SharedObservable, Subscriber};
 use futures_util::future::try_join;
+#[cfg(not(target_family = "wasm"))]
+use http::{header, HeaderMap, HeaderValue, StatusCode};
 use matrix_sdk_base::event_cache::store::media::IgnoreMediaRetentionPolicy;
 pub use matrix_sdk_base::{event_cache::store::media::MediaRetentionPolicy, media::*};
 use mime::Mime;
@@ -36,14 +43,20 @@ use ruma::{
     MilliSecondsSinceUnixEpoch, MxcUri, OwnedMxcUri, TransactionId, UInt,
 };
 #[cfg(not(target_family = "wasm"))]
+use serde::{Deserialize, Serialize};
+#[cfg(not(target_family = "wasm"))]
 use tempfile::{Builder as TempFileBuilder, NamedTempFile, TempDir};
 #[cfg(not(target_family = "wasm"))]
 use tokio::{fs::File as TokioFile, io::AsyncWriteExt};
+#[cfg(not(target_family = "wasm"))]
+use tracing::debug;
 
 use crate::{
     attachment::Thumbnail, client::futures::SendMediaUploadRequest, config::RequestConfig, Client,
     Error, Result, TransmissionProgress,
 };
+#[cfg(not(target_family = "wasm"))]
+use crate::{HttpError, RumaApiError};
 
 /// A conservative upload speed of 1Mbps
 const DEFAULT_UPLOAD_SPEED: u64 = 125_000;
@@ -58,6 +71,19 @@ const MIN_UPLOAD_REQUEST_TIMEOUT: Duration = Duration::from_secs(60 * 5);
 // possible would be coming from the user themselves, which we consider a
 // non-threat.
 const LOCAL_MXC_SERVER_NAME: &str = "send-queue.localhost";
+/// The default timeout of a streaming media download request.
+///
+/// A download that times out can be resumed, so this only needs to be large
+/// enough to make progress on slow connections.
+#[cfg(not(target_family = "wasm"))]
+const MEDIA_DOWNLOAD_REQUEST_TIMEOUT: Duration = Duration::from_secs(60 * 30);
+/// The suffix of the file containing the data of an unfinished download.
+#[cfg(not(target_family = "wasm"))]
+const PARTIAL_DOWNLOAD_SUFFIX: &str = ".part";
+/// The suffix of the file describing the source of the data of an unfinished
+/// download.
+#[cfg(not(target_family = "wasm"))]
+const PARTIAL_DOWNLOAD_SOURCE_SUFFIX: &str = ".part.source";
 
 /// A high-level API to interact with the media API.
 #[derive(Debug, Clone)]
@@ -66,6 +92,18 @@ pub struct Media {
     client: Client,
 }
 
+/// The source of the data of an unfinished download, saved next to it so the
+/// download is only resumed with the same content.
+#[cfg(not(target_family = "wasm"))]
+#[derive(Serialize, Deserialize)]
+struct PartialDownloadSource {
+    /// The request of the media being downloaded.
+    request: MediaRequestParameters,
+    /// The strong `ETag` of the content returned by the server, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+}
+
 /// A file handle that takes ownership of a media file on disk. When the handle
 /// is dropped, the file will be removed from the disk.
 #[derive(Debug)]
@@ -118,6 +156,68 @@ impl fmt::Display for PersistError {
     }
 }
 
+/// `IntoFuture` returned by [`Media::download_to_file()`].
+#[cfg(not(target_family = "wasm"))]
+#[allow(missing_debug_implementations)]
+pub struct DownloadMediaFile {
+    media: Media,
+    request: MediaRequestParameters,
+    destination: PathBuf,
+    request_config: Option<RequestConfig>,
+    progress: SharedObservable<TransmissionProgress>,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl DownloadMediaFile {
+    /// Replace the default `SharedObservable` used for tracking the download
+    /// progress.
+    ///
+    /// Note that any subscribers obtained from
+    /// [`subscribe_to_progress`][Self::subscribe_to_progress] will be
+    /// invalidated by this.
+    pub fn with_progress_observable(
+        mut self,
+        progress: SharedObservable<TransmissionProgress>,
+    ) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Use the given [`RequestConfig`] for the download request, instead of the
+    /// one provided by default.
+    ///
+    /// The timeout of the config applies to each step of the download: to
+    /// receive the response headers, and then to receive each chunk of the
+    /// content. A large media can take longer than the timeout to download, as
+    /// long as the server keeps sending data.
+    pub fn with_request_config(mut self, request_config: impl Into<Option<RequestConfig>>) -> Self {
+        self.request_config = request_config.into();
+        self
+    }
+
+    /// Get a subscriber to observe the progress of the download.
+    ///
+    /// When a download is resumed, the progress starts at the size of the data
+    /// that was already downloaded.
+    pub fn subscribe_to_progress(&self) -> Subscriber<TransmissionProgress> {
+        self.progress.subscribe()
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl IntoFuture for DownloadMediaFile {
+    type Output = Result<()>;
+    matrix_sdk_common::boxed_into_future!();
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { media, request, destination, request_config, progress } = self;
+
+        Box::pin(async move {
+            media.download_to_file_inner(&request, &destination, request_config, progress).await
+        })
+    }
+}
+
 /// A preallocated MXC URI created by [`Media::create_content_uri()`], and
 /// to be used with [`Media::upload_preallocated()`].
 #[derive(Debug)]
@@ -128,6 +228,15 @@ pub struct PreallocatedMxcUri {
     expire_date: Option<MilliSecondsSinceUnixEpoch>,
 }
 
+impl PreallocatedMxcUri {
+    /// Rebuilds a preallocated MXC URI from a URI previously returned by
+    /// [`Media::create_content_uri()`], whose expiration date isn't known
+    /// anymore.
+    pub(crate) fn from_uri(uri: OwnedMxcUri) -> Self {
+        Self { uri, expire_date: None }
+    }
+}
+
 /// An error that happened in the realm of media.
 #[derive(Debug, thiserror::Error)]
 pub enum MediaError {
@@ -158,6 +267,10 @@ pub enum MediaError {
     /// Fetching the `max_upload_size` value from the homeserver failed.
     #[error("Fetching the `max_upload_size` value from the homeserver failed: {0}")]
     FetchMaxUploadSizeFailed(String),
+
+    /// The media is encrypted, but the `e2e-encryption` feature is disabled.
+    #[error("the media is encrypted, but the `e2e-encryption` feature is disabled")]
+    EncryptionNotSupported,
 }
 
 impl Media {
@@ -273,6 +386,19 @@ impl Media {
         uri: PreallocatedMxcUri,
         content_type: &Mime,
         data: Vec<u8>,
+    ) -> Result<()> {
+        self.upload_preallocated_inner(uri, content_type, data, None, None).await
+    }
+
+    /// Same as [`Self::upload_preallocated`], with an optional request config
+    /// and an optional observable to report the upload progress.
+    pub(crate) async fn upload_preallocated_inner(
+        &self,
+        uri: PreallocatedMxcUri,
+        content_type: &Mime,
+        data: Vec<u8>,
+        request_config: Option<RequestConfig>,
+        send_progress: Option<SharedObservable<TransmissionProgress>>,
     ) -> Result<()> {
         // Do a best-effort at reporting an expired MXC URI here; otherwise the server
         // may complain about it later.
@@ -291,9 +417,15 @@ impl Media {
             content_type: Some(content_type.as_ref().to_owned()),
         });
 
-        let request_config = self.client.request_config().timeout(timeout);
+        let request_config =
+            request_config.unwrap_or_else(|| self.client.request_config()).timeout(timeout);
+
+        let mut fut = self.client.send(request).with_request_config(request_config);
+        if let Some(send_progress) = send_progress {
+            fut = fut.with_send_progress_observable(send_progress);
+        }
 
-        if let Err(err) = self.client.send(request).with_request_config(request_config).await {
+        if let Err(err) = fut.await {
             match err.client_api_error_kind() {
                 Some(ErrorKind::CannotOverwriteMedia) => {
                     Err(Error::Media(MediaError::CannotOverwriteMedia))
@@ -434,19 +566,7 @@ impl Media {
             }
         }
 
-        // Use the authenticated endpoints when the server supports Matrix 1.11 or the
-        // authenticated media stable feature.
-        let (use_auth, request_config) =
-            if self.client.server_versions().await?.contains(&MatrixVersion::V1_11) {
-                (true, None)
-            } else if self.client.unstable_features().await?.contains(&FeatureFlag::Msc3916Stable) {
-                // We need to force the use of the stable endpoint with the Matrix version
-                // because Ruma does not handle stable features.
-                let request_config = self.client.request_config();
-                (true, Some(request_config.force_matrix_version(MatrixVersion::V1_11)))
-            } else {
-                (false, None)
-            };
+        let (use_auth, request_config) = self.authenticated_media_config().await?;
 
         let content: Vec<u8> = match &request.source {
             MediaSource::Encrypted(file) => {
@@ -532,6 +652,362 @@ impl Media {
         Ok(content)
     }
 
+    /// Download a media file straight to the given destination on disk.
+    ///
+    /// Contrary to [`Media::get_media_file()`], the content is never fully
+    /// loaded in memory: it is written to disk chunk by chunk as it is
+    /// received, which makes this method suitable for large files. If the
+    /// content is encrypted, it is decrypted on the fly, and only moved to
+    /// the destination once its hash has been verified.
+    ///
+    /// While the download is ongoing, the data is stored next to the
+    /// destination, in a file with the same name and a `.part` suffix. If the
+    /// download is interrupted, for example because the returned future was
+    /// dropped or the connection was lost, calling this method again with
+    /// the same request and destination resumes it from where it stopped,
+    /// using an HTTP `Range` request. The request and the `ETag` of the
+    /// content are saved in a file with a `.part.source` suffix, so the
+    /// download starts over if the partial data comes from another media, or
+    /// if the content changed on the server. It also starts over if the server
+    /// doesn't support range requests.
+    ///
+    /// The media cache is neither read nor updated by this method.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The `MediaRequest` of the content.
+    ///
+    /// * `destination` - The path of the file where the media should be
+    ///   written. It is replaced if it already exists.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, media::{MediaFormat, MediaRequestParameters}};
+    /// # use matrix_sdk::ruma::events::room::MediaSource;
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// # let source: MediaSource = todo!();
+    /// let request = MediaRequestParameters { source, format: MediaFormat::File };
+    ///
+    /// let download = client.media().download_to_file(&request, "/home/example/video.mp4");
+    /// let mut progress = download.subscribe_to_progress();
+    ///
+    /// tokio::spawn(async move {
+    ///     while let Some(progress) = progress.next().await {
+    ///         println!("Downloaded {} of {} bytes", progress.current, progress.total);
+    ///     }
+    /// });
+    ///
+    /// download.await?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[cfg(not(target_family = "wasm"))]
+    pub fn download_to_file(
+        &self,
+        request: &MediaRequestParameters,
+        destination: impl Into<PathBuf>,
+    ) -> DownloadMediaFile {
+        DownloadMediaFile {
+            media: self.clone(),
+            request: request.clone(),
+            destination: destination.into(),
+            request_config: None,
+            progress: Default::default(),
+        }
+    }
+
+    /// Implementation of [`Media::download_to_file()`].
+    #[cfg(not(target_family = "wasm"))]
+    async fn download_to_file_inner(
+        &self,
+        request: &MediaRequestParameters,
+        destination: &Path,
+        request_config: Option<RequestConfig>,
+        progress: SharedObservable<TransmissionProgress>,
+    ) -> Result<()> {
+        // Local medias only live in the media cache, they are small enough to be
+        // copied at once.
+        if let Some(uri) = Self::as_local_uri(&request.source) {
+            let content = self.get_local_media_content(uri).await?;
+            tokio::fs::write(destination, &content).await?;
+            progress.set(TransmissionProgress { current: content.len(), total: content.len() });
+            return Ok(());
+        }
+
+        let (use_auth, auth_request_config) = self.authenticated_media_config().await?;
+
+        let mut request_config = request_config.unwrap_or_else(|| {
+            self.client.request_config().timeout(MEDIA_DOWNLOAD_REQUEST_TIMEOUT)
+        });
+        if let Some(version) = auth_request_config.and_then(|config| config.force_matrix_version) {
+            request_config = request_config.force_matrix_version(version);
+        }
+
+        // The content is decrypted as it's received, so the partial file only contains
+        // decrypted data.
+        #[cfg(feature = "e2e-encryption")]
+        let mut decryptor = match &request.source {
+            MediaSource::Encrypted(file) => {
+                Some(matrix_sdk_base::crypto::StreamingAttachmentDecryptor::new(
+                    file.as_ref().clone().into(),
+                )?)
+            }
+            MediaSource::Plain(_) => None,
+        };
+
+        #[cfg(not(feature = "e2e-encryption"))]
+        if matches!(request.source, MediaSource::Encrypted(_)) {
+            return Err(Error::Media(MediaError::EncryptionNotSupported));
+        }
+
+        let partial_path = Self::partial_download_path(destination, PARTIAL_DOWNLOAD_SUFFIX);
+        let source_path = Self::partial_download_path(destination, PARTIAL_DOWNLOAD_SOURCE_SUFFIX);
+
+        let mut file =
+            tokio::fs::OpenOptions::new().create(true).append(true).open(&partial_path).await?;
+        let mut offset = file.metadata().await?.len();
+
+        // The partial data can only be completed with the content of the same media.
+        let source = tokio::fs::read(&source_path)
+            .await
+            .ok()
+            .and_then(|source| serde_json::from_slice::<PartialDownloadSource>(&source).ok());
+        let etag = match source {
+            Some(source) if source.request.unique_key() == request.unique_key() => source.etag,
+            _ => {
+                if offset > 0 {
+                    debug!("Partial download comes from another media, restarting download");
+                    file.set_len(0).await?;
+                    offset = 0;
+                }
+                None
+            }
+        };
+
+        let mut response = match with_download_timeout(
+            request_config.timeout,
+            self.send_streaming_media_request(
+                request,
+                use_auth,
+                request_config,
+                offset,
+                etag.as_deref(),
+            ),
+        )
+        .await
+        {
+            Ok(response) => response,
+            Err(error) if offset > 0 && is_range_not_satisfiable(&error) => {
+                // The partial file is not a prefix of the content, start over.
+                debug!("Partial download doesn't match the media, restarting download");
+                file.set_len(0).await?;
+                offset = 0;
+                with_download_timeout(
+                    request_config.timeout,
+                    self.send_streaming_media_request(request, use_auth, request_config, 0, None),
+                )
+                .await?
+            }
+            Err(error) => return Err(error),
+        };
+
+        if offset > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+            // The server ignored the range, or the content changed since the partial
+            // download, it's sending the whole content again.
+            debug!("Server doesn't resume the content, restarting download");
+            file.set_len(0).await?;
+            offset = 0;
+        }
+
+        if offset == 0 {
+            // Remember where the data comes from, to be able to resume the download.
+            let source = PartialDownloadSource {
+                request: request.clone(),
+                etag: strong_etag(response.headers()),
+            };
+            tokio::fs::write(&source_path, serde_json::to_vec(&source)?).await?;
+        }
+
+        if offset > 0 {
+            debug!(offset, "Resuming media download");
+
+            // The decryption continues after the data that was already decrypted.
+            #[cfg(feature = "e2e-encryption")]
+            if let Some(decryptor) = &mut decryptor {
+                use tokio::io::AsyncReadExt;
+
+                let mut partial_file = TokioFile::open(&partial_path).await?;
+                let mut buffer = vec![0; 64 * 1024];
+
+                loop {
+                    let read_bytes = partial_file.read(&mut buffer).await?;
+                    if read_bytes == 0 {
+                        break;
+                    }
+                    decryptor.skip_decrypted_chunk(&buffer[..read_bytes]);
+                }
+            }
+        }
+
+        let total = content_range_total(response.headers())
+            .or_else(|| response.content_length().map(|length| length + offset))
+            .unwrap_or_default();
+        progress.set(TransmissionProgress {
+            current: usize::try_from(offset).unwrap_or(usize::MAX),
+            total: usize::try_from(total).unwrap_or(usize::MAX),
+        });
+
+        // The timeout applies to each chunk, since the duration of the whole download
+        // depends on the size of the media.
+        while let Some(chunk) = with_download_timeout(request_config.timeout, async {
+            Ok(response.chunk().await.map_err(HttpError::from)?)
+        })
+        .await?
+        {
+            let chunk_len = chunk.len();
+
+            #[cfg(feature = "e2e-encryption")]
+            let chunk = match &mut decryptor {
+                Some(decryptor) => {
+                    let mut chunk = chunk.to_vec();
+                    decryptor.decrypt_chunk(&mut chunk);
+                    chunk.into()
+                }
+                None => chunk,
+            };
+
+            file.write_all(&chunk).await?;
+            progress.update(|progress| progress.current += chunk_len);
+        }
+
+        // Make sure the content is on disk before using it.
+        file.sync_all().await?;
+        drop(file);
+
+        #[cfg(feature = "e2e-encryption")]
+        if let Some(decryptor) = decryptor {
+            if let Err(error) = decryptor.finish() {
+                // The data is corrupted, the download must start over.
+                tokio::fs::remove_file(&partial_path).await?;
+                tokio::fs::remove_file(&source_path).await?;
+                return Err(error.into());
+            }
+        }
+
+        tokio::fs::rename(&partial_path, destination).await?;
+        tokio::fs::remove_file(&source_path).await?;
+        Ok(())
+    }
+
+    /// Send the request to download the given media, without buffering the
+    /// response.
+    ///
+    /// If `offset` is not 0, only the content starting at this offset is
+    /// requested. If an `etag` is provided, the whole content is sent instead
+    /// if it doesn't match the current content anymore.
+    #[cfg(not(target_family = "wasm"))]
+    async fn send_streaming_media_request(
+        &self,
+        request: &MediaRequestParameters,
+        use_auth: bool,
+        request_config: RequestConfig,
+        offset: u64,
+        etag: Option<&str>,
+    ) -> Result<reqwest::Response> {
+        let mut headers = HeaderMap::new();
+        if offset > 0 {
+            let range = HeaderValue::try_from(format!("bytes={offset}-"))
+                .expect("a range made of an integer is a valid header value");
+            headers.insert(header::RANGE, range);
+
+            if let Some(etag) = etag.and_then(|etag| HeaderValue::try_from(etag).ok()) {
+                headers.insert(header::IF_RANGE, etag);
+            }
+        }
+
+        let config = Some(request_config);
+
+        let uri = match &request.source {
+            MediaSource::Plain(uri) => uri,
+            MediaSource::Encrypted(file) => &file.url,
+        };
+
+        // Encrypted thumbnails are stored as separate files, only plain media can be
+        // thumbnailed by the server.
+        let thumbnail_settings = match (&request.source, &request.format) {
+            (MediaSource::Plain(_), MediaFormat::Thumbnail(settings)) => Some(settings),
+            _ => None,
+        };
+
+        let response = if let Some(settings) = thumbnail_settings {
+            if use_auth {
+                let mut request =
+                    authenticated_media::get_content_thumbnail::v1::Request::from_uri(
+                        uri,
+                        settings.width,
+                        settings.height,
+                    )?;
+                request.method = Some(settings.method.clone());
+                request.animated = Some(settings.animated);
+
+                self.client.send_streaming(request, config, headers).await?
+            } else {
+                #[allow(deprecated)]
+                let request = {
+                    let mut request = media::get_content_thumbnail::v3::Request::from_url(
+                        uri,
+                        settings.width,
+                        settings.height,
+                    )?;
+                    request.method = Some(settings.method.clone());
+                    request.animated = Some(settings.animated);
+                    request
+                };
+
+                self.client.send_streaming(request, config, headers).await?
+            }
+        } else if use_auth {
+            let request = authenticated_media::get_content::v1::Request::from_uri(uri)?;
+            self.client.send_streaming(request, config, headers).await?
+        } else {
+            #[allow(deprecated)]
+            let request = media::get_content::v3::Request::from_url(uri)?;
+            self.client.send_streaming(request, config, headers).await?
+        };
+
+        Ok(response)
+    }
+
+    /// The path of a file related to an unfinished download to the given
+    /// destination, with the given suffix.
+    #[cfg(not(target_family = "wasm"))]
+    fn partial_download_path(destination: &Path, suffix: &str) -> PathBuf {
+        let mut file_name = destination.file_name().unwrap_or_default().to_owned();
+        file_name.push(suffix);
+        destination.with_file_name(file_name)
+    }
+
+    /// Whether the authenticated media endpoints should be used, along with
+    /// the request config required to use them, if any.
+    ///
+    /// The authenticated endpoints are used when the server supports Matrix
+    /// 1.11 or the authenticated media stable feature.
+    async fn authenticated_media_config(&self) -> Result<(bool, Option<RequestConfig>)> {
+        if self.client.server_versions().await?.contains(&MatrixVersion::V1_11) {
+            Ok((true, None))
+        } else if self.client.unstable_features().await?.contains(&FeatureFlag::Msc3916Stable) {
+            // We need to force the use of the stable endpoint with the Matrix version
+            // because Ruma does not handle stable features.
+            let request_config = self.client.request_config();
+            Ok((true, Some(request_config.force_matrix_version(MatrixVersion::V1_11))))
+        } else {
+            Ok((false, None))
+        }
+    }
+
     /// Get a media file's content that is only available in the media cache.
     ///
     /// # Arguments
@@ -806,6 +1282,53 @@ impl Media {
     }
 }
 
+/// Whether the given error is a `416 Range Not Satisfiable` response.
+#[cfg(not(target_family = "wasm"))]
+fn is_range_not_satisfiable(error: &Error) -> bool {
+    let Error::Http(error) = error else {
+        return false;
+    };
+
+    let status_code = match error.as_ruma_api_error() {
+        Some(RumaApiError::ClientApi(error)) => error.status_code,
+        Some(RumaApiError::Other(error)) => error.status_code,
+        _ => return false,
+    };
+
+    status_code == StatusCode::RANGE_NOT_SATISFIABLE
+}
+
+/// Get the complete length of the content from the `Content-Range` header of a
+/// `206 Partial Content` response, if any.
+#[cfg(not(target_family = "wasm"))]
+fn content_range_total(headers: &HeaderMap) -> Option<u64> {
+    // The header looks like `bytes 200-999/1000`, or `bytes 200-999/*` if the
+    // length is unknown.
+    let content_range = headers.get(header::CONTENT_RANGE)?.to_str().ok()?;
+    let (_, total) = content_range.strip_prefix("bytes ")?.split_once('/')?;
+    total.parse().ok()
+}
+
+/// Get the strong `ETag` of the content from the headers of a response, which
+/// is the only kind that can be used in an `If-Range` header.
+#[cfg(not(target_family = "wasm"))]
+fn strong_etag(headers: &HeaderMap) -> Option<String> {
+    let etag = headers.get(header::ETAG)?.to_str().ok()?;
+    (!etag.starts_with("W/")).then(|| etag.to_owned())
+}
+
+/// Run a step of a streaming download, failing if it takes longer than the
+/// given timeout.
+#[cfg(not(target_family = "wasm"))]
+async fn with_download_timeout<T>(
+    timeout: Duration,
+    step: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    matrix_sdk_common::timeout::timeout(step, timeout).await.map_err(|_| {
+        Error::Io(std::io::Error::new(std::io::ErrorKind::TimedOut, "the media download timed out"))
+    })?
+}
+
 #[cfg(test)]
 mod tests {
     use assert_matches2::assert_matches;
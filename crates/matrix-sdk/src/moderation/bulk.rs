@@ -0,0 +1,432 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bulk moderation actions: removing all the recent events of a user from a
+//! room, and banning or kicking a user from all our rooms.
+
+use std::{collections::HashSet, future::IntoFuture, time::Duration};
+
+use eyeball::{SharedObservable, Subscriber};
+use matrix_sdk_base::deserialized_responses::TimelineEvent;
+use matrix_sdk_common::boxed_into_future;
+use ruma::{
+    api::client::{error::ErrorKind, redact::redact_event},
+    assign,
+    events::room::member::MembershipState,
+    MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedUserId, TransactionId, UserId,
+};
+use serde::Deserialize;
+use tracing::{debug, instrument, warn, Instrument, Span};
+
+use super::Moderation;
+use crate::{
+    config::RequestConfig, error::RetryKind, sleep::sleep, Error, HttpError, Result, Room,
+};
+
+/// The number of events requested by each back-pagination, when looking for
+/// the events to redact.
+const PAGINATION_BATCH_SIZE: u16 = 50;
+
+/// The number of times a redaction is retried after being rate-limited.
+const MAX_RATE_LIMITED_RETRIES: u32 = 5;
+
+/// The delay before retrying a rate-limited redaction, when the homeserver
+/// doesn't say how long to wait. It's doubled after each attempt.
+const DEFAULT_RATE_LIMIT_DELAY: Duration = Duration::from_secs(1);
+
+/// The progress of a [`RedactEventsBy`] operation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RedactionProgress {
+    /// The events of the user are being searched, by paginating backwards.
+    Searching {
+        /// The number of events to redact found so far.
+        found: usize,
+    },
+
+    /// The events are being redacted.
+    Redacting {
+        /// The number of events that were processed, successfully or not.
+        done: usize,
+        /// The total number of events to redact.
+        total: usize,
+    },
+
+    /// The homeserver rate-limited the redactions, which are paused.
+    RateLimited {
+        /// The number of events that were processed, successfully or not.
+        done: usize,
+        /// The total number of events to redact.
+        total: usize,
+        /// How long the redactions are paused.
+        retry_after: Duration,
+    },
+
+    /// All the events were processed.
+    Done {
+        /// The number of redacted events.
+        redacted: usize,
+        /// The number of events that couldn't be redacted.
+        failed: usize,
+    },
+}
+
+/// The outcome of a [`RedactEventsBy`] operation.
+#[derive(Debug)]
+pub struct RedactionOutcome {
+    /// The IDs of the redacted events.
+    pub redacted: Vec<OwnedEventId>,
+    /// The events that couldn't be redacted, with the error of their last
+    /// attempt.
+    pub failed: Vec<(OwnedEventId, HttpError)>,
+}
+
+/// The fields of an event needed to decide whether it must be redacted.
+#[derive(Deserialize)]
+struct EventToRedact {
+    event_id: OwnedEventId,
+    sender: OwnedUserId,
+    origin_server_ts: MilliSecondsSinceUnixEpoch,
+    #[serde(rename = "type")]
+    event_type: String,
+    state_key: Option<String>,
+    #[serde(default)]
+    unsigned: EventToRedactUnsigned,
+}
+
+#[derive(Default, Deserialize)]
+struct EventToRedactUnsigned {
+    redacted_because: Option<serde::de::IgnoredAny>,
+}
+
+/// The events to redact, collected from the event cache.
+struct EventsToRedact<'a> {
+    user_id: &'a UserId,
+    since: MilliSecondsSinceUnixEpoch,
+    event_ids: Vec<OwnedEventId>,
+    seen: HashSet<OwnedEventId>,
+    /// The timestamp of the oldest event seen, from any sender.
+    oldest: Option<MilliSecondsSinceUnixEpoch>,
+}
+
+impl<'a> EventsToRedact<'a> {
+    fn new(user_id: &'a UserId, since: MilliSecondsSinceUnixEpoch) -> Self {
+        Self { user_id, since, event_ids: Vec::new(), seen: HashSet::new(), oldest: None }
+    }
+
+    fn collect(&mut self, events: impl IntoIterator<Item = TimelineEvent>) {
+        for event in events {
+            let Ok(event) = event.raw().deserialize_as::<EventToRedact>() else {
+                continue;
+            };
+
+            if self.oldest.is_none_or(|oldest| event.origin_server_ts < oldest) {
+                self.oldest = Some(event.origin_server_ts);
+            }
+
+            // The state events are kept, so the state of the room isn't reset, as well as
+            // the events that are already redacted and the redactions themselves.
+            let must_redact = event.sender == self.user_id
+                && event.origin_server_ts >= self.since
+                && event.state_key.is_none()
+                && event.unsigned.redacted_because.is_none()
+                && event.event_type != "m.room.redaction";
+
+            if must_redact && self.seen.insert(event.event_id.clone()) {
+                self.event_ids.push(event.event_id);
+            }
+        }
+    }
+
+    /// Whether the events older than `since` were reached.
+    fn reached_since(&self) -> bool {
+        self.oldest.is_some_and(|oldest| oldest < self.since)
+    }
+}
+
+impl Room {
+    /// Redact all the events sent by the given user in this room since the
+    /// given time, e.g. to remove the messages of a spammer.
+    ///
+    /// The events are found in the event cache, which is paginated backwards
+    /// until it reaches events older than `since`, the start of the room, or
+    /// an empty batch of events, so the event cache must be
+    /// subscribed to, see [`EventCache::subscribe()`]. The state events of the
+    /// user, like their membership, are kept.
+    ///
+    /// The events are redacted one at a time, pausing when the homeserver
+    /// rate-limits the redactions. The progress can be followed with
+    /// [`RedactEventsBy::subscribe_to_progress()`].
+    ///
+    /// [`EventCache::subscribe()`]: crate::event_cache::EventCache::subscribe
+    pub fn redact_events_by<'a>(
+        &'a self,
+        user_id: &'a UserId,
+        since: MilliSecondsSinceUnixEpoch,
+    ) -> RedactEventsBy<'a> {
+        RedactEventsBy {
+            room: self,
+            user_id,
+            since,
+            reason: None,
+            progress: SharedObservable::new(RedactionProgress::Searching { found: 0 }),
+            tracing_span: Span::current(),
+        }
+    }
+}
+
+/// Future returned by [`Room::redact_events_by()`].
+#[derive(Debug)]
+pub struct RedactEventsBy<'a> {
+    room: &'a Room,
+    user_id: &'a UserId,
+    since: MilliSecondsSinceUnixEpoch,
+    reason: Option<String>,
+    progress: SharedObservable<RedactionProgress>,
+    tracing_span: Span,
+}
+
+impl RedactEventsBy<'_> {
+    /// Set the reason of the redactions.
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    /// Subscribe to the progress of the redactions.
+    pub fn subscribe_to_progress(&self) -> Subscriber<RedactionProgress> {
+        self.progress.subscribe()
+    }
+}
+
+impl<'a> IntoFuture for RedactEventsBy<'a> {
+    type Output = Result<RedactionOutcome>;
+    boxed_into_future!(extra_bounds: 'a);
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { room, user_id, since, reason, progress, tracing_span } = self;
+
+        let future = async move {
+            let (event_cache, _drop_handles) = room.event_cache().await?;
+
+            let mut events = EventsToRedact::new(user_id, since);
+            events.collect(event_cache.events().await);
+
+            let pagination = event_cache.pagination();
+            while !events.reached_since() {
+                progress.set(RedactionProgress::Searching { found: events.event_ids.len() });
+
+                let outcome = pagination.run_backwards_once(PAGINATION_BATCH_SIZE).await?;
+
+                // Stop at the start of the room, or if the homeserver doesn't return any
+                // event, so we don't keep paginating for nothing.
+                let reached_end = outcome.reached_start || outcome.events.is_empty();
+                events.collect(outcome.events);
+
+                if reached_end {
+                    break;
+                }
+            }
+
+            let total = events.event_ids.len();
+            debug!(total, "Found the events to redact");
+
+            let mut outcome = RedactionOutcome { redacted: Vec::new(), failed: Vec::new() };
+
+            for (done, event_id) in events.event_ids.into_iter().enumerate() {
+                progress.set(RedactionProgress::Redacting { done, total });
+
+                match redact_with_rate_limit(room, &event_id, reason.as_deref(), |retry_after| {
+                    progress.set(RedactionProgress::RateLimited { done, total, retry_after });
+                })
+                .await
+                {
+                    Ok(()) => outcome.redacted.push(event_id),
+                    Err(error) => {
+                        warn!(%event_id, ?error, "Couldn't redact an event");
+                        outcome.failed.push((event_id, error));
+                    }
+                }
+            }
+
+            progress.set(RedactionProgress::Done {
+                redacted: outcome.redacted.len(),
+                failed: outcome.failed.len(),
+            });
+
+            Ok(outcome)
+        };
+
+        Box::pin(future.instrument(tracing_span))
+    }
+}
+
+/// Redact an event, waiting and retrying when the homeserver rate-limits the
+/// request.
+///
+/// The automatic retries of the HTTP client are disabled, so the caller is
+/// told about the pauses with `on_rate_limited`.
+async fn redact_with_rate_limit(
+    room: &Room,
+    event_id: &OwnedEventId,
+    reason: Option<&str>,
+    on_rate_limited: impl Fn(Duration),
+) -> Result<(), HttpError> {
+    // The same transaction ID is used for the retries, so a redaction isn't sent
+    // twice.
+    let txn_id = TransactionId::new();
+    let mut default_delay = DEFAULT_RATE_LIMIT_DELAY;
+    let mut attempts = 0;
+
+    loop {
+        let request = assign!(
+            redact_event::v3::Request::new(room.room_id().to_owned(), event_id.clone(), txn_id.clone()),
+            { reason: reason.map(ToOwned::to_owned) }
+        );
+
+        let Err(error) = room
+            .client
+            .send(request)
+            .with_request_config(RequestConfig::new().disable_retry())
+            .await
+        else {
+            return Ok(());
+        };
+
+        let is_rate_limited =
+            matches!(error.client_api_error_kind(), Some(ErrorKind::LimitExceeded { .. }))
+                || error
+                    .as_client_api_error()
+                    .is_some_and(|error| error.status_code.as_u16() == 429);
+
+        if !is_rate_limited || attempts >= MAX_RATE_LIMITED_RETRIES {
+            return Err(error);
+        }
+
+        let retry_after = match error.retry_kind() {
+            RetryKind::Transient { retry_after: Some(retry_after) } => retry_after,
+            _ => {
+                let delay = default_delay;
+                default_delay *= 2;
+                delay
+            }
+        };
+
+        debug!(?retry_after, "The redactions are rate-limited");
+        on_rate_limited(retry_after);
+        sleep(retry_after).await;
+
+        attempts += 1;
+    }
+}
+
+/// The outcome of a ban or a kick of a user from all our rooms.
+#[derive(Debug, Default)]
+pub struct BulkModerationOutcome {
+    /// The rooms the user was banned or kicked from.
+    pub succeeded: Vec<OwnedRoomId>,
+    /// The rooms where we don't have the power level to ban or kick the user.
+    pub skipped: Vec<OwnedRoomId>,
+    /// The rooms where the ban or the kick failed, with its error.
+    pub failed: Vec<(OwnedRoomId, Error)>,
+}
+
+/// A moderation action applied to a user in all our rooms.
+#[derive(Clone, Copy, Debug)]
+enum BulkAction {
+    Ban,
+    Kick,
+}
+
+impl Moderation {
+    /// Ban the given user from all the joined rooms where we have the power
+    /// level to do it, including the rooms they aren't in, to prevent them
+    /// from joining.
+    #[instrument(skip(self))]
+    pub async fn ban_user_from_all_rooms(
+        &self,
+        user_id: &UserId,
+        reason: Option<&str>,
+    ) -> Result<BulkModerationOutcome> {
+        self.apply_to_all_rooms(BulkAction::Ban, user_id, reason).await
+    }
+
+    /// Kick the given user from all the joined rooms they are in, or invited
+    /// to, where we have the power level to do it.
+    #[instrument(skip(self))]
+    pub async fn kick_user_from_all_rooms(
+        &self,
+        user_id: &UserId,
+        reason: Option<&str>,
+    ) -> Result<BulkModerationOutcome> {
+        self.apply_to_all_rooms(BulkAction::Kick, user_id, reason).await
+    }
+
+    async fn apply_to_all_rooms(
+        &self,
+        action: BulkAction,
+        user_id: &UserId,
+        reason: Option<&str>,
+    ) -> Result<BulkModerationOutcome> {
+        let mut outcome = BulkModerationOutcome::default();
+
+        for room in self.client.joined_rooms() {
+            let own_user_id = room.own_user_id();
+            let power_levels = room.power_levels_or_default().await;
+
+            let result = match action {
+                BulkAction::Ban => {
+                    let is_banned = room
+                        .get_member_no_sync(user_id)
+                        .await?
+                        .is_some_and(|member| *member.membership() == MembershipState::Ban);
+                    if is_banned {
+                        continue;
+                    }
+
+                    if !power_levels.user_can_ban_user(own_user_id, user_id) {
+                        outcome.skipped.push(room.room_id().to_owned());
+                        continue;
+                    }
+
+                    room.ban_user(user_id, reason).await
+                }
+                BulkAction::Kick => {
+                    let is_member = room.get_member_no_sync(user_id).await?.is_some_and(|member| {
+                        matches!(member.membership(), MembershipState::Join | MembershipState::Invite)
+                    });
+                    if !is_member {
+                        continue;
+                    }
+
+                    if !power_levels.user_can_kick_user(own_user_id, user_id) {
+                        outcome.skipped.push(room.room_id().to_owned());
+                        continue;
+                    }
+
+                    room.kick_user(user_id, reason).await
+                }
+            };
+
+            match result {
+                Ok(()) => outcome.succeeded.push(room.room_id().to_owned()),
+                Err(error) => {
+                    warn!(room_id = %room.room_id(), ?action, ?error, "Couldn't moderate the user");
+                    outcome.failed.push((room.room_id().to_owned(), error));
+                }
+            }
+        }
+
+        Ok(outcome)
+    }
+}
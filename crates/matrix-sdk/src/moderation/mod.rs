@@ -0,0 +1,497 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reports of events, rooms and users to the administrators of the
+//! homeserver.
+//!
+//! The [`Moderation`] API sends the reports with a structured
+//! [`ReportReason`], keeps a local record of them in the state store, and can
+//! ignore the reported user or leave the reported room afterwards, see
+//! [`ReportOptions`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! use matrix_sdk::{
+//!     moderation::{ReportCategory, ReportOptions, ReportReason},
+//!     ruma::user_id,
+//!     Client,
+//! };
+//!
+//! # async fn example(client: Client) -> matrix_sdk::Result<()> {
+//! client
+//!     .moderation()
+//!     .report_user(
+//!         user_id!("@spammer:example.org"),
+//!         ReportReason::new(ReportCategory::Spam)
+//!             .with_details("Sends ads in DMs"),
+//!         ReportOptions { ignore_user: true, ..Default::default() },
+//!     )
+//!     .await?;
+//! # Ok(()) }
+//! ```
+
+use std::fmt;
+
+use ruma::{
+    api::client::{reporting::report_user, room::report_room},
+    events::direct::DirectUserIdentifier,
+    EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UserId,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, warn};
+
+use crate::{room::ReportedContentScore, Client, Result, Room, RoomState};
+
+mod bulk;
+
+pub use self::bulk::{BulkModerationOutcome, RedactEventsBy, RedactionOutcome, RedactionProgress};
+
+/// The key of the records of the reports in the custom values of the state
+/// store.
+const REPORTS_KEY: &[u8] = b"moderation_reports";
+
+/// The maximum number of reports kept in the local records. The oldest ones
+/// are forgotten first.
+const MAX_REPORTS: usize = 500;
+
+/// The category of a report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportCategory {
+    /// Unsolicited messages, like advertising or scams.
+    Spam,
+    /// Harassment, bullying or threats.
+    Harassment,
+    /// Hateful or violent content.
+    Abuse,
+    /// Content that is illegal.
+    IllegalContent,
+    /// Someone pretending to be someone else.
+    Impersonation,
+    /// Another category.
+    Other,
+}
+
+impl ReportCategory {
+    /// The identifier of this category, used in the reason sent to the
+    /// homeserver.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Spam => "spam",
+            Self::Harassment => "harassment",
+            Self::Abuse => "abuse",
+            Self::IllegalContent => "illegal_content",
+            Self::Impersonation => "impersonation",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for ReportCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The reason of a report, made of an optional category and optional details.
+///
+/// The homeserver APIs only accept a free-form reason, so it's sent as
+/// `[category] details`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportReason {
+    /// The category of the report.
+    pub category: Option<ReportCategory>,
+    /// The details of the report, written by the user.
+    pub details: Option<String>,
+}
+
+impl ReportReason {
+    /// Create a `ReportReason` with the given category and no details.
+    pub fn new(category: ReportCategory) -> Self {
+        Self { category: Some(category), details: None }
+    }
+
+    /// Set the details of the report.
+    pub fn with_details(mut self, details: impl Into<String>) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+
+    /// The free-form reason sent to the homeserver, if there is one.
+    pub fn to_reason_string(&self) -> Option<String> {
+        let details = self.details.as_deref().map(str::trim).filter(|details| !details.is_empty());
+
+        match (&self.category, details) {
+            (Some(category), Some(details)) => Some(format!("[{category}] {details}")),
+            (Some(category), None) => Some(format!("[{category}]")),
+            (None, Some(details)) => Some(details.to_owned()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// What to do after a report.
+///
+/// Nothing is done by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReportOptions {
+    /// The score of a reported event, from `0`, inoffensive, to `-100`, very
+    /// offensive.
+    ///
+    /// It's only used for the reports of events.
+    pub score: Option<ReportedContentScore>,
+
+    /// Ignore the reported user, or the sender of the reported event.
+    pub ignore_user: bool,
+
+    /// Leave the reported room, the room of the reported event, or the direct
+    /// rooms with the reported user.
+    pub leave_room: bool,
+}
+
+/// What was reported.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReportTarget {
+    /// An event.
+    Event {
+        /// The room of the event.
+        room_id: OwnedRoomId,
+        /// The ID of the event.
+        event_id: OwnedEventId,
+        /// The sender of the event, if it could be loaded.
+        sender: Option<OwnedUserId>,
+    },
+    /// A room.
+    Room {
+        /// The ID of the room.
+        room_id: OwnedRoomId,
+    },
+    /// A user.
+    User {
+        /// The ID of the user.
+        user_id: OwnedUserId,
+    },
+}
+
+/// The local record of a report.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportRecord {
+    /// What was reported.
+    pub target: ReportTarget,
+    /// The reason of the report.
+    pub reason: ReportReason,
+    /// When the report was sent.
+    pub reported_at: MilliSecondsSinceUnixEpoch,
+    /// The user that was ignored after the report.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignored_user: Option<OwnedUserId>,
+    /// The rooms that were left after the report.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub left_rooms: Vec<OwnedRoomId>,
+}
+
+impl ReportRecord {
+    /// Whether this report is about the given user, either reported directly
+    /// or as the sender of a reported event.
+    pub fn concerns_user(&self, user_id: &UserId) -> bool {
+        match &self.target {
+            ReportTarget::Event { sender, .. } => sender.as_deref() == Some(user_id),
+            ReportTarget::Room { .. } => false,
+            ReportTarget::User { user_id: reported } => reported == user_id,
+        }
+    }
+}
+
+impl Client {
+    /// Get the [`Moderation`] API of this client, to report events, rooms and
+    /// users.
+    pub fn moderation(&self) -> Moderation {
+        Moderation { client: self.clone() }
+    }
+}
+
+/// The API to report events, rooms and users to the administrators of the
+/// homeserver.
+///
+/// Get one with [`Client::moderation()`].
+#[derive(Debug, Clone)]
+pub struct Moderation {
+    client: Client,
+}
+
+impl Moderation {
+    /// Report an event of the given room.
+    ///
+    /// The room must be joined. See [`Room::report_content()`].
+    #[instrument(skip(self, room, reason, options), fields(room_id = %room.room_id()))]
+    pub async fn report_event(
+        &self,
+        room: &Room,
+        event_id: &EventId,
+        reason: ReportReason,
+        options: ReportOptions,
+    ) -> Result<ReportRecord> {
+        room.report_content(event_id.to_owned(), options.score, reason.to_reason_string()).await?;
+
+        // The sender is only needed for the record and to ignore them.
+        let sender = match room.load_or_fetch_event(event_id, None).await {
+            Ok(event) => event.raw().get_field::<OwnedUserId>("sender").ok().flatten(),
+            Err(error) => {
+                warn!(?error, "Couldn't load the reported event");
+                None
+            }
+        };
+
+        let mut record = ReportRecord {
+            target: ReportTarget::Event {
+                room_id: room.room_id().to_owned(),
+                event_id: event_id.to_owned(),
+                sender: sender.clone(),
+            },
+            reason,
+            reported_at: MilliSecondsSinceUnixEpoch::now(),
+            ignored_user: None,
+            left_rooms: Vec::new(),
+        };
+
+        if options.ignore_user {
+            if let Some(sender) = sender {
+                self.ignore_user(&mut record, &sender).await;
+            }
+        }
+
+        if options.leave_room {
+            self.leave_room(&mut record, room).await;
+        }
+
+        self.save_report(record.clone()).await?;
+
+        Ok(record)
+    }
+
+    /// Report a room.
+    ///
+    /// The room doesn't need to be joined. See [`Room::report_room()`].
+    #[instrument(skip(self, reason, options))]
+    pub async fn report_room(
+        &self,
+        room_id: &RoomId,
+        reason: ReportReason,
+        options: ReportOptions,
+    ) -> Result<ReportRecord> {
+        let mut request = report_room::v3::Request::new(room_id.to_owned());
+        request.reason = reason.to_reason_string();
+        self.client.send(request).await?;
+
+        let mut record = ReportRecord {
+            target: ReportTarget::Room { room_id: room_id.to_owned() },
+            reason,
+            reported_at: MilliSecondsSinceUnixEpoch::now(),
+            ignored_user: None,
+            left_rooms: Vec::new(),
+        };
+
+        if options.leave_room {
+            if let Some(room) = self.client.get_room(room_id) {
+                self.leave_room(&mut record, &room).await;
+            }
+        }
+
+        self.save_report(record.clone()).await?;
+
+        Ok(record)
+    }
+
+    /// Report a user, as defined in [MSC4260].
+    ///
+    /// [MSC4260]: https://github.com/matrix-org/matrix-spec-proposals/pull/4260
+    #[instrument(skip(self, reason, options))]
+    pub async fn report_user(
+        &self,
+        user_id: &UserId,
+        reason: ReportReason,
+        options: ReportOptions,
+    ) -> Result<ReportRecord> {
+        let request = report_user::v3::Request::new(
+            user_id.to_owned(),
+            reason.to_reason_string().unwrap_or_default(),
+        );
+        self.client.send(request).await?;
+
+        let mut record = ReportRecord {
+            target: ReportTarget::User { user_id: user_id.to_owned() },
+            reason,
+            reported_at: MilliSecondsSinceUnixEpoch::now(),
+            ignored_user: None,
+            left_rooms: Vec::new(),
+        };
+
+        if options.ignore_user {
+            self.ignore_user(&mut record, user_id).await;
+        }
+
+        if options.leave_room {
+            let direct_rooms = self.client.joined_rooms().into_iter().filter(|room| {
+                let targets = room.direct_targets();
+                targets.len() == 1 && targets.contains(<&DirectUserIdentifier>::from(user_id))
+            });
+
+            for room in direct_rooms {
+                self.leave_room(&mut record, &room).await;
+            }
+        }
+
+        self.save_report(record.clone()).await?;
+
+        Ok(record)
+    }
+
+    /// The local records of the reports sent by this client, from the oldest to
+    /// the newest.
+    ///
+    /// Only the most recent 500 reports are kept.
+    pub async fn reports(&self) -> Result<Vec<ReportRecord>> {
+        let Some(bytes) = self.client.state_store().get_custom_value(REPORTS_KEY).await? else {
+            return Ok(Vec::new());
+        };
+
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Whether the given user was reported, directly or as the sender of a
+    /// reported event.
+    pub async fn has_reported_user(&self, user_id: &UserId) -> Result<bool> {
+        Ok(self.reports().await?.iter().any(|record| record.concerns_user(user_id)))
+    }
+
+    /// Forget the local records of the reports.
+    pub async fn clear_reports(&self) -> Result<()> {
+        let _guard = self.client.locks().moderation_reports_lock.lock().await;
+        self.client.state_store().remove_custom_value(REPORTS_KEY).await?;
+        Ok(())
+    }
+
+    /// Add a record to the local records of the reports.
+    async fn save_report(&self, record: ReportRecord) -> Result<()> {
+        let _guard = self.client.locks().moderation_reports_lock.lock().await;
+
+        let mut records = self.reports().await?;
+        push_report(&mut records, record);
+
+        self.client
+            .state_store()
+            .set_custom_value(REPORTS_KEY, serde_json::to_vec(&records)?)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Ignore the given user after a report.
+    ///
+    /// A failure doesn't fail the report, which was already sent.
+    async fn ignore_user(&self, record: &mut ReportRecord, user_id: &UserId) {
+        match self.client.account().ignore_user(user_id).await {
+            Ok(()) => record.ignored_user = Some(user_id.to_owned()),
+            Err(error) => warn!(?error, %user_id, "Couldn't ignore the reported user"),
+        }
+    }
+
+    /// Leave the given room after a report.
+    ///
+    /// A failure doesn't fail the report, which was already sent.
+    async fn leave_room(&self, record: &mut ReportRecord, room: &Room) {
+        if !matches!(room.state(), RoomState::Joined | RoomState::Invited | RoomState::Knocked) {
+            return;
+        }
+
+        match room.leave().await {
+            Ok(()) => record.left_rooms.push(room.room_id().to_owned()),
+            Err(error) => {
+                warn!(?error, room_id = %room.room_id(), "Couldn't leave the reported room")
+            }
+        }
+    }
+}
+
+/// Add a record to the given records, forgetting the oldest ones if there are
+/// more than [`MAX_REPORTS`].
+fn push_report(records: &mut Vec<ReportRecord>, record: ReportRecord) {
+    records.push(record);
+
+    if records.len() > MAX_REPORTS {
+        records.drain(..records.len() - MAX_REPORTS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{owned_user_id, MilliSecondsSinceUnixEpoch, UInt};
+
+    use super::{
+        push_report, ReportCategory, ReportReason, ReportRecord, ReportTarget, MAX_REPORTS,
+    };
+
+    #[test]
+    fn test_report_reason_string() {
+        assert_eq!(ReportReason::default().to_reason_string(), None);
+        assert_eq!(
+            ReportReason::new(ReportCategory::Spam).to_reason_string().as_deref(),
+            Some("[spam]")
+        );
+        assert_eq!(
+            ReportReason::new(ReportCategory::IllegalContent)
+                .with_details(" Selling stolen cards ")
+                .to_reason_string()
+                .as_deref(),
+            Some("[illegal_content] Selling stolen cards")
+        );
+        assert_eq!(
+            ReportReason { category: None, details: Some("Rude".to_owned()) }
+                .to_reason_string()
+                .as_deref(),
+            Some("Rude")
+        );
+        assert_eq!(
+            ReportReason { category: None, details: Some("  ".to_owned()) }.to_reason_string(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_push_report_prunes_oldest_records() {
+        let record = |ts: usize| ReportRecord {
+            target: ReportTarget::User { user_id: owned_user_id!("@spammer:example.org") },
+            reason: ReportReason::new(ReportCategory::Spam),
+            reported_at: MilliSecondsSinceUnixEpoch(UInt::new(ts as u64).unwrap()),
+            ignored_user: None,
+            left_rooms: Vec::new(),
+        };
+
+        let mut records = Vec::new();
+        for ts in 0..MAX_REPORTS {
+            push_report(&mut records, record(ts));
+        }
+        assert_eq!(records.len(), MAX_REPORTS);
+        assert_eq!(records[0], record(0));
+
+        // Once the limit is reached, the oldest records are forgotten.
+        push_report(&mut records, record(MAX_REPORTS));
+        push_report(&mut records, record(MAX_REPORTS + 1));
+        assert_eq!(records.len(), MAX_REPORTS);
+        assert_eq!(records[0], record(2));
+        assert_eq!(records.last(), Some(&record(MAX_REPORTS + 1)));
+    }
+}
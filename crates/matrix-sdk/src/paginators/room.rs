@@ -95,6 +95,12 @@ pub struct StartFromResult {
 
     /// Whether the /context query returned a next batch token.
     pub has_next: bool,
+
+    /// The token to paginate backwards from the first event, if any.
+    pub prev_batch_token: Option<String>,
+
+    /// The token to paginate forwards from the last event, if any.
+    pub next_batch_token: Option<String>,
 }
 
 /// Reset the state to a given target on drop.
@@ -185,17 +191,7 @@ impl<PR: PaginableRoom> Paginator<PR> {
         let has_prev = response.prev_batch_token.is_some();
         let has_next = response.next_batch_token.is_some();
 
-        {
-            let mut tokens = self.tokens.lock().unwrap();
-            tokens.previous = match response.prev_batch_token {
-                Some(token) => PaginationToken::HasMore(token),
-                None => PaginationToken::HitEnd,
-            };
-            tokens.next = match response.next_batch_token {
-                Some(token) => PaginationToken::HasMore(token),
-                None => PaginationToken::HitEnd,
-            };
-        }
+        self.set_tokens(response.prev_batch_token.clone(), response.next_batch_token.clone());
 
         // Forget the reset state guard, so its Drop method is not called.
         reset_state_guard.disarm();
@@ -216,7 +212,48 @@ impl<PR: PaginableRoom> Paginator<PR> {
             .chain(response.events_after)
             .collect();
 
-        Ok(StartFromResult { events, has_prev, has_next })
+        Ok(StartFromResult {
+            events,
+            has_prev,
+            has_next,
+            prev_batch_token: response.prev_batch_token,
+            next_batch_token: response.next_batch_token,
+        })
+    }
+
+    /// Starts the pagination from events that have been retrieved beforehand,
+    /// e.g. from a cache, given the tokens to paginate backward and forward
+    /// from them.
+    ///
+    /// A missing token means that the corresponding end of the timeline has
+    /// been reached.
+    ///
+    /// Only works for fresh [`Paginator`] objects, which are in the
+    /// [`PaginatorState::Initial`] state.
+    pub fn start_from_tokens(
+        &self,
+        prev_batch_token: Option<String>,
+        next_batch_token: Option<String>,
+    ) -> Result<(), PaginatorError> {
+        self.check_state(PaginatorState::Initial)?;
+
+        self.set_tokens(prev_batch_token, next_batch_token);
+        self.state.set(PaginatorState::Idle);
+
+        Ok(())
+    }
+
+    /// Set the tokens used for the subsequent paginations.
+    fn set_tokens(&self, prev_batch_token: Option<String>, next_batch_token: Option<String>) {
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.previous = match prev_batch_token {
+            Some(token) => PaginationToken::HasMore(token),
+            None => PaginationToken::HitEnd,
+        };
+        tokens.next = match next_batch_token {
+            Some(token) => PaginationToken::HasMore(token),
+            None => PaginationToken::HitEnd,
+        };
     }
 
     /// Runs a backward pagination (requesting `num_events` to the server), from
@@ -522,6 +559,7 @@ mod tests {
                 prev_batch_token: self.prev_batch_token.lock().await.clone(),
                 next_batch_token: self.next_batch_token.lock().await.clone(),
                 state: Vec::new(),
+                is_stale: false,
             })
         }
 
@@ -644,6 +682,38 @@ mod tests {
         assert_event_matches_msg(&context.events[10], "fetch_from");
     }
 
+    #[async_test]
+    async fn test_start_from_tokens() {
+        // Prepare test data.
+        let room = TestRoom::new(false, *ROOM_ID, *USER_ID);
+        let event_factory = &room.event_factory;
+
+        // When I start the paginator from known tokens, no request is sent,
+        let paginator = Arc::new(Paginator::new(room.clone()));
+        paginator
+            .start_from_tokens(Some("prev".to_owned()), None)
+            .expect("start_from_tokens should work");
+
+        assert_eq!(paginator.state().get(), PaginatorState::Idle);
+        assert!(!paginator.hit_timeline_start());
+        assert!(paginator.hit_timeline_end());
+
+        // And I can't start it a second time.
+        assert_let!(
+            Err(PaginatorError::InvalidPreviousState { .. }) =
+                paginator.start_from_tokens(None, None)
+        );
+
+        // Then I can back-paginate from the previous token.
+        *room.prev_events.lock().await = vec![event_factory.text_msg("previous").into_event()];
+
+        let prev =
+            paginator.paginate_backward(uint!(100)).await.expect("paginate backward should work");
+        assert!(prev.hit_end_of_timeline);
+        assert_eq!(prev.events.len(), 1);
+        assert_event_matches_msg(&prev.events[0], "previous");
+    }
+
     #[async_test]
     async fn test_paginate_backward() {
         // Prepare test data.
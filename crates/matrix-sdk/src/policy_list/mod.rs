@@ -0,0 +1,635 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Moderation policy lists, as defined in [MSC2313].
+//!
+//! A policy list is a room whose `m.policy.rule.user`, `m.policy.rule.room`
+//! and `m.policy.rule.server` state events are rules recommending an action
+//! against the entities matching their glob, like banning `@spam*:example.org`.
+//!
+//! The [`PolicyListManager`] keeps the rules of the subscribed policy lists
+//! up to date with the sync, matches the users, rooms and servers against
+//! them, and can enforce the ban recommendations, see [`PolicyEnforcement`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! use matrix_sdk::{
+//!     policy_list::PolicyEnforcement,
+//!     ruma::{room_id, user_id},
+//!     Client,
+//! };
+//!
+//! # async fn example(client: Client) -> matrix_sdk::Result<()> {
+//! let policy_lists = client.policy_lists();
+//! policy_lists.subscribe(room_id!("!policies:example.org")).await?;
+//! policy_lists.set_enforcement(PolicyEnforcement {
+//!     decline_invites: true,
+//!     ..Default::default()
+//! });
+//!
+//! if client.is_banned_by_policy(user_id!("@spammer:example.org")) {
+//!     println!("Don't talk to this user");
+//! }
+//! # Ok(()) }
+//! ```
+//!
+//! [MSC2313]: https://github.com/matrix-org/matrix-spec-proposals/pull/2313
+
+use std::{
+    collections::BTreeSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLock as StdRwLock,
+    },
+};
+
+use ruma::{
+    events::{
+        policy::rule::{
+            room::PolicyRuleRoomEventContent, server::PolicyRuleServerEventContent,
+            user::PolicyRuleUserEventContent,
+        },
+        room::member::{MembershipState, OriginalSyncRoomMemberEvent, StrippedRoomMemberEvent},
+        AnySyncMessageLikeEvent, SyncStateEvent,
+    },
+    serde::Raw,
+    OwnedRoomId, OwnedUserId, RoomId, ServerName, UserId,
+};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tracing::{debug, instrument, warn};
+use wildmatch::WildMatch;
+
+use crate::{
+    deserialized_responses::RawAnySyncOrStrippedState, Client, Error, Result, Room, RoomState,
+};
+
+mod rules;
+
+pub use self::rules::{PolicyEntityType, PolicyRule};
+use self::rules::{PolicyRuleStateEvent, PolicyRules};
+
+/// The key of the custom value of the state store holding the IDs of the
+/// subscribed policy rooms.
+const SUBSCRIPTIONS_KEY: &[u8] = b"policy_list_subscriptions";
+
+/// An entity that can be matched against the rules of the policy lists.
+#[derive(Clone, Copy, Debug)]
+pub enum PolicyEntity<'a> {
+    /// A user, matched against the user rules and the server rules of their
+    /// server.
+    User(&'a UserId),
+    /// A room, matched against the room rules.
+    Room(&'a RoomId),
+    /// A server, matched against the server rules.
+    Server(&'a ServerName),
+}
+
+impl<'a> From<&'a UserId> for PolicyEntity<'a> {
+    fn from(user_id: &'a UserId) -> Self {
+        Self::User(user_id)
+    }
+}
+
+impl<'a> From<&'a RoomId> for PolicyEntity<'a> {
+    fn from(room_id: &'a RoomId) -> Self {
+        Self::Room(room_id)
+    }
+}
+
+impl<'a> From<&'a ServerName> for PolicyEntity<'a> {
+    fn from(server_name: &'a ServerName) -> Self {
+        Self::Server(server_name)
+    }
+}
+
+/// How the ban recommendations of the policy lists are enforced.
+///
+/// Nothing is enforced by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PolicyEnforcement {
+    /// Decline the invites sent by banned users, or to banned rooms.
+    pub decline_invites: bool,
+
+    /// Add the banned users to the ignored users of the account when they
+    /// invite us or send a message in one of our rooms, which hides their
+    /// events.
+    pub ignore_users: bool,
+
+    /// Ban the banned users from the rooms where we have the power level to
+    /// ban them, when they join them or when a new rule matches them.
+    pub apply_bans: bool,
+}
+
+/// The outcome of [`PolicyListManager::apply_bans()`].
+#[derive(Debug, Default)]
+pub struct AppliedBans {
+    /// The users who were banned, with the rooms they were banned from.
+    pub banned: Vec<(OwnedRoomId, OwnedUserId)>,
+
+    /// The users who couldn't be banned, with the rooms they should have
+    /// been banned from and the error.
+    pub failed: Vec<(OwnedRoomId, OwnedUserId, Error)>,
+}
+
+/// The state of the policy lists, shared by a client and its notification
+/// clients.
+#[derive(Debug, Default)]
+pub(crate) struct PolicyListData {
+    /// The rules of the subscribed policy lists.
+    rules: StdRwLock<PolicyRules>,
+
+    /// The IDs of the subscribed policy rooms.
+    subscriptions: StdRwLock<BTreeSet<OwnedRoomId>>,
+
+    /// A lock to save the subscriptions in the state store one at a time.
+    subscriptions_save_lock: Mutex<()>,
+
+    /// How the rules are enforced.
+    enforcement: StdRwLock<PolicyEnforcement>,
+
+    /// The banned users who were ignored, or are being ignored, by the
+    /// enforcement of the rules, so they are ignored only once.
+    ignored_users: StdRwLock<BTreeSet<OwnedUserId>>,
+
+    /// Whether the event handlers keeping the rules up to date and enforcing
+    /// them were registered.
+    handlers_registered: AtomicBool,
+}
+
+impl Client {
+    /// Get the [`PolicyListManager`] of this client, to subscribe to policy
+    /// lists.
+    pub fn policy_lists(&self) -> PolicyListManager {
+        PolicyListManager { client: self.clone() }
+    }
+
+    /// Whether the given user, room or server is banned by a rule of the
+    /// subscribed policy lists.
+    ///
+    /// See [`PolicyListManager::matching_rule()`] to get the rule.
+    pub fn is_banned_by_policy<'a>(&self, entity: impl Into<PolicyEntity<'a>>) -> bool {
+        self.policy_lists().matching_rule(entity).is_some()
+    }
+}
+
+/// The manager of the moderation policy lists of a client.
+///
+/// The subscriptions are saved in the state store, and restored with
+/// [`PolicyListManager::restore_subscriptions()`] when the client is
+/// restarted. The rules are kept in memory, and loaded from the state of the
+/// policy rooms.
+///
+/// Get one with [`Client::policy_lists()`].
+#[derive(Debug, Clone)]
+pub struct PolicyListManager {
+    client: Client,
+}
+
+impl PolicyListManager {
+    fn data(&self) -> &PolicyListData {
+        &self.client.inner.policy_list_data
+    }
+
+    /// Subscribe to the policy list in the given room.
+    ///
+    /// The current rules are loaded from the state of the room, and the rules
+    /// are then updated with the sync. The client must be in the room, or
+    /// join it, to receive its state.
+    #[instrument(skip(self))]
+    pub async fn subscribe(&self, room_id: &RoomId) -> Result<()> {
+        self.register_handlers();
+
+        if !self.data().subscriptions.write().unwrap().insert(room_id.to_owned()) {
+            return Ok(());
+        }
+
+        self.save_subscriptions().await?;
+        self.load_rules(room_id).await
+    }
+
+    /// Restore the subscriptions saved in the state store by a previous
+    /// session, and load their rules from the state of the policy rooms.
+    ///
+    /// This should be called once, after the client is restored.
+    pub async fn restore_subscriptions(&self) -> Result<()> {
+        let Some(bytes) = self.client.state_store().get_custom_value(SUBSCRIPTIONS_KEY).await?
+        else {
+            return Ok(());
+        };
+
+        let room_ids: BTreeSet<OwnedRoomId> = serde_json::from_slice(&bytes)?;
+        if room_ids.is_empty() {
+            return Ok(());
+        }
+
+        self.register_handlers();
+        self.data().subscriptions.write().unwrap().extend(room_ids.iter().cloned());
+
+        for room_id in room_ids {
+            self.load_rules(&room_id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Load the current rules of the given policy room from its state.
+    async fn load_rules(&self, room_id: &RoomId) -> Result<()> {
+        let Some(room) = self.client.get_room(room_id) else {
+            debug!("The policy room isn't known yet, its rules will come with the sync");
+            return Ok(());
+        };
+
+        for entity_type in
+            [PolicyEntityType::User, PolicyEntityType::Room, PolicyEntityType::Server]
+        {
+            for raw_event in room.get_state_events(entity_type.event_type()).await? {
+                let event = match &raw_event {
+                    RawAnySyncOrStrippedState::Sync(raw) => raw.deserialize_as(),
+                    RawAnySyncOrStrippedState::Stripped(raw) => raw.deserialize_as(),
+                };
+
+                match event {
+                    Ok(event) => {
+                        self.update_rule(room_id, entity_type, event);
+                    }
+                    Err(error) => warn!(?error, "Couldn't deserialize a policy rule"),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Unsubscribe from the policy list in the given room, and forget its
+    /// rules.
+    pub async fn unsubscribe(&self, room_id: &RoomId) -> Result<()> {
+        if !self.data().subscriptions.write().unwrap().remove(room_id) {
+            return Ok(());
+        }
+
+        self.data().rules.write().unwrap().remove_room(room_id);
+        self.save_subscriptions().await
+    }
+
+    /// Save the current subscriptions in the state store.
+    async fn save_subscriptions(&self) -> Result<()> {
+        let _guard = self.data().subscriptions_save_lock.lock().await;
+
+        let bytes = serde_json::to_vec(&*self.data().subscriptions.read().unwrap())?;
+        self.client.state_store().set_custom_value(SUBSCRIPTIONS_KEY, bytes).await?;
+
+        Ok(())
+    }
+
+    /// The IDs of the subscribed policy rooms.
+    pub fn subscriptions(&self) -> Vec<OwnedRoomId> {
+        self.data().subscriptions.read().unwrap().iter().cloned().collect()
+    }
+
+    /// All the rules of the subscribed policy lists.
+    pub fn rules(&self) -> Vec<PolicyRule> {
+        self.data().rules.read().unwrap().all()
+    }
+
+    /// Set how the ban recommendations of the policy lists are enforced.
+    pub fn set_enforcement(&self, enforcement: PolicyEnforcement) {
+        *self.data().enforcement.write().unwrap() = enforcement;
+        self.register_handlers();
+    }
+
+    /// How the ban recommendations of the policy lists are enforced.
+    pub fn enforcement(&self) -> PolicyEnforcement {
+        *self.data().enforcement.read().unwrap()
+    }
+
+    /// Get the ban rule matching the given user, room or server, if any.
+    ///
+    /// The users are also banned by the rules matching their server.
+    pub fn matching_rule<'a>(&self, entity: impl Into<PolicyEntity<'a>>) -> Option<PolicyRule> {
+        let rules = self.data().rules.read().unwrap();
+
+        match entity.into() {
+            PolicyEntity::User(user_id) => {
+                rules.find_ban(PolicyEntityType::User, user_id.as_str()).or_else(|| {
+                    rules.find_ban(PolicyEntityType::Server, user_id.server_name().as_str())
+                })
+            }
+            PolicyEntity::Room(room_id) => rules.find_ban(PolicyEntityType::Room, room_id.as_str()),
+            PolicyEntity::Server(server_name) => {
+                rules.find_ban(PolicyEntityType::Server, server_name.as_str())
+            }
+        }
+        .cloned()
+    }
+
+    /// Ban the members of our rooms who are banned by the policy lists, in
+    /// the rooms where we have the power level to ban them.
+    ///
+    /// The policy rooms themselves are left untouched. A user who can't be
+    /// banned from a room doesn't prevent the other bans: the returned
+    /// [`AppliedBans`] lists both the successful bans and the failures.
+    pub async fn apply_bans(&self) -> AppliedBans {
+        self.ban_members(None, |user_id| self.matching_rule(user_id)).await
+    }
+
+    /// Ban the members of our rooms for which the given function returns a
+    /// rule.
+    ///
+    /// If the rule can only match a single user, only their membership is
+    /// checked in each room, instead of all the members.
+    async fn ban_members(
+        &self,
+        single_user: Option<&UserId>,
+        matching_rule: impl Fn(&UserId) -> Option<PolicyRule>,
+    ) -> AppliedBans {
+        let mut applied = AppliedBans::default();
+
+        for room in self.client.joined_rooms() {
+            if self.is_subscribed(room.room_id()) {
+                continue;
+            }
+
+            let members = match single_user {
+                Some(user_id) => room.get_member_no_sync(user_id).await.map(|member| {
+                    member
+                        .filter(|member| *member.membership() == MembershipState::Join)
+                        .map(|member| member.user_id().to_owned())
+                        .into_iter()
+                        .collect()
+                }),
+                None => room.joined_user_ids().await.map_err(Error::from),
+            };
+
+            let members = match members {
+                Ok(members) => members,
+                Err(error) => {
+                    warn!(
+                        room_id = %room.room_id(), ?error,
+                        "Couldn't load the members of a room to ban"
+                    );
+                    continue;
+                }
+            };
+
+            let banned_members = members
+                .into_iter()
+                .filter_map(|user_id| matching_rule(&user_id).map(|rule| (user_id, rule)))
+                .collect::<Vec<_>>();
+
+            // Only the rooms with banned members are affected.
+            if banned_members.is_empty() {
+                continue;
+            }
+
+            let own_user_id = room.own_user_id();
+            let power_levels = room.power_levels_or_default().await;
+
+            for (user_id, rule) in banned_members {
+                if !power_levels.user_can_ban_user(own_user_id, &user_id) {
+                    continue;
+                }
+
+                let reason = (!rule.reason.is_empty()).then_some(rule.reason.as_str());
+                match room.ban_user(&user_id, reason).await {
+                    Ok(()) => applied.banned.push((room.room_id().to_owned(), user_id)),
+                    Err(error) => {
+                        warn!(
+                            room_id = %room.room_id(), ?error,
+                            "Couldn't ban a user banned by a policy"
+                        );
+                        applied.failed.push((room.room_id().to_owned(), user_id, error));
+                    }
+                }
+            }
+        }
+
+        applied
+    }
+
+    fn is_subscribed(&self, room_id: &RoomId) -> bool {
+        self.data().subscriptions.read().unwrap().contains(room_id)
+    }
+
+    /// Update a rule from its state event.
+    ///
+    /// Returns the new rule, if the event contains a valid rule.
+    fn update_rule(
+        &self,
+        policy_room_id: &RoomId,
+        entity_type: PolicyEntityType,
+        event: PolicyRuleStateEvent,
+    ) -> Option<PolicyRule> {
+        self.data().rules.write().unwrap().update(
+            policy_room_id,
+            entity_type,
+            event.state_key,
+            event.content,
+        )
+    }
+
+    /// Register the event handlers keeping the rules up to date and enforcing
+    /// them, once.
+    fn register_handlers(&self) {
+        if self.data().handlers_registered.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let client = &self.client;
+
+        client.add_event_handler(
+            |raw: Raw<SyncStateEvent<PolicyRuleUserEventContent>>, room: Room| async move {
+                on_policy_rule_event(room, PolicyEntityType::User, raw.cast()).await;
+            },
+        );
+        client.add_event_handler(
+            |raw: Raw<SyncStateEvent<PolicyRuleRoomEventContent>>, room: Room| async move {
+                on_policy_rule_event(room, PolicyEntityType::Room, raw.cast()).await;
+            },
+        );
+        client.add_event_handler(
+            |raw: Raw<SyncStateEvent<PolicyRuleServerEventContent>>, room: Room| async move {
+                on_policy_rule_event(room, PolicyEntityType::Server, raw.cast()).await;
+            },
+        );
+
+        client.add_event_handler(on_stripped_member_event);
+        client.add_event_handler(on_member_event);
+        client.add_event_handler(on_message_like_event);
+    }
+}
+
+/// Update the rules with a policy rule event received in the sync.
+#[instrument(skip_all, fields(room_id = %room.room_id()))]
+async fn on_policy_rule_event(
+    room: Room,
+    entity_type: PolicyEntityType,
+    raw: Raw<PolicyRuleStateEvent>,
+) {
+    let policy_lists = room.client().policy_lists();
+
+    if !policy_lists.is_subscribed(room.room_id()) {
+        return;
+    }
+
+    let event = match raw.deserialize() {
+        Ok(event) => event,
+        Err(error) => {
+            warn!(?error, "Couldn't deserialize a policy rule");
+            return;
+        }
+    };
+
+    let Some(rule) = policy_lists.update_rule(room.room_id(), entity_type, event) else {
+        return;
+    };
+
+    if !rule.is_ban()
+        || entity_type == PolicyEntityType::Room
+        || !policy_lists.enforcement().apply_bans
+    {
+        return;
+    }
+
+    // A user rule without wildcards matches a single user.
+    let single_user = (entity_type == PolicyEntityType::User && !rule.entity.contains(['*', '?']))
+        .then(|| UserId::parse(&rule.entity).ok())
+        .flatten();
+
+    let glob = WildMatch::new(&rule.entity);
+    let applied = policy_lists
+        .ban_members(single_user.as_deref(), |user_id| {
+            let matches = match entity_type {
+                PolicyEntityType::User => glob.matches(user_id.as_str()),
+                PolicyEntityType::Server => glob.matches(user_id.server_name().as_str()),
+                PolicyEntityType::Room => false,
+            };
+            matches.then(|| rule.clone())
+        })
+        .await;
+
+    debug!(
+        banned = applied.banned.len(),
+        failed = applied.failed.len(),
+        "Applied a new ban rule"
+    );
+}
+
+/// Decline or ignore the invites of the banned users, or to the banned rooms.
+async fn on_stripped_member_event(event: StrippedRoomMemberEvent, room: Room, client: Client) {
+    if room.state() != RoomState::Invited
+        || event.state_key != room.own_user_id()
+        || event.content.membership != MembershipState::Invite
+    {
+        return;
+    }
+
+    let policy_lists = client.policy_lists();
+    let enforcement = policy_lists.enforcement();
+
+    let sender_rule = policy_lists.matching_rule(&*event.sender);
+    let Some(rule) = sender_rule.clone().or_else(|| policy_lists.matching_rule(room.room_id()))
+    else {
+        return;
+    };
+
+    debug!(room_id = %room.room_id(), entity = rule.entity, "Received an invite banned by a policy");
+
+    if enforcement.ignore_users && sender_rule.is_some() {
+        ignore_user(&client, &event.sender).await;
+    }
+
+    if enforcement.decline_invites {
+        if let Err(error) = room.leave().await {
+            warn!(room_id = %room.room_id(), ?error, "Couldn't decline an invite banned by a policy");
+        }
+    }
+}
+
+/// Ban the banned users joining our rooms.
+async fn on_member_event(event: OriginalSyncRoomMemberEvent, room: Room, client: Client) {
+    let policy_lists = client.policy_lists();
+
+    if event.content.membership != MembershipState::Join
+        || !policy_lists.enforcement().apply_bans
+        || policy_lists.is_subscribed(room.room_id())
+    {
+        return;
+    }
+
+    let Ok(user_id) = UserId::parse(&event.state_key) else {
+        return;
+    };
+
+    let Some(rule) = policy_lists.matching_rule(&*user_id) else {
+        return;
+    };
+
+    let power_levels = room.power_levels_or_default().await;
+    if !power_levels.user_can_ban_user(room.own_user_id(), &user_id) {
+        return;
+    }
+
+    let reason = (!rule.reason.is_empty()).then_some(rule.reason.as_str());
+    if let Err(error) = room.ban_user(&user_id, reason).await {
+        warn!(room_id = %room.room_id(), ?error, "Couldn't ban a user banned by a policy");
+    }
+}
+
+/// Ignore the banned users sending messages in our rooms.
+async fn on_message_like_event(raw: Raw<AnySyncMessageLikeEvent>, client: Client) {
+    #[derive(Deserialize)]
+    struct Sender {
+        sender: OwnedUserId,
+    }
+
+    let policy_lists = client.policy_lists();
+    if !policy_lists.enforcement().ignore_users {
+        return;
+    }
+
+    let Ok(Sender { sender }) = raw.deserialize_as() else {
+        return;
+    };
+
+    if client.user_id() != Some(&sender) && policy_lists.matching_rule(&*sender).is_some() {
+        ignore_user(&client, &sender).await;
+    }
+}
+
+/// Add the given user to the ignored users of the account, if they aren't
+/// already.
+///
+/// A user is only ignored once by the enforcement, so every event of a
+/// spammer doesn't check their account data again, and a user who was
+/// unignored afterwards isn't ignored again.
+async fn ignore_user(client: &Client, user_id: &UserId) {
+    let ignored_users = &client.inner.policy_list_data.ignored_users;
+
+    if !ignored_users.write().unwrap().insert(user_id.to_owned()) {
+        return;
+    }
+
+    if client.is_user_ignored(user_id).await {
+        return;
+    }
+
+    if let Err(error) = client.account().ignore_user(user_id).await {
+        warn!(%user_id, ?error, "Couldn't ignore a user banned by a policy");
+
+        // Try again with the next event of the user.
+        ignored_users.write().unwrap().remove(user_id);
+    }
+}
@@ -0,0 +1,248 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The rules of the policy lists, and their matching.
+
+use std::collections::BTreeMap;
+
+use ruma::{
+    events::{policy::rule::Recommendation, StateEventType},
+    OwnedRoomId, RoomId,
+};
+use serde::Deserialize;
+use wildmatch::WildMatch;
+
+/// The unstable identifier of the `m.ban` recommendation, still used by some
+/// moderation bots.
+const UNSTABLE_BAN_RECOMMENDATION: &str = "org.matrix.mjolnir.ban";
+
+/// The type of the entities a policy rule applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PolicyEntityType {
+    /// The rule applies to users, from an `m.policy.rule.user` event.
+    User,
+    /// The rule applies to rooms, from an `m.policy.rule.room` event.
+    Room,
+    /// The rule applies to servers, from an `m.policy.rule.server` event.
+    Server,
+}
+
+impl PolicyEntityType {
+    /// The type of the state events of the rules for this type of entities.
+    pub fn event_type(self) -> StateEventType {
+        match self {
+            Self::User => StateEventType::PolicyRuleUser,
+            Self::Room => StateEventType::PolicyRuleRoom,
+            Self::Server => StateEventType::PolicyRuleServer,
+        }
+    }
+}
+
+/// A rule of a policy list.
+#[derive(Clone, Debug)]
+pub struct PolicyRule {
+    /// The ID of the policy room containing the rule.
+    pub policy_room_id: OwnedRoomId,
+    /// The type of the entities the rule applies to.
+    pub entity_type: PolicyEntityType,
+    /// The entity the rule applies to, which can be a glob with `*` and `?`
+    /// wildcards.
+    pub entity: String,
+    /// The recommended action for the entities matching the rule.
+    pub recommendation: Recommendation,
+    /// The reason of the rule.
+    pub reason: String,
+}
+
+impl PolicyRule {
+    /// Whether the recommendation of this rule is to ban the matching
+    /// entities.
+    pub fn is_ban(&self) -> bool {
+        self.recommendation == Recommendation::Ban
+            || self.recommendation.as_str() == UNSTABLE_BAN_RECOMMENDATION
+    }
+
+    /// Whether the given entity matches the glob of this rule.
+    pub fn matches(&self, entity: &str) -> bool {
+        WildMatch::new(&self.entity).matches(entity)
+    }
+}
+
+/// The content of a policy rule event, which is empty when the rule was
+/// removed.
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct PolicyRuleContent {
+    entity: Option<String>,
+    recommendation: Option<Recommendation>,
+    #[serde(default)]
+    reason: String,
+}
+
+/// A policy rule state event, with a lenient deserialization of its content.
+#[derive(Debug, Deserialize)]
+pub(super) struct PolicyRuleStateEvent {
+    pub state_key: String,
+    #[serde(default)]
+    pub content: PolicyRuleContent,
+}
+
+/// A rule with its compiled glob.
+#[derive(Debug)]
+struct CompiledRule {
+    rule: PolicyRule,
+    glob: WildMatch,
+}
+
+/// The rules of the subscribed policy lists.
+#[derive(Debug, Default)]
+pub(super) struct PolicyRules {
+    /// The rules, by policy room, type of entities and state key.
+    rules: BTreeMap<(OwnedRoomId, PolicyEntityType, String), CompiledRule>,
+}
+
+impl PolicyRules {
+    /// Update the rule with the given state key, from the content of its
+    /// latest state event.
+    ///
+    /// Returns the new rule, if the content is a valid rule.
+    pub fn update(
+        &mut self,
+        policy_room_id: &RoomId,
+        entity_type: PolicyEntityType,
+        state_key: String,
+        content: PolicyRuleContent,
+    ) -> Option<PolicyRule> {
+        let key = (policy_room_id.to_owned(), entity_type, state_key);
+
+        let (Some(entity), Some(recommendation)) = (content.entity, content.recommendation) else {
+            // The rule was removed or redacted.
+            self.rules.remove(&key);
+            return None;
+        };
+
+        let glob = WildMatch::new(&entity);
+        let rule = PolicyRule {
+            policy_room_id: policy_room_id.to_owned(),
+            entity_type,
+            entity,
+            recommendation,
+            reason: content.reason,
+        };
+        self.rules.insert(key, CompiledRule { rule: rule.clone(), glob });
+
+        Some(rule)
+    }
+
+    /// Remove all the rules of the given policy room.
+    pub fn remove_room(&mut self, policy_room_id: &RoomId) {
+        self.rules.retain(|(room_id, _, _), _| room_id != policy_room_id);
+    }
+
+    /// All the rules.
+    pub fn all(&self) -> Vec<PolicyRule> {
+        self.rules.values().map(|compiled| compiled.rule.clone()).collect()
+    }
+
+    /// Find the first ban rule for the given type of entities matching the
+    /// given entity.
+    pub fn find_ban(&self, entity_type: PolicyEntityType, entity: &str) -> Option<&PolicyRule> {
+        self.rules
+            .iter()
+            .filter(|((_, rule_type, _), _)| *rule_type == entity_type)
+            .map(|(_, compiled)| compiled)
+            .find(|compiled| compiled.rule.is_ban() && compiled.glob.matches(entity))
+            .map(|compiled| &compiled.rule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{events::policy::rule::Recommendation, room_id};
+    use serde_json::{from_value as from_json_value, json};
+
+    use super::{PolicyEntityType, PolicyRuleStateEvent, PolicyRules};
+
+    fn update(rules: &mut PolicyRules, entity_type: PolicyEntityType, event: serde_json::Value) {
+        let event: PolicyRuleStateEvent = from_json_value(event).unwrap();
+        rules.update(
+            room_id!("!policies:example.org"),
+            entity_type,
+            event.state_key,
+            event.content,
+        );
+    }
+
+    #[test]
+    fn test_policy_rules_matching() {
+        let mut rules = PolicyRules::default();
+
+        update(
+            &mut rules,
+            PolicyEntityType::User,
+            json!({
+                "state_key": "rule_1",
+                "content": {
+                    "entity": "@spam*:example.org",
+                    "recommendation": "m.ban",
+                    "reason": "spam",
+                },
+            }),
+        );
+        update(
+            &mut rules,
+            PolicyEntityType::Server,
+            json!({
+                "state_key": "rule_2",
+                "content": {
+                    "entity": "*.evil.org",
+                    "recommendation": "org.matrix.mjolnir.ban",
+                    "reason": "",
+                },
+            }),
+        );
+        update(
+            &mut rules,
+            PolicyEntityType::Room,
+            json!({
+                "state_key": "rule_3",
+                "content": {
+                    "entity": "!room:example.org",
+                    "recommendation": "org.example.mute",
+                    "reason": "noisy",
+                },
+            }),
+        );
+
+        assert_eq!(rules.all().len(), 3);
+
+        let rule = rules.find_ban(PolicyEntityType::User, "@spammer:example.org").unwrap();
+        assert_eq!(rule.reason, "spam");
+        assert_eq!(rule.recommendation, Recommendation::Ban);
+        assert!(rules.find_ban(PolicyEntityType::User, "@alice:example.org").is_none());
+        assert!(rules.find_ban(PolicyEntityType::Server, "example.org").is_none());
+
+        assert!(rules.find_ban(PolicyEntityType::Server, "matrix.evil.org").is_some());
+
+        // Only the ban recommendations are enforced.
+        assert!(rules.find_ban(PolicyEntityType::Room, "!room:example.org").is_none());
+
+        // An empty content removes the rule.
+        update(&mut rules, PolicyEntityType::User, json!({ "state_key": "rule_1", "content": {} }));
+        assert!(rules.find_ban(PolicyEntityType::User, "@spammer:example.org").is_none());
+        assert_eq!(rules.all().len(), 2);
+
+        rules.remove_room(room_id!("!policies:example.org"));
+        assert!(rules.all().is_empty());
+    }
+}
@@ -15,10 +15,22 @@
 
 //! High-level pusher API.
 
-use ruma::api::client::push::{set_pusher, PusherIds};
+use ruma::{
+    api::client::push::{
+        get_pushers, set_pusher, EmailPusherData, PusherIds, PusherInit, PusherKind,
+    },
+    push::{HttpPusherData, PushFormat},
+};
+use serde_json::Value as JsonValue;
 
 use crate::{Client, Result};
 
+/// The app id that must be used for email pushers.
+const EMAIL_PUSHER_APP_ID: &str = "m.email";
+
+/// The key of the default payload in the data of HTTP pushers.
+const DEFAULT_PAYLOAD_KEY: &str = "default_payload";
+
 /// A high-level API to interact with the pusher API.
 ///
 /// All the methods in this struct send a request to the homeserver.
@@ -46,21 +58,312 @@ impl Pusher {
         self.client.send(request).await?;
         Ok(())
     }
+
+    /// Lists all the pushers registered for the current user, on all their
+    /// devices.
+    pub async fn list(&self) -> Result<Vec<ruma::api::client::push::Pusher>> {
+        let request = get_pushers::v3::Request::new();
+        Ok(self.client.send(request).await?.pushers)
+    }
+
+    /// Checks whether the given pusher, as expected by this device, is
+    /// registered on the homeserver, with the same configuration.
+    pub async fn check_device_pusher(
+        &self,
+        pusher: &ruma::api::client::push::Pusher,
+    ) -> Result<PusherStatus> {
+        let registered = self.list().await?;
+        Ok(PusherStatus::compute(pusher, registered))
+    }
+
+    /// Makes sure the given pusher is registered on the homeserver, and up to
+    /// date.
+    ///
+    /// This is meant to be called every time the app starts, or when the push
+    /// token of the device has been rotated. In the latter case, the push key
+    /// of the pusher that's being replaced can be passed as
+    /// `replaced_push_key`, so that this pusher is deleted and the homeserver
+    /// doesn't keep on sending notifications to a stale token.
+    ///
+    /// Returns the status of the pusher before any change was made.
+    pub async fn ensure_device_pusher(
+        &self,
+        pusher: ruma::api::client::push::Pusher,
+        replaced_push_key: Option<&str>,
+    ) -> Result<PusherStatus> {
+        let registered = self.list().await?;
+
+        let stale_ids = replaced_push_key
+            .filter(|push_key| *push_key != pusher.ids.pushkey)
+            .and_then(|push_key| {
+                registered.iter().find(|registered| {
+                    registered.ids.app_id == pusher.ids.app_id && registered.ids.pushkey == push_key
+                })
+            })
+            .map(|stale| stale.ids.clone());
+
+        let status = PusherStatus::compute(&pusher, registered);
+
+        if status != PusherStatus::Registered {
+            self.set(pusher).await?;
+        }
+
+        if let Some(stale_ids) = stale_ids {
+            self.delete(stale_ids).await?;
+        }
+
+        Ok(status)
+    }
+}
+
+/// The status of a pusher on the homeserver, compared to the one expected by
+/// this device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PusherStatus {
+    /// The pusher is registered, with the expected configuration.
+    Registered,
+
+    /// A pusher with the same app id and push key is registered, but with a
+    /// different configuration (e.g. a different push gateway URL).
+    Outdated,
+
+    /// No pusher with the same app id and push key is registered.
+    Missing,
+}
+
+impl PusherStatus {
+    fn compute(
+        expected: &ruma::api::client::push::Pusher,
+        registered: Vec<ruma::api::client::push::Pusher>,
+    ) -> Self {
+        let Some(registered) = registered.into_iter().find(|registered| {
+            registered.ids.app_id == expected.ids.app_id
+                && registered.ids.pushkey == expected.ids.pushkey
+        }) else {
+            return Self::Missing;
+        };
+
+        // Only the fields set by the client are compared, the homeserver may add
+        // other fields to the pushers it returns.
+        let is_up_to_date = is_same_kind(&registered.kind, &expected.kind)
+            && registered.lang == expected.lang
+            && registered.app_display_name == expected.app_display_name
+            && registered.device_display_name == expected.device_display_name
+            && (expected.profile_tag.is_none() || registered.profile_tag == expected.profile_tag);
+
+        if is_up_to_date {
+            Self::Registered
+        } else {
+            Self::Outdated
+        }
+    }
+}
+
+/// Whether the given pusher kinds are the same, with the same push gateway
+/// URL, format and default payload, if set, for HTTP pushers.
+fn is_same_kind(registered: &PusherKind, expected: &PusherKind) -> bool {
+    match (registered, expected) {
+        (PusherKind::Http(registered), PusherKind::Http(expected)) => {
+            registered.url == expected.url
+                && registered.format == expected.format
+                && expected.data.get(DEFAULT_PAYLOAD_KEY).is_none_or(|default_payload| {
+                    registered.data.get(DEFAULT_PAYLOAD_KEY) == Some(default_payload)
+                })
+        }
+        (PusherKind::Email(_), PusherKind::Email(_)) => true,
+        // The kinds unknown to the SDK can't be compared, so the pusher is
+        // considered outdated and is registered again.
+        _ => false,
+    }
+}
+
+/// A builder for a pusher that sends notifications to a push gateway over
+/// HTTP.
+///
+/// The built pusher can then be registered with [`Pusher::set`].
+#[derive(Debug, Clone)]
+pub struct HttpPusherBuilder {
+    ids: PusherIds,
+    url: String,
+    format: Option<PushFormat>,
+    default_payload: Option<JsonValue>,
+    app_display_name: String,
+    device_display_name: String,
+    profile_tag: Option<String>,
+    lang: String,
+}
+
+impl HttpPusherBuilder {
+    /// Creates a new builder for an HTTP pusher.
+    ///
+    /// The `push_key` is the token identifying this device on the push
+    /// provider, the `app_id` is the reverse-DNS identifier of the app, and
+    /// the `url` is the URL of the push gateway's `/_matrix/push/v1/notify`
+    /// endpoint.
+    pub fn new(
+        push_key: impl Into<String>,
+        app_id: impl Into<String>,
+        url: impl Into<String>,
+    ) -> Self {
+        Self {
+            ids: PusherIds::new(push_key.into(), app_id.into()),
+            url: url.into(),
+            format: None,
+            default_payload: None,
+            app_display_name: String::new(),
+            device_display_name: String::new(),
+            profile_tag: None,
+            lang: "en".to_owned(),
+        }
+    }
+
+    /// Sets the format the homeserver should use when sending notifications to
+    /// the push gateway.
+    pub fn format(mut self, format: PushFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Requires the homeserver to only send the event id and room id of the
+    /// events to notify about, not their content.
+    pub fn event_id_only(self) -> Self {
+        self.format(PushFormat::EventIdOnly)
+    }
+
+    /// Sets the default payload that the push gateway should include in
+    /// every notification.
+    pub fn default_payload(mut self, default_payload: JsonValue) -> Self {
+        self.default_payload = Some(default_payload);
+        self
+    }
+
+    /// Sets a name allowing the user to identify the app owning this pusher.
+    pub fn app_display_name(mut self, app_display_name: impl Into<String>) -> Self {
+        self.app_display_name = app_display_name.into();
+        self
+    }
+
+    /// Sets a name allowing the user to identify the device owning this
+    /// pusher.
+    pub fn device_display_name(mut self, device_display_name: impl Into<String>) -> Self {
+        self.device_display_name = device_display_name.into();
+        self
+    }
+
+    /// Sets which set of device-specific push rules this pusher executes.
+    pub fn profile_tag(mut self, profile_tag: impl Into<String>) -> Self {
+        self.profile_tag = Some(profile_tag.into());
+        self
+    }
+
+    /// Sets the preferred language for receiving notifications (e.g. `en` or
+    /// `en-US`).
+    ///
+    /// Defaults to `en`.
+    pub fn lang(mut self, lang: impl Into<String>) -> Self {
+        self.lang = lang.into();
+        self
+    }
+
+    /// Builds the pusher.
+    pub fn build(self) -> ruma::api::client::push::Pusher {
+        let mut data = HttpPusherData::new(self.url);
+        data.format = self.format;
+        if let Some(default_payload) = self.default_payload {
+            data.data.insert(DEFAULT_PAYLOAD_KEY.to_owned(), default_payload);
+        }
+
+        PusherInit {
+            ids: self.ids,
+            kind: PusherKind::Http(data),
+            app_display_name: self.app_display_name,
+            device_display_name: self.device_display_name,
+            profile_tag: self.profile_tag,
+            lang: self.lang,
+        }
+        .into()
+    }
+}
+
+/// A builder for a pusher that sends notifications to the user by email.
+///
+/// The built pusher can then be registered with [`Pusher::set`]. The email
+/// address must have been previously associated with the user's account.
+#[derive(Debug, Clone)]
+pub struct EmailPusherBuilder {
+    address: String,
+    app_display_name: String,
+    device_display_name: String,
+    lang: String,
+}
+
+impl EmailPusherBuilder {
+    /// Creates a new builder for an email pusher, sending notifications to the
+    /// given email address.
+    pub fn new(address: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            app_display_name: "Email".to_owned(),
+            device_display_name: String::new(),
+            lang: "en".to_owned(),
+        }
+    }
+
+    /// Sets a name allowing the user to identify this pusher.
+    ///
+    /// Defaults to `Email`.
+    pub fn app_display_name(mut self, app_display_name: impl Into<String>) -> Self {
+        self.app_display_name = app_display_name.into();
+        self
+    }
+
+    /// Sets a name allowing the user to identify the device that created this
+    /// pusher.
+    pub fn device_display_name(mut self, device_display_name: impl Into<String>) -> Self {
+        self.device_display_name = device_display_name.into();
+        self
+    }
+
+    /// Sets the preferred language for receiving notifications (e.g. `en` or
+    /// `en-US`).
+    ///
+    /// Defaults to `en`.
+    pub fn lang(mut self, lang: impl Into<String>) -> Self {
+        self.lang = lang.into();
+        self
+    }
+
+    /// Builds the pusher.
+    pub fn build(self) -> ruma::api::client::push::Pusher {
+        PusherInit {
+            // For email pushers, the push key is the email address.
+            ids: PusherIds::new(self.address, EMAIL_PUSHER_APP_ID.to_owned()),
+            kind: PusherKind::Email(EmailPusherData::new()),
+            app_display_name: self.app_display_name,
+            device_display_name: self.device_display_name,
+            profile_tag: None,
+            lang: self.lang,
+        }
+        .into()
+    }
 }
 
 // The http mocking library is not supported for wasm32
 #[cfg(all(test, not(target_family = "wasm")))]
 mod tests {
+    use assert_matches2::assert_let;
     use matrix_sdk_test::{async_test, test_json};
     use ruma::{
         api::client::push::{PusherIds, PusherInit, PusherKind},
-        push::HttpPusherData,
+        push::{HttpPusherData, PushFormat},
     };
+    use serde_json::json;
     use wiremock::{
-        matchers::{method, path},
+        matchers::{body_partial_json, method, path},
         Mock, MockServer, ResponseTemplate,
     };
 
+    use super::{EmailPusherBuilder, HttpPusherBuilder, PusherStatus};
     use crate::test_utils::logged_in_client;
 
     async fn mock_api(server: MockServer) {
@@ -105,4 +408,258 @@ mod tests {
 
         assert!(response.is_ok());
     }
+
+    fn mock_get_pushers(pushers: serde_json::Value) -> Mock {
+        Mock::given(method("GET"))
+            .and(path("_matrix/client/r0/pushers"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "pushers": pushers })))
+    }
+
+    fn registered_http_pusher(push_key: &str, url: &str) -> serde_json::Value {
+        json!({
+            "pushkey": push_key,
+            "app_id": "org.example.app",
+            "kind": "http",
+            "app_display_name": "Example",
+            "device_display_name": "Phone",
+            "lang": "en",
+            "data": {
+                "url": url,
+                "format": "event_id_only",
+            },
+        })
+    }
+
+    fn expected_http_pusher(push_key: &str) -> ruma::api::client::push::Pusher {
+        HttpPusherBuilder::new(push_key, "org.example.app", "https://push.example.org/notify")
+            .app_display_name("Example")
+            .device_display_name("Phone")
+            .event_id_only()
+            .build()
+    }
+
+    #[async_test]
+    async fn test_list_pushers() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        mock_get_pushers(json!([registered_http_pusher(
+            "token",
+            "https://push.example.org/notify"
+        )]))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+        let pushers = client.pusher().list().await.unwrap();
+
+        assert_eq!(pushers.len(), 1);
+        assert_eq!(pushers[0].ids.pushkey, "token");
+        assert_let!(PusherKind::Http(data) = &pushers[0].kind);
+        assert_eq!(data.url, "https://push.example.org/notify");
+        assert_eq!(data.format, Some(PushFormat::EventIdOnly));
+    }
+
+    #[test]
+    fn test_http_pusher_builder() {
+        let pusher = HttpPusherBuilder::new("token", "org.example.app", "https://push.example.org")
+            .app_display_name("Example")
+            .device_display_name("Phone")
+            .profile_tag("tag")
+            .lang("fr")
+            .event_id_only()
+            .default_payload(json!({ "aps": { "mutable-content": 1 } }))
+            .build();
+
+        assert_eq!(
+            serde_json::to_value(&pusher).unwrap(),
+            json!({
+                "pushkey": "token",
+                "app_id": "org.example.app",
+                "kind": "http",
+                "app_display_name": "Example",
+                "device_display_name": "Phone",
+                "profile_tag": "tag",
+                "lang": "fr",
+                "data": {
+                    "url": "https://push.example.org",
+                    "format": "event_id_only",
+                    "default_payload": { "aps": { "mutable-content": 1 } },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_email_pusher_builder() {
+        let pusher =
+            EmailPusherBuilder::new("alice@example.org").device_display_name("Phone").build();
+
+        assert_eq!(
+            serde_json::to_value(&pusher).unwrap(),
+            json!({
+                "pushkey": "alice@example.org",
+                "app_id": "m.email",
+                "kind": "email",
+                "app_display_name": "Email",
+                "device_display_name": "Phone",
+                "lang": "en",
+                "data": {},
+            })
+        );
+    }
+
+    #[async_test]
+    async fn test_check_device_pusher() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        mock_get_pushers(json!([
+            registered_http_pusher("token", "https://push.example.org/notify"),
+            registered_http_pusher("other_token", "https://old.example.org/notify"),
+        ]))
+        .mount(&server)
+        .await;
+
+        let pusher = client.pusher();
+        assert_eq!(
+            pusher.check_device_pusher(&expected_http_pusher("token")).await.unwrap(),
+            PusherStatus::Registered
+        );
+        assert_eq!(
+            pusher.check_device_pusher(&expected_http_pusher("other_token")).await.unwrap(),
+            PusherStatus::Outdated
+        );
+        assert_eq!(
+            pusher.check_device_pusher(&expected_http_pusher("new_token")).await.unwrap(),
+            PusherStatus::Missing
+        );
+    }
+
+    #[test]
+    fn test_pusher_status_ignores_unset_fields() {
+        let expected = expected_http_pusher("token");
+
+        // The homeserver adds fields and reorders the ones of the pusher.
+        let registered = serde_json::from_value(json!({
+            "lang": "en",
+            "data": {
+                "format": "event_id_only",
+                "url": "https://push.example.org/notify",
+                "org.example.custom": true,
+            },
+            "device_display_name": "Phone",
+            "app_display_name": "Example",
+            "kind": "http",
+            "app_id": "org.example.app",
+            "pushkey": "token",
+            "profile_tag": "server_tag",
+            "org.example.extra": 42,
+        }))
+        .unwrap();
+        assert_eq!(PusherStatus::compute(&expected, vec![registered]), PusherStatus::Registered);
+
+        // A change of a field set by the client makes the pusher outdated.
+        for (field, value) in [
+            ("lang", json!("fr")),
+            ("app_display_name", json!("Other")),
+            ("device_display_name", json!("Tablet")),
+        ] {
+            let mut registered = registered_http_pusher("token", "https://push.example.org/notify");
+            registered[field] = value;
+            let registered = serde_json::from_value(registered).unwrap();
+            assert_eq!(
+                PusherStatus::compute(&expected, vec![registered]),
+                PusherStatus::Outdated,
+                "{field}"
+            );
+        }
+
+        let mut registered = registered_http_pusher("token", "https://push.example.org/notify");
+        registered["data"]["format"] = json!(null);
+        let registered = serde_json::from_value(registered).unwrap();
+        assert_eq!(PusherStatus::compute(&expected, vec![registered]), PusherStatus::Outdated);
+
+        let mut registered = registered_http_pusher("token", "https://push.example.org/notify");
+        registered["kind"] = json!("email");
+        let registered = serde_json::from_value(registered).unwrap();
+        assert_eq!(PusherStatus::compute(&expected, vec![registered]), PusherStatus::Outdated);
+    }
+
+    #[test]
+    fn test_pusher_status_compares_profile_tag_and_default_payload() {
+        let expected =
+            HttpPusherBuilder::new("token", "org.example.app", "https://push.example.org/notify")
+                .app_display_name("Example")
+                .device_display_name("Phone")
+                .event_id_only()
+                .profile_tag("tag")
+                .default_payload(json!({ "aps": { "mutable-content": 1 } }))
+                .build();
+
+        let mut registered = registered_http_pusher("token", "https://push.example.org/notify");
+        registered["profile_tag"] = json!("tag");
+        registered["data"]["default_payload"] = json!({ "aps": { "mutable-content": 1 } });
+        let registered_json = registered;
+
+        let registered = serde_json::from_value(registered_json.clone()).unwrap();
+        assert_eq!(PusherStatus::compute(&expected, vec![registered]), PusherStatus::Registered);
+
+        // A different profile tag makes the pusher outdated.
+        let mut registered = registered_json.clone();
+        registered["profile_tag"] = json!("other_tag");
+        let registered = serde_json::from_value(registered).unwrap();
+        assert_eq!(PusherStatus::compute(&expected, vec![registered]), PusherStatus::Outdated);
+
+        // A different or missing default payload makes the pusher outdated.
+        let mut registered = registered_json.clone();
+        registered["data"]["default_payload"] = json!({ "aps": { "mutable-content": 0 } });
+        let registered = serde_json::from_value(registered).unwrap();
+        assert_eq!(PusherStatus::compute(&expected, vec![registered]), PusherStatus::Outdated);
+
+        let mut registered = registered_json;
+        registered["data"].as_object_mut().unwrap().remove("default_payload");
+        let registered = serde_json::from_value(registered).unwrap();
+        assert_eq!(PusherStatus::compute(&expected, vec![registered]), PusherStatus::Outdated);
+    }
+
+    #[async_test]
+    async fn test_ensure_device_pusher_after_token_rotation() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        mock_get_pushers(json!([registered_http_pusher(
+            "old_token",
+            "https://push.example.org/notify"
+        )]))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+        // The new pusher is registered.
+        Mock::given(method("POST"))
+            .and(path("_matrix/client/r0/pushers/set"))
+            .and(body_partial_json(json!({ "pushkey": "new_token", "kind": "http" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EMPTY))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // The stale pusher is deleted.
+        Mock::given(method("POST"))
+            .and(path("_matrix/client/r0/pushers/set"))
+            .and(body_partial_json(json!({ "pushkey": "old_token", "kind": null })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EMPTY))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let status = client
+            .pusher()
+            .ensure_device_pusher(expected_http_pusher("new_token"), Some("old_token"))
+            .await
+            .unwrap();
+
+        assert_eq!(status, PusherStatus::Missing);
+    }
 }
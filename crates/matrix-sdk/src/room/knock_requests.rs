@@ -12,10 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::BTreeSet;
+
+use async_stream::stream;
+use futures_core::Stream;
 use js_int::UInt;
-use ruma::{EventId, OwnedEventId, OwnedMxcUri, OwnedUserId, RoomId};
+use matrix_sdk_base::{RoomState, RoomStateFilter};
+use ruma::{EventId, OwnedEventId, OwnedMxcUri, OwnedRoomId, OwnedUserId, RoomId};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
 
-use crate::{room::RoomMember, Error, Room};
+use crate::{room::RoomMember, Client, Error, Room};
 
 /// A request to join a room with `knock` join rule.
 #[derive(Debug, Clone)]
@@ -102,6 +109,134 @@ impl KnockRequestMemberInfo {
     }
 }
 
+/// A knock of the current user on a room, as emitted by
+/// [`Client::subscribe_to_own_knocks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnKnock {
+    /// The room the current user knocked on.
+    pub room_id: OwnedRoomId,
+    /// The state of the knock.
+    pub state: OwnKnockState,
+}
+
+/// The state of a knock of the current user on a room.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OwnKnockState {
+    /// The knock is waiting for an answer from the room's moderators.
+    Pending,
+    /// The knock has been accepted, and the user has been invited to the room.
+    Accepted,
+    /// The user joined the room after knocking on it, e.g. because they could
+    /// join it through a `knock_restricted` join rule in the meantime.
+    Joined,
+    /// The knock has been denied by the room's moderators, either by kicking or
+    /// banning the user.
+    Denied {
+        /// The reason given by the moderator, if any.
+        reason: Option<String>,
+    },
+    /// The user retracted their knock.
+    Retracted,
+}
+
+impl OwnKnockState {
+    /// Compute the state of the knock on a room the user has knocked on, from
+    /// its current state.
+    async fn from_room(room: &Room) -> Self {
+        match room.state() {
+            RoomState::Knocked => Self::Pending,
+            RoomState::Invited => Self::Accepted,
+            RoomState::Joined => Self::Joined,
+            RoomState::Left | RoomState::Banned => {
+                // Tell a retracted knock from a denied one by looking at who changed the
+                // membership of the user.
+                let member = match room.get_member_no_sync(room.own_user_id()).await {
+                    Ok(member) => member,
+                    Err(err) => {
+                        warn!(room_id = ?room.room_id(), "Failed to load the own member: {err}");
+                        None
+                    }
+                };
+
+                match member {
+                    Some(member) if member.event().sender() == room.own_user_id() => {
+                        Self::Retracted
+                    }
+                    Some(member) => {
+                        Self::Denied { reason: member.event().reason().map(ToOwned::to_owned) }
+                    }
+                    None => Self::Denied { reason: None },
+                }
+            }
+        }
+    }
+}
+
+/// Create a stream of the knocks of the current user; see
+/// [`Client::subscribe_to_own_knocks`].
+pub(crate) fn own_knocks_stream(client: Client) -> impl Stream<Item = OwnKnock> {
+    let mut room_updates = client.subscribe_to_all_room_updates();
+
+    stream! {
+        let mut pending = BTreeSet::new();
+
+        for room in client.rooms_filtered(RoomStateFilter::KNOCKED) {
+            pending.insert(room.room_id().to_owned());
+            yield OwnKnock { room_id: room.room_id().to_owned(), state: OwnKnockState::Pending };
+        }
+
+        loop {
+            let room_ids: Vec<OwnedRoomId> = match room_updates.recv().await {
+                Ok(updates) => updates
+                    .left
+                    .into_keys()
+                    .chain(updates.joined.into_keys())
+                    .chain(updates.invited.into_keys())
+                    .chain(updates.knocked.into_keys())
+                    .collect(),
+
+                Err(RecvError::Lagged(_)) => {
+                    // Some updates have been missed, check all the rooms that may have changed.
+                    pending
+                        .iter()
+                        .cloned()
+                        .chain(
+                            client
+                                .rooms_filtered(RoomStateFilter::KNOCKED)
+                                .iter()
+                                .map(|room| room.room_id().to_owned()),
+                        )
+                        .collect()
+                }
+
+                Err(RecvError::Closed) => break,
+            };
+
+            for room_id in room_ids {
+                let Some(room) = client.get_room(&room_id) else {
+                    continue;
+                };
+
+                let was_pending = pending.contains(&room_id);
+                if was_pending == (room.state() == RoomState::Knocked) {
+                    // Either an unrelated room, or a knock that's still pending.
+                    continue;
+                }
+
+                let state = OwnKnockState::from_room(&room).await;
+
+                if was_pending {
+                    pending.remove(&room_id);
+                } else {
+                    pending.insert(room_id.clone());
+                }
+
+                yield OwnKnock { room_id, state };
+            }
+        }
+    }
+}
+
 // The http mocking library is not supported for wasm32
 #[cfg(all(test, not(target_family = "wasm")))]
 mod tests {
@@ -182,6 +182,15 @@ pub struct EventWithContextResponse {
     /// If lazy-loading of members was requested, this may contain room
     /// membership events.
     pub state: Vec<Raw<AnyStateEvent>>,
+
+    /// Whether this response was loaded from the event cache because the
+    /// homeserver couldn't be reached, in offline-first mode.
+    ///
+    /// In this case, the events are those of the event cache up to the
+    /// previous and next gaps, there's no `next_batch_token` and no `state`.
+    /// There's no `prev_batch_token` either if the previous gap stands for
+    /// events evicted from the event cache.
+    pub is_stale: bool,
 }
 
 /// Options for [super::Room::list_threads].
@@ -61,24 +61,27 @@ use ruma::events::{
     SyncMessageLikeEvent,
 };
 use ruma::{
-    api::client::{
-        config::{set_global_account_data, set_room_account_data},
-        context,
-        error::ErrorKind,
-        filter::LazyLoadOptions,
-        membership::{
-            ban_user, forget_room, get_member_events,
-            invite_user::{self, v3::InvitationRecipient},
-            kick_user, leave_room, unban_user, Invite3pid,
+    api::{
+        client::{
+            config::{set_global_account_data, set_room_account_data},
+            context,
+            error::ErrorKind,
+            filter::LazyLoadOptions,
+            membership::{
+                ban_user, forget_room, get_member_events,
+                invite_user::{self, v3::InvitationRecipient},
+                kick_user, leave_room, unban_user, Invite3pid,
+            },
+            message::send_message_event,
+            read_marker::set_read_marker,
+            receipt::create_receipt,
+            redact::redact_event,
+            room::{get_event_by_timestamp, get_room_event, report_content, report_room},
+            state::{get_state_events_for_key, send_state_event},
+            tag::{create_tag, delete_tag},
+            typing::create_typing_event::{self, v3::Typing},
         },
-        message::send_message_event,
-        read_marker::set_read_marker,
-        receipt::create_receipt,
-        redact::redact_event,
-        room::{get_room_event, report_content, report_room},
-        state::{get_state_events_for_key, send_state_event},
-        tag::{create_tag, delete_tag},
-        typing::create_typing_event::{self, v3::Typing},
+        Direction,
     },
     assign,
     events::{
@@ -118,8 +121,9 @@ use ruma::{
     push::{Action, PushConditionRoomCtx, Ruleset},
     serde::Raw,
     time::Instant,
-    EventId, Int, MatrixToUri, MatrixUri, MxcUri, OwnedEventId, OwnedRoomId, OwnedServerName,
-    OwnedTransactionId, OwnedUserId, RoomId, TransactionId, UInt, UserId,
+    EventId, Int, MatrixToUri, MatrixUri, MilliSecondsSinceUnixEpoch, MxcUri, OwnedEventId,
+    OwnedRoomId, OwnedServerName, OwnedTransactionId, OwnedUserId, RoomId, TransactionId, UInt,
+    UserId,
 };
 use serde::de::DeserializeOwned;
 use thiserror::Error;
@@ -154,7 +158,7 @@ use crate::{
     },
     sync::RoomUpdate,
     utils::{IntoRawMessageLikeEventContent, IntoRawStateEventContent},
-    BaseRoom, Client, Error, HttpResult, Result, RoomState, TransmissionProgress,
+    BaseRoom, Client, Error, HttpError, HttpResult, Result, RoomState, TransmissionProgress,
 };
 #[cfg(feature = "e2e-encryption")]
 use crate::{crypto::types::events::CryptoContextInfo, encryption::backups::BackupState};
@@ -168,6 +172,7 @@ mod member;
 mod messages;
 pub mod power_levels;
 pub mod reply;
+pub mod server_acl;
 
 /// Contains all the functionality for modifying the privacy settings in a room.
 pub mod privacy_settings;
@@ -643,6 +648,15 @@ impl Room {
 
     /// Fetch the event with the given `EventId` in this room, using the
     /// `/context` endpoint to get more information.
+    ///
+    /// If the client is in offline-first mode (see
+    /// [`ClientBuilder::with_offline_first`]) and the homeserver can't be
+    /// reached, the event and its context are loaded from the
+    /// [`EventCache`][crate::event_cache] instead, if it contains the event.
+    /// The response is then marked as
+    /// [stale](EventWithContextResponse::is_stale).
+    ///
+    /// [`ClientBuilder::with_offline_first`]: crate::ClientBuilder::with_offline_first
     pub async fn event_with_context(
         &self,
         event_id: &EventId,
@@ -660,7 +674,13 @@ impl Room {
                 LazyLoadOptions::Enabled { include_redundant_members: false };
         }
 
-        let response = self.client.send(request).with_request_config(request_config).await?;
+        let response = match self.client.send(request).with_request_config(request_config).await {
+            Ok(response) => response,
+            Err(err) if self.client.is_offline_first() && err.is_network_error() => {
+                return self.cached_event_with_context(event_id, err).await;
+            }
+            Err(err) => return Err(err.into()),
+        };
 
         let push_ctx = self.push_context().await?;
         let push_ctx = push_ctx.as_ref();
@@ -707,6 +727,68 @@ impl Room {
             state: response.state,
             prev_batch_token: response.start,
             next_batch_token: response.end,
+            is_stale: false,
+        })
+    }
+
+    /// Find the event closest to the given timestamp in this room, using the
+    /// `/timestamp_to_event` endpoint.
+    ///
+    /// With [`Direction::Forward`], the homeserver looks for the first event
+    /// sent at or after the timestamp; with [`Direction::Backward`], for the
+    /// last event sent at or before it.
+    ///
+    /// The returned event can then be used as the target of a `/context`
+    /// request, e.g. with [`Room::event_with_context`], to jump to a date in
+    /// the room's history.
+    pub async fn event_for_timestamp(
+        &self,
+        timestamp: MilliSecondsSinceUnixEpoch,
+        direction: Direction,
+    ) -> Result<get_event_by_timestamp::v1::Response> {
+        let request = get_event_by_timestamp::v1::Request::new(
+            self.room_id().to_owned(),
+            timestamp,
+            direction,
+        );
+
+        Ok(self.client.send(request).await?)
+    }
+
+    /// Load the event with the given `EventId` and its context from the event
+    /// cache, after the `/context` request failed with the given network
+    /// error.
+    ///
+    /// Returns the error if the event isn't in the event cache.
+    async fn cached_event_with_context(
+        &self,
+        event_id: &EventId,
+        error: HttpError,
+    ) -> Result<EventWithContextResponse> {
+        let context = match self.event_cache().await {
+            Ok((event_cache, _drop_handles)) => event_cache.find_event_with_context(event_id).await,
+            Err(err) => {
+                debug!("error when getting the event cache: {err}");
+                None
+            }
+        };
+
+        let Some(context) = context else {
+            return Err(error.into());
+        };
+
+        debug!("couldn't fetch the event context, using the event cache: {error}");
+
+        Ok(EventWithContextResponse {
+            event: Some(context.event),
+            events_before: context.events_before,
+            events_after: context.events_after,
+            prev_batch_token: context.prev_batch_token,
+            // The cache has no token to paginate forwards: the events after are those
+            // up to the next gap, or the end of the timeline.
+            next_batch_token: None,
+            state: Vec::new(),
+            is_stale: true,
         })
     }
 
@@ -716,7 +798,7 @@ impl Room {
             .members_request_deduplicated_handler
             .run(self.room_id().to_owned(), async move {
                 let request = get_member_events::v3::Request::new(self.inner.room_id().to_owned());
-                let response = self
+                let response = match self
                     .client
                     .send(request.clone())
                     .with_request_config(
@@ -724,7 +806,19 @@ impl Room {
                         // https://github.com/element-hq/synapse/issues/16872
                         RequestConfig::new().timeout(Duration::from_secs(60)).retry_limit(3),
                     )
-                    .await?;
+                    .await
+                {
+                    Ok(response) => response,
+
+                    Err(err) if self.client.is_offline_first() && err.is_network_error() => {
+                        // Keep using the members we have in the store. They are reported as
+                        // stale as long as the members aren't synced.
+                        debug!("couldn't fetch the members, using the stored ones: {err}");
+                        return Ok(());
+                    }
+
+                    Err(err) => return Err(err.into()),
+                };
 
                 // That's a large `Future`. Let's `Box::pin` to reduce its size on the stack.
                 Box::pin(self.client.base_client().receive_all_members(
@@ -842,6 +936,13 @@ impl Room {
     /// This method will de-duplicate requests if it is called multiple times in
     /// quick succession, in that case the return value will be `None`. This
     /// method does nothing if the members are already synced.
+    ///
+    /// If the client is in offline-first mode (see
+    /// [`ClientBuilder::with_offline_first`]) and the homeserver can't be
+    /// reached, this doesn't fail: the members from the store are kept, and
+    /// [`Room::are_members_stale`] returns `true` until they are synced.
+    ///
+    /// [`ClientBuilder::with_offline_first`]: crate::ClientBuilder::with_offline_first
     pub async fn sync_members(&self) -> Result<()> {
         if !self.are_events_visible() {
             return Ok(());
@@ -854,6 +955,20 @@ impl Room {
         }
     }
 
+    /// Whether the members returned by [`Room::members`] and
+    /// [`Room::get_member`] might be incomplete or outdated, because the
+    /// member list of this room isn't synced with the homeserver.
+    ///
+    /// This can only happen in offline-first mode, where these methods use the
+    /// members from the store when the homeserver can't be reached, see
+    /// [`ClientBuilder::with_offline_first`]. This relies on
+    /// [`Room::are_members_synced`], so it is persisted across restarts.
+    ///
+    /// [`ClientBuilder::with_offline_first`]: crate::ClientBuilder::with_offline_first
+    pub fn are_members_stale(&self) -> bool {
+        self.client.is_offline_first() && !self.are_members_synced()
+    }
+
     /// Get a specific member of this room.
     ///
     /// *Note*: This method will fetch the members from the homeserver if the
@@ -3042,6 +3157,11 @@ impl Room {
 
         self.client.base_client().forget_room(self.inner.room_id()).await?;
 
+        // The invite to this room, if it was filtered, doesn't need to be remembered.
+        if let Err(error) = self.client.forget_filtered_invite(self.inner.room_id()).await {
+            warn!(room_id = ?self.room_id(), "failed to forget the filtered invite: {error}");
+        }
+
         Ok(())
     }
 
@@ -3443,6 +3563,20 @@ impl Room {
         ObservableLiveLocation::new(&self.client, self.room_id())
     }
 
+    /// Retract the knock of the current user on this room.
+    ///
+    /// This can only be called on a room the user has knocked on, i.e. in the
+    /// [`RoomState::Knocked`] state.
+    pub async fn retract_knock(&self) -> Result<()> {
+        let state = self.state();
+
+        if state != RoomState::Knocked {
+            return Err(Error::WrongRoomState(Box::new(WrongRoomState::new("Knocked", state))));
+        }
+
+        self.leave().await
+    }
+
     /// Subscribe to knock requests in this `Room`.
     ///
     /// The current requests to join the room will be emitted immediately
@@ -0,0 +1,178 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers for the [`m.room.server_acl`] state event, which decides which
+//! servers can participate in a room.
+//!
+//! [`m.room.server_acl`]: https://spec.matrix.org/latest/client-server-api/#mroomserver_acl
+
+use std::collections::BTreeSet;
+
+use matrix_sdk_base::deserialized_responses::SyncOrStrippedState;
+use ruma::{
+    api::client::state::send_state_event, events::room::server_acl::RoomServerAclEventContent,
+    OwnedServerName, OwnedUserId,
+};
+use thiserror::Error;
+
+use crate::{Result, Room, RoomMemberships};
+
+/// Errors of the updates of the server ACL of a room.
+#[derive(Debug, Error)]
+pub enum ServerAclError {
+    /// A glob of the allowed or denied servers isn't a valid server name
+    /// glob.
+    #[error("invalid server name glob: {0:?}")]
+    InvalidGlob(String),
+
+    /// The new server ACL would ban our own server from the room.
+    #[error("the server ACL would ban our own server")]
+    OwnServerDenied,
+}
+
+/// A preview of the effect of a new server ACL on the current members of a
+/// room.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServerAclPreview {
+    /// The servers of the members that would be denied.
+    pub denied_servers: BTreeSet<OwnedServerName>,
+
+    /// The joined members whose server would be denied. They can't participate
+    /// in the room anymore.
+    pub affected_members: Vec<OwnedUserId>,
+}
+
+/// Check that the given string is a valid glob of server names.
+///
+/// The globs can contain the `*` and `?` wildcards, but no port.
+fn validate_glob(glob: &str) -> Result<(), ServerAclError> {
+    let is_valid = if let Some(ipv6) = glob.strip_prefix('[') {
+        // An IPv6 literal, whose colons aren't a port separator.
+        ipv6.strip_suffix(']').is_some_and(|ipv6| {
+            !ipv6.is_empty()
+                && ipv6.chars().all(|c| c.is_ascii_hexdigit() || matches!(c, ':' | '.' | '*' | '?'))
+        })
+    } else {
+        !glob.is_empty()
+            && glob.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '*' | '?'))
+    };
+
+    if is_valid {
+        Ok(())
+    } else {
+        Err(ServerAclError::InvalidGlob(glob.to_owned()))
+    }
+}
+
+impl Room {
+    /// Get the server ACL of this room, if it has one.
+    pub async fn server_acl(&self) -> Result<Option<RoomServerAclEventContent>> {
+        let Some(raw_event) = self.get_state_event_static::<RoomServerAclEventContent>().await?
+        else {
+            return Ok(None);
+        };
+
+        Ok(match raw_event.deserialize()? {
+            SyncOrStrippedState::Sync(event) => event.as_original().map(|ev| ev.content.clone()),
+            SyncOrStrippedState::Stripped(event) => Some(event.content),
+        })
+    }
+
+    /// Preview which current members of this room would be affected by a new
+    /// server ACL.
+    ///
+    /// The ACL is validated like with [`Room::update_server_acl()`].
+    pub async fn preview_server_acl(
+        &self,
+        allow: Vec<String>,
+        deny: Vec<String>,
+        allow_ip_literals: bool,
+    ) -> Result<ServerAclPreview> {
+        let content = self.new_server_acl(allow, deny, allow_ip_literals)?;
+
+        let mut preview = ServerAclPreview::default();
+
+        for member in self.members_no_sync(RoomMemberships::JOIN).await? {
+            let server_name = member.user_id().server_name();
+
+            if !content.is_allowed(server_name) {
+                preview.denied_servers.insert(server_name.to_owned());
+                preview.affected_members.push(member.user_id().to_owned());
+            }
+        }
+
+        Ok(preview)
+    }
+
+    /// Update the server ACL of this room.
+    ///
+    /// The globs of the allowed and denied servers can contain the `*` and `?`
+    /// wildcards. Note that an empty list of allowed servers denies all the
+    /// servers.
+    ///
+    /// Returns an error if a glob is invalid, or if the new ACL would ban our
+    /// own server from the room. Use [`Room::preview_server_acl()`] to see
+    /// which members would be affected first.
+    pub async fn update_server_acl(
+        &self,
+        allow: Vec<String>,
+        deny: Vec<String>,
+        allow_ip_literals: bool,
+    ) -> Result<send_state_event::v3::Response> {
+        let content = self.new_server_acl(allow, deny, allow_ip_literals)?;
+        self.send_state_event(content).await
+    }
+
+    /// Build and validate a new server ACL.
+    fn new_server_acl(
+        &self,
+        allow: Vec<String>,
+        deny: Vec<String>,
+        allow_ip_literals: bool,
+    ) -> Result<RoomServerAclEventContent, ServerAclError> {
+        for glob in allow.iter().chain(&deny) {
+            validate_glob(glob)?;
+        }
+
+        let content = RoomServerAclEventContent::new(allow_ip_literals, allow, deny);
+
+        if !content.is_allowed(self.own_user_id().server_name()) {
+            return Err(ServerAclError::OwnServerDenied);
+        }
+
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::assert_matches;
+
+    use super::{validate_glob, ServerAclError};
+
+    #[test]
+    fn test_validate_glob() {
+        validate_glob("*").unwrap();
+        validate_glob("matrix.org").unwrap();
+        validate_glob("*.evil.org").unwrap();
+        validate_glob("spam-?.example.org").unwrap();
+        validate_glob("1.2.3.4").unwrap();
+        validate_glob("[2001:db8::*]").unwrap();
+
+        for invalid in ["", "matrix.org:8448", "evil org", "[2001:db8::1", "[]", "evil.org/"] {
+            assert_matches!(validate_glob(invalid), Err(ServerAclError::InvalidGlob(glob)));
+            assert_eq!(glob, invalid);
+        }
+    }
+}
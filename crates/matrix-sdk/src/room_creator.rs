@@ -0,0 +1,477 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A builder to create rooms with the right initial state for their use.
+//!
+//! A [`RoomCreator`] is created with [`Client::room_creator()`] from a
+//! [`RoomCreatorPreset`], which decides the encryption, the history
+//! visibility, the join rule and the visibility in the room directory of the
+//! new room. Each of them can then be changed with the methods of the builder.
+
+use std::{collections::BTreeMap, future::IntoFuture};
+
+use matrix_sdk_common::boxed_into_future;
+use mime::Mime;
+use ruma::{
+    api::client::room::{create_room, Visibility},
+    assign,
+    events::{
+        room::{
+            avatar::{ImageInfo, RoomAvatarEventContent},
+            encryption::RoomEncryptionEventContent,
+            history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
+            join_rules::{JoinRule, RoomJoinRulesEventContent},
+            power_levels::RoomPowerLevelsEventContent,
+        },
+        space::{child::SpaceChildEventContent, parent::SpaceParentEventContent},
+        AnyInitialStateEvent, InitialStateEvent,
+    },
+    int,
+    room::RoomType,
+    serde::Raw,
+    Int, OwnedMxcUri, OwnedRoomId, OwnedServerName, OwnedUserId, RoomVersionId, UInt, UserId,
+};
+use serde_json::json;
+use tracing::{instrument, warn};
+
+use crate::{Client, Error, Result, Room};
+
+/// The kind of room created by a [`RoomCreator`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RoomCreatorPreset {
+    /// A direct message with the given user.
+    ///
+    /// The room is encrypted, and both users get the maximum power level.
+    EncryptedDm(OwnedUserId),
+
+    /// A private room, that users can only join when they are invited.
+    ///
+    /// The room is encrypted.
+    PrivateRoom,
+
+    /// A room that users can ask to join, and that they can only join when they
+    /// are invited.
+    ///
+    /// The room is encrypted.
+    KnockOnly,
+
+    /// A public room, that anyone can join, published in the room directory.
+    PublicChannel,
+
+    /// A private space, that users can only join when they are invited.
+    PrivateSpace,
+
+    /// A public space, that anyone can join and preview, published in the room
+    /// directory.
+    PublicSpace,
+}
+
+/// A template for the power levels of a room created by a [`RoomCreator`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PowerLevelsTemplate {
+    /// The power levels of the preset of the room on the homeserver.
+    #[default]
+    Default,
+
+    /// Only the moderators can send messages.
+    Announcements,
+
+    /// Everyone can invite users, and change the name, the topic and the
+    /// avatar of the room.
+    Collaborative,
+}
+
+/// The avatar of a room created by a [`RoomCreator`].
+#[derive(Clone, Debug)]
+enum RoomCreatorAvatar {
+    /// An avatar that was already uploaded.
+    Url(OwnedMxcUri),
+
+    /// An avatar to upload before creating the room.
+    Data { content_type: Mime, data: Vec<u8> },
+}
+
+/// A builder to create a room, returned by [`Client::room_creator()`].
+///
+/// Awaiting it creates the room, and waits for the room to come down the sync,
+/// so the sync must be running.
+#[derive(Debug)]
+pub struct RoomCreator {
+    client: Client,
+    preset: RoomCreatorPreset,
+    name: Option<String>,
+    topic: Option<String>,
+    alias: Option<String>,
+    room_version: Option<RoomVersionId>,
+    invite: Vec<OwnedUserId>,
+    encrypted: bool,
+    history_visibility: HistoryVisibility,
+    join_rule: JoinRule,
+    visibility: Visibility,
+    power_levels: PowerLevelsTemplate,
+    user_power_levels: BTreeMap<OwnedUserId, Int>,
+    space_parent: Option<OwnedRoomId>,
+    space_children: Vec<OwnedRoomId>,
+    avatar: Option<RoomCreatorAvatar>,
+}
+
+impl RoomCreator {
+    fn new(client: Client, preset: RoomCreatorPreset) -> Self {
+        // Like with `Client::create_dm()`, the rooms can only be encrypted if the
+        // client can encrypt the messages.
+        let can_encrypt = cfg!(feature = "e2e-encryption");
+
+        let (encrypted, history_visibility, join_rule, visibility) = match preset {
+            RoomCreatorPreset::EncryptedDm(_)
+            | RoomCreatorPreset::PrivateRoom
+            | RoomCreatorPreset::PrivateSpace => {
+                let encrypted = can_encrypt && preset != RoomCreatorPreset::PrivateSpace;
+                (encrypted, HistoryVisibility::Shared, JoinRule::Invite, Visibility::Private)
+            }
+            RoomCreatorPreset::KnockOnly => {
+                (can_encrypt, HistoryVisibility::Shared, JoinRule::Knock, Visibility::Private)
+            }
+            RoomCreatorPreset::PublicChannel => {
+                (false, HistoryVisibility::Shared, JoinRule::Public, Visibility::Public)
+            }
+            RoomCreatorPreset::PublicSpace => {
+                (false, HistoryVisibility::WorldReadable, JoinRule::Public, Visibility::Public)
+            }
+        };
+
+        let invite = match &preset {
+            RoomCreatorPreset::EncryptedDm(user_id) => vec![user_id.clone()],
+            _ => Vec::new(),
+        };
+
+        Self {
+            client,
+            preset,
+            name: None,
+            topic: None,
+            alias: None,
+            room_version: None,
+            invite,
+            encrypted,
+            history_visibility,
+            join_rule,
+            visibility,
+            power_levels: PowerLevelsTemplate::Default,
+            user_power_levels: BTreeMap::new(),
+            space_parent: None,
+            space_children: Vec::new(),
+            avatar: None,
+        }
+    }
+
+    /// Set the name of the room.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Set the topic of the room.
+    pub fn topic(mut self, topic: impl Into<String>) -> Self {
+        self.topic = Some(topic.into());
+        self
+    }
+
+    /// Set the localpart of the alias of the room, e.g. `rust` for
+    /// `#rust:example.org`.
+    pub fn alias(mut self, localpart: impl Into<String>) -> Self {
+        self.alias = Some(localpart.into());
+        self
+    }
+
+    /// Set the version of the room, instead of the default version of the
+    /// homeserver.
+    pub fn room_version(mut self, room_version: RoomVersionId) -> Self {
+        self.room_version = Some(room_version);
+        self
+    }
+
+    /// Invite the given users to the room.
+    pub fn invite(mut self, user_ids: impl IntoIterator<Item = OwnedUserId>) -> Self {
+        self.invite.extend(user_ids);
+        self
+    }
+
+    /// Set whether the room is encrypted.
+    pub fn encryption(mut self, encrypted: bool) -> Self {
+        self.encrypted = encrypted;
+        self
+    }
+
+    /// Set the history visibility of the room.
+    pub fn history_visibility(mut self, history_visibility: HistoryVisibility) -> Self {
+        self.history_visibility = history_visibility;
+        self
+    }
+
+    /// Set the join rule of the room.
+    pub fn join_rule(mut self, join_rule: JoinRule) -> Self {
+        self.join_rule = join_rule;
+        self
+    }
+
+    /// Set whether the room is published in the room directory.
+    pub fn visibility(mut self, visibility: Visibility) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    /// Set the template of the power levels of the room.
+    pub fn power_levels(mut self, template: PowerLevelsTemplate) -> Self {
+        self.power_levels = template;
+        self
+    }
+
+    /// Give a power level to the given user, e.g. to make them a moderator.
+    pub fn user_power_level(mut self, user_id: OwnedUserId, power_level: Int) -> Self {
+        self.user_power_levels.insert(user_id, power_level);
+        self
+    }
+
+    /// Add the room to the given space.
+    ///
+    /// The room gets the space as its canonical parent, and the space gets the
+    /// room as a child once it's created, which requires the power to do so in
+    /// the space.
+    pub fn in_space(mut self, space_id: OwnedRoomId) -> Self {
+        self.space_parent = Some(space_id);
+        self
+    }
+
+    /// Add the given room as a child of the space, for the
+    /// [`RoomCreatorPreset::PrivateSpace`] and
+    /// [`RoomCreatorPreset::PublicSpace`] presets.
+    pub fn space_child(mut self, room_id: OwnedRoomId) -> Self {
+        self.space_children.push(room_id);
+        self
+    }
+
+    /// Set the avatar of the room to an image that was already uploaded.
+    pub fn avatar_url(mut self, url: OwnedMxcUri) -> Self {
+        self.avatar = Some(RoomCreatorAvatar::Url(url));
+        self
+    }
+
+    /// Set the avatar of the room to the given image, uploaded before the room
+    /// is created.
+    pub fn avatar(mut self, content_type: Mime, data: Vec<u8>) -> Self {
+        self.avatar = Some(RoomCreatorAvatar::Data { content_type, data });
+        self
+    }
+
+    /// Whether the room is a space.
+    fn is_space(&self) -> bool {
+        matches!(self.preset, RoomCreatorPreset::PrivateSpace | RoomCreatorPreset::PublicSpace)
+    }
+
+    /// Build the request to create the room.
+    fn build_request(
+        &self,
+        own_user_id: &UserId,
+        avatar: Option<RoomAvatarEventContent>,
+    ) -> Result<create_room::v3::Request> {
+        let via: Vec<OwnedServerName> = vec![own_user_id.server_name().to_owned()];
+
+        let mut initial_state: Vec<Raw<AnyInitialStateEvent>> = vec![
+            InitialStateEvent::new(RoomHistoryVisibilityEventContent::new(
+                self.history_visibility.clone(),
+            ))
+            .to_raw_any(),
+            InitialStateEvent::new(RoomJoinRulesEventContent::new(self.join_rule.clone()))
+                .to_raw_any(),
+        ];
+
+        if self.encrypted {
+            initial_state.push(
+                InitialStateEvent::new(RoomEncryptionEventContent::with_recommended_defaults())
+                    .to_raw_any(),
+            );
+        }
+
+        if let Some(avatar) = avatar {
+            initial_state.push(InitialStateEvent::new(avatar).to_raw_any());
+        }
+
+        if let Some(space_id) = &self.space_parent {
+            let content = assign!(SpaceParentEventContent::new(via.clone()), { canonical: true });
+            initial_state
+                .push(InitialStateEvent { content, state_key: space_id.clone() }.to_raw_any());
+        }
+
+        for room_id in &self.space_children {
+            let content = SpaceChildEventContent::new(via.clone());
+            initial_state
+                .push(InitialStateEvent { content, state_key: room_id.clone() }.to_raw_any());
+        }
+
+        let preset = match self.preset {
+            RoomCreatorPreset::EncryptedDm(_) => create_room::v3::RoomPreset::TrustedPrivateChat,
+            RoomCreatorPreset::PublicChannel | RoomCreatorPreset::PublicSpace => {
+                create_room::v3::RoomPreset::PublicChat
+            }
+            _ => create_room::v3::RoomPreset::PrivateChat,
+        };
+
+        let creation_content = if self.is_space() {
+            Some(Raw::new(&assign!(create_room::v3::CreationContent::new(), {
+                room_type: Some(RoomType::Space),
+            }))?)
+        } else {
+            None
+        };
+
+        Ok(assign!(create_room::v3::Request::new(), {
+            creation_content,
+            initial_state,
+            invite: self.invite.clone(),
+            is_direct: matches!(self.preset, RoomCreatorPreset::EncryptedDm(_)),
+            name: self.name.clone(),
+            power_level_content_override: self.power_level_content_override(own_user_id)?,
+            preset: Some(preset),
+            room_alias_name: self.alias.clone(),
+            room_version: self.room_version.clone(),
+            topic: self.topic.clone(),
+            visibility: self.visibility.clone(),
+        }))
+    }
+
+    /// The changes to the power levels of the preset, if any.
+    fn power_level_content_override(
+        &self,
+        own_user_id: &UserId,
+    ) -> Result<Option<Raw<RoomPowerLevelsEventContent>>> {
+        let mut content = match self.power_levels {
+            PowerLevelsTemplate::Default => json!({}),
+            PowerLevelsTemplate::Announcements => json!({ "events_default": 50 }),
+            // The `events` of the override replace the ones of the preset, so the default
+            // ones that must be restricted to the administrators are kept.
+            PowerLevelsTemplate::Collaborative => json!({
+                "invite": 0,
+                "events": {
+                    "m.room.name": 0,
+                    "m.room.topic": 0,
+                    "m.room.avatar": 0,
+                    "m.room.power_levels": 100,
+                    "m.room.history_visibility": 100,
+                    "m.room.encryption": 100,
+                    "m.room.server_acl": 100,
+                    "m.room.tombstone": 100,
+                },
+            }),
+        };
+
+        if !self.user_power_levels.is_empty() {
+            // The users override the ones of the preset, so the ones of the preset must be
+            // kept.
+            let mut users = BTreeMap::from([(own_user_id.to_owned(), int!(100))]);
+
+            if matches!(self.preset, RoomCreatorPreset::EncryptedDm(_)) {
+                users.extend(self.invite.iter().map(|user_id| (user_id.clone(), int!(100))));
+            }
+
+            users.extend(self.user_power_levels.clone());
+            content["users"] = json!(users);
+        }
+
+        if content.as_object().is_some_and(|content| content.is_empty()) {
+            return Ok(None);
+        }
+
+        Ok(Some(Raw::new(&content)?.cast()))
+    }
+}
+
+impl IntoFuture for RoomCreator {
+    type Output = Result<Room>;
+    boxed_into_future!();
+
+    #[instrument(skip_all, name = "RoomCreator")]
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            let client = &self.client;
+            let own_user_id = client.user_id().ok_or(Error::AuthenticationRequired)?;
+
+            let avatar = match &self.avatar {
+                Some(RoomCreatorAvatar::Url(url)) => {
+                    Some(assign!(RoomAvatarEventContent::new(), { url: Some(url.clone()) }))
+                }
+                Some(RoomCreatorAvatar::Data { content_type, data }) => {
+                    let response = client.media().upload(content_type, data.clone(), None).await?;
+                    let info = assign!(ImageInfo::new(), {
+                        mimetype: Some(content_type.essence_str().to_owned()),
+                        size: UInt::new(data.len() as u64),
+                    });
+
+                    Some(assign!(RoomAvatarEventContent::new(), {
+                        url: Some(response.content_uri),
+                        info: Some(Box::new(info)),
+                    }))
+                }
+                None => None,
+            };
+
+            let request = self.build_request(own_user_id, avatar)?;
+            let room = client.create_room(request).await?;
+
+            if let Some(space_id) = &self.space_parent {
+                let content =
+                    SpaceChildEventContent::new(vec![own_user_id.server_name().to_owned()]);
+
+                match client.get_room(space_id) {
+                    Some(space) => {
+                        if let Err(error) =
+                            space.send_state_event_for_key(room.room_id(), content).await
+                        {
+                            warn!(?error, "Couldn't add the new room to its space");
+                        }
+                    }
+                    None => warn!(%space_id, "Couldn't add the new room to an unknown space"),
+                }
+            }
+
+            Ok(client.await_room_remote_echo(room.room_id()).await)
+        })
+    }
+}
+
+impl Client {
+    /// Create a builder to create a room with the given preset.
+    ///
+    /// Awaiting the builder creates the room, and waits for the room to come
+    /// down the sync.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # async {
+    /// # let client: Client = unimplemented!();
+    /// use matrix_sdk::room_creator::{PowerLevelsTemplate, RoomCreatorPreset};
+    ///
+    /// let room = client
+    ///     .room_creator(RoomCreatorPreset::PublicChannel)
+    ///     .name("Announcements")
+    ///     .alias("announcements")
+    ///     .power_levels(PowerLevelsTemplate::Announcements)
+    ///     .await?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn room_creator(&self, preset: RoomCreatorPreset) -> RoomCreator {
+        RoomCreator::new(self.clone(), preset)
+    }
+}
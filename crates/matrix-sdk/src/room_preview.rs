@@ -22,8 +22,11 @@ use futures_util::future::join_all;
 use matrix_sdk_base::{RoomHero, RoomInfo, RoomState};
 use ruma::{
     api::client::{membership::joined_members, state::get_state_events},
-    events::room::{history_visibility::HistoryVisibility, join_rules::JoinRule},
-    room::RoomType,
+    events::room::{
+        history_visibility::HistoryVisibility,
+        join_rules::{AllowRule, JoinRule},
+    },
+    room::{JoinRuleSummary, RoomType},
     space::SpaceRoomJoinRule,
     OwnedMxcUri, OwnedRoomAliasId, OwnedRoomId, OwnedServerName, RoomId, RoomOrAliasId, ServerName,
 };
@@ -79,14 +82,80 @@ pub struct RoomPreview {
 
     /// Room heroes.
     pub heroes: Option<Vec<RoomHero>>,
+
+    /// What the current user should do next to get into the room.
+    pub next_action: RoomPreviewAction,
+}
+
+/// What the current user should do next to get into a previewed room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoomPreviewAction {
+    /// The user is already a member of the room.
+    AlreadyJoined,
+    /// The user can join the room directly.
+    Join,
+    /// The user has been invited to the room, and can accept the invite.
+    AcceptInvite,
+    /// The user can knock on the room, to ask for permission to join it.
+    Knock,
+    /// The user has knocked on the room, and is waiting for an answer.
+    AwaitKnockAnswer,
+    /// The user can neither join nor knock on the room, and needs to be
+    /// invited to it.
+    AwaitInvite,
+    /// The user has been banned from the room.
+    Banned,
+}
+
+impl RoomPreviewAction {
+    /// Compute the next action for the current user, given their state in the
+    /// room and the room's join rule.
+    ///
+    /// `allowed_room_ids` are the rooms (usually spaces) whose members can join
+    /// a room with a `restricted` or `knock_restricted` join rule directly.
+    fn compute(
+        client: &Client,
+        state: Option<RoomState>,
+        join_rule: Option<&SpaceRoomJoinRule>,
+        allowed_room_ids: &[OwnedRoomId],
+    ) -> Self {
+        match state {
+            Some(RoomState::Joined) => return Self::AlreadyJoined,
+            Some(RoomState::Invited) => return Self::AcceptInvite,
+            Some(RoomState::Knocked) => return Self::AwaitKnockAnswer,
+            Some(RoomState::Banned) => return Self::Banned,
+            Some(RoomState::Left) | None => {}
+        }
+
+        let is_member_of_allowed_room = allowed_room_ids.iter().any(|room_id| {
+            client.get_room(room_id).is_some_and(|room| room.state() == RoomState::Joined)
+        });
+
+        match join_rule {
+            Some(SpaceRoomJoinRule::Public) => Self::Join,
+            Some(SpaceRoomJoinRule::Knock) => Self::Knock,
+            Some(SpaceRoomJoinRule::Restricted) if is_member_of_allowed_room => Self::Join,
+            Some(SpaceRoomJoinRule::KnockRestricted) if is_member_of_allowed_room => Self::Join,
+            Some(SpaceRoomJoinRule::KnockRestricted) => Self::Knock,
+            // Without a join rule, the room defaults to the `invite` join rule.
+            _ => Self::AwaitInvite,
+        }
+    }
 }
 
 impl RoomPreview {
+    /// Whether the current user can knock on the room, to ask for permission to
+    /// join it.
+    pub fn can_knock(&self) -> bool {
+        self.next_action == RoomPreviewAction::Knock
+    }
+
     /// Constructs a [`RoomPreview`] from the associated room info.
     ///
     /// Note: not using the room info's state/count of joined members, because
     /// we can do better than that.
     fn from_room_info(
+        client: &Client,
         room_info: RoomInfo,
         is_direct: Option<bool>,
         num_joined_members: u64,
@@ -94,6 +163,37 @@ impl RoomPreview {
         state: Option<RoomState>,
         computed_display_name: Option<String>,
     ) -> Self {
+        let allowed_room_ids = match room_info.join_rule() {
+            Some(JoinRule::Restricted(restricted) | JoinRule::KnockRestricted(restricted)) => {
+                restricted
+                    .allow
+                    .iter()
+                    .filter_map(|rule| match rule {
+                        AllowRule::RoomMembership(membership) => Some(membership.room_id.clone()),
+                        _ => None,
+                    })
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+
+        let join_rule = room_info.join_rule().map(|rule| match rule {
+            JoinRule::Invite => SpaceRoomJoinRule::Invite,
+            JoinRule::Knock => SpaceRoomJoinRule::Knock,
+            JoinRule::Private => SpaceRoomJoinRule::Private,
+            JoinRule::Restricted(_) => SpaceRoomJoinRule::Restricted,
+            JoinRule::KnockRestricted(_) => SpaceRoomJoinRule::KnockRestricted,
+            JoinRule::Public => SpaceRoomJoinRule::Public,
+            _ => {
+                // The JoinRule enum is non-exhaustive. Let's do a white lie and pretend it's
+                // private (a cautious choice).
+                SpaceRoomJoinRule::Private
+            }
+        });
+
+        let next_action =
+            RoomPreviewAction::compute(client, state, join_rule.as_ref(), &allowed_room_ids);
+
         RoomPreview {
             room_id: room_info.room_id().to_owned(),
             canonical_alias: room_info.canonical_alias().map(ToOwned::to_owned),
@@ -101,19 +201,7 @@ impl RoomPreview {
             topic: room_info.topic().map(ToOwned::to_owned),
             avatar_url: room_info.avatar_url().map(ToOwned::to_owned),
             room_type: room_info.room_type().cloned(),
-            join_rule: room_info.join_rule().map(|rule| match rule {
-                JoinRule::Invite => SpaceRoomJoinRule::Invite,
-                JoinRule::Knock => SpaceRoomJoinRule::Knock,
-                JoinRule::Private => SpaceRoomJoinRule::Private,
-                JoinRule::Restricted(_) => SpaceRoomJoinRule::Restricted,
-                JoinRule::KnockRestricted(_) => SpaceRoomJoinRule::KnockRestricted,
-                JoinRule::Public => SpaceRoomJoinRule::Public,
-                _ => {
-                    // The JoinRule enum is non-exhaustive. Let's do a white lie and pretend it's
-                    // private (a cautious choice).
-                    SpaceRoomJoinRule::Private
-                }
-            }),
+            join_rule,
             is_world_readable: room_info
                 .history_visibility()
                 .map(|vis| *vis == HistoryVisibility::WorldReadable),
@@ -122,6 +210,7 @@ impl RoomPreview {
             state,
             is_direct,
             heroes: Some(room_info.heroes().to_vec()),
+            next_action,
         }
     }
 
@@ -136,6 +225,7 @@ impl RoomPreview {
         let display_name = room.display_name().await.ok().map(|name| name.to_string());
 
         Self::from_room_info(
+            &room.client(),
             room.clone_info(),
             is_direct,
             room.joined_members_count(),
@@ -279,6 +369,15 @@ impl RoomPreview {
 
         let summary = response.summary;
 
+        let allowed_room_ids = match &summary.join_rule {
+            JoinRuleSummary::Restricted(restricted)
+            | JoinRuleSummary::KnockRestricted(restricted) => restricted.allowed_room_ids.clone(),
+            _ => Vec::new(),
+        };
+        let join_rule = SpaceRoomJoinRule::from(summary.join_rule);
+        let next_action =
+            RoomPreviewAction::compute(client, state, Some(&join_rule), &allowed_room_ids);
+
         Ok(RoomPreview {
             room_id,
             canonical_alias: summary.canonical_alias,
@@ -288,11 +387,12 @@ impl RoomPreview {
             num_joined_members: summary.num_joined_members.into(),
             num_active_members,
             room_type: summary.room_type,
-            join_rule: Some(summary.join_rule.into()),
+            join_rule: Some(join_rule),
             is_world_readable: Some(summary.world_readable),
             state,
             is_direct,
             heroes: cached_room.map(|r| r.heroes()),
+            next_action,
         })
     }
 
@@ -339,6 +439,7 @@ impl RoomPreview {
         let is_direct = if let Some(room) = room { room.is_direct().await.ok() } else { None };
 
         Ok(Self::from_room_info(
+            client,
             room_info,
             is_direct,
             num_joined_members,
@@ -356,7 +457,7 @@ async fn search_for_room_preview_in_room_directory(
     server: Option<OwnedServerName>,
     expected_room_id: &RoomId,
 ) -> crate::Result<Option<RoomPreview>> {
-    let mut directory_search = RoomDirectorySearch::new(client);
+    let mut directory_search = RoomDirectorySearch::new(client.clone());
     directory_search.search(filter, batch_size, server).await?;
 
     let (results, _) = directory_search.results();
@@ -366,6 +467,10 @@ async fn search_for_room_preview_in_room_directory(
         if room_description.room_id != expected_room_id {
             continue;
         }
+        let join_rule = SpaceRoomJoinRule::from(room_description.join_rule);
+        // The room directory doesn't tell which rooms give access to restricted rooms.
+        let next_action = RoomPreviewAction::compute(&client, None, Some(&join_rule), &[]);
+
         return Ok(Some(RoomPreview {
             room_id: room_description.room_id,
             canonical_alias: room_description.alias,
@@ -376,11 +481,12 @@ async fn search_for_room_preview_in_room_directory(
             num_active_members: None,
             // Assume it's a room
             room_type: None,
-            join_rule: Some(room_description.join_rule.into()),
+            join_rule: Some(join_rule),
             is_world_readable: Some(room_description.is_world_readable),
             state: None,
             is_direct: None,
             heroes: None,
+            next_action,
         }));
     }
 
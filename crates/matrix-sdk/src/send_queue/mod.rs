@@ -133,11 +133,13 @@ use std::{
     str::FromStr as _,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc, RwLock,
+        Arc, Mutex as StdMutex, RwLock,
     },
 };
 
 use as_variant::as_variant;
+use eyeball::SharedObservable;
+use futures_util::pin_mut;
 #[cfg(feature = "unstable-msc4274")]
 use matrix_sdk_base::store::FinishGalleryItemInfo;
 use matrix_sdk_base::{
@@ -154,6 +156,7 @@ use matrix_sdk_base::{
 use matrix_sdk_common::executor::{spawn, JoinHandle};
 use mime::Mime;
 use ruma::{
+    api::MatrixVersion,
     events::{
         reaction::ReactionEventContent,
         relation::Annotation,
@@ -164,7 +167,7 @@ use ruma::{
         AnyMessageLikeEventContent, EventContent as _, Mentions,
     },
     serde::Raw,
-    MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedTransactionId, RoomId,
+    MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedMxcUri, OwnedRoomId, OwnedTransactionId, RoomId,
     TransactionId,
 };
 use tokio::sync::{broadcast, oneshot, Mutex, Notify, OwnedMutexGuard};
@@ -176,12 +179,21 @@ use crate::{
     client::WeakClient,
     config::RequestConfig,
     error::RetryKind,
+    media::{MediaError, PreallocatedMxcUri},
     room::{edit::EditedContent, WeakRoom},
-    Client, Media, Room,
+    Client, Media, Room, TransmissionProgress,
 };
 
 mod upload;
 
+use self::upload::MediaUploadSizes;
+
+/// Minimum size, in bytes, of a media uploaded in clear text for it to be
+/// uploaded to a preallocated MXC URI.
+///
+/// Below that size, the extra request to preallocate the URI isn't worth it.
+const MIN_SIZE_FOR_PREALLOCATED_UPLOAD: usize = 1024 * 1024;
+
 /// A client-wide send queue, for all the rooms known by a client.
 pub struct SendQueue {
     client: Client,
@@ -246,6 +258,7 @@ impl SendQueue {
             data.global_update_sender.clone(),
             data.error_sender.clone(),
             data.is_dropping.clone(),
+            data.report_media_upload_progress.clone(),
             &self.client,
             owned_room_id.clone(),
         );
@@ -285,6 +298,16 @@ impl SendQueue {
         self.data().globally_enabled.load(Ordering::SeqCst)
     }
 
+    /// Enable or disable the reporting of media upload progress, for all
+    /// rooms.
+    ///
+    /// When enabled, [`RoomSendQueueUpdate::MediaUpload`] updates are sent
+    /// while media and their thumbnails are being uploaded. This is disabled
+    /// by default.
+    pub fn enable_upload_progress(&self, enabled: bool) {
+        self.data().report_media_upload_progress.store(enabled, Ordering::SeqCst);
+    }
+
     /// Subscribe to all updates for all rooms.
     ///
     /// Use [`RoomSendQueue::subscribe`] to subscribe to update for a _specific
@@ -345,6 +368,9 @@ pub(super) struct SendQueueData {
 
     /// Are we currently dropping the Client?
     is_dropping: Arc<AtomicBool>,
+
+    /// Should the room queues report the progress of media uploads?
+    report_media_upload_progress: Arc<AtomicBool>,
 }
 
 impl SendQueueData {
@@ -359,6 +385,7 @@ impl SendQueueData {
             global_update_sender,
             error_sender,
             is_dropping: Arc::new(false.into()),
+            report_media_upload_progress: Arc::new(false.into()),
         }
     }
 }
@@ -405,6 +432,7 @@ impl RoomSendQueue {
         global_update_sender: broadcast::Sender<SendQueueUpdate>,
         global_error_sender: broadcast::Sender<SendQueueRoomError>,
         is_dropping: Arc<AtomicBool>,
+        report_media_upload_progress: Arc<AtomicBool>,
         client: &Client,
         room_id: OwnedRoomId,
     ) -> Self {
@@ -425,6 +453,7 @@ impl RoomSendQueue {
             locally_enabled.clone(),
             global_error_sender,
             is_dropping,
+            report_media_upload_progress,
         ));
 
         Self {
@@ -548,6 +577,7 @@ impl RoomSendQueue {
         locally_enabled: Arc<AtomicBool>,
         global_error_sender: broadcast::Sender<SendQueueRoomError>,
         is_dropping: Arc<AtomicBool>,
+        report_media_upload_progress: Arc<AtomicBool>,
     ) {
         trace!("spawned the sending task");
 
@@ -618,11 +648,70 @@ impl RoomSendQueue {
                 continue;
             };
 
-            match Self::handle_request(&room, queued_request, cancel_upload_rx).await {
+            // Observe the progress of media uploads, if requested.
+            let upload_progress = match &related_txn_id {
+                Some(related_to) if report_media_upload_progress.load(Ordering::SeqCst) => {
+                    let is_thumbnail =
+                        queue.is_thumbnail_upload(&txn_id).await.unwrap_or_else(|err| {
+                            warn!("couldn't figure whether the upload is a thumbnail: {err}");
+                            false
+                        });
+                    Some((
+                        related_to.clone(),
+                        is_thumbnail,
+                        SharedObservable::new(TransmissionProgress::default()),
+                    ))
+                }
+                _ => None,
+            };
+
+            let fut = Self::handle_request(
+                &room,
+                &queue,
+                queued_request,
+                cancel_upload_rx,
+                upload_progress.as_ref().map(|(_, _, progress)| progress.clone()),
+            );
+
+            let result = if let Some((related_to, is_thumbnail, progress)) = upload_progress {
+                let mut subscriber = progress.subscribe();
+                pin_mut!(fut);
+
+                loop {
+                    tokio::select! {
+                        biased;
+
+                        Some(progress) = subscriber.next() => {
+                            let total_progress =
+                                queue.aggregated_upload_progress(&related_to, &txn_id, progress);
+
+                            send_update(
+                                &global_update_sender,
+                                &update_sender,
+                                room_id,
+                                RoomSendQueueUpdate::MediaUpload {
+                                    related_to: related_to.clone(),
+                                    is_thumbnail,
+                                    progress,
+                                    total_progress,
+                                },
+                            );
+                        }
+
+                        res = &mut fut => break res,
+                    }
+                }
+            } else {
+                fut.await
+            };
+
+            match result {
                 Ok(Some(parent_key)) => match queue.mark_as_sent(&txn_id, parent_key.clone()).await
                 {
                     Ok(()) => match parent_key {
                         SentRequestKey::Event(event_id) => {
+                            queue.forget_upload_sizes(&txn_id);
+
                             send_update(
                                 &global_update_sender,
                                 &update_sender,
@@ -632,6 +721,10 @@ impl RoomSendQueue {
                         }
 
                         SentRequestKey::Media(media_info) => {
+                            if let Some(related_to) = &related_txn_id {
+                                queue.mark_upload_as_done(related_to, &txn_id);
+                            }
+
                             send_update(
                                 &global_update_sender,
                                 &update_sender,
@@ -729,8 +822,10 @@ impl RoomSendQueue {
     /// `None`).
     async fn handle_request(
         room: &Room,
+        queue: &QueueStorage,
         request: QueuedRequest,
         cancel_upload_rx: Option<oneshot::Receiver<()>>,
+        upload_progress: Option<SharedObservable<TransmissionProgress>>,
     ) -> Result<Option<SentRequestKey>, crate::Error> {
         match request.kind {
             QueuedRequestKind::Event { content } => {
@@ -751,22 +846,15 @@ impl RoomSendQueue {
                 cache_key,
                 thumbnail_source,
                 related_to: relates_to,
+                preallocated_uri,
                 #[cfg(feature = "unstable-msc4274")]
                 accumulated,
             } => {
                 trace!(%relates_to, "uploading media related to event");
 
+                let transaction_id = &request.transaction_id;
                 let fut = async move {
-                    let data = room
-                        .client()
-                        .event_cache_store()
-                        .lock()
-                        .await?
-                        .get_media_content(&cache_key)
-                        .await?
-                        .ok_or(crate::Error::SendQueueWedgeError(Box::new(
-                            QueueWedgeError::MissingMediaContent,
-                        )))?;
+                    let data = Self::load_media_content(room, &cache_key).await?;
 
                     let mime = Mime::from_str(&content_type).map_err(|_| {
                         crate::Error::SendQueueWedgeError(Box::new(
@@ -778,29 +866,45 @@ impl RoomSendQueue {
                     let media_source = if room.latest_encryption_state().await?.is_encrypted() {
                         trace!("upload will be encrypted (encrypted room)");
                         let mut cursor = std::io::Cursor::new(data);
-                        let encrypted_file = room
-                            .client()
+                        let client = room.client();
+                        let mut upload = client
                             .upload_encrypted_file(&mut cursor)
-                            .with_request_config(RequestConfig::short_retry())
-                            .await?;
-                        MediaSource::Encrypted(Box::new(encrypted_file))
+                            .with_request_config(RequestConfig::short_retry());
+                        if let Some(upload_progress) = upload_progress {
+                            upload = upload.with_send_progress_observable(upload_progress);
+                        }
+                        MediaSource::Encrypted(Box::new(upload.await?))
                     } else {
                         trace!("upload will be in clear text (room without encryption)");
-                        let request_config = RequestConfig::short_retry()
-                            .timeout(Media::reasonable_upload_timeout(&data));
-                        let res =
-                            room.client().media().upload(&mime, data, Some(request_config)).await?;
-                        MediaSource::Plain(res.content_uri)
+                        MediaSource::Plain(
+                            Self::upload_plain_media(
+                                room,
+                                queue,
+                                transaction_id,
+                                &cache_key,
+                                &mime,
+                                data,
+                                preallocated_uri,
+                                upload_progress,
+                            )
+                            .await?,
+                        )
                     };
 
                     #[cfg(not(feature = "e2e-encryption"))]
-                    let media_source = {
-                        let request_config = RequestConfig::short_retry()
-                            .timeout(Media::reasonable_upload_timeout(&data));
-                        let res =
-                            room.client().media().upload(&mime, data, Some(request_config)).await?;
-                        MediaSource::Plain(res.content_uri)
-                    };
+                    let media_source = MediaSource::Plain(
+                        Self::upload_plain_media(
+                            room,
+                            queue,
+                            transaction_id,
+                            &cache_key,
+                            &mime,
+                            data,
+                            preallocated_uri,
+                            upload_progress,
+                        )
+                        .await?,
+                    );
 
                     let uri = match &media_source {
                         MediaSource::Plain(uri) => uri,
@@ -839,6 +943,121 @@ impl RoomSendQueue {
         }
     }
 
+    /// Loads the content of a media to upload from the event cache store.
+    async fn load_media_content(
+        room: &Room,
+        cache_key: &MediaRequestParameters,
+    ) -> Result<Vec<u8>, crate::Error> {
+        room.client().event_cache_store().lock().await?.get_media_content(cache_key).await?.ok_or(
+            crate::Error::SendQueueWedgeError(Box::new(QueueWedgeError::MissingMediaContent)),
+        )
+    }
+
+    /// Uploads a media in clear text, and returns its final MXC URI.
+    ///
+    /// If the server supports it, large media are uploaded to an MXC URI
+    /// that's been preallocated and saved in the queued request beforehand.
+    /// This way, if a previous attempt completed but couldn't be recorded as
+    /// such (e.g. because the app was killed in the meanwhile), retrying
+    /// won't upload the same media a second time.
+    ///
+    /// This isn't possible for encrypted media, since each attempt encrypts
+    /// them with a new key.
+    #[allow(clippy::too_many_arguments)]
+    async fn upload_plain_media(
+        room: &Room,
+        queue: &QueueStorage,
+        transaction_id: &TransactionId,
+        cache_key: &MediaRequestParameters,
+        mime: &Mime,
+        data: Vec<u8>,
+        preallocated_uri: Option<OwnedMxcUri>,
+        upload_progress: Option<SharedObservable<TransmissionProgress>>,
+    ) -> Result<OwnedMxcUri, crate::Error> {
+        let client = room.client();
+        let media = client.media();
+        let request_config =
+            RequestConfig::short_retry().timeout(Media::reasonable_upload_timeout(&data));
+
+        let preallocated_uri = match preallocated_uri {
+            Some(uri) => Some(PreallocatedMxcUri::from_uri(uri)),
+
+            None if data.len() >= MIN_SIZE_FOR_PREALLOCATED_UPLOAD
+                && client
+                    .server_versions()
+                    .await
+                    .is_ok_and(|versions| versions.iter().any(|v| *v >= MatrixVersion::V1_7)) =>
+            {
+                let preallocated = media.create_content_uri().await?;
+
+                match queue
+                    .save_preallocated_uri(transaction_id, Some(preallocated.uri.clone()))
+                    .await
+                {
+                    Ok(()) => Some(preallocated),
+                    Err(err) => {
+                        warn!(
+                            "couldn't save the preallocated MXC URI, uploading without it: {err}"
+                        );
+                        None
+                    }
+                }
+            }
+
+            None => None,
+        };
+
+        let Some(preallocated_uri) = preallocated_uri else {
+            let mut upload = media.upload(mime, data, Some(request_config));
+            if let Some(upload_progress) = upload_progress {
+                upload = upload.with_send_progress_observable(upload_progress);
+            }
+            return Ok(upload.await?.content_uri);
+        };
+
+        let uri = preallocated_uri.uri.clone();
+
+        match media
+            .upload_preallocated_inner(
+                preallocated_uri,
+                mime,
+                data,
+                Some(request_config),
+                upload_progress.clone(),
+            )
+            .await
+        {
+            Ok(()) => Ok(uri),
+
+            Err(crate::Error::Media(MediaError::CannotOverwriteMedia)) => {
+                // The server already has the content: a previous attempt has succeeded.
+                debug!(%uri, "media had already been uploaded by a previous attempt");
+                Ok(uri)
+            }
+
+            Err(crate::Error::Media(MediaError::ExpiredPreallocatedMxcUri)) => {
+                // The previous attempt didn't complete in time; forget about the preallocated
+                // URI, and upload the media from scratch.
+                debug!(%uri, "preallocated MXC URI has expired, uploading to a new one");
+
+                if let Err(err) = queue.save_preallocated_uri(transaction_id, None).await {
+                    warn!("couldn't forget about the expired MXC URI: {err}");
+                }
+
+                let data = Self::load_media_content(room, cache_key).await?;
+                let request_config =
+                    RequestConfig::short_retry().timeout(Media::reasonable_upload_timeout(&data));
+                let mut upload = media.upload(mime, data, Some(request_config));
+                if let Some(upload_progress) = upload_progress {
+                    upload = upload.with_send_progress_observable(upload_progress);
+                }
+                Ok(upload.await?.content_uri)
+            }
+
+            Err(err) => Err(err),
+        }
+    }
+
     /// Returns whether the room is enabled, at the room level.
     pub fn is_enabled(&self) -> bool {
         self.inner.locally_enabled.load(Ordering::SeqCst)
@@ -1009,6 +1228,14 @@ struct QueueStorage {
 
     /// To which room is this storage related.
     room_id: OwnedRoomId,
+
+    /// Sizes of the uploads of each media event, keyed by the media event's
+    /// transaction id.
+    ///
+    /// Only kept in memory, to compute the aggregated progress of uploads:
+    /// after a restart, the progress only accounts for the uploads that are
+    /// left.
+    upload_sizes: Arc<StdMutex<HashMap<OwnedTransactionId, MediaUploadSizes>>>,
 }
 
 impl QueueStorage {
@@ -1020,7 +1247,11 @@ impl QueueStorage {
 
     /// Create a new queue for queuing requests to be sent later.
     fn new(client: WeakClient, room: OwnedRoomId) -> Self {
-        Self { room_id: room, store: StoreLock { client, being_sent: Default::default() } }
+        Self {
+            room_id: room,
+            store: StoreLock { client, being_sent: Default::default() },
+            upload_sizes: Default::default(),
+        }
     }
 
     /// Push a new event to be sent in the queue, with a default priority of 0.
@@ -1461,6 +1692,7 @@ impl QueueStorage {
                         cache_key: thumbnail_media_request,
                         thumbnail_source: None, // the thumbnail has no thumbnails :)
                         related_to: send_event_txn.clone(),
+                        preallocated_uri: None,
                         #[cfg(feature = "unstable-msc4274")]
                         accumulated: vec![],
                     },
@@ -1498,6 +1730,7 @@ impl QueueStorage {
                         cache_key: file_media_request,
                         thumbnail_source: None,
                         related_to: send_event_txn,
+                        preallocated_uri: None,
                         #[cfg(feature = "unstable-msc4274")]
                         accumulated: vec![],
                     },
@@ -1563,30 +1796,42 @@ impl QueueStorage {
         let client = guard.client()?;
         let store = client.state_store();
 
-        let local_requests =
-            store.load_send_queue_requests(&self.room_id).await?.into_iter().filter_map(|queued| {
-                Some(LocalEcho {
-                    transaction_id: queued.transaction_id.clone(),
-                    content: match queued.kind {
-                        QueuedRequestKind::Event { content } => LocalEchoContent::Event {
-                            serialized_event: content,
-                            send_handle: SendHandle {
-                                room: room.clone(),
-                                transaction_id: queued.transaction_id,
-                                media_handles: vec![],
-                                created_at: queued.created_at,
-                            },
-                            send_error: queued.error,
-                        },
+        let queued_requests = store.load_send_queue_requests(&self.room_id).await?;
 
-                        QueuedRequestKind::MediaUpload { .. } => {
-                            // Don't return uploaded medias as their own things; the accompanying
-                            // event represented as a dependent request should be sufficient.
-                            return None;
-                        }
+        // Errors of media uploads are reflected onto their media event.
+        let mut upload_errors: HashMap<OwnedTransactionId, QueueWedgeError> = queued_requests
+            .iter()
+            .filter_map(|queued| match (&queued.kind, &queued.error) {
+                (QueuedRequestKind::MediaUpload { related_to, .. }, Some(error)) => {
+                    Some((related_to.clone(), error.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        let local_requests = queued_requests.into_iter().filter_map(|queued| {
+            Some(LocalEcho {
+                transaction_id: queued.transaction_id.clone(),
+                content: match queued.kind {
+                    QueuedRequestKind::Event { content } => LocalEchoContent::Event {
+                        serialized_event: content,
+                        send_handle: SendHandle {
+                            room: room.clone(),
+                            transaction_id: queued.transaction_id,
+                            media_handles: vec![],
+                            created_at: queued.created_at,
+                        },
+                        send_error: queued.error,
                     },
-                })
-            });
+
+                    QueuedRequestKind::MediaUpload { .. } => {
+                        // Don't return uploaded medias as their own things; the accompanying
+                        // event represented as a dependent request should be sufficient.
+                        return None;
+                    }
+                },
+            })
+        });
 
         let reactions_and_medias = store
             .load_dependent_queued_requests(&self.room_id)
@@ -1622,6 +1867,7 @@ impl QueueStorage {
                     thumbnail_info,
                 } => {
                     // Materialize as an event local echo.
+                    let send_error = upload_errors.remove(&*dep.own_transaction_id);
                     Some(LocalEcho {
                         transaction_id: dep.own_transaction_id.clone().into(),
                         content: LocalEchoContent::Event {
@@ -1636,7 +1882,7 @@ impl QueueStorage {
                                 }],
                                 created_at: dep.created_at,
                             },
-                            send_error: None,
+                            send_error,
                         },
                     })
                 }
@@ -1644,12 +1890,14 @@ impl QueueStorage {
                 #[cfg(feature = "unstable-msc4274")]
                 DependentQueuedRequestKind::FinishGallery { local_echo, item_infos } => {
                     // Materialize as an event local echo.
+                    let send_error = upload_errors.remove(&*dep.own_transaction_id);
                     self.create_gallery_local_echo(
                         dep.own_transaction_id,
                         room,
                         dep.created_at,
                         local_echo,
                         item_infos,
+                        send_error,
                     )
                 }
             });
@@ -1666,6 +1914,7 @@ impl QueueStorage {
         created_at: MilliSecondsSinceUnixEpoch,
         local_echo: Box<RoomMessageEventContent>,
         item_infos: Vec<FinishGalleryItemInfo>,
+        send_error: Option<QueueWedgeError>,
     ) -> Option<LocalEcho> {
         Some(LocalEcho {
             transaction_id: transaction_id.clone().into(),
@@ -1683,7 +1932,7 @@ impl QueueStorage {
                         .collect(),
                     created_at,
                 },
-                send_error: None,
+                send_error,
             },
         })
     }
@@ -2144,6 +2393,28 @@ pub enum RoomSendQueueUpdate {
         /// The final media source for the file that was just uploaded.
         file: MediaSource,
     },
+
+    /// A media upload has made progress.
+    ///
+    /// Only sent if it's been enabled with
+    /// [`SendQueue::enable_upload_progress`].
+    MediaUpload {
+        /// The media event this upload relates to.
+        related_to: OwnedTransactionId,
+
+        /// Whether the media being uploaded is a thumbnail, or a file.
+        is_thumbnail: bool,
+
+        /// Progress of the current upload (thumbnail or file), in bytes.
+        progress: TransmissionProgress,
+
+        /// Aggregated progress of all the uploads of the media event, in
+        /// bytes.
+        ///
+        /// This is a best effort estimate: uploads that completed before the
+        /// app restarted aren't taken into account.
+        total_progress: TransmissionProgress,
+    },
 }
 
 /// A [`RoomSendQueueUpdate`] with an associated [`OwnedRoomId`].
@@ -2280,6 +2551,8 @@ impl SendHandle {
 
         for handles in &self.media_handles {
             if queue.abort_upload(&self.transaction_id, handles).await? {
+                queue.forget_upload_sizes(&self.transaction_id);
+
                 // Propagate a cancelled update.
                 self.room.send_update(RoomSendQueueUpdate::CancelledLocalEvent {
                     transaction_id: self.transaction_id.clone(),
@@ -2308,6 +2581,76 @@ impl SendHandle {
         }
     }
 
+    /// Pauses the upload of the media attached to this local echo, if it
+    /// hasn't completed yet.
+    ///
+    /// The upload is interrupted if it's in progress, and the upload request
+    /// is marked as wedged with [`QueueWedgeError::UploadPaused`]; this state
+    /// persists across restarts of the app. Use [`Self::restart_upload`] to
+    /// upload the media again.
+    ///
+    /// Matrix doesn't support partial uploads, so the upload can't be resumed
+    /// where it stopped: the interrupted file (or thumbnail) is uploaded again
+    /// from the beginning when restarting.
+    ///
+    /// Returns true if an upload has been paused, false if there was no upload
+    /// left to pause.
+    #[instrument(skip(self), fields(room_id = %self.room.inner.room.room_id(), txn_id = %self.transaction_id))]
+    pub async fn pause_upload(&self) -> Result<bool, RoomSendQueueStorageError> {
+        trace!("received a pause upload request");
+
+        for handles in &self.media_handles {
+            if self.room.inner.queue.pause_upload(handles).await? {
+                trace!("successful pause");
+
+                self.room.send_update(RoomSendQueueUpdate::SendError {
+                    transaction_id: self.transaction_id.clone(),
+                    error: Arc::new(crate::Error::SendQueueWedgeError(Box::new(
+                        QueueWedgeError::UploadPaused,
+                    ))),
+                    is_recoverable: false,
+                });
+
+                return Ok(true);
+            }
+        }
+
+        debug!("no upload left to pause");
+        Ok(false)
+    }
+
+    /// Restarts the upload of the media attached to this local echo, after it
+    /// was paused with [`Self::pause_upload`].
+    ///
+    /// The interrupted file (or thumbnail) is uploaded again from the
+    /// beginning; the uploads of this local echo that had already completed
+    /// aren't repeated.
+    ///
+    /// Returns true if an upload has been restarted, false if there was no
+    /// paused upload.
+    #[instrument(skip(self), fields(room_id = %self.room.inner.room.room_id(), txn_id = %self.transaction_id))]
+    pub async fn restart_upload(&self) -> Result<bool, RoomSendQueueStorageError> {
+        trace!("received a restart upload request");
+
+        for handles in &self.media_handles {
+            if self.room.inner.queue.restart_upload(handles).await? {
+                trace!("successful restart");
+
+                // Wake up the queue, in case the room was asleep while the upload was paused.
+                self.room.inner.notifier.notify_one();
+
+                self.room.send_update(RoomSendQueueUpdate::RetryEvent {
+                    transaction_id: self.transaction_id.clone(),
+                });
+
+                return Ok(true);
+            }
+        }
+
+        debug!("no paused upload to restart");
+        Ok(false)
+    }
+
     /// Edits the content of a local echo with a raw event content.
     ///
     /// Returns true if the event to be sent was replaced, false if not (i.e.
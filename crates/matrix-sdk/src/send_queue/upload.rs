@@ -14,14 +14,15 @@
 
 //! Private implementations of the media upload mechanism.
 
+use std::collections::HashMap;
 #[cfg(feature = "unstable-msc4274")]
-use std::{collections::HashMap, iter::zip};
+use std::iter::zip;
 
 use matrix_sdk_base::{
     event_cache::store::media::IgnoreMediaRetentionPolicy,
     media::{MediaFormat, MediaRequestParameters},
     store::{
-        ChildTransactionId, DependentQueuedRequestKind, FinishUploadThumbnailInfo,
+        ChildTransactionId, DependentQueuedRequestKind, FinishUploadThumbnailInfo, QueueWedgeError,
         QueuedRequestKind, SentMediaInfo, SentRequestKey, SerializableEventContent,
     },
     RoomState,
@@ -42,7 +43,7 @@ use ruma::{
         },
         AnyMessageLikeEventContent, Mentions,
     },
-    MilliSecondsSinceUnixEpoch, OwnedTransactionId, TransactionId,
+    MilliSecondsSinceUnixEpoch, OwnedMxcUri, OwnedTransactionId, TransactionId,
 };
 use tracing::{debug, error, instrument, trace, warn, Span};
 
@@ -54,7 +55,7 @@ use crate::{
         LocalEcho, LocalEchoContent, MediaHandles, RoomSendQueueStorageError, RoomSendQueueUpdate,
         SendHandle,
     },
-    Client, Media, Room,
+    Client, Media, Room, TransmissionProgress,
 };
 #[cfg(feature = "unstable-msc4274")]
 use crate::{
@@ -218,6 +219,9 @@ impl RoomSendQueue {
 
         let file_media_request = Media::make_local_file_media_request(&upload_file_txn);
 
+        let file_size = data.len();
+        let thumbnail_size = config.thumbnail.as_ref().map(|thumbnail| thumbnail.data.len());
+
         let MediaCacheResult { upload_thumbnail_txn, event_thumbnail_info, queue_thumbnail_info } =
             RoomSendQueue::cache_media(&room, data, config.thumbnail.take(), &file_media_request)
                 .await?;
@@ -256,6 +260,15 @@ impl RoomSendQueue {
             )
             .await?;
 
+        self.inner.queue.register_upload_sizes(
+            &send_event_txn,
+            upload_thumbnail_txn
+                .clone()
+                .zip(thumbnail_size)
+                .into_iter()
+                .chain([(upload_file_txn.clone(), file_size)]),
+        );
+
         trace!("manager sends a media to the background task");
 
         self.inner.notifier.notify_one();
@@ -325,6 +338,7 @@ impl RoomSendQueue {
         let mut item_types = Vec::with_capacity(gallery.len());
         let mut item_queue_infos = Vec::with_capacity(gallery.len());
         let mut media_handles = Vec::with_capacity(gallery.len());
+        let mut upload_sizes = Vec::with_capacity(gallery.len() * 2);
 
         for item_info in gallery.items {
             let GalleryItemInfo { filename, content_type, data, .. } = item_info;
@@ -335,6 +349,9 @@ impl RoomSendQueue {
 
             let file_media_request = Media::make_local_file_media_request(&upload_file_txn);
 
+            let file_size = data.len();
+            let thumbnail_size = item_info.thumbnail.as_ref().map(|thumbnail| thumbnail.data.len());
+
             let MediaCacheResult {
                 upload_thumbnail_txn,
                 event_thumbnail_info,
@@ -359,6 +376,9 @@ impl RoomSendQueue {
                 thumbnail: queue_thumbnail_info,
             });
 
+            upload_sizes.extend(upload_thumbnail_txn.clone().zip(thumbnail_size));
+            upload_sizes.push((upload_file_txn.clone(), file_size));
+
             media_handles.push(MediaHandles { upload_file_txn, upload_thumbnail_txn });
         }
 
@@ -389,6 +409,8 @@ impl RoomSendQueue {
             )
             .await?;
 
+        self.inner.queue.register_upload_sizes(&send_event_txn, upload_sizes);
+
         trace!("manager sends a gallery to the background task");
 
         self.inner.notifier.notify_one();
@@ -655,6 +677,7 @@ impl QueueStorage {
             // upload.
             thumbnail_source: parent_is_thumbnail_upload.then_some(sent_media.file),
             related_to: event_txn,
+            preallocated_uri: None,
             #[cfg(feature = "unstable-msc4274")]
             accumulated,
         };
@@ -800,6 +823,214 @@ impl QueueStorage {
         Ok(true)
     }
 
+    /// Pause the upload of a media, if it hasn't completed yet.
+    ///
+    /// The pending upload request (either the thumbnail or the file itself) is
+    /// marked as wedged, and the upload is interrupted if it's in progress.
+    ///
+    /// Returns true if an upload has been paused, false if both uploads have
+    /// completed already, or the upload was already wedged.
+    #[instrument(skip(self, handles))]
+    pub(super) async fn pause_upload(
+        &self,
+        handles: &MediaHandles,
+    ) -> Result<bool, RoomSendQueueStorageError> {
+        let mut guard = self.store.lock().await;
+        let client = guard.client()?;
+        let store = client.state_store();
+
+        // Only one of the thumbnail or file upload can be an actual request at any
+        // time; the other one is either done, or a dependent request.
+        let Some(request) =
+            store.load_send_queue_requests(&self.room_id).await?.into_iter().find(|request| {
+                request.transaction_id == handles.upload_file_txn
+                    || handles.upload_thumbnail_txn.as_ref() == Some(&request.transaction_id)
+            })
+        else {
+            trace!("no pending upload request");
+            return Ok(false);
+        };
+
+        if request.is_wedged() {
+            trace!("upload request was already wedged");
+            return Ok(false);
+        }
+
+        if guard
+            .being_sent
+            .as_ref()
+            .is_some_and(|info| info.transaction_id == request.transaction_id)
+        {
+            // SAFETY: we knew it was Some(), in the condition above.
+            let info = guard.being_sent.take().unwrap();
+            if info.cancel_upload() {
+                trace!("interrupted ongoing upload");
+            }
+        }
+
+        store
+            .update_send_queue_request_status(
+                &self.room_id,
+                &request.transaction_id,
+                Some(QueueWedgeError::UploadPaused),
+            )
+            .await?;
+
+        debug!(txn_id = %request.transaction_id, "successfully paused upload");
+        Ok(true)
+    }
+
+    /// Restart the upload of a media that was paused with
+    /// [`Self::pause_upload`].
+    ///
+    /// The pending upload request is unwedged, so it's sent again from the
+    /// beginning.
+    ///
+    /// Returns true if an upload has been restarted, false if there was no
+    /// paused upload.
+    #[instrument(skip(self, handles))]
+    pub(super) async fn restart_upload(
+        &self,
+        handles: &MediaHandles,
+    ) -> Result<bool, RoomSendQueueStorageError> {
+        let guard = self.store.lock().await;
+        let client = guard.client()?;
+        let store = client.state_store();
+
+        let Some(request) =
+            store.load_send_queue_requests(&self.room_id).await?.into_iter().find(|request| {
+                request.transaction_id == handles.upload_file_txn
+                    || handles.upload_thumbnail_txn.as_ref() == Some(&request.transaction_id)
+            })
+        else {
+            trace!("no pending upload request");
+            return Ok(false);
+        };
+
+        if !matches!(request.error, Some(QueueWedgeError::UploadPaused)) {
+            trace!("upload request wasn't paused");
+            return Ok(false);
+        }
+
+        store.update_send_queue_request_status(&self.room_id, &request.transaction_id, None).await?;
+
+        debug!(txn_id = %request.transaction_id, "successfully restarted upload");
+        Ok(true)
+    }
+
+    /// Returns whether the upload request identified by the given transaction
+    /// id is the upload of a thumbnail.
+    pub(super) async fn is_thumbnail_upload(
+        &self,
+        upload_txn: &TransactionId,
+    ) -> Result<bool, RoomSendQueueStorageError> {
+        let guard = self.store.lock().await;
+        let dependent_requests =
+            guard.client()?.state_store().load_dependent_queued_requests(&self.room_id).await?;
+
+        // The request finishing the media upload records which upload is its thumbnail.
+        let is_thumbnail = |info: &Option<FinishUploadThumbnailInfo>| {
+            info.as_ref().is_some_and(|info| info.txn == upload_txn)
+        };
+
+        Ok(dependent_requests.iter().any(|dependent| match &dependent.kind {
+            DependentQueuedRequestKind::FinishUpload { thumbnail_info, .. } => {
+                is_thumbnail(thumbnail_info)
+            }
+            #[cfg(feature = "unstable-msc4274")]
+            DependentQueuedRequestKind::FinishGallery { item_infos, .. } => {
+                item_infos.iter().any(|item| is_thumbnail(&item.thumbnail_info))
+            }
+            _ => false,
+        }))
+    }
+
+    /// Saves the MXC URI preallocated for an upload request, or forgets about
+    /// it, if `uri` is `None`.
+    pub(super) async fn save_preallocated_uri(
+        &self,
+        upload_txn: &TransactionId,
+        uri: Option<OwnedMxcUri>,
+    ) -> Result<(), RoomSendQueueStorageError> {
+        let guard = self.store.lock().await;
+        let client = guard.client()?;
+        let store = client.state_store();
+
+        let Some(mut request) = store
+            .load_send_queue_requests(&self.room_id)
+            .await?
+            .into_iter()
+            .find(|request| request.transaction_id == upload_txn)
+        else {
+            warn!(%upload_txn, "couldn't find the upload request to save a preallocated URI");
+            return Ok(());
+        };
+
+        if let QueuedRequestKind::MediaUpload { preallocated_uri, .. } = &mut request.kind {
+            *preallocated_uri = uri;
+            store.update_send_queue_request(&self.room_id, upload_txn, request.kind).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Remembers the sizes of the uploads of a media event, so as to report an
+    /// aggregated upload progress.
+    pub(super) fn register_upload_sizes(
+        &self,
+        event_txn: &TransactionId,
+        sizes: impl IntoIterator<Item = (OwnedTransactionId, usize)>,
+    ) {
+        self.upload_sizes
+            .lock()
+            .unwrap()
+            .entry(event_txn.to_owned())
+            .or_default()
+            .pending
+            .extend(sizes);
+    }
+
+    /// Computes the aggregated progress of all the uploads of a media event,
+    /// given the progress of one of its uploads.
+    pub(super) fn aggregated_upload_progress(
+        &self,
+        event_txn: &TransactionId,
+        upload_txn: &TransactionId,
+        progress: TransmissionProgress,
+    ) -> TransmissionProgress {
+        let mut upload_sizes = self.upload_sizes.lock().unwrap();
+        let sizes = upload_sizes.entry(event_txn.to_owned()).or_default();
+
+        // The size of the request body is the most accurate one (e.g. for encrypted
+        // media).
+        sizes.pending.insert(upload_txn.to_owned(), progress.total);
+
+        TransmissionProgress {
+            current: sizes.uploaded + progress.current,
+            total: sizes.uploaded + sizes.pending.values().sum::<usize>(),
+        }
+    }
+
+    /// Marks one of the uploads of a media event as done, for the purpose of
+    /// computing the aggregated upload progress.
+    pub(super) fn mark_upload_as_done(
+        &self,
+        event_txn: &TransactionId,
+        upload_txn: &TransactionId,
+    ) {
+        if let Some(sizes) = self.upload_sizes.lock().unwrap().get_mut(event_txn) {
+            if let Some(size) = sizes.pending.remove(upload_txn) {
+                sizes.uploaded += size;
+            }
+        }
+    }
+
+    /// Forgets about the upload sizes of a media event, once it's been sent or
+    /// aborted.
+    pub(super) fn forget_upload_sizes(&self, event_txn: &TransactionId) {
+        self.upload_sizes.lock().unwrap().remove(event_txn);
+    }
+
     #[instrument(skip(self, caption, formatted_caption))]
     pub(super) async fn edit_media_caption(
         &self,
@@ -920,6 +1151,17 @@ impl QueueStorage {
     }
 }
 
+/// Sizes of the uploads (thumbnails and files) of a media event, in bytes.
+#[derive(Debug, Default)]
+pub(super) struct MediaUploadSizes {
+    /// Sizes of the uploads that haven't completed yet, keyed by the upload
+    /// transaction id.
+    pending: HashMap<OwnedTransactionId, usize>,
+
+    /// Total size of the uploads that have completed.
+    uploaded: usize,
+}
+
 /// Update cache keys in the cache store after uploading a media file /
 /// thumbnail.
 async fn update_media_cache_keys_after_upload(
@@ -18,6 +18,11 @@ use matrix_sdk_base::{
     store::{RoomLoadSettings, StoreConfig},
     SessionMeta,
 };
+#[cfg(not(target_family = "wasm"))]
+use matrix_sdk_test::{
+    homeserver::{FakeHomeserver, FakeSession},
+    sync_replay::SyncReplayServer,
+};
 use ruma::{api::MatrixVersion, owned_device_id, owned_user_id, OwnedDeviceId, OwnedUserId};
 
 use crate::{
@@ -70,6 +75,12 @@ impl MockClientBuilder {
         self
     }
 
+    /// Enable the offline-first mode for the Client.
+    pub fn offline_first(mut self) -> Self {
+        self.builder = self.builder.with_offline_first(true);
+        self
+    }
+
     /// Use the given encryption settings with the test client.
     #[cfg(feature = "e2e-encryption")]
     pub fn with_encryption_settings(
@@ -121,6 +132,29 @@ impl MockClientBuilder {
         self
     }
 
+    /// Create a new [`MockClientBuilder`] connected to the given
+    /// [`FakeHomeserver`], and logged in with the given session.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn for_fake_homeserver(homeserver: &FakeHomeserver, session: FakeSession) -> Self {
+        Self::new(Some(&homeserver.uri())).logged_in_with_token(
+            session.access_token,
+            session.user_id,
+            session.device_id,
+        )
+    }
+
+    /// Create a new [`MockClientBuilder`] connected to the given
+    /// [`SyncReplayServer`], and logged in with the session the replayed
+    /// recording was made with.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn for_sync_replay(server: &SyncReplayServer) -> Self {
+        Self::new(Some(&server.uri())).logged_in_with_token(
+            "1234".to_owned(),
+            server.user_id().clone(),
+            server.device_id().clone(),
+        )
+    }
+
     /// Override the default [`RequestConfig`] for the underlying
     /// [`ClientBuilder`].
     pub fn request_config(mut self, request_config: RequestConfig) -> Self {
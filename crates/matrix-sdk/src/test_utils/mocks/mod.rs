@@ -25,12 +25,19 @@ use std::{
 use js_int::UInt;
 use matrix_sdk_base::deserialized_responses::TimelineEvent;
 use matrix_sdk_test::{
-    test_json, InvitedRoomBuilder, JoinedRoomBuilder, KnockedRoomBuilder, LeftRoomBuilder,
-    SyncResponseBuilder,
+    push_gateway::PushNotificationBuilder, test_json, InvitedRoomBuilder, JoinedRoomBuilder,
+    KnockedRoomBuilder, LeftRoomBuilder, SyncResponseBuilder,
 };
 use percent_encoding::{AsciiSet, CONTROLS};
 use ruma::{
-    api::client::{receipt::create_receipt::v3::ReceiptType, room::Visibility},
+    api::{
+        client::{
+            push::{Pusher, PusherKind},
+            receipt::create_receipt::v3::ReceiptType,
+            room::Visibility,
+        },
+        Direction,
+    },
     device_id,
     directory::PublicRoomsChunk,
     encryption::{CrossSigningKey, DeviceKeys, OneTimeKey},
@@ -40,6 +47,7 @@ use ruma::{
         RoomAccountDataEventType, StateEventType,
     },
     media::Method,
+    push::PushFormat,
     serde::Raw,
     time::Duration,
     DeviceId, EventId, MilliSecondsSinceUnixEpoch, MxcUri, OwnedDeviceId, OwnedEventId,
@@ -582,6 +590,14 @@ impl MatrixMockServer {
             .expect_default_access_token()
     }
 
+    /// Creates a prebuilt mock for finding the event closest to a timestamp
+    /// with /room/.../timestamp_to_event.
+    pub fn mock_room_timestamp_to_event(&self) -> MockEndpoint<'_, RoomTimestampToEventEndpoint> {
+        let mock = Mock::given(method("GET"))
+            .and(path_regex(r"^/_matrix/client/.*/rooms/.*/timestamp_to_event$"));
+        self.mock_endpoint(mock, RoomTimestampToEventEndpoint).expect_default_access_token()
+    }
+
     /// Create a prebuild mock for paginating room message with the `/messages`
     /// endpoint.
     pub fn mock_room_messages(&self) -> MockEndpoint<'_, RoomMessagesEndpoint> {
@@ -1329,6 +1345,94 @@ impl MatrixMockServer {
             .and(query_param("animated", animated.to_string()));
         self.mock_endpoint(mock, AuthedMediaThumbnailEndpoint).expect_default_access_token()
     }
+
+    /// Create a prebuilt mock for the endpoint used to set or delete a pusher.
+    pub fn mock_set_pusher(&self) -> MockEndpoint<'_, SetPusherEndpoint> {
+        let mock = Mock::given(method("POST")).and(path("/_matrix/client/v3/pushers/set"));
+        self.mock_endpoint(mock, SetPusherEndpoint).expect_default_access_token()
+    }
+
+    /// Simulate the homeserver sending a push notification for the given HTTP
+    /// pusher, to the push gateway at the pusher's URL.
+    ///
+    /// The devices of the notification are filled from the pusher, and the
+    /// notification is stripped down if the pusher uses the `event_id_only`
+    /// format.
+    ///
+    /// Returns the push keys rejected by the gateway.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # tokio_test::block_on(async {
+    /// use matrix_sdk::{
+    ///     pusher::HttpPusherBuilder,
+    ///     ruma::{event_id, room_id},
+    ///     test_utils::mocks::MatrixMockServer,
+    /// };
+    /// use matrix_sdk_test::push_gateway::{PushGateway, PushNotificationBuilder};
+    ///
+    /// let mock_server = MatrixMockServer::new().await;
+    /// let gateway = PushGateway::new().await;
+    ///
+    /// let pusher = HttpPusherBuilder::new(
+    ///     "push-key",
+    ///     "io.element.app",
+    ///     gateway.notify_url(),
+    /// )
+    /// .build();
+    ///
+    /// let rejected = mock_server
+    ///     .send_push_notification(
+    ///         &pusher,
+    ///         PushNotificationBuilder::new(
+    ///             room_id!("!room:localhost"),
+    ///             event_id!("$event"),
+    ///         ),
+    ///     )
+    ///     .await;
+    ///
+    /// assert!(rejected.is_empty());
+    /// assert_eq!(gateway.received_notifications().await.len(), 1);
+    /// # anyhow::Ok(()) });
+    /// ```
+    pub async fn send_push_notification(
+        &self,
+        pusher: &Pusher,
+        notification: PushNotificationBuilder,
+    ) -> Vec<String> {
+        let PusherKind::Http(http) = &pusher.kind else {
+            panic!("only HTTP pushers can receive notifications from a push gateway");
+        };
+
+        let mut notification =
+            notification.device(&pusher.ids.app_id, &pusher.ids.pushkey, json!(http.data));
+        if http.format == Some(PushFormat::EventIdOnly) {
+            notification = notification.event_id_only();
+        }
+
+        let response = reqwest::Client::new()
+            .post(&http.url)
+            .header("content-type", "application/json")
+            .body(notification.into_request_body().to_string())
+            .send()
+            .await
+            .expect("the push gateway should be reachable")
+            .error_for_status()
+            .expect("the push gateway should accept the notification");
+
+        let body: Value = serde_json::from_slice(
+            &response.bytes().await.expect("the push gateway response should have a body"),
+        )
+        .expect("the push gateway response should be JSON");
+
+        body["rejected"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|push_key| push_key.as_str().map(ToOwned::to_owned))
+            .collect()
+    }
 }
 
 /// Parameter to [`MatrixMockServer::sync_room`].
@@ -2480,6 +2584,33 @@ impl<'a> MockEndpoint<'a, RoomEventContextEndpoint> {
     }
 }
 
+/// A prebuilt mock for the `/timestamp_to_event` endpoint.
+pub struct RoomTimestampToEventEndpoint;
+
+impl<'a> MockEndpoint<'a, RoomTimestampToEventEndpoint> {
+    /// Expects the given direction to be set on the request.
+    pub fn match_direction(self, direction: Direction) -> Self {
+        let direction = match direction {
+            Direction::Backward => "b",
+            Direction::Forward => "f",
+        };
+        Self { mock: self.mock.and(query_param("dir", direction)), ..self }
+    }
+
+    /// Returns an endpoint that emulates success, i.e. the event closest to
+    /// the timestamp is the given one.
+    pub fn ok(
+        self,
+        event_id: impl Into<OwnedEventId>,
+        origin_server_ts: MilliSecondsSinceUnixEpoch,
+    ) -> MatrixMock<'a> {
+        self.respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "event_id": event_id.into(),
+            "origin_server_ts": origin_server_ts,
+        })))
+    }
+}
+
 /// A prebuilt mock for the `/messages` endpoint.
 pub struct RoomMessagesEndpoint;
 
@@ -3650,6 +3781,15 @@ impl<'a> MockEndpoint<'a, MediaAllocatedUploadEndpoint> {
     pub fn ok(self) -> MatrixMock<'a> {
         self.respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
     }
+
+    /// Returns an error response indicating that the media at the
+    /// pre-allocated MXC URI already has content.
+    pub fn error_cannot_overwrite_media(self) -> MatrixMock<'a> {
+        self.respond_with(ResponseTemplate::new(409).set_body_json(json!({
+            "errcode": "M_CANNOT_OVERWRITE_MEDIA",
+            "error": "Media ID already has content"
+        })))
+    }
 }
 
 /// A prebuilt mock for `GET /media/v3/download` requests.
@@ -3674,6 +3814,40 @@ impl<'a> MockEndpoint<'a, MediaDownloadEndpoint> {
             ResponseTemplate::new(200).set_body_raw(b"binaryjpegfullimagedata", "image/jpeg"),
         )
     }
+
+    /// Ensures that the request asks for the content starting at the given
+    /// offset, with a `Range` header.
+    pub fn match_range_start(self, start: usize) -> Self {
+        Self { mock: self.mock.and(header("range", format!("bytes={start}-"))), ..self }
+    }
+
+    /// Ensures that the request only asks for a part of the content if it
+    /// still has the given `ETag`, with an `If-Range` header.
+    pub fn match_if_range(self, etag: &str) -> Self {
+        Self { mock: self.mock.and(header("if-range", etag)), ..self }
+    }
+
+    /// Returns a successful response with the given bytes and `ETag`.
+    pub fn ok_bytes_with_etag(self, bytes: Vec<u8>, etag: &str) -> MatrixMock<'a> {
+        self.respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("etag", etag)
+                .set_body_raw(bytes, "application/octet-stream"),
+        )
+    }
+
+    /// Returns a `206 Partial Content` response with the part of the given
+    /// bytes starting at `start`.
+    pub fn ok_partial_bytes(self, bytes: Vec<u8>, start: usize) -> MatrixMock<'a> {
+        let total = bytes.len();
+        let content_range = format!("bytes {start}-{}/{total}", total - 1);
+
+        self.respond_with(
+            ResponseTemplate::new(206)
+                .insert_header("content-range", content_range)
+                .set_body_raw(bytes[start..].to_vec(), "application/octet-stream"),
+        )
+    }
 }
 
 /// A prebuilt mock for `GET /media/v3/thumbnail` requests.
@@ -3732,3 +3906,13 @@ impl<'a> MockEndpoint<'a, JoinRoomEndpoint> {
         })))
     }
 }
+
+/// A prebuilt mock for `POST /pushers/set` requests.
+pub struct SetPusherEndpoint;
+
+impl<'a> MockEndpoint<'a, SetPusherEndpoint> {
+    /// Returns a successful empty response.
+    pub fn ok(self) -> MatrixMock<'a> {
+        self.respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+    }
+}
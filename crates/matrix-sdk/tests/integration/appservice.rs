@@ -0,0 +1,329 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use assert_matches2::assert_matches;
+use matrix_sdk::{
+    appservice::{AppService, AppServiceError, AppServiceRegistration},
+    config::RequestConfig,
+    Client, RoomState,
+};
+use matrix_sdk_test::async_test;
+use ruma::{
+    api::{appservice::Registration, MatrixVersion},
+    events::room::message::OriginalSyncRoomMessageEvent,
+    room_id, server_name, user_id,
+};
+use serde_json::{json, Value as JsonValue};
+use tokio::net::TcpListener;
+use wiremock::{
+    matchers::{header, method, path, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+fn client_builder() -> matrix_sdk::ClientBuilder {
+    Client::builder()
+        .request_config(RequestConfig::new().disable_retry())
+        .server_versions([MatrixVersion::V1_12])
+}
+
+async fn appservice(server: &MockServer) -> AppService {
+    let registration = serde_json::from_value::<Registration>(json!({
+        "id": "bridge",
+        "url": "http://localhost:9000",
+        "as_token": "as_token",
+        "hs_token": "hs_token",
+        "sender_localpart": "_bridge_bot",
+        "namespaces": {
+            "users": [{ "exclusive": true, "regex": r"@_bridge_.*:example\.org" }],
+            "aliases": [],
+            "rooms": [],
+        },
+    }))
+    .unwrap();
+    let registration = AppServiceRegistration::try_from(registration).unwrap();
+
+    AppService::builder(server.uri(), server_name!("example.org"), registration)
+        .client_builder(client_builder())
+        .virtual_user_client_builder(|_| client_builder())
+        .build()
+        .await
+        .unwrap()
+}
+
+/// Serve the router of the application service on a random port, and return
+/// the URL of the transactions endpoint.
+async fn serve(appservice: &AppService) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let router = appservice.router();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    format!("http://{address}/_matrix/app/v1/transactions")
+}
+
+fn transaction() -> JsonValue {
+    json!({
+        "events": [
+            {
+                "type": "m.room.member",
+                "event_id": "$join",
+                "room_id": "!room:example.org",
+                "sender": "@_bridge_bot:example.org",
+                "state_key": "@_bridge_bot:example.org",
+                "origin_server_ts": 0,
+                "content": { "membership": "join" },
+            },
+            {
+                "type": "m.room.message",
+                "event_id": "$message",
+                "room_id": "!room:example.org",
+                "sender": "@_bridge_alice:example.org",
+                "origin_server_ts": 1,
+                "content": { "msgtype": "m.text", "body": "Hello bridge!" },
+            },
+        ],
+    })
+}
+
+#[async_test]
+async fn test_receive_transaction() {
+    let server = MockServer::start().await;
+    let appservice = appservice(&server).await;
+
+    assert_eq!(appservice.user_id(), user_id!("@_bridge_bot:example.org"));
+    assert_eq!(appservice.device_id(), "bridge");
+
+    let received = Arc::new(AtomicUsize::new(0));
+    appservice.client().add_event_handler({
+        let received = received.clone();
+        move |event: OriginalSyncRoomMessageEvent| {
+            let received = received.clone();
+            async move {
+                assert_eq!(event.content.body(), "Hello bridge!");
+                received.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    });
+
+    let url = serve(&appservice).await;
+    let http_client = reqwest::Client::new();
+
+    let response = http_client
+        .put(format!("{url}/txn1"))
+        .bearer_auth("hs_token")
+        .body(transaction().to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "{}");
+
+    assert_eq!(received.load(Ordering::SeqCst), 1);
+    let room = appservice.client().get_room(room_id!("!room:example.org")).unwrap();
+    assert_eq!(room.state(), RoomState::Joined);
+
+    // A transaction sent again isn't processed again.
+    let response = http_client
+        .put(format!("{url}/txn1"))
+        .bearer_auth("hs_token")
+        .body(transaction().to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(received.load(Ordering::SeqCst), 1);
+}
+
+#[async_test]
+async fn test_receive_transaction_with_invalid_token() {
+    let server = MockServer::start().await;
+    let appservice = appservice(&server).await;
+    let url = serve(&appservice).await;
+    let http_client = reqwest::Client::new();
+
+    let response = http_client
+        .put(format!("{url}/txn1"))
+        .body(transaction().to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+
+    let response = http_client
+        .put(format!("{url}/txn1"))
+        .bearer_auth("as_token")
+        .body(transaction().to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+
+    assert!(appservice.client().get_room(room_id!("!room:example.org")).is_none());
+}
+
+#[async_test]
+async fn test_virtual_user() {
+    let server = MockServer::start().await;
+    let appservice = appservice(&server).await;
+
+    assert_matches!(
+        appservice.virtual_user("alice").await,
+        Err(AppServiceError::UserNotInNamespace(user_id))
+    );
+    assert_eq!(user_id, "@alice:example.org");
+
+    let alice = appservice.virtual_user("_bridge_alice").await.unwrap();
+    assert_eq!(alice.client().user_id().unwrap(), "@_bridge_alice:example.org");
+    assert_eq!(alice.user_id(), "@_bridge_alice:example.org");
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/register"))
+        .and(header("authorization", "Bearer as_token"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+            "errcode": "M_USER_IN_USE",
+            "error": "User ID already taken.",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    alice.register().await.unwrap();
+
+    Mock::given(method("PUT"))
+        .and(path("/_matrix/client/v3/profile/@_bridge_alice:example.org/displayname"))
+        .and(header("authorization", "Bearer as_token"))
+        .and(query_param("user_id", "@_bridge_alice:example.org"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    alice.set_display_name(Some("Alice (bridged)")).await.unwrap();
+}
+
+#[async_test]
+async fn test_receive_transaction_for_virtual_user() {
+    let server = MockServer::start().await;
+    let appservice = appservice(&server).await;
+
+    // A virtual user joins a room that the user of the application service isn't
+    // in.
+    let transaction = json!({
+        "events": [
+            {
+                "type": "m.room.member",
+                "event_id": "$join",
+                "room_id": "!bridged:example.org",
+                "sender": "@_bridge_bob:example.org",
+                "state_key": "@_bridge_bob:example.org",
+                "origin_server_ts": 0,
+                "content": { "membership": "join" },
+            },
+            {
+                "type": "m.room.message",
+                "event_id": "$message",
+                "room_id": "!bridged:example.org",
+                "sender": "@alice:example.org",
+                "origin_server_ts": 1,
+                "content": { "msgtype": "m.text", "body": "Hello Bob!" },
+            },
+        ],
+    });
+
+    let url = serve(&appservice).await;
+    let response = reqwest::Client::new()
+        .put(format!("{url}/txn2"))
+        .bearer_auth("hs_token")
+        .body(transaction.to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    // The room is only known by the client of the virtual user.
+    let room_id = room_id!("!bridged:example.org");
+    assert!(appservice.client().get_room(room_id).is_none());
+
+    let bob = appservice.virtual_user("_bridge_bob").await.unwrap();
+    let room = bob.client().get_room(room_id).unwrap();
+    assert_eq!(room.state(), RoomState::Joined);
+
+    // The requests of the virtual user are sent on their behalf.
+    Mock::given(method("PUT"))
+        .and(path("/_matrix/client/v3/profile/@_bridge_bob:example.org/displayname"))
+        .and(header("authorization", "Bearer as_token"))
+        .and(query_param("user_id", "@_bridge_bob:example.org"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    bob.client().account().set_display_name(Some("Bob (bridged)")).await.unwrap();
+}
+
+#[async_test]
+async fn test_virtual_user_rejects_encrypted_rooms() {
+    let server = MockServer::start().await;
+    let appservice = appservice(&server).await;
+
+    // The user of the application service and a virtual user are in an encrypted
+    // room.
+    let room_id = room_id!("!encrypted:example.org");
+    let transaction = json!({
+        "events": [
+            {
+                "type": "m.room.member",
+                "event_id": "$join_bot",
+                "room_id": room_id,
+                "sender": "@_bridge_bot:example.org",
+                "state_key": "@_bridge_bot:example.org",
+                "origin_server_ts": 0,
+                "content": { "membership": "join" },
+            },
+            {
+                "type": "m.room.encryption",
+                "event_id": "$encryption",
+                "room_id": room_id,
+                "sender": "@_bridge_bot:example.org",
+                "state_key": "",
+                "origin_server_ts": 1,
+                "content": { "algorithm": "m.megolm.v1.aes-sha2" },
+            },
+            {
+                "type": "m.room.member",
+                "event_id": "$join_bob",
+                "room_id": room_id,
+                "sender": "@_bridge_bob:example.org",
+                "state_key": "@_bridge_bob:example.org",
+                "origin_server_ts": 2,
+                "content": { "membership": "join" },
+            },
+        ],
+    });
+
+    let url = serve(&appservice).await;
+    let response = reqwest::Client::new()
+        .put(format!("{url}/txn3"))
+        .bearer_auth("hs_token")
+        .body(transaction.to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let room = appservice.client().get_room(room_id).unwrap();
+    assert!(room.encryption_state().is_encrypted());
+
+    // The encrypted room isn't fed to the client of the virtual user, and they
+    // can't join it.
+    let bob = appservice.virtual_user("_bridge_bob").await.unwrap();
+    assert!(bob.client().get_room(room_id).is_none());
+
+    assert_matches!(
+        bob.join_room_by_id(room_id).await,
+        Err(AppServiceError::EncryptedRoom(rejected_room_id))
+    );
+    assert_eq!(rejected_room_id, room_id);
+}
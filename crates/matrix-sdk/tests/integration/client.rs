@@ -1,4 +1,4 @@
-use std::{collections::BTreeMap, time::Duration};
+use std::{collections::BTreeMap, future::IntoFuture, time::Duration};
 
 use assert_matches2::{assert_let, assert_matches};
 use eyeball_im::VectorDiff;
@@ -11,12 +11,19 @@ use matrix_sdk::{
     test_utils::{
         client::mock_matrix_session, mocks::MatrixMockServer, no_retry_test_client_with_server,
     },
-    Client, Error, MemoryStore, StateChanges, StateStore,
+    Client, CompactionProgress, Error, MemoryStore, StateChanges, StateStore,
 };
-use matrix_sdk_base::{sync::RoomUpdates, RoomState};
-use matrix_sdk_common::executor::spawn;
+use matrix_sdk_base::{
+    event_cache::store::media::IgnoreMediaRetentionPolicy,
+    media::{MediaFormat, MediaRequestParameters},
+    sync::RoomUpdates,
+    RoomState,
+};
+use matrix_sdk_common::{executor::spawn, store_usage::ItemsUsage};
 use matrix_sdk_test::{
-    async_test, sync_state_event,
+    async_test,
+    event_factory::EventFactory,
+    sync_state_event,
     test_json::{
         self,
         sync::{
@@ -41,9 +48,10 @@ use ruma::{
     event_id,
     events::{
         direct::{DirectEventContent, OwnedDirectUserIdentifier},
+        room::MediaSource,
         AnyInitialStateEvent,
     },
-    room_id,
+    mxc_uri, room_id,
     serde::Raw,
     user_id, OwnedUserId,
 };
@@ -1437,3 +1445,71 @@ async fn test_logout() {
     assert_matches!(res, Err(Error::OAuth(oauth_error)));
     assert_matches!(*oauth_error, OAuthError::Logout(OAuthTokenRevocationError::Url(_)));
 }
+
+#[async_test]
+async fn test_storage_report_and_compact_stores() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().sqlite_store(dir.path()).build().await;
+
+    let room_id = room_id!("!galette:saucisse.bzh");
+    server.sync_joined_room(&client, room_id).await;
+
+    let event = EventFactory::new()
+        .room(room_id)
+        .sender(user_id!("@alice:saucisse.bzh"))
+        .text_msg("hello")
+        .event_id(event_id!("$ev0"))
+        .into_event();
+    let event_size = event.raw().json().get().len() as u64;
+    let gif = b"GIF89a\x01\x00\x01\x00".to_vec();
+
+    {
+        let event_cache_store = client.event_cache_store().lock().await.unwrap();
+        event_cache_store.save_event(room_id, event).await.unwrap();
+
+        let request = MediaRequestParameters {
+            source: MediaSource::Plain(mxc_uri!("mxc://saucisse.bzh/gif").to_owned()),
+            format: MediaFormat::File,
+        };
+        event_cache_store
+            .add_media_content(&request, gif.clone(), IgnoreMediaRetentionPolicy::No)
+            .await
+            .unwrap();
+    }
+
+    let report = client.storage_report().await.unwrap();
+
+    // The SQLite store reports the size of the serialized events, which is a bit
+    // bigger than their JSON.
+    assert_eq!(report.room_events.len(), 1);
+    assert_eq!(report.room_events[room_id].count, 1);
+    assert!(report.room_events[room_id].size >= event_size);
+    assert_eq!(report.media.len(), 1);
+    assert_eq!(report.media["image/gif"], ItemsUsage { count: 1, size: gif.len() as u64 });
+
+    // All the stores are on disk, and report their tables.
+    let crypto_store = report.crypto_store.as_ref().unwrap();
+    assert!(report.state_store.tables.contains_key("kv"));
+    assert!(crypto_store.tables.contains_key("inbound_group_session"));
+    assert!(report.event_cache_store.tables["events"] > 0);
+    assert_eq!(
+        report.total_size(),
+        report.state_store.total_size.unwrap()
+            + crypto_store.total_size.unwrap()
+            + report.event_cache_store.total_size.unwrap()
+    );
+
+    let compact_stores = client.compact_stores();
+    let progress = compact_stores.subscribe_to_progress();
+    assert_eq!(progress.get(), CompactionProgress::NotStarted);
+
+    spawn(compact_stores.into_future()).await.unwrap().unwrap();
+    assert_eq!(progress.get(), CompactionProgress::Done);
+
+    // The data is still there after the compaction.
+    let new_report = client.storage_report().await.unwrap();
+    assert_eq!(new_report.room_events, report.room_events);
+    assert_eq!(new_report.media, report.media);
+    assert!(new_report.total_size() <= report.total_size());
+}
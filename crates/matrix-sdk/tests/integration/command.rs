@@ -0,0 +1,194 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use matrix_sdk::{
+    command::{Command, CommandContext, CommandRouter, Rest},
+    test_utils::mocks::MatrixMockServer,
+};
+use matrix_sdk_test::{
+    async_test, event_factory::EventFactory, JoinedRoomBuilder, ALICE, BOB, DEFAULT_TEST_ROOM_ID,
+};
+use ruma::{event_id, int, user_id, OwnedUserId};
+use serde_json::json;
+
+fn router() -> CommandRouter {
+    CommandRouter::new()
+        .command(
+            Command::new("echo", |ctx: CommandContext, text: Rest| async move {
+                ctx.reply_text(text.0).await
+            })
+            .description("Repeat the text"),
+        )
+        .command(
+            Command::new("kick", |ctx: CommandContext, user_id: OwnedUserId| async move {
+                ctx.reply_text(format!("Kicking {user_id}")).await
+            })
+            .min_power_level(50),
+        )
+}
+
+/// Mock the reply to the given event, with the given message type.
+///
+/// The body of the replies starts with the fallback of the replied-to event,
+/// so it isn't matched.
+async fn mock_reply(server: &MatrixMockServer, event_id: &str, msgtype: &str) {
+    server
+        .mock_room_send()
+        .body_matches_partial_json(json!({
+            "msgtype": msgtype,
+            "m.relates_to": { "m.in_reply_to": { "event_id": event_id } },
+        }))
+        .ok(event_id!("$reply"))
+        .mock_once()
+        .named(event_id)
+        .mount()
+        .await;
+}
+
+#[async_test]
+async fn test_command_with_prefix_and_mention() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = &DEFAULT_TEST_ROOM_ID;
+
+    server.mock_room_state_encryption().plain().mount().await;
+    server.sync_joined_room(&client, room_id).await;
+
+    router().register(&client);
+
+    server
+        .mock_room_send()
+        .body_matches_partial_json(json!({
+            "msgtype": "m.text",
+            "m.relates_to": { "m.in_reply_to": { "event_id": "$command" } },
+            "m.mentions": { "user_ids": [*ALICE] },
+        }))
+        .ok(event_id!("$reply"))
+        .mock_once()
+        .mount()
+        .await;
+    mock_reply(&server, "$mention", "m.text").await;
+
+    let f = EventFactory::new().room(room_id).sender(&ALICE);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_timeline_event(f.text_msg("!echo hello world").event_id(event_id!("$command")))
+                .add_timeline_event(f.text_msg("not a command").event_id(event_id!("$message")))
+                .add_timeline_event(
+                    f.text_msg("@example:localhost: echo mentioned")
+                        .event_id(event_id!("$mention")),
+                ),
+        )
+        .await;
+}
+
+#[async_test]
+async fn test_command_errors_and_permissions() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = &DEFAULT_TEST_ROOM_ID;
+
+    server.mock_room_state_encryption().plain().mount().await;
+    server.sync_joined_room(&client, room_id).await;
+
+    router().register(&client);
+
+    // The unknown commands are ignored by default.
+    server
+        .mock_room_send()
+        .body_matches_partial_json(json!({
+            "m.relates_to": { "m.in_reply_to": { "event_id": "$unknown" } },
+        }))
+        .ok(event_id!("$reply"))
+        .never()
+        .mount()
+        .await;
+    mock_reply(&server, "$help", "m.notice").await;
+    mock_reply(&server, "$denied", "m.notice").await;
+    mock_reply(&server, "$invalid", "m.notice").await;
+    mock_reply(&server, "$allowed", "m.text").await;
+
+    let f = EventFactory::new().room(room_id);
+    let power_levels = f
+        .power_levels(&mut BTreeMap::from([(ALICE.to_owned(), int!(100))]))
+        .sender(&ALICE)
+        .state_key("");
+
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_state_event(power_levels)
+                .add_timeline_event(
+                    f.text_msg("!dance").sender(&BOB).event_id(event_id!("$unknown")),
+                )
+                .add_timeline_event(f.text_msg("!HELP").sender(&BOB).event_id(event_id!("$help")))
+                .add_timeline_event(
+                    f.text_msg("!kick @carol:localhost")
+                        .sender(&BOB)
+                        .event_id(event_id!("$denied")),
+                )
+                .add_timeline_event(
+                    f.text_msg("!kick bob").sender(&ALICE).event_id(event_id!("$invalid")),
+                )
+                .add_timeline_event(
+                    f.text_msg("!kick @carol:localhost")
+                        .sender(&ALICE)
+                        .event_id(event_id!("$allowed")),
+                ),
+        )
+        .await;
+}
+
+#[async_test]
+async fn test_reply_to_unknown_commands() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = &DEFAULT_TEST_ROOM_ID;
+
+    server.mock_room_state_encryption().plain().mount().await;
+    server.sync_joined_room(&client, room_id).await;
+
+    router().reply_to_unknown_commands(true).register(&client);
+
+    mock_reply(&server, "$unknown", "m.notice").await;
+
+    let f = EventFactory::new().room(room_id).sender(&BOB);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_timeline_event(f.text_msg("!dance").event_id(event_id!("$unknown"))),
+        )
+        .await;
+}
+
+#[async_test]
+async fn test_command_rate_limit() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = &DEFAULT_TEST_ROOM_ID;
+
+    server.mock_room_state_encryption().plain().mount().await;
+    server.sync_joined_room(&client, room_id).await;
+
+    router().rate_limit(1, Duration::from_secs(3600)).register(&client);
+
+    server.mock_room_send().ok(event_id!("$reply")).expect(2).mount().await;
+
+    let f = EventFactory::new().room(room_id);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_timeline_event(f.text_msg("!echo one").sender(&ALICE))
+                .add_timeline_event(f.text_msg("!echo two").sender(&ALICE))
+                .add_timeline_event(f.text_msg("!echo three").sender(&BOB))
+                // The messages of the bot itself are ignored.
+                .add_timeline_event(
+                    f.text_msg("!echo four").sender(user_id!("@example:localhost")),
+                ),
+        )
+        .await;
+}
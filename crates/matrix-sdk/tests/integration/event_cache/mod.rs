@@ -9,9 +9,10 @@ use matrix_sdk::{
     assert_let_timeout, assert_next_matches_with_timeout,
     deserialized_responses::TimelineEvent,
     event_cache::{
-        BackPaginationOutcome, EventCacheError, RoomEventCacheUpdate, RoomPaginationStatus,
+        BackPaginationOutcome, DetachedContext, EventCacheError, RoomEventCacheUpdate,
+        RoomPaginationStatus,
     },
-    linked_chunk::{ChunkIdentifier, LinkedChunkId, Position, Update},
+    linked_chunk::{ChunkContent, ChunkIdentifier, LinkedChunkId, Position, Update},
     store::StoreConfig,
     test_utils::{
         assert_event_matches_msg,
@@ -19,7 +20,7 @@ use matrix_sdk::{
     },
 };
 use matrix_sdk_base::event_cache::{
-    store::{EventCacheStore, MemoryStore},
+    store::{EventCacheStore, EventRetentionPolicy, MemoryStore},
     Gap,
 };
 use matrix_sdk_test::{
@@ -28,11 +29,18 @@ use matrix_sdk_test::{
 };
 use ruma::{
     event_id,
-    events::{AnySyncMessageLikeEvent, AnySyncTimelineEvent, TimelineEventType},
-    room_id, user_id, EventId, RoomVersionId,
+    events::{
+        room::message::RoomMessageEventContentWithoutRelation, AnySyncMessageLikeEvent,
+        AnySyncTimelineEvent, TimelineEventType,
+    },
+    room_id, uint, user_id, EventId, MilliSecondsSinceUnixEpoch, RoomVersionId,
 };
 use serde_json::json;
 use tokio::{spawn, sync::broadcast, time::sleep};
+use wiremock::{
+    matchers::{method, path_regex},
+    Mock, ResponseTemplate,
+};
 
 mod threads;
 
@@ -1659,6 +1667,155 @@ async fn test_apply_redaction_when_redacted_and_redaction_are_in_same_sync() {
     assert!(subscriber.is_empty());
 }
 
+#[async_test]
+async fn test_event_aggregations_are_indexed() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let event_cache = client.event_cache();
+
+    // Immediately subscribe the event cache to sync updates.
+    event_cache.subscribe().unwrap();
+
+    let room_id = room_id!("!omelette:fromage.fr");
+    let room = server.sync_joined_room(&client, room_id).await;
+    let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+    let (_events, mut subscriber) = room_event_cache.subscribe().await;
+
+    let f = EventFactory::new().room(room_id).sender(*ALICE);
+    let original_id = event_id!("$1");
+
+    // An event is edited, and reacted to.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_timeline_bulk([
+                f.text_msg("hello").event_id(original_id).into_raw_sync(),
+                f.text_msg("* hello!")
+                    .edit(original_id, RoomMessageEventContentWithoutRelation::text_plain("hello!"))
+                    .event_id(event_id!("$2"))
+                    .into_raw_sync(),
+                f.reaction(original_id, "👍")
+                    .sender(*BOB)
+                    .event_id(event_id!("$3"))
+                    .into_raw_sync(),
+                f.reaction(original_id, "👍").event_id(event_id!("$4")).into_raw_sync(),
+            ]),
+        )
+        .await;
+
+    assert_let_timeout!(Ok(RoomEventCacheUpdate::UpdateTimelineEvents { .. }) = subscriber.recv());
+
+    let aggregations = room_event_cache.find_event_aggregations(&[original_id.to_owned()]).await;
+    let aggregations = &aggregations[original_id];
+    assert_eq!(aggregations.latest_edit.as_deref(), Some(event_id!("$2")));
+    assert_eq!(aggregations.reaction_count("👍"), 2);
+    assert!(aggregations.redacted_by.is_none());
+
+    // One of the reactions is redacted.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_timeline_event(
+                f.redaction(event_id!("$3")).sender(*BOB).event_id(event_id!("$5")).into_raw_sync(),
+            ),
+        )
+        .await;
+
+    assert_let_timeout!(Ok(RoomEventCacheUpdate::UpdateTimelineEvents { .. }) = subscriber.recv());
+
+    let aggregations = room_event_cache.find_event_aggregations(&[original_id.to_owned()]).await;
+    let aggregations = &aggregations[original_id];
+    assert_eq!(aggregations.reaction_count("👍"), 1);
+    assert_eq!(aggregations.reactions["👍"][0].sender, *ALICE);
+
+    // Then the event itself is redacted.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_timeline_event(
+                f.redaction(original_id).event_id(event_id!("$6")).into_raw_sync(),
+            ),
+        )
+        .await;
+
+    assert_let_timeout!(Ok(RoomEventCacheUpdate::UpdateTimelineEvents { .. }) = subscriber.recv());
+
+    let aggregations = room_event_cache.find_event_aggregations(&[original_id.to_owned()]).await;
+    assert_eq!(aggregations[original_id].redacted_by.as_deref(), Some(event_id!("$6")));
+
+    // Clearing the room's event cache clears the aggregations too.
+    room_event_cache.clear().await.unwrap();
+    assert!(room_event_cache.find_event_aggregations(&[original_id.to_owned()]).await.is_empty());
+}
+
+#[async_test]
+async fn test_edits_received_before_the_original_event_are_checked() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let event_cache = client.event_cache();
+
+    // Immediately subscribe the event cache to sync updates.
+    event_cache.subscribe().unwrap();
+
+    let room_id = room_id!("!omelette:fromage.fr");
+    let room = server.sync_joined_room(&client, room_id).await;
+    let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+    let (_events, mut subscriber) = room_event_cache.subscribe().await;
+
+    let f = EventFactory::new().room(room_id).sender(*ALICE);
+    let original_id = event_id!("$1");
+
+    // The edits are received before the original event, one of them from another
+    // sender.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_timeline_bulk([
+                f.text_msg("* hello!")
+                    .edit(original_id, RoomMessageEventContentWithoutRelation::text_plain("hello!"))
+                    .event_id(event_id!("$2"))
+                    .into_raw_sync(),
+                f.text_msg("* hijacked")
+                    .edit(
+                        original_id,
+                        RoomMessageEventContentWithoutRelation::text_plain("hijacked"),
+                    )
+                    .sender(*BOB)
+                    .event_id(event_id!("$3"))
+                    .into_raw_sync(),
+            ]),
+        )
+        .await;
+
+    assert_let_timeout!(Ok(RoomEventCacheUpdate::UpdateTimelineEvents { .. }) = subscriber.recv());
+
+    // The sender of the edits can't be checked yet.
+    let aggregations = room_event_cache.find_event_aggregations(&[original_id.to_owned()]).await;
+    assert!(aggregations[original_id].latest_edit.is_some());
+
+    // Then the original event is received.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_timeline_event(f.text_msg("hello").event_id(original_id).into_raw_sync()),
+        )
+        .await;
+
+    assert_let_timeout!(Ok(RoomEventCacheUpdate::UpdateTimelineEvents { .. }) = subscriber.recv());
+
+    // Only the edit from the sender of the original event is kept.
+    let aggregations = room_event_cache.find_event_aggregations(&[original_id.to_owned()]).await;
+    assert_eq!(aggregations[original_id].latest_edit.as_deref(), Some(event_id!("$2")));
+
+    let aggregated_events =
+        room_event_cache.find_aggregated_events(&[original_id.to_owned()]).await;
+    assert_eq!(aggregated_events.len(), 1);
+    assert_eq!(aggregated_events[0].event_id().as_deref(), Some(event_id!("$2")));
+}
+
 #[async_test]
 async fn test_lazy_loading() {
     let room_id = room_id!("!foo:bar.baz");
@@ -1703,7 +1860,7 @@ async fn test_lazy_loading() {
                         previous: Some(ChunkIdentifier::new(0)),
                         new: ChunkIdentifier::new(1),
                         next: None,
-                        gap: Gap { prev_token: "raclette".to_owned() },
+                        gap: Gap::new("raclette"),
                     },
                     // chunk #3
                     Update::NewItemsChunk {
@@ -2414,6 +2571,175 @@ async fn test_clear_all_rooms() {
     assert!(maybe_last_chunk.is_none());
 }
 
+#[async_test]
+async fn test_apply_event_retention_policy() {
+    let room_id = room_id!("!galette:saucisse.bzh");
+    let f = EventFactory::new().room(room_id).sender(*ALICE);
+
+    let ev0 = f.text_msg("hello").event_id(event_id!("$ev0")).into_event();
+    let ev1 = f.text_msg("world").event_id(event_id!("$ev1")).into_event();
+    let ev2 = f.text_msg("sup").event_id(event_id!("$ev2")).into_event();
+
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    // The room has two chunks in the store.
+    client
+        .event_cache_store()
+        .lock()
+        .await
+        .unwrap()
+        .handle_linked_chunk_updates(
+            LinkedChunkId::Room(room_id),
+            vec![
+                Update::NewItemsChunk { previous: None, new: ChunkIdentifier::new(0), next: None },
+                Update::PushItems {
+                    at: Position::new(ChunkIdentifier::new(0), 0),
+                    items: vec![ev0, ev1],
+                },
+                Update::NewItemsChunk {
+                    previous: Some(ChunkIdentifier::new(0)),
+                    new: ChunkIdentifier::new(1),
+                    next: None,
+                },
+                Update::PushItems {
+                    at: Position::new(ChunkIdentifier::new(1), 0),
+                    items: vec![ev2.clone()],
+                },
+            ],
+        )
+        .await
+        .unwrap();
+
+    let event_cache = client.event_cache();
+    event_cache.subscribe().unwrap();
+
+    let room = server.sync_joined_room(&client, room_id).await;
+    let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+
+    // Keep a single event per room.
+    let policy = EventRetentionPolicy::empty().with_max_events_per_room(Some(1));
+    event_cache.set_event_retention_policy(policy).await.unwrap();
+    assert_eq!(event_cache.event_retention_policy().await.unwrap(), policy);
+
+    event_cache.apply_event_retention_policy().await.unwrap();
+
+    // The first chunk has been replaced by a gap in the store.
+    let event_cache_store = client.event_cache_store().lock().await.unwrap();
+    let chunk = event_cache_store
+        .load_previous_chunk(LinkedChunkId::Room(room_id), ChunkIdentifier::new(1))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_let!(ChunkContent::Gap(gap) = chunk.content);
+    assert_eq!(gap.evicted_before.as_deref().unwrap(), "$ev2");
+    drop(event_cache_store);
+
+    let events = room_event_cache.events().await;
+    assert_eq!(events.len(), 1);
+    assert_event_id!(events[0], "$ev2");
+
+    // When back-paginating, a token is resolved for the gap with /context, and the
+    // evicted events are fetched again.
+    server
+        .mock_room_event_context()
+        .room(room_id)
+        .match_event_id()
+        .ok(ev2, "before-ev2", "after-ev2")
+        .mock_once()
+        .mount()
+        .await;
+
+    server
+        .mock_room_messages()
+        .match_from("before-ev2")
+        .ok(RoomMessagesResponseTemplate::default().events(vec![
+            f.text_msg("world").event_id(event_id!("$ev1")),
+            f.text_msg("hello").event_id(event_id!("$ev0")),
+        ]))
+        .mock_once()
+        .mount()
+        .await;
+
+    let outcome = room_event_cache.pagination().run_backwards_until(20).await.unwrap();
+    assert!(outcome.reached_start);
+
+    let events = room_event_cache.events().await;
+    assert_eq!(events.len(), 3);
+    assert_event_id!(events[0], "$ev0");
+    assert_event_id!(events[1], "$ev1");
+    assert_event_id!(events[2], "$ev2");
+}
+
+#[async_test]
+async fn test_back_paginate_evicted_events_without_token() {
+    let room_id = room_id!("!galette:saucisse.bzh");
+    let f = EventFactory::new().room(room_id).sender(*ALICE);
+
+    let ev1 = f.text_msg("world").event_id(event_id!("$ev1")).into_event();
+
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    // The room has a gap of evicted events before its only event.
+    client
+        .event_cache_store()
+        .lock()
+        .await
+        .unwrap()
+        .handle_linked_chunk_updates(
+            LinkedChunkId::Room(room_id),
+            vec![
+                Update::NewGapChunk {
+                    previous: None,
+                    new: ChunkIdentifier::new(0),
+                    next: None,
+                    gap: Gap::for_evicted_events(event_id!("$ev1")),
+                },
+                Update::NewItemsChunk {
+                    previous: Some(ChunkIdentifier::new(0)),
+                    new: ChunkIdentifier::new(1),
+                    next: None,
+                },
+                Update::PushItems {
+                    at: Position::new(ChunkIdentifier::new(1), 0),
+                    items: vec![ev1.clone()],
+                },
+            ],
+        )
+        .await
+        .unwrap();
+
+    let event_cache = client.event_cache();
+    event_cache.subscribe().unwrap();
+
+    let room = server.sync_joined_room(&client, room_id).await;
+    let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+
+    // The /context response doesn't have a token before the event, so there's
+    // nothing before it: the back-pagination stops without paginating from the end
+    // of the room.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/context/\$ev1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "event": ev1.into_raw().json(),
+            "state": [],
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    server.mock_room_messages().ok(RoomMessagesResponseTemplate::default()).expect(0).mount().await;
+
+    let outcome = room_event_cache.pagination().run_backwards_once(20).await.unwrap();
+    assert!(outcome.reached_start);
+    assert!(outcome.events.is_empty());
+
+    let events = room_event_cache.events().await;
+    assert_eq!(events.len(), 1);
+    assert_event_id!(events[0], "$ev1");
+}
+
 #[async_test]
 async fn test_sync_while_back_paginate() {
     let server = MatrixMockServer::new().await;
@@ -2522,3 +2848,171 @@ async fn test_sync_while_back_paginate() {
 
     assert!(subscriber.is_empty());
 }
+
+#[async_test]
+async fn test_detached_context_is_joined_by_backpagination() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    client.event_cache().subscribe().unwrap();
+
+    let room_id = room_id!("!galette:saucisse.bzh");
+    let f = EventFactory::new().room(room_id).sender(user_id!("@ben:saucisse.bzh"));
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_timeline_event(f.text_msg("live").event_id(event_id!("$5")).server_ts(50))
+                .set_timeline_prev_batch("prev_batch".to_owned())
+                .set_timeline_limited(),
+        )
+        .await;
+
+    let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+
+    let (events, mut room_stream) = room_event_cache.subscribe().await;
+    wait_for_initial_events(events, &mut room_stream).await;
+
+    // The context around a date the user jumped to is saved in the event cache.
+    room_event_cache
+        .save_detached_context(DetachedContext {
+            events: vec![
+                f.text_msg("hello").event_id(event_id!("$1")).server_ts(10).into_event(),
+                f.text_msg("world").event_id(event_id!("$2")).server_ts(20).into_event(),
+                f.text_msg("!").event_id(event_id!("$3")).server_ts(30).into_event(),
+            ],
+            prev_batch_token: Some("detached_prev".to_owned()),
+            next_batch_token: Some("detached_next".to_owned()),
+        })
+        .await
+        .unwrap();
+
+    // It can be retrieved for a date within its events.
+    let date = MilliSecondsSinceUnixEpoch(uint!(20));
+    assert_let!(Some(context) = room_event_cache.detached_context_at(date).await);
+    assert_eq!(context.events.len(), 3);
+    assert_eq!(context.prev_batch_token.as_deref(), Some("detached_prev"));
+    assert_eq!(context.next_batch_token.as_deref(), Some("detached_next"));
+    assert!(room_event_cache
+        .detached_context_at(MilliSecondsSinceUnixEpoch(uint!(5)))
+        .await
+        .is_none());
+
+    // When back-paginating the room reaches the detached context,
+    server
+        .mock_room_messages()
+        .match_from("prev_batch")
+        .ok(RoomMessagesResponseTemplate::default().end_token("messages_end").events(vec![
+            f.text_msg("?").event_id(event_id!("$4")).server_ts(40),
+            f.text_msg("!").event_id(event_id!("$3")).server_ts(30),
+        ]))
+        .mock_once()
+        .mount()
+        .await;
+
+    let outcome = room_event_cache.pagination().run_backwards_once(20).await.unwrap();
+
+    // Then the older events of the detached context are joined with the
+    // back-paginated ones,
+    assert!(outcome.reached_start.not());
+    assert_eq!(outcome.events.len(), 4);
+    assert_event_id!(outcome.events[0], "$4");
+    assert_event_id!(outcome.events[1], "$3");
+    assert_event_id!(outcome.events[2], "$2");
+    assert_event_id!(outcome.events[3], "$1");
+
+    let (events, _) = room_event_cache.subscribe().await;
+    assert_eq!(events.len(), 5);
+    assert_event_id!(events[0], "$1");
+    assert_event_id!(events[1], "$2");
+    assert_event_id!(events[2], "$3");
+    assert_event_id!(events[3], "$4");
+    assert_event_id!(events[4], "$5");
+
+    // The detached context is gone,
+    assert!(room_event_cache.detached_context_at(date).await.is_none());
+
+    // And the next back-pagination resumes from the detached context's token.
+    server
+        .mock_room_messages()
+        .match_from("detached_prev")
+        .ok(RoomMessagesResponseTemplate::default())
+        .mock_once()
+        .mount()
+        .await;
+
+    let outcome = room_event_cache.pagination().run_backwards_once(20).await.unwrap();
+    assert!(outcome.reached_start);
+    assert!(outcome.events.is_empty());
+}
+
+#[async_test]
+async fn test_detached_context_overlapping_the_room() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    client.event_cache().subscribe().unwrap();
+
+    let room_id = room_id!("!galette:saucisse.bzh");
+    let f = EventFactory::new().room(room_id).sender(user_id!("@ben:saucisse.bzh"));
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_timeline_event(f.text_msg("hello").event_id(event_id!("$1")).server_ts(10)),
+        )
+        .await;
+
+    let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+
+    let (events, mut room_stream) = room_event_cache.subscribe().await;
+    wait_for_initial_events(events, &mut room_stream).await;
+
+    // A detached context overlapping the room's events isn't saved.
+    room_event_cache
+        .save_detached_context(DetachedContext {
+            events: vec![
+                f.text_msg("hello").event_id(event_id!("$1")).server_ts(10).into_event(),
+                f.text_msg("world").event_id(event_id!("$2")).server_ts(20).into_event(),
+            ],
+            prev_batch_token: None,
+            next_batch_token: None,
+        })
+        .await
+        .unwrap();
+
+    assert!(room_event_cache
+        .detached_context_at(MilliSecondsSinceUnixEpoch(uint!(10)))
+        .await
+        .is_none());
+
+    // A detached context that doesn't overlap them is saved,
+    room_event_cache
+        .save_detached_context(DetachedContext {
+            events: vec![
+                f.text_msg("world").event_id(event_id!("$2")).server_ts(20).into_event(),
+                f.text_msg("!").event_id(event_id!("$3")).server_ts(30).into_event(),
+            ],
+            prev_batch_token: Some("detached_prev".to_owned()),
+            next_batch_token: None,
+        })
+        .await
+        .unwrap();
+
+    let date = MilliSecondsSinceUnixEpoch(uint!(25));
+    assert!(room_event_cache.detached_context_at(date).await.is_some());
+
+    // But it's dropped once its events are received from sync.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_timeline_event(f.text_msg("!").event_id(event_id!("$3")).server_ts(30)),
+        )
+        .await;
+
+    assert_let_timeout!(Ok(RoomEventCacheUpdate::UpdateTimelineEvents { .. }) = room_stream.recv());
+    assert!(room_event_cache.detached_context_at(date).await.is_none());
+}
@@ -0,0 +1,146 @@
+//! Tests of several clients talking to each other through a [`FakeHomeserver`].
+
+use assert_matches2::assert_let;
+use futures_util::{pin_mut, StreamExt};
+use matrix_sdk::{
+    config::SyncSettings,
+    media::{MediaFormat, MediaRequestParameters},
+    sliding_sync::Version as SlidingSyncVersion,
+    test_utils::{assert_event_matches_msg, client::MockClientBuilder},
+    Client, SlidingSyncList, SlidingSyncMode,
+};
+use matrix_sdk_test::{async_test, homeserver::FakeHomeserver};
+use ruma::events::room::{message::RoomMessageEventContent, MediaSource};
+
+async fn sync_once(client: &Client) {
+    client.sync_once(SyncSettings::new()).await.unwrap();
+}
+
+#[cfg(feature = "e2e-encryption")]
+#[async_test]
+async fn test_encrypted_messages_between_two_clients() {
+    let homeserver = FakeHomeserver::new().await;
+    let alice_id = homeserver.register_user("alice", "alice_password");
+    homeserver.register_user("bob", "bob_password");
+
+    // Alice's session is created directly on the homeserver,
+    let alice = MockClientBuilder::for_fake_homeserver(&homeserver, homeserver.login(&alice_id))
+        .build()
+        .await;
+
+    // While Bob logs in with their password.
+    let bob = Client::builder().homeserver_url(homeserver.uri()).build().await.unwrap();
+    bob.matrix_auth().login_username("bob", "bob_password").send().await.unwrap();
+    let bob_id = bob.user_id().unwrap().to_owned();
+
+    // Both upload their keys.
+    sync_once(&alice).await;
+    sync_once(&bob).await;
+
+    // Alice creates an encrypted DM with Bob, who joins it.
+    let alice_room = alice.create_dm(&bob_id).await.unwrap();
+    assert!(alice_room.latest_encryption_state().await.unwrap().is_encrypted());
+
+    sync_once(&bob).await;
+    let bob_room = bob.get_room(alice_room.room_id()).unwrap();
+    bob_room.join().await.unwrap();
+    sync_once(&bob).await;
+
+    // Alice sees Bob joining, and sends an encrypted message.
+    sync_once(&alice).await;
+    assert_eq!(alice_room.joined_members_count(), 2);
+
+    alice_room.send(RoomMessageEventContent::text_plain("Hello Bob!")).await.unwrap();
+
+    // Bob receives the room key and the message, and decrypts it.
+    let response = bob.sync_once(SyncSettings::new()).await.unwrap();
+    let joined_room = &response.rooms.joined[alice_room.room_id()];
+    let event = joined_room.timeline.events.last().unwrap();
+
+    assert!(event.encryption_info().is_some());
+    assert_event_matches_msg(event, "Hello Bob!");
+}
+
+#[async_test]
+async fn test_sliding_sync() {
+    let homeserver = FakeHomeserver::new().await;
+    let alice_id = homeserver.register_user("alice", "alice_password");
+    let bob_id = homeserver.register_user("bob", "bob_password");
+
+    let alice = MockClientBuilder::for_fake_homeserver(&homeserver, homeserver.login(&alice_id))
+        .build()
+        .await;
+    alice.set_sliding_sync_version(SlidingSyncVersion::Native);
+
+    // Bob created two rooms, and invited Alice to one of them.
+    let room_id = homeserver.create_room(&bob_id, &[&alice_id]);
+    homeserver.create_room(&bob_id, &[]);
+
+    let sliding_sync = alice
+        .sliding_sync("main")
+        .unwrap()
+        .with_all_extensions()
+        .add_list(
+            SlidingSyncList::builder("all")
+                .sync_mode(SlidingSyncMode::new_selective().add_range(0..=10))
+                .timeline_limit(10),
+        )
+        .build()
+        .await
+        .unwrap();
+
+    let stream = sliding_sync.sync();
+    pin_mut!(stream);
+
+    // Alice only sees the room she's invited to.
+    let summary = stream.next().await.unwrap().unwrap();
+    assert_eq!(summary.rooms, std::slice::from_ref(&room_id));
+    assert_eq!(
+        sliding_sync
+            .on_list("all", |list| std::future::ready(list.maximum_number_of_rooms()))
+            .await,
+        Some(Some(1))
+    );
+
+    // She joins it, and receives a message from Bob.
+    alice.get_room(&room_id).unwrap().join().await.unwrap();
+    homeserver.send_event(
+        &room_id,
+        &bob_id,
+        "m.room.message",
+        serde_json::json!({
+            "msgtype": "m.text",
+            "body": "Welcome!",
+        }),
+    );
+
+    let summary = stream.next().await.unwrap().unwrap();
+    assert!(summary.rooms.contains(&room_id));
+
+    let room = alice.get_room(&room_id).unwrap();
+    assert_let!(Some(latest_event) = room.latest_event());
+    assert_event_matches_msg(&latest_event.event().clone(), "Welcome!");
+}
+
+#[async_test]
+async fn test_media() {
+    let homeserver = FakeHomeserver::new().await;
+    let alice_id = homeserver.register_user("alice", "alice_password");
+    let bob_id = homeserver.register_user("bob", "bob_password");
+
+    let alice = MockClientBuilder::for_fake_homeserver(&homeserver, homeserver.login(&alice_id))
+        .build()
+        .await;
+    let bob = MockClientBuilder::for_fake_homeserver(&homeserver, homeserver.login(&bob_id))
+        .build()
+        .await;
+
+    // A media uploaded by Alice can be downloaded by Bob.
+    let response = alice.media().upload(&mime::TEXT_PLAIN, b"hello".to_vec(), None).await.unwrap();
+
+    let request = MediaRequestParameters {
+        source: MediaSource::Plain(response.content_uri),
+        format: MediaFormat::File,
+    };
+    assert_eq!(bob.media().get_media_content(&request, false).await.unwrap(), b"hello");
+}
@@ -0,0 +1,276 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use matrix_sdk::{
+    config::StoreConfig,
+    invite_filter::{FilteredInviteAction, InviteFilter, InviteFilterReason},
+    test_utils::mocks::MatrixMockServer,
+    Client, MemoryStore,
+};
+use matrix_sdk_test::{
+    async_test, event_factory::EventFactory, GlobalAccountDataTestEvent, InvitedRoomBuilder,
+    JoinedRoomBuilder,
+};
+use ruma::{
+    events::room::member::MembershipState, owned_server_name, room_id, user_id, RoomId, UserId,
+};
+use serde_json::json;
+
+async fn sync_invite(
+    server: &MatrixMockServer,
+    client: &Client,
+    room_id: &RoomId,
+    inviter: &UserId,
+) {
+    let own_user_id = client.user_id().unwrap();
+    let f = EventFactory::new().room(room_id).sender(inviter);
+
+    server
+        .sync_room(
+            client,
+            InvitedRoomBuilder::new(room_id).add_state_event(
+                f.member(inviter)
+                    .invited(own_user_id)
+                    .membership(MembershipState::Invite)
+                    .into_raw(),
+            ),
+        )
+        .await;
+}
+
+#[async_test]
+async fn test_quarantine_invites() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let friend = user_id!("@friend:example.org");
+    let stranger = user_id!("@stranger:example.org");
+    let spammer = user_id!("@spammer:spam.org");
+
+    // We share a room with our friend.
+    let joined_room_id = room_id!("!joined:example.org");
+    let f = EventFactory::new().room(joined_room_id);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(joined_room_id)
+                .add_state_event(f.member(friend).membership(MembershipState::Join)),
+        )
+        .await;
+
+    // An invite received before the filter is set is filtered too.
+    let spam_room_id = room_id!("!spam:example.org");
+    sync_invite(&server, &client, spam_room_id, spammer).await;
+    assert!(!client.is_invite_quarantined(spam_room_id));
+
+    client
+        .set_invite_filter(InviteFilter {
+            blocked_servers: BTreeSet::from([owned_server_name!("spam.org")]),
+            block_without_shared_rooms: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    assert!(client.is_invite_quarantined(spam_room_id));
+    assert_eq!(
+        client.check_invite(spammer, spam_room_id).await,
+        Some(InviteFilterReason::BlockedServer)
+    );
+
+    // The new invites are filtered with the sync.
+    let friend_room_id = room_id!("!friend:example.org");
+    sync_invite(&server, &client, friend_room_id, friend).await;
+    assert!(!client.is_invite_quarantined(friend_room_id));
+
+    let stranger_room_id = room_id!("!stranger:example.org");
+    sync_invite(&server, &client, stranger_room_id, stranger).await;
+    assert!(client.is_invite_quarantined(stranger_room_id));
+    assert_eq!(
+        client.check_invite(stranger, stranger_room_id).await,
+        Some(InviteFilterReason::NoSharedRooms)
+    );
+
+    assert_eq!(
+        client.quarantined_invites(),
+        vec![spam_room_id.to_owned(), stranger_room_id.to_owned()]
+    );
+
+    // A released invite isn't quarantined anymore.
+    client.release_quarantined_invite(stranger_room_id).await.unwrap();
+    assert!(!client.is_invite_quarantined(stranger_room_id));
+
+    // The invites released by a new filter are updated in the room list.
+    let mut room_info_updates = client.room_info_notable_update_receiver();
+    client
+        .set_invite_filter(InviteFilter { block_without_shared_rooms: true, ..Default::default() })
+        .await
+        .unwrap();
+
+    assert!(client.is_invite_quarantined(spam_room_id));
+    assert_eq!(room_info_updates.try_recv().unwrap().room_id, spam_room_id);
+    assert!(room_info_updates.is_empty());
+
+    client
+        .set_invite_filter(InviteFilter {
+            blocked_servers: BTreeSet::from([owned_server_name!("other.org")]),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    assert!(!client.is_invite_quarantined(spam_room_id));
+    assert_eq!(room_info_updates.try_recv().unwrap().room_id, spam_room_id);
+    assert!(room_info_updates.is_empty());
+
+    client.remove_invite_filter().await.unwrap();
+    assert!(client.quarantined_invites().is_empty());
+    assert_eq!(client.check_invite(spammer, spam_room_id).await, None);
+}
+
+#[async_test]
+async fn test_decline_filtered_invites() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let spammer = user_id!("@spammer:example.org");
+    server
+        .mock_sync()
+        .ok_and_run(&client, |sync_builder| {
+            sync_builder.add_global_account_data_event(GlobalAccountDataTestEvent::Custom(json!({
+                "content": {
+                    "ignored_users": {
+                        spammer: {}
+                    }
+                },
+                "type": "m.ignored_user_list",
+            })));
+        })
+        .await;
+    assert!(client.is_user_ignored(spammer).await);
+
+    client
+        .set_invite_filter(InviteFilter {
+            block_ignored_users: true,
+            action: FilteredInviteAction::Decline,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let room_id = room_id!("!spam:example.org");
+    server.mock_room_leave().ok(room_id).mock_once().mount().await;
+
+    sync_invite(&server, &client, room_id, spammer).await;
+
+    // A declined invite isn't quarantined.
+    assert!(!client.is_invite_quarantined(room_id));
+}
+
+#[async_test]
+async fn test_restore_invite_filter() {
+    let store = Arc::new(MemoryStore::new());
+    let store_config = || {
+        StoreConfig::new("cross-process-store-locks-holder-name".to_owned())
+            .state_store(store.clone())
+    };
+
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().store_config(store_config()).build().await;
+
+    let spammer = user_id!("@spammer:spam.org");
+    let spam_room_id = room_id!("!spam:example.org");
+    let other_spam_room_id = room_id!("!other_spam:example.org");
+
+    client
+        .set_invite_filter(InviteFilter {
+            blocked_servers: BTreeSet::from([owned_server_name!("spam.org")]),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    sync_invite(&server, &client, spam_room_id, spammer).await;
+    sync_invite(&server, &client, other_spam_room_id, spammer).await;
+    client.release_quarantined_invite(other_spam_room_id).await.unwrap();
+
+    assert!(client.is_invite_quarantined(spam_room_id));
+    assert!(!client.is_invite_quarantined(other_spam_room_id));
+
+    // A client built on the same store, like the one of a notification process,
+    // uses the saved filter without setting it.
+    let other_client = server.client_builder().store_config(store_config()).build().await;
+    assert!(other_client.invite_filter().is_none());
+
+    assert_eq!(
+        other_client.check_invite(spammer, spam_room_id).await,
+        Some(InviteFilterReason::BlockedServer)
+    );
+    assert!(other_client.invite_filter().is_some());
+    assert!(other_client.is_invite_quarantined(spam_room_id));
+
+    // The released invites stay released.
+    assert_eq!(other_client.check_invite(spammer, other_spam_room_id).await, None);
+    assert!(!other_client.is_invite_quarantined(other_spam_room_id));
+
+    // A restored client knows about the quarantined invites, and filters the new
+    // ones.
+    let restored_client = server.client_builder().store_config(store_config()).build().await;
+    restored_client.restore_invite_filter().await.unwrap();
+    assert_eq!(restored_client.quarantined_invites(), vec![spam_room_id.to_owned()]);
+
+    let new_spam_room_id = room_id!("!new_spam:example.org");
+    sync_invite(&server, &restored_client, new_spam_room_id, spammer).await;
+    assert!(restored_client.is_invite_quarantined(new_spam_room_id));
+}
+
+#[async_test]
+async fn test_forget_handled_filtered_invites() {
+    let store = Arc::new(MemoryStore::new());
+    let store_config = || {
+        StoreConfig::new("cross-process-store-locks-holder-name".to_owned())
+            .state_store(store.clone())
+    };
+
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().store_config(store_config()).build().await;
+    let own_user_id = client.user_id().unwrap().to_owned();
+
+    let spammer = user_id!("@spammer:spam.org");
+    let joined_room_id = room_id!("!joined:example.org");
+    let declined_room_id = room_id!("!declined:example.org");
+
+    client
+        .set_invite_filter(InviteFilter {
+            blocked_servers: BTreeSet::from([owned_server_name!("spam.org")]),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    sync_invite(&server, &client, joined_room_id, spammer).await;
+    sync_invite(&server, &client, declined_room_id, spammer).await;
+    client.release_quarantined_invite(declined_room_id).await.unwrap();
+    assert_eq!(client.quarantined_invites(), vec![joined_room_id.to_owned()]);
+
+    // A quarantined invite that was accepted is forgotten.
+    let f = EventFactory::new().room(joined_room_id).sender(&own_user_id);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(joined_room_id)
+                .add_state_event(f.member(&own_user_id).membership(MembershipState::Join)),
+        )
+        .await;
+    assert!(client.quarantined_invites().is_empty());
+
+    // A released invite that was declined is forgotten.
+    server.mock_room_leave().ok(declined_room_id).mock_once().mount().await;
+    server.mock_room_forget().ok().mock_once().mount().await;
+    client.get_room(declined_room_id).unwrap().leave().await.unwrap();
+
+    // The saved state doesn't contain them anymore.
+    let other_client = server.client_builder().store_config(store_config()).build().await;
+    assert_eq!(
+        other_client.check_invite(spammer, declined_room_id).await,
+        Some(InviteFilterReason::BlockedServer)
+    );
+    assert!(other_client.quarantined_invites().is_empty());
+}
@@ -8,17 +8,26 @@ use wiremock::{
 };
 
 mod account;
+#[cfg(feature = "appservice")]
+mod appservice;
 mod client;
+mod command;
 #[cfg(feature = "e2e-encryption")]
 mod encryption;
 mod event_cache;
+mod fake_homeserver;
+mod invite_filter;
 mod matrix_auth;
 mod media;
+mod moderation;
 mod notification;
+mod policy_list;
 mod refresh_token;
 mod room;
+mod room_creator;
 mod room_preview;
 mod send_queue;
+mod sync_replay;
 #[cfg(feature = "experimental-widgets")]
 mod widget;
 
@@ -9,6 +9,7 @@ use ruma::{
     events::room::{message::ImageMessageEventContent, ImageInfo, MediaSource},
     mxc_uri, owned_mxc_uri, uint,
 };
+use serde_json::json;
 
 #[async_test]
 async fn test_get_media_content_no_auth() {
@@ -321,3 +322,275 @@ async fn test_async_media_upload() {
         .await
         .unwrap();
 }
+
+#[cfg(not(target_family = "wasm"))]
+#[async_test]
+async fn test_download_to_file() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().no_server_versions().build().await;
+    let dir = tempfile::tempdir().unwrap();
+    let destination = dir.path().join("video.mp4");
+    let content = b"some very long video content".to_vec();
+
+    server.mock_versions().ok_custom(&["v1.1"], &Default::default()).mount().await;
+    server
+        .mock_media_download()
+        .ok_bytes_with_etag(content.clone(), "\"v1\"")
+        .named("download")
+        .expect(1)
+        .mount()
+        .await;
+
+    let request = MediaRequestParameters {
+        source: MediaSource::Plain(owned_mxc_uri!("mxc://localhost/video")),
+        format: MediaFormat::File,
+    };
+
+    let download = client.media().download_to_file(&request, &destination);
+    let progress = download.subscribe_to_progress();
+    download.await.unwrap();
+
+    assert_eq!(std::fs::read(&destination).unwrap(), content);
+    assert!(!dir.path().join("video.mp4.part").exists());
+    assert!(!dir.path().join("video.mp4.part.source").exists());
+
+    let progress = progress.get();
+    assert_eq!(progress.current, content.len());
+    assert_eq!(progress.total, content.len());
+}
+
+#[cfg(not(target_family = "wasm"))]
+#[async_test]
+async fn test_download_to_file_resumes_partial_download() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().no_server_versions().build().await;
+    let dir = tempfile::tempdir().unwrap();
+    let destination = dir.path().join("video.mp4");
+    let content = b"some very long video content".to_vec();
+
+    let request = MediaRequestParameters {
+        source: MediaSource::Plain(owned_mxc_uri!("mxc://localhost/video")),
+        format: MediaFormat::File,
+    };
+
+    // A previous download was interrupted after 10 bytes.
+    std::fs::write(dir.path().join("video.mp4.part"), &content[..10]).unwrap();
+    std::fs::write(
+        dir.path().join("video.mp4.part.source"),
+        json!({ "request": request, "etag": "\"v1\"" }).to_string(),
+    )
+    .unwrap();
+
+    server.mock_versions().ok_custom(&["v1.1"], &Default::default()).mount().await;
+    server
+        .mock_media_download()
+        .match_range_start(10)
+        .match_if_range("\"v1\"")
+        .ok_partial_bytes(content.clone(), 10)
+        .named("resumed_download")
+        .expect(1)
+        .mount()
+        .await;
+
+    let download = client.media().download_to_file(&request, &destination);
+    let progress = download.subscribe_to_progress();
+    download.await.unwrap();
+
+    assert_eq!(std::fs::read(&destination).unwrap(), content);
+    assert!(!dir.path().join("video.mp4.part").exists());
+
+    // The progress accounts for the bytes that were downloaded previously.
+    let progress = progress.get();
+    assert_eq!(progress.current, content.len());
+    assert_eq!(progress.total, content.len());
+}
+
+#[cfg(not(target_family = "wasm"))]
+#[async_test]
+async fn test_download_to_file_restarts_when_range_is_ignored() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().no_server_versions().build().await;
+    let dir = tempfile::tempdir().unwrap();
+    let destination = dir.path().join("video.mp4");
+    let content = b"some very long video content".to_vec();
+
+    let request = MediaRequestParameters {
+        source: MediaSource::Plain(owned_mxc_uri!("mxc://localhost/video")),
+        format: MediaFormat::File,
+    };
+
+    std::fs::write(dir.path().join("video.mp4.part"), b"some very").unwrap();
+    std::fs::write(
+        dir.path().join("video.mp4.part.source"),
+        json!({ "request": request }).to_string(),
+    )
+    .unwrap();
+
+    // The server doesn't support range requests, it sends the full content.
+    server.mock_versions().ok_custom(&["v1.1"], &Default::default()).mount().await;
+    server
+        .mock_media_download()
+        .ok_bytes(content.clone())
+        .named("full_download")
+        .expect(1)
+        .mount()
+        .await;
+
+    client.media().download_to_file(&request, &destination).await.unwrap();
+
+    assert_eq!(std::fs::read(&destination).unwrap(), content);
+}
+
+#[cfg(not(target_family = "wasm"))]
+#[async_test]
+async fn test_download_to_file_restarts_partial_download_of_another_media() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().no_server_versions().build().await;
+    let dir = tempfile::tempdir().unwrap();
+    let destination = dir.path().join("video.mp4");
+    let content = b"some very long video content".to_vec();
+
+    // The partial data was downloaded for another media.
+    let other_request = MediaRequestParameters {
+        source: MediaSource::Plain(owned_mxc_uri!("mxc://localhost/other")),
+        format: MediaFormat::File,
+    };
+    std::fs::write(dir.path().join("video.mp4.part"), b"other").unwrap();
+    std::fs::write(
+        dir.path().join("video.mp4.part.source"),
+        json!({ "request": other_request }).to_string(),
+    )
+    .unwrap();
+
+    server.mock_versions().ok_custom(&["v1.1"], &Default::default()).mount().await;
+    server
+        .mock_media_download()
+        .match_range_start(5)
+        .ok_partial_bytes(content.clone(), 5)
+        .named("resumed_download")
+        .expect(0)
+        .mount()
+        .await;
+    server
+        .mock_media_download()
+        .ok_bytes(content.clone())
+        .named("full_download")
+        .expect(1)
+        .mount()
+        .await;
+
+    let request = MediaRequestParameters {
+        source: MediaSource::Plain(owned_mxc_uri!("mxc://localhost/video")),
+        format: MediaFormat::File,
+    };
+
+    client.media().download_to_file(&request, &destination).await.unwrap();
+
+    assert_eq!(std::fs::read(&destination).unwrap(), content);
+    assert!(!dir.path().join("video.mp4.part.source").exists());
+}
+
+#[cfg(all(feature = "e2e-encryption", not(target_family = "wasm")))]
+#[async_test]
+async fn test_download_to_file_encrypted() {
+    use std::io::{Cursor, Read};
+
+    use matrix_sdk_base::crypto::AttachmentEncryptor;
+    use ruma::events::room::{EncryptedFile, EncryptedFileInit};
+
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().no_server_versions().build().await;
+    let dir = tempfile::tempdir().unwrap();
+    let destination = dir.path().join("secret.txt");
+    let content = b"some secret content".to_vec();
+
+    let mut cursor = Cursor::new(content.clone());
+    let mut encryptor = AttachmentEncryptor::new(&mut cursor);
+    let mut encrypted = Vec::new();
+    encryptor.read_to_end(&mut encrypted).unwrap();
+    let keys = encryptor.finish();
+
+    let file: EncryptedFile = EncryptedFileInit {
+        url: owned_mxc_uri!("mxc://localhost/secret"),
+        key: keys.key,
+        iv: keys.iv,
+        hashes: keys.hashes,
+        v: keys.version,
+    }
+    .into();
+
+    let request = MediaRequestParameters {
+        source: MediaSource::Encrypted(Box::new(file)),
+        format: MediaFormat::File,
+    };
+
+    // Only the first bytes were downloaded and decrypted before.
+    std::fs::write(dir.path().join("secret.txt.part"), &content[..5]).unwrap();
+    std::fs::write(
+        dir.path().join("secret.txt.part.source"),
+        json!({ "request": request }).to_string(),
+    )
+    .unwrap();
+
+    server.mock_versions().ok_custom(&["v1.1"], &Default::default()).mount().await;
+    server
+        .mock_media_download()
+        .match_range_start(5)
+        .ok_partial_bytes(encrypted.clone(), 5)
+        .named("resumed_download")
+        .expect(1)
+        .mount()
+        .await;
+
+    client.media().download_to_file(&request, &destination).await.unwrap();
+
+    assert_eq!(std::fs::read(&destination).unwrap(), content);
+    assert!(!dir.path().join("secret.txt.part").exists());
+}
+
+#[cfg(all(feature = "e2e-encryption", not(target_family = "wasm")))]
+#[async_test]
+async fn test_download_to_file_encrypted_hash_mismatch() {
+    use std::io::{Cursor, Read};
+
+    use matrix_sdk_base::crypto::AttachmentEncryptor;
+    use ruma::events::room::{EncryptedFile, EncryptedFileInit};
+
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().no_server_versions().build().await;
+    let dir = tempfile::tempdir().unwrap();
+    let destination = dir.path().join("secret.txt");
+
+    let mut cursor = Cursor::new(b"some secret content".to_vec());
+    let mut encryptor = AttachmentEncryptor::new(&mut cursor);
+    let mut encrypted = Vec::new();
+    encryptor.read_to_end(&mut encrypted).unwrap();
+    let keys = encryptor.finish();
+
+    let file: EncryptedFile = EncryptedFileInit {
+        url: owned_mxc_uri!("mxc://localhost/secret"),
+        key: keys.key,
+        iv: keys.iv,
+        hashes: keys.hashes,
+        v: keys.version,
+    }
+    .into();
+
+    // The content was tampered with.
+    encrypted[0] ^= 1;
+
+    server.mock_versions().ok_custom(&["v1.1"], &Default::default()).mount().await;
+    server.mock_media_download().ok_bytes(encrypted).named("download").expect(1).mount().await;
+
+    let request = MediaRequestParameters {
+        source: MediaSource::Encrypted(Box::new(file)),
+        format: MediaFormat::File,
+    };
+
+    client.media().download_to_file(&request, &destination).await.unwrap_err();
+
+    // Nothing is kept, the next download starts over.
+    assert!(!destination.exists());
+    assert!(!dir.path().join("secret.txt.part").exists());
+    assert!(!dir.path().join("secret.txt.part.source").exists());
+}
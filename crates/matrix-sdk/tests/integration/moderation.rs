@@ -0,0 +1,326 @@
+use std::collections::BTreeMap;
+
+use matrix_sdk::{
+    moderation::{RedactionProgress, ReportCategory, ReportOptions, ReportReason, ReportTarget},
+    room::ReportedContentScore,
+    test_utils::mocks::{MatrixMockServer, RoomMessagesResponseTemplate},
+};
+use matrix_sdk_test::{async_test, event_factory::EventFactory, JoinedRoomBuilder};
+use ruma::{
+    event_id, events::room::member::MembershipState, int, room_id, user_id,
+    MilliSecondsSinceUnixEpoch,
+};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_json, body_partial_json, method, path, path_regex},
+    Mock, ResponseTemplate,
+};
+
+#[async_test]
+async fn test_report_user_and_ignore_them() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let spammer = user_id!("@spammer:example.org");
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/.*/users/@spammer:example.org/report$"))
+        .and(body_json(json!({ "reason": "[spam] Sends ads in DMs" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/account_data/m.ignored_user_list$"))
+        .and(body_partial_json(json!({ "ignored_users": { spammer: {} } })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let moderation = client.moderation();
+    assert!(moderation.reports().await.unwrap().is_empty());
+
+    let record = moderation
+        .report_user(
+            spammer,
+            ReportReason::new(ReportCategory::Spam).with_details("Sends ads in DMs"),
+            ReportOptions { ignore_user: true, ..Default::default() },
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(record.target, ReportTarget::User { user_id: spammer.to_owned() });
+    assert_eq!(record.ignored_user.as_deref(), Some(spammer));
+    assert!(record.left_rooms.is_empty());
+
+    // The report is recorded in the state store.
+    assert_eq!(moderation.reports().await.unwrap(), vec![record]);
+    assert!(moderation.has_reported_user(spammer).await.unwrap());
+    assert!(!moderation.has_reported_user(user_id!("@alice:example.org")).await.unwrap());
+
+    moderation.clear_reports().await.unwrap();
+    assert!(moderation.reports().await.unwrap().is_empty());
+}
+
+#[async_test]
+async fn test_report_event_and_leave_room() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!room:example.org");
+    let event_id = event_id!("$offensive_event");
+    let sender = user_id!("@troll:example.org");
+
+    let room = server.sync_joined_room(&client, room_id).await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/.*/rooms/.*/report/\$offensive_event$"))
+        .and(body_json(json!({ "reason": "[harassment]", "score": -80 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let f = EventFactory::new().room(room_id).sender(sender);
+    server
+        .mock_room_event()
+        .match_event_id()
+        .ok(f.text_msg("you're all idiots").event_id(event_id).into())
+        .mock_once()
+        .mount()
+        .await;
+    server.mock_room_leave().ok(room_id).mock_once().mount().await;
+
+    let record = client
+        .moderation()
+        .report_event(
+            &room,
+            event_id,
+            ReportReason::new(ReportCategory::Harassment),
+            ReportOptions {
+                score: Some(ReportedContentScore::new(-80).unwrap()),
+                leave_room: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        record.target,
+        ReportTarget::Event {
+            room_id: room_id.to_owned(),
+            event_id: event_id.to_owned(),
+            sender: Some(sender.to_owned()),
+        }
+    );
+    assert_eq!(record.left_rooms, vec![room_id.to_owned()]);
+    assert!(record.ignored_user.is_none());
+
+    let moderation = client.moderation();
+    assert!(moderation.has_reported_user(sender).await.unwrap());
+
+    // Rooms can be reported without being joined.
+    let other_room_id = room_id!("!other:example.org");
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/.*/rooms/!other:example.org/report$"))
+        .and(body_json(json!({ "reason": "Illegal streams" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    moderation
+        .report_room(
+            other_room_id,
+            ReportReason { category: None, details: Some("Illegal streams".to_owned()) },
+            ReportOptions { leave_room: true, ..Default::default() },
+        )
+        .await
+        .unwrap();
+
+    let reports = moderation.reports().await.unwrap();
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[1].target, ReportTarget::Room { room_id: other_room_id.to_owned() });
+    assert!(reports[1].left_rooms.is_empty());
+}
+
+#[async_test]
+async fn test_redact_events_by() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    client.event_cache().subscribe().unwrap();
+
+    let room_id = room_id!("!room:example.org");
+    let spammer = user_id!("@spammer:example.org");
+    let f = EventFactory::new().room(room_id);
+
+    // The most recent events come with the sync.
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .set_timeline_limited()
+                .set_timeline_prev_batch("prev")
+                .add_timeline_event(
+                    f.text_msg("Buy my stuff")
+                        .sender(spammer)
+                        .event_id(event_id!("$spam2"))
+                        .server_ts(3000),
+                )
+                .add_timeline_event(
+                    f.text_msg("Go away").sender(user_id!("@alice:example.org")).server_ts(3500),
+                )
+                // The state events of the user are kept.
+                .add_timeline_event(
+                    f.member(spammer).display_name("Spammer").sender(spammer).server_ts(3600),
+                ),
+        )
+        .await;
+
+    // The older events come with a back-pagination, which stops once it reaches
+    // the events older than `since`.
+    server
+        .mock_room_messages()
+        .match_from("prev")
+        .ok(RoomMessagesResponseTemplate::default().end_token("older").events(vec![
+            f.text_msg("Cheap pills").sender(spammer).event_id(event_id!("$spam1")).server_ts(2000),
+            f.text_msg("Hello").sender(spammer).server_ts(500),
+        ]))
+        .mock_once()
+        .mount()
+        .await;
+
+    // The first redaction is rate-limited, and retried.
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/redact/\$spam2/"))
+        .respond_with(ResponseTemplate::new(429).set_body_json(json!({
+            "errcode": "M_LIMIT_EXCEEDED",
+            "error": "Too many requests",
+            "retry_after_ms": 10,
+        })))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(server.server())
+        .await;
+    server.mock_room_redact().ok(event_id!("$redaction")).expect(2).mount().await;
+
+    let redact = room
+        .redact_events_by(spammer, MilliSecondsSinceUnixEpoch(1000u32.into()))
+        .with_reason("Spam");
+    let progress = redact.subscribe_to_progress();
+    let outcome = redact.await.unwrap();
+
+    assert_eq!(
+        outcome.redacted,
+        vec![event_id!("$spam2").to_owned(), event_id!("$spam1").to_owned()]
+    );
+    assert!(outcome.failed.is_empty());
+    assert_eq!(progress.get(), RedactionProgress::Done { redacted: 2, failed: 0 });
+}
+
+#[async_test]
+async fn test_redact_events_by_stops_on_empty_pagination() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    client.event_cache().subscribe().unwrap();
+
+    let room_id = room_id!("!room:example.org");
+    let spammer = user_id!("@spammer:example.org");
+    let f = EventFactory::new().room(room_id);
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .set_timeline_limited()
+                .set_timeline_prev_batch("prev")
+                .add_timeline_event(
+                    f.text_msg("Buy my stuff")
+                        .sender(spammer)
+                        .event_id(event_id!("$spam"))
+                        .server_ts(3000),
+                ),
+        )
+        .await;
+
+    // The homeserver returns an empty batch of events without reaching the start
+    // of the room: the search stops there instead of paginating again.
+    server
+        .mock_room_messages()
+        .match_from("prev")
+        .ok(RoomMessagesResponseTemplate::default().end_token("older"))
+        .mock_once()
+        .mount()
+        .await;
+    server.mock_room_redact().ok(event_id!("$redaction")).expect(1).mount().await;
+
+    let outcome =
+        room.redact_events_by(spammer, MilliSecondsSinceUnixEpoch(1000u32.into())).await.unwrap();
+
+    assert_eq!(outcome.redacted, vec![event_id!("$spam").to_owned()]);
+    assert!(outcome.failed.is_empty());
+}
+
+#[async_test]
+async fn test_ban_and_kick_user_from_all_rooms() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let own_user_id = client.user_id().unwrap().to_owned();
+    let spammer = user_id!("@spammer:example.org");
+
+    // We moderate this room, and the spammer is in it.
+    let moderated_room_id = room_id!("!moderated:example.org");
+    let f = EventFactory::new().room(moderated_room_id).sender(&own_user_id);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(moderated_room_id)
+                .add_state_event(
+                    f.power_levels(&mut BTreeMap::from([(own_user_id.clone(), int!(100))]))
+                        .state_key(""),
+                )
+                .add_state_event(
+                    f.member(spammer).sender(spammer).membership(MembershipState::Join),
+                ),
+        )
+        .await;
+
+    // We don't have the power level to ban or kick in this room.
+    let other_room_id = room_id!("!other:example.org");
+    let f = EventFactory::new().room(other_room_id);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(other_room_id).add_state_event(
+                f.member(spammer).sender(spammer).membership(MembershipState::Join),
+            ),
+        )
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path(format!("/_matrix/client/v3/rooms/{moderated_room_id}/kick")))
+        .and(body_partial_json(json!({ "user_id": spammer, "reason": "Spam" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let moderation = client.moderation();
+    let outcome = moderation.kick_user_from_all_rooms(spammer, Some("Spam")).await.unwrap();
+    assert_eq!(outcome.succeeded, vec![moderated_room_id.to_owned()]);
+    assert_eq!(outcome.skipped, vec![other_room_id.to_owned()]);
+    assert!(outcome.failed.is_empty());
+
+    Mock::given(method("POST"))
+        .and(path(format!("/_matrix/client/v3/rooms/{moderated_room_id}/ban")))
+        .and(body_partial_json(json!({ "user_id": spammer })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let outcome = moderation.ban_user_from_all_rooms(spammer, None).await.unwrap();
+    assert_eq!(outcome.succeeded, vec![moderated_room_id.to_owned()]);
+    assert_eq!(outcome.skipped, vec![other_room_id.to_owned()]);
+}
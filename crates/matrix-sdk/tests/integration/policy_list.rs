@@ -0,0 +1,352 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use matrix_sdk::{
+    config::StoreConfig,
+    policy_list::{PolicyEnforcement, PolicyEntityType},
+    test_utils::mocks::MatrixMockServer,
+    MemoryStore,
+};
+use matrix_sdk_test::{
+    async_test, event_factory::EventFactory, InvitedRoomBuilder, JoinedRoomBuilder, ALICE,
+};
+use ruma::{
+    events::{
+        policy::rule::{
+            server::PolicyRuleServerEventContent, user::PolicyRuleUserEventContent,
+            PolicyRuleEventContent, Recommendation,
+        },
+        room::member::MembershipState,
+    },
+    int, room_id,
+    serde::Raw,
+    server_name, user_id, OwnedRoomId, RoomId,
+};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_partial_json, method, path},
+    Mock, ResponseTemplate,
+};
+
+fn user_rule(entity: &str, reason: &str) -> PolicyRuleUserEventContent {
+    PolicyRuleUserEventContent(PolicyRuleEventContent::new(
+        entity.to_owned(),
+        Recommendation::Ban,
+        reason.to_owned(),
+    ))
+}
+
+async fn sync_policy_room(
+    server: &MatrixMockServer,
+    client: &matrix_sdk::Client,
+    room_id: &RoomId,
+) {
+    let f = EventFactory::new().room(room_id).sender(&ALICE);
+
+    server
+        .sync_room(
+            client,
+            JoinedRoomBuilder::new(room_id)
+                .add_state_event(
+                    f.event(user_rule("@spam*:example.org", "spam")).state_key("rule_1"),
+                )
+                .add_state_event(
+                    f.event(PolicyRuleServerEventContent(PolicyRuleEventContent::new(
+                        "evil.org".to_owned(),
+                        Recommendation::Ban,
+                        "".to_owned(),
+                    )))
+                    .state_key("rule_2"),
+                ),
+        )
+        .await;
+}
+
+#[async_test]
+async fn test_subscribe_to_policy_list() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let policy_room_id = room_id!("!policies:example.org");
+
+    sync_policy_room(&server, &client, policy_room_id).await;
+
+    // The rules of an unsubscribed room aren't used.
+    assert!(!client.is_banned_by_policy(user_id!("@spammer:example.org")));
+
+    let policy_lists = client.policy_lists();
+    policy_lists.subscribe(policy_room_id).await.unwrap();
+
+    assert_eq!(policy_lists.subscriptions(), vec![policy_room_id.to_owned()]);
+    assert_eq!(policy_lists.rules().len(), 2);
+
+    let rule = policy_lists.matching_rule(user_id!("@spammer:example.org")).unwrap();
+    assert_eq!(rule.entity_type, PolicyEntityType::User);
+    assert_eq!(rule.reason, "spam");
+    assert!(client.is_banned_by_policy(user_id!("@alice:evil.org")));
+    assert!(client.is_banned_by_policy(server_name!("evil.org")));
+    assert!(!client.is_banned_by_policy(user_id!("@alice:example.org")));
+
+    // The rules are updated with the sync.
+    let f = EventFactory::new().room(policy_room_id).sender(&ALICE);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(policy_room_id)
+                .add_timeline_event(
+                    f.event(user_rule("@alice:example.org", ""))
+                        .state_key("rule_3")
+                        .into_raw_sync(),
+                )
+                // An empty content removes a rule.
+                .add_timeline_event(
+                    Raw::new(&json!({
+                        "type": "m.policy.rule.user",
+                        "event_id": "$remove_rule_1",
+                        "sender": *ALICE,
+                        "origin_server_ts": 1,
+                        "state_key": "rule_1",
+                        "content": {},
+                    }))
+                    .unwrap()
+                    .cast(),
+                ),
+        )
+        .await;
+
+    assert!(client.is_banned_by_policy(user_id!("@alice:example.org")));
+    assert!(!client.is_banned_by_policy(user_id!("@spammer:example.org")));
+
+    policy_lists.unsubscribe(policy_room_id).await.unwrap();
+    assert!(policy_lists.rules().is_empty());
+    assert!(!client.is_banned_by_policy(user_id!("@alice:evil.org")));
+}
+
+#[async_test]
+async fn test_decline_invite_banned_by_policy() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let policy_room_id = room_id!("!policies:example.org");
+
+    let policy_lists = client.policy_lists();
+    // The rules of a room that isn't known yet come with the sync.
+    policy_lists.subscribe(policy_room_id).await.unwrap();
+    sync_policy_room(&server, &client, policy_room_id).await;
+    assert_eq!(policy_lists.rules().len(), 2);
+
+    policy_lists.set_enforcement(PolicyEnforcement { decline_invites: true, ..Default::default() });
+
+    let invited_room_id = room_id!("!invited:example.org");
+    server.mock_room_leave().ok(invited_room_id).mock_once().mount().await;
+
+    let own_user_id = client.user_id().unwrap();
+    let f = EventFactory::new().room(invited_room_id).sender(user_id!("@spammer:example.org"));
+    server
+        .sync_room(
+            &client,
+            InvitedRoomBuilder::new(invited_room_id).add_state_event(
+                f.member(user_id!("@spammer:example.org"))
+                    .invited(own_user_id)
+                    .membership(MembershipState::Invite)
+                    .into_raw(),
+            ),
+        )
+        .await;
+}
+
+#[async_test]
+async fn test_apply_bans_from_policy() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let policy_room_id = room_id!("!policies:example.org");
+    let room_id = room_id!("!room:example.org");
+    let own_user_id = client.user_id().unwrap().to_owned();
+
+    let f = EventFactory::new().room(room_id).sender(&own_user_id);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_event(
+                f.power_levels(&mut BTreeMap::from([(own_user_id.clone(), int!(100))]))
+                    .state_key(""),
+            ),
+        )
+        .await;
+
+    let policy_lists = client.policy_lists();
+    policy_lists.subscribe(policy_room_id).await.unwrap();
+    sync_policy_room(&server, &client, policy_room_id).await;
+    policy_lists.set_enforcement(PolicyEnforcement { apply_bans: true, ..Default::default() });
+
+    Mock::given(method("POST"))
+        .and(path(format!("/_matrix/client/v3/rooms/{room_id}/ban")))
+        .and(body_partial_json(json!({ "user_id": "@spammer:example.org", "reason": "spam" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // A banned user joining the room is banned.
+    let spammer = user_id!("@spammer:example.org");
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_timeline_event(f.member(spammer).sender(spammer).into_raw_sync())
+                .add_timeline_event(
+                    f.member(user_id!("@bob:example.org"))
+                        .sender(user_id!("@bob:example.org"))
+                        .into_raw_sync(),
+                ),
+        )
+        .await;
+}
+
+#[async_test]
+async fn test_apply_bans_from_new_rule() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let own_user_id = client.user_id().unwrap().to_owned();
+    let policy_room_id = room_id!("!policies:example.org");
+    let room_id = room_id!("!room:example.org");
+
+    // The troll is already in a room where we can ban them.
+    let troll = user_id!("@troll:example.org");
+    let bob = user_id!("@bob:example.org");
+    let f = EventFactory::new().room(room_id).sender(&own_user_id);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_state_event(
+                    f.power_levels(&mut BTreeMap::from([(own_user_id.clone(), int!(100))]))
+                        .state_key(""),
+                )
+                .add_timeline_event(f.member(troll).sender(troll).into_raw_sync())
+                .add_timeline_event(f.member(bob).sender(bob).into_raw_sync()),
+        )
+        .await;
+
+    let policy_lists = client.policy_lists();
+    policy_lists.subscribe(policy_room_id).await.unwrap();
+    sync_policy_room(&server, &client, policy_room_id).await;
+    policy_lists.set_enforcement(PolicyEnforcement { apply_bans: true, ..Default::default() });
+
+    Mock::given(method("POST"))
+        .and(path(format!("/_matrix/client/v3/rooms/{room_id}/ban")))
+        .and(body_partial_json(json!({ "user_id": "@troll:example.org", "reason": "trolling" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // A new rule matching the troll bans them from the room.
+    let f = EventFactory::new().room(policy_room_id).sender(&ALICE);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(policy_room_id).add_timeline_event(
+                f.event(user_rule("@troll:example.org", "trolling"))
+                    .state_key("rule_3")
+                    .into_raw_sync(),
+            ),
+        )
+        .await;
+}
+
+#[async_test]
+async fn test_apply_bans_continues_after_failure() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let own_user_id = client.user_id().unwrap().to_owned();
+    let policy_room_id = room_id!("!policies:example.org");
+    let room_id = room_id!("!room:example.org");
+
+    // Two banned users are already in a room where we can ban them.
+    let spammer = user_id!("@spammer:example.org");
+    let evil = user_id!("@alice:evil.org");
+    let f = EventFactory::new().room(room_id).sender(&own_user_id);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_state_event(
+                    f.power_levels(&mut BTreeMap::from([(own_user_id.clone(), int!(100))]))
+                        .state_key(""),
+                )
+                .add_timeline_event(f.member(evil).sender(evil).into_raw_sync())
+                .add_timeline_event(f.member(spammer).sender(spammer).into_raw_sync()),
+        )
+        .await;
+
+    let policy_lists = client.policy_lists();
+    policy_lists.subscribe(policy_room_id).await.unwrap();
+    sync_policy_room(&server, &client, policy_room_id).await;
+
+    // One of the bans fails, the other one still happens.
+    Mock::given(method("POST"))
+        .and(path(format!("/_matrix/client/v3/rooms/{room_id}/ban")))
+        .and(body_partial_json(json!({ "user_id": "@alice:evil.org" })))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "errcode": "M_FORBIDDEN",
+            "error": "You can't ban this user",
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+    Mock::given(method("POST"))
+        .and(path(format!("/_matrix/client/v3/rooms/{room_id}/ban")))
+        .and(body_partial_json(json!({ "user_id": "@spammer:example.org", "reason": "spam" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let applied = policy_lists.apply_bans().await;
+
+    assert_eq!(applied.banned, vec![(room_id.to_owned(), spammer.to_owned())]);
+    assert_eq!(applied.failed.len(), 1);
+    assert_eq!(applied.failed[0].0, room_id.to_owned());
+    assert_eq!(applied.failed[0].1, evil.to_owned());
+}
+
+#[async_test]
+async fn test_restore_policy_list_subscriptions() {
+    let store = Arc::new(MemoryStore::new());
+    let store_config = || {
+        StoreConfig::new("cross-process-store-locks-holder-name".to_owned())
+            .state_store(store.clone())
+    };
+
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().store_config(store_config()).build().await;
+    let policy_room_id = room_id!("!policies:example.org");
+
+    sync_policy_room(&server, &client, policy_room_id).await;
+
+    // The subscriptions are saved in the state store.
+    let policy_lists = client.policy_lists();
+    policy_lists.subscribe(policy_room_id).await.unwrap();
+
+    let saved_subscriptions = || async {
+        let bytes = client
+            .state_store()
+            .get_custom_value(b"policy_list_subscriptions")
+            .await
+            .unwrap()
+            .unwrap();
+        serde_json::from_slice::<Vec<OwnedRoomId>>(&bytes).unwrap()
+    };
+    assert_eq!(saved_subscriptions().await, [policy_room_id]);
+
+    // A client restored with the same stores restores the subscriptions, with
+    // their rules.
+    let restored_client = server.client_builder().store_config(store_config()).build().await;
+    let restored_policy_lists = restored_client.policy_lists();
+    assert!(restored_policy_lists.subscriptions().is_empty());
+
+    restored_policy_lists.restore_subscriptions().await.unwrap();
+    assert_eq!(restored_policy_lists.subscriptions(), [policy_room_id]);
+    assert!(restored_client.is_banned_by_policy(user_id!("@spammer:example.org")));
+
+    policy_lists.unsubscribe(policy_room_id).await.unwrap();
+    assert!(saved_subscriptions().await.is_empty());
+}
@@ -12,18 +12,19 @@ use matrix_sdk_test::{
     SyncResponseBuilder, BOB, DEFAULT_TEST_ROOM_ID,
 };
 use ruma::{
+    api::Direction,
     event_id,
     events::{
         direct::DirectUserIdentifier,
         room::{avatar, member::MembershipState, message::RoomMessageEventContent},
         AnySyncStateEvent, AnySyncTimelineEvent, StateEventType,
     },
-    mxc_uri, owned_room_alias_id, room_id, room_version_id, user_id,
+    mxc_uri, owned_room_alias_id, room_id, room_version_id, user_id, MilliSecondsSinceUnixEpoch,
 };
 use serde_json::json;
 use wiremock::{
     matchers::{body_json, header, method, path, path_regex},
-    Mock, ResponseTemplate,
+    Mock, MockServer, ResponseTemplate,
 };
 
 use crate::{logged_in_client_with_server, mock_sync};
@@ -733,6 +734,144 @@ async fn test_event_with_context() {
     assert!(room_event_cache.find_event(next_event_id).await.is_some());
 }
 
+#[async_test]
+async fn test_event_for_timestamp() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room = server.sync_joined_room(&client, &DEFAULT_TEST_ROOM_ID).await;
+
+    let timestamp = MilliSecondsSinceUnixEpoch(uint!(1_000));
+
+    server
+        .mock_room_timestamp_to_event()
+        .match_direction(Direction::Forward)
+        .ok(event_id!("$after"), MilliSecondsSinceUnixEpoch(uint!(1_042)))
+        .mock_once()
+        .mount()
+        .await;
+    server
+        .mock_room_timestamp_to_event()
+        .match_direction(Direction::Backward)
+        .ok(event_id!("$before"), MilliSecondsSinceUnixEpoch(uint!(958)))
+        .mock_once()
+        .mount()
+        .await;
+
+    let response = room.event_for_timestamp(timestamp, Direction::Forward).await.unwrap();
+    assert_eq!(response.event_id, event_id!("$after"));
+    assert_eq!(response.origin_server_ts, MilliSecondsSinceUnixEpoch(uint!(1_042)));
+
+    let response = room.event_for_timestamp(timestamp, Direction::Backward).await.unwrap();
+    assert_eq!(response.event_id, event_id!("$before"));
+    assert_eq!(response.origin_server_ts, MilliSecondsSinceUnixEpoch(uint!(958)));
+}
+
+#[async_test]
+async fn test_offline_first_members() {
+    // A mock server that isn't shared with other tests, so it can be switched off.
+    let server = MatrixMockServer::from_server(MockServer::builder().start().await);
+    let client = server.client_builder().offline_first().build().await;
+
+    let room_id = room_id!("!galette:saucisse.bzh");
+    let f = EventFactory::new().room(room_id);
+
+    // The room only knows about the lazy-loaded member of Bob.
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_event(f.member(*BOB).sender(*BOB)),
+        )
+        .await;
+    assert!(!room.are_members_synced());
+
+    // The homeserver is switched off.
+    drop(server);
+
+    // The members are served from the store, and are marked as stale.
+    let members = room.members(RoomMemberships::ACTIVE).await.unwrap();
+    assert_eq!(members.len(), 1);
+    assert_eq!(members[0].user_id(), *BOB);
+    assert!(room.are_members_stale());
+
+    assert!(room.get_member(*BOB).await.unwrap().is_some());
+}
+
+#[async_test]
+async fn test_offline_first_members_are_not_stale_once_synced() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().offline_first().build().await;
+
+    let room_id = room_id!("!galette:saucisse.bzh");
+    let room = server.sync_joined_room(&client, room_id).await;
+
+    // The members aren't synced yet.
+    assert!(room.are_members_stale());
+
+    // Only network errors are considered as being offline.
+    server.mock_get_members().error500().mount().await;
+    assert!(room.members(RoomMemberships::ACTIVE).await.is_err());
+    assert!(room.are_members_stale());
+    server.verify_and_reset().await;
+
+    server
+        .mock_get_members()
+        .ok(vec![EventFactory::new().room(room_id).member(*BOB).sender(*BOB).into_raw()])
+        .mock_once()
+        .mount()
+        .await;
+    assert_eq!(room.members(RoomMemberships::ACTIVE).await.unwrap().len(), 1);
+    assert!(room.are_members_synced());
+    assert!(!room.are_members_stale());
+}
+
+#[async_test]
+async fn test_offline_first_event_with_context() {
+    // A mock server that isn't shared with other tests, so it can be switched off.
+    let server = MatrixMockServer::from_server(MockServer::builder().start().await);
+    let client = server.client_builder().offline_first().build().await;
+    client.event_cache().subscribe().unwrap();
+
+    let room_id = room_id!("!galette:saucisse.bzh");
+    let f = EventFactory::new().room(room_id).sender(*BOB);
+
+    // The room has a gap, then 3 events.
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .set_timeline_limited()
+                .set_timeline_prev_batch("prev-batch")
+                .add_timeline_bulk([
+                    f.text_msg("hello").event_id(event_id!("$1")).into_raw_sync(),
+                    f.text_msg("world").event_id(event_id!("$2")).into_raw_sync(),
+                    f.text_msg("!").event_id(event_id!("$3")).into_raw_sync(),
+                ]),
+        )
+        .await;
+
+    // The homeserver is switched off.
+    drop(server);
+
+    // The context is loaded from the event cache.
+    let response = room.event_with_context(event_id!("$2"), true, uint!(10), None).await.unwrap();
+    assert!(response.is_stale);
+
+    assert_eq!(response.event.unwrap().event_id().as_deref(), Some(event_id!("$2")));
+    assert_eq!(response.events_before.len(), 1);
+    assert_eq!(response.events_before[0].event_id().as_deref(), Some(event_id!("$1")));
+    assert_eq!(response.events_after.len(), 1);
+    assert_eq!(response.events_after[0].event_id().as_deref(), Some(event_id!("$3")));
+
+    // Back-pagination can resume from the gap once the homeserver is back.
+    assert_eq!(response.prev_batch_token.as_deref(), Some("prev-batch"));
+    assert!(response.next_batch_token.is_none());
+
+    // An unknown event still results in the network error.
+    let error = room.event_with_context(event_id!("$4"), true, uint!(10), None).await.unwrap_err();
+    assert!(error.is_network_error());
+}
+
 #[async_test]
 async fn test_is_direct() {
     let (client, server) = logged_in_client_with_server().await;
@@ -1,17 +1,30 @@
 use std::time::Duration;
 
 use assert_matches2::assert_matches;
-use matrix_sdk::{config::SyncSettings, linked_chunk::LinkedChunkId};
+use futures_util::{pin_mut, StreamExt as _};
+use matrix_sdk::{
+    assert_next_eq_with_timeout,
+    config::SyncSettings,
+    linked_chunk::LinkedChunkId,
+    room::knock_requests::{OwnKnock, OwnKnockState},
+    test_utils::mocks::MatrixMockServer,
+    Error,
+};
 use matrix_sdk_base::{RoomInfoNotableUpdateReasons, RoomState};
 use matrix_sdk_test::{
-    async_test, test_json, GlobalAccountDataTestEvent, LeftRoomBuilder, SyncResponseBuilder,
+    async_test, event_factory::EventFactory, test_json, GlobalAccountDataTestEvent,
+    InvitedRoomBuilder, KnockedRoomBuilder, LeftRoomBuilder, SyncResponseBuilder,
     DEFAULT_TEST_ROOM_ID,
 };
 use ruma::{
-    events::direct::{DirectEventContent, DirectUserIdentifier},
-    user_id, OwnedRoomOrAliasId,
+    events::{
+        direct::{DirectEventContent, DirectUserIdentifier},
+        room::member::MembershipState,
+    },
+    room_id, user_id, OwnedRoomOrAliasId,
 };
 use serde_json::json;
+use stream_assert::assert_pending;
 use tokio::task::yield_now;
 use wiremock::{
     matchers::{header, method, path, path_regex},
@@ -253,3 +266,103 @@ async fn test_knocking() {
     let room = client.knock(room_id, None, Vec::new()).await.unwrap();
     assert_eq!(room.state(), RoomState::Knocked);
 }
+
+#[async_test]
+async fn test_own_knocks_lifecycle() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let own_user_id = client.user_id().unwrap().to_owned();
+    let moderator = user_id!("@moderator:localhost");
+    let f = EventFactory::new().sender(moderator);
+
+    let accepted_room_id = room_id!("!accepted:localhost");
+    let denied_room_id = room_id!("!denied:localhost");
+
+    // The user has already knocked on a room when subscribing.
+    server.sync_room(&client, KnockedRoomBuilder::new(accepted_room_id)).await;
+
+    let knocks = client.subscribe_to_own_knocks();
+    pin_mut!(knocks);
+
+    assert_next_eq_with_timeout!(
+        knocks,
+        OwnKnock { room_id: accepted_room_id.to_owned(), state: OwnKnockState::Pending }
+    );
+
+    // The user knocks on another room.
+    server.sync_room(&client, KnockedRoomBuilder::new(denied_room_id)).await;
+    assert_next_eq_with_timeout!(
+        knocks,
+        OwnKnock { room_id: denied_room_id.to_owned(), state: OwnKnockState::Pending }
+    );
+
+    // The first knock is accepted.
+    server.sync_room(&client, InvitedRoomBuilder::new(accepted_room_id)).await;
+    assert_next_eq_with_timeout!(
+        knocks,
+        OwnKnock { room_id: accepted_room_id.to_owned(), state: OwnKnockState::Accepted }
+    );
+
+    // The second one is denied.
+    server
+        .sync_room(
+            &client,
+            LeftRoomBuilder::new(denied_room_id).add_timeline_event(
+                f.member(moderator).kicked(&own_user_id).reason("Not today").room(denied_room_id),
+            ),
+        )
+        .await;
+    assert_next_eq_with_timeout!(
+        knocks,
+        OwnKnock {
+            room_id: denied_room_id.to_owned(),
+            state: OwnKnockState::Denied { reason: Some("Not today".to_owned()) }
+        }
+    );
+
+    // Answered knocks aren't tracked anymore.
+    server.sync_joined_room(&client, accepted_room_id).await;
+    assert_pending!(knocks);
+}
+
+#[async_test]
+async fn test_retract_knock() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let own_user_id = client.user_id().unwrap().to_owned();
+    let room_id = room_id!("!knocked:localhost");
+
+    // Only a knocked room can have its knock retracted.
+    let joined_room = server.sync_joined_room(&client, room_id!("!joined:localhost")).await;
+    assert_matches!(joined_room.retract_knock().await, Err(Error::WrongRoomState(_)));
+
+    let room = server.sync_room(&client, KnockedRoomBuilder::new(room_id)).await;
+
+    let knocks = client.subscribe_to_own_knocks();
+    pin_mut!(knocks);
+    assert_next_eq_with_timeout!(
+        knocks,
+        OwnKnock { room_id: room_id.to_owned(), state: OwnKnockState::Pending }
+    );
+
+    server.mock_room_leave().ok(room_id).mock_once().mount().await;
+    room.retract_knock().await.unwrap();
+    assert_eq!(room.state(), RoomState::Left);
+
+    // The homeserver then confirms the user left the room on their own.
+    server
+        .sync_room(
+            &client,
+            LeftRoomBuilder::new(room_id).add_timeline_event(
+                EventFactory::new()
+                    .member(&own_user_id)
+                    .membership(MembershipState::Leave)
+                    .room(room_id),
+            ),
+        )
+        .await;
+    assert_next_eq_with_timeout!(
+        knocks,
+        OwnKnock { room_id: room_id.to_owned(), state: OwnKnockState::Retracted }
+    );
+}
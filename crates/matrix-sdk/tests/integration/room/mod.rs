@@ -5,5 +5,6 @@ mod common;
 mod joined;
 mod left;
 mod notification_mode;
+mod server_acl;
 mod spaces;
 mod tags;
@@ -0,0 +1,85 @@
+use assert_matches2::assert_matches;
+use matrix_sdk::{room::server_acl::ServerAclError, test_utils::mocks::MatrixMockServer, Error};
+use matrix_sdk_test::{async_test, event_factory::EventFactory, JoinedRoomBuilder};
+use ruma::{event_id, events::StateEventType, room_id, server_name, user_id};
+use serde_json::json;
+
+#[async_test]
+async fn test_server_acl() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!acl:localhost");
+    let own_user_id = client.user_id().unwrap().to_owned();
+    let alice = user_id!("@alice:matrix.org");
+    let spammer = user_id!("@spammer:evil.org");
+
+    let f = EventFactory::new().room(room_id).sender(&own_user_id);
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_bulk([
+                f.member(&own_user_id).into_raw(),
+                f.member(alice).sender(alice).into_raw(),
+                f.member(spammer).sender(spammer).into_raw(),
+                f.server_acl(false, vec!["*".to_owned()], vec![]).state_key("").into_raw(),
+            ]),
+        )
+        .await;
+
+    let acl = room.server_acl().await.unwrap().unwrap();
+    assert_eq!(acl.allow, ["*"]);
+    assert!(acl.deny.is_empty());
+    assert!(!acl.allow_ip_literals);
+
+    // The preview lists the members of the denied servers.
+    let preview = room
+        .preview_server_acl(vec!["*".to_owned()], vec!["*.org".to_owned()], false)
+        .await
+        .unwrap();
+    assert_eq!(preview.denied_servers.len(), 2);
+    assert!(preview.denied_servers.contains(server_name!("matrix.org")));
+    assert!(preview.denied_servers.contains(server_name!("evil.org")));
+    assert_eq!(preview.affected_members.len(), 2);
+    assert!(preview.affected_members.iter().any(|user_id| user_id == alice));
+    assert!(preview.affected_members.iter().any(|user_id| user_id == spammer));
+
+    server
+        .mock_room_send_state()
+        .for_type(StateEventType::RoomServerAcl)
+        .body_matches_partial_json(json!({
+            "allow": ["*"],
+            "deny": ["evil.org"],
+            "allow_ip_literals": false,
+        }))
+        .ok(event_id!("$acl"))
+        .mock_once()
+        .mount()
+        .await;
+
+    let response =
+        room.update_server_acl(vec!["*".to_owned()], vec!["evil.org".to_owned()], false).await;
+    assert_eq!(response.unwrap().event_id, event_id!("$acl"));
+}
+
+#[async_test]
+async fn test_invalid_server_acl_is_refused() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room = server.sync_joined_room(&client, room_id!("!acl:localhost")).await;
+
+    server.mock_room_send_state().ok(event_id!("$acl")).never().mount().await;
+
+    let result =
+        room.update_server_acl(vec!["*".to_owned()], vec!["evil.org:8448".to_owned()], false).await;
+    assert_matches!(result, Err(Error::ServerAcl(ServerAclError::InvalidGlob(glob))));
+    assert_eq!(glob, "evil.org:8448");
+
+    // Our own server is denied explicitly.
+    let result =
+        room.update_server_acl(vec!["*".to_owned()], vec!["localhost".to_owned()], false).await;
+    assert_matches!(result, Err(Error::ServerAcl(ServerAclError::OwnServerDenied)));
+
+    // Our own server is not in the allowed servers.
+    let result = room.update_server_acl(vec!["matrix.org".to_owned()], vec![], false).await;
+    assert_matches!(result, Err(Error::ServerAcl(ServerAclError::OwnServerDenied)));
+}
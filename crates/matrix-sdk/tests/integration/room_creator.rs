@@ -0,0 +1,195 @@
+use std::{future::IntoFuture, time::Duration};
+
+use matrix_sdk::{
+    room_creator::{PowerLevelsTemplate, RoomCreatorPreset},
+    test_utils::mocks::MatrixMockServer,
+};
+use matrix_sdk_test::{async_test, JoinedRoomBuilder};
+use ruma::{event_id, events::StateEventType, mxc_uri, owned_user_id, room_id};
+use serde_json::{json, Value};
+use tokio::{spawn, time::timeout};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+#[async_test]
+async fn test_create_public_channel_in_space() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let space_id = room_id!("!space:localhost");
+    let room_id = room_id!("!room:localhost");
+
+    server.sync_joined_room(&client, space_id).await;
+
+    server.mock_authenticated_media_config().ok_default().mount().await;
+    server
+        .mock_upload()
+        .expect_mime_type("image/png")
+        .ok(mxc_uri!("mxc://localhost/avatar"))
+        .mock_once()
+        .mount()
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/createRoom"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": room_id })))
+        .expect(1)
+        .named("create_room")
+        .mount(server.server())
+        .await;
+
+    server
+        .mock_room_send_state()
+        .for_type(StateEventType::SpaceChild)
+        .for_key(room_id.to_string())
+        .body_matches_partial_json(json!({ "via": ["localhost"] }))
+        .ok(event_id!("$child"))
+        .mock_once()
+        .mount()
+        .await;
+
+    let creator = client
+        .room_creator(RoomCreatorPreset::PublicChannel)
+        .name("Announcements")
+        .alias("announcements")
+        .power_levels(PowerLevelsTemplate::Announcements)
+        .user_power_level(owned_user_id!("@mod:localhost"), 50.into())
+        .avatar(mime::IMAGE_PNG, b"avatar".to_vec())
+        .in_space(space_id.to_owned());
+    let task = spawn(creator.into_future());
+
+    // The room is only returned once it came down the sync.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!task.is_finished());
+
+    server.sync_room(&client, JoinedRoomBuilder::new(room_id)).await;
+    let room = timeout(Duration::from_secs(1), task).await.unwrap().unwrap().unwrap();
+    assert_eq!(room.room_id(), room_id);
+
+    let requests = server.server().received_requests().await.unwrap();
+    let request = requests.iter().find(|request| request.url.path().ends_with("/createRoom"));
+    let body: Value = request.unwrap().body_json().unwrap();
+
+    assert_eq!(body["preset"], "public_chat");
+    assert_eq!(body["visibility"], "public");
+    assert_eq!(body["name"], "Announcements");
+    assert_eq!(body["room_alias_name"], "announcements");
+    assert_eq!(
+        body["power_level_content_override"],
+        json!({
+            "events_default": 50,
+            "users": { "@example:localhost": 100, "@mod:localhost": 50 },
+        })
+    );
+
+    let initial_state = body["initial_state"].as_array().unwrap();
+    let state = |event_type: &str| {
+        initial_state
+            .iter()
+            .find(|event| event["type"] == event_type)
+            .map(|event| &event["content"])
+    };
+
+    assert_eq!(state("m.room.join_rules").unwrap(), &json!({ "join_rule": "public" }));
+    assert_eq!(
+        state("m.room.history_visibility").unwrap(),
+        &json!({ "history_visibility": "shared" })
+    );
+    assert!(state("m.room.encryption").is_none());
+    assert_eq!(state("m.room.avatar").unwrap()["url"], "mxc://localhost/avatar");
+    assert_eq!(
+        state("m.space.parent").unwrap(),
+        &json!({ "via": ["localhost"], "canonical": true })
+    );
+}
+
+#[async_test]
+async fn test_create_private_space() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!space:localhost");
+    let child_id = room_id!("!child:localhost");
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/createRoom"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": room_id })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // The room was already synced, so it's returned right away.
+    server.sync_joined_room(&client, room_id).await;
+
+    let space = client
+        .room_creator(RoomCreatorPreset::PrivateSpace)
+        .invite([owned_user_id!("@alice:localhost")])
+        .space_child(child_id.to_owned())
+        .await
+        .unwrap();
+    assert_eq!(space.room_id(), room_id);
+
+    let requests = server.server().received_requests().await.unwrap();
+    let request = requests.iter().find(|request| request.url.path().ends_with("/createRoom"));
+    let body: Value = request.unwrap().body_json().unwrap();
+
+    assert_eq!(body["preset"], "private_chat");
+    assert_eq!(body["creation_content"], json!({ "type": "m.space" }));
+    assert_eq!(body["invite"], json!(["@alice:localhost"]));
+    assert!(body.get("power_level_content_override").is_none());
+
+    let initial_state = body["initial_state"].as_array().unwrap();
+    assert!(initial_state
+        .iter()
+        .any(|event| event["type"] == "m.room.join_rules"
+            && event["content"]["join_rule"] == "invite"));
+    assert!(initial_state.iter().all(|event| event["type"] != "m.room.encryption"));
+    assert!(initial_state
+        .iter()
+        .any(|event| event["type"] == "m.space.child" && event["state_key"] == child_id.as_str()));
+}
+
+#[async_test]
+async fn test_create_collaborative_room() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!room:localhost");
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/createRoom"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": room_id })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // The room was already synced, so it's returned right away.
+    server.sync_joined_room(&client, room_id).await;
+
+    client
+        .room_creator(RoomCreatorPreset::PrivateRoom)
+        .power_levels(PowerLevelsTemplate::Collaborative)
+        .await
+        .unwrap();
+
+    let requests = server.server().received_requests().await.unwrap();
+    let request = requests.iter().find(|request| request.url.path().ends_with("/createRoom"));
+    let body: Value = request.unwrap().body_json().unwrap();
+
+    // The events restricted to the administrators by the preset stay restricted.
+    assert_eq!(
+        body["power_level_content_override"],
+        json!({
+            "invite": 0,
+            "events": {
+                "m.room.name": 0,
+                "m.room.topic": 0,
+                "m.room.avatar": 0,
+                "m.room.power_levels": 100,
+                "m.room.history_visibility": 100,
+                "m.room.encryption": 100,
+                "m.room.server_acl": 100,
+                "m.room.tombstone": 100,
+            },
+        })
+    );
+}
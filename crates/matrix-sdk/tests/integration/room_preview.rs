@@ -2,6 +2,7 @@ use assert_matches::assert_matches;
 use js_int::uint;
 use matrix_sdk::{
     config::SyncSettings,
+    room_preview::RoomPreviewAction,
     test_utils::{logged_in_client_with_server, mocks::MatrixMockServer},
 };
 use matrix_sdk_base::{RequestedRequiredStates, RoomState};
@@ -106,6 +107,8 @@ async fn test_room_preview_leave_knocked() {
 
     let room_preview = client.get_room_preview(room_id.into(), Vec::new()).await.unwrap();
     assert_eq!(room_preview.state.unwrap(), RoomState::Knocked);
+    assert_eq!(room_preview.next_action, RoomPreviewAction::AwaitKnockAnswer);
+    assert!(!room_preview.can_knock());
 
     let room = client.get_room(room_id).unwrap();
     room.leave().await.unwrap();
@@ -179,6 +182,74 @@ async fn test_room_preview_computes_name_if_room_is_known() {
     assert_eq!(room_preview.name.unwrap(), "Alice");
 }
 
+#[async_test]
+async fn test_room_preview_next_action_for_knock_restricted_room() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!room:localhost");
+    let space_id = room_id!("!space:localhost");
+    let other_space_id = room_id!("!other_space:localhost");
+
+    // The user is a member of a space giving access to the room.
+    server.sync_joined_room(&client, space_id).await;
+
+    let summary_mock = |allowed_room_id: &RoomId| {
+        Mock::given(method("GET")).and(path_regex(r"/rooms/.*/summary")).respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({
+                "room_id": room_id,
+                "guest_can_join": false,
+                "num_joined_members": 1,
+                "world_readable": false,
+                "join_rule": "knock_restricted",
+                "allowed_room_ids": [allowed_room_id],
+            })),
+        )
+    };
+
+    // The user can join the room directly, thanks to their membership of the space.
+    let guard = summary_mock(space_id).mount_as_scoped(server.server()).await;
+    let preview = client.get_room_preview(room_id.into(), Vec::new()).await.unwrap();
+    assert_eq!(preview.join_rule, Some(SpaceRoomJoinRule::KnockRestricted));
+    assert_eq!(preview.next_action, RoomPreviewAction::Join);
+    assert!(!preview.can_knock());
+    drop(guard);
+
+    // If the room is only accessible from another space, they have to knock.
+    summary_mock(other_space_id).mount(server.server()).await;
+    let preview = client.get_room_preview(room_id.into(), Vec::new()).await.unwrap();
+    assert_eq!(preview.next_action, RoomPreviewAction::Knock);
+    assert!(preview.can_knock());
+}
+
+#[async_test]
+async fn test_room_preview_next_action_from_join_rule() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!room:localhost");
+
+    for (join_rule, expected_action) in [
+        (SpaceRoomJoinRule::Public, RoomPreviewAction::Join),
+        (SpaceRoomJoinRule::Knock, RoomPreviewAction::Knock),
+        (SpaceRoomJoinRule::Invite, RoomPreviewAction::AwaitInvite),
+        (SpaceRoomJoinRule::Restricted, RoomPreviewAction::AwaitInvite),
+    ] {
+        let _guard = Mock::given(method("GET"))
+            .and(path_regex(r"/rooms/.*/summary"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "room_id": room_id,
+                "guest_can_join": false,
+                "num_joined_members": 1,
+                "world_readable": false,
+                "join_rule": join_rule,
+            })))
+            .mount_as_scoped(server.server())
+            .await;
+
+        let preview = client.get_room_preview(room_id.into(), Vec::new()).await.unwrap();
+        assert_eq!(preview.next_action, expected_action, "for the {join_rule:?} join rule");
+    }
+}
+
 async fn mock_leave(room_id: &RoomId, server: &MockServer) {
     Mock::given(method("POST"))
         .and(path_regex(r"^/_matrix/client/r0/rooms/.*/leave"))
@@ -14,7 +14,7 @@ use matrix_sdk::{
         RoomSendQueueUpdate, SendHandle, SendQueueUpdate,
     },
     test_utils::mocks::{MatrixMock, MatrixMockServer},
-    Client, MemoryStore,
+    Client, MemoryStore, QueueWedgeError, TransmissionProgress,
 };
 use matrix_sdk_test::{
     async_test, event_factory::EventFactory, InvitedRoomBuilder, KnockedRoomBuilder,
@@ -3654,3 +3654,230 @@ async fn test_update_caption_while_sending_media_event() {
     // That's all, folks!
     assert!(watch.is_empty());
 }
+
+/// Consumes the upload progress updates for a media, until it's been uploaded.
+///
+/// Returns the last progress update, as a tuple of (is_thumbnail, progress,
+/// total_progress), along with the final media source.
+async fn wait_for_upload_with_progress(
+    global_watch: &mut Receiver<SendQueueUpdate>,
+    watch: &mut Receiver<RoomSendQueueUpdate>,
+) -> ((bool, TransmissionProgress, TransmissionProgress), MediaSource) {
+    let mut last_progress = None;
+
+    loop {
+        let update = timeout(Duration::from_secs(1), watch.recv()).await.unwrap().unwrap();
+        assert!(global_watch.recv().await.is_ok());
+
+        match update {
+            RoomSendQueueUpdate::MediaUpload { is_thumbnail, progress, total_progress, .. } => {
+                last_progress = Some((is_thumbnail, progress, total_progress));
+            }
+            RoomSendQueueUpdate::UploadedMedia { file, .. } => {
+                return (last_progress.expect("there should be some upload progress"), file);
+            }
+            update => panic!("unexpected update: {update:?}"),
+        }
+    }
+}
+
+#[async_test]
+async fn test_media_upload_progress() {
+    let mock = MatrixMockServer::new().await;
+
+    // Mark the room as joined.
+    let room_id = room_id!("!a:b.c");
+    let client = mock.client_builder().build().await;
+    let room = mock.sync_joined_room(&client, room_id).await;
+
+    client.send_queue().enable_upload_progress(true);
+
+    let q = room.send_queue();
+    let (local_echoes, mut watch) = q.subscribe().await.unwrap();
+    assert!(local_echoes.is_empty());
+
+    let mut global_watch = client.send_queue().subscribe();
+
+    // Prepare endpoints. Delay the uploads, so the progress is observed before the
+    // uploads complete.
+    mock.mock_authenticated_media_config().ok_default().mount().await;
+    mock.mock_room_state_encryption().plain().mount().await;
+    mock.mock_upload()
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_delay(Duration::from_millis(200))
+                .set_body_json(json!({ "content_uri": "mxc://sdk.rs/thumbnail" })),
+        )
+        .mock_once()
+        .mount()
+        .await;
+    mock.mock_upload()
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_delay(Duration::from_millis(200))
+                .set_body_json(json!({ "content_uri": "mxc://sdk.rs/media" })),
+        )
+        .mock_once()
+        .mount()
+        .await;
+    mock.mock_room_send().ok(event_id!("$1")).mock_once().mount().await;
+
+    // Send the media, with a thumbnail of 9 bytes and a file of 11 bytes.
+    let (_handle, _filename) = queue_attachment_with_thumbnail(&q).await;
+    let (event_txn, _send_handle, _content) =
+        assert_update!((global_watch, watch) => local echo event);
+
+    // The thumbnail is uploaded first.
+    let ((is_thumbnail, progress, total_progress), file) =
+        wait_for_upload_with_progress(&mut global_watch, &mut watch).await;
+    assert!(is_thumbnail);
+    assert_eq!((progress.current, progress.total), (9, 9));
+    assert_eq!((total_progress.current, total_progress.total), (9, 20));
+    assert_let!(MediaSource::Plain(mxc) = file);
+    assert_eq!(mxc, mxc_uri!("mxc://sdk.rs/thumbnail"));
+
+    // Then the file itself.
+    let ((is_thumbnail, progress, total_progress), file) =
+        wait_for_upload_with_progress(&mut global_watch, &mut watch).await;
+    assert!(!is_thumbnail);
+    assert_eq!((progress.current, progress.total), (11, 11));
+    assert_eq!((total_progress.current, total_progress.total), (20, 20));
+    assert_let!(MediaSource::Plain(mxc) = file);
+    assert_eq!(mxc, mxc_uri!("mxc://sdk.rs/media"));
+
+    assert_update!((global_watch, watch) => edit local echo { txn = event_txn });
+    assert_update!((global_watch, watch) => sent { txn = event_txn, event_id = event_id!("$1") });
+
+    // That's all, folks!
+    assert!(watch.is_empty());
+}
+
+#[async_test]
+async fn test_pause_and_restart_media_upload() {
+    let mock = MatrixMockServer::new().await;
+
+    // Mark the room as joined.
+    let room_id = room_id!("!a:b.c");
+    let client = mock.client_builder().build().await;
+    let room = mock.sync_joined_room(&client, room_id).await;
+
+    let q = room.send_queue();
+    let (local_echoes, mut watch) = q.subscribe().await.unwrap();
+    assert!(local_echoes.is_empty());
+
+    let mut global_watch = client.send_queue().subscribe();
+
+    // Prepare endpoints. The first upload takes forever, and will be interrupted
+    // when pausing.
+    mock.mock_authenticated_media_config().ok_default().mount().await;
+    mock.mock_room_state_encryption().plain().mount().await;
+    mock.mock_upload()
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(60)))
+        .mock_once()
+        .mount()
+        .await;
+
+    // Send the media.
+    let (_handle, _filename) = queue_attachment_no_thumbnail(&q).await;
+    let (event_txn, send_handle, _content) =
+        assert_update!((global_watch, watch) => local echo event);
+
+    // Let the upload request start, then pause it.
+    sleep(Duration::from_millis(500)).await;
+    assert!(send_handle.pause_upload().await.unwrap());
+
+    let error =
+        assert_update!((global_watch, watch) => error { recoverable = false, txn = event_txn });
+    assert_let!(matrix_sdk::Error::SendQueueWedgeError(wedge_error) = &*error);
+    assert_matches!(&**wedge_error, QueueWedgeError::UploadPaused);
+
+    // Pausing doesn't disable the queue.
+    assert!(q.is_enabled());
+
+    // Pausing a second time is a no-op.
+    assert!(!send_handle.pause_upload().await.unwrap());
+
+    // The paused state is reflected in the local echoes.
+    let (local_echoes, _) = q.subscribe().await.unwrap();
+    assert_eq!(local_echoes.len(), 1);
+    assert_let!(
+        LocalEchoContent::Event { send_error: Some(QueueWedgeError::UploadPaused), .. } =
+            &local_echoes[0].content
+    );
+
+    // Restart the upload.
+    mock.mock_upload().ok(mxc_uri!("mxc://sdk.rs/media")).mock_once().mount().await;
+    mock.mock_room_send().ok(event_id!("$1")).mock_once().mount().await;
+
+    assert!(send_handle.restart_upload().await.unwrap());
+
+    assert_update!((global_watch, watch) => retry { txn = event_txn });
+    assert_update!((global_watch, watch) => uploaded { related_to = event_txn, mxc = mxc_uri!("mxc://sdk.rs/media") });
+    assert_update!((global_watch, watch) => edit local echo { txn = event_txn });
+    assert_update!((global_watch, watch) => sent { txn = event_txn, event_id = event_id!("$1") });
+
+    // That's all, folks!
+    assert!(watch.is_empty());
+}
+
+#[async_test]
+async fn test_media_upload_retry_reuses_preallocated_uri() {
+    let mock = MatrixMockServer::new().await;
+
+    // Mark the room as joined.
+    let room_id = room_id!("!a:b.c");
+    let client = mock.client_builder().build().await;
+    let room = mock.sync_joined_room(&client, room_id).await;
+
+    let q = room.send_queue();
+    let (local_echoes, mut watch) = q.subscribe().await.unwrap();
+    assert!(local_echoes.is_empty());
+
+    let mut global_watch = client.send_queue().subscribe();
+
+    // Prepare endpoints. The MXC URI must be allocated only once.
+    mock.mock_authenticated_media_config().ok_default().mount().await;
+    mock.mock_room_state_encryption().plain().mount().await;
+    mock.mock_media_allocate().ok().expect(1).mount().await;
+
+    // Fail for the first three attempts.
+    mock.mock_media_allocated_upload("example.com", "AQwafuaFswefuhsfAFAgsw")
+        .error500()
+        .up_to_n_times(3)
+        .expect(3)
+        .mount()
+        .await;
+
+    // Send a media large enough to be uploaded to a preallocated MXC URI.
+    let data = vec![42; 2 * 1024 * 1024];
+    q.send_attachment("big.jpeg", mime::IMAGE_JPEG, data, AttachmentConfig::new())
+        .await
+        .expect("queuing the attachment works");
+    let (event_txn, _send_handle, _content) =
+        assert_update!((global_watch, watch) => local echo event);
+
+    // Let the upload stumble and the queue disable itself.
+    assert_update!((global_watch, watch) => error { recoverable = true, txn = event_txn });
+    assert!(!q.is_enabled());
+
+    // Pretend that the server actually received the media during a previous
+    // attempt: it's considered as uploaded, and the same MXC URI is used.
+    mock.mock_media_allocated_upload("example.com", "AQwafuaFswefuhsfAFAgsw")
+        .error_cannot_overwrite_media()
+        .mock_once()
+        .mount()
+        .await;
+    mock.mock_room_send().ok(event_id!("$1")).mock_once().mount().await;
+
+    q.set_enabled(true);
+
+    assert_update!((global_watch, watch) => uploaded {
+        related_to = event_txn,
+        mxc = mxc_uri!("mxc://example.com/AQwafuaFswefuhsfAFAgsw")
+    });
+    assert_update!((global_watch, watch) => edit local echo { txn = event_txn });
+    assert_update!((global_watch, watch) => sent { txn = event_txn, event_id = event_id!("$1") });
+
+    // That's all, folks!
+    assert!(watch.is_empty());
+}
@@ -0,0 +1,76 @@
+use matrix_sdk::{
+    config::SyncSettings,
+    test_utils::{assert_event_matches_msg, client::MockClientBuilder},
+    Client, RoomState,
+};
+use matrix_sdk_test::{
+    async_test,
+    homeserver::FakeHomeserver,
+    sync_replay::{SyncRecording, SyncReplayServer},
+};
+use serde_json::json;
+use tempfile::tempdir;
+
+async fn sync_once(client: &Client) {
+    client.sync_once(SyncSettings::new()).await.unwrap();
+}
+
+#[async_test]
+async fn test_record_and_replay_syncs() {
+    let homeserver = FakeHomeserver::new().await;
+    let alice_id = homeserver.register_user("alice", "alice_password");
+    let bob_id = homeserver.register_user("bob", "bob_password");
+
+    let session = homeserver.login(&alice_id);
+    let access_token = session.access_token.clone();
+    let alice = MockClientBuilder::for_fake_homeserver(&homeserver, session).build().await;
+
+    let dir = tempdir().unwrap();
+    let recording_path = dir.path().join("recording.jsonl");
+    alice.start_recording_syncs(&recording_path).unwrap();
+
+    // Alice joins a room created by Bob, and receives a message.
+    let room_id = homeserver.create_room(&bob_id, &[&alice_id]);
+    sync_once(&alice).await;
+
+    alice.join_room_by_id(&room_id).await.unwrap();
+    homeserver.send_event(
+        &room_id,
+        &bob_id,
+        "m.room.message",
+        json!({ "msgtype": "m.text", "body": "Hello Alice!" }),
+    );
+    sync_once(&alice).await;
+
+    // The syncs after the end of the recording aren't recorded.
+    alice.stop_recording_syncs().await;
+    sync_once(&alice).await;
+
+    let recording_file = std::fs::read_to_string(&recording_path).unwrap();
+    assert!(!recording_file.contains(&access_token));
+
+    let recording = SyncRecording::from_file(&recording_path);
+    assert_eq!(recording.user_id(), &alice_id);
+    assert_eq!(recording.syncs().len(), 2);
+
+    let first_sync = &recording.syncs()[0];
+    assert_eq!(first_sync.request.method, "GET");
+    assert!(first_sync.request.path.ends_with("/sync"));
+    assert_eq!(first_sync.response.status, 200);
+
+    // A fresh client replaying the recording ends up in the same state.
+    let server = SyncReplayServer::start(recording).await;
+    let replayed = MockClientBuilder::for_sync_replay(&server).build().await;
+    assert_eq!(replayed.user_id(), Some(&*alice_id));
+
+    sync_once(&replayed).await;
+    let room = replayed.get_room(&room_id).unwrap();
+    assert_eq!(room.state(), RoomState::Invited);
+
+    let response = replayed.sync_once(SyncSettings::new()).await.unwrap();
+    assert_eq!(room.state(), RoomState::Joined);
+    assert_eq!(server.remaining_syncs(), 0);
+
+    let timeline = &response.rooms.joined[&room_id].timeline;
+    assert_event_matches_msg(timeline.events.last().unwrap(), "Hello Alice!");
+}
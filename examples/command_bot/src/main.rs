@@ -1,31 +1,24 @@
 use std::{env, process::exit};
 
 use matrix_sdk::{
-    Client, Room, RoomState,
+    Client,
+    command::{Command, CommandContext, CommandRouter},
     config::SyncSettings,
-    ruma::events::room::message::{
-        MessageType, OriginalSyncRoomMessageEvent, RoomMessageEventContent,
-    },
 };
 
-async fn on_room_message(event: OriginalSyncRoomMessageEvent, room: Room) {
-    if room.state() != RoomState::Joined {
-        return;
-    }
-    let MessageType::Text(text_content) = event.content.msgtype else {
-        return;
-    };
-
-    if text_content.body.contains("!party") {
-        let content = RoomMessageEventContent::text_plain("🎉🎊🥳 let's PARTY!! 🥳🎊🎉");
-
-        println!("sending");
+fn command_router() -> CommandRouter {
+    CommandRouter::new().command(
+        Command::new("party", |ctx: CommandContext, ()| async move {
+            println!("sending");
 
-        // send our message to the room we found the "!party" command in
-        room.send(content).await.unwrap();
+            // reply to the "!party" command in the room we found it in
+            ctx.reply_text("🎉🎊🥳 let's PARTY!! 🥳🎊🎉").await?;
 
-        println!("message sent");
-    }
+            println!("message sent");
+            anyhow::Ok(())
+        })
+        .description("Start a party"),
+    )
 }
 
 async fn login_and_sync(
@@ -48,9 +41,9 @@ async fn login_and_sync(
     // An initial sync to set up state and so our bot doesn't respond to old
     // messages.
     let response = client.sync_once(SyncSettings::default()).await.unwrap();
-    // add our CommandBot to be notified of incoming messages, we do this after the
-    // initial sync to avoid responding to messages before the bot was running.
-    client.add_event_handler(on_room_message);
+    // register our commands to be notified of incoming messages, we do this after
+    // the initial sync to avoid responding to messages before the bot was running.
+    command_router().register(&client);
 
     // since we called `sync_once` before we entered our sync loop we must pass
     // that sync token to `sync`
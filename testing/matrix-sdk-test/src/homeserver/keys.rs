@@ -0,0 +1,284 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The end-to-end encryption endpoints: keys, cross-signing and to-device
+//! messages.
+
+use ruma::{OwnedDeviceId, UserId};
+use serde_json::{Map as JsonMap, Value as JsonValue, json};
+use wiremock::{Request, ResponseTemplate};
+
+use super::{Requester, body, ok, state::HomeserverState};
+
+/// The usages of the cross-signing keys, and the fields they're uploaded and
+/// queried with.
+const CROSS_SIGNING_KEYS: &[(&str, &str)] = &[
+    ("master", "master_key"),
+    ("self_signing", "self_signing_key"),
+    ("user_signing", "user_signing_key"),
+];
+
+impl HomeserverState {
+    pub(super) fn upload_keys(
+        &mut self,
+        request: &Request,
+        requester: &Requester,
+    ) -> ResponseTemplate {
+        let body = body(request);
+
+        let device = self
+            .users
+            .get_mut(&requester.user_id)
+            .and_then(|user| user.devices.get_mut(&requester.device_id))
+            .expect("the requester's device exists");
+
+        let mut device_keys_changed = false;
+        if body["device_keys"].is_object() && device.keys.as_ref() != Some(&body["device_keys"]) {
+            device.keys = Some(body["device_keys"].clone());
+            device_keys_changed = true;
+        }
+
+        for (key_id, key) in body["one_time_keys"].as_object().into_iter().flatten() {
+            device.one_time_keys.insert(key_id.clone(), key.clone());
+        }
+
+        if let Some(fallback_keys) = body["fallback_keys"].as_object() {
+            device.fallback_keys = fallback_keys
+                .iter()
+                .map(|(key_id, key)| (key_id.clone(), (key.clone(), false)))
+                .collect();
+        }
+
+        let one_time_key_counts = device.one_time_key_counts();
+
+        if device_keys_changed {
+            self.mark_device_list_changed(&requester.user_id);
+        }
+
+        ok(json!({ "one_time_key_counts": one_time_key_counts }))
+    }
+
+    pub(super) fn query_keys(&self, request: &Request, requester: &Requester) -> ResponseTemplate {
+        let body = body(request);
+
+        let mut device_keys = JsonMap::new();
+        let mut cross_signing_keys = CROSS_SIGNING_KEYS
+            .iter()
+            .map(|(usage, _)| (*usage, JsonMap::new()))
+            .collect::<Vec<_>>();
+
+        for (user_id, device_ids) in body["device_keys"].as_object().into_iter().flatten() {
+            let Some(user) =
+                UserId::parse(user_id).ok().and_then(|user_id| self.users.get(&user_id))
+            else {
+                continue;
+            };
+
+            let device_ids = device_ids
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(JsonValue::as_str)
+                .collect::<Vec<_>>();
+
+            let user_device_keys = user
+                .devices
+                .iter()
+                .filter(|(device_id, _)| {
+                    device_ids.is_empty() || device_ids.contains(&device_id.as_str())
+                })
+                .filter_map(|(device_id, device)| {
+                    Some((device_id.to_string(), device.keys.clone()?))
+                })
+                .collect::<JsonMap<_, _>>();
+            device_keys.insert(user_id.clone(), JsonValue::Object(user_device_keys));
+
+            for (usage, keys) in &mut cross_signing_keys {
+                // The user-signing key is only visible to its owner.
+                if *usage == "user_signing" && user_id != requester.user_id.as_str() {
+                    continue;
+                }
+
+                if let Some(key) = user.cross_signing_keys.get(*usage) {
+                    keys.insert(user_id.clone(), key.clone());
+                }
+            }
+        }
+
+        let mut response = json!({ "device_keys": device_keys, "failures": {} });
+        for (usage, keys) in cross_signing_keys {
+            response[format!("{usage}_keys")] = JsonValue::Object(keys);
+        }
+
+        ok(response)
+    }
+
+    pub(super) fn claim_keys(&mut self, request: &Request) -> ResponseTemplate {
+        let body = body(request);
+        let mut one_time_keys = JsonMap::new();
+
+        for (user_id, devices) in body["one_time_keys"].as_object().into_iter().flatten() {
+            let Some(user) =
+                UserId::parse(user_id).ok().and_then(|user_id| self.users.get_mut(&user_id))
+            else {
+                continue;
+            };
+
+            let mut user_keys = JsonMap::new();
+
+            for (device_id, algorithm) in devices.as_object().into_iter().flatten() {
+                let (Some(device), Some(algorithm)) = (
+                    user.devices.get_mut(&OwnedDeviceId::from(device_id.as_str())),
+                    algorithm.as_str(),
+                ) else {
+                    continue;
+                };
+
+                let one_time_key_id = device
+                    .one_time_keys
+                    .keys()
+                    .find(|key_id| super::state::key_algorithm(key_id) == algorithm)
+                    .cloned();
+
+                // Use a one-time key if there's one left, or the fallback key otherwise.
+                let claimed_key = if let Some(key_id) = one_time_key_id {
+                    device.one_time_keys.remove_entry(&key_id)
+                } else {
+                    device
+                        .fallback_keys
+                        .iter_mut()
+                        .find(|(key_id, _)| super::state::key_algorithm(key_id) == algorithm)
+                        .map(|(key_id, (key, used))| {
+                            *used = true;
+                            (key_id.clone(), key.clone())
+                        })
+                };
+
+                if let Some((key_id, key)) = claimed_key {
+                    user_keys.insert(device_id.clone(), json!({ key_id: key }));
+                }
+            }
+
+            one_time_keys.insert(user_id.clone(), JsonValue::Object(user_keys));
+        }
+
+        ok(json!({ "one_time_keys": one_time_keys, "failures": {} }))
+    }
+
+    pub(super) fn upload_cross_signing_keys(
+        &mut self,
+        request: &Request,
+        requester: &Requester,
+    ) -> ResponseTemplate {
+        let body = body(request);
+        let user = self.users.get_mut(&requester.user_id).expect("the requester is registered");
+
+        for (usage, field) in CROSS_SIGNING_KEYS {
+            if body[*field].is_object() {
+                user.cross_signing_keys.insert((*usage).to_owned(), body[*field].clone());
+            }
+        }
+
+        self.mark_device_list_changed(&requester.user_id);
+
+        ok(json!({}))
+    }
+
+    pub(super) fn upload_signatures(&mut self, request: &Request) -> ResponseTemplate {
+        let body = body(request);
+        let mut changed_users = Vec::new();
+
+        for (user_id, signed_keys) in body.as_object().into_iter().flatten() {
+            let Some((user_id, user)) = UserId::parse(user_id)
+                .ok()
+                .and_then(|user_id| self.users.get_mut(&user_id).map(|user| (user_id, user)))
+            else {
+                continue;
+            };
+
+            for (key_id, signed_key) in signed_keys.as_object().into_iter().flatten() {
+                // The key ID is either a device ID, or the public key of a cross-signing key.
+                let public_key_id = format!("ed25519:{key_id}");
+                let key = match user.devices.get_mut(&OwnedDeviceId::from(key_id.as_str())) {
+                    Some(device) => device.keys.as_mut(),
+                    None => user
+                        .cross_signing_keys
+                        .values_mut()
+                        .find(|key| key["keys"].get(&public_key_id).is_some()),
+                };
+
+                if let Some(key) = key {
+                    merge_signatures(key, &signed_key["signatures"]);
+                    changed_users.push(user_id.clone());
+                }
+            }
+        }
+
+        for user_id in changed_users {
+            self.mark_device_list_changed(&user_id);
+        }
+
+        ok(json!({ "failures": {} }))
+    }
+
+    pub(super) fn send_to_device(
+        &mut self,
+        request: &Request,
+        requester: &Requester,
+        event_type: &str,
+    ) -> ResponseTemplate {
+        let body = body(request);
+        let position = self.advance_stream();
+
+        for (user_id, messages) in body["messages"].as_object().into_iter().flatten() {
+            let Some(user) =
+                UserId::parse(user_id).ok().and_then(|user_id| self.users.get_mut(&user_id))
+            else {
+                continue;
+            };
+
+            for (device_id, content) in messages.as_object().into_iter().flatten() {
+                let event = json!({
+                    "type": event_type,
+                    "sender": requester.user_id,
+                    "content": content,
+                });
+
+                for (_, device) in user
+                    .devices
+                    .iter_mut()
+                    .filter(|(id, _)| device_id == "*" || id.as_str() == device_id)
+                {
+                    device.to_device_events.push((position, event.clone()));
+                }
+            }
+        }
+
+        ok(json!({}))
+    }
+}
+
+/// Merge the given signatures into the `signatures` of a signed object.
+fn merge_signatures(signed_object: &mut JsonValue, signatures: &JsonValue) {
+    for (user_id, user_signatures) in signatures.as_object().into_iter().flatten() {
+        let existing = signed_object["signatures"][user_id.as_str()].as_object().cloned();
+        let mut merged = existing.unwrap_or_default();
+
+        for (key_id, signature) in user_signatures.as_object().into_iter().flatten() {
+            merged.insert(key_id.clone(), signature.clone());
+        }
+
+        signed_object["signatures"][user_id.as_str()] = JsonValue::Object(merged);
+    }
+}
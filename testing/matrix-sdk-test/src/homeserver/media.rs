@@ -0,0 +1,50 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The media repository endpoints.
+//!
+//! Thumbnails aren't generated: the original media is returned instead.
+
+use serde_json::json;
+use wiremock::{Request, ResponseTemplate};
+
+use super::{
+    error, ok,
+    state::{HomeserverState, Media},
+};
+
+impl HomeserverState {
+    pub(super) fn upload_media(&mut self, request: &Request) -> ResponseTemplate {
+        let content_type = request
+            .headers
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("application/octet-stream")
+            .to_owned();
+
+        let media_id = format!("fake_media_{}", self.next_id());
+        self.media.insert(media_id.clone(), Media { content_type, data: request.body.clone() });
+
+        ok(json!({ "content_uri": format!("mxc://{}/{media_id}", self.server_name) }))
+    }
+
+    pub(super) fn download_media(&self, media_id: &str) -> ResponseTemplate {
+        match self.media.get(media_id) {
+            Some(media) => {
+                ResponseTemplate::new(200).set_body_raw(media.data.clone(), &media.content_type)
+            }
+            None => error(404, "M_NOT_FOUND", "Media not found"),
+        }
+    }
+}
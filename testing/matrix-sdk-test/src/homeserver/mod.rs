@@ -0,0 +1,530 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A stateful in-process homeserver, for tests involving several clients.
+//!
+//! Contrary to the mocks of individual endpoints, the [`FakeHomeserver`] keeps
+//! track of the users, rooms, events, keys and to-device messages it receives,
+//! so several clients can talk to each other through it, like they would
+//! through a real homeserver.
+//!
+//! It implements a subset of the client-server API, large enough for the
+//! common scenarios:
+//!
+//! - logging in with a password,
+//! - creating, joining, leaving rooms, inviting and kicking users,
+//! - sending message-like and state events, redacting events,
+//! - `/sync`, and a simplified version of the sliding sync (MSC4186),
+//! - uploading, querying and claiming end-to-end encryption keys, uploading
+//!   cross-signing keys and signatures,
+//! - sending to-device messages,
+//! - uploading and downloading media.
+//!
+//! Authorization rules are mostly ignored, and the history of rooms is always
+//! visible to their members.
+
+use std::sync::{Arc, Mutex};
+
+use percent_encoding::percent_decode_str;
+use ruma::{
+    OwnedDeviceId, OwnedEventId, OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, UserId,
+};
+use serde_json::{Value as JsonValue, json};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate, matchers::any};
+
+mod keys;
+mod media;
+mod rooms;
+mod state;
+mod sync;
+
+use self::state::{HomeserverState, User};
+
+/// A stateful homeserver running in-process, that several clients can talk
+/// to.
+///
+/// See the [module documentation](self) for the supported features.
+///
+/// # Examples
+///
+/// ```no_run
+/// use matrix_sdk_test::homeserver::FakeHomeserver;
+///
+/// # async {
+/// let homeserver = FakeHomeserver::new().await;
+///
+/// let alice = homeserver.register_user("alice", "password");
+/// let bob = homeserver.register_user("bob", "password");
+///
+/// // Log the users in, without going through the `/login` endpoint.
+/// let alice_session = homeserver.login(&alice);
+/// let bob_session = homeserver.login(&bob);
+///
+/// // Build clients for `homeserver.uri()`, restoring these sessions.
+/// # };
+/// ```
+pub struct FakeHomeserver {
+    server: MockServer,
+    state: Arc<Mutex<HomeserverState>>,
+}
+
+impl FakeHomeserver {
+    /// Start a new homeserver named `localhost`, listening on a random local
+    /// port.
+    pub async fn new() -> Self {
+        Self::with_server_name("localhost".try_into().expect("localhost is a valid server name"))
+            .await
+    }
+
+    /// Start a new homeserver with the given name, listening on a random local
+    /// port.
+    ///
+    /// The name is only used in the IDs generated by the homeserver.
+    pub async fn with_server_name(server_name: OwnedServerName) -> Self {
+        let server = MockServer::start().await;
+        let state = Arc::new(Mutex::new(HomeserverState::new(server_name)));
+
+        Mock::given(any()).respond_with(Router { state: state.clone() }).mount(&server).await;
+
+        Self { server, state }
+    }
+
+    /// The URL of this homeserver, to use as the homeserver URL of clients.
+    pub fn uri(&self) -> String {
+        self.server.uri()
+    }
+
+    /// The name of this homeserver.
+    pub fn server_name(&self) -> OwnedServerName {
+        self.state().server_name.clone()
+    }
+
+    /// Register a new user with the given localpart and password, and return
+    /// their user ID.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the user is already registered, or if the localpart isn't
+    /// valid.
+    pub fn register_user(&self, localpart: &str, password: &str) -> OwnedUserId {
+        let mut state = self.state();
+
+        let user_id = UserId::parse(format!("@{localpart}:{}", state.server_name))
+            .expect("the localpart should be valid");
+        assert!(!state.users.contains_key(&user_id), "the user {user_id} is already registered");
+
+        state
+            .users
+            .insert(user_id.clone(), User { password: password.to_owned(), ..Default::default() });
+
+        user_id
+    }
+
+    /// Set the display name of a registered user, which is used in their
+    /// future membership events.
+    pub fn set_display_name(&self, user_id: &UserId, display_name: &str) {
+        let mut state = self.state();
+        let user = state.users.get_mut(user_id).expect("the user should be registered");
+        user.display_name = Some(display_name.to_owned());
+    }
+
+    /// Log a registered user in on a new device, without going through the
+    /// `/login` endpoint.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the user isn't registered.
+    pub fn login(&self, user_id: &UserId) -> FakeSession {
+        let (access_token, device_id) = self.state().add_device(user_id, None);
+        FakeSession { user_id: user_id.to_owned(), device_id, access_token }
+    }
+
+    /// Create a room on behalf of a user, who is joined to it, and return its
+    /// ID.
+    ///
+    /// The other users are invited to the room.
+    pub fn create_room(&self, creator: &UserId, invites: &[&UserId]) -> OwnedRoomId {
+        let invites = invites.iter().map(|user_id| json!(user_id)).collect::<Vec<_>>();
+        self.state().create_room(creator, &json!({ "invite": invites }))
+    }
+
+    /// Send a message-like event in a room on behalf of a user, and return its
+    /// ID.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the room doesn't exist.
+    pub fn send_event(
+        &self,
+        room_id: &RoomId,
+        sender: &UserId,
+        event_type: &str,
+        content: JsonValue,
+    ) -> OwnedEventId {
+        self.state().send_event(room_id, sender, event_type, None, content, None)
+    }
+
+    /// Get all the events of a room, in topological order, in the format of
+    /// the client-server API.
+    pub fn room_events(&self, room_id: &RoomId) -> Vec<JsonValue> {
+        self.state()
+            .rooms
+            .get(room_id)
+            .map(|room| room.events.iter().map(|event| event.event.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Return the underlying [`wiremock`] server, e.g. to inspect the received
+    /// requests.
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, HomeserverState> {
+        self.state.lock().unwrap()
+    }
+}
+
+/// A session of a user logged in on a [`FakeHomeserver`].
+#[derive(Clone, Debug)]
+pub struct FakeSession {
+    /// The ID of the user.
+    pub user_id: OwnedUserId,
+
+    /// The ID of the device of the session.
+    pub device_id: OwnedDeviceId,
+
+    /// The access token of the session.
+    pub access_token: String,
+}
+
+/// The [`Respond`] implementation dispatching the requests to the handlers of
+/// the endpoints.
+struct Router {
+    state: Arc<Mutex<HomeserverState>>,
+}
+
+impl Respond for Router {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let segments = request
+            .url
+            .path_segments()
+            .map(|segments| {
+                segments
+                    .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let segments = segments.iter().map(String::as_str).collect::<Vec<_>>();
+
+        self.state.lock().unwrap().handle(request, request.method.as_str(), &segments)
+    }
+}
+
+/// The user and device a request was made by.
+pub(super) struct Requester {
+    pub user_id: OwnedUserId,
+    pub device_id: OwnedDeviceId,
+}
+
+impl HomeserverState {
+    fn handle(&mut self, request: &Request, method: &str, segments: &[&str]) -> ResponseTemplate {
+        match (method, segments) {
+            ("GET", ["_matrix", "client", "versions"]) => ok(json!({
+                "versions": [
+                    "r0.6.1", "v1.1", "v1.2", "v1.3", "v1.4", "v1.5", "v1.6", "v1.7", "v1.8",
+                    "v1.9", "v1.10", "v1.11", "v1.12",
+                ],
+                "unstable_features": {
+                    "org.matrix.simplified_msc3575": true,
+                },
+            })),
+
+            ("GET", ["_matrix", "client", "v3", "login"]) => {
+                ok(json!({ "flows": [{ "type": "m.login.password" }] }))
+            }
+            ("POST", ["_matrix", "client", "v3", "login"]) => self.password_login(request),
+
+            ("GET", ["_matrix", "client", "v1", "media", "config"]) => {
+                ok(json!({ "m.upload.size": 50 * 1024 * 1024 }))
+            }
+            (
+                "GET",
+                [
+                    "_matrix",
+                    "client",
+                    "v1",
+                    "media",
+                    "download" | "thumbnail",
+                    _server_name,
+                    media_id,
+                    ..,
+                ]
+                | ["_matrix", "media", "v3", "download" | "thumbnail", _server_name, media_id, ..],
+            ) => self.download_media(media_id),
+
+            _ => {
+                let Some(requester) = self.authenticate(request) else {
+                    return error(401, "M_UNKNOWN_TOKEN", "Unknown access token");
+                };
+
+                match segments {
+                    ["_matrix", "client", "v3" | "r0", rest @ ..] => {
+                        self.handle_client(request, &requester, method, rest)
+                    }
+                    ["_matrix", "client", "unstable", "org.matrix.simplified_msc3575", "sync"]
+                        if method == "POST" =>
+                    {
+                        self.sliding_sync(request, &requester)
+                    }
+                    ["_matrix", "media", "v3", "upload"] if method == "POST" => {
+                        self.upload_media(request)
+                    }
+                    _ => unrecognized(),
+                }
+            }
+        }
+    }
+
+    fn handle_client(
+        &mut self,
+        request: &Request,
+        requester: &Requester,
+        method: &str,
+        segments: &[&str],
+    ) -> ResponseTemplate {
+        match (method, segments) {
+            ("GET", ["account", "whoami"]) => {
+                ok(json!({ "user_id": requester.user_id, "device_id": requester.device_id }))
+            }
+            ("POST", ["logout"]) => {
+                self.access_tokens.retain(|_, (user_id, device_id)| {
+                    *user_id != requester.user_id || *device_id != requester.device_id
+                });
+                ok(json!({}))
+            }
+            ("GET", ["devices"]) => {
+                let devices = self.users[&requester.user_id]
+                    .devices
+                    .keys()
+                    .map(|device_id| json!({ "device_id": device_id }))
+                    .collect::<Vec<_>>();
+                ok(json!({ "devices": devices }))
+            }
+
+            ("GET", ["sync"]) => self.sync(request, requester),
+            ("POST", ["user", _, "filter"]) => ok(json!({ "filter_id": "0" })),
+
+            ("GET", ["profile", user_id, rest @ ..]) => self.get_profile(user_id, rest),
+            ("PUT", ["profile", user_id, field]) => {
+                self.set_profile(request, requester, user_id, field)
+            }
+
+            (_, ["user", user_id, "account_data", event_type]) => {
+                self.account_data(request, requester, method, user_id, None, event_type)
+            }
+            (_, ["user", user_id, "rooms", room_id, "account_data", event_type]) => {
+                self.account_data(request, requester, method, user_id, Some(room_id), event_type)
+            }
+
+            ("POST", ["createRoom"]) => {
+                let room_id = self.create_room(&requester.user_id, &body(request));
+                ok(json!({ "room_id": room_id }))
+            }
+            ("GET", ["directory", "room", alias]) => self.resolve_alias(alias),
+            ("POST", ["join", room_id_or_alias]) => self.join(requester, room_id_or_alias),
+            (_, ["rooms", room_id, rest @ ..]) => {
+                self.handle_room(request, requester, method, room_id, rest)
+            }
+
+            ("POST", ["keys", "upload"]) => self.upload_keys(request, requester),
+            ("POST", ["keys", "query"]) => self.query_keys(request, requester),
+            ("POST", ["keys", "claim"]) => self.claim_keys(request),
+            ("POST", ["keys", "device_signing", "upload"]) => {
+                self.upload_cross_signing_keys(request, requester)
+            }
+            ("POST", ["keys", "signatures", "upload"]) => self.upload_signatures(request),
+            ("PUT", ["sendToDevice", event_type, _txn_id]) => {
+                self.send_to_device(request, requester, event_type)
+            }
+            ("GET", ["room_keys", "version"]) => {
+                error(404, "M_NOT_FOUND", "No current backup version")
+            }
+
+            _ => unrecognized(),
+        }
+    }
+
+    /// Find the user and device the access token of the request belongs to.
+    fn authenticate(&self, request: &Request) -> Option<Requester> {
+        let access_token = request
+            .headers
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))?;
+
+        let (user_id, device_id) = self.access_tokens.get(access_token)?;
+        Some(Requester { user_id: user_id.clone(), device_id: device_id.clone() })
+    }
+
+    fn password_login(&mut self, request: &Request) -> ResponseTemplate {
+        let body = body(request);
+
+        let Some(user) = body["identifier"]["user"].as_str().or(body["user"].as_str()) else {
+            return error(400, "M_BAD_JSON", "Missing user identifier");
+        };
+
+        let user_id = if user.starts_with('@') {
+            UserId::parse(user).ok()
+        } else {
+            UserId::parse(format!("@{user}:{}", self.server_name)).ok()
+        };
+
+        let Some(user_id) = user_id.filter(|user_id| {
+            self.users
+                .get(user_id)
+                .is_some_and(|user| body["password"].as_str() == Some(user.password.as_str()))
+        }) else {
+            return error(403, "M_FORBIDDEN", "Invalid username or password");
+        };
+
+        let device_id = body["device_id"].as_str().map(OwnedDeviceId::from);
+        let (access_token, device_id) = self.add_device(&user_id, device_id);
+
+        ok(json!({
+            "user_id": user_id,
+            "device_id": device_id,
+            "access_token": access_token,
+        }))
+    }
+
+    fn get_profile(&self, user_id: &str, fields: &[&str]) -> ResponseTemplate {
+        let Some(user) = UserId::parse(user_id).ok().and_then(|user_id| self.users.get(&user_id))
+        else {
+            return error(404, "M_NOT_FOUND", "Profile not found");
+        };
+
+        let profile = json!({
+            "displayname": user.display_name,
+            "avatar_url": user.avatar_url,
+        });
+
+        match fields {
+            [] => ok(profile),
+            [field @ ("displayname" | "avatar_url")] => ok(json!({ *field: profile[*field] })),
+            _ => unrecognized(),
+        }
+    }
+
+    fn set_profile(
+        &mut self,
+        request: &Request,
+        requester: &Requester,
+        user_id: &str,
+        field: &str,
+    ) -> ResponseTemplate {
+        if user_id != requester.user_id.as_str() {
+            return error(403, "M_FORBIDDEN", "Cannot set the profile of another user");
+        }
+
+        let value = body(request)[field].as_str().map(ToOwned::to_owned);
+        let user = self.users.get_mut(&requester.user_id).expect("the requester is registered");
+
+        match field {
+            "displayname" => user.display_name = value,
+            "avatar_url" => user.avatar_url = value,
+            _ => return unrecognized(),
+        }
+
+        ok(json!({}))
+    }
+
+    fn account_data(
+        &mut self,
+        request: &Request,
+        requester: &Requester,
+        method: &str,
+        user_id: &str,
+        room_id: Option<&str>,
+        event_type: &str,
+    ) -> ResponseTemplate {
+        if user_id != requester.user_id.as_str() {
+            return error(403, "M_FORBIDDEN", "Cannot access the account data of another user");
+        }
+
+        let Ok(room_id) = room_id.map(RoomId::parse).transpose() else {
+            return error(400, "M_INVALID_PARAM", "Invalid room ID");
+        };
+
+        match method {
+            "GET" => {
+                let user = &self.users[&requester.user_id];
+                let content = match room_id {
+                    Some(room_id) => user.room_account_data.get(&(room_id, event_type.to_owned())),
+                    None => user.account_data.get(event_type),
+                };
+
+                match content {
+                    Some((_, content)) => ok(content.clone()),
+                    None => error(404, "M_NOT_FOUND", "Account data not found"),
+                }
+            }
+            "PUT" => {
+                let position = self.advance_stream();
+                let content = body(request);
+                let user =
+                    self.users.get_mut(&requester.user_id).expect("the requester is registered");
+
+                match room_id {
+                    Some(room_id) => {
+                        user.room_account_data
+                            .insert((room_id, event_type.to_owned()), (position, content));
+                    }
+                    None => {
+                        user.account_data.insert(event_type.to_owned(), (position, content));
+                    }
+                }
+
+                ok(json!({}))
+            }
+            _ => unrecognized(),
+        }
+    }
+}
+
+/// A successful response with the given JSON body.
+pub(super) fn ok(body: JsonValue) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(body)
+}
+
+/// An error response, in the format of the client-server API.
+pub(super) fn error(status: u16, errcode: &str, message: &str) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_json(json!({ "errcode": errcode, "error": message }))
+}
+
+/// The error response for the endpoints that aren't supported.
+pub(super) fn unrecognized() -> ResponseTemplate {
+    error(404, "M_UNRECOGNIZED", "Unrecognized request")
+}
+
+/// The JSON body of a request, or an empty object if it doesn't have one.
+pub(super) fn body(request: &Request) -> JsonValue {
+    request.body_json().unwrap_or_else(|_| json!({}))
+}
+
+/// Get the value of a query parameter of a request.
+pub(super) fn query_param(request: &Request, name: &str) -> Option<String> {
+    request.url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned())
+}
+
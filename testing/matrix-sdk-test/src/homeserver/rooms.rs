@@ -0,0 +1,377 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The endpoints to create rooms, manage their memberships and send or fetch
+//! their events.
+
+use ruma::{OwnedRoomId, RoomAliasId, RoomId, UserId};
+use serde_json::{Value as JsonValue, json};
+use wiremock::{Request, ResponseTemplate};
+
+use super::{
+    Requester, body, error, ok, query_param,
+    state::{HomeserverState, Room},
+    unrecognized,
+};
+
+impl HomeserverState {
+    /// Create a room, with the body of a `/createRoom` request, and return its
+    /// ID.
+    pub(super) fn create_room(&mut self, creator: &UserId, body: &JsonValue) -> OwnedRoomId {
+        let id = self.next_id();
+        let room_id = RoomId::parse(format!("!fake_room_{id}:{}", self.server_name))
+            .expect("the generated room ID should be valid");
+        self.rooms.insert(room_id.clone(), Room::default());
+
+        let mut create_content = json!({ "creator": creator, "room_version": "10" });
+        if let (Some(create_content), Some(creation_content)) =
+            (create_content.as_object_mut(), body["creation_content"].as_object())
+        {
+            create_content.extend(creation_content.clone());
+        }
+        self.send_event(&room_id, creator, "m.room.create", Some(""), create_content, None);
+
+        self.set_membership(&room_id, creator, creator, "join", json!({}));
+
+        let invites = body["invite"].as_array().cloned().unwrap_or_default();
+        let preset =
+            body["preset"].as_str().unwrap_or(if body["visibility"].as_str() == Some("public") {
+                "public_chat"
+            } else {
+                "private_chat"
+            });
+
+        let mut users = json!({ creator.as_str(): 100 });
+        if preset == "trusted_private_chat" {
+            for invite in &invites {
+                if let Some(user_id) = invite.as_str() {
+                    users[user_id] = json!(100);
+                }
+            }
+        }
+        self.send_event(
+            &room_id,
+            creator,
+            "m.room.power_levels",
+            Some(""),
+            json!({ "users": users, "users_default": 0 }),
+            None,
+        );
+
+        let join_rule = if preset == "public_chat" { "public" } else { "invite" };
+        self.send_event(
+            &room_id,
+            creator,
+            "m.room.join_rules",
+            Some(""),
+            json!({ "join_rule": join_rule }),
+            None,
+        );
+        self.send_event(
+            &room_id,
+            creator,
+            "m.room.history_visibility",
+            Some(""),
+            json!({ "history_visibility": "shared" }),
+            None,
+        );
+
+        if let Some(alias_name) = body["room_alias_name"].as_str() {
+            if let Ok(alias) = RoomAliasId::parse(format!("#{alias_name}:{}", self.server_name)) {
+                self.room_aliases.insert(alias.clone(), room_id.clone());
+                self.send_event(
+                    &room_id,
+                    creator,
+                    "m.room.canonical_alias",
+                    Some(""),
+                    json!({ "alias": alias }),
+                    None,
+                );
+            }
+        }
+
+        for event in body["initial_state"].as_array().into_iter().flatten() {
+            if let Some(event_type) = event["type"].as_str() {
+                self.send_event(
+                    &room_id,
+                    creator,
+                    event_type,
+                    Some(event["state_key"].as_str().unwrap_or_default()),
+                    event["content"].clone(),
+                    None,
+                );
+            }
+        }
+
+        if let Some(name) = body["name"].as_str() {
+            self.send_event(
+                &room_id,
+                creator,
+                "m.room.name",
+                Some(""),
+                json!({ "name": name }),
+                None,
+            );
+        }
+        if let Some(topic) = body["topic"].as_str() {
+            self.send_event(
+                &room_id,
+                creator,
+                "m.room.topic",
+                Some(""),
+                json!({ "topic": topic }),
+                None,
+            );
+        }
+
+        let is_direct = body["is_direct"].as_bool().unwrap_or(false);
+        for invite in invites {
+            if let Some(user_id) = invite.as_str().and_then(|user_id| UserId::parse(user_id).ok()) {
+                let extra_content =
+                    if is_direct { json!({ "is_direct": true }) } else { json!({}) };
+                self.set_membership(&room_id, creator, &user_id, "invite", extra_content);
+            }
+        }
+
+        room_id
+    }
+
+    pub(super) fn resolve_alias(&self, alias: &str) -> ResponseTemplate {
+        let room_id =
+            RoomAliasId::parse(alias).ok().and_then(|alias| self.room_aliases.get(&alias));
+
+        match room_id {
+            Some(room_id) => ok(json!({ "room_id": room_id, "servers": [self.server_name] })),
+            None => error(404, "M_NOT_FOUND", "Room alias not found"),
+        }
+    }
+
+    pub(super) fn join(
+        &mut self,
+        requester: &Requester,
+        room_id_or_alias: &str,
+    ) -> ResponseTemplate {
+        let room_id = if room_id_or_alias.starts_with('#') {
+            RoomAliasId::parse(room_id_or_alias)
+                .ok()
+                .and_then(|alias| self.room_aliases.get(&alias).cloned())
+        } else {
+            RoomId::parse(room_id_or_alias).ok()
+        };
+
+        let Some((room_id, room)) =
+            room_id.and_then(|room_id| self.rooms.get(&room_id).map(|room| (room_id, room)))
+        else {
+            return error(404, "M_NOT_FOUND", "Unknown room");
+        };
+
+        let membership = room.membership(&requester.user_id).map(|(membership, _)| membership);
+        let join_rule = room
+            .state_event("m.room.join_rules", "")
+            .and_then(|event| event.event["content"]["join_rule"].as_str());
+
+        match membership {
+            Some("join") => {}
+            Some("invite") => {
+                self.set_membership(
+                    &room_id,
+                    &requester.user_id,
+                    &requester.user_id,
+                    "join",
+                    json!({}),
+                );
+            }
+            Some("ban") => return error(403, "M_FORBIDDEN", "You are banned from this room"),
+            _ if join_rule == Some("public") => {
+                self.set_membership(
+                    &room_id,
+                    &requester.user_id,
+                    &requester.user_id,
+                    "join",
+                    json!({}),
+                );
+            }
+            _ => return error(403, "M_FORBIDDEN", "You are not invited to this room"),
+        }
+
+        ok(json!({ "room_id": room_id }))
+    }
+
+    pub(super) fn handle_room(
+        &mut self,
+        request: &Request,
+        requester: &Requester,
+        method: &str,
+        room_id: &str,
+        segments: &[&str],
+    ) -> ResponseTemplate {
+        let Some(room_id) =
+            RoomId::parse(room_id).ok().filter(|room_id| self.rooms.contains_key(room_id))
+        else {
+            return error(404, "M_NOT_FOUND", "Unknown room");
+        };
+
+        if let ("POST", ["join"]) = (method, segments) {
+            return self.join(requester, room_id.as_str());
+        }
+
+        let room = &self.rooms[&room_id];
+        let membership = room.membership(&requester.user_id).map(|(membership, _)| membership);
+
+        // All the other endpoints require the user to be in the room, except for
+        // leaving it, which can be used to reject an invite.
+        match (method, segments, membership) {
+            ("POST", ["leave"], Some("join" | "invite")) => {}
+            (_, _, Some("join")) => {}
+            _ => return error(403, "M_FORBIDDEN", "You are not joined to this room"),
+        }
+
+        match (method, segments) {
+            ("POST", ["leave"]) => {
+                self.set_membership(
+                    &room_id,
+                    &requester.user_id,
+                    &requester.user_id,
+                    "leave",
+                    json!({}),
+                );
+                ok(json!({}))
+            }
+            ("POST", [action @ ("invite" | "kick" | "ban" | "unban")]) => {
+                let body = body(request);
+                let Some(target) =
+                    body["user_id"].as_str().and_then(|user_id| UserId::parse(user_id).ok())
+                else {
+                    return error(400, "M_BAD_JSON", "Missing user ID");
+                };
+
+                let membership = match *action {
+                    "invite" => "invite",
+                    "ban" => "ban",
+                    _ => "leave",
+                };
+                let extra_content = match body["reason"].as_str() {
+                    Some(reason) => json!({ "reason": reason }),
+                    None => json!({}),
+                };
+
+                self.set_membership(
+                    &room_id,
+                    &requester.user_id,
+                    &target,
+                    membership,
+                    extra_content,
+                );
+                ok(json!({}))
+            }
+
+            ("PUT", ["send", event_type, txn_id]) => {
+                let event_id = self.send_event(
+                    &room_id,
+                    &requester.user_id,
+                    event_type,
+                    None,
+                    body(request),
+                    Some((requester.device_id.clone(), (*txn_id).to_owned())),
+                );
+                ok(json!({ "event_id": event_id }))
+            }
+            ("PUT", ["redact", redacted_event_id, txn_id]) => {
+                let mut content = body(request);
+                content["redacts"] = json!(redacted_event_id);
+
+                let event_id = self.send_event(
+                    &room_id,
+                    &requester.user_id,
+                    "m.room.redaction",
+                    None,
+                    content,
+                    Some((requester.device_id.clone(), (*txn_id).to_owned())),
+                );
+                ok(json!({ "event_id": event_id }))
+            }
+            ("PUT", ["state", event_type, state_key @ ..]) if state_key.len() <= 1 => {
+                let state_key = state_key.first().copied().unwrap_or_default();
+                let event_id = self.send_event(
+                    &room_id,
+                    &requester.user_id,
+                    event_type,
+                    Some(state_key),
+                    body(request),
+                    None,
+                );
+                ok(json!({ "event_id": event_id }))
+            }
+
+            ("GET", ["state"]) => {
+                ok(JsonValue::Array(room.state_events().map(|event| event.event.clone()).collect()))
+            }
+            ("GET", ["state", event_type, state_key @ ..]) if state_key.len() <= 1 => {
+                let state_key = state_key.first().copied().unwrap_or_default();
+                match room.state_event(event_type, state_key) {
+                    Some(event) => ok(event.event["content"].clone()),
+                    None => error(404, "M_NOT_FOUND", "Event not found"),
+                }
+            }
+            ("GET", ["members"]) => {
+                let members = room
+                    .state_events()
+                    .filter(|event| event.event["type"] == "m.room.member")
+                    .map(|event| event.event.clone())
+                    .collect::<Vec<_>>();
+                ok(json!({ "chunk": members }))
+            }
+            ("GET", ["event", event_id]) => {
+                match room.events.iter().find(|event| event.event["event_id"] == *event_id) {
+                    Some(event) => ok(event.event.clone()),
+                    None => error(404, "M_NOT_FOUND", "Event not found"),
+                }
+            }
+            ("GET", ["messages"]) => messages(room, request),
+
+            ("PUT", ["typing", ..]) | ("POST", ["receipt", ..] | ["read_markers"]) => ok(json!({})),
+
+            _ => unrecognized(),
+        }
+    }
+}
+
+/// Paginate the events of a room, with the `/messages` endpoint.
+///
+/// The tokens are indices in the events of the room.
+fn messages(room: &Room, request: &Request) -> ResponseTemplate {
+    let limit =
+        query_param(request, "limit").and_then(|limit| limit.parse().ok()).unwrap_or(10usize);
+    let backwards = query_param(request, "dir").as_deref() != Some("f");
+    let from = query_param(request, "from").and_then(|from| from.parse::<usize>().ok());
+
+    let (start, chunk, end) = if backwards {
+        let from = from.unwrap_or(room.events.len()).min(room.events.len());
+        let start = from.saturating_sub(limit);
+        let chunk = room.events[start..from].iter().rev().map(|event| event.event.clone());
+        (from, chunk.collect::<Vec<_>>(), (start > 0).then_some(start))
+    } else {
+        let from = from.unwrap_or(0).min(room.events.len());
+        let end = (from + limit).min(room.events.len());
+        let chunk = room.events[from..end].iter().map(|event| event.event.clone());
+        (from, chunk.collect::<Vec<_>>(), (end < room.events.len()).then_some(end))
+    };
+
+    let mut response = json!({ "start": start.to_string(), "chunk": chunk });
+    if let Some(end) = end {
+        response["end"] = json!(end.to_string());
+    }
+
+    ok(response)
+}
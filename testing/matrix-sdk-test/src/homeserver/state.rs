@@ -0,0 +1,431 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The in-memory state of a [`FakeHomeserver`](super::FakeHomeserver).
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use ruma::{
+    MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedEventId, OwnedRoomAliasId, OwnedRoomId,
+    OwnedServerName, OwnedUserId, RoomId, UserId,
+};
+use serde_json::{Value as JsonValue, json};
+
+/// The state of the homeserver, shared by all the requests.
+pub(super) struct HomeserverState {
+    /// The name of the homeserver, used in the IDs it generates.
+    pub server_name: OwnedServerName,
+
+    /// The position of the latest change in the homeserver, used as the
+    /// token of the sync endpoints.
+    pub stream_position: u64,
+
+    /// A counter used to generate unique IDs.
+    next_id: u64,
+
+    /// The registered users.
+    pub users: BTreeMap<OwnedUserId, User>,
+
+    /// The logged-in devices, by access token.
+    pub access_tokens: HashMap<String, (OwnedUserId, OwnedDeviceId)>,
+
+    /// The rooms.
+    pub rooms: BTreeMap<OwnedRoomId, Room>,
+
+    /// The room aliases, pointing to their room.
+    pub room_aliases: BTreeMap<OwnedRoomAliasId, OwnedRoomId>,
+
+    /// The uploaded media, by media ID.
+    pub media: HashMap<String, Media>,
+
+    /// The users whose devices or cross-signing keys changed, along with the
+    /// stream position of the change.
+    pub device_list_changes: Vec<(u64, OwnedUserId)>,
+
+    /// The event IDs of the events sent with a transaction ID, to make
+    /// retries of the same request idempotent.
+    pub transactions: HashMap<(OwnedDeviceId, String), OwnedEventId>,
+}
+
+impl HomeserverState {
+    pub fn new(server_name: OwnedServerName) -> Self {
+        Self {
+            server_name,
+            stream_position: 0,
+            next_id: 0,
+            users: BTreeMap::new(),
+            access_tokens: HashMap::new(),
+            rooms: BTreeMap::new(),
+            room_aliases: BTreeMap::new(),
+            media: HashMap::new(),
+            device_list_changes: Vec::new(),
+            transactions: HashMap::new(),
+        }
+    }
+
+    /// Generate a new unique ID.
+    pub fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Move the stream forward, and return the new position.
+    pub fn advance_stream(&mut self) -> u64 {
+        self.stream_position += 1;
+        self.stream_position
+    }
+
+    /// Log a new device in for the given user, and return its access token.
+    pub fn add_device(
+        &mut self,
+        user_id: &UserId,
+        device_id: Option<OwnedDeviceId>,
+    ) -> (String, OwnedDeviceId) {
+        let id = self.next_id();
+        let device_id = device_id.unwrap_or_else(|| format!("FAKEDEVICE{id}").into());
+        let access_token = format!("fake_access_token_{id}");
+
+        let user = self.users.get_mut(user_id).expect("the user must be registered");
+        user.devices.entry(device_id.clone()).or_default();
+
+        self.access_tokens.insert(access_token.clone(), (user_id.to_owned(), device_id.clone()));
+
+        (access_token, device_id)
+    }
+
+    /// Remember that the devices or the cross-signing keys of the given user
+    /// changed.
+    pub fn mark_device_list_changed(&mut self, user_id: &UserId) {
+        let position = self.advance_stream();
+        self.device_list_changes.push((position, user_id.to_owned()));
+    }
+
+    /// Create a new event in the given room, and return its ID.
+    ///
+    /// The room must exist.
+    pub fn send_event(
+        &mut self,
+        room_id: &RoomId,
+        sender: &UserId,
+        event_type: &str,
+        state_key: Option<&str>,
+        content: JsonValue,
+        transaction: Option<(OwnedDeviceId, String)>,
+    ) -> OwnedEventId {
+        if let Some(event_id) = transaction.as_ref().and_then(|txn| self.transactions.get(txn)) {
+            return event_id.clone();
+        }
+
+        let id = self.next_id();
+        let position = self.advance_stream();
+        let event_id = OwnedEventId::try_from(format!("$fake_event_{id}"))
+            .expect("the generated event ID should be valid");
+
+        let mut event = json!({
+            "type": event_type,
+            "event_id": event_id,
+            "room_id": room_id,
+            "sender": sender,
+            "origin_server_ts": MilliSecondsSinceUnixEpoch::now(),
+            "content": content,
+        });
+
+        if let Some(state_key) = state_key {
+            event["state_key"] = json!(state_key);
+        }
+
+        let room = self.rooms.get_mut(room_id).expect("the room must exist");
+
+        if let Some(state_key) = state_key {
+            room.state.insert((event_type.to_owned(), state_key.to_owned()), room.events.len());
+        }
+
+        room.events.push(RoomEvent {
+            position,
+            event,
+            transaction: transaction
+                .as_ref()
+                .map(|(device_id, txn_id)| (sender.to_owned(), device_id.clone(), txn_id.clone())),
+        });
+
+        if let Some(transaction) = transaction {
+            self.transactions.insert(transaction, event_id.clone());
+        }
+
+        event_id
+    }
+
+    /// Update the membership of a user in a room, and return the ID of the
+    /// membership event.
+    pub fn set_membership(
+        &mut self,
+        room_id: &RoomId,
+        sender: &UserId,
+        target: &UserId,
+        membership: &str,
+        extra_content: JsonValue,
+    ) -> OwnedEventId {
+        let mut content = json!({ "membership": membership });
+
+        if membership == "join" {
+            if let Some(user) = self.users.get(target) {
+                if let Some(display_name) = &user.display_name {
+                    content["displayname"] = json!(display_name);
+                }
+                if let Some(avatar_url) = &user.avatar_url {
+                    content["avatar_url"] = json!(avatar_url);
+                }
+            }
+        }
+
+        if let (Some(content), JsonValue::Object(extra_content)) =
+            (content.as_object_mut(), extra_content)
+        {
+            content.extend(extra_content);
+        }
+
+        self.send_event(room_id, sender, "m.room.member", Some(target.as_str()), content, None)
+    }
+
+    /// Whether the two users share a room they're both joined to.
+    pub fn share_a_room(&self, user_id: &UserId, other_user_id: &UserId) -> bool {
+        self.rooms.values().any(|room| {
+            room.membership(user_id).is_some_and(|(membership, _)| membership == "join")
+                && room
+                    .membership(other_user_id)
+                    .is_some_and(|(membership, _)| membership == "join")
+        })
+    }
+}
+
+/// A registered user.
+#[derive(Default)]
+pub(super) struct User {
+    /// The password of the user.
+    pub password: String,
+
+    /// The display name of the user.
+    pub display_name: Option<String>,
+
+    /// The avatar URL of the user.
+    pub avatar_url: Option<String>,
+
+    /// The devices of the user.
+    pub devices: BTreeMap<OwnedDeviceId, Device>,
+
+    /// The cross-signing keys of the user, by usage (`master`,
+    /// `self_signing` or `user_signing`).
+    pub cross_signing_keys: BTreeMap<String, JsonValue>,
+
+    /// The global account data of the user, by type, along with the stream
+    /// position of their last change.
+    pub account_data: BTreeMap<String, (u64, JsonValue)>,
+
+    /// The room account data of the user, by room and type, along with the
+    /// stream position of their last change.
+    pub room_account_data: BTreeMap<(OwnedRoomId, String), (u64, JsonValue)>,
+}
+
+/// A device of a user.
+#[derive(Default)]
+pub(super) struct Device {
+    /// The identity keys of the device, as uploaded by the device.
+    pub keys: Option<JsonValue>,
+
+    /// The unclaimed one-time keys of the device, by key ID.
+    pub one_time_keys: BTreeMap<String, JsonValue>,
+
+    /// The fallback keys of the device, by key ID, along with whether they
+    /// have been used.
+    pub fallback_keys: BTreeMap<String, (JsonValue, bool)>,
+
+    /// The to-device events sent to this device, along with the stream
+    /// position at which they were sent.
+    pub to_device_events: Vec<(u64, JsonValue)>,
+}
+
+impl Device {
+    /// The number of unclaimed one-time keys, by algorithm.
+    pub fn one_time_key_counts(&self) -> BTreeMap<String, u64> {
+        let mut counts = BTreeMap::new();
+
+        for key_id in self.one_time_keys.keys() {
+            *counts.entry(key_algorithm(key_id).to_owned()).or_default() += 1;
+        }
+
+        counts
+    }
+
+    /// The algorithms of the fallback keys that haven't been used.
+    pub fn unused_fallback_key_types(&self) -> BTreeSet<String> {
+        self.fallback_keys
+            .iter()
+            .filter(|(_, (_, used))| !used)
+            .map(|(key_id, _)| key_algorithm(key_id).to_owned())
+            .collect()
+    }
+}
+
+/// The algorithm of a key ID, e.g. `signed_curve25519` for
+/// `signed_curve25519:AAAAHQ`.
+pub(super) fn key_algorithm(key_id: &str) -> &str {
+    key_id.split_once(':').map_or(key_id, |(algorithm, _)| algorithm)
+}
+
+/// A room.
+#[derive(Default)]
+pub(super) struct Room {
+    /// All the events of the room, in topological order.
+    pub events: Vec<RoomEvent>,
+
+    /// The current state of the room: the index of the latest state event,
+    /// by type and state key.
+    pub state: BTreeMap<(String, String), usize>,
+}
+
+impl Room {
+    /// Get the latest state event with the given type and state key.
+    pub fn state_event(&self, event_type: &str, state_key: &str) -> Option<&RoomEvent> {
+        self.state
+            .get(&(event_type.to_owned(), state_key.to_owned()))
+            .map(|index| &self.events[*index])
+    }
+
+    /// Get all the events of the current state of the room.
+    pub fn state_events(&self) -> impl Iterator<Item = &RoomEvent> {
+        self.state.values().map(|index| &self.events[*index])
+    }
+
+    /// Get the membership of the given user, along with the stream position
+    /// of their membership event.
+    pub fn membership(&self, user_id: &UserId) -> Option<(&str, u64)> {
+        let event = self.state_event("m.room.member", user_id.as_str())?;
+        let membership = event.event["content"]["membership"].as_str()?;
+        Some((membership, event.position))
+    }
+
+    /// Get the users with the given membership.
+    pub fn members_with_membership(&self, membership: &str) -> Vec<OwnedUserId> {
+        self.state
+            .iter()
+            .filter(|((event_type, _), _)| event_type == "m.room.member")
+            .filter(|(_, index)| {
+                self.events[**index].event["content"]["membership"].as_str() == Some(membership)
+            })
+            .filter_map(|((_, state_key), _)| UserId::parse(state_key).ok())
+            .collect()
+    }
+
+    /// The position of the latest event of the room.
+    pub fn latest_position(&self) -> u64 {
+        self.events.last().map_or(0, |event| event.position)
+    }
+
+    /// The summary of the room, from the point of view of the given user.
+    pub fn summary(&self, user_id: &UserId) -> JsonValue {
+        let joined = self.members_with_membership("join");
+        let invited = self.members_with_membership("invite");
+        let heroes = joined
+            .iter()
+            .chain(&invited)
+            .filter(|member| *member != user_id)
+            .take(5)
+            .collect::<Vec<_>>();
+
+        json!({
+            "m.joined_member_count": joined.len(),
+            "m.invited_member_count": invited.len(),
+            "m.heroes": heroes,
+        })
+    }
+
+    /// The stripped state of the room, for the users who are invited to it.
+    pub fn stripped_state(&self, user_id: &UserId) -> Vec<JsonValue> {
+        const STRIPPED_STATE_TYPES: &[&str] = &[
+            "m.room.create",
+            "m.room.join_rules",
+            "m.room.name",
+            "m.room.avatar",
+            "m.room.topic",
+            "m.room.canonical_alias",
+            "m.room.encryption",
+        ];
+
+        let invite_event = self.state_event("m.room.member", user_id.as_str());
+        let inviter = invite_event.and_then(|event| event.event["sender"].as_str());
+
+        self.state_events()
+            .filter(|event| {
+                let event_type = event.event["type"].as_str().unwrap_or_default();
+                let state_key = event.event["state_key"].as_str();
+
+                STRIPPED_STATE_TYPES.contains(&event_type)
+                    || event_type == "m.room.member"
+                        && (state_key == Some(user_id.as_str()) || state_key == inviter)
+            })
+            .map(|event| {
+                json!({
+                    "type": event.event["type"],
+                    "state_key": event.event["state_key"],
+                    "sender": event.event["sender"],
+                    "content": event.event["content"],
+                })
+            })
+            .collect()
+    }
+}
+
+/// An event of a room.
+pub(super) struct RoomEvent {
+    /// The stream position at which the event was sent.
+    pub position: u64,
+
+    /// The event, in the format of the client-server API, with its room ID.
+    pub event: JsonValue,
+
+    /// The user, device and transaction ID the event was sent with, if any.
+    pub transaction: Option<(OwnedUserId, OwnedDeviceId, String)>,
+}
+
+impl RoomEvent {
+    /// The event, in the format of the `/sync` endpoint, from the point of
+    /// view of the given device.
+    ///
+    /// The transaction ID is only included for the device that sent the
+    /// event.
+    pub fn to_sync_event(&self, user_id: &UserId, device_id: &OwnedDeviceId) -> JsonValue {
+        let mut event = self.event.clone();
+
+        if let Some(event) = event.as_object_mut() {
+            event.remove("room_id");
+        }
+
+        if let Some((sender, sender_device_id, txn_id)) = &self.transaction {
+            if sender == user_id && sender_device_id == device_id {
+                event["unsigned"] = json!({ "transaction_id": txn_id });
+            }
+        }
+
+        event
+    }
+}
+
+/// An uploaded media.
+pub(super) struct Media {
+    /// The content type of the media.
+    pub content_type: String,
+
+    /// The content of the media.
+    pub data: Vec<u8>,
+}
@@ -0,0 +1,407 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The `/sync` endpoint, and a simplified version of the sliding sync.
+//!
+//! The sync tokens are stream positions of the homeserver. The endpoints
+//! don't wait for new data: when there's none, they answer after a short delay
+//! instead, to avoid busy loops in the clients.
+
+use std::{collections::BTreeSet, time::Duration};
+
+use ruma::{OwnedRoomId, OwnedUserId};
+use serde_json::{Map as JsonMap, Value as JsonValue, json};
+use wiremock::{Request, ResponseTemplate};
+
+use super::{Requester, body, error, ok, query_param, state::HomeserverState};
+
+/// The maximum delay before answering a sync request without new data.
+const EMPTY_SYNC_DELAY: Duration = Duration::from_millis(100);
+
+impl HomeserverState {
+    pub(super) fn sync(&mut self, request: &Request, requester: &Requester) -> ResponseTemplate {
+        let since = match query_param(request, "since").map(|since| since.parse::<u64>()) {
+            Some(Ok(since)) => Some(since),
+            Some(Err(_)) => return error(400, "M_INVALID_PARAM", "Invalid sync token"),
+            None => None,
+        };
+
+        let mut join = JsonMap::new();
+        let mut invite = JsonMap::new();
+        let mut leave = JsonMap::new();
+
+        let user_id = &requester.user_id;
+        let device_id = &requester.device_id;
+        let user = &self.users[user_id];
+
+        for (room_id, room) in &self.rooms {
+            let Some((membership, membership_position)) = room.membership(user_id) else {
+                continue;
+            };
+            let is_new_membership = since.is_none_or(|since| membership_position > since);
+
+            match membership {
+                "join" => {
+                    // The whole history of the room is sent to the users who just joined it.
+                    let events = room
+                        .events
+                        .iter()
+                        .filter(|event| {
+                            is_new_membership || since.is_none_or(|since| event.position > since)
+                        })
+                        .map(|event| event.to_sync_event(user_id, device_id))
+                        .collect::<Vec<_>>();
+
+                    let account_data = user
+                        .room_account_data
+                        .iter()
+                        .filter(|((account_data_room_id, _), (position, _))| {
+                            account_data_room_id == room_id
+                                && since.is_none_or(|since| *position > since)
+                        })
+                        .map(|((_, event_type), (_, content))| {
+                            json!({ "type": event_type, "content": content })
+                        })
+                        .collect::<Vec<_>>();
+
+                    if events.is_empty() && account_data.is_empty() && !is_new_membership {
+                        continue;
+                    }
+
+                    join.insert(
+                        room_id.to_string(),
+                        json!({
+                            "timeline": { "events": events, "limited": false },
+                            "state": { "events": [] },
+                            "account_data": { "events": account_data },
+                            "summary": room.summary(user_id),
+                        }),
+                    );
+                }
+
+                "invite" if is_new_membership => {
+                    invite.insert(
+                        room_id.to_string(),
+                        json!({ "invite_state": { "events": room.stripped_state(user_id) } }),
+                    );
+                }
+
+                "leave" | "ban" if since.is_some() && is_new_membership => {
+                    let events = room
+                        .events
+                        .iter()
+                        .filter(|event| {
+                            since.is_none_or(|since| event.position > since)
+                                && event.position <= membership_position
+                        })
+                        .map(|event| event.to_sync_event(user_id, device_id))
+                        .collect::<Vec<_>>();
+
+                    leave.insert(
+                        room_id.to_string(),
+                        json!({
+                            "timeline": { "events": events, "limited": false },
+                            "state": { "events": [] },
+                        }),
+                    );
+                }
+
+                _ => {}
+            }
+        }
+
+        let account_data = self.account_data_since(user_id, since);
+        let to_device_events = self.take_to_device_events(requester, since);
+        let changed_device_lists = self.changed_device_lists_since(user_id, since);
+
+        let is_empty = join.is_empty()
+            && invite.is_empty()
+            && leave.is_empty()
+            && account_data.is_empty()
+            && to_device_events.is_empty()
+            && changed_device_lists.is_empty();
+
+        let device = &self.users[user_id].devices[device_id];
+        let response = json!({
+            "next_batch": self.stream_position.to_string(),
+            "rooms": { "join": join, "invite": invite, "leave": leave },
+            "account_data": { "events": account_data },
+            "to_device": { "events": to_device_events },
+            "device_lists": { "changed": changed_device_lists, "left": [] },
+            "device_one_time_keys_count": device.one_time_key_counts(),
+            "device_unused_fallback_key_types": device.unused_fallback_key_types(),
+        });
+
+        delay_if_empty(request, ok(response), since.is_some() && is_empty)
+    }
+
+    pub(super) fn sliding_sync(
+        &mut self,
+        request: &Request,
+        requester: &Requester,
+    ) -> ResponseTemplate {
+        let since = match query_param(request, "pos").map(|pos| pos.parse::<u64>()) {
+            Some(Ok(since)) => Some(since),
+            Some(Err(_)) => return error(400, "M_UNKNOWN_POS", "Unknown position"),
+            None => None,
+        };
+
+        let body = body(request);
+        let user_id = &requester.user_id;
+        let device_id = &requester.device_id;
+
+        // The rooms of the user, from the most recently active to the least recently
+        // active one.
+        let mut rooms = self
+            .rooms
+            .iter()
+            .filter(|(_, room)| {
+                room.membership(user_id)
+                    .is_some_and(|(membership, _)| matches!(membership, "join" | "invite"))
+            })
+            .collect::<Vec<_>>();
+        rooms.sort_by_key(|(_, room)| std::cmp::Reverse(room.latest_position()));
+
+        // The rooms to send, with the maximum number of timeline events to send for
+        // each of them.
+        let mut room_timeline_limits = Vec::<(OwnedRoomId, u64)>::new();
+        let mut add_room = |room_id: &OwnedRoomId, timeline_limit: u64| match room_timeline_limits
+            .iter_mut()
+            .find(|(id, _)| id == room_id)
+        {
+            Some((_, limit)) => *limit = (*limit).max(timeline_limit),
+            None => room_timeline_limits.push((room_id.clone(), timeline_limit)),
+        };
+
+        let mut lists = JsonMap::new();
+        for (list_name, list) in body["lists"].as_object().into_iter().flatten() {
+            let timeline_limit = list["timeline_limit"].as_u64().unwrap_or(1);
+
+            for range in list["ranges"].as_array().into_iter().flatten() {
+                let (Some(start), Some(end)) = (range[0].as_u64(), range[1].as_u64()) else {
+                    continue;
+                };
+
+                for (room_id, _) in
+                    rooms.iter().skip(start as usize).take((end - start + 1) as usize)
+                {
+                    add_room(room_id, timeline_limit);
+                }
+            }
+
+            lists.insert(list_name.clone(), json!({ "count": rooms.len() }));
+        }
+
+        for (room_id, subscription) in body["room_subscriptions"].as_object().into_iter().flatten()
+        {
+            if let Some((room_id, _)) = rooms.iter().find(|(id, _)| id.as_str() == room_id) {
+                add_room(room_id, subscription["timeline_limit"].as_u64().unwrap_or(1));
+            }
+        }
+
+        let mut response_rooms = JsonMap::new();
+        for (room_id, timeline_limit) in room_timeline_limits {
+            let room = &self.rooms[&room_id];
+            let (membership, membership_position) =
+                room.membership(user_id).expect("the user is in the room");
+            let is_initial = since.is_none_or(|since| membership_position > since);
+
+            if !is_initial && room.latest_position() <= since.unwrap_or_default() {
+                continue;
+            }
+
+            let mut response_room = json!({
+                "initial": is_initial,
+                "bump_stamp": room.latest_position(),
+            });
+
+            if let Some(name) = room
+                .state_event("m.room.name", "")
+                .and_then(|event| event.event["content"]["name"].as_str())
+            {
+                response_room["name"] = json!(name);
+            }
+
+            if membership == "invite" {
+                response_room["invite_state"] = json!(room.stripped_state(user_id));
+            } else {
+                let new_events = room
+                    .events
+                    .iter()
+                    .filter(|event| is_initial || since.is_none_or(|since| event.position > since))
+                    .collect::<Vec<_>>();
+                let skipped = new_events.len().saturating_sub(timeline_limit as usize);
+                let timeline = new_events[skipped..]
+                    .iter()
+                    .map(|event| event.to_sync_event(user_id, device_id))
+                    .collect::<Vec<_>>();
+
+                response_room["timeline"] = json!(timeline);
+                response_room["num_live"] = json!(if is_initial { 0 } else { timeline.len() });
+                response_room["limited"] = json!(skipped > 0);
+                if skipped > 0 {
+                    response_room["prev_batch"] = json!(skipped.to_string());
+                }
+
+                if is_initial {
+                    response_room["required_state"] = JsonValue::Array(
+                        room.state_events()
+                            .map(|event| event.to_sync_event(user_id, device_id))
+                            .collect(),
+                    );
+                }
+
+                let summary = room.summary(user_id);
+                response_room["joined_count"] = summary["m.joined_member_count"].clone();
+                response_room["invited_count"] = summary["m.invited_member_count"].clone();
+            }
+
+            response_rooms.insert(room_id.to_string(), response_room);
+        }
+
+        let extensions = &body["extensions"];
+        let mut response_extensions = JsonMap::new();
+
+        if extensions["to_device"]["enabled"].as_bool() == Some(true) {
+            let to_device_since = extensions["to_device"]["since"]
+                .as_str()
+                .and_then(|since| since.parse::<u64>().ok());
+            let events = self.take_to_device_events(requester, to_device_since);
+
+            response_extensions.insert(
+                "to_device".to_owned(),
+                json!({ "next_batch": self.stream_position.to_string(), "events": events }),
+            );
+        }
+
+        if extensions["e2ee"]["enabled"].as_bool() == Some(true) {
+            let device = &self.users[user_id].devices[device_id];
+            response_extensions.insert(
+                "e2ee".to_owned(),
+                json!({
+                    "device_lists": {
+                        "changed": self.changed_device_lists_since(user_id, since),
+                        "left": [],
+                    },
+                    "device_one_time_keys_count": device.one_time_key_counts(),
+                    "device_unused_fallback_key_types": device.unused_fallback_key_types(),
+                }),
+            );
+        }
+
+        if extensions["account_data"]["enabled"].as_bool() == Some(true) {
+            response_extensions.insert(
+                "account_data".to_owned(),
+                json!({ "global": self.account_data_since(user_id, since), "rooms": {} }),
+            );
+        }
+
+        let is_empty = response_rooms.is_empty()
+            && response_extensions.values().all(|extension| {
+                extension["events"].as_array().is_none_or(Vec::is_empty)
+                    && extension["global"].as_array().is_none_or(Vec::is_empty)
+                    && extension["device_lists"]["changed"].as_array().is_none_or(Vec::is_empty)
+            });
+
+        let mut response = json!({
+            "pos": self.stream_position.to_string(),
+            "lists": lists,
+            "rooms": response_rooms,
+            "extensions": response_extensions,
+        });
+        if let Some(txn_id) = body["txn_id"].as_str() {
+            response["txn_id"] = json!(txn_id);
+        }
+
+        delay_if_empty(request, ok(response), since.is_some() && is_empty)
+    }
+
+    /// The global account data events of the user that changed after the given
+    /// stream position.
+    fn account_data_since(&self, user_id: &OwnedUserId, since: Option<u64>) -> Vec<JsonValue> {
+        self.users[user_id]
+            .account_data
+            .iter()
+            .filter(|(_, (position, _))| since.is_none_or(|since| *position > since))
+            .map(|(event_type, (_, content))| json!({ "type": event_type, "content": content }))
+            .collect()
+    }
+
+    /// The to-device events sent to the device of the requester after the
+    /// given stream position.
+    ///
+    /// The events sent before it have been received by the device, and are
+    /// forgotten.
+    fn take_to_device_events(
+        &mut self,
+        requester: &Requester,
+        since: Option<u64>,
+    ) -> Vec<JsonValue> {
+        let device = self
+            .users
+            .get_mut(&requester.user_id)
+            .and_then(|user| user.devices.get_mut(&requester.device_id))
+            .expect("the requester's device exists");
+
+        if let Some(since) = since {
+            device.to_device_events.retain(|(position, _)| *position > since);
+        }
+
+        device.to_device_events.iter().map(|(_, event)| event.clone()).collect()
+    }
+
+    /// The users whose devices changed after the given stream position, and
+    /// who share a room with the given user.
+    ///
+    /// Returns an empty list for initial syncs.
+    fn changed_device_lists_since(
+        &self,
+        user_id: &OwnedUserId,
+        since: Option<u64>,
+    ) -> Vec<OwnedUserId> {
+        let Some(since) = since else {
+            return Vec::new();
+        };
+
+        self.device_list_changes
+            .iter()
+            .filter(|(position, _)| *position > since)
+            .map(|(_, changed_user_id)| changed_user_id)
+            .filter(|changed_user_id| {
+                *changed_user_id == user_id || self.share_a_room(user_id, changed_user_id)
+            })
+            .cloned()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Delay the response to a sync request if it doesn't contain any new data,
+/// up to the timeout of the request.
+fn delay_if_empty(
+    request: &Request,
+    response: ResponseTemplate,
+    is_empty: bool,
+) -> ResponseTemplate {
+    if !is_empty {
+        return response;
+    }
+
+    let timeout = query_param(request, "timeout")
+        .and_then(|timeout| timeout.parse().ok())
+        .map_or(Duration::ZERO, Duration::from_millis);
+
+    response.set_delay(timeout.min(EMPTY_SYNC_DELAY))
+}
@@ -116,8 +116,14 @@ pub mod __macro_support {
 pub mod mocks;
 
 pub mod event_factory;
+#[cfg(not(target_family = "wasm"))]
+pub mod homeserver;
 pub mod notification_settings;
+#[cfg(not(target_family = "wasm"))]
+pub mod push_gateway;
 mod sync_builder;
+#[cfg(not(target_family = "wasm"))]
+pub mod sync_replay;
 pub mod test_json;
 
 pub use self::sync_builder::{
@@ -0,0 +1,233 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A lightweight in-process stand-in for a [push gateway], and helpers to
+//! build the notifications a homeserver would send to it.
+//!
+//! [push gateway]: https://spec.matrix.org/latest/push-gateway-api/
+
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
+
+use ruma::{EventId, RoomId, UserId};
+use serde_json::{Map as JsonMap, Value as JsonValue, json};
+use wiremock::{
+    Mock, MockServer, Request, ResponseTemplate,
+    matchers::{method, path},
+};
+
+/// The path of the push gateway endpoint homeservers send notifications to.
+pub const PUSH_GATEWAY_NOTIFY_PATH: &str = "/_matrix/push/v1/notify";
+
+/// An in-process push gateway, recording every notification it receives.
+///
+/// The gateway accepts all the notifications by default, individual push keys
+/// can be rejected with [`PushGateway::reject_push_key`], which makes the
+/// gateway report them in the `rejected` field of its responses, like a real
+/// gateway would do for a push key that isn't valid anymore.
+pub struct PushGateway {
+    server: MockServer,
+    rejected_push_keys: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl PushGateway {
+    /// Start a new push gateway, listening on a random local port.
+    pub async fn new() -> Self {
+        let server = MockServer::start().await;
+        let rejected_push_keys = Arc::<Mutex<BTreeSet<String>>>::default();
+
+        Mock::given(method("POST"))
+            .and(path(PUSH_GATEWAY_NOTIFY_PATH))
+            .respond_with({
+                let rejected_push_keys = rejected_push_keys.clone();
+
+                move |request: &Request| {
+                    let Ok(body) = request.body_json::<JsonValue>() else {
+                        return ResponseTemplate::new(400);
+                    };
+
+                    let rejected_push_keys = rejected_push_keys.lock().unwrap();
+                    let rejected = body["notification"]["devices"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|device| device["pushkey"].as_str())
+                        .filter(|push_key| rejected_push_keys.contains(*push_key))
+                        .collect::<Vec<_>>();
+
+                    ResponseTemplate::new(200).set_body_json(json!({ "rejected": rejected }))
+                }
+            })
+            .mount(&server)
+            .await;
+
+        Self { server, rejected_push_keys }
+    }
+
+    /// The URL of the notify endpoint of this gateway, to use as the `url` of
+    /// an HTTP pusher.
+    pub fn notify_url(&self) -> String {
+        format!("{}{PUSH_GATEWAY_NOTIFY_PATH}", self.server.uri())
+    }
+
+    /// Reject all the future notifications sent to the given push key.
+    pub fn reject_push_key(&self, push_key: impl Into<String>) {
+        self.rejected_push_keys.lock().unwrap().insert(push_key.into());
+    }
+
+    /// Get the `notification` objects received by this gateway so far, in
+    /// the order they were received.
+    pub async fn received_notifications(&self) -> Vec<JsonValue> {
+        self.server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|request| request.url.path() == PUSH_GATEWAY_NOTIFY_PATH)
+            .filter_map(|request| request.body_json::<JsonValue>().ok())
+            .map(|mut body| body["notification"].take())
+            .collect()
+    }
+
+    /// Return the underlying [`wiremock`] server.
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+}
+
+/// A builder for the notifications a homeserver sends to a push gateway.
+///
+/// # Examples
+///
+/// ```
+/// use matrix_sdk_test::push_gateway::PushNotificationBuilder;
+/// use ruma::{event_id, room_id, user_id};
+/// use serde_json::json;
+///
+/// let notification =
+///     PushNotificationBuilder::new(room_id!("!room:localhost"), event_id!("$event"))
+///         .event_type("m.room.message")
+///         .sender(user_id!("@alice:localhost"))
+///         .content(json!({ "msgtype": "m.text", "body": "Hello!" }))
+///         .unread_count(2)
+///         .device("io.element.app", "push-key", json!({}))
+///         .into_request_body();
+/// ```
+#[derive(Clone, Debug)]
+pub struct PushNotificationBuilder {
+    notification: JsonMap<String, JsonValue>,
+    devices: Vec<JsonValue>,
+}
+
+impl PushNotificationBuilder {
+    /// Create a new notification about the given event, with a `high`
+    /// priority.
+    pub fn new(room_id: &RoomId, event_id: &EventId) -> Self {
+        let mut notification = JsonMap::new();
+        notification.insert("room_id".to_owned(), json!(room_id));
+        notification.insert("event_id".to_owned(), json!(event_id));
+        notification.insert("prio".to_owned(), json!("high"));
+
+        Self { notification, devices: Vec::new() }
+    }
+
+    /// Set the type of the event.
+    pub fn event_type(self, event_type: &str) -> Self {
+        self.field("type", json!(event_type))
+    }
+
+    /// Set the sender of the event.
+    pub fn sender(self, sender: &UserId) -> Self {
+        self.field("sender", json!(sender))
+    }
+
+    /// Set the display name of the sender of the event.
+    pub fn sender_display_name(self, display_name: &str) -> Self {
+        self.field("sender_display_name", json!(display_name))
+    }
+
+    /// Set the name of the room the event was sent in.
+    pub fn room_name(self, room_name: &str) -> Self {
+        self.field("room_name", json!(room_name))
+    }
+
+    /// Set the content of the event.
+    pub fn content(self, content: JsonValue) -> Self {
+        self.field("content", content)
+    }
+
+    /// Set the number of unread messages of the user.
+    pub fn unread_count(mut self, count: u64) -> Self {
+        self.counts().insert("unread".to_owned(), json!(count));
+        self
+    }
+
+    /// Set the number of missed calls of the user.
+    pub fn missed_calls(mut self, count: u64) -> Self {
+        self.counts().insert("missed_calls".to_owned(), json!(count));
+        self
+    }
+
+    /// Give the notification a `low` priority.
+    pub fn low_priority(self) -> Self {
+        self.field("prio", json!("low"))
+    }
+
+    /// Add a device the notification should be delivered to.
+    ///
+    /// `data` is the data of the pusher, without its `url`.
+    pub fn device(mut self, app_id: &str, push_key: &str, data: JsonValue) -> Self {
+        self.devices.push(json!({
+            "app_id": app_id,
+            "pushkey": push_key,
+            "data": data,
+        }));
+        self
+    }
+
+    /// Strip the notification down to the fields a homeserver sends for a
+    /// pusher with the `event_id_only` format.
+    pub fn event_id_only(mut self) -> Self {
+        self.notification
+            .retain(|key, _| matches!(key.as_str(), "event_id" | "room_id" | "counts" | "prio"));
+        self
+    }
+
+    /// Build the `notification` object.
+    pub fn build(self) -> JsonValue {
+        let mut notification = self.notification;
+        notification.insert("devices".to_owned(), JsonValue::Array(self.devices));
+        JsonValue::Object(notification)
+    }
+
+    /// Build the full body of the request sent to the push gateway.
+    pub fn into_request_body(self) -> JsonValue {
+        json!({ "notification": self.build() })
+    }
+
+    fn field(mut self, key: &str, value: JsonValue) -> Self {
+        self.notification.insert(key.to_owned(), value);
+        self
+    }
+
+    fn counts(&mut self) -> &mut JsonMap<String, JsonValue> {
+        self.notification
+            .entry("counts")
+            .or_insert_with(|| JsonValue::Object(JsonMap::new()))
+            .as_object_mut()
+            .expect("the counts of a notification are always an object")
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Replay of the sync recordings made with `Client::start_recording_syncs()`.
+//!
+//! A [`SyncReplayServer`] serves the responses of a [`SyncRecording`] to the
+//! sync requests it receives, in the order they were recorded, so a fresh
+//! client goes through the same syncs as the client that made the recording.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use ruma::{OwnedDeviceId, OwnedUserId};
+use serde::Deserialize;
+use serde_json::{Value as JsonValue, json};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate, matchers::path_regex};
+
+/// A recording of the sync requests of a client, and of their responses.
+#[derive(Clone, Debug)]
+pub struct SyncRecording {
+    user_id: OwnedUserId,
+    device_id: OwnedDeviceId,
+    syncs: Vec<RecordedSync>,
+}
+
+impl SyncRecording {
+    /// Load the recording in the file at the given path.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file can't be read, or isn't a valid recording.
+    pub fn from_file(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let recording = std::fs::read_to_string(path)
+            .unwrap_or_else(|error| panic!("couldn't read {}: {error}", path.display()));
+        Self::parse(&recording)
+    }
+
+    /// Parse a recording, in the JSON Lines format written by the client.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the recording isn't valid.
+    pub fn parse(recording: &str) -> Self {
+        #[derive(Deserialize)]
+        struct Header {
+            user_id: OwnedUserId,
+            device_id: OwnedDeviceId,
+        }
+
+        let mut lines = recording.lines().filter(|line| !line.trim().is_empty());
+
+        let header = lines.next().expect("the recording should have a header");
+        let Header { user_id, device_id } =
+            serde_json::from_str(header).expect("the header of the recording should be valid");
+
+        let syncs = lines
+            .map(|line| serde_json::from_str(line).expect("the recorded sync should be valid"))
+            .collect();
+
+        Self { user_id, device_id, syncs }
+    }
+
+    /// The user ID of the session the recording was made with.
+    pub fn user_id(&self) -> &OwnedUserId {
+        &self.user_id
+    }
+
+    /// The device ID of the session the recording was made with.
+    pub fn device_id(&self) -> &OwnedDeviceId {
+        &self.device_id
+    }
+
+    /// The recorded syncs, in the order they happened.
+    pub fn syncs(&self) -> &[RecordedSync] {
+        &self.syncs
+    }
+}
+
+/// A recorded sync request, and its response.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RecordedSync {
+    /// The sync request.
+    pub request: RecordedRequest,
+
+    /// The response to the sync request.
+    pub response: RecordedResponse,
+}
+
+/// A recorded sync request.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RecordedRequest {
+    /// The HTTP method of the request.
+    pub method: String,
+
+    /// The path of the request.
+    pub path: String,
+
+    /// The query parameters of the request.
+    pub query: BTreeMap<String, String>,
+
+    /// The JSON body of the request, or `null` if it doesn't have one.
+    pub body: JsonValue,
+}
+
+/// A recorded response to a sync request.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RecordedResponse {
+    /// The HTTP status code of the response.
+    pub status: u16,
+
+    /// The JSON body of the response, or `null` if it doesn't have one.
+    pub body: JsonValue,
+}
+
+/// A mock server replaying the responses of a [`SyncRecording`].
+///
+/// The recorded responses are served in order to the requests to the sync
+/// endpoints, regardless of their parameters, as long as they're sent to the
+/// same endpoint as the recorded request. The other endpoints aren't mocked:
+/// use [`SyncReplayServer::server()`] to mount more mocks if needed.
+///
+/// Once all the recorded responses have been served, the sync requests
+/// receive an `M_NOT_FOUND` error.
+pub struct SyncReplayServer {
+    server: MockServer,
+    user_id: OwnedUserId,
+    device_id: OwnedDeviceId,
+    remaining_syncs: Arc<Mutex<VecDeque<RecordedSync>>>,
+}
+
+impl SyncReplayServer {
+    /// Start a new server replaying the given recording.
+    pub async fn start(recording: SyncRecording) -> Self {
+        let server = MockServer::start().await;
+        let remaining_syncs = Arc::new(Mutex::new(VecDeque::from(recording.syncs)));
+
+        Mock::given(path_regex(r"^/_matrix/client/.+/sync$"))
+            .respond_with(Replayer { remaining_syncs: remaining_syncs.clone() })
+            .mount(&server)
+            .await;
+
+        Self { server, user_id: recording.user_id, device_id: recording.device_id, remaining_syncs }
+    }
+
+    /// The URL of the server, to use as the homeserver URL of the client.
+    pub fn uri(&self) -> String {
+        self.server.uri()
+    }
+
+    /// The user ID of the session the recording was made with.
+    pub fn user_id(&self) -> &OwnedUserId {
+        &self.user_id
+    }
+
+    /// The device ID of the session the recording was made with.
+    pub fn device_id(&self) -> &OwnedDeviceId {
+        &self.device_id
+    }
+
+    /// The number of recorded responses that haven't been served yet.
+    pub fn remaining_syncs(&self) -> usize {
+        self.remaining_syncs.lock().unwrap().len()
+    }
+
+    /// Return the underlying [`wiremock`] server, e.g. to mount mocks for the
+    /// endpoints other than the sync ones.
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+}
+
+/// The [`Respond`] implementation serving the recorded responses.
+struct Replayer {
+    remaining_syncs: Arc<Mutex<VecDeque<RecordedSync>>>,
+}
+
+impl Respond for Replayer {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let mut remaining_syncs = self.remaining_syncs.lock().unwrap();
+
+        let Some(sync) = remaining_syncs.front() else {
+            return error(404, "M_NOT_FOUND", "All the recorded syncs have been replayed");
+        };
+
+        // The path of the homeserver URL may have a prefix.
+        if !request.url.path().ends_with(&sync.request.path) {
+            return error(
+                400,
+                "M_UNRECOGNIZED",
+                &format!("Expected a request to {}", sync.request.path),
+            );
+        }
+
+        let sync = remaining_syncs.pop_front().expect("the recorded sync exists");
+        let mut body = sync.response.body;
+
+        // Sliding sync only applies the sticky parameters of a request if its
+        // transaction ID is echoed back, and the transaction IDs are random.
+        if body.get("txn_id").is_some() {
+            let txn_id = request.body_json::<JsonValue>().ok().map(|body| body["txn_id"].clone());
+            body["txn_id"] = txn_id.unwrap_or_default();
+        }
+
+        ResponseTemplate::new(sync.response.status).set_body_json(body)
+    }
+}
+
+/// An error response, in the format of the client-server API.
+fn error(status: u16, errcode: &str, message: &str) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_json(json!({ "errcode": errcode, "error": message }))
+}